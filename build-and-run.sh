@@ -35,6 +35,14 @@ if [ -z ${BTI_EL3} ]; then
     BTI_EL3=0
 fi
 
+if [ -z ${QEMU_TEST_EXIT} ]; then
+    QEMU_TEST_EXIT=0
+fi
+
+if [ -z ${FVP_CCI} ]; then
+    FVP_CCI=0
+fi
+
 if [ -z "${CARGO}" ]; then
     CARGO="cargo"
 fi
@@ -73,7 +81,7 @@ case "$PLAT" in
         QEMU_WAIT="-S"
     fi
     make -C $TFA PLAT=qemu ${DEBUG} CC=clang NEED_BL32=yes NEED_BL31=no bl1 bl2
-    make PLAT=qemu ${DEBUG} CARGO="${CARGO}" PAUTH_EL3=${PAUTH_EL3} PAUTH_LR_EL3=${PAUTH_LR_EL3} BTI_EL3=${BTI_EL3} all
+    make PLAT=qemu ${DEBUG} CARGO="${CARGO}" PAUTH_EL3=${PAUTH_EL3} PAUTH_LR_EL3=${PAUTH_LR_EL3} BTI_EL3=${BTI_EL3} QEMU_TEST_EXIT=${QEMU_TEST_EXIT} all
     ln -fsr ${BL1} ${OUT}
     ln -fsr ${BL2} ${OUT}
     cd ${OUT}
@@ -93,10 +101,12 @@ case "$PLAT" in
         -C bp.pl011_uart0.out_file=- \
         -C bp.pl011_uart1.unbuffered_output=1 \
         -C bp.pl011_uart1.out_file=- \
+        -C bp.pl011_uart2.unbuffered_output=1 \
+        -C bp.pl011_uart2.out_file=- \
         -C bp.ve_sysregs.exit_on_shutdown=1 \
         -C bp.vis.disable_visualisation=1 \
         -C cache_state_modelled=1 \
-        -C cci550.force_on_from_start=1 \
+        -C cci550.force_on_from_start=$((1 - FVP_CCI)) \
         -C cluster0.NUM_CORES=4 \
         -C cluster1.NUM_CORES=4 \
         -C cluster0.cpu0.etm-present=0 \
@@ -113,6 +123,8 @@ case "$PLAT" in
         -C cluster1.has_amu=1 \
         -C cluster0.has_branch_target_exception=1 \
         -C cluster1.has_branch_target_exception=1 \
+        -C cluster0.has_brbe=1 \
+        -C cluster1.has_brbe=1 \
         -C cluster0.has_ete=1 \
         -C cluster1.has_ete=1 \
         -C cluster0.has_fgt2=2 \
@@ -168,7 +180,7 @@ case "$PLAT" in
 
     if [[ "${RME:-}" == 1 ]]; then
         RMM=${RMM:-"$STF_RMM"}
-        make PLAT=fvp RME=${RME} TEST_RMM_BOOT_FAIL=${TEST_RMM_BOOT_FAIL} ${DEBUG} CARGO="${CARGO}" PAUTH_EL3=${PAUTH_EL3} PAUTH_LR_EL3=${PAUTH_LR_EL3} BTI_EL3=${BTI_EL3} all
+        make PLAT=fvp RME=${RME} TEST_RMM_BOOT_FAIL=${TEST_RMM_BOOT_FAIL} ${DEBUG} CARGO="${CARGO}" PAUTH_EL3=${PAUTH_EL3} PAUTH_LR_EL3=${PAUTH_LR_EL3} BTI_EL3=${BTI_EL3} FVP_CCI=${FVP_CCI} all
 	# RME_GPT_MAX_BLOCK=0: disables Contiguous descriptors in TF-A's GPT setup, as RF-A doesn't
 	# support them yet.
         make -C $TFA ${DEBUG} "${FVP_TFA_COMMON_ARGS[@]}" ENABLE_FEAT_RME=1 ENABLE_RMM=1 RME_GPT_MAX_BLOCK=0 RMM="$RMM" all fip
@@ -214,7 +226,7 @@ case "$PLAT" in
             ${FVP_COMMON_ARGS}
 
     else
-        make PLAT=fvp ${DEBUG} CARGO="${CARGO}" PAUTH_EL3=${PAUTH_EL3} PAUTH_LR_EL3=${PAUTH_LR_EL3} BTI_EL3=${BTI_EL3} all
+        make PLAT=fvp ${DEBUG} CARGO="${CARGO}" PAUTH_EL3=${PAUTH_EL3} PAUTH_LR_EL3=${PAUTH_LR_EL3} BTI_EL3=${BTI_EL3} FVP_CCI=${FVP_CCI} all
         make -C $TFA ${DEBUG} "${FVP_TFA_COMMON_ARGS[@]}" all fip
         FVP_Base_RevC-2xAEMvA \
             -C cluster0.has_arm_v9-0=1 \
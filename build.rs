@@ -4,6 +4,8 @@
 
 //! Build script for RF-A.
 
+use std::{env, path::Path, process::Command};
+
 fn main() {
     println!("cargo::rustc-check-cfg=cfg(bti)");
 
@@ -11,4 +13,86 @@ fn main() {
     // included inside a macro with `#[include_first]` is changed.
     // TODO: Remove once `#[include_first]` handles this automatically.
     println!("cargo:rerun-if-changed=src");
+
+    embed_build_info();
+}
+
+/// Passes the git revision, a hash of the build configuration and the anti-rollback version to the
+/// crate as environment variables, for `build_info`.
+fn embed_build_info() {
+    let (revision, dirty) = git_revision().unwrap_or((0, false));
+    println!("cargo:rustc-env=RF_A_GIT_REVISION={revision:016x}");
+    println!("cargo:rustc-env=RF_A_GIT_DIRTY={}", u8::from(dirty));
+    println!(
+        "cargo:rustc-env=RF_A_BUILD_CONFIG_HASH={:016x}",
+        config_hash()
+    );
+    println!(
+        "cargo:rustc-env=RF_A_ROLLBACK_VERSION={}",
+        rollback_version()
+    );
+
+    // Re-run when the checked out commit or the working tree changes. Cargo always re-runs the
+    // build script if a path doesn't exist, so only watch the ones which do.
+    if let Some(git_dir) = git(&["rev-parse", "--git-dir"]) {
+        for file in ["HEAD", "index"] {
+            let path = Path::new(&git_dir).join(file);
+            if path.exists() {
+                println!("cargo:rerun-if-changed={}", path.display());
+            }
+        }
+    }
+    println!("cargo:rerun-if-env-changed=CARGO_ENCODED_RUSTFLAGS");
+    println!("cargo:rerun-if-env-changed=RF_A_ROLLBACK_VERSION");
+}
+
+/// Returns the anti-rollback version set in the `RF_A_ROLLBACK_VERSION` environment variable, or 0
+/// if it isn't set.
+fn rollback_version() -> u32 {
+    match env::var("RF_A_ROLLBACK_VERSION") {
+        Ok(version) if !version.is_empty() => version
+            .parse()
+            .expect("RF_A_ROLLBACK_VERSION must be a 32-bit unsigned integer"),
+        _ => 0,
+    }
+}
+
+/// Returns the first 64 bits of the current git commit hash, and whether the working tree has
+/// uncommitted changes, or `None` if this isn't being built from a git checkout.
+fn git_revision() -> Option<(u64, bool)> {
+    let head = git(&["rev-parse", "HEAD"])?;
+    let revision = u64::from_str_radix(head.get(..16)?, 16).ok()?;
+    let dirty = git(&["status", "--porcelain", "--untracked-files=no"])
+        .is_some_and(|status| !status.is_empty());
+    Some((revision, dirty))
+}
+
+/// Runs git with the given arguments, returning its trimmed output if it succeeds.
+fn git(args: &[&str]) -> Option<String> {
+    let output = Command::new("git").args(args).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    Some(String::from_utf8(output.stdout).ok()?.trim().to_owned())
+}
+
+/// Returns an FNV-1a hash of the enabled features, profile, target and compiler flags.
+fn config_hash() -> u64 {
+    let mut features = env::vars()
+        .filter_map(|(name, _)| name.strip_prefix("CARGO_FEATURE_").map(str::to_owned))
+        .collect::<Vec<_>>();
+    features.sort();
+
+    let mut hash = 0xcbf2_9ce4_8422_2325_u64;
+    let variables = ["PROFILE", "TARGET", "CARGO_ENCODED_RUSTFLAGS"]
+        .into_iter()
+        .map(|name| env::var(name).unwrap_or_default());
+    for part in features.into_iter().chain(variables) {
+        // Include a separator so that adjacent parts can't run together.
+        for byte in part.bytes().chain([0]) {
+            hash ^= u64::from(byte);
+            hash = hash.wrapping_mul(0x0000_0100_0000_01b3);
+        }
+    }
+    hash
 }
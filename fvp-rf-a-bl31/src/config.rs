@@ -2,6 +2,20 @@
 //
 // SPDX-License-Identifier: BSD-3-Clause
 
+use rf_a_bl31::{logger::pl011::Pl011Config, runtime_config::ConsoleSelection};
+
 pub const FVP_CLUSTER_COUNT: usize = 2;
 pub const FVP_MAX_CPUS_PER_CLUSTER: usize = 4;
 pub const FVP_MAX_PE_PER_CPU: usize = 1;
+
+/// The default console for runtime logs: `Shared` is UART0, while `SecureOnly` is UART2, leaving
+/// UART0 for the normal world. Crash output always goes to UART1.
+pub const FVP_RUNTIME_CONSOLE: ConsoleSelection = ConsoleSelection::Shared;
+
+/// Configuration applied to the runtime UART by BL31, rather than relying on earlier firmware to
+/// have set it up.
+pub const FVP_RUNTIME_CONSOLE_CONFIG: Pl011Config = Pl011Config {
+    clock_hz: 24_000_000,
+    baud_rate: 115_200,
+    flow_control: false,
+};
@@ -0,0 +1,225 @@
+// Copyright The Rusted Firmware-A Contributors.
+//
+// SPDX-License-Identifier: BSD-3-Clause
+
+//! Dynamic configuration passed to BL31 by TF-A's BL2.
+//!
+//! BL2 passes the `bl_params` list of the images it loaded in `x0`, and the address of FW_CONFIG in
+//! `x1`. FW_CONFIG's DTB registry gives the addresses at which BL2 loaded the other configuration
+//! blobs, including TOS_FW_CONFIG (the SPMC manifest) and HW_CONFIG, which describes the platform's
+//! peripherals. The SPMC's endpoint ID and FF-A version are read from TOS_FW_CONFIG's `attribute`
+//! node, and the secure interrupts owned by each secure partition from the `interrupts` property of
+//! its node under `hypervisor`.
+
+use arm_ffa::Version;
+use arm_fvp_base_pac::MemoryMap;
+use arrayvec::ArrayVec;
+use core::{ops::Range, slice};
+use rf_a_bl31::{
+    bl_params::{BL32_IMAGE_ID, BL33_IMAGE_ID, image_entry_point},
+    fdt::{FDT_HEADER_SIZE, Fdt, Node},
+    reexports::arm_gic::IntId,
+    services::ffa::secure_interrupts::SecureInterruptAssignment,
+};
+
+/// The maximum number of secure interrupts which may be assigned to secure partitions in
+/// TOS_FW_CONFIG.
+const MAX_SECURE_INTERRUPTS: usize = 16;
+
+/// Addresses discovered from the parameters passed by BL2.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct FwConfig {
+    /// The entry point of BL32.
+    pub bl32_pc: usize,
+    /// The entry point of BL33.
+    pub bl33_pc: usize,
+    /// The physical address of the SPMC manifest blob.
+    pub tos_fw_config: u64,
+    /// The FF-A endpoint ID of the SPMC, from the SPMC manifest.
+    pub spmc_id: u16,
+    /// The FF-A version implemented by the SPMC, from the SPMC manifest.
+    pub spmc_version: Version,
+    /// The secure interrupts assigned to each secure partition, from the SPMC manifest.
+    pub secure_interrupts: ArrayVec<SecureInterruptAssignment, MAX_SECURE_INTERRUPTS>,
+    /// The physical address of the normal world's configuration blob.
+    pub nt_fw_config: u64,
+    /// The memory reserved for HW_CONFIG in secure memory.
+    pub hw_config: Range<usize>,
+    /// The physical address of the copy of HW_CONFIG given to the normal world.
+    pub hw_config_ns: u64,
+}
+
+impl FwConfig {
+    /// Parses the `bl_params` list and FW_CONFIG at the given addresses.
+    ///
+    /// Panics if either is missing or malformed, as the entry points and configuration addresses
+    /// can't be known without them.
+    ///
+    /// # Safety
+    ///
+    /// `bl_params` must be the address of the `bl_params_t` written by BL2, and `fw_config` the
+    /// address of FW_CONFIG. Both must still be mapped by the early mapping, i.e. in trusted SRAM.
+    pub unsafe fn parse(bl_params: u64, fw_config: u64) -> Self {
+        let bl_params = bl_params as usize;
+        let sram = MemoryMap::TRUSTED_SRAM;
+        // SAFETY: Trusted SRAM is mapped by the early mapping, and nothing else modifies the
+        // `bl_params` list which BL2 wrote there.
+        let bl32 = unsafe { image_entry_point(bl_params, &sram, BL32_IMAGE_ID) }
+            .expect("Failed to find BL32 entry point");
+        // SAFETY: As above.
+        let bl33 = unsafe { image_entry_point(bl_params, &sram, BL33_IMAGE_ID) }
+            .expect("Failed to find BL33 entry point");
+
+        // SAFETY: Our caller promised that `fw_config` is the address of FW_CONFIG and is still
+        // mapped.
+        let fw_config = unsafe { sram_blob(fw_config as usize) };
+        let fw_config = Fdt::new(fw_config).expect("Invalid FW_CONFIG");
+        let registry = fw_config
+            .find_compatible("fconf,dyn_cfg-dtb_registry")
+            .expect("FW_CONFIG has no DTB registry");
+        let hw_config = registry_entry(&registry, "hw-config");
+        let hw_config_address = load_address(&hw_config) as usize;
+        let hw_config_size = hw_config
+            .property_u32("max-size")
+            .expect("hw-config has no max-size") as usize;
+
+        let tos_fw_config = load_address(&registry_entry(&registry, "tos_fw-config"));
+        // SAFETY: BL2 loaded TOS_FW_CONFIG at this address, and nothing else modifies it.
+        let spmc_manifest = unsafe { sram_blob(tos_fw_config as usize) };
+        let spmc_manifest = Fdt::new(spmc_manifest).expect("Invalid TOS_FW_CONFIG");
+        let attribute = spmc_manifest
+            .root()
+            .child("attribute")
+            .expect("TOS_FW_CONFIG has no attribute node");
+        let attribute = |name| {
+            let value = attribute
+                .property_u32(name)
+                .unwrap_or_else(|| panic!("TOS_FW_CONFIG has no {name}"));
+            u16::try_from(value).unwrap_or_else(|_| {
+                panic!("TOS_FW_CONFIG {name} {value:#x} doesn't fit in 16 bits")
+            })
+        };
+
+        Self {
+            bl32_pc: bl32.pc,
+            bl33_pc: bl33.pc,
+            tos_fw_config,
+            spmc_id: attribute("spmc_id"),
+            spmc_version: Version(attribute("maj_ver"), attribute("min_ver")),
+            secure_interrupts: secure_interrupts(&spmc_manifest).expect("Invalid TOS_FW_CONFIG"),
+            nt_fw_config: load_address(&registry_entry(&registry, "nt_fw-config")),
+            hw_config: hw_config_address..hw_config_address + hw_config_size,
+            hw_config_ns: hw_config
+                .property_u64("secondary-load-address")
+                .expect("hw-config has no secondary-load-address"),
+        }
+    }
+}
+
+/// The peripherals described by HW_CONFIG which BL31 uses.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct HwConfig {
+    /// The base address of the console UART given by `stdout-path`.
+    pub uart_base: usize,
+    /// The base address of the GIC distributor.
+    pub gicd_base: usize,
+    /// The base address of the first GIC redistributor frame.
+    pub gicr_base: usize,
+}
+
+impl HwConfig {
+    /// Parses HW_CONFIG, panicking if it is malformed or doesn't describe a required peripheral.
+    pub fn parse(hw_config: &[u8]) -> Self {
+        let hw_config = Fdt::new(hw_config).expect("Invalid HW_CONFIG");
+        let uart = hw_config.stdout().expect("HW_CONFIG has no stdout-path");
+        let gic = hw_config
+            .find_compatible("arm,gic-v3")
+            .expect("HW_CONFIG has no GICv3");
+
+        let reg_address = |node: &Node, index| {
+            hw_config
+                .reg(node, index)
+                .unwrap_or_else(|| panic!("Failed to get reg {index} of {}", node.name()))
+                .address as usize
+        };
+        Self {
+            uart_base: reg_address(&uart, 0),
+            gicd_base: reg_address(&gic, 0),
+            gicr_base: reg_address(&gic, 1),
+        }
+    }
+}
+
+/// Returns the secure interrupts assigned to secure partitions by the SPMC manifest, or `None` if
+/// they are malformed.
+///
+/// Each partition node under `hypervisor` may have an `interrupts` property with a list of
+/// `<INTID attributes>` pairs, as in the device regions of an FF-A partition manifest, in which
+/// case it must also have an `id` property with the partition's endpoint ID. The attributes are
+/// left for the SPMC to apply.
+fn secure_interrupts(
+    spmc_manifest: &Fdt,
+) -> Option<ArrayVec<SecureInterruptAssignment, MAX_SECURE_INTERRUPTS>> {
+    let mut assignments = ArrayVec::new();
+    let Some(hypervisor) = spmc_manifest.root().child("hypervisor") else {
+        return Some(assignments);
+    };
+    for partition in hypervisor.children() {
+        let Some(interrupts) = partition.property("interrupts") else {
+            continue;
+        };
+        let endpoint_id = u16::try_from(partition.property_u32("id")?).ok()?;
+        let (interrupts, []) = interrupts.as_chunks::<8>() else {
+            return None;
+        };
+        for interrupt in interrupts {
+            let interrupt_id = u32::from_be_bytes(*interrupt.first_chunk()?);
+            assignments
+                .try_push(SecureInterruptAssignment {
+                    interrupt_id: IntId::try_from(interrupt_id).ok()?,
+                    endpoint_id,
+                })
+                .ok()?;
+        }
+    }
+    Some(assignments)
+}
+
+/// Returns the entry for the given configuration blob in FW_CONFIG's DTB registry.
+fn registry_entry<'a>(registry: &Node<'a>, name: &str) -> Node<'a> {
+    registry
+        .child(name)
+        .unwrap_or_else(|| panic!("FW_CONFIG has no {name} entry"))
+}
+
+/// Returns the address at which BL2 loaded the configuration blob with the given registry entry.
+fn load_address(entry: &Node) -> u64 {
+    entry
+        .property_u64("load-address")
+        .unwrap_or_else(|| panic!("{} has no load-address", entry.name()))
+}
+
+/// Returns the devicetree blob at the given address in trusted SRAM, as long as its header says.
+///
+/// # Safety
+///
+/// `address` must be the address of a devicetree blob which stays mapped and unmodified while the
+/// returned slice is in use.
+unsafe fn sram_blob(address: usize) -> &'static [u8] {
+    let sram = MemoryMap::TRUSTED_SRAM;
+    assert!(
+        sram.contains(&address) && sram.contains(&(address + FDT_HEADER_SIZE - 1)),
+        "Devicetree blob {address:#x} outside trusted SRAM"
+    );
+    // SAFETY: The header is within trusted SRAM, which is mapped, and our caller promised that
+    // nothing modifies it.
+    let header = unsafe { slice::from_raw_parts(address as *const u8, FDT_HEADER_SIZE) };
+    let size = Fdt::total_size(header).expect("Invalid devicetree blob header");
+    assert!(
+        sram.contains(&(address + size - 1)),
+        "Devicetree blob {address:#x} extends outside trusted SRAM"
+    );
+    // SAFETY: The whole blob is within trusted SRAM, and our caller promised that nothing modifies
+    // it.
+    unsafe { slice::from_raw_parts(address as *const u8, size) }
+}
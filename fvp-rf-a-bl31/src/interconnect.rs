@@ -0,0 +1,78 @@
+// Copyright The Rusted Firmware-A Contributors.
+//
+// SPDX-License-Identifier: BSD-3-Clause
+
+//! The FVP's CCI-550 cache coherent interconnect.
+//!
+//! By default the FVP is run with `cci550.force_on_from_start=1`, so both clusters are coherent
+//! from reset and BL31 leaves the CCI alone. With the `cci` feature, BL31 instead adds each cluster
+//! to the coherency domain when it powers up, and removes it when it powers down, as on platforms
+//! without hardware-assisted coherency.
+
+#[cfg(feature = "cci")]
+use crate::map_peripheral;
+#[cfg(feature = "cci")]
+use arm_fvp_base_pac::{Cci550Map, arm_cci::Cci5x0};
+use arm_fvp_base_pac::{PhysicalInstance, arm_cci::Cci5x0Registers};
+#[cfg(not(feature = "cci"))]
+use rf_a_bl31::interconnect::HardwareCoherency;
+#[cfg(feature = "cci")]
+use rf_a_bl31::interconnect::Interconnect;
+
+/// The interconnect control used by the FVP PSCI implementation.
+#[cfg(feature = "cci")]
+pub type FvpInterconnect = FvpCci;
+
+/// The interconnect control used by the FVP PSCI implementation.
+#[cfg(not(feature = "cci"))]
+pub type FvpInterconnect = HardwareCoherency;
+
+/// Creates the interconnect control for the CCI-550 at the given address.
+#[cfg(feature = "cci")]
+pub fn new_interconnect(cci: PhysicalInstance<Cci5x0Registers>) -> FvpInterconnect {
+    FvpCci {
+        cci: Cci5x0::new(map_peripheral(cci)),
+    }
+}
+
+/// Creates the interconnect control, which ignores the CCI-550 as it is controlled by hardware.
+#[cfg(not(feature = "cci"))]
+pub fn new_interconnect(_cci: PhysicalInstance<Cci5x0Registers>) -> FvpInterconnect {
+    HardwareCoherency
+}
+
+/// Software control of the CCI-550 slave interfaces to which the clusters are connected.
+#[cfg(feature = "cci")]
+pub struct FvpCci {
+    cci: Cci5x0<'static>,
+}
+
+#[cfg(feature = "cci")]
+impl FvpCci {
+    /// Returns the index of the CCI-550 slave interface to which the given cluster is connected.
+    fn interface(cluster: usize) -> usize {
+        match cluster {
+            0 => Cci550Map::CLUSTER0,
+            1 => Cci550Map::CLUSTER1,
+            _ => panic!("Cluster {cluster} isn't connected to the CCI-550"),
+        }
+    }
+}
+
+#[cfg(feature = "cci")]
+impl Interconnect for FvpCci {
+    unsafe fn enter_coherency(&mut self, cluster: usize) {
+        // SAFETY: Our caller promised that the cluster doesn't allocate shareable data into its
+        // caches until this returns.
+        unsafe { self.cci.add_master_to_coherency(Self::interface(cluster)) }
+    }
+
+    unsafe fn exit_coherency(&mut self, cluster: usize) {
+        // SAFETY: Our caller promised that the cluster's caches have been cleaned and invalidated,
+        // and that it won't allocate shareable data into them until it is added back.
+        unsafe {
+            self.cci
+                .remove_master_from_coherency(Self::interface(cluster))
+        }
+    }
+}
@@ -0,0 +1,123 @@
+// Copyright The Rusted Firmware-A Contributors.
+//
+// SPDX-License-Identifier: BSD-3-Clause
+
+//! Lock ordering for the FVP peripheral locks.
+//!
+//! The FVP peripherals are each protected by their own lock, so that unrelated operations on
+//! different cores don't contend. To avoid deadlocks, a core which needs to hold more than one of
+//! them at a time must acquire them in increasing [`LockLevel`] order. In debug builds this is
+//! checked on every acquisition, and a violation panics rather than risking a deadlock which would
+//! only show up under the right interleaving between cores.
+
+use crate::Fvp;
+use core::{
+    ops::{Deref, DerefMut},
+    sync::atomic::{AtomicU32, Ordering},
+};
+use rf_a_bl31::{
+    context::CoresImpl,
+    debug::DEBUG,
+    platform::Platform,
+    reexports::percore::Cores,
+    spin_mutex::{SpinMutex, SpinMutexGuard},
+};
+
+/// The position of each FVP peripheral lock in the lock order.
+///
+/// A lock may only be acquired while the current core holds no lock of the same or a higher level.
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
+#[repr(u8)]
+pub enum LockLevel {
+    /// The system peripheral, which is also used by the NV counters.
+    System,
+    /// The saved GIC state for system suspend.
+    GicContext,
+    /// The power controller.
+    PowerController,
+    /// The cache coherent interconnect.
+    Interconnect,
+    /// The generic timer control frame.
+    TimerControl,
+    /// The generic timer CNTCTLBase frame, which is programmed using the frequency from the control
+    /// frame.
+    TimerCtl,
+}
+
+impl LockLevel {
+    const fn bit(self) -> u32 {
+        1 << self as u8
+    }
+}
+
+/// A bitmask of the [`LockLevel`]s currently held by each core, only maintained in debug builds.
+static HELD_LEVELS: [AtomicU32; Fvp::CORE_COUNT] = [const { AtomicU32::new(0) }; Fvp::CORE_COUNT];
+
+/// A spin lock with a fixed place in the FVP lock order.
+pub struct OrderedMutex<T> {
+    level: LockLevel,
+    inner: SpinMutex<T>,
+}
+
+impl<T> OrderedMutex<T> {
+    /// Creates a new lock at the given level of the lock order.
+    pub const fn new(level: LockLevel, value: T) -> Self {
+        Self {
+            level,
+            inner: SpinMutex::new(value),
+        }
+    }
+
+    /// Acquires the lock, spinning until it is available.
+    ///
+    /// In debug builds, panics if the current core already holds a lock at the same or a higher
+    /// level.
+    pub fn lock(&self) -> OrderedMutexGuard<'_, T> {
+        if DEBUG {
+            let held = HELD_LEVELS[CoresImpl::<Fvp>::core_index()].load(Ordering::Relaxed);
+            assert!(
+                held & !(self.level.bit() - 1) == 0,
+                "Lock order violation: acquiring {:?} while holding levels {held:#x}",
+                self.level
+            );
+        }
+        let guard = self.inner.lock();
+        if DEBUG {
+            HELD_LEVELS[CoresImpl::<Fvp>::core_index()]
+                .fetch_or(self.level.bit(), Ordering::Relaxed);
+        }
+        OrderedMutexGuard {
+            level: self.level,
+            guard,
+        }
+    }
+}
+
+/// A guard for an [`OrderedMutex`], which releases it when dropped.
+pub struct OrderedMutexGuard<'a, T> {
+    level: LockLevel,
+    guard: SpinMutexGuard<'a, T>,
+}
+
+impl<T> Deref for OrderedMutexGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.guard
+    }
+}
+
+impl<T> DerefMut for OrderedMutexGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.guard
+    }
+}
+
+impl<T> Drop for OrderedMutexGuard<'_, T> {
+    fn drop(&mut self) {
+        if DEBUG {
+            HELD_LEVELS[CoresImpl::<Fvp>::core_index()]
+                .fetch_and(!self.level.bit(), Ordering::Relaxed);
+        }
+    }
+}
@@ -8,26 +8,41 @@
 #![no_std]
 
 mod config;
+mod fw_config;
+mod interconnect;
+mod lock_order;
 
-use self::config::{FVP_CLUSTER_COUNT, FVP_MAX_CPUS_PER_CLUSTER, FVP_MAX_PE_PER_CPU};
+use self::config::{
+    FVP_CLUSTER_COUNT, FVP_MAX_CPUS_PER_CLUSTER, FVP_MAX_PE_PER_CPU, FVP_RUNTIME_CONSOLE,
+    FVP_RUNTIME_CONSOLE_CONFIG,
+};
+use self::{
+    fw_config::{FwConfig, HwConfig},
+    interconnect::{FvpInterconnect, new_interconnect},
+    lock_order::{LockLevel, OrderedMutex},
+};
 use arm_fvp_base_pac::{
     MemoryMap, Peripherals, PhysicalInstance,
+    arm_cci::Cci5x0Registers,
     arm_generic_timer::memory_mapped::{
         CntAcr, CntControlBase, CntCtlBase, GenericTimerControl, GenericTimerCtl,
     },
     power_controller::{FvpPowerController, FvpPowerControllerRegisters, SystemStatus},
-    system::{FvpSystemPeripheral, FvpSystemRegisters, SystemConfigFunction},
+    system::{BoardRevision, FvpSystemPeripheral, SystemConfigFunction},
 };
-use arm_pl011_uart::{Uart, UniqueMmioPointer};
+use arm_pl011_uart::UniqueMmioPointer;
 #[cfg(feature = "pauth")]
 use core::arch::asm;
 use core::{
     mem::offset_of,
     ops::{Range, RangeInclusive},
     ptr::NonNull,
+    slice,
 };
 #[cfg(feature = "pauth")]
 use rf_a_bl31::reexports::arm_sysregs::read_cntpct_el0;
+#[cfg(feature = "pauth")]
+use rf_a_bl31::rng;
 #[cfg(feature = "rme")]
 use rf_a_bl31::services::rmmd::{
     RMM_SHARED_BUFFER_SIZE,
@@ -36,26 +51,32 @@ use rf_a_bl31::services::rmmd::{
 };
 use rf_a_bl31::{
     aarch64::{dsb_ish, dsb_sy, wfi},
-    all_asm, asm_macros_common, asm_macros_common_purge, bl31_warm_entrypoint,
+    affinity_core_position, all_asm, asm_macros_common, asm_macros_common_purge,
+    bl31_warm_entrypoint,
     context::{CoresImpl, EntryPointInfo},
     cpu::{aem_generic::AemGeneric, define_cpu_ops},
     cpu_extensions::{
-        CpuExtension, amu::Amu, fgt::Fgt, fgt2::Fgt2, hcx::Hcx, mpam::Mpam, mte2::MemoryTagging,
-        pmuv3::MultiThreadedPmu, ras::Ras, sctlr2::Sctlr2, simd::Simd, spe::StatisticalProfiling,
-        sys_reg_trace::SysRegTrace, tcr2::Tcr2, trbe::TraceBufferNonSecure, trf::TraceFiltering,
+        CpuExtension, amu::Amu, brbe::Brbe, fgt::Fgt, fgt2::Fgt2, hcx::Hcx, mpam::Mpam,
+        mte2::MemoryTagging, pmuv3::MultiThreadedPmu, ras::Ras, sctlr2::Sctlr2, scxt::Scxtnum,
+        simd::Simd, spe::StatisticalProfiling, sys_reg_trace::SysRegTrace, tcr2::Tcr2,
+        trbe::TraceBufferNonSecure, trf::TraceFiltering,
     },
     debug::DEBUG,
     errata_framework::define_errata_list,
     gic_debug_macros, gic_debug_macros_purge,
-    gicv3::{Gic, GicConfig, InterruptConfig},
-    logger::LockedWriter,
+    gicv3::{Gic, GicConfig, InterruptConfig, SgiRegistry, SgiUser},
+    interconnect::Interconnect,
+    logger::pl011::Pl011Console,
     naked_asm,
+    nv_counter::{
+        BootRequest, MmioNvCounters, NvCounterError, NvCounterId, NvCounterRegisters, NvCounters,
+    },
     pagetable::{
-        IdMap, MT_DEVICE, MT_MEMORY_EL3,
+        IdMap, MT_DEVICE, MT_MEMORY_EL3, MT_MEMORY_NS, MT_RO_DATA_EL3,
         early_pagetable::{EarlyRegion, define_early_mapping},
     },
     panic_handler,
-    platform::{DummyService, Platform},
+    platform::{Board, DummyService, Platform, Soc, topology::AffinityTopology},
     reexports::{
         aarch64_paging::{
             descriptor::VirtualAddress,
@@ -73,17 +94,26 @@ use rf_a_bl31::{
         arm_sysregs::{CntfrqEl0, IccSreEl3, MpidrEl1, read_mpidr_el1, write_cntfrq_el0},
         log,
         percore::Cores,
-        spin::mutex::SpinMutex,
+        spin::Once,
     },
+    runtime_config::{ConsoleSelection, RuntimeConfig, runtime_config},
     services::{
-        arch::WorkaroundSupport,
+        arch::{
+            ARM_JEP106_CONTINUATION_CODE, ARM_JEP106_IDENTIFICATION_CODE, WorkaroundSupport,
+            soc_id_version,
+        },
+        ffa::spmd::SpmcManifest,
         psci::{
-            CPU_POWER_LEVEL, PlatformPowerStateInterface, PowerStateType, PsciCompositePowerState,
-            PsciPlatformInterface, PsciPlatformOptionalFeatures,
+            CPU_POWER_LEVEL, PlatformPowerStateInterface, PowerStateTable, PowerStateTableEntry,
+            PowerStateType, PsciCompositePowerState, PsciPlatformInterface,
+            PsciPlatformOptionalFeatures,
         },
-        trng::NotSupportedTrngPlatformImpl,
+        trng::RndrTrngPlatformImpl,
     },
+    spin_mutex::SpinMutex,
     statics,
+    system_suspend_notifier::{SystemSuspendNotifier, SystemSuspendNotifiers},
+    timer::poll_until,
 };
 
 /// Converts `RangeInclusive` into `Range`.
@@ -106,7 +136,15 @@ const fn aligned_range_covering(
     start_address..end_address
 }
 
-const UART_RANGE: Range<usize> = from_inclusive_range(&MemoryMap::UART0);
+/// The UART used for runtime logs by default, as selected by `FVP_RUNTIME_CONSOLE`.
+const UART_RANGE: Range<usize> = match FVP_RUNTIME_CONSOLE {
+    ConsoleSelection::Shared => from_inclusive_range(&MemoryMap::UART0),
+    ConsoleSelection::SecureOnly => from_inclusive_range(&MemoryMap::UART2),
+};
+
+/// The UART given to RMM. This must not be the secure-only UART, as RMM can't access it.
+#[cfg(feature = "rme")]
+const RMM_UART_BASE: usize = *MemoryMap::UART0.start();
 
 const CRASH_UART_BASE: usize = *MemoryMap::UART1.start();
 
@@ -114,17 +152,32 @@ const CRASH_UART_BASE: usize = *MemoryMap::UART1.start();
 const DEVICE0_RANGE: Range<usize> =
     aligned_range_covering(&MemoryMap::VE_SYSTEM, &MemoryMap::POWER_CONTROLLER);
 
-/// Peripheral range from REFCLK_CNTCONTROL to AP_REFCLK_CNTBASE1.
-const DEVICE1_RANGE: Range<usize> = aligned_range_covering(
-    &MemoryMap::REFCLK_CNTCONTROL,
-    &MemoryMap::AP_REFCLK_CNTBASE1,
-);
+/// Peripheral range from CCI_550 to AP_REFCLK_CNTBASE1.
+const DEVICE1_RANGE: Range<usize> =
+    aligned_range_covering(&MemoryMap::CCI_550, &MemoryMap::AP_REFCLK_CNTBASE1);
 
 /// Peripherals range that covers the GIC.
 const DEVICE2_RANGE: Range<usize> = aligned_range_covering(&MemoryMap::GICD, &MemoryMap::GICR);
 
-const PLATFORM_CORE_COUNT: usize =
-    FVP_CLUSTER_COUNT * FVP_MAX_CPUS_PER_CLUSTER * FVP_MAX_PE_PER_CPU;
+/// Peripheral range from TRUSTED_RNG to TRUSTED_ROOT_KEY_STORAGE, including the NV counters.
+const DEVICE3_RANGE: Range<usize> = aligned_range_covering(
+    &MemoryMap::TRUSTED_RNG,
+    &MemoryMap::TRUSTED_ROOT_KEY_STORAGE,
+);
+
+const FVP_TOPOLOGY: AffinityTopology = AffinityTopology {
+    clusters: FVP_CLUSTER_COUNT,
+    cores_per_cluster: FVP_MAX_CPUS_PER_CLUSTER,
+    threads_per_core: FVP_MAX_PE_PER_CPU,
+};
+const PLATFORM_CORE_COUNT: usize = FVP_TOPOLOGY.core_count();
+
+/// How long to wait for an in-flight power off of a CPU to complete before powering it on again.
+const POWER_OFF_TIMEOUT_MS: u64 = 100;
+
+/// Non-secure DRAM which is mapped into EL3 so that the SPMD can write partition information to the
+/// normal world's RX buffer, if the normal world maps its RX/TX buffers here.
+const NS_BUFFER_RANGE: Range<usize> = 0x8f00_0000..0x8f20_0000;
 
 const ARM_TRUSTED_SRAM_RANGE: Range<usize> = from_inclusive_range(&MemoryMap::TRUSTED_SRAM);
 const ARM_SHARED_RAM_BASE: usize = ARM_TRUSTED_SRAM_RANGE.start;
@@ -153,19 +206,13 @@ const GPT_L0: MemoryRegion = MemoryRegion::new(ARM_GPT_L0_BASE, ARM_GPT_L0_BASE
 #[cfg(feature = "rme")]
 const GPT_L1: MemoryRegion = MemoryRegion::new(ARM_GPT_L1_BASE, ARM_GPT_L1_BASE + ARM_GPT_L1_SIZE);
 
-const DEVICE_REGIONS: [MemoryRegion; 3] = [
+const DEVICE_REGIONS: [MemoryRegion; 4] = [
     MemoryRegion::new(DEVICE0_RANGE.start, DEVICE0_RANGE.end),
     MemoryRegion::new(DEVICE1_RANGE.start, DEVICE1_RANGE.end),
     MemoryRegion::new(DEVICE2_RANGE.start, DEVICE2_RANGE.end),
+    MemoryRegion::new(DEVICE3_RANGE.start, DEVICE3_RANGE.end),
 ];
 
-// TODO: These addresses should be parsed from FW_CONFIG
-/// The physical address of the SPMC manifest blob.
-const TOS_FW_CONFIG_ADDRESS: u64 = 0x0400_1500;
-const NT_FW_CONFIG_ADDRESS: u64 = 0x8000_0000;
-const HW_CONFIG_ADDRESS: u64 = 0x07f0_0000;
-const HW_CONFIG_ADDRESS_NS: u64 = 0x8200_0000;
-
 const EARLY_REGIONS: [EarlyRegion; 2] = [
     EarlyRegion {
         address_range: ARM_TRUSTED_SRAM_RANGE,
@@ -179,19 +226,25 @@ const EARLY_REGIONS: [EarlyRegion; 2] = [
 
 define_early_mapping!(Fvp, EARLY_REGIONS);
 
-const fn secure_sgi_configuration(index: u32) -> (IntId, InterruptConfig) {
-    (
-        IntId::sgi(index),
-        InterruptConfig {
-            priority: HIGHEST_S_PRIORITY,
-            group: Group::Secure(SecureIntGroup::Group1S),
-            trigger: Trigger::Edge,
-        },
-    )
-}
+/// The configuration of the secure SGIs used by the SPMC.
+/// The SDEI event used to delegate RAS errors to the normal world.
+const RAS_SDEI_EVENT: u32 = 5000;
+/// The SDEI event used to notify the normal world of a secure watchdog timeout.
+const WATCHDOG_SDEI_EVENT: u32 = 8000;
+
+const SECURE_SGI_CONFIG: InterruptConfig = InterruptConfig {
+    priority: HIGHEST_S_PRIORITY,
+    group: Group::Secure(SecureIntGroup::Group1S),
+    trigger: Trigger::Edge,
+};
 
 fn device_regions_include<T>(physical_instance: &PhysicalInstance<T>) -> bool {
-    let start = physical_instance.pa();
+    device_regions_contain::<T>(physical_instance.pa())
+}
+
+/// Returns whether a `T` at the given physical address is entirely within one of the
+/// DEVICE_REGIONS.
+fn device_regions_contain<T>(start: usize) -> bool {
     let end = start + size_of::<T>() - 1;
 
     DEVICE_REGIONS.iter().any(|region| {
@@ -211,8 +264,71 @@ fn map_peripheral<T>(physical_instance: PhysicalInstance<T>) -> UniqueMmioPointe
     unsafe { UniqueMmioPointer::new(NonNull::new(physical_instance.pa() as *mut T).unwrap()) }
 }
 
+/// Creates an identity mapped `UniqueMmioPointer` to a peripheral whose address was discovered from
+/// HW_CONFIG. The function will panic if the peripheral is not part of the mapped DEVICE_REGIONS.
+///
+/// # Safety
+///
+/// `pa` must be the physical address of a `T` peripheral, and there must be no other references
+/// to it, including through `Peripherals`.
+unsafe fn map_discovered_peripheral<T>(pa: usize) -> UniqueMmioPointer<'static, T> {
+    assert!(
+        device_regions_contain::<T>(pa),
+        "Peripheral {pa:#x} from HW_CONFIG isn't mapped"
+    );
+
+    // SAFETY: Our caller promised that `pa` is the address of a unique `T` peripheral, and it
+    // remains valid after turning on the MMU because of the identity mapping of the DEVICE_REGIONS.
+    unsafe { UniqueMmioPointer::new(NonNull::new(pa as *mut T).unwrap()) }
+}
+
+/// The configuration passed by BL2, parsed during early cold boot.
+static FW_CONFIG: Once<FwConfig> = Once::new();
+
+/// Returns the configuration passed by BL2.
+fn fw_config() -> &'static FwConfig {
+    FW_CONFIG.get().expect("FW_CONFIG not parsed yet")
+}
+
 static FVP_PSCI_PLATFORM_IMPL: SpinMutex<Option<FvpPsciPlatformImpl>> = SpinMutex::new(None);
 
+/// The implementation defined SoC ID of the FVP, reported by the arch SOC_ID SMC.
+const FVP_SOC_ID: u16 = 0;
+
+static FVP_SYSTEM: Once<OrderedMutex<FvpSystemPeripheral>> = Once::new();
+
+static FVP_SYSTEM_TIMER: Once<FvpSystemTimer> = Once::new();
+
+static FVP_NV_COUNTERS: SpinMutex<Option<FvpNvCounters>> = SpinMutex::new(None);
+
+/// The FVP trusted NV counters, plus the non-volatile flags in the system peripheral which are used
+/// to record boot requests.
+struct FvpNvCounters {
+    counters: MmioNvCounters<'static>,
+    system: &'static OrderedMutex<FvpSystemPeripheral<'static>>,
+}
+
+impl NvCounters for FvpNvCounters {
+    fn read(&self, counter: NvCounterId) -> Result<u32, NvCounterError> {
+        self.counters.read(counter)
+    }
+
+    fn write(&mut self, counter: NvCounterId, value: u32) -> Result<(), NvCounterError> {
+        self.counters.write(counter, value)
+    }
+
+    fn write_boot_request(&mut self, request: BootRequest) -> Result<(), NvCounterError> {
+        let mut system = self.system.lock();
+        system.clear_non_volatile_flags(u32::MAX);
+        system.set_non_volatile_flags(request as u32);
+        if system.non_volatile_flags() == request as u32 {
+            Ok(())
+        } else {
+            Err(NvCounterError::WriteFailed)
+        }
+    }
+}
+
 define_cpu_ops!(Fvp, [AemGeneric]);
 define_errata_list!(Fvp, []);
 
@@ -334,42 +450,133 @@ static RAS: Ras<{ Fvp::CORE_COUNT }, Fvp> = Ras::new();
 static TCR2: Tcr2<{ Fvp::CORE_COUNT }, Fvp> = Tcr2::new();
 static SIMD: Simd<{ Fvp::CORE_COUNT }, Fvp> = Simd::simd();
 static SCTLR2: Sctlr2<{ Fvp::CORE_COUNT }, Fvp> = Sctlr2::new();
+static SCXTNUM: Scxtnum<{ Fvp::CORE_COUNT }, Fvp> = Scxtnum::new();
+
+/// The SoC of the Fixed Virtual Platform, with the AEM cores in two clusters.
+pub struct FvpSoc;
 
 // SAFETY: `core_position` is indeed a naked function, doesn't access the stack or any other memory,
-// only clobbers x0-x5, and returns a unique core index as long as `FVP_MAX_CPUS_PER_CLUSTER` and
-// `FVP_MAX_PE_PER_CPU` are correct.
-unsafe impl Platform for Fvp {
+// only clobbers x0-x5, and returns a unique core index as long as `FVP_TOPOLOGY` is correct.
+// `dump_registers` is also a naked function which only uses the crash console and the registers it
+// is documented to clobber.
+unsafe impl Soc for FvpSoc {
     const CORE_COUNT: usize = PLATFORM_CORE_COUNT;
     const CACHE_WRITEBACK_GRANULE: usize = 1 << 6;
 
-    const PAGE_HEAP_PAGE_COUNT: usize = 6;
+    const GIC_CONFIG: GicConfig = GicConfig {
+        sgis: SgiRegistry::EMPTY.claim_range(8, 15, SgiUser::Spmc, SECURE_SGI_CONFIG),
+        interrupts_config: &[],
+    };
+
+    // Set write-through mode to ensure all written values are propagated to system memory.
+    // This guarantees correct Once and Mutex behavior.
+    const NORMAL_MEMORY_MAIR_ATTRIBUTE: MairAttribute = MairAttribute::normal(
+        NormalMemory::WriteThroughTransientReadWriteAllocate,
+        NormalMemory::WriteThroughTransientReadWriteAllocate,
+    );
+
+    fn mpidr_is_valid(mpidr: MpidrEl1) -> bool {
+        FVP_TOPOLOGY.mpidr_is_valid(mpidr)
+    }
+
+    fn arch_workaround_1_supported() -> WorkaroundSupport {
+        WorkaroundSupport::SafeButNotRequired
+    }
+
+    fn arch_workaround_1() {}
+
+    fn arch_workaround_2_supported() -> WorkaroundSupport {
+        WorkaroundSupport::SafeButNotRequired
+    }
+
+    fn arch_workaround_2() {}
+
+    fn arch_workaround_3_supported() -> WorkaroundSupport {
+        WorkaroundSupport::SafeButNotRequired
+    }
+
+    fn arch_workaround_3() {}
+
+    fn arch_workaround_4_supported() -> WorkaroundSupport {
+        WorkaroundSupport::SafeButNotRequired
+    }
+
+    fn soc_id_version() -> Option<u32> {
+        Some(soc_id_version(
+            ARM_JEP106_CONTINUATION_CODE,
+            ARM_JEP106_IDENTIFICATION_CODE,
+            FVP_SOC_ID,
+        ))
+    }
+
+    /// Returns the board revision from the V2M `SYS_ID` register.
+    fn soc_id_revision() -> u32 {
+        let system_id = FVP_SYSTEM
+            .get()
+            .expect("FVP system peripheral not initialised")
+            .lock()
+            .system_id();
+        match system_id.map(|system_id| system_id.revision) {
+            Ok(BoardRevision::RevA) => 0,
+            Ok(BoardRevision::RevB) => 1,
+            Ok(BoardRevision::RevC) => 2,
+            Err(e) => {
+                log::warn!("Invalid SYS_ID: {e:?}");
+                0
+            }
+        }
+    }
+
+    /// Calculates core linear index as: ClusterId * FVP_MAX_CPUS_PER_CLUSTER * FVP_MAX_PE_PER_CPU +
+    /// CPUId * FVP_MAX_PE_PER_CPU + ThreadId
+    #[unsafe(naked)]
+    extern "C" fn core_position(mpidr: u64) -> usize {
+        affinity_core_position!(FVP_TOPOLOGY)
+    }
+
+    /// Dumps relevant GIC registers.
+    ///
+    /// Clobbers x0-x11, x16, x17, sp.
+    #[unsafe(naked)]
+    unsafe extern "C" fn dump_registers() {
+        naked_asm!(
+            asm_macros_common!(),
+            gic_debug_macros!(),
+            "mov_imm	x16, {GICD_BASE}",
+            "arm_print_gic_regs",
+            "ret",
+
+            gic_debug_macros_purge!(),
+            asm_macros_common_purge!(),
+            DEBUG = const DEBUG as i32,
+            ICC_SRE_SRE_BIT = const IccSreEl3::SRE.bits(),
+            GICD_ISPENDR = const offset_of!(Gicd, ispendr),
+            GICD_BASE = const *MemoryMap::GICD.start(),
+        );
+    }
+}
+
+// SAFETY: `cold_boot_handler` and the crash console functions are naked functions which don't use
+// the stack, and only clobber the registers they are documented to clobber.
+unsafe impl Board for Fvp {
+    type Soc = FvpSoc;
+
+    const PAGE_HEAP_PAGE_COUNT: usize = 7;
 
     #[cfg(feature = "rme")]
     const RMM_SHARED_BUFFER_START: usize = 0xffbf_f000;
 
-    type LogSinkImpl = LockedWriter<Uart<'static>>;
+    type LogSinkImpl = Pl011Console;
     type IdMap = IdMap<{ Self::PAGE_HEAP_PAGE_COUNT }>;
     type PsciPlatformImpl = FvpPsciPlatformImpl<'static>;
-    // TODO: Implement TRNG for FVP.
-    type TrngPlatformImpl = NotSupportedTrngPlatformImpl;
+    type TrngPlatformImpl = RndrTrngPlatformImpl<TRNG_UUID>;
 
     type PlatformServiceImpl = DummyService;
-
-    const GIC_CONFIG: GicConfig = GicConfig {
-        interrupts_config: &[
-            secure_sgi_configuration(8),
-            secure_sgi_configuration(9),
-            secure_sgi_configuration(10),
-            secure_sgi_configuration(11),
-            secure_sgi_configuration(12),
-            secure_sgi_configuration(13),
-            secure_sgi_configuration(14),
-            secure_sgi_configuration(15),
-        ],
-    };
+    type NvCountersImpl = FvpNvCounters;
 
     const CPU_EXTENSIONS: &'static [&'static dyn CpuExtension] = &[
         &AMU,
+        &Brbe,
         &FGT,
         &FGT2,
         &HCX,
@@ -378,6 +585,7 @@ unsafe impl Platform for Fvp {
         &MultiThreadedPmu,
         &RAS,
         &SCTLR2,
+        &SCXTNUM,
         &SIMD,
         &StatisticalProfiling,
         &SysRegTrace,
@@ -386,44 +594,108 @@ unsafe impl Platform for Fvp {
         &TraceFiltering,
     ];
 
-    // Set write-through mode to ensure all written values are propagated to system memory.
-    // This guarantees correct Once and Mutex behavior.
-    const NORMAL_MEMORY_MAIR_ATTRIBUTE: MairAttribute = MairAttribute::normal(
-        NormalMemory::WriteThroughTransientReadWriteAllocate,
-        NormalMemory::WriteThroughTransientReadWriteAllocate,
-    );
+    const SDEI_EVENTS: &'static [u32] = &[RAS_SDEI_EVENT, WATCHDOG_SDEI_EVENT];
+
+    fn runtime_config(_arg0: u64, _arg1: u64, _arg2: u64, _arg3: u64) -> RuntimeConfig {
+        // TODO: Parse this from FW_CONFIG.
+        RuntimeConfig {
+            console: FVP_RUNTIME_CONSOLE,
+            ..RuntimeConfig::DEFAULT
+        }
+    }
+
+    fn init_with_early_mapping(arg0: u64, arg1: u64, _arg2: u64, _arg3: u64) {
+        FW_CONFIG.call_once(|| {
+            // SAFETY: BL2 passes the address of its `bl_params_t` in x0 and of FW_CONFIG in x1. Both
+            // are in trusted SRAM, which is mapped by the early mapping.
+            unsafe { FwConfig::parse(arg0, arg1) }
+        });
+    }
 
     fn init(_arg0: u64, _arg1: u64, _arg2: u64, _arg3: u64) {
         let peripherals = Peripherals::take().unwrap();
 
-        let uart_pointer = map_peripheral(peripherals.uart0);
+        let hw_config = &fw_config().hw_config;
+        // SAFETY: BL2 loaded HW_CONFIG into this region, which `map_extra_regions` mapped and which
+        // nothing writes to.
+        let hw_config = HwConfig::parse(unsafe {
+            slice::from_raw_parts(hw_config.start as *const u8, hw_config.len())
+        });
+
+        let uart_pointer = match runtime_config().console {
+            // SAFETY: HW_CONFIG describes the shared UART at this address, and the corresponding
+            // field of `peripherals` is never used.
+            ConsoleSelection::Shared => unsafe { map_discovered_peripheral(hw_config.uart_base) },
+            ConsoleSelection::SecureOnly => map_peripheral(peripherals.uart2),
+        };
 
         LOGGER
-            .init(LockedWriter::new(Uart::new(uart_pointer)))
+            .init(
+                Pl011Console::with_config(uart_pointer, &FVP_RUNTIME_CONSOLE_CONFIG)
+                    .expect("Invalid UART configuration"),
+            )
             .expect("Failed to initialise logger");
 
+        let system = FVP_SYSTEM.call_once(|| {
+            OrderedMutex::new(
+                LockLevel::System,
+                FvpSystemPeripheral::new(map_peripheral(peripherals.system)),
+            )
+        });
+
+        let timer = FVP_SYSTEM_TIMER.call_once(|| {
+            FvpSystemTimer::new(peripherals.refclk_cntcontrol, peripherals.ap_refclk_cntctl)
+        });
+        timer.init();
+
         let psci_platform = FvpPsciPlatformImpl::new(
             peripherals.power_controller,
-            peripherals.system,
-            peripherals.refclk_cntcontrol,
-            peripherals.ap_refclk_cntctl,
+            peripherals.cci_550,
+            system,
+            timer,
         );
 
-        psci_platform.init_generic_timer();
+        // SAFETY: The earlier boot stages enabled the primary cluster's caches after adding it to
+        // the coherency domain, or it was never removed from it, so this changes nothing its caches
+        // rely on. It is done anyway in case BL31 is the first stage to control the interconnect.
+        unsafe {
+            psci_platform
+                .interconnect
+                .lock()
+                .enter_coherency(FvpPsciPlatformImpl::current_cluster());
+        }
 
         *FVP_PSCI_PLATFORM_IMPL.lock() = Some(psci_platform);
 
+        let nv_counters_start = *MemoryMap::TRUSTED_NV_COUNTERS.start();
+        assert!(DEVICE3_RANGE.contains(&nv_counters_start));
+        // SAFETY: The trusted NV counters aren't part of `Peripherals`, and this is the only place
+        // we create a pointer to them. They are identity mapped as part of DEVICE3_RANGE.
+        let nv_counters = unsafe {
+            UniqueMmioPointer::new(
+                NonNull::new(nv_counters_start as *mut NvCounterRegisters).unwrap(),
+            )
+        };
+        *FVP_NV_COUNTERS.lock() = Some(FvpNvCounters {
+            counters: MmioNvCounters::new(nv_counters),
+            system,
+        });
+
         // Write warm boot entry point the shared memory, so secondary cores can pick it up during
         // boot.
-        // Safety: WARM_ENTRYPOINT_FIELD points to a valid, writable address.
+        // Safety: WARM_ENTRYPOINT_FIELD points to a valid, writable address. It is written as device
+        // memory, so that it is visible to secondary cores whether or not the MMU is enabled.
         unsafe {
-            *WARM_ENTRYPOINT_FIELD = bl31_warm_entrypoint::<Fvp>;
+            WARM_ENTRYPOINT_FIELD.write_volatile(bl31_warm_entrypoint::<Fvp>);
         }
         dsb_sy();
 
         GIC.call_once(|| {
-            let gicd = map_peripheral(peripherals.gicd);
-            let mut gicr = map_peripheral(peripherals.gicr);
+            // SAFETY: HW_CONFIG describes the GIC distributor at this address, and the corresponding
+            // field of `peripherals` is never used.
+            let gicd = unsafe { map_discovered_peripheral(hw_config.gicd_base) };
+            // SAFETY: As above, for the first GIC redistributor.
+            let mut gicr = unsafe { map_discovered_peripheral(hw_config.gicr_base) };
             // SAFETY: `gicr` points to a continuously mapped GIC redistributor memory area until
             // the last redistributor block. There are no other references to this address range.
             unsafe { Gic::new(gicd, gicr.ptr_nonnull(), false) }
@@ -435,6 +707,16 @@ unsafe impl Platform for Fvp {
         // attributes.
         unsafe {
             idmap.map_region(&SHARED_RAM, MT_DEVICE);
+            idmap.map_region(
+                &MemoryRegion::new(NS_BUFFER_RANGE.start, NS_BUFFER_RANGE.end),
+                MT_MEMORY_NS,
+            );
+
+            let hw_config = &fw_config().hw_config;
+            idmap.map_region(
+                &MemoryRegion::new(hw_config.start, hw_config.end),
+                MT_RO_DATA_EL3,
+            );
 
             #[cfg(feature = "rme")]
             idmap.map_region(&GPT_L0, MT_MEMORY_EL3);
@@ -447,11 +729,16 @@ unsafe impl Platform for Fvp {
         }
     }
 
-    // This is only a toy implementation to generate a seemingly random 128-bit key from FP, LR and
-    // cntpct_el0 values. A production system must re-implement this function to generate keys from
-    // a reliable entropy source.
+    // Keys come from FEAT_RNG if it is implemented. Otherwise this falls back to a toy
+    // implementation to generate a seemingly random 128-bit key from FP, LR and cntpct_el0 values. A
+    // production system without FEAT_RNG must re-implement this function to generate keys from a
+    // reliable entropy source.
     #[cfg(feature = "pauth")]
     fn init_apkey() -> u128 {
+        if let Ok(key) = rng::read_rndr_u128() {
+            return key;
+        }
+
         let return_addr: u64;
         let frame_addr: u64;
         let cntpct = read_cntpct_el0().physicalcount();
@@ -478,11 +765,12 @@ unsafe impl Platform for Fvp {
 
     fn secure_entry_point() -> EntryPointInfo {
         let core_linear_id = CoresImpl::<Self>::core_index() as u64;
+        let fw_config = fw_config();
         EntryPointInfo {
-            pc: 0x0600_0000,
+            pc: fw_config.bl32_pc,
             args: [
-                TOS_FW_CONFIG_ADDRESS,
-                HW_CONFIG_ADDRESS,
+                fw_config.tos_fw_config,
+                fw_config.hw_config.start as u64,
                 0,
                 0,
                 core_linear_id,
@@ -493,10 +781,33 @@ unsafe impl Platform for Fvp {
         }
     }
 
+    fn spmc_manifest() -> SpmcManifest {
+        let fw_config = fw_config();
+        SpmcManifest {
+            spmc_id: fw_config.spmc_id,
+            version: fw_config.spmc_version,
+            secure_interrupts: &fw_config.secure_interrupts,
+            // Only the cores lose their state in the FVP's power down suspend states. The secure
+            // world's memory, including the SPMC's saved context, is retained.
+            resume_after_suspend: true,
+            ns_buffer_region: NS_BUFFER_RANGE,
+        }
+    }
+
     fn non_secure_entry_point() -> EntryPointInfo {
+        let fw_config = fw_config();
         EntryPointInfo {
-            pc: 0x8800_0000,
-            args: [NT_FW_CONFIG_ADDRESS, HW_CONFIG_ADDRESS_NS, 0, 0, 0, 0, 0, 0],
+            pc: fw_config.bl33_pc,
+            args: [
+                fw_config.nt_fw_config,
+                fw_config.hw_config_ns,
+                0,
+                0,
+                0,
+                0,
+                0,
+                0,
+            ],
         }
     }
 
@@ -508,74 +819,17 @@ unsafe impl Platform for Fvp {
         }
     }
 
-    fn mpidr_is_valid(mpidr: MpidrEl1) -> bool {
-        if mpidr.contains(MpidrEl1::MT) {
-            mpidr.aff3() == 0
-                && usize::from(mpidr.aff2()) < FVP_CLUSTER_COUNT
-                && usize::from(mpidr.aff1()) < FVP_MAX_CPUS_PER_CLUSTER
-                && usize::from(mpidr.aff0()) < FVP_MAX_PE_PER_CPU
-        } else {
-            mpidr.aff3() == 0
-                && mpidr.aff2() == 0
-                && usize::from(mpidr.aff1()) < FVP_CLUSTER_COUNT
-                && usize::from(mpidr.aff0()) < FVP_MAX_CPUS_PER_CLUSTER
-        }
-    }
-
     fn psci_platform() -> Option<Self::PsciPlatformImpl> {
         FVP_PSCI_PLATFORM_IMPL.lock().take()
     }
 
-    fn arch_workaround_1_supported() -> WorkaroundSupport {
-        WorkaroundSupport::SafeButNotRequired
+    fn register_system_suspend_notifiers(notifiers: &SystemSuspendNotifiers) {
+        notifiers.register(&GIC_SUSPEND);
+        notifiers.register(FVP_SYSTEM_TIMER.get().unwrap());
     }
 
-    fn arch_workaround_1() {}
-
-    fn arch_workaround_2_supported() -> WorkaroundSupport {
-        WorkaroundSupport::SafeButNotRequired
-    }
-
-    fn arch_workaround_2() {}
-
-    fn arch_workaround_3_supported() -> WorkaroundSupport {
-        WorkaroundSupport::SafeButNotRequired
-    }
-
-    fn arch_workaround_3() {}
-
-    fn arch_workaround_4_supported() -> WorkaroundSupport {
-        WorkaroundSupport::SafeButNotRequired
-    }
-
-    /// Calculates core linear index as: ClusterId * FVP_MAX_CPUS_PER_CLUSTER * FVP_MAX_PE_PER_CPU +
-    /// CPUId * FVP_MAX_PE_PER_CPU + ThreadId
-    #[unsafe(naked)]
-    extern "C" fn core_position(mpidr: u64) -> usize {
-        naked_asm!(
-            // Check for MT bit in MPIDR. If not set, shift MPIDR to left to make it look as if in a
-            // multi-threaded implementation.
-            "tst	x0, #{MPIDR_MT_MASK}",
-            "lsl	x3, x0, #{MPIDR_AFFINITY_BITS}",
-            "csel	x3, x3, x0, eq",
-            // Extract individual affinity fields from MPIDR.
-            "ubfx	x0, x3, #{MPIDR_AFF0_SHIFT}, #{MPIDR_AFFINITY_BITS}",
-            "ubfx	x1, x3, #{MPIDR_AFF1_SHIFT}, #{MPIDR_AFFINITY_BITS}",
-            "ubfx	x2, x3, #{MPIDR_AFF2_SHIFT}, #{MPIDR_AFFINITY_BITS}",
-            // Compute linear position.
-            "mov	x4, #{FVP_MAX_CPUS_PER_CLUSTER}",
-            "madd	x1, x2, x4, x1",
-            "mov	x5, #{FVP_MAX_PE_PER_CPU}",
-            "madd	x0, x1, x5, x0",
-            "ret",
-            MPIDR_MT_MASK = const MpidrEl1::MT.bits(),
-            MPIDR_AFF0_SHIFT = const MpidrEl1::AFF0_SHIFT,
-            MPIDR_AFF1_SHIFT = const MpidrEl1::AFF1_SHIFT,
-            MPIDR_AFF2_SHIFT = const MpidrEl1::AFF2_SHIFT,
-            FVP_MAX_CPUS_PER_CLUSTER = const FVP_MAX_CPUS_PER_CLUSTER,
-            MPIDR_AFFINITY_BITS = const MpidrEl1::AFFINITY_BITS,
-            FVP_MAX_PE_PER_CPU = const FVP_MAX_PE_PER_CPU,
-        );
+    fn nv_counters() -> Option<Self::NvCountersImpl> {
+        FVP_NV_COUNTERS.lock().take()
     }
 
     #[unsafe(naked)]
@@ -623,27 +877,6 @@ unsafe impl Platform for Fvp {
         );
     }
 
-    /// Dumps relevant GIC registers.
-    ///
-    /// Clobbers x0-x11, x16, x17, sp.
-    #[unsafe(naked)]
-    unsafe extern "C" fn dump_registers() {
-        naked_asm!(
-            asm_macros_common!(),
-            gic_debug_macros!(),
-            "mov_imm	x16, {GICD_BASE}",
-            "arm_print_gic_regs",
-            "ret",
-
-            gic_debug_macros_purge!(),
-            asm_macros_common_purge!(),
-            DEBUG = const DEBUG as i32,
-            ICC_SRE_SRE_BIT = const IccSreEl3::SRE.bits(),
-            GICD_ISPENDR = const offset_of!(Gicd, ispendr),
-            GICD_BASE = const *MemoryMap::GICD.start(),
-        );
-    }
-
     #[cfg(feature = "rme")]
     fn rme_prepare_manifest(buf: &mut [u8; RMM_SHARED_BUFFER_SIZE]) {
         use rf_a_bl31::services::rmmd::manifest::{
@@ -656,7 +889,7 @@ unsafe impl Platform for Fvp {
             plat_data: &[],
             plat_dram: &[
                 RmmMemoryBank {
-                    base: NT_FW_CONFIG_ADDRESS as usize,
+                    base: *MemoryMap::DRAM0.start(),
                     size: 0x7c00_0000,
                 },
                 RmmMemoryBank {
@@ -665,8 +898,7 @@ unsafe impl Platform for Fvp {
                 },
             ],
             plat_console: &[RmmConsoleInfo {
-                // Value from the pl011_uart crate.
-                base: UART_RANGE.start,
+                base: RMM_UART_BASE,
                 // Values from TF-A.
                 map_pages: 0x1,
                 name: *b"pl011\0\0\0",
@@ -723,6 +955,8 @@ unsafe impl Platform for Fvp {
 }
 
 const TRNG_REQ_WORDS: usize = 1;
+/// UUID of the FVP's FEAT_RNG entropy source.
+const TRNG_UUID: u128 = 0x23523c58_7448_4083_9d16_e3fab9f173bc;
 
 #[derive(PartialEq, PartialOrd, Debug, Eq, Ord, Clone, Copy)]
 enum FvpPowerState {
@@ -767,35 +1001,119 @@ impl FvpGicContext {
     }
 }
 
-static GIC_CONTEXT: SpinMutex<FvpGicContext> = SpinMutex::new(FvpGicContext::new());
+/// Saves the GIC distributor and redistributor state before the system is suspended, and restores
+/// it on resume.
+struct FvpGicSuspend {
+    context: OrderedMutex<FvpGicContext>,
+}
+
+impl SystemSuspendNotifier for FvpGicSuspend {
+    fn system_suspend(&self) {
+        let mut context = self.context.lock();
+        let gic = GIC.get().unwrap();
+
+        gic.redistributor_save(&mut context.redistributor_context);
+        gic.distributor_save(&mut context.distributor_context);
+    }
+
+    fn system_resume(&self) {
+        let context = self.context.lock();
+        let gic = GIC.get().unwrap();
+
+        gic.distributor_restore(&context.distributor_context);
+        gic.redistributor_restore(&context.redistributor_context);
+    }
+}
+
+static GIC_SUSPEND: FvpGicSuspend = FvpGicSuspend {
+    context: OrderedMutex::new(LockLevel::GicContext, FvpGicContext::new()),
+};
+
+/// The system level generic timer, which counts for all the cores.
+struct FvpSystemTimer<'a> {
+    control: OrderedMutex<GenericTimerControl<'a>>,
+    ctl: OrderedMutex<GenericTimerCtl<'a>>,
+}
+
+impl FvpSystemTimer<'_> {
+    const NS_TIMER_INDEX: usize = 1;
+
+    fn new(control: PhysicalInstance<CntControlBase>, ctl: PhysicalInstance<CntCtlBase>) -> Self {
+        Self {
+            control: OrderedMutex::new(
+                LockLevel::TimerControl,
+                GenericTimerControl::new(map_peripheral(control)),
+            ),
+            ctl: OrderedMutex::new(
+                LockLevel::TimerCtl,
+                GenericTimerCtl::new(map_peripheral(ctl)),
+            ),
+        }
+    }
+
+    /// Enables and initialises the timer, and sets the current core's counter frequency.
+    fn init(&self) {
+        let mut timer_control = self.control.lock();
+
+        timer_control.set_enable(true);
+
+        let frequency = timer_control.base_frequency();
+
+        let mut timer_ctl = self.ctl.lock();
+
+        timer_ctl.set_access_control(Self::NS_TIMER_INDEX, CntAcr::all());
+        timer_ctl.set_non_secure_access(Self::NS_TIMER_INDEX, true);
+        timer_ctl.set_frequency(frequency);
+
+        write_cntfrq_el0(CntfrqEl0::from_bits_retain(frequency.into()));
+    }
+
+    /// Sets the current core's counter frequency to that of the timer.
+    fn set_cntfrq(&self) {
+        let frequency = self.control.lock().base_frequency();
+        write_cntfrq_el0(CntfrqEl0::from_bits_retain(frequency.into()));
+    }
+}
+
+impl SystemSuspendNotifier for FvpSystemTimer<'_> {
+    fn system_resume(&self) {
+        // TODO: plat_arm_security_setup();
+        self.init();
+    }
+}
 
 struct FvpPsciPlatformImpl<'a> {
-    power_controller: SpinMutex<FvpPowerController<'a>>,
-    system: SpinMutex<FvpSystemPeripheral<'a>>,
-    timer_control: SpinMutex<GenericTimerControl<'a>>,
-    timer_ctl: SpinMutex<GenericTimerCtl<'a>>,
+    power_controller: OrderedMutex<FvpPowerController<'a>>,
+    interconnect: OrderedMutex<FvpInterconnect>,
+    system: &'a OrderedMutex<FvpSystemPeripheral<'a>>,
+    timer: &'a FvpSystemTimer<'a>,
 }
 
-impl FvpPsciPlatformImpl<'_> {
+impl<'a> FvpPsciPlatformImpl<'a> {
     const CLUSTER_POWER_LEVEL: usize = 1;
-    const NS_TIMER_INDEX: usize = 1;
 
     fn new(
         power_controller: PhysicalInstance<FvpPowerControllerRegisters>,
-        system: PhysicalInstance<FvpSystemRegisters>,
-        timer_control: PhysicalInstance<CntControlBase>,
-        timer_ctl: PhysicalInstance<CntCtlBase>,
+        cci: PhysicalInstance<Cci5x0Registers>,
+        system: &'a OrderedMutex<FvpSystemPeripheral<'a>>,
+        timer: &'a FvpSystemTimer<'a>,
     ) -> Self {
         Self {
-            power_controller: SpinMutex::new(FvpPowerController::new(map_peripheral(
-                power_controller,
-            ))),
-            system: SpinMutex::new(FvpSystemPeripheral::new(map_peripheral(system))),
-            timer_control: SpinMutex::new(GenericTimerControl::new(map_peripheral(timer_control))),
-            timer_ctl: SpinMutex::new(GenericTimerCtl::new(map_peripheral(timer_ctl))),
+            power_controller: OrderedMutex::new(
+                LockLevel::PowerController,
+                FvpPowerController::new(map_peripheral(power_controller)),
+            ),
+            interconnect: OrderedMutex::new(LockLevel::Interconnect, new_interconnect(cci)),
+            system,
+            timer,
         }
     }
 
+    /// Returns the index of the cluster containing the current core.
+    fn current_cluster() -> usize {
+        CoresImpl::<Fvp>::core_index() / (FVP_MAX_CPUS_PER_CLUSTER * FVP_MAX_PE_PER_CPU)
+    }
+
     fn power_domain_on_finish_common(
         &self,
         previous_state: &PsciCompositePowerState<
@@ -813,6 +1131,16 @@ impl FvpPsciPlatformImpl<'_> {
 
         // Perform the common cluster specific operations.
         if previous_state.states[Self::CLUSTER_POWER_LEVEL] == FvpPowerState::Off {
+            // SAFETY: This is the first core of the cluster to run since it left the coherency
+            // domain. Its caches were enabled by the warm boot path, but so far they only hold its
+            // stack and the PSCI locks it owns, which no other cluster writes until it releases
+            // them.
+            unsafe {
+                self.interconnect
+                    .lock()
+                    .enter_coherency(Self::current_cluster());
+            }
+
             // This CPU might have woken up whilst the cluster was attempting to power down. In
             // this case the FVP power controller will have a pending cluster power off request
             // which needs to be cleared by writing to the PPONR register. This prevents the power
@@ -821,59 +1149,13 @@ impl FvpPsciPlatformImpl<'_> {
             self.power_controller.lock().power_on_processor(mpidr);
         }
 
-        // Perform the common system specific operations.
-        if previous_state.highest_level_state() == FvpPowerState::Off {
-            self.restore_system_power_domain();
-        }
+        // The system level peripherals are re-initialised by their system suspend notifiers.
 
         // Clear PWKUPR.WEN bit to ensure interrupts do not interfere with a cpu power down unless
         // the bit is set again.
         self.power_controller.lock().disable_wakeup_requests(mpidr);
 
-        let frequency = self.timer_control.lock().base_frequency();
-        write_cntfrq_el0(CntfrqEl0::from_bits_retain(frequency.into()));
-    }
-
-    // Enable and initialize the system level generic timer
-    fn init_generic_timer(&self) {
-        let mut timer_control = self.timer_control.lock();
-
-        timer_control.set_enable(true);
-
-        let frequency = timer_control.base_frequency();
-
-        let mut timer_ctl = self.timer_ctl.lock();
-
-        timer_ctl.set_access_control(Self::NS_TIMER_INDEX, CntAcr::all());
-        timer_ctl.set_non_secure_access(Self::NS_TIMER_INDEX, true);
-        timer_ctl.set_frequency(frequency);
-
-        write_cntfrq_el0(CntfrqEl0::from_bits_retain(frequency.into()));
-    }
-
-    fn save_system_power_domain() {
-        let mut context = GIC_CONTEXT.lock();
-        let gic = GIC.get().unwrap();
-
-        gic.redistributor_save(&mut context.redistributor_context);
-        gic.distributor_save(&mut context.distributor_context);
-
-        log::logger().flush();
-
-        // All the other peripheral which are configured by ARM TF are re-initialized on resume
-        // from system suspend. Hence we don't save their state here.
-    }
-
-    fn restore_system_power_domain(&self) {
-        let context = GIC_CONTEXT.lock();
-        let gic = GIC.get().unwrap();
-
-        gic.distributor_restore(&context.distributor_context);
-        gic.redistributor_restore(&context.redistributor_context);
-
-        // TODO: plat_arm_security_setup();
-
-        self.init_generic_timer();
+        self.timer.set_cntfrq();
     }
 }
 
@@ -886,6 +1168,34 @@ const PSCI_MAX_POWER_LEVEL: usize = 2;
 const PSCI_STATE_COUNT: usize = PSCI_MAX_POWER_LEVEL + 1;
 const PSCI_NON_CPU_DOMAIN_COUNT: usize = 1 + FVP_CLUSTER_COUNT;
 
+/// The power states which can be requested with `CPU_SUSPEND`, using the recommended StateID
+/// encoding.
+const PSCI_POWER_STATES: PowerStateTable<FvpPowerState, PSCI_STATE_COUNT> =
+    PowerStateTable::new(&[
+        PowerStateTableEntry {
+            power_state: PowerState::StandbyOrRetention(0x001),
+            states: [
+                FvpPowerState::Retention,
+                FvpPowerState::Run,
+                FvpPowerState::Run,
+            ],
+        },
+        PowerStateTableEntry {
+            power_state: PowerState::PowerDown(0x002),
+            states: [FvpPowerState::Off, FvpPowerState::Run, FvpPowerState::Run],
+        },
+        PowerStateTableEntry {
+            power_state: PowerState::PowerDown(0x022),
+            states: [FvpPowerState::Off, FvpPowerState::Off, FvpPowerState::Run],
+        },
+        // Ensure that the system power domain can't be powered down by CPU_SUSPEND. Only
+        // SYSTEM_SUSPEND can do that.
+        PowerStateTableEntry {
+            power_state: PowerState::PowerDown(0x222),
+            states: [FvpPowerState::Off, FvpPowerState::Off, FvpPowerState::Run],
+        },
+    ]);
+
 type NodeIndex = u8;
 
 impl
@@ -927,7 +1237,6 @@ impl
         &TOPOLOGY
     }
 
-    /// Based on 6.5 Recommended StateID Encoding
     fn try_parse_power_state(
         power_state: PowerState,
     ) -> Option<
@@ -940,47 +1249,7 @@ impl
             Self::PlatformPowerState,
         >,
     > {
-        const POWER_LEVEL_STATE_MASK: u32 = 0x0000_0fff;
-        const ARM_LOCAL_PSTATE_WIDTH: u32 = 4;
-        const ARM_LOCAL_PSTATE_MASK: u32 = (1 << ARM_LOCAL_PSTATE_WIDTH) - 1;
-        // last_at_power_level is encoded in the bits immediately following the state ID bits
-        // for each power level.
-        let last_at_pwr_lvl_shift: u32 = ARM_LOCAL_PSTATE_WIDTH * (PSCI_MAX_POWER_LEVEL as u32 + 1);
-
-        if let PowerState::StandbyOrRetention(0x01) = power_state {
-            return Some(PsciCompositePowerState::new([
-                FvpPowerState::Retention,
-                FvpPowerState::Run,
-                FvpPowerState::Run,
-            ]));
-        }
-
-        let value = match power_state {
-            PowerState::PowerDown(v) => v,
-            _ => return None,
-        };
-
-        let states = match value & POWER_LEVEL_STATE_MASK {
-            0x002 => [FvpPowerState::Off, FvpPowerState::Run, FvpPowerState::Run],
-            0x022 => [FvpPowerState::Off, FvpPowerState::Off, FvpPowerState::Run],
-            // Ensure that the system power domain level is never suspended via PSCI
-            // CPU_SUSPEND API. System suspend is only supported via PSCI SYSTEM_SUSPEND
-            // API.
-            0x222 => [FvpPowerState::Off, FvpPowerState::Off, FvpPowerState::Run],
-            _ => return None,
-        };
-
-        let last_at_power_level =
-            ((value >> last_at_pwr_lvl_shift) & ARM_LOCAL_PSTATE_MASK) as usize;
-
-        if last_at_power_level > PSCI_MAX_POWER_LEVEL {
-            return None;
-        }
-
-        Some(PsciCompositePowerState::new_with_last_power_level(
-            states,
-            last_at_power_level,
-        ))
+        PSCI_POWER_STATES.parse(power_state)
     }
 
     fn cpu_standby(&self, cpu_state: FvpPowerState) {
@@ -1023,9 +1292,10 @@ impl
             self.power_controller.lock().power_off_cluster(mpidr);
         }
 
-        // Perform the common system specific operations.
+        // The system level peripherals have already saved their state with their system suspend
+        // notifiers, so just make sure the log is out before the UART loses power.
         if target_state.highest_level_state() == FvpPowerState::Off {
-            Self::save_system_power_domain();
+            log::logger().flush();
         }
 
         self.power_controller.lock().power_off_processor(mpidr);
@@ -1064,10 +1334,6 @@ impl
     ) {
         assert_eq!(FvpPowerState::Off, target_state.cpu_level_state());
 
-        let gic = GIC.get().unwrap();
-        gic.cpu_interface_disable();
-        gic.redistributor_off();
-
         let mpidr = read_mpidr_el1().bits() as u32;
         self.power_controller.lock().power_off_processor(mpidr);
 
@@ -1078,7 +1344,7 @@ impl
 
     fn power_domain_power_down(
         &self,
-        _target_state: &PsciCompositePowerState<
+        target_state: &PsciCompositePowerState<
             PSCI_STATE_COUNT,
             PSCI_MAX_POWER_LEVEL,
             { Fvp::CORE_COUNT },
@@ -1087,6 +1353,18 @@ impl
             Self::PlatformPowerState,
         >,
     ) {
+        // Leave the coherency domain as late as possible, once the PSCI locks have been released.
+        if target_state.states[Self::CLUSTER_POWER_LEVEL] == FvpPowerState::Off {
+            // SAFETY: This is the last core of the cluster to power down, and `cpu_power_down` has
+            // already cleaned and invalidated the cluster's caches. It doesn't write any memory
+            // which other clusters read before its power down WFI, and if the power down is
+            // abandoned `power_domain_suspend_finish` adds the cluster back before it does.
+            unsafe {
+                self.interconnect
+                    .lock()
+                    .exit_coherency(Self::current_cluster());
+            }
+        }
     }
 
     fn power_domain_on(&self, mpidr: Mpidr) -> Result<(), ErrorCode> {
@@ -1095,12 +1373,14 @@ impl
         // Ensure that we do not cancel an inflight power off request for the
         // target cpu. That would leave it in a zombie wfi. Wait for it to power
         // off and then program the power controller to turn that CPU on.
-        loop {
+        poll_until(POWER_OFF_TIMEOUT_MS, || {
             let psysr = self.power_controller.lock().system_status(raw_mpidr);
-            if !psysr.contains(SystemStatus::L0) {
-                break;
-            }
-        }
+            !psysr.contains(SystemStatus::L0)
+        })
+        .map_err(|_| {
+            log::error!("Timed out waiting for CPU {raw_mpidr:#x} to power off");
+            ErrorCode::InternalFailure
+        })?;
 
         self.power_controller.lock().power_on_processor(raw_mpidr);
 
@@ -1119,9 +1399,6 @@ impl
         >,
     ) {
         self.power_domain_on_finish_common(previous_state);
-        let gic = GIC.get().unwrap();
-        gic.redistributor_init(&Fvp::GIC_CONFIG);
-        gic.cpu_interface_enable();
     }
 
     fn system_off(&self) -> ! {
@@ -1183,22 +1460,8 @@ impl
 
         MemoryMap::DRAM0.contains(&entrypoint) || MemoryMap::DRAM1.contains(&entrypoint)
     }
-
-    fn power_domain_validate_suspend(
-        &self,
-        _target_state: &PsciCompositePowerState<
-            PSCI_STATE_COUNT,
-            PSCI_MAX_POWER_LEVEL,
-            { Fvp::CORE_COUNT },
-            PSCI_NON_CPU_DOMAIN_COUNT,
-            Self::NodeIndex,
-            Self::PlatformPowerState,
-        >,
-    ) -> Result<(), ErrorCode> {
-        Ok(())
-    }
 }
 
 all_asm!(Fvp);
 statics!(Fvp);
-panic_handler!();
+panic_handler!(Fvp);
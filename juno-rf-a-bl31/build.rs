@@ -0,0 +1,46 @@
+// Copyright The Rusted Firmware-A Contributors.
+//
+// SPDX-License-Identifier: BSD-3-Clause
+
+//! Build script for RF-A on Juno.
+
+use rf_a_bl31_build::{Builder, configure_build};
+
+fn main() {
+    configure_build(&JunoBuilder);
+}
+
+/// Platform builder implementation for Juno.
+pub struct JunoBuilder;
+
+impl JunoBuilder {
+    /// The first page of trusted SRAM is the shared RAM holding the trusted mailbox, and BL31 has
+    /// the rest of it.
+    const BL31_BASE: u64 = 0x0400_1000;
+    const BL31_SIZE: u64 = Self::TRUSTED_SRAM_END - Self::BL31_BASE;
+
+    const TRUSTED_SRAM_END: u64 = 0x0404_0000;
+
+    // Trusted SRAM is too small for debug builds to also hold the contexts and stacks, so they go
+    // in the DRAM reserved for EL3 instead.
+    const BL31_RETAINED_BASE: u64 = 0xffe0_0000;
+    const BL31_RETAINED_SIZE: u64 = 0x0002_0000;
+}
+
+impl Builder for JunoBuilder {
+    fn bl31_base(&self) -> u64 {
+        Self::BL31_BASE
+    }
+
+    fn bl31_size(&self) -> u64 {
+        Self::BL31_SIZE
+    }
+
+    fn bl31_retained_base(&self) -> Option<u64> {
+        Some(Self::BL31_RETAINED_BASE)
+    }
+
+    fn bl31_retained_size(&self) -> u64 {
+        Self::BL31_RETAINED_SIZE
+    }
+}
@@ -0,0 +1,498 @@
+// Copyright The Rusted Firmware-A Contributors.
+//
+// SPDX-License-Identifier: BSD-3-Clause
+
+//! RF-A BL31 deployment for the Arm Juno development platform.
+
+#![no_main]
+#![no_std]
+
+use arm_pl011_uart::{PL011Registers, UniqueMmioPointer};
+use core::{mem::offset_of, ptr::NonNull};
+use rf_a_bl31::{
+    all_asm, asm_macros_common, asm_macros_common_purge, bl31_warm_entrypoint,
+    context::{CoresImpl, EntryPointInfo},
+    cpu::{cortex_a53::CortexA53, cortex_a57::CortexA57, cortex_a72::CortexA72},
+    cpu_extensions::{CpuExtension, simd::Simd},
+    debug::DEBUG,
+    define_cpu_ops, define_errata_list, gic_debug_macros, gic_debug_macros_purge,
+    gicv3::{Gic, GicConfig, SgiRegistry},
+    logger::pl011::{Pl011Config, Pl011Console},
+    mhu::{MhuLink, Mhuv1Channel, Mhuv1Receiver, Mhuv1Sender, SecureChannels},
+    naked_asm,
+    nv_counter::NotSupportedNvCounters,
+    pagetable::{
+        IdMap, MT_DEVICE, MT_MEMORY_EL3,
+        early_pagetable::{EarlyRegion, define_early_mapping},
+    },
+    panic_handler,
+    platform::{Board, DummyService, Platform, Soc},
+    reexports::{
+        aarch64_paging::paging::MemoryRegion,
+        arm_gic::{
+            IntId,
+            gicv3::registers::{Gicd, GicrSgi},
+        },
+        arm_psci::Mpidr,
+        arm_sysregs::{IccSreEl3, MpidrEl1},
+        log::info,
+        percore::Cores,
+    },
+    scmi::{
+        MhuScmiTransport, ScmiClient, ScmiSharedMemory,
+        psci::{
+            SCMI_PSCI_STATE_COUNT, ScmiCompositePowerState, ScmiPsciPlatform, ScmiPsciPlatformImpl,
+        },
+    },
+    services::{
+        arch::WorkaroundSupport, psci::PsciPlatformInterface, trng::NotSupportedTrngPlatformImpl,
+    },
+    statics,
+};
+
+/// Trusted SRAM, of which the first page is shared RAM and the rest holds BL31.
+const TRUSTED_SRAM_BASE: usize = 0x0400_0000;
+const TRUSTED_SRAM_SIZE: usize = 0x0004_0000;
+const SHARED_RAM_BASE: usize = TRUSTED_SRAM_BASE;
+const SHARED_RAM_SIZE: usize = 0x0000_1000;
+const SHARED_RAM: MemoryRegion =
+    MemoryRegion::new(SHARED_RAM_BASE, SHARED_RAM_BASE + SHARED_RAM_SIZE);
+const BL31_BASE: usize = SHARED_RAM_BASE + SHARED_RAM_SIZE;
+/// The DRAM reserved for EL3, holding the saved contexts and stacks.
+const EL3_DRAM_BASE: usize = 0xffe0_0000;
+const EL3_DRAM_SIZE: usize = 0x0020_0000;
+
+/// The motherboard peripherals, including the system registers.
+const V2M_DEVICE_BASE: usize = 0x1c00_0000;
+const V2M_DEVICE_SIZE: usize = 0x0400_0000;
+const V2M_DEVICE: MemoryRegion =
+    MemoryRegion::new(V2M_DEVICE_BASE, V2M_DEVICE_BASE + V2M_DEVICE_SIZE);
+/// The compute subsystem peripherals, including the GIC and MHU.
+const CSS_DEVICE_BASE: usize = 0x2000_0000;
+const CSS_DEVICE_SIZE: usize = 0x0e00_0000;
+const CSS_DEVICE: MemoryRegion =
+    MemoryRegion::new(CSS_DEVICE_BASE, CSS_DEVICE_BASE + CSS_DEVICE_SIZE);
+/// The SoC peripherals, including the UARTs.
+const SOC_DEVICE_BASE: usize = 0x7e00_0000;
+const SOC_DEVICE_SIZE: usize = 0x0200_0000;
+const SOC_DEVICE: MemoryRegion =
+    MemoryRegion::new(SOC_DEVICE_BASE, SOC_DEVICE_BASE + SOC_DEVICE_SIZE);
+
+/// The page of SCP SRAM shared with the application processors for SCMI.
+const SCP_SHARED_MEMORY_BASE: usize = 0x2e00_0000;
+const SCP_SHARED_MEMORY: MemoryRegion =
+    MemoryRegion::new(SCP_SHARED_MEMORY_BASE, SCP_SHARED_MEMORY_BASE + 0x1000);
+const SCMI_SHARED_MEMORY_ADDRESS: *mut ScmiSharedMemory = SCP_SHARED_MEMORY_BASE as _;
+
+/// Base address of the MHUv1 between the application processors and the SCP.
+const MHU_BASE: usize = 0x2b1f_0000;
+/// The window of the MHU on which the SCP rings secure doorbells to the application processors.
+const MHU_SCP_TO_AP_SECURE_ADDRESS: *mut Mhuv1Channel = (MHU_BASE + 0x200) as _;
+/// The window of the MHU on which the application processors ring secure doorbells to the SCP.
+const MHU_AP_TO_SCP_SECURE_ADDRESS: *mut Mhuv1Channel = (MHU_BASE + 0x300) as _;
+
+/// Juno has a GIC-400, with its distributor here and its CPU interface at 0x2c02_f000.
+///
+/// TODO: The GIC-400 is a GICv2, so the GICv3 driver can't drive it. Until there is GICv2 support
+/// this assumes a GIC-600 with its distributor at the same address and redistributors after it.
+const GICD_BASE: usize = 0x2c01_0000;
+const GICR_BASE: usize = 0x2c10_0000;
+/// Base address of GICv3 distributor.
+const GICD_BASE_ADDRESS: *mut Gicd = GICD_BASE as _;
+/// Base address of the first GICv3 redistributor frame.
+const GICR_BASE_ADDRESS: *mut GicrSgi = GICR_BASE as _;
+
+/// The trusted mailbox, from which the trusted ROM reads the warm boot entry point when a core is
+/// powered on.
+const TRUSTED_MAILBOX_ADDRESS: *mut u64 = SHARED_RAM_BASE as _;
+
+/// Base address of the SoC UART0, shared with the normal world.
+const UART0_BASE: usize = 0x7ff8_0000;
+/// Base address of the SoC UART1, used for crash output.
+const UART1_BASE: usize = 0x7ff7_0000;
+const PL011_BASE_ADDRESS: *mut PL011Registers = UART0_BASE as _;
+const UART_CLK_IN_HZ: u32 = 7_372_800;
+const CONSOLE_BAUDRATE: u32 = 115_200;
+
+/// Configuration applied to the runtime UART by BL31, rather than relying on earlier firmware to
+/// have set it up.
+const JUNO_RUNTIME_CONSOLE_CONFIG: Pl011Config = Pl011Config {
+    clock_hz: UART_CLK_IN_HZ,
+    baud_rate: CONSOLE_BAUDRATE,
+    flow_control: false,
+};
+
+/// The motherboard system identification register, which holds the board revision.
+const V2M_SYS_ID_ADDRESS: *const u32 = (V2M_DEVICE_BASE + 0x1_0000) as _;
+const V2M_SYS_ID_REV_SHIFT: u32 = 28;
+const V2M_SYS_ID_REV_MASK: u32 = 0xf;
+
+/// The JEP106 continuation code of Arm.
+const JEP106_ARM_BANK: u32 = 0x4;
+/// The JEP106 identification code of Arm.
+const JEP106_ARM_ID: u32 = 0x3b;
+
+/// The physical address of the SPMC manifest blob.
+// TODO: Use the correct addresses here.
+const TOS_FW_CONFIG_ADDRESS: u64 = 0;
+const HW_CONFIG_ADDRESS: u64 = 0;
+
+/// The MPIDR Aff1 value of the Cortex-A57 or Cortex-A72 cluster.
+const BIG_CLUSTER: u8 = 0;
+/// The number of cores in the Cortex-A57 or Cortex-A72 cluster.
+const BIG_CORE_COUNT: usize = 2;
+/// The MPIDR Aff1 value of the Cortex-A53 cluster, which includes the primary core.
+const LITTLE_CLUSTER: u8 = 1;
+/// The number of cores in the Cortex-A53 cluster.
+const LITTLE_CORE_COUNT: usize = 4;
+/// The number of CPU clusters.
+const CLUSTER_COUNT: usize = 2;
+
+const TRNG_REQ_WORDS: usize = 1;
+
+static SIMD: Simd<{ Juno::CORE_COUNT }, Juno> = Simd::simd();
+
+/// The Arm Juno development platform, with a big.LITTLE pair of clusters.
+struct Juno;
+
+define_cpu_ops!(Juno, [CortexA53, CortexA57, CortexA72]);
+define_errata_list!(Juno, []);
+
+define_early_mapping!(
+    Juno,
+    [
+        EarlyRegion {
+            address_range: BL31_BASE..(TRUSTED_SRAM_BASE + TRUSTED_SRAM_SIZE),
+            attributes: MT_MEMORY_EL3
+        },
+        EarlyRegion {
+            address_range: EL3_DRAM_BASE..(EL3_DRAM_BASE + EL3_DRAM_SIZE),
+            attributes: MT_MEMORY_EL3
+        },
+        EarlyRegion {
+            address_range: SOC_DEVICE_BASE..(SOC_DEVICE_BASE + SOC_DEVICE_SIZE),
+            attributes: MT_DEVICE
+        }
+    ]
+);
+
+statics!(Juno);
+all_asm!(Juno);
+panic_handler!(Juno);
+
+/// Returns the SMCCC SoC ID version: Arm's JEP106 code with no SoC-specific part number.
+fn soc_id_version() -> u32 {
+    JEP106_ARM_BANK << 24 | JEP106_ARM_ID << 16
+}
+
+/// Returns the SMCCC SoC ID revision, which is the board revision from the motherboard system
+/// registers.
+fn soc_id_revision() -> u32 {
+    // SAFETY: `V2M_SYS_ID_ADDRESS` is the address of the read-only SYS_ID register, which is
+    // mapped as device memory and has no side effects on read.
+    let sys_id = unsafe { V2M_SYS_ID_ADDRESS.read_volatile() };
+    (sys_id >> V2M_SYS_ID_REV_SHIFT) & V2M_SYS_ID_REV_MASK
+}
+
+/// The SoC of the Juno development platform.
+pub struct JunoSoc;
+
+// SAFETY: `core_position` is indeed a naked function, doesn't access the stack or any other memory,
+// only clobbers x0-x1, and returns a unique index for each core in the two clusters.
+// `dump_registers` is also a naked function which only uses the crash console and the registers it
+// is documented to clobber.
+unsafe impl Soc for JunoSoc {
+    const CORE_COUNT: usize = LITTLE_CORE_COUNT + BIG_CORE_COUNT;
+    const CACHE_WRITEBACK_GRANULE: usize = 1 << 6;
+
+    const GIC_CONFIG: GicConfig = GicConfig {
+        sgis: SgiRegistry::EMPTY,
+        interrupts_config: &[],
+    };
+
+    fn mpidr_is_valid(mpidr: MpidrEl1) -> bool {
+        if mpidr.contains(MpidrEl1::MT) || mpidr.aff3() != 0 || mpidr.aff2() != 0 {
+            return false;
+        }
+        match mpidr.aff1() {
+            BIG_CLUSTER => usize::from(mpidr.aff0()) < BIG_CORE_COUNT,
+            LITTLE_CLUSTER => usize::from(mpidr.aff0()) < LITTLE_CORE_COUNT,
+            _ => false,
+        }
+    }
+
+    // TODO: Implement the Spectre mitigations needed by the Cortex-A57 and Cortex-A72.
+    fn arch_workaround_1_supported() -> WorkaroundSupport {
+        WorkaroundSupport::SafeButNotRequired
+    }
+
+    fn arch_workaround_1() {}
+
+    fn arch_workaround_2_supported() -> WorkaroundSupport {
+        WorkaroundSupport::SafeButNotRequired
+    }
+
+    fn arch_workaround_2() {}
+
+    fn arch_workaround_3_supported() -> WorkaroundSupport {
+        WorkaroundSupport::SafeButNotRequired
+    }
+
+    fn arch_workaround_3() {}
+
+    fn arch_workaround_4_supported() -> WorkaroundSupport {
+        WorkaroundSupport::SafeButNotRequired
+    }
+
+    /// The cores of the Cortex-A53 cluster come first, as the primary core is the first of them.
+    #[unsafe(naked)]
+    extern "C" fn core_position(mpidr: u64) -> usize {
+        naked_asm!(
+            "ubfx	x1, x0, #{AFF1_SHIFT}, #8",
+            "and	x0, x0, #{AFF0_MASK}",
+            "cmp	x1, #{LITTLE_CLUSTER}",
+            "b.eq	1f",
+            "add	x0, x0, #{LITTLE_CORE_COUNT}",
+            "1:",
+            "ret",
+            AFF1_SHIFT = const MpidrEl1::AFF1_SHIFT,
+            AFF0_MASK = const MpidrEl1::AFF0_MASK << MpidrEl1::AFF0_SHIFT,
+            LITTLE_CLUSTER = const LITTLE_CLUSTER,
+            LITTLE_CORE_COUNT = const LITTLE_CORE_COUNT,
+        );
+    }
+
+    /// Dumps relevant GIC registers.
+    ///
+    /// Clobbers x0-x11, x16, x17, sp.
+    #[unsafe(naked)]
+    unsafe extern "C" fn dump_registers() {
+        naked_asm!(
+            asm_macros_common!(),
+            gic_debug_macros!(),
+            "mov_imm x16, {GICD_BASE}",
+            "arm_print_gic_regs",
+            "ret",
+            gic_debug_macros_purge!(),
+            asm_macros_common_purge!(),
+            DEBUG = const DEBUG as i32,
+            ICC_SRE_SRE_BIT = const IccSreEl3::SRE.bits(),
+            GICD_BASE = const GICD_BASE,
+            GICD_ISPENDR = const offset_of!(Gicd, ispendr),
+        );
+    }
+}
+
+// SAFETY: `cold_boot_handler` and the crash console functions are naked functions which don't use
+// the stack, and only clobber the registers they are documented to clobber.
+unsafe impl Board for Juno {
+    type Soc = JunoSoc;
+
+    type LogSinkImpl = Pl011Console;
+    type IdMap = IdMap<{ Self::PAGE_HEAP_PAGE_COUNT }>;
+    type PsciPlatformImpl = JunoPsciPlatformImpl;
+    // TODO: Get entropy from the SCP.
+    type TrngPlatformImpl = NotSupportedTrngPlatformImpl;
+
+    type PlatformServiceImpl = DummyService;
+    type NvCountersImpl = NotSupportedNvCounters;
+
+    const CPU_EXTENSIONS: &'static [&'static dyn CpuExtension] = &[&SIMD];
+
+    fn init_with_early_mapping(_arg0: u64, _arg1: u64, _arg2: u64, _arg3: u64) {
+        // SAFETY: `PL011_BASE_ADDRESS` is the base address of a PL011 device, and nothing else
+        // accesses that address range. The address is valid both with the early mapping and the
+        // main one, as it's within the `SOC_DEVICE` region that is identity mapped in both cases.
+        let uart_pointer =
+            unsafe { UniqueMmioPointer::new(NonNull::new(PL011_BASE_ADDRESS).unwrap()) };
+        LOGGER
+            .init(
+                Pl011Console::with_config(uart_pointer, &JUNO_RUNTIME_CONSOLE_CONFIG)
+                    .expect("Invalid UART configuration"),
+            )
+            .expect("Failed to initialise logger");
+    }
+
+    fn init(_arg0: u64, _arg1: u64, _arg2: u64, _arg3: u64) {
+        info!(
+            "Juno SoC ID version {:#010x}, revision {}",
+            soc_id_version(),
+            soc_id_revision()
+        );
+
+        // SAFETY: `TRUSTED_MAILBOX_ADDRESS` is in the shared RAM, which is mapped as device memory
+        // and only used for the mailbox. Other cores only read it when they are powered on, and
+        // aligned 64-bit writes are single-copy atomic.
+        unsafe {
+            TRUSTED_MAILBOX_ADDRESS.write_volatile(bl31_warm_entrypoint::<Juno> as usize as u64);
+        }
+
+        GIC.call_once(|| {
+            // SAFETY: `GICD_BASE_ADDRESS` is a unique pointer to the Juno's GICD register block.
+            let gicd = unsafe { UniqueMmioPointer::new(NonNull::new(GICD_BASE_ADDRESS).unwrap()) };
+            let gicr_base = NonNull::new(GICR_BASE_ADDRESS).unwrap();
+            // SAFETY: `gicr_base` points to a continuously mapped GIC redistributor memory area
+            // until the last redistributor block. There are no other references to this address
+            // range.
+            unsafe { Gic::new(gicd, gicr_base, false) }
+        });
+    }
+
+    fn map_extra_regions(idmap: &mut Self::IdMap) {
+        // SAFETY: Nothing is being unmapped, and the regions being mapped have the correct
+        // attributes.
+        unsafe {
+            idmap.map_region(&SHARED_RAM, MT_DEVICE);
+            idmap.map_region(&V2M_DEVICE, MT_DEVICE);
+            idmap.map_region(&CSS_DEVICE, MT_DEVICE);
+            idmap.map_region(&SCP_SHARED_MEMORY, MT_DEVICE);
+            idmap.map_region(&SOC_DEVICE, MT_DEVICE);
+        }
+    }
+
+    fn create_service() -> Self::PlatformServiceImpl {
+        DummyService
+    }
+
+    fn handle_group0_interrupt(int_id: IntId) {
+        todo!("Handle group0 interrupt {:?}", int_id)
+    }
+
+    fn secure_entry_point() -> EntryPointInfo {
+        let core_linear_id = CoresImpl::<Self>::core_index() as u64;
+        EntryPointInfo {
+            pc: 0xff00_0000,
+            args: [
+                TOS_FW_CONFIG_ADDRESS,
+                HW_CONFIG_ADDRESS,
+                0,
+                0,
+                core_linear_id,
+                0,
+                0,
+                0,
+            ],
+        }
+    }
+
+    fn non_secure_entry_point() -> EntryPointInfo {
+        EntryPointInfo {
+            pc: 0xe000_0000,
+            args: [0; 8],
+        }
+    }
+
+    fn psci_platform() -> Option<Self::PsciPlatformImpl> {
+        // SAFETY: The SCMI shared memory and the secure MHU windows are only used by this
+        // transport, which is only created once.
+        let (shared_memory, sender, receiver) = unsafe {
+            (
+                UniqueMmioPointer::new(NonNull::new(SCMI_SHARED_MEMORY_ADDRESS).unwrap()),
+                UniqueMmioPointer::new(NonNull::new(MHU_AP_TO_SCP_SECURE_ADDRESS).unwrap()),
+                UniqueMmioPointer::new(NonNull::new(MHU_SCP_TO_AP_SECURE_ADDRESS).unwrap()),
+            )
+        };
+        let link = MhuLink::new(
+            Mhuv1Sender::new(sender),
+            Mhuv1Receiver::new(receiver),
+            SecureChannels(0b1),
+        );
+        Some(ScmiPsciPlatformImpl::new(ScmiClient::new(
+            MhuScmiTransport::new(shared_memory, link, 0),
+        )))
+    }
+
+    fn nv_counters() -> Option<Self::NvCountersImpl> {
+        Some(NotSupportedNvCounters)
+    }
+
+    #[unsafe(naked)]
+    unsafe extern "C" fn cold_boot_handler() {
+        naked_asm!("ret");
+    }
+
+    #[unsafe(naked)]
+    extern "C" fn crash_console_init() -> u32 {
+        naked_asm!(
+            asm_macros_common!(),
+            "mov_imm	x0, {PLAT_JUNO_CRASH_UART_BASE}",
+            "mov_imm	x1, {PLAT_JUNO_CRASH_UART_CLK_IN_HZ}",
+            "mov_imm	x2, {PLAT_JUNO_CONSOLE_BAUDRATE}",
+            "b	console_pl011_core_init",
+            asm_macros_common_purge!(),
+            DEBUG = const DEBUG as i32,
+            PLAT_JUNO_CRASH_UART_BASE = const UART1_BASE,
+            PLAT_JUNO_CRASH_UART_CLK_IN_HZ = const UART_CLK_IN_HZ,
+            PLAT_JUNO_CONSOLE_BAUDRATE = const CONSOLE_BAUDRATE,
+        );
+    }
+
+    #[unsafe(naked)]
+    extern "C" fn crash_console_putc(char: u32) -> i32 {
+        naked_asm!(
+            asm_macros_common!(),
+            "mov_imm	x1, {PLAT_JUNO_CRASH_UART_BASE}",
+            "b	console_pl011_core_putc",
+            asm_macros_common_purge!(),
+            DEBUG = const DEBUG as i32,
+            PLAT_JUNO_CRASH_UART_BASE = const UART1_BASE,
+        );
+    }
+
+    #[unsafe(naked)]
+    extern "C" fn crash_console_flush() {
+        naked_asm!(
+            asm_macros_common!(),
+            "mov_imm	x0, {PLAT_JUNO_CRASH_UART_BASE}",
+            "b	console_pl011_core_flush",
+            asm_macros_common_purge!(),
+            DEBUG = const DEBUG as i32,
+            PLAT_JUNO_CRASH_UART_BASE = const UART1_BASE,
+        );
+    }
+}
+
+const PSCI_STATE_COUNT: usize = SCMI_PSCI_STATE_COUNT;
+const PSCI_NON_CPU_DOMAIN_COUNT: usize = CLUSTER_COUNT + 1;
+
+type JunoPsciPlatformImpl = ScmiPsciPlatformImpl<
+    { Juno::CORE_COUNT },
+    PSCI_NON_CPU_DOMAIN_COUNT,
+    JunoScmiPlatform,
+    MhuScmiTransport<'static, Mhuv1Sender<'static>, Mhuv1Receiver<'static>>,
+>;
+
+/// The Juno-specific parts of power management over SCMI.
+struct JunoScmiPlatform;
+
+impl ScmiPsciPlatform<{ Juno::CORE_COUNT }, PSCI_NON_CPU_DOMAIN_COUNT> for JunoScmiPlatform {
+    fn topology() -> &'static [usize] {
+        // The Cortex-A53 cluster comes first, to match `core_position`.
+        &[1, CLUSTER_COUNT, LITTLE_CORE_COUNT, BIG_CORE_COUNT]
+    }
+
+    /// The SCP numbers the cores of the big cluster first.
+    fn cpu_domain_id(mpidr: Mpidr) -> Option<u32> {
+        if mpidr.aff3.unwrap_or_default() != 0 || mpidr.aff2 != 0 {
+            return None;
+        }
+        let core = usize::from(mpidr.aff0);
+        match mpidr.aff1 {
+            BIG_CLUSTER if core < BIG_CORE_COUNT => Some(core as u32),
+            LITTLE_CLUSTER if core < LITTLE_CORE_COUNT => Some((BIG_CORE_COUNT + core) as u32),
+            _ => None,
+        }
+    }
+
+    fn power_domain_suspend(
+        _target_state: &ScmiCompositePowerState<{ Juno::CORE_COUNT }, PSCI_NON_CPU_DOMAIN_COUNT>,
+    ) {
+        GIC.get().unwrap().cpu_interface_disable();
+    }
+
+    fn power_domain_suspend_finish(
+        _previous_state: &ScmiCompositePowerState<{ Juno::CORE_COUNT }, PSCI_NON_CPU_DOMAIN_COUNT>,
+    ) {
+        GIC.get().unwrap().cpu_interface_enable();
+    }
+}
@@ -7,31 +7,43 @@
 #![no_main]
 #![no_std]
 
-use arm_pl011_uart::{PL011Registers, Uart};
+mod test_exit;
+
+use self::test_exit::{CRASH_EXIT_PARAMETERS, ExitCode, SYS_EXIT, TEST_EXIT, TestExitHandler};
+use arm_pl011_uart::PL011Registers;
 use arm_pl061::{PL061, PL061Registers, UniqueMmioPointer};
+#[cfg(feature = "pauth")]
+use core::arch::asm;
 use core::{mem::offset_of, ptr::NonNull};
+#[cfg(feature = "pauth")]
+use rf_a_bl31::reexports::arm_sysregs::read_cntpct_el0;
+#[cfg(feature = "pauth")]
+use rf_a_bl31::rng;
 use rf_a_bl31::{
     aarch64::{dsb_sy, isb, sev, wfi},
-    all_asm, asm_macros_common, asm_macros_common_purge, bl31_warm_entrypoint,
+    affinity_core_position, all_asm, asm_macros_common, asm_macros_common_purge,
+    bl31_warm_entrypoint,
     context::{CoresImpl, EntryPointInfo},
     cpu::qemu_max::QemuMax,
     cpu_extensions::{CpuExtension, simd::Simd},
-    debug::DEBUG,
+    debug::{DEBUG, EarlyConsole},
     define_cpu_ops, define_errata_list,
     dram::zeroed_mut,
     gic_debug_macros, gic_debug_macros_purge,
-    gicv3::{Gic, GicConfig},
+    gicv3::{Gic, GicConfig, SgiRegistry},
     logger::{
-        HybridLogger, LockedWriter,
+        HybridLogger,
         inmemory::{MemoryLogger, PerCoreMemoryLogger},
+        pl011::Pl011Console,
     },
     naked_asm,
+    nv_counter::NotSupportedNvCounters,
     pagetable::{
         IdMap, MT_DEVICE, MT_MEMORY_EL3, disable_mmu_el3,
         early_pagetable::{EarlyRegion, define_early_mapping},
     },
     panic_handler,
-    platform::{DummyService, Platform, my_core_pos},
+    platform::{Board, DummyService, Platform, Soc, my_core_pos, topology::AffinityTopology},
     reexports::{
         aarch64_paging::paging::MemoryRegion,
         arm_gic::{
@@ -41,19 +53,29 @@ use rf_a_bl31::{
         arm_psci::{ErrorCode, Mpidr, PowerState},
         arm_sysregs::{IccSreEl3, MpidrEl1},
         percore::Cores,
-        spin::mutex::{SpinMutex, SpinMutexGuard},
     },
     services::{
-        arch::WorkaroundSupport,
+        arch::{
+            ARM_JEP106_CONTINUATION_CODE, ARM_JEP106_IDENTIFICATION_CODE, WorkaroundSupport,
+            soc_id_version,
+        },
         psci::{
-            PlatformPowerStateInterface, PowerStateType, PsciCompositePowerState,
-            PsciPlatformInterface, PsciPlatformOptionalFeatures, try_get_cpu_index_by_mpidr,
+            PlatformPowerStateInterface, PowerStateTable, PowerStateTableEntry, PowerStateType,
+            PsciCompositePowerState, PsciPlatformInterface, PsciPlatformOptionalFeatures,
+            try_get_cpu_index_by_mpidr,
         },
-        trng::NotSupportedTrngPlatformImpl,
+        trng::RndrTrngPlatformImpl,
+        vendor::VendorHandlers,
     },
+    spin_mutex::{SpinMutex, SpinMutexGuard},
     statics,
 };
 
+/// The QEMU virt machine has no SoC ID register, so fixed values are reported by the arch SOC_ID
+/// SMC instead, chosen not to clash with those of the FVP.
+const QEMU_SOC_ID: u16 = 0x0001;
+const QEMU_SOC_REVISION: u32 = 0;
+
 const DEVICE0_BASE: usize = 0x0800_0000;
 const DEVICE0_SIZE: usize = 0x0100_0000;
 const DEVICE1_BASE: usize = 0x0900_0000;
@@ -89,7 +111,8 @@ const GICR_BASE_ADDRESS: *mut GicrSgi = GICR_BASE as _;
 /// Base addresses for GPIO block that controls system off and system reset as described in the
 /// [QEMU ARM virt platform docs](https://qemu-project.gitlab.io/qemu/system/arm/virt.html).
 /// Addresses taken from C TF-A.
-const SECURE_GPIO_ADDR: *mut PL061Registers = 0x090b_0000 as _;
+const SECURE_GPIO_BASE: usize = 0x090b_0000;
+const SECURE_GPIO_ADDR: *mut PL061Registers = SECURE_GPIO_BASE as _;
 
 /// Constants for the system off and system reset GPIO indices.
 const SECURE_GPIO_SYSTEM_OFF: usize = 0;
@@ -108,11 +131,17 @@ const HW_CONFIG_ADDRESS: u64 = 0;
 
 /// The number of CPU clusters.
 const CLUSTER_COUNT: usize = 1;
-const PLATFORM_CPU_PER_CLUSTER_SHIFT: usize = 2;
 /// The maximum number of CPUs in each cluster.
-const MAX_CPUS_PER_CLUSTER: usize = 1 << PLATFORM_CPU_PER_CLUSTER_SHIFT;
+const MAX_CPUS_PER_CLUSTER: usize = 4;
+const TOPOLOGY: AffinityTopology = AffinityTopology {
+    clusters: CLUSTER_COUNT,
+    cores_per_cluster: MAX_CPUS_PER_CLUSTER,
+    threads_per_core: 1,
+};
 
 const TRNG_REQ_WORDS: usize = 1;
+/// UUID of QEMU's FEAT_RNG entropy source, which is available with `-cpu max`.
+const TRNG_UUID: u128 = 0x0cdc533f_7f5f_4231_b299_3e86ba5493a0;
 
 /// The per-core log buffer size in bytes. We subtract the size of the metadata so that the total
 /// size of each `MemoryLogger` will be 1024 bytes.
@@ -129,7 +158,7 @@ static SECURE_GPIO: SpinMutex<PL061> = SpinMutex::new(PL061::new(unsafe {
     UniqueMmioPointer::new(NonNull::new(SECURE_GPIO_ADDR).unwrap())
 }));
 
-static SIMD: Simd<{ Qemu::CORE_COUNT }, Qemu> = Simd::sve(512, false);
+static SIMD: Simd<{ Qemu::CORE_COUNT }, Qemu, 64> = Simd::sve();
 
 #[repr(C, align(64))]
 struct HoldSlot {
@@ -198,32 +227,108 @@ define_early_mapping!(
 
 statics!(Qemu);
 all_asm!(Qemu);
-panic_handler!();
+panic_handler!(Qemu);
+
+/// The SoC of QEMU's 'virt' machine.
+pub struct QemuSoc;
 
 // SAFETY: `core_position` is indeed a naked function, doesn't access the stack or any other memory,
-// only clobbers x0 and x1, and returns a unique index as long as `PLATFORM_CPU_PER_CLUSTER_SHIFT`
-// is correct.
-unsafe impl Platform for Qemu {
-    const CORE_COUNT: usize = CLUSTER_COUNT * MAX_CPUS_PER_CLUSTER;
+// only clobbers x0-x5, and returns a unique index as long as `TOPOLOGY` is correct.
+// `dump_registers` is also a naked function which only uses the crash console and the registers it
+// is documented to clobber.
+unsafe impl Soc for QemuSoc {
+    const CORE_COUNT: usize = TOPOLOGY.core_count();
     const CACHE_WRITEBACK_GRANULE: usize = 1 << 6;
 
+    const GIC_CONFIG: GicConfig = GicConfig {
+        sgis: SgiRegistry::EMPTY,
+        interrupts_config: &[],
+    };
+
+    fn mpidr_is_valid(mpidr: MpidrEl1) -> bool {
+        TOPOLOGY.mpidr_is_valid(mpidr)
+    }
+
+    fn arch_workaround_1_supported() -> WorkaroundSupport {
+        WorkaroundSupport::SafeButNotRequired
+    }
+
+    fn arch_workaround_1() {}
+
+    fn arch_workaround_2_supported() -> WorkaroundSupport {
+        WorkaroundSupport::SafeButNotRequired
+    }
+
+    fn arch_workaround_2() {}
+
+    fn arch_workaround_3_supported() -> WorkaroundSupport {
+        WorkaroundSupport::SafeButNotRequired
+    }
+
+    fn arch_workaround_3() {}
+
+    fn arch_workaround_4_supported() -> WorkaroundSupport {
+        WorkaroundSupport::SafeButNotRequired
+    }
+
+    fn soc_id_version() -> Option<u32> {
+        Some(soc_id_version(
+            ARM_JEP106_CONTINUATION_CODE,
+            ARM_JEP106_IDENTIFICATION_CODE,
+            QEMU_SOC_ID,
+        ))
+    }
+
+    fn soc_id_revision() -> u32 {
+        QEMU_SOC_REVISION
+    }
+
+    #[unsafe(naked)]
+    extern "C" fn core_position(mpidr: u64) -> usize {
+        affinity_core_position!(TOPOLOGY)
+    }
+
+    /// Dumps relevant GIC and CCI registers.
+    ///
+    /// Clobbers x0-x11, x16, x17, sp.
+    #[unsafe(naked)]
+    unsafe extern "C" fn dump_registers() {
+        naked_asm!(
+            asm_macros_common!(),
+            gic_debug_macros!(),
+            "mov_imm x16, {GICD_BASE}",
+            "arm_print_gic_regs",
+            "ret",
+            gic_debug_macros_purge!(),
+            asm_macros_common_purge!(),
+            DEBUG = const DEBUG as i32,
+            ICC_SRE_SRE_BIT = const IccSreEl3::SRE.bits(),
+            GICD_BASE = const GICD_BASE,
+            GICD_ISPENDR = const offset_of!(Gicd, ispendr),
+        );
+    }
+}
+
+// SAFETY: `cold_boot_handler` and the crash console functions are naked functions which don't use
+// the stack, and only clobber the registers they are documented to clobber.
+unsafe impl Board for Qemu {
+    type Soc = QemuSoc;
+
     type LogSinkImpl = HybridLogger<
         PerCoreMemoryLogger<'static, { Self::CORE_COUNT }, LOG_BUFFER_SIZE, Self>,
-        LockedWriter<Uart<'static>>,
+        Pl011Console,
     >;
     type IdMap = IdMap<{ Self::PAGE_HEAP_PAGE_COUNT }>;
     type PsciPlatformImpl = QemuPsciPlatformImpl;
-    // QEMU does not have a TRNG.
-    type TrngPlatformImpl = NotSupportedTrngPlatformImpl;
+    type TrngPlatformImpl = RndrTrngPlatformImpl<TRNG_UUID>;
 
     type PlatformServiceImpl = DummyService;
-
-    const GIC_CONFIG: GicConfig = GicConfig {
-        interrupts_config: &[],
-    };
+    type NvCountersImpl = NotSupportedNvCounters;
 
     const CPU_EXTENSIONS: &'static [&'static dyn CpuExtension] = &[&SIMD];
 
+    const EARLY_CONSOLE: EarlyConsole = EarlyConsole::Semihosting;
+
     fn init_with_early_mapping(_arg0: u64, _arg1: u64, _arg2: u64, _arg3: u64) {
         // SAFETY: `PL011_BASE_ADDRESS` is the base address of a PL011 device, and nothing else
         // accesses that address range. The address is valid both with the early mapping and the
@@ -233,7 +338,8 @@ unsafe impl Platform for Qemu {
         LOGGER
             .init(HybridLogger::new(
                 PerCoreMemoryLogger::new(SpinMutexGuard::leak(MEMORY_LOGGERS.lock()).each_mut()),
-                LockedWriter::new(Uart::new(uart_pointer)),
+                // QEMU doesn't model the UART clock, so leave the UART as QEMU configured it.
+                Pl011Console::new(uart_pointer),
             ))
             .expect("Failed to initialise logger");
     }
@@ -257,6 +363,12 @@ unsafe impl Platform for Qemu {
         });
     }
 
+    fn register_vendor_handlers(handlers: &VendorHandlers) {
+        if TEST_EXIT {
+            handlers.register(&TestExitHandler);
+        }
+    }
+
     fn map_extra_regions(idmap: &mut Self::IdMap) {
         // SAFETY: Nothing is being unmapped, and the regions being mapped have the correct
         // attributes.
@@ -267,11 +379,16 @@ unsafe impl Platform for Qemu {
         }
     }
 
-    // This is only a toy implementation to generate a seemingly random 128-bit key from FP, LR and
-    // cntpct_el0 values. A production system must re-implement this function to generate keys from
-    // a reliable entropy source.
+    // Keys come from FEAT_RNG if it is implemented. Otherwise this falls back to a toy
+    // implementation to generate a seemingly random 128-bit key from FP, LR and cntpct_el0 values. A
+    // production system without FEAT_RNG must re-implement this function to generate keys from a
+    // reliable entropy source.
     #[cfg(feature = "pauth")]
     fn init_apkey() -> u128 {
+        if let Ok(key) = rng::read_rndr_u128() {
+            return key;
+        }
+
         let return_addr: u64;
         let frame_addr: u64;
         let cntpct = read_cntpct_el0().physicalcount();
@@ -320,13 +437,6 @@ unsafe impl Platform for Qemu {
         }
     }
 
-    fn mpidr_is_valid(mpidr: MpidrEl1) -> bool {
-        mpidr.aff3() == 0
-            && mpidr.aff2() == 0
-            && usize::from(mpidr.aff1()) < CLUSTER_COUNT
-            && usize::from(mpidr.aff0()) < MAX_CPUS_PER_CLUSTER
-    }
-
     fn psci_platform() -> Option<Self::PsciPlatformImpl> {
         Some(QemuPsciPlatformImpl {
             per_cpu_powerdown_kinds: [const { SpinMutex::new(PowerDownKind::Off) };
@@ -334,40 +444,8 @@ unsafe impl Platform for Qemu {
         })
     }
 
-    fn arch_workaround_1_supported() -> WorkaroundSupport {
-        WorkaroundSupport::SafeButNotRequired
-    }
-
-    fn arch_workaround_1() {}
-
-    fn arch_workaround_2_supported() -> WorkaroundSupport {
-        WorkaroundSupport::SafeButNotRequired
-    }
-
-    fn arch_workaround_2() {}
-
-    fn arch_workaround_3_supported() -> WorkaroundSupport {
-        WorkaroundSupport::SafeButNotRequired
-    }
-
-    fn arch_workaround_3() {}
-
-    fn arch_workaround_4_supported() -> WorkaroundSupport {
-        WorkaroundSupport::SafeButNotRequired
-    }
-
-    #[unsafe(naked)]
-    extern "C" fn core_position(mpidr: u64) -> usize {
-        naked_asm!(
-            "and	x1, x0, #{MPIDR_CPU_MASK}",
-            "and	x0, x0, #{MPIDR_CLUSTER_MASK}",
-            "add	x0, x1, x0, LSR #({MPIDR_AFFINITY_BITS} - {PLATFORM_CPU_PER_CLUSTER_SHIFT})",
-            "ret",
-            MPIDR_CPU_MASK = const MpidrEl1::AFF0_MASK << MpidrEl1::AFF0_SHIFT,
-            MPIDR_CLUSTER_MASK = const MpidrEl1::AFF1_MASK << MpidrEl1::AFF1_SHIFT,
-            MPIDR_AFFINITY_BITS = const MpidrEl1::AFFINITY_BITS,
-            PLATFORM_CPU_PER_CLUSTER_SHIFT = const PLATFORM_CPU_PER_CLUSTER_SHIFT,
-        );
+    fn nv_counters() -> Option<Self::NvCountersImpl> {
+        Some(NotSupportedNvCounters)
     }
 
     #[unsafe(naked)]
@@ -415,23 +493,36 @@ unsafe impl Platform for Qemu {
         );
     }
 
-    /// Dumps relevant GIC and CCI registers.
+    /// Resets the system through the secure GPIO, like `system_reset` but without the stack.
     ///
-    /// Clobbers x0-x11, x16, x17, sp.
+    /// With the `test_exit` feature, makes QEMU exit with `ExitCode::Crash` instead.
     #[unsafe(naked)]
-    unsafe extern "C" fn dump_registers() {
+    extern "C" fn crash_reset() -> ! {
         naked_asm!(
             asm_macros_common!(),
-            gic_debug_macros!(),
-            "mov_imm x16, {GICD_BASE}",
-            "arm_print_gic_regs",
-            "ret",
-            gic_debug_macros_purge!(),
+            "mov	x0, #{TEST_EXIT}",
+            "cbz	x0, 2f",
+            "mov	w0, #{SYS_EXIT}",
+            "adrp	x1, {CRASH_EXIT_PARAMETERS}",
+            "add	x1, x1, :lo12:{CRASH_EXIT_PARAMETERS}",
+            "hlt	#0xf000",
+            "2:",
+            "mov_imm	x0, {SECURE_GPIO_RESET_DATA}",
+            "str	wzr, [x0]",
+            "mov	w1, #{SECURE_GPIO_RESET_MASK}",
+            "str	w1, [x0]",
+            "isb",
+            "1:",
+            "wfi",
+            "b	1b",
             asm_macros_common_purge!(),
             DEBUG = const DEBUG as i32,
-            ICC_SRE_SRE_BIT = const IccSreEl3::SRE.bits(),
-            GICD_BASE = const GICD_BASE,
-            GICD_ISPENDR = const offset_of!(Gicd, ispendr),
+            // The PL061 data register only writes the pins selected by address bits 9:2.
+            SECURE_GPIO_RESET_DATA = const SECURE_GPIO_BASE + (1 << SECURE_GPIO_SYSTEM_RESET << 2),
+            SECURE_GPIO_RESET_MASK = const 1 << SECURE_GPIO_SYSTEM_RESET,
+            TEST_EXIT = const TEST_EXIT as u8,
+            SYS_EXIT = const SYS_EXIT,
+            CRASH_EXIT_PARAMETERS = sym CRASH_EXIT_PARAMETERS,
         );
     }
 }
@@ -474,6 +565,54 @@ const PSCI_MAX_POWER_LEVEL: usize = 2;
 const PSCI_STATE_COUNT: usize = PSCI_MAX_POWER_LEVEL + 1;
 const PSCI_NON_CPU_DOMAIN_COUNT: usize = CLUSTER_COUNT + 1;
 
+/// The power states which can be requested with `CPU_SUSPEND`, using the recommended StateID
+/// encoding.
+const PSCI_POWER_STATES: PowerStateTable<QemuPowerState, PSCI_STATE_COUNT> =
+    PowerStateTable::new(&[
+        PowerStateTableEntry {
+            power_state: PowerState::StandbyOrRetention(0x001),
+            states: [
+                QemuPowerState::Retention,
+                QemuPowerState::On,
+                QemuPowerState::On,
+            ],
+        },
+        PowerStateTableEntry {
+            power_state: PowerState::PowerDown(0x002),
+            states: [
+                QemuPowerState::PowerDown,
+                QemuPowerState::On,
+                QemuPowerState::On,
+            ],
+        },
+        PowerStateTableEntry {
+            power_state: PowerState::PowerDown(0x012),
+            states: [
+                QemuPowerState::PowerDown,
+                QemuPowerState::Retention,
+                QemuPowerState::On,
+            ],
+        },
+        PowerStateTableEntry {
+            power_state: PowerState::PowerDown(0x022),
+            states: [
+                QemuPowerState::PowerDown,
+                QemuPowerState::PowerDown,
+                QemuPowerState::On,
+            ],
+        },
+        // Ensure that the system power domain can't be powered down by CPU_SUSPEND. Only
+        // SYSTEM_SUSPEND can do that.
+        PowerStateTableEntry {
+            power_state: PowerState::PowerDown(0x222),
+            states: [
+                QemuPowerState::PowerDown,
+                QemuPowerState::PowerDown,
+                QemuPowerState::On,
+            ],
+        },
+    ]);
+
 struct QemuPsciPlatformImpl {
     per_cpu_powerdown_kinds: [SpinMutex<PowerDownKind>; Qemu::CORE_COUNT],
 }
@@ -510,66 +649,7 @@ impl
             QemuPowerState,
         >,
     > {
-        const POWER_STATES_MASK: u32 = 0x0000_0fff;
-        const LOCAL_PSTATE_WIDTH: u32 = 4;
-        const LOCAL_PSTATE_MASK: u32 = (1 << LOCAL_PSTATE_WIDTH) - 1;
-        // last_at_power_level is encoded in the bits immediately following the state ID bits
-        // for each power level.
-        let last_at_power_level_shift: u32 = LOCAL_PSTATE_WIDTH * (PSCI_MAX_POWER_LEVEL as u32 + 1);
-
-        let last_at_power_level_mask: u32 = LOCAL_PSTATE_MASK << last_at_power_level_shift;
-        let last_at_power_level: u32 =
-            (u32::from(power_state) & last_at_power_level_mask) >> last_at_power_level_shift;
-        if last_at_power_level as usize > PSCI_MAX_POWER_LEVEL {
-            return None;
-        }
-
-        let raw_composite_power_states = u32::from(power_state) & POWER_STATES_MASK;
-
-        if let PowerState::StandbyOrRetention(0x1) = power_state {
-            return Some(PsciCompositePowerState::new_with_last_power_level(
-                [
-                    QemuPowerState::Retention,
-                    QemuPowerState::On,
-                    QemuPowerState::On,
-                ],
-                last_at_power_level as usize,
-            ));
-        }
-
-        if let PowerState::StandbyOrRetention(_) = power_state {
-            return None;
-        }
-
-        let composite_states = match raw_composite_power_states {
-            0x2 => [
-                QemuPowerState::PowerDown,
-                QemuPowerState::On,
-                QemuPowerState::On,
-            ],
-            0x12 => [
-                QemuPowerState::PowerDown,
-                QemuPowerState::Retention,
-                QemuPowerState::On,
-            ],
-            0x22 => [
-                QemuPowerState::PowerDown,
-                QemuPowerState::PowerDown,
-                QemuPowerState::On,
-            ],
-            // Ensure that the system power domain can't be powered down by CPU_SUSPEND. Only SYSTEM_SUSPEND can do that.
-            0x222 => [
-                QemuPowerState::PowerDown,
-                QemuPowerState::PowerDown,
-                QemuPowerState::On,
-            ],
-            _ => return None,
-        };
-
-        Some(PsciCompositePowerState::new_with_last_power_level(
-            composite_states,
-            last_at_power_level as usize,
-        ))
+        PSCI_POWER_STATES.parse(power_state)
     }
 
     fn cpu_standby(&self, cpu_state: QemuPowerState) {
@@ -582,20 +662,6 @@ impl
         wfi();
     }
 
-    fn power_domain_validate_suspend(
-        &self,
-        _target_state: &PsciCompositePowerState<
-            PSCI_STATE_COUNT,
-            PSCI_MAX_POWER_LEVEL,
-            { Qemu::CORE_COUNT },
-            PSCI_NON_CPU_DOMAIN_COUNT,
-            Self::NodeIndex,
-            QemuPowerState,
-        >,
-    ) -> Result<(), ErrorCode> {
-        Ok(())
-    }
-
     fn power_domain_suspend(
         &self,
         _target_state: &PsciCompositePowerState<
@@ -637,7 +703,6 @@ impl
     ) {
         assert_eq!(target_state.cpu_level_state(), QemuPowerState::PowerDown);
 
-        GIC.get().unwrap().cpu_interface_disable();
         *self.per_cpu_powerdown_kinds[CoresImpl::<Qemu>::core_index()].lock() = PowerDownKind::Off;
     }
 
@@ -694,12 +759,12 @@ impl
         >,
     ) {
         assert_eq!(previous_state.cpu_level_state(), QemuPowerState::PowerDown);
-        let gic = GIC.get().unwrap();
-        gic.redistributor_init(&Qemu::GIC_CONFIG);
-        gic.cpu_interface_enable();
     }
 
     fn system_off(&self) -> ! {
+        if TEST_EXIT {
+            test_exit::exit(ExitCode::SystemOff);
+        }
         let mut gpio = SECURE_GPIO.lock();
         gpio.pin_set(SECURE_GPIO_SYSTEM_OFF, false).unwrap();
         gpio.pin_set(SECURE_GPIO_SYSTEM_OFF, true).unwrap();
@@ -708,6 +773,9 @@ impl
     }
 
     fn system_reset(&self) -> ! {
+        if TEST_EXIT {
+            test_exit::exit(ExitCode::SystemReset);
+        }
         let mut gpio = SECURE_GPIO.lock();
         gpio.pin_set(SECURE_GPIO_SYSTEM_RESET, false).unwrap();
         gpio.pin_set(SECURE_GPIO_SYSTEM_RESET, true).unwrap();
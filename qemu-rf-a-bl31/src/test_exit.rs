@@ -0,0 +1,73 @@
+// Copyright The Rusted Firmware-A Contributors.
+//
+// SPDX-License-Identifier: BSD-3-Clause
+
+//! Exits QEMU with an exit code which tells CI how a test run ended.
+//!
+//! With the `test_exit` feature, `SYSTEM_OFF`, `SYSTEM_RESET`, a crash in EL3 and the
+//! `QEMU_TEST_EXIT` vendor-specific EL3 monitor SMC all make QEMU exit through semihosting, each
+//! with a different [`ExitCode`], rather than powering off or resetting the machine. This requires
+//! QEMU to be run with `-semihosting-config enable=on`, as otherwise the semihosting call faults.
+
+use core::ops::RangeInclusive;
+use rf_a_bl31::{
+    semihosting::{AdpStopped, semihosting_exit},
+    services::vendor::{
+        FunctionId, INVALID_PARAMETER, NOT_SUPPORTED, SetFrom, SmcReturn, VendorHandler,
+    },
+};
+
+/// Whether QEMU should exit through semihosting.
+pub const TEST_EXIT: bool = cfg!(feature = "test_exit");
+
+/// Reports the result of the normal world's tests, with 0 in x1 if they passed or 1 if any failed.
+const QEMU_TEST_EXIT: u32 = 0x8700_0040;
+
+/// The `SYS_EXIT` semihosting operation code.
+pub const SYS_EXIT: u32 = 0x18;
+
+/// The exit codes with which QEMU exits.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[repr(u8)]
+pub enum ExitCode {
+    /// The normal world reported that all its tests passed.
+    TestsPassed = 0,
+    /// The normal world reported that some of its tests failed.
+    TestsFailed = 1,
+    /// The normal world called `SYSTEM_OFF`.
+    SystemOff = 2,
+    /// The normal world called `SYSTEM_RESET`.
+    SystemReset = 3,
+    /// EL3 crashed.
+    Crash = 4,
+}
+
+/// The `SYS_EXIT` parameter block used by `crash_reset`, which can't build one on the stack.
+pub static CRASH_EXIT_PARAMETERS: [u64; 2] =
+    [AdpStopped::ApplicationExit as u64, ExitCode::Crash as u64];
+
+/// Makes QEMU exit with the given code.
+pub fn exit(code: ExitCode) -> ! {
+    semihosting_exit(AdpStopped::ApplicationExit, code as u64);
+    panic!("QEMU didn't exit with {code:?}");
+}
+
+/// Handles the `QEMU_TEST_EXIT` SMC.
+pub struct TestExitHandler;
+
+impl VendorHandler for TestExitHandler {
+    fn function_numbers(&self) -> RangeInclusive<u16> {
+        0x0040..=0x0040
+    }
+
+    fn handle_non_secure_smc(&self, function: FunctionId, regs: &mut SmcReturn) {
+        match function.0 {
+            QEMU_TEST_EXIT => match regs.values()[1] {
+                0 => exit(ExitCode::TestsPassed),
+                1 => exit(ExitCode::TestsFailed),
+                _ => regs.set_from(INVALID_PARAMETER),
+            },
+            _ => regs.set_from(NOT_SUPPORTED),
+        }
+    }
+}
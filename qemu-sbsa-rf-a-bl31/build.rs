@@ -0,0 +1,34 @@
+// Copyright The Rusted Firmware-A Contributors.
+//
+// SPDX-License-Identifier: BSD-3-Clause
+
+//! Build script for RF-A on the QEMU sbsa-ref machine.
+
+use rf_a_bl31_build::{Builder, configure_build};
+
+fn main() {
+    configure_build(&QemuSbsaBuilder);
+}
+
+/// Platform builder implementation for the QEMU sbsa-ref machine.
+pub struct QemuSbsaBuilder;
+
+impl QemuSbsaBuilder {
+    /// BL31 goes at the top of secure RAM, just below BL1's RW data, as with C TF-A's qemu_sbsa
+    /// port.
+    const BL31_BASE: u64 = Self::BL1_RW_BASE - Self::BL31_SIZE;
+    const BL31_SIZE: u64 = 0x0040_0000;
+
+    const SEC_SRAM_END: u64 = 0x4000_0000;
+    const BL1_RW_BASE: u64 = Self::SEC_SRAM_END - 0x0001_2000;
+}
+
+impl Builder for QemuSbsaBuilder {
+    fn bl31_base(&self) -> u64 {
+        Self::BL31_BASE
+    }
+
+    fn bl31_size(&self) -> u64 {
+        Self::BL31_SIZE
+    }
+}
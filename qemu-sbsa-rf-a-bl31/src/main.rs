@@ -0,0 +1,796 @@
+// Copyright The Rusted Firmware-A Contributors.
+//
+// SPDX-License-Identifier: BSD-3-Clause
+
+//! RF-A BL31 deployment for the QEMU sbsa-ref machine.
+
+#![no_main]
+#![no_std]
+
+use arm_pl011_uart::PL011Registers;
+#[cfg(feature = "pauth")]
+use core::arch::asm;
+use core::{mem::offset_of, ptr::NonNull};
+#[cfg(feature = "pauth")]
+use rf_a_bl31::reexports::arm_sysregs::read_cntpct_el0;
+#[cfg(feature = "pauth")]
+use rf_a_bl31::rng;
+use rf_a_bl31::{
+    aarch64::{dsb_sy, isb, sev, wfi},
+    affinity_core_position, all_asm, asm_macros_common, asm_macros_common_purge,
+    bl31_warm_entrypoint,
+    context::{CoresImpl, EntryPointInfo},
+    cpu::qemu_max::QemuMax,
+    cpu_extensions::{CpuExtension, simd::Simd},
+    debug::{DEBUG, EarlyConsole},
+    define_cpu_ops, define_errata_list,
+    dram::zeroed_mut,
+    gic_debug_macros, gic_debug_macros_purge,
+    gicv3::{Gic, GicConfig, SgiRegistry},
+    logger::{
+        HybridLogger,
+        inmemory::{MemoryLogger, PerCoreMemoryLogger},
+        pl011::Pl011Console,
+    },
+    naked_asm,
+    nv_counter::NotSupportedNvCounters,
+    pagetable::{
+        IdMap, MT_DEVICE, MT_MEMORY_EL3, disable_mmu_el3,
+        early_pagetable::{EarlyRegion, define_early_mapping},
+    },
+    panic_handler,
+    platform::{Board, DummyService, Platform, Soc, my_core_pos, topology::AffinityTopology},
+    reexports::{
+        aarch64_paging::paging::MemoryRegion,
+        arm_gic::{
+            IntId,
+            gicv3::registers::{Gicd, GicrSgi},
+        },
+        arm_psci::{ErrorCode, Mpidr, PowerState},
+        arm_sysregs::{IccSreEl3, MpidrEl1},
+        percore::Cores,
+    },
+    services::{
+        arch::WorkaroundSupport,
+        psci::{
+            PlatformPowerStateInterface, PowerStateTable, PowerStateTableEntry, PowerStateType,
+            PsciCompositePowerState, PsciPlatformInterface, PsciPlatformOptionalFeatures,
+            try_get_cpu_index_by_mpidr,
+        },
+        trng::RndrTrngPlatformImpl,
+    },
+    spin_mutex::{SpinMutex, SpinMutexGuard},
+    statics,
+};
+use safe_mmio::{UniqueMmioPointer, fields::ReadWrite};
+
+const DEVICE0_BASE: usize = 0x4000_0000;
+const DEVICE0_SIZE: usize = 0x0400_0000;
+const DEVICE1_BASE: usize = 0x5000_0000;
+const DEVICE1_SIZE: usize = 0x0400_0000;
+const DEVICE2_BASE: usize = 0x6000_0000;
+const DEVICE2_SIZE: usize = 0x1000_0000;
+const SEC_SRAM_BASE: usize = 0x2000_0000;
+const SHARED_RAM_BASE: usize = SEC_SRAM_BASE;
+const SHARED_RAM_SIZE: usize = 0x0000_1000;
+const SHARED_RAM: MemoryRegion =
+    MemoryRegion::new(SHARED_RAM_BASE, SHARED_RAM_BASE + SHARED_RAM_SIZE);
+const DEVICE0: MemoryRegion = MemoryRegion::new(DEVICE0_BASE, DEVICE0_BASE + DEVICE0_SIZE);
+const DEVICE1: MemoryRegion = MemoryRegion::new(DEVICE1_BASE, DEVICE1_BASE + DEVICE1_SIZE);
+const DEVICE2: MemoryRegion = MemoryRegion::new(DEVICE2_BASE, DEVICE2_BASE + DEVICE2_SIZE);
+/// BL31 sits at the top of secure RAM, just below BL1's RW data. This must match `build.rs`.
+const BL31_BASE: usize = 0x3fbe_e000;
+const BL31_SIZE: usize = 0x0040_0000;
+const BL32_BASE: usize = SHARED_RAM_BASE + SHARED_RAM_SIZE;
+
+/// The part of the secure flash used by the secure variable store.
+const SECURE_VARSTORE_BASE: usize = 0x0100_0000;
+const SECURE_VARSTORE_SIZE: usize = 0x0010_0000;
+const SECURE_VARSTORE: MemoryRegion = MemoryRegion::new(
+    SECURE_VARSTORE_BASE,
+    SECURE_VARSTORE_BASE + SECURE_VARSTORE_SIZE,
+);
+
+const GICD_BASE: usize = 0x4006_0000;
+const GICR_BASE: usize = 0x4008_0000;
+
+const TRUSTED_MAILBOX_BASE: usize = SHARED_RAM_BASE;
+const HOLD_SLOTS: *mut [HoldSlot; QemuSbsa::CORE_COUNT] = TRUSTED_MAILBOX_BASE as _;
+
+const HOLD_MAGIC1: u64 = 0xCAFE_CAFE;
+const HOLD_MAGIC2: u64 = 0xBEEF_BEEF;
+const HOLD_STATE_WAIT: u64 = !0;
+
+/// Base address of the secure world PL011 UART.
+const SECURE_UART_BASE: usize = 0x6003_0000;
+const PL011_BASE_ADDRESS: *mut PL011Registers = SECURE_UART_BASE as _;
+/// Base address of GICv3 distributor.
+const GICD_BASE_ADDRESS: *mut Gicd = GICD_BASE as _;
+/// Base address of the first GICv3 redistributor frame.
+const GICR_BASE_ADDRESS: *mut GicrSgi = GICR_BASE as _;
+
+/// Command register of the secure embedded controller, which handles system off and system reset.
+/// Values taken from C TF-A.
+const SECURE_EC_ADDRESS: *mut ReadWrite<u32> = DEVICE1_BASE as _;
+const SECURE_EC_CMD_POWEROFF: u32 = 0x01;
+const SECURE_EC_CMD_REBOOT: u32 = 0x02;
+
+/// The base of non-secure DRAM, where the Flattened Device Tree Blob (DTB) is loaded.
+const DTB_ADDRESS: u64 = 0x100_0000_0000;
+/// The non-secure firmware is loaded from the start of the non-secure flash.
+const NS_FLASH_BASE: usize = 0x1000_0000;
+
+// TODO: Use the correct addresses here.
+/// The physical address of the SPMC manifest blob.
+const TOS_FW_CONFIG_ADDRESS: u64 = 0;
+const HW_CONFIG_ADDRESS: u64 = 0;
+
+/// The number of CPU clusters.
+///
+/// sbsa-ref supports many more, but we only support a single cluster for now so that the runtime
+/// services still fit on the stack during cold boot.
+const CLUSTER_COUNT: usize = 1;
+/// The maximum number of CPUs in each cluster. sbsa-ref puts 8 CPUs in each cluster in their MPIDR.
+const MAX_CPUS_PER_CLUSTER: usize = 8;
+const TOPOLOGY: AffinityTopology = AffinityTopology {
+    clusters: CLUSTER_COUNT,
+    cores_per_cluster: MAX_CPUS_PER_CLUSTER,
+    threads_per_core: 1,
+};
+
+const TRNG_REQ_WORDS: usize = 1;
+/// UUID of QEMU's FEAT_RNG entropy source, which is available with `-cpu max`.
+const TRNG_UUID: u128 = 0x68bf7b49_7558_40e4_8c76_6fb6fb85ea8b;
+
+/// The per-core log buffer size in bytes. We subtract the size of the metadata so that the total
+/// size of each `MemoryLogger` will be 1024 bytes.
+const LOG_BUFFER_SIZE: usize = 1024 - size_of::<MemoryLogger<0>>();
+
+zeroed_mut! {
+    /// Per-core in-memory loggers.
+    MEMORY_LOGGERS, [MemoryLogger<LOG_BUFFER_SIZE>; QemuSbsa::CORE_COUNT]
+}
+
+// SAFETY: `SECURE_EC_ADDRESS` is the command register of the secure embedded controller, and
+// nothing else accesses it.
+static SECURE_EC: SpinMutex<UniqueMmioPointer<ReadWrite<u32>>> =
+    SpinMutex::new(unsafe { UniqueMmioPointer::new(NonNull::new(SECURE_EC_ADDRESS).unwrap()) });
+
+static SIMD: Simd<{ QemuSbsa::CORE_COUNT }, QemuSbsa, 64> = Simd::sve();
+
+#[repr(C, align(64))]
+struct HoldSlot {
+    entry: u64,
+    magic1: u64,
+    magic2: u64,
+}
+
+/// Initialise the hold pen by writing magic tags to every slot.
+fn plat_hold_pen_init() {
+    // SAFETY: `TRUSTED_MAILBOX_BASE` is the base address of the shared mailbox device memory.
+    // Other cores are concurrently reading from this region, but aligned 64bit writes are
+    // 'single-copy atomic' as they will either complete in full or not at all.
+    unsafe {
+        for i in 0..QemuSbsa::CORE_COUNT {
+            let slot_ptr = &raw mut (*HOLD_SLOTS)[i];
+
+            (&raw mut (*slot_ptr).entry).write_volatile(HOLD_STATE_WAIT);
+
+            // Ensure the entry value is committed before the magic
+            // tags that make this slot visible to polling secondaries.
+            core::arch::asm!("dmb sy");
+
+            (&raw mut (*slot_ptr).magic1).write_volatile(HOLD_MAGIC1);
+            (&raw mut (*slot_ptr).magic2).write_volatile(HOLD_MAGIC2);
+        }
+    }
+}
+
+/// Signal a secondary core to branch to the given entrypoint.
+fn plat_hold_pen_signal(cpu_index: usize, entrypoint: unsafe extern "C" fn() -> !) {
+    // SAFETY: `TRUSTED_MAILBOX_BASE` is the base address of the shared mailbox device memory.
+    // Other cores are concurrently reading from or writing to this region, but aligned 64bit writes
+    // are 'single-copy atomic' as they will either complete in full or not at all.
+    unsafe {
+        let slot_ptr = &raw mut (*HOLD_SLOTS)[cpu_index];
+        (&raw mut (*slot_ptr).entry).write_volatile(entrypoint as usize as u64);
+    }
+
+    // Ensure that the entry value is committed before signalling secondary cores to wake up.
+    dsb_sy();
+
+    // Signal the secondary core to wake up and jump to the given entrypoint.
+    sev();
+}
+
+/// The aarch64 'sbsa-ref' machine of the QEMU emulator.
+struct QemuSbsa;
+
+define_cpu_ops!(QemuSbsa, [QemuMax]);
+define_errata_list!(QemuSbsa, []);
+
+define_early_mapping!(
+    QemuSbsa,
+    [
+        EarlyRegion {
+            address_range: BL31_BASE..(BL31_BASE + BL31_SIZE),
+            attributes: MT_MEMORY_EL3
+        },
+        EarlyRegion {
+            address_range: DEVICE2_BASE..(DEVICE2_BASE + DEVICE2_SIZE),
+            attributes: MT_DEVICE
+        }
+    ]
+);
+
+statics!(QemuSbsa);
+all_asm!(QemuSbsa);
+panic_handler!(QemuSbsa);
+
+/// The SoC of QEMU's 'sbsa-ref' machine.
+pub struct QemuSbsaSoc;
+
+// SAFETY: `core_position` is indeed a naked function, doesn't access the stack or any other memory,
+// only clobbers x0-x5, and returns a unique index as long as `TOPOLOGY` is correct.
+// `dump_registers` is also a naked function which only uses the crash console and the registers it
+// is documented to clobber.
+unsafe impl Soc for QemuSbsaSoc {
+    const CORE_COUNT: usize = TOPOLOGY.core_count();
+    const CACHE_WRITEBACK_GRANULE: usize = 1 << 6;
+
+    const GIC_CONFIG: GicConfig = GicConfig {
+        sgis: SgiRegistry::EMPTY,
+        interrupts_config: &[],
+    };
+
+    fn mpidr_is_valid(mpidr: MpidrEl1) -> bool {
+        TOPOLOGY.mpidr_is_valid(mpidr)
+    }
+
+    fn arch_workaround_1_supported() -> WorkaroundSupport {
+        WorkaroundSupport::SafeButNotRequired
+    }
+
+    fn arch_workaround_1() {}
+
+    fn arch_workaround_2_supported() -> WorkaroundSupport {
+        WorkaroundSupport::SafeButNotRequired
+    }
+
+    fn arch_workaround_2() {}
+
+    fn arch_workaround_3_supported() -> WorkaroundSupport {
+        WorkaroundSupport::SafeButNotRequired
+    }
+
+    fn arch_workaround_3() {}
+
+    fn arch_workaround_4_supported() -> WorkaroundSupport {
+        WorkaroundSupport::SafeButNotRequired
+    }
+
+    #[unsafe(naked)]
+    extern "C" fn core_position(mpidr: u64) -> usize {
+        affinity_core_position!(TOPOLOGY)
+    }
+
+    /// Dumps relevant GIC and CCI registers.
+    ///
+    /// Clobbers x0-x11, x16, x17, sp.
+    #[unsafe(naked)]
+    unsafe extern "C" fn dump_registers() {
+        naked_asm!(
+            asm_macros_common!(),
+            gic_debug_macros!(),
+            "mov_imm x16, {GICD_BASE}",
+            "arm_print_gic_regs",
+            "ret",
+            gic_debug_macros_purge!(),
+            asm_macros_common_purge!(),
+            DEBUG = const DEBUG as i32,
+            ICC_SRE_SRE_BIT = const IccSreEl3::SRE.bits(),
+            GICD_BASE = const GICD_BASE,
+            GICD_ISPENDR = const offset_of!(Gicd, ispendr),
+        );
+    }
+}
+
+// SAFETY: `cold_boot_handler` and the crash console functions are naked functions which don't use
+// the stack, and only clobber the registers they are documented to clobber.
+unsafe impl Board for QemuSbsa {
+    type Soc = QemuSbsaSoc;
+
+    type LogSinkImpl = HybridLogger<
+        PerCoreMemoryLogger<'static, { Self::CORE_COUNT }, LOG_BUFFER_SIZE, Self>,
+        Pl011Console,
+    >;
+    type IdMap = IdMap<{ Self::PAGE_HEAP_PAGE_COUNT }>;
+    type PsciPlatformImpl = QemuSbsaPsciPlatformImpl;
+    type TrngPlatformImpl = RndrTrngPlatformImpl<TRNG_UUID>;
+
+    type PlatformServiceImpl = DummyService;
+    type NvCountersImpl = NotSupportedNvCounters;
+
+    const CPU_EXTENSIONS: &'static [&'static dyn CpuExtension] = &[&SIMD];
+
+    const EARLY_CONSOLE: EarlyConsole = EarlyConsole::Semihosting;
+
+    fn init_with_early_mapping(_arg0: u64, _arg1: u64, _arg2: u64, _arg3: u64) {
+        // SAFETY: `PL011_BASE_ADDRESS` is the base address of a PL011 device, and nothing else
+        // accesses that address range. The address is valid both with the early mapping and the
+        // main one, as it's within the `DEVICE2` region that is identity mapped in both cases.
+        let uart_pointer =
+            unsafe { UniqueMmioPointer::new(NonNull::new(PL011_BASE_ADDRESS).unwrap()) };
+        LOGGER
+            .init(HybridLogger::new(
+                PerCoreMemoryLogger::new(SpinMutexGuard::leak(MEMORY_LOGGERS.lock()).each_mut()),
+                // QEMU doesn't model the UART clock, so leave the UART as QEMU configured it.
+                Pl011Console::new(uart_pointer),
+            ))
+            .expect("Failed to initialise logger");
+    }
+
+    fn init(_arg0: u64, _arg1: u64, _arg2: u64, _arg3: u64) {
+        // Initialise hold pen for all secondary cores.
+        plat_hold_pen_init();
+
+        GIC.call_once(|| {
+            // SAFETY: `GICD_BASE_ADDRESS` is a unique pointer to the QEMU sbsa-ref's GICD register block.
+            let gicd = unsafe { UniqueMmioPointer::new(NonNull::new(GICD_BASE_ADDRESS).unwrap()) };
+            let gicr_base = NonNull::new(GICR_BASE_ADDRESS).unwrap();
+            // SAFETY: `gicr_base` points to a continuously mapped GIC redistributor memory area
+            // until the last redistributor block. There are no other references to this address
+            // range.
+            unsafe { Gic::new(gicd, gicr_base, false) }
+        });
+    }
+
+    fn map_extra_regions(idmap: &mut Self::IdMap) {
+        // SAFETY: Nothing is being unmapped, and the regions being mapped have the correct
+        // attributes.
+        unsafe {
+            idmap.map_region(&SHARED_RAM, MT_DEVICE);
+            idmap.map_region(&DEVICE0, MT_DEVICE);
+            idmap.map_region(&DEVICE1, MT_DEVICE);
+            idmap.map_region(&DEVICE2, MT_DEVICE);
+            idmap.map_region(&SECURE_VARSTORE, MT_DEVICE);
+        }
+    }
+
+    // Keys come from FEAT_RNG if it is implemented. Otherwise this falls back to a toy
+    // implementation to generate a seemingly random 128-bit key from FP, LR and cntpct_el0 values. A
+    // production system without FEAT_RNG must re-implement this function to generate keys from a
+    // reliable entropy source.
+    #[cfg(feature = "pauth")]
+    fn init_apkey() -> u128 {
+        if let Ok(key) = rng::read_rndr_u128() {
+            return key;
+        }
+
+        let return_addr: u64;
+        let frame_addr: u64;
+        let cntpct = read_cntpct_el0().physicalcount();
+
+        // SAFETY: We are just reading general purpose registers.
+        unsafe {
+            asm!("mov {0}, x30", out(reg) return_addr, options(nomem, nostack, preserves_flags));
+            asm!("mov {0}, x29", out(reg) frame_addr, options(nomem, nostack, preserves_flags));
+        }
+
+        let key_lo = (return_addr << 13) ^ frame_addr ^ cntpct;
+        let key_hi = (frame_addr << 15) ^ return_addr ^ cntpct;
+
+        ((key_hi as u128) << 64) | (key_lo as u128)
+    }
+
+    fn create_service() -> Self::PlatformServiceImpl {
+        DummyService
+    }
+
+    fn handle_group0_interrupt(int_id: IntId) {
+        todo!("Handle group0 interrupt {:?}", int_id)
+    }
+
+    fn secure_entry_point() -> EntryPointInfo {
+        let core_linear_id = CoresImpl::<Self>::core_index() as u64;
+        EntryPointInfo {
+            pc: BL32_BASE,
+            args: [
+                TOS_FW_CONFIG_ADDRESS,
+                HW_CONFIG_ADDRESS,
+                0,
+                0,
+                core_linear_id,
+                0,
+                0,
+                0,
+            ],
+        }
+    }
+
+    fn non_secure_entry_point() -> EntryPointInfo {
+        EntryPointInfo {
+            pc: NS_FLASH_BASE,
+            args: [DTB_ADDRESS, 0, 0, 0, 0, 0, 0, 0],
+        }
+    }
+
+    fn psci_platform() -> Option<Self::PsciPlatformImpl> {
+        Some(QemuSbsaPsciPlatformImpl {
+            per_cpu_powerdown_kinds: [const { SpinMutex::new(PowerDownKind::Off) };
+                QemuSbsa::CORE_COUNT],
+        })
+    }
+
+    fn nv_counters() -> Option<Self::NvCountersImpl> {
+        Some(NotSupportedNvCounters)
+    }
+
+    #[unsafe(naked)]
+    unsafe extern "C" fn cold_boot_handler() {
+        naked_asm!("ret");
+    }
+
+    #[unsafe(naked)]
+    extern "C" fn crash_console_init() -> u32 {
+        naked_asm!(
+            asm_macros_common!(),
+            "mov_imm	x0, {PLAT_QEMU_CRASH_UART_BASE}",
+            "mov_imm	x1, {PLAT_QEMU_CRASH_UART_CLK_IN_HZ}",
+            "mov_imm	x2, {PLAT_QEMU_CONSOLE_BAUDRATE}",
+            "b	console_pl011_core_init",
+            asm_macros_common_purge!(),
+            DEBUG = const DEBUG as i32,
+            PLAT_QEMU_CRASH_UART_BASE = const SECURE_UART_BASE,
+            PLAT_QEMU_CRASH_UART_CLK_IN_HZ = const 1,
+            PLAT_QEMU_CONSOLE_BAUDRATE = const 115_200,
+        );
+    }
+
+    #[unsafe(naked)]
+    extern "C" fn crash_console_putc(char: u32) -> i32 {
+        naked_asm!(
+            asm_macros_common!(),
+            "mov_imm	x1, {PLAT_QEMU_CRASH_UART_BASE}",
+            "b	console_pl011_core_putc",
+            asm_macros_common_purge!(),
+            DEBUG = const DEBUG as i32,
+            PLAT_QEMU_CRASH_UART_BASE = const SECURE_UART_BASE,
+        );
+    }
+
+    #[unsafe(naked)]
+    extern "C" fn crash_console_flush() {
+        naked_asm!(
+            asm_macros_common!(),
+            "mov_imm	x0, {PLAT_QEMU_CRASH_UART_BASE}",
+            "b	console_pl011_core_flush",
+            asm_macros_common_purge!(),
+            DEBUG = const DEBUG as i32,
+            PLAT_QEMU_CRASH_UART_BASE = const SECURE_UART_BASE,
+        );
+    }
+
+    /// Resets the system through the secure EC, like `system_reset` but without the stack.
+    #[unsafe(naked)]
+    extern "C" fn crash_reset() -> ! {
+        naked_asm!(
+            asm_macros_common!(),
+            "mov_imm	x0, {SECURE_EC_BASE}",
+            "mov	w1, #{SECURE_EC_CMD_REBOOT}",
+            "str	w1, [x0]",
+            "isb",
+            "1:",
+            "wfi",
+            "b	1b",
+            asm_macros_common_purge!(),
+            DEBUG = const DEBUG as i32,
+            SECURE_EC_BASE = const DEVICE1_BASE,
+            SECURE_EC_CMD_REBOOT = const SECURE_EC_CMD_REBOOT,
+        );
+    }
+}
+
+#[derive(PartialEq, PartialOrd, Debug, Eq, Ord, Clone, Copy)]
+enum QemuSbsaPowerState {
+    On,
+    Retention,
+    PowerDown,
+}
+
+impl PlatformPowerStateInterface for QemuSbsaPowerState {
+    const OFF: Self = Self::PowerDown;
+    const RUN: Self = Self::On;
+
+    fn power_state_type(&self) -> PowerStateType {
+        match self {
+            Self::PowerDown => PowerStateType::PowerDown,
+            Self::Retention => PowerStateType::StandbyOrRetention,
+            Self::On => PowerStateType::Run,
+        }
+    }
+}
+
+impl From<QemuSbsaPowerState> for usize {
+    fn from(_value: QemuSbsaPowerState) -> Self {
+        todo!()
+    }
+}
+
+#[derive(PartialEq, Clone, Copy, Eq)]
+enum PowerDownKind {
+    // For CPU_OFF
+    Off,
+    // For CPU_SUSPEND
+    Suspend,
+}
+
+const PSCI_MAX_POWER_LEVEL: usize = 2;
+const PSCI_STATE_COUNT: usize = PSCI_MAX_POWER_LEVEL + 1;
+const PSCI_NON_CPU_DOMAIN_COUNT: usize = CLUSTER_COUNT + 1;
+
+/// The power states which can be requested with `CPU_SUSPEND`, using the recommended StateID
+/// encoding.
+const PSCI_POWER_STATES: PowerStateTable<QemuSbsaPowerState, PSCI_STATE_COUNT> =
+    PowerStateTable::new(&[
+        PowerStateTableEntry {
+            power_state: PowerState::StandbyOrRetention(0x001),
+            states: [
+                QemuSbsaPowerState::Retention,
+                QemuSbsaPowerState::On,
+                QemuSbsaPowerState::On,
+            ],
+        },
+        PowerStateTableEntry {
+            power_state: PowerState::PowerDown(0x002),
+            states: [
+                QemuSbsaPowerState::PowerDown,
+                QemuSbsaPowerState::On,
+                QemuSbsaPowerState::On,
+            ],
+        },
+        PowerStateTableEntry {
+            power_state: PowerState::PowerDown(0x012),
+            states: [
+                QemuSbsaPowerState::PowerDown,
+                QemuSbsaPowerState::Retention,
+                QemuSbsaPowerState::On,
+            ],
+        },
+        PowerStateTableEntry {
+            power_state: PowerState::PowerDown(0x022),
+            states: [
+                QemuSbsaPowerState::PowerDown,
+                QemuSbsaPowerState::PowerDown,
+                QemuSbsaPowerState::On,
+            ],
+        },
+        // Ensure that the system power domain can't be powered down by CPU_SUSPEND. Only
+        // SYSTEM_SUSPEND can do that.
+        PowerStateTableEntry {
+            power_state: PowerState::PowerDown(0x222),
+            states: [
+                QemuSbsaPowerState::PowerDown,
+                QemuSbsaPowerState::PowerDown,
+                QemuSbsaPowerState::On,
+            ],
+        },
+    ]);
+
+struct QemuSbsaPsciPlatformImpl {
+    per_cpu_powerdown_kinds: [SpinMutex<PowerDownKind>; QemuSbsa::CORE_COUNT],
+}
+
+impl
+    PsciPlatformInterface<
+        PSCI_STATE_COUNT,
+        PSCI_MAX_POWER_LEVEL,
+        { QemuSbsa::CORE_COUNT },
+        PSCI_NON_CPU_DOMAIN_COUNT,
+    > for QemuSbsaPsciPlatformImpl
+{
+    const POWER_DOMAIN_COUNT: usize = PSCI_NON_CPU_DOMAIN_COUNT + QemuSbsa::CORE_COUNT;
+
+    const FEATURES: PsciPlatformOptionalFeatures = PsciPlatformOptionalFeatures::OS_INITIATED_MODE;
+
+    type PlatformPowerState = QemuSbsaPowerState;
+
+    type NodeIndex = u8;
+
+    fn topology() -> &'static [usize] {
+        &[1, CLUSTER_COUNT, MAX_CPUS_PER_CLUSTER]
+    }
+
+    fn try_parse_power_state(
+        power_state: PowerState,
+    ) -> Option<
+        PsciCompositePowerState<
+            PSCI_STATE_COUNT,
+            PSCI_MAX_POWER_LEVEL,
+            { QemuSbsa::CORE_COUNT },
+            PSCI_NON_CPU_DOMAIN_COUNT,
+            Self::NodeIndex,
+            QemuSbsaPowerState,
+        >,
+    > {
+        PSCI_POWER_STATES.parse(power_state)
+    }
+
+    fn cpu_standby(&self, cpu_state: QemuSbsaPowerState) {
+        assert_eq!(
+            cpu_state.power_state_type(),
+            PowerStateType::StandbyOrRetention
+        );
+
+        dsb_sy();
+        wfi();
+    }
+
+    fn power_domain_suspend(
+        &self,
+        _target_state: &PsciCompositePowerState<
+            PSCI_STATE_COUNT,
+            PSCI_MAX_POWER_LEVEL,
+            { QemuSbsa::CORE_COUNT },
+            PSCI_NON_CPU_DOMAIN_COUNT,
+            Self::NodeIndex,
+            QemuSbsaPowerState,
+        >,
+    ) {
+        *self.per_cpu_powerdown_kinds[CoresImpl::<QemuSbsa>::core_index()].lock() =
+            PowerDownKind::Suspend;
+    }
+
+    fn power_domain_suspend_finish(
+        &self,
+        _previous_state: &PsciCompositePowerState<
+            PSCI_STATE_COUNT,
+            PSCI_MAX_POWER_LEVEL,
+            { QemuSbsa::CORE_COUNT },
+            PSCI_NON_CPU_DOMAIN_COUNT,
+            Self::NodeIndex,
+            QemuSbsaPowerState,
+        >,
+    ) {
+    }
+
+    fn power_domain_off(
+        &self,
+        target_state: &PsciCompositePowerState<
+            PSCI_STATE_COUNT,
+            PSCI_MAX_POWER_LEVEL,
+            { QemuSbsa::CORE_COUNT },
+            PSCI_NON_CPU_DOMAIN_COUNT,
+            Self::NodeIndex,
+            QemuSbsaPowerState,
+        >,
+    ) {
+        assert_eq!(
+            target_state.cpu_level_state(),
+            QemuSbsaPowerState::PowerDown
+        );
+
+        *self.per_cpu_powerdown_kinds[CoresImpl::<QemuSbsa>::core_index()].lock() =
+            PowerDownKind::Off;
+    }
+
+    fn power_domain_power_down(
+        &self,
+        _target_state: &PsciCompositePowerState<
+            PSCI_STATE_COUNT,
+            PSCI_MAX_POWER_LEVEL,
+            { QemuSbsa::CORE_COUNT },
+            PSCI_NON_CPU_DOMAIN_COUNT,
+            Self::NodeIndex,
+            QemuSbsaPowerState,
+        >,
+    ) {
+        if *self.per_cpu_powerdown_kinds[CoresImpl::<QemuSbsa>::core_index()].lock()
+            == PowerDownKind::Off
+        {
+            // SAFETY: `disable_mmu_el3` is safe to call here as the CPU is about to be switched off.
+            // `plat_secondary_cold_boot_setup` is trusted assembly.
+            unsafe {
+                disable_mmu_el3();
+                plat_secondary_cold_boot_setup();
+            }
+        } else {
+            dsb_sy();
+            wfi();
+            // Instead of behaving as if this was a powerdown abandon, simply call the bl31
+            // warmboot entry point. This is closer to what real hardware would do most of the time.
+            // SAFETY: `bl31_warmboot_entrypoint` and `disable_mmu_el3` are trusted assembly.
+            unsafe {
+                disable_mmu_el3();
+                bl31_warm_entrypoint::<QemuSbsa>();
+            }
+        }
+    }
+
+    fn power_domain_on(&self, mpidr: Mpidr) -> Result<(), ErrorCode> {
+        let cpu_index = try_get_cpu_index_by_mpidr::<QemuSbsa, Self::NodeIndex>(mpidr)
+            .ok_or(ErrorCode::InvalidParameters)?;
+        debug_assert!(usize::from(cpu_index) < QemuSbsa::CORE_COUNT);
+        plat_hold_pen_signal(cpu_index.into(), bl31_warm_entrypoint::<QemuSbsa>);
+        Ok(())
+    }
+
+    fn power_domain_on_finish(
+        &self,
+        previous_state: &PsciCompositePowerState<
+            PSCI_STATE_COUNT,
+            PSCI_MAX_POWER_LEVEL,
+            { QemuSbsa::CORE_COUNT },
+            PSCI_NON_CPU_DOMAIN_COUNT,
+            Self::NodeIndex,
+            QemuSbsaPowerState,
+        >,
+    ) {
+        assert_eq!(
+            previous_state.cpu_level_state(),
+            QemuSbsaPowerState::PowerDown
+        );
+    }
+
+    fn system_off(&self) -> ! {
+        SECURE_EC.lock().write(SECURE_EC_CMD_POWEROFF);
+        isb();
+        panic!("System off was not triggered by secure EC");
+    }
+
+    fn system_reset(&self) -> ! {
+        SECURE_EC.lock().write(SECURE_EC_CMD_REBOOT);
+        isb();
+        panic!("System reset was not triggered by secure EC");
+    }
+}
+
+/// Polls the holding pen for the given core until the magic tags and entrypoint are set, then
+/// jumps to the entrypoint. This is called by secondary cores after waking up from a power-down
+/// state.
+#[unsafe(naked)]
+unsafe extern "C" fn plat_secondary_cold_boot_setup() -> ! {
+    naked_asm!(
+        "bl  {plat_my_core_pos}",
+        // x0 = core index
+        "mov x1, #{HOLD_SLOT_SIZE}",
+        "ldr x2, ={HOLD_SLOTS_BASE}",
+        "madd x2, x0, x1, x2", // x2 = HOLD_SLOTS_BASE + core_pos * HOLD_SLOT_SIZE
+    "0:",
+        "ldr x0, [x2, #{MAGIC1_OFFSET}]", // load magic1
+        "ldr x1, ={HOLD_MAGIC1}",
+        "cmp x0, x1",
+        "b.ne 1f",
+
+        "ldr x0, [x2, #{MAGIC2_OFFSET}]", // load magic2
+        "ldr x1, ={HOLD_MAGIC2}",
+        "cmp x0, x1",
+        "b.ne 1f",
+
+        // Ensure that the loads above are totally completed before we load the entrypoint.
+        // This prevents the pipeline from speculatively pulling a stale 'entry' value.
+        "dmb sy",
+
+        "ldr x16, [x2, #{ENTRY_OFFSET}]", // load entry
+        "ldr x1, ={HOLD_STATE_WAIT}",
+        "cmp x16, x1",
+        "b.eq 1f",
+
+        // Prevent reuse of stale entry
+        "str x1, [x2, #{ENTRY_OFFSET}]", // reset to wait
+        // x16 is chosen to make this bti c compatible, not just bti j
+        "br x16",
+    "1:",
+        "wfe",
+        "b 0b",
+        HOLD_SLOT_SIZE = const core::mem::size_of::<HoldSlot>(),
+        ENTRY_OFFSET = const offset_of!(HoldSlot, entry),
+        MAGIC1_OFFSET = const offset_of!(HoldSlot, magic1),
+        MAGIC2_OFFSET = const offset_of!(HoldSlot, magic2),
+        HOLD_SLOTS_BASE = const TRUSTED_MAILBOX_BASE,
+        HOLD_MAGIC1 = const HOLD_MAGIC1,
+        HOLD_MAGIC2 = const HOLD_MAGIC2,
+        HOLD_STATE_WAIT = const HOLD_STATE_WAIT,
+        plat_my_core_pos = sym my_core_pos::<QemuSbsa>,
+    );
+}
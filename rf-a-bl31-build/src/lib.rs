@@ -20,6 +20,9 @@ fn setup_linker(builder: &dyn Builder) {
     if builder.bl31_dram_base().is_none() {
         assert_eq!(builder.bl31_dram_size(), 0);
     }
+    if builder.bl31_retained_base().is_none() {
+        assert_eq!(builder.bl31_retained_size(), 0);
+    }
 
     define_linker_symbol("BL31_BASE", builder.bl31_base());
     define_linker_symbol("BL31_SIZE", builder.bl31_size());
@@ -28,10 +31,30 @@ fn setup_linker(builder: &dyn Builder) {
         builder.bl31_dram_base().unwrap_or_default(),
     );
     define_linker_symbol("BL31_DRAM_SIZE", builder.bl31_dram_size());
+    define_linker_symbol(
+        "BL31_RETAINED_BASE",
+        builder.bl31_retained_base().unwrap_or_default(),
+    );
+    define_linker_symbol("BL31_RETAINED_SIZE", builder.bl31_retained_size());
     define_linker_symbol("PAGE_SIZE", PAGE_SIZE);
 
+    let out_dir = env::var_os("OUT_DIR").unwrap();
+    let out_dir = Path::new(&out_dir);
+
+    // Without a separate retained region, the contexts and stacks stay in the BL31 image.
+    let retained_region = if builder.bl31_retained_base().is_some() {
+        "bl31_retained"
+    } else {
+        "image"
+    };
+    File::create(out_dir.join("bl31_regions.ld"))
+        .unwrap()
+        .write_all(format!("REGION_ALIAS(\"el3_retained\", {retained_region});\n").as_bytes())
+        .unwrap();
+    println!("cargo:rustc-link-arg=-L{}", out_dir.display());
+
     // Write linker script to the out directory, so that the binary build can find it.
-    let linker_script_path = Path::new(&env::var_os("OUT_DIR").unwrap()).join("bl31.ld");
+    let linker_script_path = out_dir.join("bl31.ld");
     File::create(&linker_script_path)
         .unwrap()
         .write_all(include_bytes!("bl31.ld"))
@@ -82,6 +105,28 @@ pub trait Builder {
         0
     }
 
+    /// Base address of a region of memory which keeps its contents across the deepest power state
+    /// of every cluster, if the BL31 image itself isn't retained.
+    ///
+    /// The saved CPU contexts, per-CPU data and stacks are placed here rather than in the BL31
+    /// image, so that a core can resume from a cluster power down without reloading them from
+    /// DRAM. The platform must include this region in its early page table mapping. If this
+    /// returns `None` then they are placed at the end of the BL31 image.
+    ///
+    /// This is passed to the linker script through the `BL31_RETAINED_BASE` symbol.
+    fn bl31_retained_base(&self) -> Option<u64> {
+        None
+    }
+
+    /// Size of the retained memory region for BL31, if any.
+    ///
+    /// If there is no separate retained region then this should return 0.
+    ///
+    /// This is passed to the linker script through the `BL31_RETAINED_SIZE` symbol.
+    fn bl31_retained_size(&self) -> u64 {
+        0
+    }
+
     /// Sets up platform-specific configurations (code generation, file inclusions, etc.).
     fn configure_build(&self) -> BuildResult {
         Ok(())
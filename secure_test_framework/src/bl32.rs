@@ -34,6 +34,7 @@ use crate::{
     },
     platform::{BL32_IDMAP, Platform, PlatformImpl},
     secondary::secondary_entry,
+    tests::psci_osi::LAST_WARM_BOOT_TYPE,
     util::{
         NORMAL_WORLD_ID, SECURE_WORLD_ID, SPMC_DEFAULT_ID, SPMD_DEFAULT_ID, current_el,
         expect_ffa_success,
@@ -279,7 +280,8 @@ fn handle_psci_request64(_params: [u64; 4]) -> DirectMsgArgs {
     }
 }
 
-fn handle_warm_boot_request(_boot_type: WarmBootType) -> DirectMsgArgs {
+fn handle_warm_boot_request(boot_type: WarmBootType) -> DirectMsgArgs {
+    LAST_WARM_BOOT_TYPE[PlatformImpl::core_index()].store(boot_type as u64, Ordering::SeqCst);
     DirectMsgArgs::PowerPsciResp {
         psci_status: (ReturnCode::Success).into(),
     }
@@ -186,6 +186,9 @@ fn bl33_main(x0: u64, x1: u64, x2: u64, x3: u64) -> ! {
         secure_test_counts.failed,
     );
 
+    PlatformImpl::report_test_result(
+        normal_test_counts.failed == 0 && secure_test_counts.failed == 0,
+    );
     let ret = psci::system_off::<Smc>();
     panic!("PSCI_SYSTEM_OFF returned {:?}", ret);
 }
@@ -277,6 +280,7 @@ fn call_test_helper(test_index: usize, args: [u64; 3]) -> Result<[u64; 4], ()> {
 #[panic_handler]
 fn panic(info: &PanicInfo) -> ! {
     error!("{info}");
+    PlatformImpl::report_test_result(false);
     let _ = psci::system_off::<Smc>();
     loop {}
 }
@@ -5,8 +5,8 @@
 use arm_ffa::{
     Error, FfaError, Interface, Uuid, Version,
     interface_args::{
-        DirectMsgArgs, Feature, MemAddr, MemOpBuf, MsgSend2Flags, MsgWaitFlags, RxTxAddr,
-        SecondaryEpRegisterAddr, SuccessArgs, TargetInfo, VersionFlags, VersionQueryType,
+        DirectMsg2Args, DirectMsgArgs, Feature, MemAddr, MemOpBuf, MsgSend2Flags, MsgWaitFlags,
+        RxTxAddr, SecondaryEpRegisterAddr, SuccessArgs, TargetInfo, VersionFlags, VersionQueryType,
     },
     memory_management::{Handle, MemPermissionsGetSet, MemReclaimFlags},
     notification::{NotificationBindFlags, NotificationGetFlags, NotificationSetFlags},
@@ -109,6 +109,34 @@ pub fn direct_response(
     })
 }
 
+/// Sends an FF-A v1.2 direct message request, with arguments in x4-x17.
+pub fn direct_request2(
+    source: u16,
+    destination: u16,
+    uuid: Uuid,
+    args: DirectMsg2Args,
+) -> Result<Interface, Error> {
+    call(Interface::MsgSendDirectReq2 {
+        src_id: source,
+        dst_id: destination,
+        uuid,
+        args,
+    })
+}
+
+/// Sends an FF-A v1.2 direct message response, with arguments in x4-x17.
+pub fn direct_response2(
+    source: u16,
+    destination: u16,
+    args: DirectMsg2Args,
+) -> Result<Interface, Error> {
+    call(Interface::MsgSendDirectResp2 {
+        src_id: source,
+        dst_id: destination,
+        args,
+    })
+}
+
 pub fn mem_donate(
     total_len: u32,
     frag_len: u32,
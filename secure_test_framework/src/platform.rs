@@ -50,6 +50,10 @@ pub unsafe trait Platform {
     #[cfg(feature = "rme")]
     const PAS_CONFIG: PasConfig;
 
+    /// The address of two pages of normal world memory within the region which RF-A maps into EL3
+    /// for the normal world's RX/TX buffers, if there is one.
+    const NS_RXTX_BUFFERS: Option<usize> = None;
+
     /// Returns something to which logs should be sent.
     ///
     /// This should only be called once, and may panic on subsequent calls.
@@ -141,6 +145,18 @@ pub unsafe trait Platform {
     fn osi_should_wake_core(_core_index: usize) -> bool {
         true
     }
+
+    /// Returns whether RF-A tells the SPMC to resume from its saved state when a core wakes from a
+    /// power down suspend state on this platform, rather than to re-initialise it.
+    fn spmc_resumes_after_suspend() -> bool {
+        false
+    }
+
+    /// Reports to the host whether all tests passed, before the system is powered off.
+    ///
+    /// The default implementation does nothing.
+    #[allow(unused)]
+    fn report_test_result(_passed: bool) {}
 }
 
 // SAFETY: `Platform::core_position` is guaranteed to return a unique value for any valid MPIDR
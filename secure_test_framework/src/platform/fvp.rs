@@ -62,6 +62,9 @@ unsafe impl Platform for Fvp {
         root_start: 0xFFC0_0000,
     };
 
+    // The start of RF-A's `NS_BUFFER_RANGE` for FVP.
+    const NS_RXTX_BUFFERS: Option<usize> = Some(0x8f00_0000);
+
     fn make_log_sink() -> &'static mut (dyn Write + Send) {
         let uart = UART.call_once(|| {
             // SAFETY: `PL011_BASE_ADDRESS` is the base address of a PL011 device, and nothing else
@@ -192,6 +195,10 @@ unsafe impl Platform for Fvp {
         // in another cluster.
         core_index == 0 || core_index == 1 || core_index == FVP_MAX_CPUS_PER_CLUSTER
     }
+
+    fn spmc_resumes_after_suspend() -> bool {
+        true
+    }
 }
 
 // BL32:
@@ -17,6 +17,7 @@ use arm_pl011_uart::{PL011Registers, Uart, UniqueMmioPointer};
 use arm_psci::PowerState;
 use arm_sysregs::MpidrEl1;
 use core::{arch::global_asm, fmt::Write, ptr::NonNull};
+use smccc::smc32;
 use spin::{
     Once,
     mutex::{SpinMutex, SpinMutexGuard},
@@ -27,6 +28,10 @@ const PL011_BASE_ADDRESS: NonNull<PL011Registers> = NonNull::new(0x0900_0000 as
 const GICD_BASE: NonNull<Gicd> = NonNull::new(0x0800_0000 as _).unwrap();
 const GICR_BASE: NonNull<GicrSgi> = NonNull::new(0x080A_0000 as _).unwrap();
 
+/// RF-A's vendor-specific EL3 monitor SMC for reporting the test result to CI on QEMU.
+#[allow(unused)]
+const QEMU_TEST_EXIT: u32 = 0x8700_0040;
+
 /// The number of CPU clusters.
 const CLUSTER_COUNT: usize = 1;
 const PLATFORM_CPU_PER_CLUSTER_SHIFT: usize = 2;
@@ -139,6 +144,12 @@ unsafe impl Platform for Qemu {
     fn osi_state_id_core_standby() -> u32 {
         Self::STATE_ID_CORE_STANDBY
     }
+
+    fn report_test_result(passed: bool) {
+        // If RF-A was built with `QEMU_TEST_EXIT=1` this makes QEMU exit with a code for the
+        // result, otherwise it returns `NOT_SUPPORTED` and we carry on to power off as usual.
+        smc32(QEMU_TEST_EXIT, [(!passed).into(), 0, 0, 0, 0, 0, 0]);
+    }
 }
 
 pub static BL33_IDMAP: InitialPagetable = {
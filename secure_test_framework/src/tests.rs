@@ -8,7 +8,7 @@ mod dit;
 mod ffa_spmd;
 mod interrupts;
 mod psci;
-mod psci_osi;
+pub mod psci_osi;
 #[cfg(feature = "rme")]
 mod rmi;
 #[cfg(any(not(feature = "rme"), feature = "test_rmm_fail"))]
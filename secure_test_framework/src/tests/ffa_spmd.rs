@@ -13,16 +13,17 @@ use crate::{
         expect::{expect_eq, fail},
         normal_world_test, secure_world_test,
     },
+    platform::{Platform, PlatformImpl},
     util::{
-        NORMAL_WORLD_ID, SPMC_DEFAULT_ID, expect_ffa_interface, expect_ffa_mem_retrieve_resp,
-        expect_ffa_success, log_error,
+        NORMAL_WORLD_ID, SECURE_WORLD_ID, SPMC_DEFAULT_ID, SPMD_DEFAULT_ID, expect_ffa_interface,
+        expect_ffa_mem_retrieve_resp, expect_ffa_success, log_error,
     },
 };
 use arm_ffa::{
-    FfaError, FuncId, Interface, Uuid,
+    FfaError, FuncId, Interface, Uuid, Version,
     interface_args::{
-        Feature, MemAddr, MsgSend2Flags, MsgWaitFlags, RxTxAddr, SuccessArgs, SuccessArgsFeatures,
-        SuccessArgsIdGet, SuccessArgsSpmIdGet, TargetInfo,
+        DirectMsg2Args, Feature, FeatureId, MemAddr, MsgSend2Flags, MsgWaitFlags, RxTxAddr,
+        SuccessArgs, SuccessArgsFeatures, SuccessArgsIdGet, SuccessArgsSpmIdGet, TargetInfo,
     },
     memory_management::{
         DataAccessPermGetSet, Handle, InstructionAccessPermGetSet, MemPermissionsGetSet,
@@ -34,9 +35,14 @@ use arm_ffa::{
         SuccessArgsNotificationInfoGet32,
     },
     partition_info::{
-        PartitionInfoGetFlags, SuccessArgsPartitionInfoGet, SuccessArgsPartitionInfoGetRegs,
+        PartitionIdType, PartitionInfo, PartitionInfoGetFlags, PartitionInfoIterator,
+        PartitionProperties, SuccessArgsPartitionInfoGet, SuccessArgsPartitionInfoGetRegs,
     },
 };
+use core::slice;
+
+/// The size of each of the RX/TX buffers which the tests map.
+const FFA_PAGE_SIZE: usize = 4096;
 
 normal_world_test!(test_ffa_no_msg_wait);
 /// Check that FFA_MSG_WAIT returns NOT_SUPPORTED as normal world isn't allowed to call FFA_MSG_WAIT.
@@ -93,7 +99,6 @@ fn test_ffa_spm_id_get() -> TestResult {
         other => fail!("SPM_ID_GET returned unexpected interface: {other:?}"),
     };
 
-    // TODO: parse manifest and test for that value.
     expect_eq!(id, SPMC_DEFAULT_ID);
     Ok(())
 }
@@ -200,6 +205,55 @@ fn ffa_features_handler(interface: Interface) -> Option<Interface> {
     })
 }
 
+/// The managed exit interrupt ID reported by the fake SPMC.
+const MANAGED_EXIT_INTID: u32 = 4;
+
+normal_world_test!(
+    test_ffa_features_managed_exit,
+    handler = ffa_features_managed_exit_handler
+);
+/// Check that FFA_FEATURES queries for the managed exit interrupt are answered by the SPMC, and its
+/// properties returned to the normal world unchanged.
+fn test_ffa_features_managed_exit() -> TestResult {
+    let args = expect_ffa_interface!(
+        expect_ffa_success,
+        "FEATURES failed",
+        ffa::features(Feature::FeatureId(FeatureId::ManagedExitInterrupt), 0)
+    );
+    let properties = log_error(
+        "Retrieving SuccessArgsFeatures failed",
+        SuccessArgsFeatures::try_from(args),
+    )?
+    .properties;
+
+    expect_eq!(properties, [MANAGED_EXIT_INTID, 0]);
+    Ok(())
+}
+
+fn ffa_features_managed_exit_handler(interface: Interface) -> Option<Interface> {
+    let Interface::Features {
+        feat_id,
+        input_properties,
+    } = interface
+    else {
+        return None;
+    };
+
+    assert_eq!(feat_id, Feature::FeatureId(FeatureId::ManagedExitInterrupt));
+    assert_eq!(input_properties, 0);
+
+    Some(Interface::Success {
+        args: SuccessArgsFeatures {
+            properties: [MANAGED_EXIT_INTID, 0],
+        }
+        .into(),
+        target_info: TargetInfo {
+            endpoint_id: 0,
+            vcpu_id: 0,
+        },
+    })
+}
+
 normal_world_test!(test_ffa_rx_acquire, handler = rx_acquire_handler);
 /// Check that the FFA_RX_ACQUIRE interface (and its parameters) is successfully forwarded from normal world
 /// to secure world and back.
@@ -285,6 +339,10 @@ fn test_ffa_partition_info_get() -> TestResult {
 
 /// Check that the interface values forwarded from normal world match the expected ones.
 fn partition_info_get_handler(interface: Interface) -> Option<Interface> {
+    // Refuse to let the SPMD cache the partition information, so that it forwards the original call.
+    if let Interface::PartitionInfoGetRegs { .. } = interface {
+        return Some(Interface::error(FfaError::NotSupported, true));
+    }
     let Interface::PartitionInfoGet { uuid, flags } = interface else {
         return None;
     };
@@ -308,6 +366,175 @@ fn partition_info_get_handler(interface: Interface) -> Option<Interface> {
     })
 }
 
+normal_world_test!(
+    test_ffa_partition_info_get_cached,
+    handler = partition_info_get_cached_handler
+);
+/// Check that the SPMD fetches the partition information from secure world with FFA_PARTITION_INFO_GET_REGS, and then
+/// answers FFA_PARTITION_INFO_GET itself. The SPMD keeps the partition information for the rest of the boot, so this
+/// must run after `test_ffa_partition_info_get`, as it does when the tests are sorted by name.
+fn test_ffa_partition_info_get_cached() -> TestResult {
+    let partitions = cached_partitions();
+
+    let args = expect_ffa_interface!(
+        expect_ffa_success,
+        "PARTITION_INFO_GET failed",
+        ffa::partition_info_get(Uuid::nil(), PartitionInfoGetFlags { count_only: true })
+    );
+    expect_eq!(
+        args,
+        SuccessArgsPartitionInfoGet {
+            count: partitions.len() as u32,
+            size: None,
+        }
+        .into()
+    );
+
+    // The handler doesn't accept FFA_PARTITION_INFO_GET, so this must be answered by the SPMD.
+    let args = expect_ffa_interface!(
+        expect_ffa_success,
+        "PARTITION_INFO_GET failed",
+        ffa::partition_info_get(
+            partitions[1].uuid,
+            PartitionInfoGetFlags { count_only: true }
+        )
+    );
+    expect_eq!(
+        args,
+        SuccessArgsPartitionInfoGet {
+            count: 1,
+            size: None,
+        }
+        .into()
+    );
+
+    // The SPMD can only write the descriptors to RX/TX buffers which RF-A maps into EL3.
+    let Some(buffers) = PlatformImpl::NS_RXTX_BUFFERS else {
+        return Ok(());
+    };
+    expect_ffa_interface!(
+        expect_ffa_success,
+        "RXTX_MAP failed",
+        ffa::rxtx_map(
+            RxTxAddr::Addr64 {
+                rx: buffers as u64,
+                tx: (buffers + FFA_PAGE_SIZE) as u64,
+            },
+            1
+        )
+    );
+    let args = expect_ffa_interface!(
+        expect_ffa_success,
+        "PARTITION_INFO_GET failed",
+        ffa::partition_info_get(Uuid::nil(), PartitionInfoGetFlags { count_only: false })
+    );
+    expect_eq!(
+        args,
+        SuccessArgsPartitionInfoGet {
+            count: partitions.len() as u32,
+            size: Some(PartitionInfo::DESC_SIZE as u32),
+        }
+        .into()
+    );
+    // SAFETY: The RX buffer is mapped as normal memory, and nothing else uses it while we own it.
+    let rx = unsafe {
+        slice::from_raw_parts(
+            buffers as *const u8,
+            partitions.len() * PartitionInfo::DESC_SIZE,
+        )
+    };
+    let written = PartitionInfoIterator::new(Version(1, 2), rx, partitions.len()).unwrap();
+    for (written, expected) in written.zip(partitions) {
+        expect_eq!(written.ok(), Some(expected));
+    }
+    expect_ffa_interface!(
+        expect_ffa_success,
+        "RX_RELEASE failed",
+        ffa::rx_release(NORMAL_WORLD_ID)
+    );
+    expect_ffa_interface!(
+        expect_ffa_success,
+        "RXTX_UNMAP failed",
+        ffa::rxtx_unmap(NORMAL_WORLD_ID)
+    );
+    Ok(())
+}
+
+/// Returns the partitions which secure world reports in `test_ffa_partition_info_get_cached`.
+fn cached_partitions() -> [PartitionInfo; 2] {
+    let partition = |partition_id, uuid| PartitionInfo {
+        uuid: Uuid::parse_str(uuid).unwrap(),
+        partition_id,
+        partition_id_type: PartitionIdType::PeEndpoint {
+            execution_ctx_count: 1,
+        },
+        props: PartitionProperties {
+            support_direct_req_rec: true,
+            support_direct_req2_rec: Some(false),
+            support_direct_req2_send: Some(false),
+            is_aarch64: true,
+            ..Default::default()
+        },
+    };
+    [
+        partition(0x8001, "b1b2b3b4c1c2d1d2e1e2e3e4e5e6e7e8"),
+        partition(0x8002, "c1c2c3c4d1d2e1e2f1f2f3f4f5f6f7f8"),
+    ]
+}
+
+/// Returns the cached partitions one at a time for FFA_PARTITION_INFO_GET_REGS, and accepts the RX/TX buffer calls
+/// which go with the SPMD writing them to the RX buffer.
+fn partition_info_get_cached_handler(interface: Interface) -> Option<Interface> {
+    const INFO_TAG: u16 = 0x42;
+
+    let success = Interface::Success {
+        args: SuccessArgs::Args32([0, 0, 0, 0, 0, 0]),
+        target_info: TargetInfo {
+            endpoint_id: 0,
+            vcpu_id: 0,
+        },
+    };
+    match interface {
+        Interface::PartitionInfoGetRegs {
+            uuid,
+            start_index,
+            info_tag,
+        } => {
+            let partitions = cached_partitions();
+            assert!(uuid.is_nil());
+            assert_eq!(info_tag, if start_index == 0 { 0 } else { INFO_TAG });
+
+            let mut descriptor_data = [0; 15 * 8];
+            let index = usize::from(start_index);
+            PartitionInfo::pack(
+                Version(1, 2),
+                &partitions[index..=index],
+                &mut descriptor_data[..PartitionInfo::DESC_SIZE],
+                true,
+            );
+            Some(Interface::Success {
+                args: SuccessArgsPartitionInfoGetRegs {
+                    last_index: partitions.len() as u16 - 1,
+                    current_index: start_index,
+                    info_tag: INFO_TAG,
+                    descriptor_data,
+                }
+                .into(),
+                target_info: TargetInfo {
+                    endpoint_id: 0,
+                    vcpu_id: 0,
+                },
+            })
+        }
+        Interface::RxTxMap { .. } | Interface::RxAcquire { .. } => Some(success),
+        Interface::RxRelease { vm_id } | Interface::RxTxUnmap { id: vm_id } => {
+            assert_eq!(vm_id, NORMAL_WORLD_ID);
+            Some(success)
+        }
+        _ => None,
+    }
+}
+
 normal_world_test!(
     test_ffa_partition_info_get_regs,
     handler = partition_info_get_regs_handler
@@ -705,6 +932,95 @@ fn test_ffa_msg_send2() -> TestResult {
     Ok(())
 }
 
+/// UUID of the secure partition service targeted by FFA_MSG_SEND_DIRECT_REQ2 tests.
+const DIRECT_REQ2_UUID: Uuid = Uuid::from_u128(0x7a1c_2b3d_4e5f_6071_8293_a4b5_c6d7_e8f9);
+
+/// Arguments sent in x4-x17 of FFA_MSG_SEND_DIRECT_REQ2.
+const DIRECT_REQ2_ARGS: [u64; 14] = [
+    0x0404_0404_0404_0404,
+    0x0505_0505_0505_0505,
+    0x0606_0606_0606_0606,
+    0x0707_0707_0707_0707,
+    0x0808_0808_0808_0808,
+    0x0909_0909_0909_0909,
+    0x1010_1010_1010_1010,
+    0x1111_1111_1111_1111,
+    0x1212_1212_1212_1212,
+    0x1313_1313_1313_1313,
+    0x1414_1414_1414_1414,
+    0x1515_1515_1515_1515,
+    0x1616_1616_1616_1616,
+    0x1717_1717_1717_1717,
+];
+
+/// Check that all of x4-x17 of a direct request forwarded from normal world are preserved, and
+/// respond with the same registers inverted so that the return path is checked too.
+fn direct_req2_handler(interface: Interface) -> Option<Interface> {
+    let Interface::MsgSendDirectReq2 {
+        src_id,
+        dst_id,
+        uuid,
+        args,
+    } = interface
+    else {
+        return None;
+    };
+
+    assert_eq!(src_id, NORMAL_WORLD_ID);
+    assert_eq!(dst_id, SECURE_WORLD_ID);
+    assert_eq!(uuid, DIRECT_REQ2_UUID);
+    assert_eq!(args.0, DIRECT_REQ2_ARGS);
+
+    Some(Interface::MsgSendDirectResp2 {
+        src_id: dst_id,
+        dst_id: src_id,
+        args: DirectMsg2Args(DIRECT_REQ2_ARGS.map(|arg| !arg)),
+    })
+}
+
+normal_world_test!(test_ffa_direct_req2, handler = direct_req2_handler);
+/// Check that FFA_MSG_SEND_DIRECT_REQ2 and the corresponding FFA_MSG_SEND_DIRECT_RESP2 are
+/// forwarded between worlds with all of x4-x17 preserved.
+fn test_ffa_direct_req2() -> TestResult {
+    let response = log_error(
+        "MSG_SEND_DIRECT_REQ2 failed",
+        ffa::direct_request2(
+            NORMAL_WORLD_ID,
+            SECURE_WORLD_ID,
+            DIRECT_REQ2_UUID,
+            DirectMsg2Args(DIRECT_REQ2_ARGS),
+        ),
+    )?;
+
+    expect_eq!(
+        response,
+        Interface::MsgSendDirectResp2 {
+            src_id: SECURE_WORLD_ID,
+            dst_id: NORMAL_WORLD_ID,
+            args: DirectMsg2Args(DIRECT_REQ2_ARGS.map(|arg| !arg)),
+        }
+    );
+    Ok(())
+}
+
+normal_world_test!(test_ffa_direct_req2_to_spmd);
+/// Check that the SPMD rejects FFA_MSG_SEND_DIRECT_REQ2 addressed to itself, as it doesn't
+/// implement any services over it.
+fn test_ffa_direct_req2_to_spmd() -> TestResult {
+    let error = log_error(
+        "MSG_SEND_DIRECT_REQ2 failed",
+        ffa::direct_request2(
+            NORMAL_WORLD_ID,
+            SPMD_DEFAULT_ID,
+            DIRECT_REQ2_UUID,
+            DirectMsg2Args(DIRECT_REQ2_ARGS),
+        ),
+    )?;
+
+    expect_eq!(error, Interface::error(FfaError::InvalidParameters, true));
+    Ok(())
+}
+
 // Check that the interface values forwarded from normal world match the expected ones.
 // Return a FFA_MEM_RETRIEVE_RESP with the same values that were received to emulate what would be returned by secure
 // world
@@ -772,35 +1088,99 @@ fn mem_reclaim_handler(interface: Interface) -> Option<Interface> {
     })
 }
 
-// Check that the interface values forwarded from normal world match the expected ones.
-fn notification_bitmap_destroy_handler(interface: Interface) -> Option<Interface> {
-    let Interface::NotificationBitmapDestroy { vm_id } = interface else {
-        return None;
-    };
-
-    assert_eq!(vm_id, 5035);
-
-    Some(Interface::Success {
-        target_info: TargetInfo {
-            endpoint_id: 0,
-            vcpu_id: 0,
-        },
-        args: SuccessArgs::Args32([0, 0, 0, 0, 0, 0]),
-    })
+// Accept any notification bitmap creation or destruction forwarded from normal world.
+fn notification_bitmap_handler(interface: Interface) -> Option<Interface> {
+    match interface {
+        Interface::NotificationBitmapCreate { .. }
+        | Interface::NotificationBitmapDestroy { .. } => Some(Interface::Success {
+            target_info: TargetInfo {
+                endpoint_id: 0,
+                vcpu_id: 0,
+            },
+            args: SuccessArgs::Args32([0, 0, 0, 0, 0, 0]),
+        }),
+        _ => None,
+    }
 }
 
 normal_world_test!(
-    test_ffa_notification_bitmap_destroy,
-    handler = notification_bitmap_destroy_handler
+    test_ffa_notification_bitmap_create_destroy,
+    handler = notification_bitmap_handler
 );
-fn test_ffa_notification_bitmap_destroy() -> TestResult {
+/// Check that the SPMD keeps track of the notification bitmaps created by normal world, and denies
+/// creating a bitmap twice or destroying a bitmap which doesn't exist.
+fn test_ffa_notification_bitmap_create_destroy() -> TestResult {
+    let args = expect_ffa_interface!(
+        expect_ffa_success,
+        "NOTIFICATION_BITMAP_CREATE failed",
+        ffa::notification_bitmap_create(5035, 4)
+    );
+    expect_eq!(args, SuccessArgs::Args32([0, 0, 0, 0, 0, 0]));
+
+    let error = log_error(
+        "NOTIFICATION_BITMAP_CREATE failed",
+        ffa::notification_bitmap_create(5035, 4),
+    )?;
+    expect_eq!(
+        error,
+        Interface::Error {
+            error_arg: 0,
+            target_info: TargetInfo {
+                endpoint_id: 0,
+                vcpu_id: 0
+            },
+            error_code: FfaError::Denied,
+            is_32bit: true,
+        }
+    );
+
     let args = expect_ffa_interface!(
         expect_ffa_success,
         "NOTIFICATION_BITMAP_DESTROY failed",
         ffa::notification_bitmap_destroy(5035)
     );
-
     expect_eq!(args, SuccessArgs::Args32([0, 0, 0, 0, 0, 0]));
+
+    let error = log_error(
+        "NOTIFICATION_BITMAP_DESTROY failed",
+        ffa::notification_bitmap_destroy(5035),
+    )?;
+    expect_eq!(
+        error,
+        Interface::Error {
+            error_arg: 0,
+            target_info: TargetInfo {
+                endpoint_id: 0,
+                vcpu_id: 0
+            },
+            error_code: FfaError::Denied,
+            is_32bit: true,
+        }
+    );
+
+    Ok(())
+}
+
+normal_world_test!(test_ffa_notification_bitmap_create_secure_id);
+/// Check that normal world can't create a notification bitmap for a secure endpoint ID.
+fn test_ffa_notification_bitmap_create_secure_id() -> TestResult {
+    let error = log_error(
+        "NOTIFICATION_BITMAP_CREATE failed",
+        ffa::notification_bitmap_create(0x8003, 4),
+    )?;
+
+    expect_eq!(
+        error,
+        Interface::Error {
+            error_arg: 0,
+            target_info: TargetInfo {
+                endpoint_id: 0,
+                vcpu_id: 0
+            },
+            error_code: FfaError::InvalidParameters,
+            is_32bit: true,
+        }
+    );
     Ok(())
 }
 
@@ -908,7 +1288,7 @@ fn notification_set_handler(interface: Interface) -> Option<Interface> {
         return None;
     };
 
-    assert_eq!(sender_id, 0x8017);
+    assert_eq!(sender_id, 0x17);
     assert_eq!(receiver_id, 0x8044);
     assert_eq!(
         flags,
@@ -933,11 +1313,12 @@ normal_world_test!(
     handler = notification_set_handler
 );
 fn test_ffa_notification_set() -> TestResult {
+    // The sender doesn't need a notification bitmap, as the receiver is a partition.
     let args = expect_ffa_interface!(
         expect_ffa_success,
         "NOTIFICATION_SET failed",
         ffa::notification_set(
-            0x8017,
+            0x17,
             0x8044,
             NotificationSetFlags {
                 delay_schedule_receiver: true,
@@ -951,6 +1332,37 @@ fn test_ffa_notification_set() -> TestResult {
     Ok(())
 }
 
+normal_world_test!(test_ffa_notification_set_no_bitmap);
+/// Check that the SPMD rejects NOTIFICATION_SET to a VM which doesn't have a notification bitmap.
+fn test_ffa_notification_set_no_bitmap() -> TestResult {
+    let error = log_error(
+        "NOTIFICATION_SET failed",
+        ffa::notification_set(
+            0x17,
+            0x18,
+            NotificationSetFlags {
+                delay_schedule_receiver: false,
+                vcpu_id: None,
+            },
+            0x1,
+        ),
+    )?;
+
+    expect_eq!(
+        error,
+        Interface::Error {
+            error_arg: 0,
+            target_info: TargetInfo {
+                endpoint_id: 0,
+                vcpu_id: 0
+            },
+            error_code: FfaError::InvalidParameters,
+            is_32bit: true,
+        }
+    );
+    Ok(())
+}
+
 // Check that the interface values forwarded from normal world match the expected ones.
 // Return a SuccessArgsNotificationGet with some sensible values.
 fn notification_get_handler(interface: Interface) -> Option<Interface> {
@@ -960,7 +1372,7 @@ fn notification_get_handler(interface: Interface) -> Option<Interface> {
         vcpu_id,
     } = interface
     else {
-        return None;
+        return notification_bitmap_handler(interface);
     };
 
     assert_eq!(vcpu_id, 17);
@@ -1002,6 +1414,12 @@ fn test_ffa_notification_get() -> TestResult {
         hyp_bitmap_id: true,
     };
 
+    expect_ffa_interface!(
+        expect_ffa_success,
+        "NOTIFICATION_BITMAP_CREATE failed",
+        ffa::notification_bitmap_create(44, 32)
+    );
+
     let args = expect_ffa_interface!(
         expect_ffa_success,
         "NOTIFICATION_GET failed",
@@ -1018,10 +1436,47 @@ fn test_ffa_notification_get() -> TestResult {
             expect_eq!(args.vm_notifications, None);
             expect_eq!(args.spm_notifications, Some(0));
             expect_eq!(args.hypervisor_notifications, Some(0));
-
-            Ok(())
         }
     }
+
+    expect_ffa_interface!(
+        expect_ffa_success,
+        "NOTIFICATION_BITMAP_DESTROY failed",
+        ffa::notification_bitmap_destroy(44)
+    );
+    Ok(())
+}
+
+normal_world_test!(test_ffa_notification_get_no_bitmap);
+/// Check that the SPMD rejects NOTIFICATION_GET for a VM which doesn't have a notification bitmap.
+fn test_ffa_notification_get_no_bitmap() -> TestResult {
+    let error = log_error(
+        "NOTIFICATION_GET failed",
+        ffa::notification_get(
+            0,
+            44,
+            NotificationGetFlags {
+                sp_bitmap_id: true,
+                vm_bitmap_id: false,
+                spm_bitmap_id: false,
+                hyp_bitmap_id: false,
+            },
+        ),
+    )?;
+
+    expect_eq!(
+        error,
+        Interface::Error {
+            error_arg: 0,
+            target_info: TargetInfo {
+                endpoint_id: 0,
+                vcpu_id: 0
+            },
+            error_code: FfaError::InvalidParameters,
+            is_32bit: true,
+        }
+    );
+    Ok(())
 }
 
 // Check that the interface values forwarded from normal world match the expected ones.
@@ -1201,6 +1656,23 @@ fn test_ffa_no_normal_world_resume() -> TestResult {
     Ok(())
 }
 
+secure_world_test!(test_ffa_no_direct_resp2_secure_destination);
+/// Check that the SPMD doesn't forward FFA_MSG_SEND_DIRECT_RESP2 from secure world to another
+/// secure endpoint.
+fn test_ffa_no_direct_resp2_secure_destination() -> TestResult {
+    let error = log_error(
+        "MSG_SEND_DIRECT_RESP2 failed",
+        ffa::direct_response2(
+            SECURE_WORLD_ID,
+            SPMC_DEFAULT_ID,
+            DirectMsg2Args(DIRECT_REQ2_ARGS),
+        ),
+    )?;
+
+    expect_eq!(error, Interface::error(FfaError::InvalidParameters, true));
+    Ok(())
+}
+
 secure_world_test!(test_ffa_normal_world_resume);
 /// Try to resume normal world execution. Since normal world was not preempted in the first place, this should fail.
 fn test_ffa_normal_world_resume() -> TestResult {
@@ -1221,6 +1693,44 @@ fn test_ffa_normal_world_resume() -> TestResult {
     Ok(())
 }
 
+secure_world_test!(test_ffa_id_get_secure);
+/// Check that FFA_ID_GET called from secure world returns the ID of the SPMC, rather than the normal
+/// world endpoint ID.
+fn test_ffa_id_get_secure() -> TestResult {
+    let id = match log_error("ID_GET failed", ffa::id_get())? {
+        Interface::Success { args, .. } => {
+            log_error(
+                "ID_GET returned invalid arguments",
+                SuccessArgsIdGet::try_from(args),
+            )?
+            .id
+        }
+        other => fail!("ID_GET returned unexpected interface: {other:?}"),
+    };
+
+    expect_eq!(id, SPMC_DEFAULT_ID);
+    Ok(())
+}
+
+secure_world_test!(test_ffa_spm_id_get_secure);
+/// Check that FFA_SPM_ID_GET called from secure world returns the ID of the SPMD, rather than the
+/// ID of the SPMC.
+fn test_ffa_spm_id_get_secure() -> TestResult {
+    let id = match log_error("SPM_ID_GET failed", ffa::spm_id_get())? {
+        Interface::Success { args, .. } => {
+            log_error(
+                "SPM_ID_GET returned invalid arguments",
+                SuccessArgsSpmIdGet::try_from(args),
+            )?
+            .id
+        }
+        other => fail!("SPM_ID_GET returned unexpected interface: {other:?}"),
+    };
+
+    expect_eq!(id, SPMD_DEFAULT_ID);
+    Ok(())
+}
+
 secure_world_test!(test_ffa_features_secure);
 /// Test FFA_FEATURE interface from secure world.
 /// Currently, this test checks that the SPMD returns success.
@@ -1236,6 +1746,30 @@ fn test_ffa_features_secure() -> TestResult {
     Ok(())
 }
 
+secure_world_test!(test_ffa_features_secure_feature_id);
+/// Feature IDs describe partitions, which are owned by the SPMC, so the SPMD doesn't support querying
+/// them from the secure world.
+fn test_ffa_features_secure_feature_id() -> TestResult {
+    let error = log_error(
+        "FEATURES failed",
+        ffa::features(Feature::FeatureId(FeatureId::ManagedExitInterrupt), 0),
+    )?;
+
+    expect_eq!(
+        error,
+        Interface::Error {
+            error_arg: 0,
+            target_info: TargetInfo {
+                endpoint_id: 0,
+                vcpu_id: 0
+            },
+            error_code: FfaError::NotSupported,
+            is_32bit: true,
+        }
+    );
+    Ok(())
+}
+
 normal_world_test!(test_ffa_no_yield);
 fn test_ffa_no_yield() -> TestResult {
     // Normal world isn't allowed to call FFA_YIELD.
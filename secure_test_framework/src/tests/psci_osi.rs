@@ -8,7 +8,7 @@
 use crate::util::{enable_pauth, get_pauth_key};
 use crate::{
     framework::{
-        TestError, TestResult,
+        TestError, TestHelperProxy, TestResult,
         expect::{expect_eq, fail},
         normal_world_test,
     },
@@ -20,13 +20,14 @@ use crate::{
     util::timer::{NonSecureTimer, Timer},
 };
 use aarch64_rt::{enable_mmu, set_exception_vector};
+use arm_ffa::interface_args::WarmBootType;
 use arm_gic::{Trigger, irq_disable, irq_enable};
 use arm_psci::{ErrorCode, FunctionId, ReturnCode};
 use arm_sysregs::read_mpidr_el1;
 use core::{
     arch::naked_asm,
     hint::spin_loop,
-    sync::atomic::{AtomicBool, AtomicU32, Ordering},
+    sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering},
 };
 use log::trace;
 use smccc::{
@@ -193,6 +194,11 @@ impl OsiCoreState {
 static OSI_CORES: [OsiCoreState; PlatformImpl::CORE_COUNT] =
     [const { OsiCoreState::new() }; PlatformImpl::CORE_COUNT];
 
+/// The boot type of the last warm boot request which the secure world received from the SPMD on
+/// each core, or `u64::MAX` if it hasn't received one.
+pub static LAST_WARM_BOOT_TYPE: [AtomicU64; PlatformImpl::CORE_COUNT] =
+    [const { AtomicU64::new(u64::MAX) }; PlatformImpl::CORE_COUNT];
+
 /// Interrupt handler for the non-secure timer. Signals completion of the suspend duration.
 fn timer_handler() {
     NonSecureTimer::stop();
@@ -662,3 +668,35 @@ fn test_psci_suspend_osi_invalid_level() -> TestResult {
         Ok(())
     })
 }
+
+/// Returns the boot type of the last warm boot request which the secure world received on the
+/// primary core.
+fn last_warm_boot_type_helper(_args: [u64; 3]) -> Result<[u64; 4], ()> {
+    Ok([LAST_WARM_BOOT_TYPE[0].load(Ordering::SeqCst), 0, 0, 0])
+}
+
+normal_world_test!(
+    test_psci_suspend_powerdown_warm_boot_type,
+    helper = last_warm_boot_type_helper
+);
+/// Checks that when the primary core wakes from a power down state, the SPMC is told to resume
+/// from its saved state if the platform keeps it, or otherwise to re-initialise its state.
+fn test_psci_suspend_powerdown_warm_boot_type(helper: &TestHelperProxy) -> TestResult {
+    with_osi_support(|| {
+        run_osi_suspend_test(
+            0,
+            PlatformImpl::osi_state_id_core_power_down(),
+            None,
+            ReturnCode::Success,
+        )?;
+
+        let [boot_type, ..] = helper([0; 3])?;
+        let expected = if PlatformImpl::spmc_resumes_after_suspend() {
+            WarmBootType::ExitFromSuspendToRam
+        } else {
+            WarmBootType::ExitFromLowPower
+        };
+        expect_eq!(boot_type, expected as u64);
+        Ok(())
+    })
+}
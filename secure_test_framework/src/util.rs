@@ -19,7 +19,6 @@ pub const SECURE_WORLD_ID: u16 = 0x8001;
 pub const SPMC_DEFAULT_ID: u16 = 0x8000;
 
 /// Default ID for the SPMD
-#[allow(unused)]
 pub const SPMD_DEFAULT_ID: u16 = 0xffff;
 
 /// Returns the current exception level at which we are running.
@@ -0,0 +1,397 @@
+// Copyright The Rusted Firmware-A Contributors.
+//
+// SPDX-License-Identifier: BSD-3-Clause
+
+//! Parsing of the `bl_params` list which TF-A's BL2 passes to BL31 in `x0`, describing the images
+//! which it loaded and their entry points.
+//!
+//! The list is a chain of pointers written by an earlier boot stage, so rather than trusting them,
+//! every structure is checked to be within the memory BL2 writes it to, and to have the type,
+//! version and size expected, before it is read.
+
+use crate::context::EntryPointInfo;
+use core::{ffi::c_void, ops::RangeBounds};
+
+/// Image ID of BL32, i.e. the SPMC or other secure payload.
+pub const BL32_IMAGE_ID: u32 = 4;
+/// Image ID of BL33, i.e. the normal world bootloader.
+pub const BL33_IMAGE_ID: u32 = 5;
+
+/// Parameter type of `bl_params_t`.
+const PARAM_BL_PARAMS: u8 = 0x05;
+/// Parameter type of `entry_point_info_t`.
+const PARAM_EP: u8 = 0x01;
+/// Version of the parameter structures used since TF-A's `LOAD_IMAGE_V2`.
+const VERSION_2: u8 = 0x02;
+
+/// The maximum number of images in the list, to avoid looping forever on a corrupted list.
+const MAX_IMAGES: usize = 32;
+
+/// `param_header_t` from TF-A.
+#[derive(Clone, Copy, Debug)]
+#[repr(C)]
+struct ParamHeader {
+    param_type: u8,
+    version: u8,
+    size: u16,
+    attr: u32,
+}
+
+/// `bl_params_t` from TF-A.
+#[repr(C)]
+struct BlParams {
+    h: ParamHeader,
+    head: *const BlParamsNode,
+}
+
+/// `bl_params_node_t` from TF-A.
+#[repr(C)]
+struct BlParamsNode {
+    image_id: u32,
+    image_info: *const c_void,
+    ep_info: *const TfaEntryPointInfo,
+    next_params_info: *const BlParamsNode,
+}
+
+/// `entry_point_info_t` from TF-A, for AArch64.
+#[repr(C)]
+struct TfaEntryPointInfo {
+    h: ParamHeader,
+    pc: usize,
+    spsr: u32,
+    args: [u64; 8],
+}
+
+/// An error parsing the `bl_params` list.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum BlParamsError {
+    /// The `bl_params` pointer was null.
+    Missing,
+    /// A structure in the list had an unexpected type, or a size too small for its type.
+    BadHeader,
+    /// A structure in the list had a version other than the one we support.
+    UnsupportedVersion(u8),
+    /// A structure in the list was misaligned or not entirely within the memory BL2 could have
+    /// written it to, so the pointer to it is corrupt.
+    OutOfBounds(usize),
+    /// The list was longer than any valid list would be, so is probably circular.
+    TooLong,
+    /// There was no entry for the given image ID, or it had no entry point.
+    ImageNotFound(u32),
+}
+
+/// A structure which starts with a `param_header_t`.
+trait WithHeader {
+    /// The parameter type which the header must have.
+    const PARAM_TYPE: u8;
+
+    fn header(&self) -> &ParamHeader;
+}
+
+impl WithHeader for BlParams {
+    const PARAM_TYPE: u8 = PARAM_BL_PARAMS;
+
+    fn header(&self) -> &ParamHeader {
+        &self.h
+    }
+}
+
+impl WithHeader for TfaEntryPointInfo {
+    const PARAM_TYPE: u8 = PARAM_EP;
+
+    fn header(&self) -> &ParamHeader {
+        &self.h
+    }
+}
+
+/// Returns a reference to the structure of type `T` at the given non-null address, as long as it is
+/// aligned and entirely within `memory`.
+///
+/// # Safety
+///
+/// `memory` must be mapped, and not modified while the returned reference is in use.
+unsafe fn structure_at<'a, T>(
+    address: usize,
+    memory: &impl RangeBounds<usize>,
+) -> Result<&'a T, BlParamsError> {
+    let in_bounds = address.is_multiple_of(align_of::<T>())
+        && memory.contains(&address)
+        && address
+            .checked_add(size_of::<T>() - 1)
+            .is_some_and(|last| memory.contains(&last));
+    if !in_bounds {
+        return Err(BlParamsError::OutOfBounds(address));
+    }
+    // SAFETY: The structure is aligned and within `memory`, which our caller promised is mapped and
+    // not modified. All of the structures in the list are valid for any bit pattern.
+    Ok(unsafe { &*(address as *const T) })
+}
+
+/// Returns a reference to the parameter structure of type `T` at the given non-null address, after
+/// checking its bounds and that its header has the expected type, version and size.
+///
+/// # Safety
+///
+/// `memory` must be mapped, and not modified while the returned reference is in use.
+unsafe fn parameters_at<'a, T: WithHeader>(
+    address: usize,
+    memory: &impl RangeBounds<usize>,
+) -> Result<&'a T, BlParamsError> {
+    // SAFETY: Our caller promised that `memory` is mapped and not modified.
+    let parameters = unsafe { structure_at::<T>(address, memory) }?;
+    let header = parameters.header();
+    if header.param_type != T::PARAM_TYPE || usize::from(header.size) < size_of::<T>() {
+        Err(BlParamsError::BadHeader)
+    } else if header.version != VERSION_2 {
+        Err(BlParamsError::UnsupportedVersion(header.version))
+    } else {
+        Ok(parameters)
+    }
+}
+
+/// Returns the entry point of the image with the given ID from the `bl_params` list at the given
+/// address.
+///
+/// Every structure in the list must be within `memory`, which should be the memory BL2 writes them
+/// to, so that a corrupt pointer is rejected rather than followed.
+///
+/// # Safety
+///
+/// `memory` must be mapped, and not concurrently modified.
+pub unsafe fn image_entry_point(
+    bl_params: usize,
+    memory: &impl RangeBounds<usize>,
+    image_id: u32,
+) -> Result<EntryPointInfo, BlParamsError> {
+    if bl_params == 0 {
+        return Err(BlParamsError::Missing);
+    }
+    // SAFETY: Our caller promised that `memory` is mapped and not modified.
+    let bl_params = unsafe { parameters_at::<BlParams>(bl_params, memory) }?;
+
+    let mut node = bl_params.head as usize;
+    for _ in 0..MAX_IMAGES {
+        if node == 0 {
+            return Err(BlParamsError::ImageNotFound(image_id));
+        }
+        // SAFETY: Our caller promised that `memory` is mapped and not modified.
+        let current = unsafe { structure_at::<BlParamsNode>(node, memory) }?;
+        if current.image_id == image_id {
+            if current.ep_info.is_null() {
+                return Err(BlParamsError::ImageNotFound(image_id));
+            }
+            // SAFETY: Our caller promised that `memory` is mapped and not modified.
+            let ep_info =
+                unsafe { parameters_at::<TfaEntryPointInfo>(current.ep_info as usize, memory) }?;
+            return Ok(EntryPointInfo {
+                pc: ep_info.pc,
+                args: ep_info.args,
+            });
+        }
+        node = current.next_params_info as usize;
+    }
+    Err(BlParamsError::TooLong)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::ptr::null;
+
+    const EP_HEADER: ParamHeader = ParamHeader {
+        param_type: PARAM_EP,
+        version: VERSION_2,
+        size: size_of::<TfaEntryPointInfo>() as u16,
+        attr: 0,
+    };
+    const BL_PARAMS_HEADER: ParamHeader = ParamHeader {
+        param_type: PARAM_BL_PARAMS,
+        version: VERSION_2,
+        size: size_of::<BlParams>() as u16,
+        attr: 0,
+    };
+
+    #[test]
+    fn find_images() {
+        let bl32_ep = TfaEntryPointInfo {
+            h: EP_HEADER,
+            pc: 0x0600_0000,
+            spsr: 0x3c5,
+            args: [0x0400_1500, 0x07f0_0000, 0, 0, 0, 0, 0, 0],
+        };
+        let bl33_ep = TfaEntryPointInfo {
+            h: EP_HEADER,
+            pc: 0x8800_0000,
+            spsr: 0x3c9,
+            args: [0x8000_0000, 0x8200_0000, 0, 0, 0, 0, 0, 0],
+        };
+        let bl33 = BlParamsNode {
+            image_id: BL33_IMAGE_ID,
+            image_info: null(),
+            ep_info: &bl33_ep,
+            next_params_info: null(),
+        };
+        let bl32 = BlParamsNode {
+            image_id: BL32_IMAGE_ID,
+            image_info: null(),
+            ep_info: &bl32_ep,
+            next_params_info: &bl33,
+        };
+        let bl_params = BlParams {
+            h: BL_PARAMS_HEADER,
+            head: &bl32,
+        };
+        let address = &raw const bl_params as usize;
+
+        // SAFETY: `bl_params` and everything it points to is valid, and nothing modifies it.
+        unsafe {
+            assert_eq!(
+                image_entry_point(address, &.., BL32_IMAGE_ID),
+                Ok(EntryPointInfo {
+                    pc: 0x0600_0000,
+                    args: [0x0400_1500, 0x07f0_0000, 0, 0, 0, 0, 0, 0],
+                })
+            );
+            assert_eq!(
+                image_entry_point(address, &.., BL33_IMAGE_ID),
+                Ok(EntryPointInfo {
+                    pc: 0x8800_0000,
+                    args: [0x8000_0000, 0x8200_0000, 0, 0, 0, 0, 0, 0],
+                })
+            );
+            assert_eq!(
+                image_entry_point(address, &.., 3),
+                Err(BlParamsError::ImageNotFound(3))
+            );
+            assert_eq!(
+                image_entry_point(0, &.., BL33_IMAGE_ID),
+                Err(BlParamsError::Missing)
+            );
+        }
+    }
+
+    #[test]
+    fn bad_headers() {
+        let bad_ep = TfaEntryPointInfo {
+            h: BL_PARAMS_HEADER,
+            pc: 0x8800_0000,
+            spsr: 0,
+            args: [0; 8],
+        };
+        let node = BlParamsNode {
+            image_id: BL33_IMAGE_ID,
+            image_info: null(),
+            ep_info: &bad_ep,
+            next_params_info: null(),
+        };
+        let bl_params = BlParams {
+            h: BL_PARAMS_HEADER,
+            head: &node,
+        };
+        let bad_bl_params = BlParams {
+            h: EP_HEADER,
+            head: &node,
+        };
+
+        // SAFETY: Both lists and everything they point to are valid.
+        unsafe {
+            assert_eq!(
+                image_entry_point(&raw const bl_params as usize, &.., BL33_IMAGE_ID),
+                Err(BlParamsError::BadHeader)
+            );
+            assert_eq!(
+                image_entry_point(&raw const bad_bl_params as usize, &.., BL33_IMAGE_ID),
+                Err(BlParamsError::BadHeader)
+            );
+        }
+    }
+
+    #[test]
+    fn malformed_lists() {
+        let ep = TfaEntryPointInfo {
+            h: EP_HEADER,
+            pc: 0x8800_0000,
+            spsr: 0x3c9,
+            args: [0; 8],
+        };
+        let node = BlParamsNode {
+            image_id: BL33_IMAGE_ID,
+            image_info: null(),
+            ep_info: &ep,
+            next_params_info: null(),
+        };
+        let bl_params = BlParams {
+            h: BL_PARAMS_HEADER,
+            head: &node,
+        };
+        let address = &raw const bl_params as usize;
+        let old_bl_params = BlParams {
+            h: ParamHeader {
+                version: 0x01,
+                ..BL_PARAMS_HEADER
+            },
+            head: &node,
+        };
+        let short_bl_params = BlParams {
+            h: ParamHeader {
+                size: 4,
+                ..BL_PARAMS_HEADER
+            },
+            head: &node,
+        };
+
+        // SAFETY: All of the lists and everything they point to are valid, and nothing modifies
+        // them. Structures outside the given memory range or misaligned aren't accessed.
+        unsafe {
+            // The list itself is outside memory.
+            assert_eq!(
+                image_entry_point(address, &(0..address), BL33_IMAGE_ID),
+                Err(BlParamsError::OutOfBounds(address))
+            );
+            // Only the list header is in memory, not the node it points to.
+            assert_eq!(
+                image_entry_point(
+                    address,
+                    &(address..address + size_of::<BlParams>()),
+                    BL33_IMAGE_ID
+                ),
+                Err(BlParamsError::OutOfBounds(&raw const node as usize))
+            );
+            assert_eq!(
+                image_entry_point(address + 1, &.., BL33_IMAGE_ID),
+                Err(BlParamsError::OutOfBounds(address + 1))
+            );
+            assert_eq!(
+                image_entry_point(&raw const old_bl_params as usize, &.., BL33_IMAGE_ID),
+                Err(BlParamsError::UnsupportedVersion(0x01))
+            );
+            assert_eq!(
+                image_entry_point(&raw const short_bl_params as usize, &.., BL33_IMAGE_ID),
+                Err(BlParamsError::BadHeader)
+            );
+        }
+    }
+
+    #[test]
+    fn circular_list() {
+        let mut node = BlParamsNode {
+            image_id: BL32_IMAGE_ID,
+            image_info: null(),
+            ep_info: null(),
+            next_params_info: null(),
+        };
+        node.next_params_info = &raw const node;
+        let bl_params = BlParams {
+            h: BL_PARAMS_HEADER,
+            head: &node,
+        };
+
+        // SAFETY: `bl_params` and everything it points to is valid, and nothing modifies it.
+        unsafe {
+            assert_eq!(
+                image_entry_point(&raw const bl_params as usize, &.., BL33_IMAGE_ID),
+                Err(BlParamsError::TooLong)
+            );
+        }
+    }
+}
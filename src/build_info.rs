@@ -0,0 +1,101 @@
+// Copyright The Rusted Firmware-A Contributors.
+//
+// SPDX-License-Identifier: BSD-3-Clause
+
+//! Information about the RF-A build, embedded at compile time so that the firmware running on a
+//! device can be identified.
+
+use core::fmt::{self, Display, Formatter};
+
+/// Information identifying an RF-A build.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct BuildInfo {
+    /// The major version of the RF-A crate.
+    pub major: u16,
+    /// The minor version of the RF-A crate.
+    pub minor: u16,
+    /// The patch version of the RF-A crate.
+    pub patch: u16,
+    /// The first 64 bits of the git commit hash RF-A was built from, or 0 if unknown.
+    pub git_revision: u64,
+    /// Whether the git working tree had uncommitted changes when RF-A was built.
+    pub git_dirty: bool,
+    /// A hash of the enabled features, profile, target and compiler flags.
+    pub config_hash: u64,
+    /// The anti-rollback version of the image, which is compared against the platform's trusted
+    /// firmware NV counter. It should be increased whenever a security fix is released, so that
+    /// older images can be rejected.
+    pub rollback_version: u32,
+}
+
+impl BuildInfo {
+    /// Returns the version of the RF-A crate, encoded with the major version in bits [47:32], the
+    /// minor version in bits [31:16] and the patch version in bits [15:0].
+    pub const fn version(&self) -> u64 {
+        (self.major as u64) << 32 | (self.minor as u64) << 16 | self.patch as u64
+    }
+}
+
+impl Display for BuildInfo {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "v{}.{}.{} (", self.major, self.minor, self.patch)?;
+        if self.git_revision == 0 {
+            write!(f, "unknown revision")?;
+        } else {
+            write!(f, "{:016x}", self.git_revision)?;
+        }
+        if self.git_dirty {
+            write!(f, "-dirty")?;
+        }
+        write!(f, ", config {:016x})", self.config_hash)
+    }
+}
+
+/// Information about the running RF-A build.
+pub const BUILD_INFO: BuildInfo = BuildInfo {
+    major: parse(env!("CARGO_PKG_VERSION_MAJOR"), 10) as u16,
+    minor: parse(env!("CARGO_PKG_VERSION_MINOR"), 10) as u16,
+    patch: parse(env!("CARGO_PKG_VERSION_PATCH"), 10) as u16,
+    git_revision: parse(env!("RF_A_GIT_REVISION"), 16),
+    git_dirty: parse(env!("RF_A_GIT_DIRTY"), 10) != 0,
+    config_hash: parse(env!("RF_A_BUILD_CONFIG_HASH"), 16),
+    rollback_version: parse(env!("RF_A_ROLLBACK_VERSION"), 10) as u32,
+};
+
+/// Parses a number passed by the build script, failing the build if it is invalid.
+const fn parse(value: &str, radix: u32) -> u64 {
+    match u64::from_str_radix(value, radix) {
+        Ok(value) => value,
+        Err(_) => panic!("Invalid build info value"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn display() {
+        let mut info = BuildInfo {
+            major: 1,
+            minor: 2,
+            patch: 3,
+            git_revision: 0x0123_4567_89ab_cdef,
+            git_dirty: true,
+            config_hash: 0xfedc_ba98_7654_3210,
+            rollback_version: 4,
+        };
+        assert_eq!(info.version(), 0x0001_0002_0003);
+        assert_eq!(
+            info.to_string(),
+            "v1.2.3 (0123456789abcdef-dirty, config fedcba9876543210)"
+        );
+
+        info.git_revision = 0;
+        info.git_dirty = false;
+        assert_eq!(
+            info.to_string(),
+            "v1.2.3 (unknown revision, config fedcba9876543210)"
+        );
+    }
+}
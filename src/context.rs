@@ -5,38 +5,51 @@
 //! Handles initialising, saving and restoring register context when switching between EL3 and lower
 //! ELs.
 
+mod feature_traps;
+
+pub use feature_traps::{FeatureTraps, TrapControl};
+
 use crate::errata_framework::PlatformErrata;
 #[cfg(feature = "sel2")]
 use crate::errata_framework::erratum_applies;
 use crate::{
     aarch64::isb,
     cpu_extensions::{
-        CpuExtension, initialise_el3_sysregs, mpam::mpam_is_present, pmuv3, trf::TraceFiltering,
+        CpuExtension, initialise_el3_sysregs, mpam::mpam_is_present, os_lock, pmuv3,
+        trf::TraceFiltering,
     },
+    crash_dump::{CrashDump, DoubleFaultRecord},
     debug::CrashBuffer,
+    exceptions::{ExceptionClass, inject_undef64},
     gicv3,
     platform::{Platform, exception_free},
+    runtime_config::runtime_config,
     smccc::SmcReturn,
 };
 use arm_psci::EntryPoint;
+#[cfg(not(feature = "sel2"))]
+use arm_sysregs::write_cnthctl_el2;
+use arm_sysregs::{
+    CnthctlEl2, CptrEl3, EsrEl3, MdcrEl3, Mpam3El3, ScrEl3, SpsrEl3, read_id_aa64pfr0_el1,
+    read_id_aa64pfr1_el1, read_mpidr_el1, write_cptr_el3, write_mpam3_el3, write_scr_el3,
+};
 #[cfg(feature = "sel2")]
 use arm_sysregs::{
-    CnthctlEl2, CntvoffEl2, ContextidrEl2, CptrEl2, ElrEl2, EsrEl2, FarEl2, HcrEl2, HpfarEl2,
-    IccSreEl2, IchHcrEl2, IchVmcrEl2, MairEl2, MdcrEl2, SctlrEl2, SpEl2, SpsrEl2, TcrEl2, TpidrEl2,
-    Ttbr0El2, Ttbr1El2, VbarEl2, VmpidrEl2, VpidrEl2, VtcrEl2, VttbrEl2, read_actlr_el2,
-    read_afsr0_el2, read_afsr1_el2, read_amair_el2, read_cnthctl_el2, read_cntvoff_el2,
-    read_contextidr_el2, read_cptr_el2, read_elr_el2, read_esr_el2, read_far_el2, read_hacr_el2,
-    read_hcr_el2, read_hpfar_el2, read_hstr_el2, read_icc_sre_el2, read_ich_hcr_el2,
-    read_ich_vmcr_el2, read_id_aa64mmfr1_el1, read_mair_el2, read_mdcr_el2, read_scr_el3,
-    read_sctlr_el2, read_sp_el2, read_spsr_el2, read_tcr_el2, read_tpidr_el2, read_ttbr0_el2,
-    read_ttbr1_el2, read_vbar_el2, read_vmpidr_el2, read_vpidr_el2, read_vtcr_el2, read_vttbr_el2,
-    write_actlr_el2, write_afsr0_el2, write_afsr1_el2, write_amair_el2, write_cnthctl_el2,
-    write_cntvoff_el2, write_contextidr_el2, write_cptr_el2, write_elr_el2, write_esr_el2,
-    write_far_el2, write_hacr_el2, write_hcr_el2, write_hpfar_el2, write_hstr_el2,
-    write_icc_sre_el2, write_ich_hcr_el2, write_ich_vmcr_el2, write_mair_el2, write_mdcr_el2,
-    write_sctlr_el2, write_sp_el2, write_spsr_el2, write_tcr_el2, write_tpidr_el2, write_ttbr0_el2,
-    write_ttbr1_el2, write_vbar_el2, write_vmpidr_el2, write_vpidr_el2, write_vtcr_el2,
-    write_vttbr_el2,
+    CntvoffEl2, ContextidrEl2, CptrEl2, ElrEl2, EsrEl2, FarEl2, HcrEl2, HpfarEl2, IccSreEl2,
+    IchHcrEl2, IchVmcrEl2, MairEl2, MdcrEl2, SctlrEl2, SpEl2, SpsrEl2, TcrEl2, TpidrEl2, Ttbr0El2,
+    Ttbr1El2, VbarEl2, VmpidrEl2, VpidrEl2, VtcrEl2, VttbrEl2, read_actlr_el2, read_afsr0_el2,
+    read_afsr1_el2, read_amair_el2, read_cnthctl_el2, read_cntvoff_el2, read_contextidr_el2,
+    read_cptr_el2, read_elr_el2, read_esr_el2, read_far_el2, read_hacr_el2, read_hcr_el2,
+    read_hpfar_el2, read_hstr_el2, read_icc_sre_el2, read_ich_hcr_el2, read_ich_vmcr_el2,
+    read_id_aa64mmfr1_el1, read_mair_el2, read_mdcr_el2, read_scr_el3, read_sctlr_el2, read_sp_el2,
+    read_spsr_el2, read_tcr_el2, read_tpidr_el2, read_ttbr0_el2, read_ttbr1_el2, read_vbar_el2,
+    read_vmpidr_el2, read_vpidr_el2, read_vtcr_el2, read_vttbr_el2, write_actlr_el2,
+    write_afsr0_el2, write_afsr1_el2, write_amair_el2, write_cnthctl_el2, write_cntvoff_el2,
+    write_contextidr_el2, write_cptr_el2, write_elr_el2, write_esr_el2, write_far_el2,
+    write_hacr_el2, write_hcr_el2, write_hpfar_el2, write_hstr_el2, write_icc_sre_el2,
+    write_ich_hcr_el2, write_ich_vmcr_el2, write_mair_el2, write_mdcr_el2, write_sctlr_el2,
+    write_sp_el2, write_spsr_el2, write_tcr_el2, write_tpidr_el2, write_ttbr0_el2, write_ttbr1_el2,
+    write_vbar_el2, write_vmpidr_el2, write_vpidr_el2, write_vtcr_el2, write_vttbr_el2,
 };
 #[cfg(not(feature = "sel2"))]
 use arm_sysregs::{
@@ -52,19 +65,17 @@ use arm_sysregs::{
     write_sctlr_el1, write_sp_el1, write_spsr_el1, write_tcr_el1, write_tpidr_el0, write_tpidr_el1,
     write_tpidrro_el0, write_ttbr0_el1, write_ttbr1_el1, write_vbar_el1,
 };
-use arm_sysregs::{
-    CptrEl3, EsrEl3, MdcrEl3, Mpam3El3, ScrEl3, SpsrEl3, read_mpidr_el1, write_cptr_el3,
-    write_mpam3_el3, write_scr_el3,
-};
 #[cfg(not(any(test, feature = "fakes")))]
 pub use asm::init_cpu_data_ptr;
 use core::{
     cell::{RefCell, RefMut},
     marker::PhantomData,
+    mem::{offset_of, replace},
     ops::{Index, IndexMut},
 };
 #[cfg(all(target_arch = "aarch64", not(any(test, feature = "fakes"))))]
 use include_first::include_first;
+use log::warn;
 use percore::{Cores, ExceptionFree, ExceptionLock, PerCore};
 use spin::Once;
 
@@ -125,7 +136,7 @@ pub struct CpuContext {
 }
 
 impl CpuContext {
-    const EMPTY: Self = Self {
+    pub(crate) const EMPTY: Self = Self {
         gpregs: GpRegs::EMPTY,
         pauth_regs: PAuthRegs::EMPTY,
         el3_state: El3State::EMPTY,
@@ -162,6 +173,15 @@ impl CpuContext {
     pub(crate) fn skip_lower_el_instruction(&mut self) {
         self.el3_state.elr_el3 += core::mem::size_of::<u32>();
     }
+
+    /// Moves the saved ELR_EL3 back by the size of an instruction.
+    ///
+    /// For exceptions such as HVC where ELR_EL3 points to the instruction after the one which
+    /// caused the exception, this makes it point to the instruction itself again.
+    /// Should only be used by [`crate::services::Services::handle_unknown_hvc()`].
+    pub(crate) fn rewind_lower_el_instruction(&mut self) {
+        self.el3_state.elr_el3 -= core::mem::size_of::<u32>();
+    }
 }
 
 /// AArch64 general purpose register context structure. Usually x0-x18 and lr are saved as the
@@ -194,6 +214,11 @@ impl GpRegs {
 /// FEAT_PAuth key registers.
 /// FEAT_PAuth is mandatory from Armv8.3, so it is assumed to be both present and used by lower ELs
 /// in multiple worlds.
+///
+/// All five keys are saved to the context of the world which was running on every entry to EL3,
+/// and restored from the context of the world being entered on every exit, by `context.S`. This is
+/// what allows `SCR_EL3.APK` and `SCR_EL3.API` to be set for every world without one world's keys
+/// leaking into another.
 #[derive(Clone, Debug)]
 #[repr(C, align(16))]
 struct PAuthRegs {
@@ -209,6 +234,16 @@ struct PAuthRegs {
     apgakey_hi: u64,
 }
 
+// `context.S` saves and restores each key with a single `stp` or `ldp`, so the high half of each
+// must immediately follow the low half.
+const _: () = {
+    assert!(offset_of!(PAuthRegs, apiakey_hi) == offset_of!(PAuthRegs, apiakey_lo) + 8);
+    assert!(offset_of!(PAuthRegs, apibkey_hi) == offset_of!(PAuthRegs, apibkey_lo) + 8);
+    assert!(offset_of!(PAuthRegs, apdakey_hi) == offset_of!(PAuthRegs, apdakey_lo) + 8);
+    assert!(offset_of!(PAuthRegs, apdbkey_hi) == offset_of!(PAuthRegs, apdbkey_lo) + 8);
+    assert!(offset_of!(PAuthRegs, apgakey_hi) == offset_of!(PAuthRegs, apgakey_lo) + 8);
+};
+
 impl PAuthRegs {
     const EMPTY: Self = Self {
         apiakey_lo: 0,
@@ -564,6 +599,241 @@ impl El2Sysregs {
     }
 }
 
+#[cfg(feature = "sel2")]
+impl El2Sysregs {
+    /// Decodes the saved stage 2 translation configuration.
+    pub fn stage2_config(&self) -> Result<Stage2Config, Stage2ConfigError> {
+        Stage2Config::decode(self.vtcr_el2, self.vttbr_el2)
+    }
+
+    /// Returns the saved FP/SIMD, SVE and SME trap controls, in whichever layout of `CPTR_EL2` the
+    /// saved `HCR_EL2.E2H` selects.
+    pub fn feature_traps(&self) -> FeatureTraps {
+        FeatureTraps::from_cptr_el2(self.cptr_el2, self.hcr_el2.contains(HcrEl2::E2H))
+    }
+
+    /// Sets the saved FP/SIMD, SVE and SME trap controls, in whichever layout of `CPTR_EL2` the
+    /// saved `HCR_EL2.E2H` selects.
+    pub fn set_feature_traps(&mut self, traps: FeatureTraps) {
+        self.cptr_el2 = traps.to_cptr_el2(self.cptr_el2, self.hcr_el2.contains(HcrEl2::E2H));
+    }
+}
+
+/// The S-EL2 system registers which only exist in the secure world, so aren't part of
+/// [`El2Sysregs`].
+///
+/// The normal world can't access these, so they don't need to be switched on a world switch, but
+/// they are lost when the core is powered down.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg(feature = "sel2")]
+pub struct SecureEl2Sysregs {
+    vstcr_el2: u64,
+    vsttbr_el2: u64,
+}
+
+#[cfg(feature = "sel2")]
+impl SecureEl2Sysregs {
+    pub(crate) const EMPTY: Self = Self {
+        vstcr_el2: 0,
+        vsttbr_el2: 0,
+    };
+
+    /// Reads the current values of the secure EL2 system registers.
+    pub fn save() -> Self {
+        Self {
+            vstcr_el2: secure_el2_sysregs::read_vstcr_el2(),
+            vsttbr_el2: secure_el2_sysregs::read_vsttbr_el2(),
+        }
+    }
+
+    /// Writes the saved values back to the secure EL2 system registers.
+    pub fn restore(&self) {
+        // SAFETY: We're restoring the values previously saved, so they must be valid.
+        unsafe {
+            secure_el2_sysregs::write_vstcr_el2(self.vstcr_el2);
+            secure_el2_sysregs::write_vsttbr_el2(self.vsttbr_el2);
+        }
+    }
+}
+
+/// Accessors for `VSTCR_EL2` and `VSTTBR_EL2`, which `arm_sysregs` doesn't provide.
+///
+/// `VSTCR_EL2` is accessed by its encoding, as the assembler doesn't recognise its name.
+#[cfg(all(
+    feature = "sel2",
+    target_arch = "aarch64",
+    not(any(test, feature = "fakes"))
+))]
+mod secure_el2_sysregs {
+    use core::arch::asm;
+
+    pub fn read_vstcr_el2() -> u64 {
+        let value;
+        // SAFETY: Reading VSTCR_EL2 has no side effects.
+        unsafe {
+            asm!(
+                "mrs {}, s3_4_c2_c6_2",
+                out(reg) value,
+                options(nomem, nostack, preserves_flags),
+            );
+        }
+        value
+    }
+
+    pub fn read_vsttbr_el2() -> u64 {
+        let value;
+        // SAFETY: Reading VSTTBR_EL2 has no side effects.
+        unsafe {
+            asm!("mrs {}, vsttbr_el2", out(reg) value, options(nomem, nostack, preserves_flags));
+        }
+        value
+    }
+
+    /// # Safety
+    ///
+    /// The value must be a valid secure stage 2 translation configuration for the SPMC.
+    pub unsafe fn write_vstcr_el2(value: u64) {
+        // SAFETY: Our caller promised that the value is valid.
+        unsafe {
+            asm!(
+                "msr s3_4_c2_c6_2, {}",
+                in(reg) value,
+                options(nomem, nostack, preserves_flags),
+            );
+        }
+    }
+
+    /// # Safety
+    ///
+    /// The value must be a valid secure stage 2 translation table base for the SPMC.
+    pub unsafe fn write_vsttbr_el2(value: u64) {
+        // SAFETY: Our caller promised that the value is valid.
+        unsafe {
+            asm!("msr vsttbr_el2, {}", in(reg) value, options(nomem, nostack, preserves_flags));
+        }
+    }
+}
+
+/// There are no fake secure EL2 registers, so they always read as 0 and writes are ignored.
+#[cfg(all(
+    feature = "sel2",
+    not(all(target_arch = "aarch64", not(any(test, feature = "fakes"))))
+))]
+mod secure_el2_sysregs {
+    pub fn read_vstcr_el2() -> u64 {
+        0
+    }
+
+    pub fn read_vsttbr_el2() -> u64 {
+        0
+    }
+
+    pub unsafe fn write_vstcr_el2(_value: u64) {}
+
+    pub unsafe fn write_vsttbr_el2(_value: u64) {}
+}
+
+/// A translation granule size for stage 2 translation, as given by `VTCR_EL2.TG0`.
+#[cfg(feature = "sel2")]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Stage2Granule {
+    /// 4 KiB pages.
+    Size4KiB,
+    /// 16 KiB pages.
+    Size16KiB,
+    /// 64 KiB pages.
+    Size64KiB,
+}
+
+/// A reason why a stage 2 translation configuration is invalid.
+#[cfg(feature = "sel2")]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Stage2ConfigError {
+    /// `VTCR_EL2.TG0` has a reserved value.
+    ReservedGranule(u8),
+    /// `VTCR_EL2.PS` has a reserved value.
+    ReservedPhysicalSize(u8),
+    /// `VTCR_EL2.T0SZ` gives an IPA size which isn't supported.
+    InvalidIpaSize(u8),
+    /// The IPA size is larger than the physical address size.
+    IpaLargerThanPhysical {
+        /// The size of the IPA space in bits.
+        ipa_bits: u8,
+        /// The size of the output address space in bits.
+        pa_bits: u8,
+    },
+    /// `VTTBR_EL2.VMID` uses more than 8 bits, but `VTCR_EL2.VS` doesn't enable 16-bit VMIDs.
+    VmidTooWide(u16),
+}
+
+/// The stage 2 translation configuration of EL2, decoded from `VTCR_EL2` and `VTTBR_EL2`.
+#[cfg(feature = "sel2")]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Stage2Config {
+    /// The size of the IPA space in bits.
+    pub ipa_bits: u8,
+    /// The size of the output address space in bits.
+    pub pa_bits: u8,
+    /// The translation granule.
+    pub granule: Stage2Granule,
+    /// The VMID of the current stage 2 translation tables.
+    pub vmid: u16,
+    /// Whether VMIDs are 16 bits rather than 8 bits.
+    pub vmid_16bit: bool,
+}
+
+#[cfg(feature = "sel2")]
+impl Stage2Config {
+    /// Decodes and checks the given stage 2 configuration register values.
+    ///
+    /// This doesn't check the values against the features implemented by the PE, so e.g. a 52-bit
+    /// IPA size is accepted whenever `VTCR_EL2.DS` is set.
+    pub fn decode(vtcr: VtcrEl2, vttbr: VttbrEl2) -> Result<Self, Stage2ConfigError> {
+        let granule = match vtcr.tg0() {
+            0b00 => Stage2Granule::Size4KiB,
+            0b01 => Stage2Granule::Size64KiB,
+            0b10 => Stage2Granule::Size16KiB,
+            tg0 => return Err(Stage2ConfigError::ReservedGranule(tg0)),
+        };
+        let pa_bits = match vtcr.ps() {
+            0b000 => 32,
+            0b001 => 36,
+            0b010 => 40,
+            0b011 => 42,
+            0b100 => 44,
+            0b101 => 48,
+            0b110 => 52,
+            ps => return Err(Stage2ConfigError::ReservedPhysicalSize(ps)),
+        };
+
+        // Without FEAT_TTST the smallest IPA space is 25 bits, and without 52-bit addressing the
+        // largest is 48 bits.
+        let t0sz = vtcr.t0sz();
+        let min_t0sz = if vtcr.contains(VtcrEl2::DS) { 12 } else { 16 };
+        if !(min_t0sz..=39).contains(&t0sz) {
+            return Err(Stage2ConfigError::InvalidIpaSize(t0sz));
+        }
+        let ipa_bits = 64 - t0sz;
+        if ipa_bits > pa_bits {
+            return Err(Stage2ConfigError::IpaLargerThanPhysical { ipa_bits, pa_bits });
+        }
+
+        let vmid_16bit = vtcr.contains(VtcrEl2::VS);
+        let vmid = vttbr.vmid();
+        if !vmid_16bit && vmid > u16::from(u8::MAX) {
+            return Err(Stage2ConfigError::VmidTooWide(vmid));
+        }
+
+        Ok(Self {
+            ipa_bits,
+            pa_bits,
+            granule,
+            vmid,
+            vmid_16bit,
+        })
+    }
+}
+
 #[cfg(feature = "sel2")]
 fn errata_ich_vmcr_el2_applies<PlatformImpl: PlatformErrata>() -> bool {
     erratum_applies::<PlatformImpl>(3_300_099)
@@ -646,7 +916,7 @@ impl PerWorldContext {
         // not taken to EL3.
         //
         // SCR_EL3.APK: Set to one so that PAuth key register accesses are not
-        // trapped to EL3.
+        // trapped to EL3. All five keys are switched between worlds in context.S.
         //
         // SCR_EL3.API: Set to one so that execution of PAuth instructions are not
         // trapped to EL3.
@@ -683,6 +953,15 @@ pub struct CpuData {
     apiakey_hi: u64,
     /// Buffer used to store register values during the crash dump process.
     pub crash_buffer: CrashBuffer,
+    /// The register state saved when the CPU last crashed.
+    pub crash_dump: CrashDump,
+    /// Non-zero while the CPU is handling a crash, so that a fault in the crash path is caught by
+    /// the second-level handler rather than recursing.
+    in_crash_handler: u64,
+    /// Written by the second-level handler if the CPU faulted while handling a crash.
+    pub double_fault: DoubleFaultRecord,
+    /// The number of exceptions from lower ELs which the CPU is part way through handling.
+    exception_nesting: u64,
 }
 
 impl CpuData {
@@ -693,9 +972,43 @@ impl CpuData {
         #[cfg(feature = "pauth")]
         apiakey_hi: 0,
         crash_buffer: CrashBuffer::EMPTY,
+        crash_dump: CrashDump::EMPTY,
+        in_crash_handler: 0,
+        double_fault: DoubleFaultRecord::EMPTY,
+        exception_nesting: 0,
     };
+
+    /// Marks the CPU as handling a crash, and returns whether it already was.
+    pub fn enter_crash_handler(&mut self) -> bool {
+        replace(&mut self.in_crash_handler, 1) != 0
+    }
+
+    /// Records that the CPU has started handling an exception from a lower EL.
+    pub fn enter_exception_handler(&mut self) {
+        self.exception_nesting += 1;
+    }
+
+    /// Records that the CPU has finished handling an exception from a lower EL.
+    pub fn exit_exception_handler(&mut self) {
+        self.exception_nesting -= 1;
+    }
+
+    /// Records that the CPU isn't handling any exceptions from lower ELs, such as when it starts
+    /// again with an empty stack after powering down part way through handling one.
+    pub fn clear_exception_nesting(&mut self) {
+        self.exception_nesting = 0;
+    }
+
+    /// Returns the number of exceptions from lower ELs which the CPU is part way through handling.
+    pub fn exception_nesting(&self) -> u64 {
+        self.exception_nesting
+    }
 }
 
+/// The offset within `CpuData` of the flag set by `CpuData::enter_crash_handler`, for the assembly
+/// crash reporting code.
+pub const CPU_DATA_IN_CRASH_HANDLER_OFFSET: usize = offset_of!(CpuData, in_crash_handler);
+
 static PER_WORLD_CONTEXT: Once<PerWorld<PerWorldContext>> = Once::new();
 
 /// Gets the `PerWorldContext` for the given world.
@@ -714,8 +1027,28 @@ pub fn cpu_data_set_apkey<PlatformImpl: CpuDataIndex>(token: ExceptionFree, apke
     });
 }
 
+/// Zeroes the apkey fields of every CPU's data.
+///
+/// Each core loads its EL3 key from its `CpuData` on every entry to EL3, so this doesn't change the
+/// key in use by any core until it next enters EL3, which will then use a zero key.
+#[cfg(feature = "pauth")]
+pub fn cpu_data_scrub_apkeys<PlatformImpl: CpuDataIndex + Platform>() {
+    for cpu_index in 0..PlatformImpl::CORE_COUNT {
+        let cpu_data = PlatformImpl::cpu_data_by_index(cpu_index);
+        // SAFETY: `cpu_data_by_index` returns a valid pointer for any index less than
+        // `CORE_COUNT`. The apkey fields are only written by each core for itself while it boots,
+        // and the writes are volatile so that they aren't optimised away. A core which reads its
+        // key part way through this will use a mixture of the two for its entire stay in EL3,
+        // which is still consistent.
+        unsafe {
+            (&raw mut (*cpu_data).apiakey_lo).write_volatile(0);
+            (&raw mut (*cpu_data).apiakey_hi).write_volatile(0);
+        }
+    }
+}
+
 /// An array with one `T` for each world.
-#[derive(Clone, Debug, Default, Eq, PartialEq)]
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
 #[repr(transparent)]
 pub struct PerWorld<T>(pub [T; CPU_DATA_CONTEXT_NUM]);
 
@@ -827,6 +1160,22 @@ pub fn switch_world<PlatformImpl: CpuStateAccess + PlatformErrata + Platform>(
     });
 }
 
+/// Handles an FP/SIMD, SVE or SME access from the given world which was trapped by `CPTR_EL3`, by
+/// letting the CPU extension which owns those registers make them available to the world.
+///
+/// If no extension handles the trap, e.g. because the world isn't allowed to use the registers at
+/// all, an undefined instruction exception is injected into the world instead.
+pub fn handle_feature_trap<PlatformImpl: CpuStateAccess + Platform>(world: World, esr: EsrEl3) {
+    let class = ExceptionClass::from_esr(esr);
+    if !PlatformImpl::CPU_EXTENSIONS
+        .iter()
+        .any(|ext| ext.is_present() && ext.handle_feature_trap(world, class))
+    {
+        warn!("Unhandled {class:?} from {world:?} world, ESR_EL3 {esr:?}");
+        inject_undef64::<PlatformImpl>(world);
+    }
+}
+
 /// Restores lower EL and some per-world EL3 system registers of the given world.
 ///
 /// This doesn't save the current state of the lower EL system registers, so should only be used for
@@ -859,16 +1208,16 @@ fn initialise_per_world_contexts<PlatformImpl: Platform>() {
         #[cfg(feature = "rme")]
         per_world[World::Realm].initialise_common();
 
-        // NS world can always access AMUv1 registers.
-        per_world[World::NonSecure].cptr_el3 -= CptrEl3::TAM;
         // SCR_EL3.FGTEN: Do not trap FGT register accesses to EL3. FEAT_FGT is mandatory since
         // ARMv8.6.
         per_world[World::NonSecure].scr_el3 |= ScrEl3::NS | ScrEl3::FGTEN;
         gicv3::set_routing_model(&mut per_world[World::NonSecure].scr_el3, World::NonSecure);
 
-        // Enable Secure EL1 access to timer registers.
-        // Otherwise they would be accessible only at EL3.
-        per_world[World::Secure].scr_el3 |= ScrEl3::ST;
+        // SCR_EL3.ST: Let Secure EL1 access the secure physical timer if the platform allows it.
+        // Otherwise its registers are accessible only at EL3.
+        if PlatformImpl::TIMER_ACCESS[World::Secure].secure_physical_timer {
+            per_world[World::Secure].scr_el3 |= ScrEl3::ST;
+        }
         gicv3::set_routing_model(&mut per_world[World::Secure].scr_el3, World::Secure);
 
         #[cfg(feature = "rme")]
@@ -882,6 +1231,18 @@ fn initialise_per_world_contexts<PlatformImpl: Platform>() {
             gicv3::set_routing_model(&mut per_world[World::Realm].scr_el3, World::Realm);
         }
 
+        // Trap ID register reads from any world which must have features hidden from it, so that
+        // they can be emulated.
+        for (context, hidden) in per_world
+            .0
+            .iter_mut()
+            .zip(&runtime_config().hidden_id_features.0)
+        {
+            if !hidden.is_empty() {
+                context.scr_el3 |= ScrEl3::TID3;
+            }
+        }
+
         for ext in PlatformImpl::CPU_EXTENSIONS {
             if ext.is_present() {
                 ext.configure_per_world(World::NonSecure, &mut per_world[World::NonSecure]);
@@ -901,6 +1262,7 @@ pub fn initialise_contexts<PlatformImpl: CpuStateAccess + Platform>(
     #[cfg(feature = "rme")] realm_entry_point: &EntryPointInfo,
 ) {
     initialise_el3_sysregs::<PlatformImpl>();
+    os_lock::unlock();
     initialise_per_world_contexts::<PlatformImpl>();
 
     exception_free(|token| {
@@ -915,19 +1277,115 @@ pub fn initialise_contexts<PlatformImpl: CpuStateAccess + Platform>(
     });
 }
 
+/// Optional PSTATE bits to set on the initial entry to a world, in addition to masking all
+/// exceptions.
+///
+/// Bits for features which the CPU doesn't implement are left clear.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct InitialPstate {
+    /// Allow speculative store bypass (`PSTATE.SSBS`), if FEAT_SSBS is implemented.
+    pub ssbs: bool,
+    /// Prevent privileged access to unprivileged memory (`PSTATE.PAN`).
+    pub pan: bool,
+    /// Use data independent timing (`PSTATE.DIT`), if FEAT_DIT is implemented.
+    pub dit: bool,
+}
+
+impl InitialPstate {
+    /// Leaves SSBS, PAN and DIT clear.
+    pub const DEFAULT: Self = Self {
+        ssbs: false,
+        pan: false,
+        dit: false,
+    };
+
+    /// Returns the SPSR_EL3 value to enter the given AArch64 mode with this policy.
+    fn spsr(self, mode: SpsrEl3, ssbs_present: bool, dit_present: bool) -> SpsrEl3 {
+        let mut spsr = SpsrEl3::D | SpsrEl3::A | SpsrEl3::I | SpsrEl3::F | mode;
+        if self.ssbs && ssbs_present {
+            spsr |= SpsrEl3::SSBS;
+        }
+        // FEAT_PAN is mandatory from Armv8.1.
+        if self.pan {
+            spsr |= SpsrEl3::PAN;
+        }
+        if self.dit && dit_present {
+            spsr |= SpsrEl3::DIT;
+        }
+        spsr
+    }
+}
+
+/// Which of the generic timer's physical counter and timers the lower ELs of a world may access
+/// directly, rather than having their accesses trapped.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct TimerAccess {
+    /// Allow Secure EL1 to access the secure physical timer (`SCR_EL3.ST`), rather than trapping to
+    /// EL3. This is ignored for other worlds.
+    pub secure_physical_timer: bool,
+    /// Allow EL1 and EL0 to access the physical counter and timer without trapping to EL2, in the
+    /// initial value of `CNTHCTL_EL2`. EL2 software may change this.
+    pub el1_physical_counter: bool,
+}
+
+impl TimerAccess {
+    /// Allows access to both the secure physical timer and the physical counter and timer.
+    pub const DEFAULT: Self = Self {
+        secure_physical_timer: true,
+        el1_physical_counter: true,
+    };
+
+    /// Returns the initial `CNTHCTL_EL2` value for this policy.
+    fn cnthctl_el2(self) -> CnthctlEl2 {
+        if self.el1_physical_counter {
+            // With HCR_EL2.E2H clear, as it is on entry to EL2, bits 0 and 1 are EL1PCTEN and
+            // EL1PCEN rather than EL0PCTEN and EL0VCTEN.
+            CnthctlEl2::EL0PCTEN | CnthctlEl2::EL0VCTEN
+        } else {
+            CnthctlEl2::empty()
+        }
+    }
+}
+
+/// Sets the initial `CNTHCTL_EL2` for the given world, according to the platform's `TIMER_ACCESS`
+/// policy.
+fn initialise_cnthctl<PlatformImpl: Platform>(
+    #[cfg_attr(not(feature = "sel2"), allow(unused))] context: &mut CpuContext,
+    world: World,
+) {
+    let cnthctl_el2 = PlatformImpl::TIMER_ACCESS[world].cnthctl_el2();
+    #[cfg(feature = "sel2")]
+    {
+        context.el2_sysregs.cnthctl_el2 = cnthctl_el2;
+    }
+    // Without S-EL2 the EL2 registers aren't switched between worlds, as they belong to the normal
+    // world, so its initial value is written directly.
+    #[cfg(not(feature = "sel2"))]
+    if world == World::NonSecure {
+        write_cnthctl_el2(cnthctl_el2);
+    }
+}
+
+/// Sets the SPSR for the initial entry to the given world in the given mode, according to the
+/// platform's `INITIAL_PSTATE` policy.
+fn initialise_spsr<PlatformImpl: Platform>(context: &mut CpuContext, world: World, mode: SpsrEl3) {
+    context.el3_state.spsr_el3 = PlatformImpl::INITIAL_PSTATE[world].spsr(
+        mode,
+        read_id_aa64pfr1_el1().is_feat_ssbs_present(),
+        read_id_aa64pfr0_el1().is_feat_dit_present(),
+    );
+}
+
 /// Initialises parts of the given CPU context that are the same for all worlds.
 fn initialise_common(context: &mut CpuContext, entry_point: &EntryPointInfo) {
     *context = CpuContext::EMPTY;
     context.el3_state.elr_el3 = entry_point.pc;
     context.gpregs.registers[..entry_point.args.len()].copy_from_slice(&entry_point.args);
 
-    context.el3_state.spsr_el3 =
-        SpsrEl3::D | SpsrEl3::A | SpsrEl3::I | SpsrEl3::F | SpsrEl3::M_AARCH64_EL2H;
-
     #[cfg(feature = "sel2")]
     {
         // TODO: Initialise the rest of the context.el2_sysregs too.
-        context.el2_sysregs.cptr_el2 = CptrEl2::RES1;
+        context.el2_sysregs.set_feature_traps(FeatureTraps::NONE);
         context.el2_sysregs.icc_sre_el2 =
             IccSreEl2::DIB | IccSreEl2::DFB | IccSreEl2::ENABLE | IccSreEl2::SRE;
         context.el2_sysregs.tcr_el2 = TcrEl2::RES1;
@@ -967,6 +1425,11 @@ fn initialise_common(context: &mut CpuContext, entry_point: &EntryPointInfo) {
     //  accesses to Trace Buffer control registers at EL2 and EL1 in any
     //  security state generates trap exceptions to EL3.
     //  If FEAT_TRBE is not implemented, these bits are RES0.
+    //
+    // MDCR_EL3.SBRBE: Set to zero so that branch recording is prohibited in
+    //  Secure state, and accesses to the BRBE registers at EL2 and EL1 in any
+    //  security state generate trap exceptions to EL3. If FEAT_BRBE is not
+    //  implemented, these bits are RES0.
     context.el3_state.mdcr_el3 = MdcrEl3::SDD | MdcrEl3::SPD32;
 
     if TraceFiltering.is_present() {
@@ -984,6 +1447,8 @@ fn initialise_nonsecure<PlatformImpl: Platform>(
     entry_point: &EntryPointInfo,
 ) {
     initialise_common(context, entry_point);
+    initialise_spsr::<PlatformImpl>(context, World::NonSecure, SpsrEl3::M_AARCH64_EL2H);
+    initialise_cnthctl::<PlatformImpl>(context, World::NonSecure);
 
     // Configure CPU extensions for the non-secure world.
     for ext in PlatformImpl::CPU_EXTENSIONS {
@@ -1000,11 +1465,11 @@ fn initialise_secure<PlatformImpl: Platform>(
 ) {
     initialise_common(context, entry_point);
 
+    #[cfg(feature = "sel2")]
+    initialise_spsr::<PlatformImpl>(context, World::Secure, SpsrEl3::M_AARCH64_EL2H);
     #[cfg(not(feature = "sel2"))]
-    {
-        context.el3_state.spsr_el3 =
-            SpsrEl3::D | SpsrEl3::A | SpsrEl3::I | SpsrEl3::F | SpsrEl3::M_AARCH64_EL1H;
-    }
+    initialise_spsr::<PlatformImpl>(context, World::Secure, SpsrEl3::M_AARCH64_EL1H);
+    initialise_cnthctl::<PlatformImpl>(context, World::Secure);
 
     // Configure CPU extensions for the secure world.
     for ext in PlatformImpl::CPU_EXTENSIONS {
@@ -1021,6 +1486,8 @@ fn initialise_realm<PlatformImpl: Platform>(
     entry_point: &EntryPointInfo,
 ) {
     initialise_common(context, entry_point);
+    initialise_spsr::<PlatformImpl>(context, World::Realm, SpsrEl3::M_AARCH64_EL2H);
+    initialise_cnthctl::<PlatformImpl>(context, World::Realm);
 
     // Configure CPU extensions for the Realm world.
     for ext in PlatformImpl::CPU_EXTENSIONS {
@@ -1082,7 +1549,7 @@ mod asm {
     use super::*;
     use crate::{
         debug::{DEBUG, ENABLE_ASSERTIONS},
-        exceptions::RunResult,
+        exceptions::{ExceptionClass, FUNCID_CC_SHIFT, RunResult},
         naked_asm,
         platform::my_core_pos,
         smccc::NOT_SUPPORTED,
@@ -1150,6 +1617,15 @@ mod asm {
         PMCR_EL0_DP_BIT = const PmcrEl0::DP.bits(),
         MODE_SP_EL0 = const StackPointer::El0 as u8,
         MODE_SP_ELX = const StackPointer::ElX as u8,
+        EC_AARCH32_SMC = const ExceptionClass::Smc32.code(),
+        EC_AARCH64_SMC = const ExceptionClass::Smc64.code(),
+        EC_AARCH64_SYS = const ExceptionClass::SysregTrap.code(),
+        EC_AARCH64_HVC = const ExceptionClass::Hvc64.code(),
+        EC_FP_TRAP = const ExceptionClass::FpTrap.code(),
+        EC_SVE_TRAP = const ExceptionClass::SveTrap.code(),
+        EC_SME_TRAP = const ExceptionClass::SmeTrap.code(),
+        EC_IMP_DEF_EL3 = const ExceptionClass::ImpDefEl3.code(),
+        FUNCID_CC_SHIFT = const FUNCID_CC_SHIFT,
         CTX_NESTED_EA_FLAG = const offset_of!(El3State, nested_ea_flag),
         CTX_GPREGS_OFFSET = const offset_of!(GpRegs, registers),
         CTX_EL3STATE_OFFSET = const offset_of!(CpuContext, el3_state),
@@ -1189,6 +1665,8 @@ mod asm {
         SMC_UNK = const NOT_SUPPORTED,
         RUN_RESULT_SMC = const RunResult::SMC,
         RUN_RESULT_SYSREG_TRAP = const RunResult::SYSREG_TRAP,
+        RUN_RESULT_HVC = const RunResult::HVC,
+        RUN_RESULT_FEATURE_TRAP = const RunResult::FEATURE_TRAP,
         RUN_RESULT_INTERRUPT = const RunResult::INTERRUPT,
         CPU_DATA_APIAKEY_OFFSET = const APIAKEY_OFFSET,
         ENABLE_PAUTH = const cfg!(feature = "pauth") as u32,
@@ -1256,3 +1734,149 @@ macro_rules! context_asm {
 #[allow(clippy::single_component_path_imports)]
 #[cfg(all(target_arch = "aarch64", not(any(test, feature = "fakes"))))]
 pub use context_asm;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::platform::test::TestPlatform;
+    use arm_sysregs::{ElrEl1, EsrEl1, VbarEl1};
+
+    #[cfg(feature = "sel2")]
+    #[test]
+    fn stage2_config() {
+        let vtcr = VtcrEl2::RES1
+            .with_t0sz(24)
+            .with_tg0(0b00)
+            .with_ps(0b010)
+            .with_sl0(0b01);
+        assert_eq!(
+            Stage2Config::decode(vtcr, VttbrEl2::empty().with_vmid(0x42)),
+            Ok(Stage2Config {
+                ipa_bits: 40,
+                pa_bits: 40,
+                granule: Stage2Granule::Size4KiB,
+                vmid: 0x42,
+                vmid_16bit: false,
+            })
+        );
+        assert_eq!(
+            Stage2Config::decode(vtcr, VttbrEl2::empty().with_vmid(0x1234)),
+            Err(Stage2ConfigError::VmidTooWide(0x1234))
+        );
+        assert_eq!(
+            Stage2Config::decode(vtcr | VtcrEl2::VS, VttbrEl2::empty().with_vmid(0x1234))
+                .map(|config| config.vmid),
+            Ok(0x1234)
+        );
+
+        assert_eq!(
+            Stage2Config::decode(vtcr.with_tg0(0b11), VttbrEl2::empty()),
+            Err(Stage2ConfigError::ReservedGranule(0b11))
+        );
+        assert_eq!(
+            Stage2Config::decode(vtcr.with_ps(0b111), VttbrEl2::empty()),
+            Err(Stage2ConfigError::ReservedPhysicalSize(0b111))
+        );
+        assert_eq!(
+            Stage2Config::decode(vtcr.with_t0sz(12), VttbrEl2::empty()),
+            Err(Stage2ConfigError::InvalidIpaSize(12))
+        );
+        assert_eq!(
+            Stage2Config::decode(vtcr.with_t0sz(16), VttbrEl2::empty()),
+            Err(Stage2ConfigError::IpaLargerThanPhysical {
+                ipa_bits: 48,
+                pa_bits: 40
+            })
+        );
+    }
+
+    #[test]
+    fn initial_pstate_spsr() {
+        let daif = SpsrEl3::D | SpsrEl3::A | SpsrEl3::I | SpsrEl3::F;
+        assert_eq!(
+            InitialPstate::DEFAULT.spsr(SpsrEl3::M_AARCH64_EL2H, true, true),
+            daif | SpsrEl3::M_AARCH64_EL2H
+        );
+
+        let all = InitialPstate {
+            ssbs: true,
+            pan: true,
+            dit: true,
+        };
+        assert_eq!(
+            all.spsr(SpsrEl3::M_AARCH64_EL1H, true, true),
+            daif | SpsrEl3::M_AARCH64_EL1H | SpsrEl3::SSBS | SpsrEl3::PAN | SpsrEl3::DIT
+        );
+        // Bits for features which aren't implemented are left clear.
+        assert_eq!(
+            all.spsr(SpsrEl3::M_AARCH64_EL1H, false, false),
+            daif | SpsrEl3::M_AARCH64_EL1H | SpsrEl3::PAN
+        );
+    }
+
+    #[test]
+    fn timer_access_cnthctl() {
+        assert_eq!(TimerAccess::DEFAULT.cnthctl_el2().bits(), 0b11);
+        let no_counter = TimerAccess {
+            el1_physical_counter: false,
+            ..TimerAccess::DEFAULT
+        };
+        assert_eq!(no_counter.cnthctl_el2(), CnthctlEl2::empty());
+    }
+
+    #[test]
+    fn secure_fp_trap_injects_undef() {
+        initialise_per_world_contexts::<TestPlatform>();
+        exception_free(|token| {
+            let el3_state = &mut TestPlatform::cpu_state(token)[World::Secure].el3_state;
+            el3_state.elr_el3 = 0x1000;
+            el3_state.spsr_el3 = SpsrEl3::M_AARCH64_EL1H;
+        });
+        arm_sysregs::fake::SYSREGS.lock().unwrap().vbar_el1 = VbarEl1::from_bits_retain(0x8000);
+
+        handle_feature_trap::<TestPlatform>(
+            World::Secure,
+            EsrEl3::from_bits_retain(u64::from(ExceptionClass::FpTrap.code()) << 26),
+        );
+
+        // The secure world takes an undefined instruction exception at the faulting instruction,
+        // rather than EL3 panicking.
+        let elr_el3 = exception_free(|token| {
+            TestPlatform::cpu_state(token)[World::Secure]
+                .el3_state
+                .elr_el3
+        });
+        assert_eq!(elr_el3, 0x8200);
+        {
+            let sysregs = arm_sysregs::fake::SYSREGS.lock().unwrap();
+            assert_eq!(sysregs.elr_el1, ElrEl1::from_bits_retain(0x1000));
+            assert_eq!(sysregs.esr_el1, EsrEl1::IL);
+        }
+
+        exception_free(|token| {
+            TestPlatform::cpu_state(token)[World::Secure].el3_state = El3State::EMPTY;
+        });
+        arm_sysregs::fake::SYSREGS.lock().unwrap().reset();
+    }
+
+    #[test]
+    fn enter_crash_handler() {
+        let mut cpu_data = CpuData::EMPTY;
+        assert!(!cpu_data.enter_crash_handler());
+        assert!(cpu_data.enter_crash_handler());
+        assert!(cpu_data.enter_crash_handler());
+    }
+
+    #[test]
+    fn exception_nesting() {
+        let mut cpu_data = CpuData::EMPTY;
+        assert_eq!(cpu_data.exception_nesting(), 0);
+        cpu_data.enter_exception_handler();
+        cpu_data.enter_exception_handler();
+        assert_eq!(cpu_data.exception_nesting(), 2);
+        cpu_data.exit_exception_handler();
+        assert_eq!(cpu_data.exception_nesting(), 1);
+        cpu_data.clear_exception_nesting();
+        assert_eq!(cpu_data.exception_nesting(), 0);
+    }
+}
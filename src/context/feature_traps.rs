@@ -0,0 +1,237 @@
+// Copyright The Rusted Firmware-A Contributors.
+//
+// SPDX-License-Identifier: BSD-3-Clause
+
+//! Layout-independent view of the FP/SIMD, SVE and SME trap controls in `CPACR_EL1` and
+//! `CPTR_EL2`.
+//!
+//! `CPTR_EL2` has two layouts: when `HCR_EL2.E2H` is 1 it has `FPEN`, `ZEN` and `SMEN` fields in the
+//! same format as `CPACR_EL1`, but when it is 0 it has single `TFP`, `TZ` and `TSM` trap bits and a
+//! different set of RES1 bits. [`FeatureTraps`] lets context code read and modify the controls
+//! without caring which layout is in use.
+
+use arm_sysregs::{CpacrEl1, CptrEl2};
+
+/// Which accesses to a feature are trapped, in the format of the `FPEN`, `ZEN` and `SMEN` fields.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum TrapControl {
+    /// Accesses from all ELs which the register controls are trapped.
+    TrapAll,
+    /// Only accesses from EL0 are trapped.
+    TrapEl0,
+    /// No accesses are trapped.
+    NoTrap,
+}
+
+impl TrapControl {
+    /// Decodes a 2-bit `FPEN`, `ZEN` or `SMEN` field.
+    const fn from_field(value: u8) -> Self {
+        match value & 0b11 {
+            0b01 => Self::TrapEl0,
+            0b11 => Self::NoTrap,
+            _ => Self::TrapAll,
+        }
+    }
+
+    /// Encodes the control as a 2-bit `FPEN`, `ZEN` or `SMEN` field.
+    const fn field(self) -> u8 {
+        match self {
+            Self::TrapAll => 0b00,
+            Self::TrapEl0 => 0b01,
+            Self::NoTrap => 0b11,
+        }
+    }
+
+    /// Decodes a single trap bit of the `HCR_EL2.E2H == 0` layout of `CPTR_EL2`.
+    const fn from_trap_bit(trapped: bool) -> Self {
+        if trapped { Self::TrapAll } else { Self::NoTrap }
+    }
+
+    /// Encodes the control as a single trap bit, for the `HCR_EL2.E2H == 0` layout of `CPTR_EL2`.
+    ///
+    /// That layout can't limit traps to EL0, so `TrapEl0` traps all accesses.
+    const fn trap_bit(self) -> bool {
+        !matches!(self, Self::NoTrap)
+    }
+}
+
+/// The FP/SIMD, SVE and SME trap controls of `CPACR_EL1` or `CPTR_EL2`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct FeatureTraps {
+    /// FP/SIMD accesses.
+    pub fp: TrapControl,
+    /// SVE accesses.
+    pub sve: TrapControl,
+    /// SME accesses.
+    pub sme: TrapControl,
+}
+
+impl FeatureTraps {
+    /// No accesses are trapped.
+    pub const NONE: Self = Self {
+        fp: TrapControl::NoTrap,
+        sve: TrapControl::NoTrap,
+        sme: TrapControl::NoTrap,
+    };
+
+    /// All accesses are trapped.
+    pub const ALL: Self = Self {
+        fp: TrapControl::TrapAll,
+        sve: TrapControl::TrapAll,
+        sme: TrapControl::TrapAll,
+    };
+
+    /// Bits of `CPTR_EL2` which are RES1 when `HCR_EL2.E2H` is 0, and RES0 otherwise.
+    const CPTR_EL2_NVHE_RES1: CptrEl2 = CptrEl2::RES1;
+
+    /// Reads the trap controls from the given `CPACR_EL1` value.
+    pub const fn from_cpacr_el1(cpacr: CpacrEl1) -> Self {
+        Self {
+            fp: TrapControl::from_field(cpacr.fpen()),
+            sve: TrapControl::from_field(cpacr.zen()),
+            sme: TrapControl::from_field(cpacr.smen()),
+        }
+    }
+
+    /// Returns `cpacr` with its trap controls replaced by these.
+    pub const fn to_cpacr_el1(self, cpacr: CpacrEl1) -> CpacrEl1 {
+        cpacr
+            .with_fpen(self.fp.field())
+            .with_zen(self.sve.field())
+            .with_smen(self.sme.field())
+    }
+
+    /// Reads the trap controls from the given `CPTR_EL2` value, in the layout selected by
+    /// `HCR_EL2.E2H`.
+    pub const fn from_cptr_el2(cptr: CptrEl2, e2h: bool) -> Self {
+        if e2h {
+            Self {
+                fp: TrapControl::from_field(cptr.fpen()),
+                sve: TrapControl::from_field(cptr.zen()),
+                sme: TrapControl::from_field(cptr.smen()),
+            }
+        } else {
+            Self {
+                fp: TrapControl::from_trap_bit(cptr.contains(CptrEl2::TFP)),
+                sve: TrapControl::from_trap_bit(cptr.contains(CptrEl2::TZ)),
+                sme: TrapControl::from_trap_bit(cptr.contains(CptrEl2::TSM)),
+            }
+        }
+    }
+
+    /// Returns `cptr` with its trap controls replaced by these, in the layout selected by
+    /// `HCR_EL2.E2H`.
+    ///
+    /// The RES1 bits of the `HCR_EL2.E2H == 0` layout are set or cleared to match the layout, and
+    /// any fields of the other layout are cleared. `TrapEl0` traps all accesses if `e2h` is false.
+    pub const fn to_cptr_el2(self, cptr: CptrEl2, e2h: bool) -> CptrEl2 {
+        let cptr = cptr
+            .difference(Self::CPTR_EL2_NVHE_RES1)
+            .difference(CptrEl2::TFP.union(CptrEl2::TZ).union(CptrEl2::TSM));
+        if e2h {
+            cptr.with_fpen(self.fp.field())
+                .with_zen(self.sve.field())
+                .with_smen(self.sme.field())
+        } else {
+            let mut cptr = cptr
+                .with_fpen(0)
+                .with_zen(0)
+                .with_smen(0)
+                .union(Self::CPTR_EL2_NVHE_RES1);
+            if self.fp.trap_bit() {
+                cptr = cptr.union(CptrEl2::TFP);
+            }
+            if self.sve.trap_bit() {
+                cptr = cptr.union(CptrEl2::TZ);
+            }
+            if self.sme.trap_bit() {
+                cptr = cptr.union(CptrEl2::TSM);
+            }
+            cptr
+        }
+    }
+
+    /// Converts a `CPTR_EL2` value between layouts, e.g. when `HCR_EL2.E2H` changes.
+    pub const fn convert_cptr_el2(cptr: CptrEl2, from_e2h: bool, to_e2h: bool) -> CptrEl2 {
+        Self::from_cptr_el2(cptr, from_e2h).to_cptr_el2(cptr, to_e2h)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cpacr_el1() {
+        let cpacr = CpacrEl1::TTA.with_fpen(0b11).with_zen(0b01).with_smen(0b10);
+        let traps = FeatureTraps::from_cpacr_el1(cpacr);
+        assert_eq!(
+            traps,
+            FeatureTraps {
+                fp: TrapControl::NoTrap,
+                sve: TrapControl::TrapEl0,
+                sme: TrapControl::TrapAll,
+            }
+        );
+        assert_eq!(
+            FeatureTraps::NONE.to_cpacr_el1(cpacr),
+            CpacrEl1::TTA.with_fpen(0b11).with_zen(0b11).with_smen(0b11)
+        );
+    }
+
+    #[test]
+    fn cptr_el2_nvhe() {
+        assert_eq!(
+            FeatureTraps::NONE.to_cptr_el2(CptrEl2::empty(), false),
+            CptrEl2::RES1
+        );
+        let cptr = FeatureTraps::ALL.to_cptr_el2(CptrEl2::TCPAC, false);
+        assert_eq!(
+            cptr,
+            CptrEl2::RES1 | CptrEl2::TCPAC | CptrEl2::TFP | CptrEl2::TZ | CptrEl2::TSM
+        );
+        assert_eq!(FeatureTraps::from_cptr_el2(cptr, false), FeatureTraps::ALL);
+
+        // Traps can't be limited to EL0.
+        let traps = FeatureTraps {
+            fp: TrapControl::TrapEl0,
+            ..FeatureTraps::NONE
+        };
+        assert_eq!(
+            traps.to_cptr_el2(CptrEl2::empty(), false),
+            CptrEl2::RES1 | CptrEl2::TFP
+        );
+    }
+
+    #[test]
+    fn cptr_el2_vhe() {
+        let traps = FeatureTraps {
+            fp: TrapControl::NoTrap,
+            sve: TrapControl::TrapEl0,
+            sme: TrapControl::TrapAll,
+        };
+        let cptr = traps.to_cptr_el2(CptrEl2::TCPAC, true);
+        assert_eq!(
+            cptr,
+            CptrEl2::TCPAC
+                .with_fpen(0b11)
+                .with_zen(0b01)
+                .with_smen(0b00)
+        );
+        assert_eq!(FeatureTraps::from_cptr_el2(cptr, true), traps);
+    }
+
+    #[test]
+    fn convert_cptr_el2() {
+        let nvhe = CptrEl2::RES1 | CptrEl2::TZ | CptrEl2::TCPAC;
+        let vhe = FeatureTraps::convert_cptr_el2(nvhe, false, true);
+        assert_eq!(
+            vhe,
+            CptrEl2::TCPAC
+                .with_fpen(0b11)
+                .with_zen(0b00)
+                .with_smen(0b11)
+        );
+        assert_eq!(FeatureTraps::convert_cptr_el2(vhe, true, false), nvhe);
+    }
+}
@@ -11,12 +11,20 @@ macro_rules! add_cpu_mod {
     };
 }
 
+pub mod exception_vectors;
+
 add_cpu_mod!(aem_generic);
 add_cpu_mod!(c1_pro);
 add_cpu_mod!(c1_ultra);
+add_cpu_mod!(cortex_a53);
+add_cpu_mod!(cortex_a57);
+add_cpu_mod!(cortex_a72);
+add_cpu_mod!(cortex_a710);
+add_cpu_mod!(neoverse_n2);
 add_cpu_mod!(qemu_max);
 
 use arm_sysregs::MidrEl1;
+use exception_vectors::ExceptionVectors;
 
 /// The `Cpu` trait captures low level CPU specific operations.
 ///
@@ -33,6 +41,12 @@ pub unsafe trait Cpu {
     /// the `Cpu` implementation.
     const MIDR: MidrEl1;
 
+    /// The EL3 exception vectors to use on this CPU, if it needs different entry sequences from
+    /// the standard vectors, e.g. to mitigate Spectre-BHB.
+    ///
+    /// These are installed in VBAR_EL3 before `reset_handler` is called.
+    const EXCEPTION_VECTORS: Option<&'static ExceptionVectors> = None;
+
     /// This function is called on CPU cold boot.
     extern "C" fn reset_handler();
 
@@ -63,6 +77,7 @@ pub struct CpuOps {
     ///
     /// Note that only the bits included in [`Self::MIDR_MASK`] will be compared.
     pub midr: MidrEl1,
+    exception_vectors: Option<&'static ExceptionVectors>,
     reset_handler: extern "C" fn(),
     dump_registers: extern "C" fn(),
     power_down_level0: fn(),
@@ -85,6 +100,7 @@ impl CpuOps {
     pub const fn from_cpu<T: Cpu>() -> Self {
         Self {
             midr: T::MIDR.intersection(Self::MIDR_MASK),
+            exception_vectors: T::EXCEPTION_VECTORS,
             reset_handler: T::reset_handler,
             dump_registers: T::dump_registers,
             power_down_level0: T::power_down_level0,
@@ -211,7 +227,8 @@ pub extern "C" fn cpu_reset_handler<PlatformImpl: PlatformCpuOps>() {
     (ops.reset_handler)()
 }
 
-/// Finds the CPU operations for the current CPU and calls the reset handler for it.
+/// Finds the CPU operations for the current CPU, installs its exception vectors if it has its own
+/// and calls the reset handler for it.
 #[cfg(not(any(test, feature = "fakes")))]
 #[unsafe(naked)]
 pub extern "C" fn cpu_reset_handler<PlatformImpl: PlatformCpuOps>() {
@@ -221,6 +238,13 @@ pub extern "C" fn cpu_reset_handler<PlatformImpl: PlatformCpuOps>() {
         mov	x30, x4
         cbz	x0, 1f
 
+        /* Install the CPU-specific exception vectors, if any */
+        ldr	x1, [x0, #{exception_vectors_offset}]
+        cbz	x1, 2f
+        msr	vbar_el3, x1
+        isb
+
+    2:
         /* Read and jump to reset handler function */
         ldr	x1, [x0, #{reset_handler_offset}]
         br	x1
@@ -230,6 +254,7 @@ pub extern "C" fn cpu_reset_handler<PlatformImpl: PlatformCpuOps>() {
         b	el3_panic
         ",
         get_cpu_ops = sym PlatformImpl::get_cpu_ops,
+        exception_vectors_offset = const core::mem::offset_of!(CpuOps, exception_vectors),
         reset_handler_offset = const core::mem::offset_of!(CpuOps, reset_handler),
     );
 }
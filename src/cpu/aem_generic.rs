@@ -70,7 +70,7 @@ fn read_cache_size(level: CacheLevel) -> CacheSize {
 /// Load-Exclusive and Store-Exclusive Instruction Usage Restrictions'). The only exception is
 /// when the function is restricted to architectures that guarantee predictable behavior for
 /// exclusive accesses.
-unsafe fn flush_cache_levels(levels: RangeInclusive<u8>) {
+pub(super) unsafe fn flush_cache_levels(levels: RangeInclusive<u8>) {
     let clidr_el1 = read_clidr_el1();
 
     for level_num in levels {
@@ -0,0 +1,66 @@
+// Copyright The Rusted Firmware-A Contributors.
+//
+// SPDX-License-Identifier: BSD-3-Clause
+
+//! CPU operations for the Arm Cortex-A53 CPU.
+
+use super::{Cpu, aem_generic::flush_cache_levels};
+use crate::{aarch64::isb, naked_asm};
+use arm_sysregs::{MidrEl1, read_write_sysreg};
+
+/// CPU operations for the Arm Cortex-A53 CPU.
+pub struct CortexA53;
+
+/// SAFETY: `reset_handler` and `dump_registers` are implemented as naked functions and only clobber
+/// x1, x6 and x8.
+unsafe impl Cpu for CortexA53 {
+    const MIDR: MidrEl1 = MidrEl1::from_bits_retain(0x410F_D030);
+
+    #[unsafe(naked)]
+    extern "C" fn reset_handler() {
+        naked_asm!(
+            // Enable coherency with the other cores in the cluster.
+            "mrs x1, s3_1_c15_c2_1",
+            "orr x1, x1, #{CPUECTLR_SMPEN}",
+            "msr s3_1_c15_c2_1, x1",
+            "isb",
+            "ret",
+            CPUECTLR_SMPEN = const CPUECTLR_SMPEN,
+        );
+    }
+
+    #[unsafe(naked)]
+    extern "C" fn dump_registers() {
+        static CORTEX_A53_REGS: [u8; 14] = *b"cpuectlr_el1\0\0";
+
+        naked_asm!(
+            "adr x6, {cortex_a53_regs}",
+            "mrs x8, s3_1_c15_c2_1",
+            "ret",
+            cortex_a53_regs = sym CORTEX_A53_REGS,
+        );
+    }
+
+    /// Flushes the L1 cache and leaves the cluster's coherency domain.
+    fn power_down_level0() {
+        // SAFETY: This isn't called within an exclusive access sequence.
+        unsafe { flush_cache_levels(1..=1) }
+        disable_smp();
+    }
+
+    /// Flushes the L1 and L2 caches and leaves the cluster's coherency domain.
+    fn power_down_level1() {
+        // SAFETY: This isn't called within an exclusive access sequence.
+        unsafe { flush_cache_levels(1..=2) }
+        disable_smp();
+    }
+}
+
+/// Takes the core out of the cluster's coherency domain, so that it can be powered down.
+fn disable_smp() {
+    write_cpuectlr(read_cpuectlr() & !CPUECTLR_SMPEN);
+    isb();
+}
+
+read_write_sysreg!(cpuectlr: s3_1_c15_c2_1, u64, safe_read, safe_write);
+const CPUECTLR_SMPEN: u64 = 1 << 6;
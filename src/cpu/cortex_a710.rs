@@ -0,0 +1,126 @@
+// Copyright The Rusted Firmware-A Contributors.
+//
+// SPDX-License-Identifier: BSD-3-Clause
+
+//! CPU operations and errata workarounds for the Arm Cortex-A710 CPU.
+
+use crate::{
+    aarch64::isb,
+    cpu::Cpu,
+    errata_framework::{
+        Cve, Erratum, ErratumId, ErratumType, RevisionVariant, implement_erratum_check,
+    },
+    naked_asm,
+};
+use arm_sysregs::{MidrEl1, read_write_sysreg};
+
+/// CPU operations for the Arm Cortex-A710 CPU.
+pub struct CortexA710;
+
+/// SAFETY: `reset_handler` and `dump_registers` are implemented as naked functions and only clobber
+/// x1.
+unsafe impl Cpu for CortexA710 {
+    const MIDR: MidrEl1 = MidrEl1::from_bits_retain(0x410F_D470);
+
+    #[unsafe(naked)]
+    extern "C" fn reset_handler() {
+        naked_asm!(
+            // Disable speculative loads by zeroing SSBS.
+            "msr s3_3_c4_c2_6, xzr",
+            "ret"
+        );
+    }
+
+    #[unsafe(naked)]
+    extern "C" fn dump_registers() {
+        static CORTEX_A710_REGS: [u8; 18] = *b"imp_cpuectlr_el1\0\0";
+
+        naked_asm!(
+            "adr x6, {cortex_a710_regs}",
+            "mrs x8, s3_0_c15_c1_4",
+            "ret",
+            cortex_a710_regs = sym CORTEX_A710_REGS,
+        );
+    }
+
+    fn power_down_level0() {
+        let cpupwrctlr = read_cpupwrctlr();
+        write_cpupwrctlr(cpupwrctlr | CORE_PWRDN_ENABLE_BIT_MASK);
+        isb();
+    }
+
+    fn power_down_level1() {
+        Self::power_down_level0();
+    }
+
+    fn handle_power_down_abandon() {
+        let cpupwrctlr = read_cpupwrctlr();
+        write_cpupwrctlr(cpupwrctlr & !CORE_PWRDN_ENABLE_BIT_MASK);
+        isb();
+    }
+}
+
+/// Workaround for CPU erratum 2055002.
+pub struct Erratum2055002;
+
+// SAFETY: `check` and `workaround` are both implemented using naked_asm, don't use the stack or
+// memory, and only clobber x0-x4.
+unsafe impl Erratum for Erratum2055002 {
+    const ID: ErratumId = 2_055_002;
+    const CVE: Cve = 0;
+    const APPLY_ON: ErratumType = ErratumType::Reset;
+
+    #[unsafe(naked)]
+    extern "C" fn check() -> bool {
+        implement_erratum_check!(
+            CortexA710::MIDR,
+            RevisionVariant::new(1, 0),
+            RevisionVariant::new(2, 0)
+        );
+    }
+
+    #[unsafe(naked)]
+    extern "C" fn workaround() {
+        // Set bit 46 in CORTEX_A710_IMP_CPUACTLR_EL1.
+        naked_asm!(
+            "mrs x1, s3_0_c15_c1_0",
+            "orr x1, x1, #(1 << 46)",
+            "msr s3_0_c15_c1_0, x1",
+            "ret",
+        )
+    }
+}
+
+/// Workaround for CPU erratum 2083908.
+pub struct Erratum2083908;
+
+// SAFETY: `check` and `workaround` are both implemented using naked_asm, don't use the stack or
+// memory, and only clobber x0-x4.
+unsafe impl Erratum for Erratum2083908 {
+    const ID: ErratumId = 2_083_908;
+    const CVE: Cve = 0;
+    const APPLY_ON: ErratumType = ErratumType::Reset;
+
+    #[unsafe(naked)]
+    extern "C" fn check() -> bool {
+        implement_erratum_check!(
+            CortexA710::MIDR,
+            RevisionVariant::new(2, 0),
+            RevisionVariant::new(2, 1)
+        );
+    }
+
+    #[unsafe(naked)]
+    extern "C" fn workaround() {
+        // Set bit 13 in CORTEX_A710_IMP_CPUACTLR5_EL1.
+        naked_asm!(
+            "mrs x1, s3_0_c15_c8_0",
+            "orr x1, x1, #(1 << 13)",
+            "msr s3_0_c15_c8_0, x1",
+            "ret",
+        )
+    }
+}
+
+read_write_sysreg!(cpupwrctlr: s3_0_c15_c2_7, u64, safe_read, safe_write);
+const CORE_PWRDN_ENABLE_BIT_MASK: u64 = 0x1;
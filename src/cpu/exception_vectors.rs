@@ -0,0 +1,140 @@
+// Copyright The Rusted Firmware-A Contributors.
+//
+// SPDX-License-Identifier: BSD-3-Clause
+
+//! Alternative EL3 exception vector tables, for CPUs which need extra mitigations on entry from
+//! lower ELs.
+//!
+//! A CPU selects its vectors with [`Cpu::EXCEPTION_VECTORS`](super::Cpu::EXCEPTION_VECTORS), and
+//! they are installed in VBAR_EL3 by `cpu_reset_handler` on both cold and warm boot, so that
+//! different cores in a heterogeneous system may use different vectors.
+
+use core::fmt::{self, Debug, Formatter};
+
+/// An EL3 exception vector table.
+///
+/// These are defined in assembly with [`exception_vectors!`](crate::exception_vectors), and should
+/// only be referred to, never read or written from Rust.
+#[repr(C, align(2048))]
+pub struct ExceptionVectors([u32; 512]);
+
+impl Debug for ExceptionVectors {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "ExceptionVectors({:p})", self)
+    }
+}
+
+/// Defines an EL3 exception vector table which applies a mitigation on every entry from a lower EL,
+/// before branching to the corresponding entry of the standard `runtime_exceptions` vectors.
+/// Exceptions from EL3 itself branch straight to the standard vectors.
+///
+/// The mitigation may be one of:
+///
+/// - `bhb_loop = N`: executes a loop of `N` taken branches to overwrite the branch history, to
+///   mitigate Spectre-BHB (CVE-2022-23960) on CPUs without FEAT_CLRBHB.
+/// - `clrbhb`: executes `CLRBHB` to clear the branch history, on CPUs with FEAT_CLRBHB.
+///
+/// This declares an extern static `ExceptionVectors` with the given name, which can then be used
+/// for `Cpu::EXCEPTION_VECTORS`.
+#[cfg(all(target_arch = "aarch64", not(any(test, feature = "fakes"))))]
+#[macro_export]
+macro_rules! exception_vectors {
+    ($name:ident, bhb_loop = $iterations:expr) => {
+        $crate::exception_vectors!(
+            @table $name,
+            "mov	x0, #{ITERATIONS}
+            1:
+            b	. + 4
+            subs	x0, x0, #1
+            b.ne	1b
+            dsb	sy
+            isb",
+            ITERATIONS = const $iterations,
+        );
+    };
+    ($name:ident, clrbhb) => {
+        $crate::exception_vectors!(
+            @table $name,
+            "hint	#22 /* clrbhb */
+            isb",
+        );
+    };
+    (@table $name:ident, $mitigation:literal, $($operands:tt)*) => {
+        unsafe extern "C" {
+            static $name: $crate::cpu::exception_vectors::ExceptionVectors;
+        }
+
+        core::arch::global_asm!(
+            ".pushsection .vectors, \"ax\"",
+            ".align 11, 0",
+            ".global {vectors}",
+            "{vectors}:",
+            // Current EL with SP_EL0 and SP_ELx.
+            ".align 7, 0",
+            "b	sync_exception_sp_el0",
+            ".align 7, 0",
+            "b	irq_sp_el0",
+            ".align 7, 0",
+            "b	fiq_sp_el0",
+            ".align 7, 0",
+            "b	serror_sp_el0",
+            ".align 7, 0",
+            "b	sync_exception_sp_elx",
+            ".align 7, 0",
+            "b	irq_sp_elx",
+            ".align 7, 0",
+            "b	fiq_sp_elx",
+            ".align 7, 0",
+            "b	serror_sp_elx",
+            // Lower EL using AArch64. SP_EL3 points to the CpuContext of the world we came from, so
+            // x0 and x1 can be saved there while the mitigation runs.
+            ".align 7, 0",
+            "stp	x0, x1, [sp, #{CTX_GPREG_X0}]",
+            $mitigation,
+            "ldp	x0, x1, [sp, #{CTX_GPREG_X0}]",
+            "b	sync_exception_aarch64",
+            ".align 7, 0",
+            "stp	x0, x1, [sp, #{CTX_GPREG_X0}]",
+            $mitigation,
+            "ldp	x0, x1, [sp, #{CTX_GPREG_X0}]",
+            "b	irq_aarch64",
+            ".align 7, 0",
+            "stp	x0, x1, [sp, #{CTX_GPREG_X0}]",
+            $mitigation,
+            "ldp	x0, x1, [sp, #{CTX_GPREG_X0}]",
+            "b	fiq_aarch64",
+            ".align 7, 0",
+            "stp	x0, x1, [sp, #{CTX_GPREG_X0}]",
+            $mitigation,
+            "ldp	x0, x1, [sp, #{CTX_GPREG_X0}]",
+            "b	serror_aarch64",
+            // Lower EL using AArch32.
+            ".align 7, 0",
+            "stp	x0, x1, [sp, #{CTX_GPREG_X0}]",
+            $mitigation,
+            "ldp	x0, x1, [sp, #{CTX_GPREG_X0}]",
+            "b	sync_exception_aarch32",
+            ".align 7, 0",
+            "stp	x0, x1, [sp, #{CTX_GPREG_X0}]",
+            $mitigation,
+            "ldp	x0, x1, [sp, #{CTX_GPREG_X0}]",
+            "b	irq_aarch32",
+            ".align 7, 0",
+            "stp	x0, x1, [sp, #{CTX_GPREG_X0}]",
+            $mitigation,
+            "ldp	x0, x1, [sp, #{CTX_GPREG_X0}]",
+            "b	fiq_aarch32",
+            ".align 7, 0",
+            "stp	x0, x1, [sp, #{CTX_GPREG_X0}]",
+            $mitigation,
+            "ldp	x0, x1, [sp, #{CTX_GPREG_X0}]",
+            "b	serror_aarch32",
+            ".fill {vectors} + 0x800 - .",
+            ".popsection",
+            vectors = sym $name,
+            CTX_GPREG_X0 = const core::mem::offset_of!($crate::context::CpuContext, gpregs)
+                + core::mem::offset_of!($crate::context::GpRegs, registers),
+            $($operands)*
+        );
+    };
+}
@@ -4,26 +4,132 @@
 
 //! A framework for managing ARM architectural CPU extensions using a trait-based approach.
 
+/// Like `arm_sysregs::read_write_sysreg!` or `arm_sysregs::read_sysreg!`, for a register which is
+/// only implemented along with some optional feature.
+///
+/// The first argument is a function returning whether the feature is present, such as
+/// `requires(is_feat_scxtnum_present)`. It may be followed by the lowest exception level which can
+/// access the register, such as `min_el(2)`. In debug builds the generated accessors assert that
+/// the feature is present and that the current exception level is high enough, to catch accesses
+/// which would trap or be undefined. The assertions are skipped with fake registers, as the fake ID
+/// registers advertise no features.
+///
+/// Registers whose name the assembler may not know are given as `name: encoding`, as with
+/// `arm_sysregs`, and the accessors are named after `name`.
+macro_rules! read_write_optional_sysreg {
+    (
+        requires($feature_present:path) $(, min_el($min_el:literal))?
+        $sysreg:ident $(: $asm_sysreg:ident)?, $type:ident $(: $bitflags_type:ty)?,
+        safe_read, safe_write, $fake_sysregs:expr
+    ) => {
+        paste::paste! {
+            mod [< unchecked_ $sysreg >] {
+                #[cfg(any(test, feature = "fakes"))]
+                use super::*;
+
+                arm_sysregs::read_write_sysreg!(
+                    $sysreg $(: $asm_sysreg)?, $type $(: $bitflags_type)?, safe_read, safe_write,
+                    $fake_sysregs
+                );
+            }
+
+            #[doc = concat!("Returns the value of the `", stringify!($sysreg), "` system register.")]
+            pub fn [< read_ $sysreg >]() -> optional_sysreg_type!($type $(: $bitflags_type)?) {
+                check_optional_sysreg_access!("Read", $sysreg, $feature_present $(, $min_el)?);
+                [< unchecked_ $sysreg >]::[< read_ $sysreg >]()
+            }
+
+            #[doc = concat!("Writes `value` to the `", stringify!($sysreg), "` system register.")]
+            pub fn [< write_ $sysreg >](value: optional_sysreg_type!($type $(: $bitflags_type)?)) {
+                check_optional_sysreg_access!("Wrote", $sysreg, $feature_present $(, $min_el)?);
+                [< unchecked_ $sysreg >]::[< write_ $sysreg >](value)
+            }
+        }
+    };
+    (
+        requires($feature_present:path) $(, min_el($min_el:literal))?
+        $sysreg:ident $(: $asm_sysreg:ident)?, $type:ident $(: $bitflags_type:ty)?,
+        safe, $fake_sysregs:expr
+    ) => {
+        paste::paste! {
+            mod [< unchecked_ $sysreg >] {
+                #[cfg(any(test, feature = "fakes"))]
+                use super::*;
+
+                arm_sysregs::read_sysreg!(
+                    $sysreg $(: $asm_sysreg)?, $type $(: $bitflags_type)?, safe, $fake_sysregs
+                );
+            }
+
+            #[doc = concat!("Returns the value of the `", stringify!($sysreg), "` system register.")]
+            pub fn [< read_ $sysreg >]() -> optional_sysreg_type!($type $(: $bitflags_type)?) {
+                check_optional_sysreg_access!("Read", $sysreg, $feature_present $(, $min_el)?);
+                [< unchecked_ $sysreg >]::[< read_ $sysreg >]()
+            }
+        }
+    };
+}
+
+/// Asserts in debug builds that an optional system register may be accessed, for the accessors
+/// generated by `read_write_optional_sysreg!`.
+macro_rules! check_optional_sysreg_access {
+    ($access:literal, $sysreg:ident, $feature_present:path $(, $min_el:literal)?) => {
+        #[cfg(not(any(test, feature = "fakes")))]
+        {
+            debug_assert!(
+                $feature_present(),
+                concat!($access, " ", stringify!($sysreg), " without the required feature")
+            );
+            $(
+                debug_assert!(
+                    arm_sysregs::read_currentel().el() >= $min_el,
+                    concat!($access, " ", stringify!($sysreg), " below EL", $min_el)
+                );
+            )?
+        }
+    };
+}
+
+/// Returns the type of the value of a register declared with `read_write_optional_sysreg!`.
+macro_rules! optional_sysreg_type {
+    ($type:ident) => {
+        $type
+    };
+    ($type:ident : $bitflags_type:ty) => {
+        $bitflags_type
+    };
+}
+
 pub mod amu;
+pub mod brbe;
+pub mod bti;
 pub mod fgt;
 pub mod fgt2;
 pub mod hcx;
+pub mod id_registers;
 pub mod mpam;
+pub mod mpmm;
 pub mod mte2;
+pub mod os_lock;
 #[cfg(feature = "pauth")]
 pub mod pauth;
 pub mod pmuv3;
 pub mod ras;
 pub mod sctlr2;
+pub mod scxt;
 pub mod simd;
 pub mod spe;
+#[cfg(all(target_arch = "aarch64", not(feature = "sel2")))]
+pub mod sve_sme;
 pub mod sys_reg_trace;
 pub mod tcr2;
 pub mod trbe;
 pub mod trf;
 
+use self::id_registers::IdFeatures;
 use crate::{
     context::{CpuContext, PerWorldContext, World},
+    exceptions::ExceptionClass,
     platform::Platform,
 };
 
@@ -70,6 +176,24 @@ pub trait CpuExtension: Sync {
     /// resume from suspend to powerdown, it should implement this function and override the
     /// default.
     fn restore_context_after_suspend_to_powerdown(&self) {}
+
+    /// Handles an access from the given world which was trapped by `CPTR_EL3`, with the given
+    /// exception class.
+    ///
+    /// Returns true if the trap was handled, in which case the lower EL is entered again to retry
+    /// the instruction. The default implementation handles nothing.
+    fn handle_feature_trap(&self, _world: World, _class: ExceptionClass) -> bool {
+        false
+    }
+
+    /// Returns the features advertised in the ID registers which this extension makes available to
+    /// the given world.
+    ///
+    /// If ID register reads from the world are trapped, any feature which no extension makes
+    /// available is hidden from it.
+    fn id_features(&self, _world: World) -> IdFeatures {
+        IdFeatures::empty()
+    }
 }
 
 /// Enable architecture extensions for EL3 execution. This function only updates
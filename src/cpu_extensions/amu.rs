@@ -4,22 +4,30 @@
 
 //! Activity Monitor Unit (AMU) extension support.
 
+pub mod registers;
+
+use self::registers::{
+    AMU_GROUP_COUNTER_COUNT, group1_virtual_offsets, is_feat_amuv1p1_present,
+    read_amevcntvoff0_el2, read_amevcntvoff1_el2, write_amevcntvoff0_el2, write_amevcntvoff1_el2,
+};
+use super::id_registers::IdFeatures;
 use crate::{
-    context::PerCoreState,
+    context::{PerCoreState, PerWorldContext, World},
     cpu_extensions::CpuExtension,
     platform::{Platform, exception_free},
 };
 use arm_sysregs::{
-    AmcrEl0, Amevcntr00El0, Amevcntr01El0, Amevcntr02El0, Amevcntr03El0, Amevcntr10El0,
-    Amevcntr11El0, Amevcntr12El0, Amevcntr13El0, Amevcntr14El0, Amevcntr15El0, Amevcntr16El0,
-    Amevcntr17El0, Amevcntr18El0, Amevcntr19El0, Amevcntr110El0, Amevcntr111El0, Amevcntr112El0,
-    Amevcntr113El0, Amevcntr114El0, Amevcntr115El0, AmuserenrEl0, read_amcgcr_el0, read_amcr_el0,
-    read_amevcntr00_el0, read_amevcntr01_el0, read_amevcntr02_el0, read_amevcntr03_el0,
-    read_amevcntr10_el0, read_amevcntr11_el0, read_amevcntr12_el0, read_amevcntr13_el0,
-    read_amevcntr14_el0, read_amevcntr15_el0, read_amevcntr16_el0, read_amevcntr17_el0,
-    read_amevcntr18_el0, read_amevcntr19_el0, read_amevcntr110_el0, read_amevcntr111_el0,
-    read_amevcntr112_el0, read_amevcntr113_el0, read_amevcntr114_el0, read_amevcntr115_el0,
-    read_amuserenr_el0, read_id_aa64pfr0_el1, write_amcr_el0, write_amevcntr00_el0,
+    Amcntenset0El0, Amcntenset1El0, AmcrEl0, Amevcntr00El0, Amevcntr01El0, Amevcntr02El0,
+    Amevcntr03El0, Amevcntr10El0, Amevcntr11El0, Amevcntr12El0, Amevcntr13El0, Amevcntr14El0,
+    Amevcntr15El0, Amevcntr16El0, Amevcntr17El0, Amevcntr18El0, Amevcntr19El0, Amevcntr110El0,
+    Amevcntr111El0, Amevcntr112El0, Amevcntr113El0, Amevcntr114El0, Amevcntr115El0, AmuserenrEl0,
+    CptrEl3, ScrEl3, read_amcgcr_el0, read_amcr_el0, read_amevcntr00_el0, read_amevcntr01_el0,
+    read_amevcntr02_el0, read_amevcntr03_el0, read_amevcntr10_el0, read_amevcntr11_el0,
+    read_amevcntr12_el0, read_amevcntr13_el0, read_amevcntr14_el0, read_amevcntr15_el0,
+    read_amevcntr16_el0, read_amevcntr17_el0, read_amevcntr18_el0, read_amevcntr19_el0,
+    read_amevcntr110_el0, read_amevcntr111_el0, read_amevcntr112_el0, read_amevcntr113_el0,
+    read_amevcntr114_el0, read_amevcntr115_el0, read_amuserenr_el0, read_id_aa64pfr0_el1,
+    write_amcntenset0_el0, write_amcntenset1_el0, write_amcr_el0, write_amevcntr00_el0,
     write_amevcntr01_el0, write_amevcntr02_el0, write_amevcntr03_el0, write_amevcntr10_el0,
     write_amevcntr11_el0, write_amevcntr12_el0, write_amevcntr13_el0, write_amevcntr14_el0,
     write_amevcntr15_el0, write_amevcntr16_el0, write_amevcntr17_el0, write_amevcntr18_el0,
@@ -29,7 +37,7 @@ use arm_sysregs::{
 use core::cell::RefCell;
 use percore::{ExceptionLock, PerCore};
 
-#[derive(Clone, Copy, Default)]
+#[derive(Clone, Copy)]
 struct AmuContext {
     amcr_el0: AmcrEl0,
     amuserenr_el0: AmuserenrEl0,
@@ -53,6 +61,19 @@ struct AmuContext {
     amevcntr113_el0: Amevcntr113El0,
     amevcntr114_el0: Amevcntr114El0,
     amevcntr115_el0: Amevcntr115El0,
+    amevcntvoff0_el2: [u64; AMU_GROUP_COUNTER_COUNT],
+    amevcntvoff1_el2: [u64; AMU_GROUP_COUNTER_COUNT],
+}
+
+/// The group 0 counters which have a virtual offset register. Counter 1 counts at the constant
+/// frequency of the generic timer, so is offset by `CNTVOFF_EL2` instead.
+const GROUP0_VIRTUAL_OFFSETS: [usize; 3] = [0, 2, 3];
+
+/// Returns the indices of the first `n_group1` group 1 counters which have a virtual offset
+/// register.
+fn group1_virtual_offset_counters(n_group1: u8) -> impl Iterator<Item = usize> {
+    let offsets = group1_virtual_offsets();
+    (0..usize::from(n_group1).min(AMU_GROUP_COUNTER_COUNT)).filter(move |n| offsets & (1 << n) != 0)
 }
 
 impl AmuContext {
@@ -79,9 +100,11 @@ impl AmuContext {
         amevcntr113_el0: Amevcntr113El0::empty(),
         amevcntr114_el0: Amevcntr114El0::empty(),
         amevcntr115_el0: Amevcntr115El0::empty(),
+        amevcntvoff0_el2: [0; AMU_GROUP_COUNTER_COUNT],
+        amevcntvoff1_el2: [0; AMU_GROUP_COUNTER_COUNT],
     };
 
-    fn save(&mut self) {
+    fn save(&mut self, has_virtual_offsets: bool) {
         self.amcr_el0 = read_amcr_el0();
         self.amuserenr_el0 = read_amuserenr_el0();
 
@@ -140,9 +163,18 @@ impl AmuContext {
         if n_group1 > 15 {
             self.amevcntr115_el0 = read_amevcntr115_el0();
         }
+
+        if has_virtual_offsets {
+            for n in GROUP0_VIRTUAL_OFFSETS {
+                self.amevcntvoff0_el2[n] = read_amevcntvoff0_el2(n);
+            }
+            for n in group1_virtual_offset_counters(n_group1) {
+                self.amevcntvoff1_el2[n] = read_amevcntvoff1_el2(n);
+            }
+        }
     }
 
-    fn restore(&self) {
+    fn restore(&self, has_virtual_offsets: bool) {
         write_amcr_el0(AmcrEl0::empty());
 
         let n_group1 = read_amcgcr_el0().cg1nc();
@@ -201,25 +233,58 @@ impl AmuContext {
             write_amevcntr115_el0(self.amevcntr115_el0);
         }
 
+        if has_virtual_offsets {
+            for n in GROUP0_VIRTUAL_OFFSETS {
+                write_amevcntvoff0_el2(n, self.amevcntvoff0_el2[n]);
+            }
+            for n in group1_virtual_offset_counters(n_group1) {
+                write_amevcntvoff1_el2(n, self.amevcntvoff1_el2[n]);
+            }
+        }
+
         write_amuserenr_el0(self.amuserenr_el0);
         write_amcr_el0(self.amcr_el0);
     }
 }
 
 /// Activity Monitor Unit (AMU) extension support.
+///
+/// Gives the Non-secure world access to the activity monitors (FEAT_AMUv1), e.g. for
+/// frequency-invariant scheduling, while they stay trapped for the other worlds. The architected
+/// group 0 counters are always enabled, and the platform chooses which of its auxiliary group 1
+/// counters to enable.
+///
+/// With FEAT_AMUv1p1, Non-secure EL2 can also use the virtual offset registers to adjust the
+/// counter values seen by its guests. Only the Non-secure world can access the AMU, so the counters
+/// and offsets don't need to be switched between worlds, but they are saved across a suspend to
+/// powerdown.
 pub struct Amu<const CORE_COUNT: usize, PlatformImpl: Platform> {
     context: PerCoreState<CORE_COUNT, PlatformImpl, AmuContext>,
+    group1_counters: u16,
 }
 
 impl<const CORE_COUNT: usize, PlatformImpl: Platform> Amu<CORE_COUNT, PlatformImpl> {
-    /// Constructs a new instance of the AMU CPU extension.
+    /// Constructs a new instance of the AMU CPU extension, with none of the group 1 counters
+    /// enabled.
     pub const fn new() -> Self {
+        Self::with_group1_counters(0)
+    }
+
+    /// Constructs a new instance of the AMU CPU extension, enabling the group 1 counters in the
+    /// given mask, where bit `n` is for `AMEVCNTR1<n>_EL0`. Bits for counters which the CPU doesn't
+    /// implement are ignored.
+    pub const fn with_group1_counters(group1_counters: u16) -> Self {
         Self {
             context: PerCore::new(
                 [const { ExceptionLock::new(RefCell::new(AmuContext::EMPTY)) }; CORE_COUNT],
             ),
+            group1_counters,
         }
     }
+
+    fn has_virtual_offsets(&self) -> bool {
+        is_feat_amuv1p1_present()
+    }
 }
 
 impl<const CORE_COUNT: usize, PlatformImpl: Platform> Default for Amu<CORE_COUNT, PlatformImpl> {
@@ -235,6 +300,34 @@ impl<const CORE_COUNT: usize, PlatformImpl: Platform> CpuExtension
         read_id_aa64pfr0_el1().is_feat_amuv1_present()
     }
 
+    fn init(&self) {
+        let amcgcr = read_amcgcr_el0();
+        write_amcntenset0_el0(Amcntenset0El0::from_bits_retain((1 << amcgcr.cg0nc()) - 1));
+        let implemented_group1 = (1u64 << amcgcr.cg1nc()) - 1;
+        write_amcntenset1_el0(Amcntenset1El0::from_bits_retain(
+            u64::from(self.group1_counters) & implemented_group1,
+        ));
+    }
+
+    fn configure_per_world(&self, world: World, context: &mut PerWorldContext) {
+        if world == World::NonSecure {
+            // CPTR_EL3.TAM: Don't trap AMU register accesses from the Non-secure world.
+            context.cptr_el3 -= CptrEl3::TAM;
+            // SCR_EL3.AMVOFFEN: Apply the virtual offsets for Non-secure EL1 and EL0.
+            if self.has_virtual_offsets() {
+                context.scr_el3 |= ScrEl3::AMVOFFEN;
+            }
+        }
+    }
+
+    fn id_features(&self, world: World) -> IdFeatures {
+        if world == World::NonSecure {
+            IdFeatures::AMU
+        } else {
+            IdFeatures::empty()
+        }
+    }
+
     fn save_context_before_suspend_to_powerdown(&self) {
         if !self.is_present() {
             return;
@@ -242,7 +335,7 @@ impl<const CORE_COUNT: usize, PlatformImpl: Platform> CpuExtension
 
         exception_free(|token| {
             let mut ctx = self.context.get().borrow_mut(token);
-            ctx.save();
+            ctx.save(self.has_virtual_offsets());
         });
     }
 
@@ -253,7 +346,57 @@ impl<const CORE_COUNT: usize, PlatformImpl: Platform> CpuExtension
 
         exception_free(|token| {
             let ctx = self.context.get().borrow_mut(token);
-            ctx.restore();
+            ctx.restore(self.has_virtual_offsets());
         });
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::platform::test::TestPlatform;
+    use arm_sysregs::{AmcgcrEl0, fake::SYSREGS};
+    use registers::FAKE_AMU_REGISTERS;
+
+    #[test]
+    fn enable_counters() {
+        let amu = Amu::<1, TestPlatform>::with_group1_counters(0b1010_0101);
+        SYSREGS.lock().unwrap().amcgcr_el0 = AmcgcrEl0::empty().with_cg0nc(4).with_cg1nc(6);
+        amu.init();
+        let sysregs = SYSREGS.lock().unwrap();
+        assert_eq!(sysregs.amcntenset0_el0.bits(), 0b1111);
+        // Only the implemented group 1 counters are enabled.
+        assert_eq!(sysregs.amcntenset1_el0.bits(), 0b10_0101);
+    }
+
+    #[test]
+    fn non_secure_only() {
+        let amu = Amu::<1, TestPlatform>::new();
+        let mut context = PerWorldContext {
+            cptr_el3: CptrEl3::TAM,
+            ..Default::default()
+        };
+        amu.configure_per_world(World::NonSecure, &mut context);
+        assert!(!context.cptr_el3.contains(CptrEl3::TAM));
+
+        let mut context = PerWorldContext {
+            cptr_el3: CptrEl3::TAM,
+            ..Default::default()
+        };
+        amu.configure_per_world(World::Secure, &mut context);
+        assert!(context.cptr_el3.contains(CptrEl3::TAM));
+        assert!(!context.scr_el3.contains(ScrEl3::AMVOFFEN));
+
+        assert_eq!(amu.id_features(World::NonSecure), IdFeatures::AMU);
+        assert_eq!(amu.id_features(World::Secure), IdFeatures::empty());
+    }
+
+    #[test]
+    fn group1_offsets() {
+        FAKE_AMU_REGISTERS.lock().unwrap().amcg1idr_el0 = 0b1001_0110 << 16 | 0xffff;
+        assert_eq!(
+            group1_virtual_offset_counters(5).collect::<Vec<_>>(),
+            [1, 2, 4]
+        );
+    }
+}
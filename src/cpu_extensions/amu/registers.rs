@@ -0,0 +1,148 @@
+// Copyright The Rusted Firmware-A Contributors.
+//
+// SPDX-License-Identifier: BSD-3-Clause
+
+//! Accessors for the AMU registers which `arm_sysregs` doesn't provide.
+//!
+//! The virtual offset registers `AMEVCNTVOFF0<n>_EL2` and `AMEVCNTVOFF1<n>_EL2` are accessed by
+//! index, and by their encodings as not all assemblers know their names.
+
+use arm_sysregs::read_id_aa64pfr0_el1;
+#[cfg(any(test, feature = "fakes"))]
+use std::sync::Mutex;
+
+/// The maximum number of counters in group 0 or group 1.
+pub const AMU_GROUP_COUNTER_COUNT: usize = 16;
+
+/// Fake AMU registers for unit tests.
+#[cfg(any(test, feature = "fakes"))]
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct FakeAmuRegisters {
+    /// Fake value of `AMCG1IDR_EL0`.
+    pub amcg1idr_el0: u64,
+    /// Fake values of `AMEVCNTVOFF0<n>_EL2`.
+    pub amevcntvoff0_el2: [u64; AMU_GROUP_COUNTER_COUNT],
+    /// Fake values of `AMEVCNTVOFF1<n>_EL2`.
+    pub amevcntvoff1_el2: [u64; AMU_GROUP_COUNTER_COUNT],
+}
+
+#[cfg(any(test, feature = "fakes"))]
+impl FakeAmuRegisters {
+    const RESET: Self = Self {
+        amcg1idr_el0: 0,
+        amevcntvoff0_el2: [0; AMU_GROUP_COUNTER_COUNT],
+        amevcntvoff1_el2: [0; AMU_GROUP_COUNTER_COUNT],
+    };
+
+    /// Resets all the fake registers to 0.
+    pub fn reset(&mut self) {
+        *self = Self::RESET;
+    }
+}
+
+/// The fake AMU registers used in place of the real ones in unit tests.
+#[cfg(any(test, feature = "fakes"))]
+pub static FAKE_AMU_REGISTERS: Mutex<FakeAmuRegisters> = Mutex::new(FakeAmuRegisters::RESET);
+
+read_write_optional_sysreg!(
+    requires(is_feat_amuv1p1_present)
+    amcg1idr_el0: s3_3_c13_c2_6, u64, safe, FAKE_AMU_REGISTERS
+);
+
+/// Returns whether FEAT_AMUv1p1 is implemented, which adds the virtual offset registers.
+pub fn is_feat_amuv1p1_present() -> bool {
+    read_id_aa64pfr0_el1().is_feat_amuv1p1_present()
+}
+
+/// Returns the mask of group 1 counters which implement a virtual offset, from the `AMEVCNTVOFF1`
+/// field of `AMCG1IDR_EL0`.
+pub fn group1_virtual_offsets() -> u16 {
+    (read_amcg1idr_el0() >> 16) as u16
+}
+
+/// Generates `read_$sysreg(n)` and `write_$sysreg(n, value)` for the indexed registers
+/// `$name<n>_EL2`, with a `match` arm for each of the given indices and encodings.
+macro_rules! indexed_amu_sysreg {
+    ($sysreg:ident, $name:literal, [$($n:literal => $encoding:literal),*]) => {
+        paste::paste! {
+            #[doc = concat!("Returns the value of the `", $name, "<n>_EL2` system register.")]
+            ///
+            /// # Panics
+            ///
+            /// Panics if there is no such register for `n`.
+            #[cfg(all(target_arch = "aarch64", not(any(test, feature = "fakes"))))]
+            pub fn [< read_ $sysreg >](n: usize) -> u64 {
+                let value;
+                match n {
+                    $(
+                        // SAFETY: Reading a virtual offset register has no side effects.
+                        $n => unsafe {
+                            core::arch::asm!(
+                                concat!("mrs {value}, ", $encoding),
+                                options(nomem, nostack, preserves_flags),
+                                value = out(reg) value,
+                            );
+                        },
+                    )*
+                    _ => panic!("Invalid AMU counter {n}"),
+                }
+                value
+            }
+
+            #[doc = concat!("Writes `value` to the `", $name, "<n>_EL2` system register.")]
+            ///
+            /// # Panics
+            ///
+            /// Panics if there is no such register for `n`.
+            #[cfg(all(target_arch = "aarch64", not(any(test, feature = "fakes"))))]
+            pub fn [< write_ $sysreg >](n: usize, value: u64) {
+                match n {
+                    $(
+                        // SAFETY: Writing a virtual offset register only affects the counter
+                        // values seen by EL1 and EL0, not memory safety.
+                        $n => unsafe {
+                            core::arch::asm!(
+                                concat!("msr ", $encoding, ", {value}"),
+                                options(nostack, preserves_flags),
+                                value = in(reg) value,
+                            );
+                        },
+                    )*
+                    _ => panic!("Invalid AMU counter {n}"),
+                }
+            }
+
+            #[doc = concat!("Returns the value of the fake `", $name, "<n>_EL2` system register.")]
+            #[cfg(any(test, feature = "fakes"))]
+            pub fn [< read_ $sysreg >](n: usize) -> u64 {
+                FAKE_AMU_REGISTERS.lock().unwrap().$sysreg[n]
+            }
+
+            #[doc = concat!("Writes `value` to the fake `", $name, "<n>_EL2` system register.")]
+            #[cfg(any(test, feature = "fakes"))]
+            pub fn [< write_ $sysreg >](n: usize, value: u64) {
+                FAKE_AMU_REGISTERS.lock().unwrap().$sysreg[n] = value;
+            }
+        }
+    };
+}
+
+// Counter 1 of group 0 counts at the constant frequency of the generic timer, so is offset by
+// CNTVOFF_EL2 rather than having its own virtual offset register.
+indexed_amu_sysreg!(
+    amevcntvoff0_el2,
+    "AMEVCNTVOFF0",
+    [0 => "s3_4_c13_c8_0", 2 => "s3_4_c13_c8_2", 3 => "s3_4_c13_c8_3"]
+);
+indexed_amu_sysreg!(
+    amevcntvoff1_el2,
+    "AMEVCNTVOFF1",
+    [
+        0 => "s3_4_c13_c10_0", 1 => "s3_4_c13_c10_1", 2 => "s3_4_c13_c10_2",
+        3 => "s3_4_c13_c10_3", 4 => "s3_4_c13_c10_4", 5 => "s3_4_c13_c10_5",
+        6 => "s3_4_c13_c10_6", 7 => "s3_4_c13_c10_7", 8 => "s3_4_c13_c11_0",
+        9 => "s3_4_c13_c11_1", 10 => "s3_4_c13_c11_2", 11 => "s3_4_c13_c11_3",
+        12 => "s3_4_c13_c11_4", 13 => "s3_4_c13_c11_5", 14 => "s3_4_c13_c11_6",
+        15 => "s3_4_c13_c11_7"
+    ]
+);
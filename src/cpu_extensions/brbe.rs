@@ -0,0 +1,66 @@
+// Copyright The Rusted Firmware-A Contributors.
+//
+// SPDX-License-Identifier: BSD-3-Clause
+
+//! Branch Record Buffer Extension
+
+use super::{CpuExtension, id_registers::IdFeatures};
+use crate::context::{CpuContext, World};
+use arm_sysregs::read_id_aa64dfr0_el1;
+
+/// `MDCR_EL3.SBRBE` value which allows branch recording and access to the BRBE registers in
+/// Non-secure state, but prohibits recording in Secure state and traps Secure accesses to EL3.
+const SBRBE_NON_SECURE_ONLY: u8 = 0b01;
+
+/// Branch Record Buffer Extension
+///
+/// Configures the Branch Record Buffer Extension (FEAT_BRBE) so that branches can be recorded in
+/// the Non-secure world only, and BRBE register accesses from the Secure world trap to EL3.
+///
+/// FEAT_BRBE records the most recent taken branches in a buffer of system registers, which the
+/// Non-secure world can use for profiling and debugging. There are no BRBE controls at EL3 other
+/// than `MDCR_EL3.SBRBE`, and the buffer itself belongs to the Non-secure world, so nothing needs
+/// to be saved or restored on world switches.
+pub struct Brbe;
+
+impl CpuExtension for Brbe {
+    fn is_present(&self) -> bool {
+        read_id_aa64dfr0_el1().is_feat_brbe_present()
+    }
+
+    fn id_features(&self, world: World) -> IdFeatures {
+        if world == World::NonSecure {
+            IdFeatures::BRBE
+        } else {
+            IdFeatures::empty()
+        }
+    }
+
+    fn configure_per_cpu(&self, world: World, context: &mut CpuContext) {
+        if world == World::NonSecure {
+            // MDCR_EL3.SBRBE: Allow BRBE to be used in Non-secure state, and prohibit it in
+            // Secure state. The other worlds keep the default of 0b00, which also traps their
+            // accesses to the BRBE registers.
+            context.el3_state.mdcr_el3.set_sbrbe(SBRBE_NON_SECURE_ONLY);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn non_secure_only() {
+        let mut context = CpuContext::EMPTY;
+        Brbe.configure_per_cpu(World::NonSecure, &mut context);
+        assert_eq!(context.el3_state.mdcr_el3.sbrbe(), SBRBE_NON_SECURE_ONLY);
+
+        let mut context = CpuContext::EMPTY;
+        Brbe.configure_per_cpu(World::Secure, &mut context);
+        assert_eq!(context.el3_state.mdcr_el3.sbrbe(), 0);
+
+        assert_eq!(Brbe.id_features(World::NonSecure), IdFeatures::BRBE);
+        assert_eq!(Brbe.id_features(World::Secure), IdFeatures::empty());
+    }
+}
@@ -0,0 +1,49 @@
+// Copyright The Rusted Firmware-A Contributors.
+//
+// SPDX-License-Identifier: BSD-3-Clause
+
+//! Branch Target Identification extension (FEAT_BTI)
+//!
+//! When RF-A is built with `BTI_EL3=1`, the compiler places `bti` landing pads at the targets of
+//! indirect branches, the `naked_asm!` macro adds one to each naked function, and code regions are
+//! mapped as guarded pages by `pagetable::MT_CODE_EL3`. Indirect branches to anything else in a
+//! guarded page only fault once `SCTLR_EL3.BT` is set, which is done here on each core after the
+//! MMU is enabled. The guarded page attribute is ignored on cores without FEAT_BTI, so BTI is only
+//! enabled if the core implements it.
+
+use crate::aarch64::isb;
+use arm_sysregs::{SctlrEl3, read_id_aa64pfr1_el1, read_sctlr_el3, write_sctlr_el3};
+
+/// Indicates whether FEAT_BTI is implemented.
+pub fn is_feat_bti_present() -> bool {
+    read_id_aa64pfr1_el1().bt() != 0
+}
+
+/// Enables Branch Target Identification at EL3, if RF-A was built with BTI landing pads and the
+/// current core implements FEAT_BTI.
+pub fn init() {
+    if cfg!(bti) && is_feat_bti_present() {
+        // SAFETY: RF-A was built with `bti` landing pads at all indirect branch targets, so
+        // enabling BTI won't cause any valid branch in EL3 to fault.
+        unsafe {
+            write_sctlr_el3(read_sctlr_el3() | SctlrEl3::BT);
+        }
+        isb();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arm_sysregs::{IdAa64pfr1El1, fake::SYSREGS};
+
+    #[test]
+    fn detect_bti() {
+        SYSREGS.lock().unwrap().id_aa64pfr1_el1 = IdAa64pfr1El1::empty();
+        assert!(!is_feat_bti_present());
+
+        SYSREGS.lock().unwrap().id_aa64pfr1_el1 = IdAa64pfr1El1::empty().with_bt(0b0001);
+        assert!(is_feat_bti_present());
+        SYSREGS.lock().unwrap().reset();
+    }
+}
@@ -0,0 +1,250 @@
+// Copyright The Rusted Firmware-A Contributors.
+//
+// SPDX-License-Identifier: BSD-3-Clause
+
+//! Emulation of ID group 3 registers, to hide features from lower ELs.
+//!
+//! When `RuntimeConfig::hidden_id_features` hides any features from a world, reads of the ID group
+//! 3 registers from that world are trapped to EL3 with SCR_EL3.TID3, and emulated here. As well as
+//! the features hidden by the runtime configuration, any feature which no CPU extension of the
+//! platform makes available to the world is hidden, as accesses to its registers would trap.
+
+use crate::{context::World, platform::Platform, sysreg_trap::SysregEncoding};
+use arm_sysregs::{
+    read_id_aa64dfr0_el1, read_id_aa64dfr1_el1, read_id_aa64isar1_el1, read_id_aa64isar2_el1,
+    read_id_aa64mmfr0_el1, read_id_aa64mmfr1_el1, read_id_aa64mmfr2_el1, read_id_aa64mmfr3_el1,
+    read_id_aa64pfr0_el1, read_id_aa64pfr1_el1, read_id_aa64smfr0_el1,
+};
+use bitflags::bitflags;
+
+const ID_AA64PFR0_EL1: SysregEncoding = SysregEncoding::new(3, 0, 0, 4, 0);
+const ID_AA64PFR1_EL1: SysregEncoding = SysregEncoding::new(3, 0, 0, 4, 1);
+const ID_AA64ZFR0_EL1: SysregEncoding = SysregEncoding::new(3, 0, 0, 4, 4);
+const ID_AA64SMFR0_EL1: SysregEncoding = SysregEncoding::new(3, 0, 0, 4, 5);
+const ID_AA64DFR0_EL1: SysregEncoding = SysregEncoding::new(3, 0, 0, 5, 0);
+
+bitflags! {
+    /// Features which may be hidden from a lower EL in the ID registers.
+    #[derive(Clone, Copy, Debug, Eq, PartialEq)]
+    #[repr(transparent)]
+    pub struct IdFeatures: u32 {
+        /// FEAT_MPAM.
+        const MPAM = 1 << 0;
+        /// FEAT_SVE.
+        const SVE = 1 << 1;
+        /// FEAT_SME.
+        const SME = 1 << 2;
+        /// FEAT_MTE and its extensions.
+        const MTE = 1 << 3;
+        /// FEAT_SPE.
+        const SPE = 1 << 4;
+        /// FEAT_TRBE.
+        const TRBE = 1 << 5;
+        /// FEAT_BRBE.
+        const BRBE = 1 << 6;
+        /// FEAT_AMUv1 and its extensions.
+        const AMU = 1 << 7;
+    }
+}
+
+impl IdFeatures {
+    /// Returns the ID register fields which advertise the given features, as (register, shift)
+    /// pairs for 4-bit fields.
+    fn fields(self) -> impl Iterator<Item = (SysregEncoding, u32)> {
+        [
+            (Self::MPAM, ID_AA64PFR0_EL1, 40),
+            (Self::MPAM, ID_AA64PFR1_EL1, 16),
+            (Self::SVE, ID_AA64PFR0_EL1, 32),
+            (Self::SME, ID_AA64PFR1_EL1, 24),
+            (Self::MTE, ID_AA64PFR1_EL1, 8),
+            (Self::SPE, ID_AA64DFR0_EL1, 32),
+            (Self::TRBE, ID_AA64DFR0_EL1, 44),
+            (Self::BRBE, ID_AA64DFR0_EL1, 52),
+            (Self::AMU, ID_AA64PFR0_EL1, 44),
+        ]
+        .into_iter()
+        .filter(move |(feature, _, _)| self.contains(*feature))
+        .map(|(_, register, shift)| (register, shift))
+    }
+}
+
+/// Returns whether the given encoding is one of the ID group 3 registers trapped by SCR_EL3.TID3.
+pub const fn is_id_group3(encoding: SysregEncoding) -> bool {
+    encoding.op0 == 3
+        && encoding.op1 == 0
+        && encoding.crn == 0
+        && encoding.crm >= 1
+        && encoding.crm <= 7
+}
+
+/// Returns the features which should be hidden from the given world.
+pub fn hidden_features<PlatformImpl: Platform>(world: World, configured: IdFeatures) -> IdFeatures {
+    let available = PlatformImpl::CPU_EXTENSIONS
+        .iter()
+        .filter(|extension| extension.is_present())
+        .fold(IdFeatures::empty(), |available, extension| {
+            available | extension.id_features(world)
+        });
+    configured | available.complement()
+}
+
+/// Returns the value of the given ID register with the given features hidden.
+pub fn sanitise(encoding: SysregEncoding, value: u64, hidden: IdFeatures) -> u64 {
+    let hides_all = |features| hidden.contains(features);
+    if (encoding == ID_AA64ZFR0_EL1 && hides_all(IdFeatures::SVE | IdFeatures::SME))
+        || (encoding == ID_AA64SMFR0_EL1 && hides_all(IdFeatures::SME))
+    {
+        return 0;
+    }
+
+    hidden
+        .fields()
+        .filter(|(register, _)| *register == encoding)
+        .fold(value, |value, (_, shift)| value & !(0xf << shift))
+}
+
+/// Emulates a read from the given world of the given ID group 3 register.
+pub fn emulate_read<PlatformImpl: Platform>(
+    encoding: SysregEncoding,
+    world: World,
+    configured: IdFeatures,
+) -> u64 {
+    sanitise(
+        encoding,
+        read(encoding),
+        hidden_features::<PlatformImpl>(world, configured),
+    )
+}
+
+/// Reads an ID group 3 register with the given CRm and op2.
+macro_rules! read_id_register {
+    ($crm:literal, $op2:literal) => {{
+        #[cfg(all(target_arch = "aarch64", not(any(test, feature = "fakes"))))]
+        {
+            let value: u64;
+            // SAFETY: Reading an ID register has no side effects. Unallocated encodings in the ID
+            // register space are RAZ.
+            unsafe {
+                core::arch::asm!(
+                    concat!("mrs {value}, s3_0_c0_c", $crm, "_", $op2),
+                    value = out(reg) value,
+                    options(nomem, nostack, preserves_flags),
+                );
+            }
+            value
+        }
+        #[cfg(not(all(target_arch = "aarch64", not(any(test, feature = "fakes")))))]
+        {
+            0
+        }
+    }};
+}
+
+/// Reads the ID group 3 register with one of the given CRm and op2 values.
+macro_rules! read_id_register_in {
+    ($encoding:expr; $(($crm:literal, $op2:literal)),* $(,)?) => {
+        match ($encoding.crm, $encoding.op2) {
+            $(($crm, $op2) => read_id_register!($crm, $op2),)*
+            _ => panic!("{:?} is not an ID group 3 register", $encoding),
+        }
+    };
+}
+
+/// Reads the real value of the given ID group 3 register.
+fn read(encoding: SysregEncoding) -> u64 {
+    match (encoding.crm, encoding.op2) {
+        (4, 0) => read_id_aa64pfr0_el1().bits(),
+        (4, 1) => read_id_aa64pfr1_el1().bits(),
+        (4, 5) => read_id_aa64smfr0_el1().bits(),
+        (5, 0) => read_id_aa64dfr0_el1().bits(),
+        (5, 1) => read_id_aa64dfr1_el1().bits(),
+        (6, 1) => read_id_aa64isar1_el1().bits(),
+        (6, 2) => read_id_aa64isar2_el1().bits(),
+        (7, 0) => read_id_aa64mmfr0_el1().bits(),
+        (7, 1) => read_id_aa64mmfr1_el1().bits(),
+        (7, 2) => read_id_aa64mmfr2_el1().bits(),
+        (7, 3) => read_id_aa64mmfr3_el1().bits(),
+        _ => read_id_register_in!(
+            encoding;
+            (1, 0), (1, 1), (1, 2), (1, 3), (1, 4), (1, 5), (1, 6), (1, 7), (2, 0), (2, 1), (2, 2),
+            (2, 3), (2, 4), (2, 5), (2, 6), (2, 7), (3, 0), (3, 1), (3, 2), (3, 3), (3, 4), (3, 5),
+            (3, 6), (3, 7), (4, 2), (4, 3), (4, 4), (4, 6), (4, 7), (5, 2), (5, 3), (5, 4), (5, 5),
+            (5, 6), (5, 7), (6, 0), (6, 3), (6, 4), (6, 5), (6, 6), (6, 7), (7, 4), (7, 5), (7, 6),
+            (7, 7),
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::platform::test::TestPlatform;
+    use arm_sysregs::{IdAa64pfr0El1, IdAa64pfr1El1, fake::SYSREGS};
+
+    #[test]
+    fn id_group3() {
+        assert!(is_id_group3(ID_AA64PFR0_EL1));
+        assert!(is_id_group3(SysregEncoding::new(3, 0, 0, 1, 0)));
+        assert!(is_id_group3(SysregEncoding::new(3, 0, 0, 7, 7)));
+        assert!(!is_id_group3(SysregEncoding::new(3, 0, 0, 0, 0)));
+        assert!(!is_id_group3(SysregEncoding::new(3, 1, 0, 0, 1)));
+        assert!(!is_id_group3(SysregEncoding::new(3, 0, 1, 0, 0)));
+    }
+
+    #[test]
+    fn sanitise_fields() {
+        assert_eq!(
+            sanitise(ID_AA64PFR0_EL1, 0x0000_1111_1111_1111, IdFeatures::SVE),
+            0x0000_1110_1111_1111
+        );
+        assert_eq!(
+            sanitise(ID_AA64PFR1_EL1, 0x1111_1111, IdFeatures::SVE),
+            0x1111_1111
+        );
+        assert_eq!(
+            sanitise(
+                ID_AA64PFR1_EL1,
+                0x1111_1111,
+                IdFeatures::SME | IdFeatures::MTE
+            ),
+            0x1011_1011
+        );
+        assert_eq!(sanitise(ID_AA64ZFR0_EL1, 0x11, IdFeatures::SVE), 0x11);
+        assert_eq!(
+            sanitise(ID_AA64ZFR0_EL1, 0x11, IdFeatures::SVE | IdFeatures::SME),
+            0
+        );
+        assert_eq!(sanitise(ID_AA64SMFR0_EL1, 0x11, IdFeatures::SME), 0);
+        assert_eq!(
+            sanitise(
+                ID_AA64DFR0_EL1,
+                0x0011_1111_0000_0000,
+                IdFeatures::BRBE | IdFeatures::TRBE
+            ),
+            0x0001_0111_0000_0000
+        );
+    }
+
+    #[test]
+    fn emulate_without_extensions() {
+        // The test platform has no CPU extensions, so every feature is hidden.
+        SYSREGS.lock().unwrap().id_aa64pfr0_el1 =
+            IdAa64pfr0El1::from_bits_retain(0x0000_1111_1111_1111);
+        SYSREGS.lock().unwrap().id_aa64pfr1_el1 = IdAa64pfr1El1::from_bits_retain(0x1111_1111);
+
+        assert_eq!(
+            emulate_read::<TestPlatform>(ID_AA64PFR0_EL1, World::NonSecure, IdFeatures::empty()),
+            0x0000_0010_1111_1111
+        );
+        assert_eq!(
+            emulate_read::<TestPlatform>(ID_AA64PFR1_EL1, World::Secure, IdFeatures::empty()),
+            0x1010_1011
+        );
+        assert_eq!(
+            emulate_read::<TestPlatform>(ID_AA64ZFR0_EL1, World::Secure, IdFeatures::empty()),
+            0
+        );
+
+        SYSREGS.lock().unwrap().reset();
+    }
+}
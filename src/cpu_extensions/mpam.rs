@@ -19,7 +19,7 @@ mod mpam_sel2;
 
 #[cfg(feature = "sel2")]
 use self::mpam_sel2::MpamCpuContext;
-use super::CpuExtension;
+use super::{CpuExtension, id_registers::IdFeatures};
 #[cfg(feature = "sel2")]
 use crate::context::{CPU_DATA_CONTEXT_NUM, PerCoreState, PerWorld};
 use crate::{
@@ -72,6 +72,14 @@ impl<const CORE_COUNT: usize, PlatformImpl: Platform> CpuExtension
         mpam_is_present()
     }
 
+    fn id_features(&self, world: World) -> IdFeatures {
+        if world == World::Secure {
+            IdFeatures::empty()
+        } else {
+            IdFeatures::MPAM
+        }
+    }
+
     fn configure_per_world(&self, world: World, ctx: &mut PerWorldContext) {
         if world != World::Secure {
             // Enable MPAM configuration and clear the default TRAPLOWER=1 for worlds other than
@@ -0,0 +1,182 @@
+// Copyright The Rusted Firmware-A Contributors.
+//
+// SPDX-License-Identifier: BSD-3-Clause
+
+//! The Maximum Power Mitigation Mechanism (MPMM), which throttles high-activity instructions such as
+//! SIMD and SVE operations on some cores to keep their power consumption within the limits of the
+//! selected gear.
+//!
+//! MPMM is IMPLEMENTATION DEFINED rather than architectural, so it has no ID register field. Cores
+//! which implement it count the activity of each gear in AMU auxiliary (group 1) counters, and its
+//! control registers may only be accessed on those cores, so the platform must say which cores
+//! support it with `Board::mpmm_gear`.
+
+use super::CpuExtension;
+use crate::platform::Platform;
+use arm_sysregs::{
+    read_amcgcr_el0, read_id_aa64pfr0_el1, read_mpidr_el1, read_sysreg, read_write_sysreg,
+};
+use bitflags::bitflags;
+use core::marker::PhantomData;
+#[cfg(any(test, feature = "fakes"))]
+use std::sync::Mutex;
+
+bitflags! {
+    /// CPU Power Performance Management Configuration Register.
+    #[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+    #[repr(transparent)]
+    pub struct CpuppmcrEl3: u64 {
+        /// MPMM is controlled by an external pin rather than by `CPUMPMMCR_EL3`.
+        const MPMMPINCTL = 1 << 0;
+    }
+}
+
+bitflags! {
+    /// CPU MPMM Control Register.
+    #[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+    #[repr(transparent)]
+    pub struct CpumpmmcrEl3: u64 {
+        /// Enables MPMM.
+        const MPMM_EN = 1 << 0;
+        /// The gear selected while MPMM is enabled.
+        const MPMM_GEAR = 0b11 << 1;
+    }
+}
+
+impl CpumpmmcrEl3 {
+    const MPMM_GEAR_SHIFT: u32 = 1;
+
+    /// Returns a copy of the register value with the `MPMM_GEAR` field set to the given gear.
+    pub const fn with_gear(self, gear: MpmmGear) -> Self {
+        Self::from_bits_retain(
+            (self.bits() & !Self::MPMM_GEAR.bits()) | ((gear as u64) << Self::MPMM_GEAR_SHIFT),
+        )
+    }
+}
+
+/// Fake MPMM registers for unit tests.
+#[cfg(any(test, feature = "fakes"))]
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct FakeMpmmRegisters {
+    /// Fake value of `CPUPPMCR_EL3`.
+    pub cpuppmcr_el3: CpuppmcrEl3,
+    /// Fake value of `CPUMPMMCR_EL3`.
+    pub cpumpmmcr_el3: CpumpmmcrEl3,
+}
+
+/// The fake MPMM registers used in place of the real ones in unit tests.
+#[cfg(any(test, feature = "fakes"))]
+pub static FAKE_MPMM_REGISTERS: Mutex<FakeMpmmRegisters> = Mutex::new(FakeMpmmRegisters {
+    cpuppmcr_el3: CpuppmcrEl3::empty(),
+    cpumpmmcr_el3: CpumpmmcrEl3::empty(),
+});
+
+read_sysreg!(cpuppmcr_el3: s3_6_c15_c2_0, u64: CpuppmcrEl3, safe, FAKE_MPMM_REGISTERS);
+read_write_sysreg!(
+    cpumpmmcr_el3: s3_6_c15_c2_1, u64: CpumpmmcrEl3, safe_read, safe_write, FAKE_MPMM_REGISTERS
+);
+
+/// An MPMM gear, which sets the threshold at which high-activity instructions are throttled.
+///
+/// The thresholds of each gear are IMPLEMENTATION DEFINED, and are usually chosen by the platform
+/// according to the power budget of the core.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[repr(u8)]
+pub enum MpmmGear {
+    /// Gear 0.
+    Gear0 = 0,
+    /// Gear 1.
+    Gear1 = 1,
+    /// Gear 2.
+    Gear2 = 2,
+}
+
+/// Enables MPMM with the gear chosen by the platform, on the cores where the platform supports it.
+///
+/// The platform should also enable the AMU and its auxiliary counters, so that the normal world can
+/// monitor the activity of each gear.
+pub struct Mpmm<PlatformImpl: Platform>(PhantomData<fn() -> PlatformImpl>);
+
+impl<PlatformImpl: Platform> Mpmm<PlatformImpl> {
+    /// Constructs a new instance of the MPMM CPU extension.
+    pub const fn new() -> Self {
+        Self(PhantomData)
+    }
+
+    /// Returns the gear to select on the core with the given index, or `None` if MPMM isn't
+    /// supported or may not be controlled by software on it.
+    fn gear(core_index: usize) -> Option<MpmmGear> {
+        let gear = PlatformImpl::mpmm_gear(core_index)?;
+        let has_auxiliary_counters =
+            read_id_aa64pfr0_el1().is_feat_amuv1_present() && read_amcgcr_el0().cg1nc() > 0;
+        (has_auxiliary_counters && !read_cpuppmcr_el3().contains(CpuppmcrEl3::MPMMPINCTL))
+            .then_some(gear)
+    }
+
+    /// Returns the gear to select on the current core, if any.
+    fn current_gear() -> Option<MpmmGear> {
+        Self::gear(PlatformImpl::core_position(read_mpidr_el1().bits()))
+    }
+}
+
+impl<PlatformImpl: Platform> Default for Mpmm<PlatformImpl> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<PlatformImpl: Platform> CpuExtension for Mpmm<PlatformImpl> {
+    fn is_present(&self) -> bool {
+        Self::current_gear().is_some()
+    }
+
+    fn init(&self) {
+        // CPUMPMMCR_EL3 is reset when the core powers down, so this is done on resume as well as
+        // on boot.
+        if let Some(gear) = Self::current_gear() {
+            write_cpumpmmcr_el3((read_cpumpmmcr_el3() | CpumpmmcrEl3::MPMM_EN).with_gear(gear));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::platform::test::TestPlatform;
+    use arm_sysregs::{AmcgcrEl0, IdAa64pfr0El1, fake::SYSREGS};
+
+    #[test]
+    fn gear_field() {
+        assert_eq!(
+            CpumpmmcrEl3::MPMM_EN.with_gear(MpmmGear::Gear2).bits(),
+            0b101
+        );
+        assert_eq!(
+            CpumpmmcrEl3::all().with_gear(MpmmGear::Gear0),
+            CpumpmmcrEl3::MPMM_EN
+        );
+    }
+
+    #[test]
+    fn gear_requires_support() {
+        {
+            let mut sysregs = SYSREGS.lock().unwrap();
+            sysregs.id_aa64pfr0_el1 = IdAa64pfr0El1::empty().with_amu(1);
+            sysregs.amcgcr_el0 = AmcgcrEl0::empty().with_cg0nc(4).with_cg1nc(3);
+        }
+        FAKE_MPMM_REGISTERS.lock().unwrap().cpuppmcr_el3 = CpuppmcrEl3::empty();
+
+        // The test platform only supports MPMM on core 0.
+        assert_eq!(Mpmm::<TestPlatform>::gear(0), Some(MpmmGear::Gear1));
+        assert_eq!(Mpmm::<TestPlatform>::gear(1), None);
+
+        // MPMM can't be used if software doesn't control it.
+        FAKE_MPMM_REGISTERS.lock().unwrap().cpuppmcr_el3 = CpuppmcrEl3::MPMMPINCTL;
+        assert_eq!(Mpmm::<TestPlatform>::gear(0), None);
+        FAKE_MPMM_REGISTERS.lock().unwrap().cpuppmcr_el3 = CpuppmcrEl3::empty();
+
+        // Or without the AMU auxiliary counters.
+        SYSREGS.lock().unwrap().amcgcr_el0 = AmcgcrEl0::empty().with_cg0nc(4);
+        assert_eq!(Mpmm::<TestPlatform>::gear(0), None);
+    }
+}
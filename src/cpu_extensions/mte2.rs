@@ -9,7 +9,7 @@ mod mte2_sel1;
 #[cfg(feature = "sel2")]
 mod mte2_sel2;
 
-use super::CpuExtension;
+use super::{CpuExtension, id_registers::IdFeatures};
 use crate::{
     context::{CPU_DATA_CONTEXT_NUM, PerCoreState, PerWorld, PerWorldContext, World},
     platform::Platform,
@@ -64,6 +64,14 @@ impl<const CORE_COUNT: usize, PlatformImpl: Platform> CpuExtension
         mte2_is_present()
     }
 
+    fn id_features(&self, world: World) -> IdFeatures {
+        if world == World::NonSecure || world == World::Secure {
+            IdFeatures::MTE
+        } else {
+            IdFeatures::empty()
+        }
+    }
+
     fn configure_per_world(&self, world: World, context: &mut PerWorldContext) {
         // Allow access to Allocation Tags for Non-secure and Secure worlds when FEAT_MTE2 is
         // implemented.
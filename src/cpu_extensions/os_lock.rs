@@ -0,0 +1,109 @@
+// Copyright The Rusted Firmware-A Contributors.
+//
+// SPDX-License-Identifier: BSD-3-Clause
+
+//! The OS Lock, which is used by an OS to save and restore the self-hosted debug registers.
+//!
+//! The OS Lock is locked on a cold reset of the PE, which prevents self-hosted debug and
+//! debugger access through the external debug interface until it is unlocked. Arm recommends that
+//! firmware unlocks it during cold boot, so EL3 does so when a CPU first boots. It is left alone
+//! on resume from suspend, where the OS is responsible for restoring its debug state and then
+//! unlocking it.
+
+use crate::aarch64::isb;
+use arm_sysregs::{read_sysreg, write_sysreg};
+use bitflags::bitflags;
+#[cfg(any(test, feature = "fakes"))]
+use std::sync::Mutex;
+
+bitflags! {
+    /// OS Lock Access Register.
+    #[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+    #[repr(transparent)]
+    pub struct OslarEl1: u64 {
+        /// Locks the OS Lock when set, or unlocks it when clear.
+        const OSLK = 1 << 0;
+    }
+}
+
+bitflags! {
+    /// OS Lock Status Register.
+    #[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+    #[repr(transparent)]
+    pub struct OslsrEl1: u64 {
+        /// Bit 0 of the `OSLM` field.
+        const OSLM0 = 1 << 0;
+        /// The OS Lock is locked.
+        const OSLK = 1 << 1;
+        /// Not 32-bit access, which reads as 0.
+        const NTT = 1 << 2;
+        /// Bit 1 of the `OSLM` field.
+        const OSLM1 = 1 << 3;
+    }
+}
+
+impl OslsrEl1 {
+    /// Returns whether the OS Lock is implemented, according to the `OSLM` field.
+    pub const fn is_os_lock_implemented(self) -> bool {
+        // OSLM is 0b10 if the OS Lock is implemented, and 0b00 if not. All other values are
+        // reserved.
+        self.contains(Self::OSLM1) && !self.contains(Self::OSLM0)
+    }
+}
+
+/// Fake OS Lock registers for unit tests.
+#[cfg(any(test, feature = "fakes"))]
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct FakeOsLockRegisters {
+    /// Fake value last written to `OSLAR_EL1`.
+    pub oslar_el1: OslarEl1,
+    /// Fake value of `OSLSR_EL1`.
+    pub oslsr_el1: OslsrEl1,
+}
+
+/// The fake OS Lock registers used in place of the real ones in unit tests.
+#[cfg(any(test, feature = "fakes"))]
+pub static FAKE_OS_LOCK_REGISTERS: Mutex<FakeOsLockRegisters> = Mutex::new(FakeOsLockRegisters {
+    oslar_el1: OslarEl1::empty(),
+    oslsr_el1: OslsrEl1::empty(),
+});
+
+write_sysreg!(oslar_el1, u64: OslarEl1, safe, FAKE_OS_LOCK_REGISTERS);
+read_sysreg!(oslsr_el1, u64: OslsrEl1, safe, FAKE_OS_LOCK_REGISTERS);
+
+/// Unlocks the OS Lock if it is implemented and locked.
+///
+/// This should be called when a CPU first boots, but not when it resumes from suspend.
+pub(crate) fn unlock() {
+    let oslsr = read_oslsr_el1();
+    if oslsr.is_os_lock_implemented() && oslsr.contains(OslsrEl1::OSLK) {
+        write_oslar_el1(OslarEl1::empty());
+        isb();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unlock_locked() {
+        {
+            let mut registers = FAKE_OS_LOCK_REGISTERS.lock().unwrap();
+            registers.oslar_el1 = OslarEl1::OSLK;
+            registers.oslsr_el1 = OslsrEl1::OSLM1 | OslsrEl1::OSLK;
+        }
+        unlock();
+        assert_eq!(
+            FAKE_OS_LOCK_REGISTERS.lock().unwrap().oslar_el1,
+            OslarEl1::empty()
+        );
+    }
+
+    #[test]
+    fn not_implemented() {
+        assert!(!OslsrEl1::empty().is_os_lock_implemented());
+        assert!(!(OslsrEl1::OSLM0 | OslsrEl1::OSLM1).is_os_lock_implemented());
+        assert!((OslsrEl1::OSLM1 | OslsrEl1::NTT).is_os_lock_implemented());
+    }
+}
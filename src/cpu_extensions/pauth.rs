@@ -14,14 +14,16 @@
 
 use crate::{
     aarch64::isb,
-    context::{CpuDataIndex, cpu_data_set_apkey},
+    context::{CpuDataIndex, cpu_data_scrub_apkeys, cpu_data_set_apkey},
     platform::{Platform, exception_free},
+    scrub::Secret,
 };
 use arm_sysregs::{
     ApiakeyhiEl1, ApiakeyloEl1, Sctlr2El3, SctlrEl3, read_id_aa64isar1_el1, read_id_aa64isar2_el1,
     read_sctlr_el3, read_sctlr2_el3, write_apiakeyhi_el1, write_apiakeylo_el1, write_sctlr_el3,
     write_sctlr2_el3,
 };
+use core::marker::PhantomData;
 
 const PAUTH_LR_IMPLEMENTED: u8 = 0b110;
 
@@ -51,6 +53,30 @@ fn set_apkey<PlatformImpl: CpuDataIndex + Platform>() {
     exception_free(|token| cpu_data_set_apkey::<PlatformImpl>(token, key));
 }
 
+/// The copies of the EL3 PAuth key kept in each core's `CpuData`, to be zeroed before the system is
+/// turned off or reset.
+///
+/// The key in the `APIAKey` registers of the core turning off the system can't be cleared, as it is
+/// still in use to authenticate return addresses until the system goes down. The keys can't be
+/// replaced either, as return addresses on the stacks of every core are signed with them, so they
+/// are only scrubbed when the system is certain to go down.
+pub struct El3Keys<PlatformImpl>(PhantomData<fn() -> PlatformImpl>);
+
+impl<PlatformImpl: CpuDataIndex + Platform + 'static> El3Keys<PlatformImpl> {
+    /// The instance to register with `Secrets`.
+    pub const INSTANCE: &'static Self = &Self(PhantomData);
+}
+
+impl<PlatformImpl: CpuDataIndex + Platform + 'static> Secret for El3Keys<PlatformImpl> {
+    fn scrub(&self) {
+        cpu_data_scrub_apkeys::<PlatformImpl>();
+    }
+
+    fn is_replaceable(&self) -> bool {
+        false
+    }
+}
+
 /// Enables Pointer Authentication at EL3.
 ///
 /// # Safety
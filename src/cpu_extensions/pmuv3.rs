@@ -7,6 +7,8 @@
 //! PMU configuration is not optional so we do not implement `CpuExtension`
 //! for basic PMU configuration, only for MTPMU which is non-obligatory.
 
+pub mod registers;
+
 use arm_sysregs::{MdcrEl3, PmcrEl0, read_id_aa64dfr0_el1, read_pmcr_el0, write_pmcr_el0};
 
 use crate::{
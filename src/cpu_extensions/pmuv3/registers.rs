@@ -0,0 +1,238 @@
+// Copyright The Rusted Firmware-A Contributors.
+//
+// SPDX-License-Identifier: BSD-3-Clause
+
+//! Accessors for the PMUv3 counter and control registers which `arm_sysregs` doesn't provide.
+//!
+//! These follow the `read_<sysreg>` / `write_<sysreg>` convention of `arm_sysregs`, so that PMU
+//! state can be saved, restored or sanitised when switching worlds. The event counter registers
+//! `PMEVTYPER<n>_EL0` and `PMEVCNTR<n>_EL0` are accessed by index.
+
+use arm_sysregs::read_write_sysreg;
+use bitflags::bitflags;
+#[cfg(any(test, feature = "fakes"))]
+use std::sync::Mutex;
+
+/// The maximum number of event counters, not counting the cycle counter.
+pub const PMU_EVENT_COUNTER_COUNT: usize = 31;
+
+/// The bit of `PMCNTENSET_EL0` and `PMCNTENCLR_EL0` for the cycle counter `PMCCNTR_EL0`. Bit `n`
+/// is for event counter `n`.
+pub const PMCNTEN_CYCLE_COUNTER: u64 = 1 << 31;
+
+bitflags! {
+    /// Performance Monitors User Enable Register.
+    #[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+    #[repr(transparent)]
+    pub struct PmuserenrEl0: u64 {
+        /// Enables EL0 access to all PMU registers which it can be given access to.
+        const EN = 1 << 0;
+        /// Enables EL0 writes to `PMSWINC_EL0`.
+        const SW = 1 << 1;
+        /// Enables EL0 reads of the cycle counter.
+        const CR = 1 << 2;
+        /// Enables EL0 reads of the event counters, and reads and writes of `PMSELR_EL0`.
+        const ER = 1 << 3;
+        /// Enables EL0 access to the Instruction Counter, with FEAT_PMUv3_ICNTR.
+        const UEN = 1 << 4;
+        /// Traps EL0 reads of `PMEVTYPER<n>_EL0` and `PMCCFILTR_EL0`, with FEAT_PMUv3_TH2.
+        const TID = 1 << 6;
+    }
+}
+
+bitflags! {
+    /// Performance Monitors Cycle Counter Filter Register.
+    #[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+    #[repr(transparent)]
+    pub struct PmccfiltrEl0: u64 {
+        /// Doesn't count cycles in EL1.
+        const P = 1 << 31;
+        /// Doesn't count cycles in EL0.
+        const U = 1 << 30;
+        /// Non-secure EL1 filtering, inverted relative to `P` if set.
+        const NSK = 1 << 29;
+        /// Non-secure EL0 filtering, inverted relative to `U` if set.
+        const NSU = 1 << 28;
+        /// Counts cycles in EL2.
+        const NSH = 1 << 27;
+        /// Secure EL3 filtering, inverted relative to `P` if set.
+        const M = 1 << 26;
+        /// Secure EL2 filtering, with FEAT_SEL2.
+        const SH = 1 << 24;
+        /// Counts cycles only in Transactional state, with FEAT_TME.
+        const T = 1 << 23;
+        /// Realm EL1 filtering, with FEAT_RME.
+        const RLK = 1 << 22;
+        /// Realm EL0 filtering, with FEAT_RME.
+        const RLU = 1 << 21;
+        /// Realm EL2 filtering, with FEAT_RME.
+        const RLH = 1 << 20;
+    }
+}
+
+/// Fake PMU registers for unit tests.
+#[cfg(any(test, feature = "fakes"))]
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct FakePmuRegisters {
+    /// Fake value of `PMCNTENSET_EL0`.
+    pub pmcntenset_el0: u64,
+    /// Fake value of `PMCNTENCLR_EL0`.
+    pub pmcntenclr_el0: u64,
+    /// Fake value of `PMUSERENR_EL0`.
+    pub pmuserenr_el0: PmuserenrEl0,
+    /// Fake value of `PMSELR_EL0`.
+    pub pmselr_el0: u64,
+    /// Fake value of `PMCCFILTR_EL0`.
+    pub pmccfiltr_el0: PmccfiltrEl0,
+    /// Fake values of `PMEVTYPER<n>_EL0`.
+    pub pmevtyper_el0: [u64; PMU_EVENT_COUNTER_COUNT],
+    /// Fake values of `PMEVCNTR<n>_EL0`.
+    pub pmevcntr_el0: [u64; PMU_EVENT_COUNTER_COUNT],
+}
+
+#[cfg(any(test, feature = "fakes"))]
+impl FakePmuRegisters {
+    const RESET: Self = Self {
+        pmcntenset_el0: 0,
+        pmcntenclr_el0: 0,
+        pmuserenr_el0: PmuserenrEl0::empty(),
+        pmselr_el0: 0,
+        pmccfiltr_el0: PmccfiltrEl0::empty(),
+        pmevtyper_el0: [0; PMU_EVENT_COUNTER_COUNT],
+        pmevcntr_el0: [0; PMU_EVENT_COUNTER_COUNT],
+    };
+
+    /// Resets all the fake registers to 0.
+    pub fn reset(&mut self) {
+        *self = Self::RESET;
+    }
+}
+
+/// The fake PMU registers used in place of the real ones in unit tests.
+#[cfg(any(test, feature = "fakes"))]
+pub static FAKE_PMU_REGISTERS: Mutex<FakePmuRegisters> = Mutex::new(FakePmuRegisters::RESET);
+
+read_write_sysreg!(
+    pmcntenset_el0,
+    u64,
+    safe_read,
+    safe_write,
+    FAKE_PMU_REGISTERS
+);
+read_write_sysreg!(
+    pmcntenclr_el0,
+    u64,
+    safe_read,
+    safe_write,
+    FAKE_PMU_REGISTERS
+);
+read_write_sysreg!(pmuserenr_el0, u64: PmuserenrEl0, safe_read, safe_write, FAKE_PMU_REGISTERS);
+read_write_sysreg!(pmselr_el0, u64, safe_read, safe_write, FAKE_PMU_REGISTERS);
+read_write_sysreg!(pmccfiltr_el0, u64: PmccfiltrEl0, safe_read, safe_write, FAKE_PMU_REGISTERS);
+
+/// Generates `read_$sysreg(n)` and `write_$sysreg(n, value)` for the indexed registers
+/// `$asm_prefix<n>_el0`, with a `match` arm for each of the given indices.
+macro_rules! indexed_pmu_sysreg {
+    ($sysreg:ident, $asm_prefix:literal, [$($n:literal),*]) => {
+        paste::paste! {
+            #[doc = concat!("Returns the value of the `", $asm_prefix, "<n>_el0` system register.")]
+            ///
+            /// # Panics
+            ///
+            /// Panics if `n` is not less than `PMU_EVENT_COUNTER_COUNT`.
+            #[cfg(all(target_arch = "aarch64", not(any(test, feature = "fakes"))))]
+            pub fn [< read_ $sysreg >](n: usize) -> u64 {
+                let value;
+                match n {
+                    $(
+                        // SAFETY: Reading a PMU register has no side effects.
+                        $n => unsafe {
+                            core::arch::asm!(
+                                concat!("mrs {value}, ", $asm_prefix, $n, "_el0"),
+                                options(nomem, nostack, preserves_flags),
+                                value = out(reg) value,
+                            );
+                        },
+                    )*
+                    _ => panic!("Invalid PMU event counter {n}"),
+                }
+                value
+            }
+
+            #[doc = concat!("Writes `value` to the `", $asm_prefix, "<n>_el0` system register.")]
+            ///
+            /// # Panics
+            ///
+            /// Panics if `n` is not less than `PMU_EVENT_COUNTER_COUNT`.
+            #[cfg(all(target_arch = "aarch64", not(any(test, feature = "fakes"))))]
+            pub fn [< write_ $sysreg >](n: usize, value: u64) {
+                match n {
+                    $(
+                        // SAFETY: Writing a PMU register only affects performance monitoring, not
+                        // memory safety.
+                        $n => unsafe {
+                            core::arch::asm!(
+                                concat!("msr ", $asm_prefix, $n, "_el0, {value}"),
+                                options(nostack, preserves_flags),
+                                value = in(reg) value,
+                            );
+                        },
+                    )*
+                    _ => panic!("Invalid PMU event counter {n}"),
+                }
+            }
+
+            #[doc = concat!("Returns the value of the fake `", $asm_prefix, "<n>_el0` system register.")]
+            #[cfg(any(test, feature = "fakes"))]
+            pub fn [< read_ $sysreg >](n: usize) -> u64 {
+                FAKE_PMU_REGISTERS.lock().unwrap().$sysreg[n]
+            }
+
+            #[doc = concat!("Writes `value` to the fake `", $asm_prefix, "<n>_el0` system register.")]
+            #[cfg(any(test, feature = "fakes"))]
+            pub fn [< write_ $sysreg >](n: usize, value: u64) {
+                FAKE_PMU_REGISTERS.lock().unwrap().$sysreg[n] = value;
+            }
+        }
+    };
+}
+
+indexed_pmu_sysreg!(
+    pmevtyper_el0,
+    "pmevtyper",
+    [
+        0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20, 21, 22, 23, 24,
+        25, 26, 27, 28, 29, 30
+    ]
+);
+indexed_pmu_sysreg!(
+    pmevcntr_el0,
+    "pmevcntr",
+    [
+        0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20, 21, 22, 23, 24,
+        25, 26, 27, 28, 29, 30
+    ]
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fake_registers() {
+        FAKE_PMU_REGISTERS.lock().unwrap().reset();
+
+        write_pmuserenr_el0(PmuserenrEl0::EN | PmuserenrEl0::CR);
+        write_pmccfiltr_el0(PmccfiltrEl0::P | PmccfiltrEl0::NSH);
+        write_pmcntenset_el0(PMCNTEN_CYCLE_COUNTER | 0b101);
+        write_pmevtyper_el0(30, 0x11);
+        write_pmevcntr_el0(2, 42);
+
+        assert_eq!(read_pmuserenr_el0(), PmuserenrEl0::EN | PmuserenrEl0::CR);
+        assert_eq!(read_pmccfiltr_el0(), PmccfiltrEl0::P | PmccfiltrEl0::NSH);
+        assert_eq!(read_pmcntenset_el0(), 0x8000_0005);
+        assert_eq!(read_pmevtyper_el0(30), 0x11);
+        assert_eq!(read_pmevcntr_el0(2), 42);
+        assert_eq!(read_pmevcntr_el0(0), 0);
+    }
+}
@@ -0,0 +1,159 @@
+// Copyright The Rusted Firmware-A Contributors.
+//
+// SPDX-License-Identifier: BSD-3-Clause
+
+//! FEAT_CSV2_2 and FEAT_CSV2_1p2 introduce the SCXTNUM_ELx registers, which hold a software
+//! context number for each EL. The PE can use it to separate branch prediction and other
+//! speculative state between contexts, so software can use it to mitigate side-channel attacks
+//! between processes or VMs.
+
+#[cfg(not(feature = "sel2"))]
+mod scxt_sel1;
+#[cfg(feature = "sel2")]
+mod scxt_sel2;
+
+#[cfg(not(feature = "sel2"))]
+use self::scxt_sel1::ScxtCpuContext;
+#[cfg(feature = "sel2")]
+use self::scxt_sel2::ScxtCpuContext;
+use super::CpuExtension;
+use crate::{
+    context::{CPU_DATA_CONTEXT_NUM, PerCoreState, PerWorld, PerWorldContext, World},
+    platform::Platform,
+};
+use arm_sysregs::{ScrEl3, read_id_aa64pfr0_el1, read_id_aa64pfr1_el1};
+use core::cell::RefCell;
+use percore::{ExceptionLock, PerCore};
+#[cfg(any(test, feature = "fakes"))]
+use std::sync::Mutex;
+
+/// Fake SCXTNUM_ELx registers for unit tests.
+#[cfg(any(test, feature = "fakes"))]
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct FakeScxtnumRegisters {
+    /// Fake value of `SCXTNUM_EL0`.
+    pub scxtnum_el0: u64,
+    /// Fake value of `SCXTNUM_EL1`.
+    pub scxtnum_el1: u64,
+    /// Fake value of `SCXTNUM_EL2`.
+    pub scxtnum_el2: u64,
+}
+
+/// The fake SCXTNUM_ELx registers used in place of the real ones in unit tests.
+#[cfg(any(test, feature = "fakes"))]
+pub static FAKE_SCXTNUM_REGISTERS: Mutex<FakeScxtnumRegisters> = Mutex::new(FakeScxtnumRegisters {
+    scxtnum_el0: 0,
+    scxtnum_el1: 0,
+    scxtnum_el2: 0,
+});
+
+// The registers are given by encoding, as the assembler only knows their names when FEAT_CSV2_2 is
+// enabled for the target.
+read_write_optional_sysreg!(
+    requires(is_feat_scxtnum_present)
+    scxtnum_el0: s3_3_c13_c0_7, u64, safe_read, safe_write, FAKE_SCXTNUM_REGISTERS
+);
+read_write_optional_sysreg!(
+    requires(is_feat_scxtnum_present), min_el(1)
+    scxtnum_el1: s3_0_c13_c0_7, u64, safe_read, safe_write, FAKE_SCXTNUM_REGISTERS
+);
+read_write_optional_sysreg!(
+    requires(is_feat_scxtnum_present), min_el(2)
+    scxtnum_el2: s3_4_c13_c0_7, u64, safe_read, safe_write, FAKE_SCXTNUM_REGISTERS
+);
+
+/// Returns whether the SCXTNUM_ELx registers are implemented, i.e. FEAT_CSV2_2 or FEAT_CSV2_1p2.
+pub fn is_feat_scxtnum_present() -> bool {
+    let csv2 = read_id_aa64pfr0_el1().csv2();
+    csv2 >= 2 || (csv2 == 1 && read_id_aa64pfr1_el1().csv2_frac() >= 2)
+}
+
+/// Enables access to the SCXTNUM_ELx registers at lower ELs, along with context switching of
+/// those registers on world switch.
+pub struct Scxtnum<const CORE_COUNT: usize, PlatformImpl: Platform> {
+    context: PerCoreState<CORE_COUNT, PlatformImpl, PerWorld<ScxtCpuContext>>,
+}
+
+impl<const CORE_COUNT: usize, PlatformImpl: Platform> Scxtnum<CORE_COUNT, PlatformImpl> {
+    /// Constructs a new instance of the SCXTNUM CPU extension.
+    pub const fn new() -> Self {
+        Self {
+            context: PerCore::new(
+                [const {
+                    ExceptionLock::new(RefCell::new(PerWorld(
+                        [ScxtCpuContext::EMPTY; CPU_DATA_CONTEXT_NUM],
+                    )))
+                }; CORE_COUNT],
+            ),
+        }
+    }
+}
+
+impl<const CORE_COUNT: usize, PlatformImpl: Platform> Default
+    for Scxtnum<CORE_COUNT, PlatformImpl>
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const CORE_COUNT: usize, PlatformImpl: Platform> CpuExtension
+    for Scxtnum<CORE_COUNT, PlatformImpl>
+{
+    fn is_present(&self) -> bool {
+        is_feat_scxtnum_present()
+    }
+
+    fn configure_per_world(&self, _world: World, context: &mut PerWorldContext) {
+        // Enable access to SCXTNUM_ELx registers at lower ELs.
+        context.scr_el3 |= ScrEl3::ENSCXT;
+    }
+
+    fn save_context(&self, world: World) {
+        if self.is_present() {
+            self.save_registers(world);
+        }
+    }
+
+    fn restore_context(&self, world: World) {
+        if self.is_present() {
+            self.restore_registers(world);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::platform::test::TestPlatform;
+
+    #[test]
+    fn save_restore() {
+        let scxtnum = Scxtnum::<{ TestPlatform::CORE_COUNT }, TestPlatform>::new();
+        let set = |value| {
+            let mut registers = FAKE_SCXTNUM_REGISTERS.lock().unwrap();
+            registers.scxtnum_el0 = value;
+            registers.scxtnum_el1 = value + 1;
+            registers.scxtnum_el2 = value + 2;
+        };
+
+        set(10);
+        scxtnum.save_registers(World::NonSecure);
+        set(20);
+        scxtnum.save_registers(World::Secure);
+
+        scxtnum.restore_registers(World::NonSecure);
+        let registers = FAKE_SCXTNUM_REGISTERS.lock().unwrap().clone();
+        #[cfg(not(feature = "sel2"))]
+        assert_eq!((registers.scxtnum_el0, registers.scxtnum_el1), (10, 11));
+        #[cfg(feature = "sel2")]
+        assert_eq!(registers.scxtnum_el2, 12);
+
+        scxtnum.restore_registers(World::Secure);
+        let registers = FAKE_SCXTNUM_REGISTERS.lock().unwrap().clone();
+        #[cfg(not(feature = "sel2"))]
+        assert_eq!((registers.scxtnum_el0, registers.scxtnum_el1), (20, 21));
+        #[cfg(feature = "sel2")]
+        assert_eq!(registers.scxtnum_el2, 22);
+    }
+}
@@ -0,0 +1,43 @@
+// Copyright The Rusted Firmware-A Contributors.
+//
+// SPDX-License-Identifier: BSD-3-Clause
+
+//! SCXTNUM_ELx context management for when Secure EL2 is not enabled.
+
+use super::{Scxtnum, read_scxtnum_el0, read_scxtnum_el1, write_scxtnum_el0, write_scxtnum_el1};
+use crate::{
+    context::World,
+    platform::{Platform, exception_free},
+};
+
+pub struct ScxtCpuContext {
+    scxtnum_el0: u64,
+    scxtnum_el1: u64,
+}
+
+impl ScxtCpuContext {
+    pub const EMPTY: Self = Self {
+        scxtnum_el0: 0,
+        scxtnum_el1: 0,
+    };
+}
+
+impl<const CORE_COUNT: usize, PlatformImpl: Platform> Scxtnum<CORE_COUNT, PlatformImpl> {
+    /// Saves the system register values to this context struct.
+    pub fn save_registers(&self, world: World) {
+        exception_free(|token| {
+            let mut ctx = self.context.get().borrow_mut(token);
+            ctx[world].scxtnum_el0 = read_scxtnum_el0();
+            ctx[world].scxtnum_el1 = read_scxtnum_el1();
+        })
+    }
+
+    /// Restores the system register values from this context struct.
+    pub fn restore_registers(&self, world: World) {
+        exception_free(|token| {
+            let ctx = self.context.get().borrow_mut(token);
+            write_scxtnum_el0(ctx[world].scxtnum_el0);
+            write_scxtnum_el1(ctx[world].scxtnum_el1);
+        })
+    }
+}
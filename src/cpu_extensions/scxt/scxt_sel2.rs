@@ -0,0 +1,35 @@
+// Copyright The Rusted Firmware-A Contributors.
+//
+// SPDX-License-Identifier: BSD-3-Clause
+
+//! SCXTNUM_ELx context management for when Secure EL2 is enabled.
+
+use super::{Scxtnum, read_scxtnum_el2, write_scxtnum_el2};
+use crate::{
+    context::World,
+    platform::{Platform, exception_free},
+};
+
+pub struct ScxtCpuContext {
+    scxtnum_el2: u64,
+}
+
+impl ScxtCpuContext {
+    pub const EMPTY: Self = Self { scxtnum_el2: 0 };
+}
+
+impl<const CORE_COUNT: usize, PlatformImpl: Platform> Scxtnum<CORE_COUNT, PlatformImpl> {
+    /// Saves the system register values to this context struct.
+    pub fn save_registers(&self, world: World) {
+        exception_free(|token| {
+            self.context.get().borrow_mut(token)[world].scxtnum_el2 = read_scxtnum_el2();
+        })
+    }
+
+    /// Restores the system register values from this context struct.
+    pub fn restore_registers(&self, world: World) {
+        exception_free(|token| {
+            write_scxtnum_el2(self.context.get().borrow_mut(token)[world].scxtnum_el2);
+        })
+    }
+}
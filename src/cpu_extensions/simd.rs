@@ -3,20 +3,32 @@
 // SPDX-License-Identifier: BSD-3-Clause
 
 //! SIMD, SVE and SME support.
+//!
+//! Without Secure EL2, RF-A switches the FP/SIMD, SVE and SME registers between worlds itself, and
+//! does so lazily: the registers of the world which last used them stay loaded across world
+//! switches, and other worlds' accesses are trapped with `CPTR_EL3`. Only when another world traps
+//! are the loaded registers saved and that world's restored, so worlds which don't use the
+//! registers cost nothing on a world switch.
 
 #[cfg(all(target_arch = "aarch64", not(feature = "sel2")))]
 mod simd_sel1;
 
 #[cfg(all(target_arch = "aarch64", not(feature = "sel2")))]
-use self::simd_sel1::{SimdCpuContext, SveCpuContext};
-use super::CpuExtension;
+use self::simd_sel1::SimdCpuContext;
 #[cfg(all(target_arch = "aarch64", not(feature = "sel2")))]
-use crate::context::{CPU_DATA_CONTEXT_NUM, PerCoreState, PerWorld};
+use super::sve_sme::{SveSmeContext, clear_svcr, streaming_vector_length};
+use super::{CpuExtension, id_registers::IdFeatures};
 use crate::{
     aarch64::isb,
     context::{PerWorldContext, World},
     platform::Platform,
 };
+#[cfg(all(target_arch = "aarch64", not(feature = "sel2")))]
+use crate::{
+    context::{CPU_DATA_CONTEXT_NUM, CpuContext, PerCoreState, PerWorld, world_context},
+    exceptions::ExceptionClass,
+    platform::exception_free,
+};
 use arm_sysregs::{
     CptrEl3, IdAa64smfr0El1, ScrEl3, SmcrEl3, ZcrEl3, read_cptr_el3, read_id_aa64pfr0_el1,
     read_id_aa64pfr1_el1, read_id_aa64smfr0_el1, write_cptr_el3, write_smcr_el3, write_zcr_el3,
@@ -30,43 +42,22 @@ use percore::{ExceptionLock, PerCore};
 const FP_NOT_SUPPORTED: u8 = 0xf;
 const ADVSIMD_NOT_SUPPORTED: u8 = 0xf;
 
-/// Returns whether SVE and SME access must be permitted based on given `world`.
-fn needs_sve_sme(world: World) -> bool {
-    match world {
-        World::NonSecure => true,
-        World::Secure if cfg!(feature = "sel2") => true,
-
-        #[cfg(feature = "rme")]
-        World::Realm => true,
-
-        _ => false,
-    }
-}
 /// FEAT_SVE support.
 ///
-/// Enables NS world SVE register access and configures the maximum SVE vector length.
-struct Sve<const CORE_COUNT: usize, PlatformImpl: Platform> {
+/// Enables SVE register access for the worlds which may use it and configures the maximum SVE
+/// vector length.
+struct Sve {
     /// Limits the Effective Non-streaming SVE vector length to `vector_length` bits.
     vector_length: u64,
-    #[cfg(all(target_arch = "aarch64", not(feature = "sel2")))]
-    ns_context: PerCoreState<CORE_COUNT, PlatformImpl, SveCpuContext>,
-    _platform: PhantomData<PlatformImpl>,
 }
 
-impl<const CORE_COUNT: usize, PlatformImpl: Platform> Sve<CORE_COUNT, PlatformImpl> {
+impl Sve {
     const fn new(vector_length: u64) -> Self {
         assert!(
             vector_length.is_multiple_of(128) && vector_length >= 128 && vector_length <= 2048,
             "Invalid SVE vector length"
         );
-        Self {
-            vector_length,
-            #[cfg(all(target_arch = "aarch64", not(feature = "sel2")))]
-            ns_context: PerCore::new(
-                [const { ExceptionLock::new(RefCell::new(SveCpuContext::EMPTY)) }; CORE_COUNT],
-            ),
-            _platform: PhantomData,
-        }
+        Self { vector_length }
     }
 
     fn is_present() -> bool {
@@ -96,19 +87,15 @@ impl<const CORE_COUNT: usize, PlatformImpl: Platform> Sve<CORE_COUNT, PlatformIm
         }
     }
 
-    fn configure_per_world(world: World, ctx: &mut PerWorldContext) {
-        // Allow SVE register access to normal world unconditionally,
-        // secure world if S-EL2 enabled, and realm world if enabled.
-        if needs_sve_sme(world) {
-            ctx.cptr_el3 |= CptrEl3::EZ;
-        }
+    fn configure_per_world(ctx: &mut PerWorldContext) {
+        ctx.cptr_el3 |= CptrEl3::EZ;
     }
 }
 
 /// FEAT_SME support.
 ///
-/// Enables NS world SME register access and configures the maximum Streaming SVE (SSVE) vector
-/// length.
+/// Enables SME register access for the worlds which may use it and configures the maximum Streaming
+/// SVE (SSVE) vector length.
 struct Sme {
     /// Limits the Effective Streaming SVE vector length to `vector_length` bits.
     vector_length: u64,
@@ -127,6 +114,10 @@ impl Sme {
         read_id_aa64pfr1_el1().is_feat_sme_present()
     }
 
+    fn is_sme2_present() -> bool {
+        read_id_aa64pfr1_el1().is_feat_sme2_present()
+    }
+
     fn init(&self) {
         // Temporarily allow SME register access, to configure the maximum SSVE vector length.
         let cptr_el3 = read_cptr_el3();
@@ -144,7 +135,7 @@ impl Sme {
         }
 
         // Enable access to ZT0 registers if SME2 is present.
-        if read_id_aa64pfr1_el1().is_feat_sme2_present() {
+        if Self::is_sme2_present() {
             smcr_el3 |= SmcrEl3::EZT0;
         }
 
@@ -153,6 +144,17 @@ impl Sme {
         unsafe {
             write_smcr_el3(smcr_el3);
         }
+        isb();
+
+        // Cores may not support any streaming vector length as short as the one requested, in
+        // which case a longer one is used which the context buffers may not have room for.
+        #[cfg(all(target_arch = "aarch64", not(feature = "sel2")))]
+        assert!(
+            streaming_vector_length() as u64 * 8 <= self.vector_length,
+            "Streaming SVE vector length {} bits is longer than {}",
+            streaming_vector_length() * 8,
+            self.vector_length
+        );
 
         // Restore CPTR_EL3.
         // SAFETY: We're restoring the value previously saved, so it must be valid.
@@ -161,31 +163,50 @@ impl Sme {
         }
     }
 
-    fn configure_per_world(world: World, ctx: &mut PerWorldContext) {
-        // Allow SME register access to normal world unconditionally,
-        // secure world if S-EL2 enabled, and realm world if enabled.
-        if needs_sve_sme(world) {
-            ctx.cptr_el3 |= CptrEl3::ESM;
-            ctx.scr_el3 |= ScrEl3::ENTP2;
-        }
+    fn configure_per_world(ctx: &mut PerWorldContext) {
+        ctx.cptr_el3 |= CptrEl3::ESM;
+        ctx.scr_el3 |= ScrEl3::ENTP2;
     }
 }
 
 /// Enables FP, SIMD, SVE and SME CPU extensions.
-pub struct Simd<const CORE_COUNT: usize, PlatformImpl: Platform> {
-    sve: Option<Sve<CORE_COUNT, PlatformImpl>>,
+///
+/// Without Secure EL2, the SVE and SME registers of each world which may use them are saved in
+/// buffers sized for `VL_BYTES`, the maximum SVE vector length in bytes, and `SVL_BYTES`, the
+/// maximum streaming vector length in bytes. These are both 0 if SVE and SME aren't enabled, and
+/// `SVL_BYTES` is 0 if only SVE is.
+pub struct Simd<
+    const CORE_COUNT: usize,
+    PlatformImpl: Platform,
+    const VL_BYTES: usize = 0,
+    const SVL_BYTES: usize = 0,
+> {
+    sve: Option<Sve>,
     sme: Option<Sme>,
+    /// Whether the secure world may use FP/SIMD registers.
+    secure_fp: bool,
+    /// Whether the secure world may use SVE and SME registers without Secure EL2.
+    secure_sve_sme: bool,
     #[cfg(all(target_arch = "aarch64", not(feature = "sel2")))]
     context: PerCoreState<CORE_COUNT, PlatformImpl, PerWorld<SimdCpuContext>>,
+    #[cfg(all(target_arch = "aarch64", not(feature = "sel2")))]
+    sve_context:
+        PerCoreState<CORE_COUNT, PlatformImpl, PerWorld<SveSmeContext<VL_BYTES, SVL_BYTES>>>,
+    /// The world whose registers are loaded on each core, if any.
+    #[cfg(all(target_arch = "aarch64", not(feature = "sel2")))]
+    loaded_world: PerCoreState<CORE_COUNT, PlatformImpl, Option<World>>,
+    _platform: PhantomData<PlatformImpl>,
 }
 
-impl<const CORE_COUNT: usize, PlatformImpl: Platform> Simd<CORE_COUNT, PlatformImpl> {
-    /// Creates a new `Simd` extension with SVE and SME disabled.
-    #[allow(clippy::self_named_constructors)]
-    pub const fn simd() -> Self {
+impl<const CORE_COUNT: usize, PlatformImpl: Platform, const VL_BYTES: usize, const SVL_BYTES: usize>
+    Simd<CORE_COUNT, PlatformImpl, VL_BYTES, SVL_BYTES>
+{
+    const fn new(sve: Option<Sve>, sme: Option<Sme>) -> Self {
         Self {
-            sve: None,
-            sme: None,
+            sve,
+            sme,
+            secure_fp: true,
+            secure_sve_sme: false,
             #[cfg(all(target_arch = "aarch64", not(feature = "sel2")))]
             context: PerCore::new(
                 [const {
@@ -194,75 +215,116 @@ impl<const CORE_COUNT: usize, PlatformImpl: Platform> Simd<CORE_COUNT, PlatformI
                     )))
                 }; CORE_COUNT],
             ),
-        }
-    }
-
-    /// Creates a new `Simd` extension.
-    ///
-    /// Enables SVE. Configures the maximum vector length for SVE to `vector_length`.
-    ///
-    /// If `enable_sme` is set, SME extension is enabled as well and SSVE vector length is also set
-    /// to `vector_length`.
-    pub const fn sve(vector_length: u64, enable_sme: bool) -> Self {
-        Self {
-            sve: Some(Sve::new(vector_length)),
-            sme: if enable_sme {
-                Some(Sme::new(vector_length))
-            } else {
-                None
-            },
             #[cfg(all(target_arch = "aarch64", not(feature = "sel2")))]
-            context: PerCore::new(
+            sve_context: PerCore::new(
                 [const {
                     ExceptionLock::new(RefCell::new(PerWorld(
-                        [SimdCpuContext::EMPTY; CPU_DATA_CONTEXT_NUM],
+                        [const { SveSmeContext::EMPTY }; CPU_DATA_CONTEXT_NUM],
                     )))
                 }; CORE_COUNT],
             ),
+            #[cfg(all(target_arch = "aarch64", not(feature = "sel2")))]
+            loaded_world: PerCore::new(
+                [const { ExceptionLock::new(RefCell::new(None)) }; CORE_COUNT],
+            ),
+            _platform: PhantomData,
         }
     }
-}
 
-impl<const CORE_COUNT: usize, PlatformImpl: Platform> CpuExtension
-    for Simd<CORE_COUNT, PlatformImpl>
-{
-    fn is_present(&self) -> bool {
-        // We assume that SVE or SME presence implies SIMD presence,
-        // so its sufficient to only check for the 'base' extension.
-        let id_aa64pfr0_el1 = read_id_aa64pfr0_el1();
-        id_aa64pfr0_el1.fp() != FP_NOT_SUPPORTED
-            && id_aa64pfr0_el1.advsimd() != ADVSIMD_NOT_SUPPORTED
+    /// Creates a new `Simd` extension with SVE and SME disabled.
+    #[allow(clippy::self_named_constructors)]
+    pub const fn simd() -> Self {
+        Self::new(None, None)
     }
 
-    fn init(&self) {
-        if let Some(sve) = &self.sve
-            && Sve::<CORE_COUNT, PlatformImpl>::is_present()
-        {
-            sve.init();
-        }
-        if let Some(sme) = &self.sme
-            && Sme::is_present()
-        {
-            sme.init();
-        }
+    /// Creates a new `Simd` extension with SVE, and with SME if `SVL_BYTES` is not 0.
+    ///
+    /// Configures the maximum vector length for SVE to `VL_BYTES` and the maximum streaming vector
+    /// length for SME to `SVL_BYTES`. `VL_BYTES` must be at least `SVL_BYTES`, as the SVE registers
+    /// are saved at the streaming vector length in Streaming SVE mode.
+    pub const fn sve() -> Self {
+        assert!(
+            SVL_BYTES <= VL_BYTES,
+            "SVE vector length must be at least the streaming vector length"
+        );
+        Self::new(
+            Some(Sve::new(VL_BYTES as u64 * 8)),
+            if SVL_BYTES != 0 {
+                Some(Sme::new(SVL_BYTES as u64 * 8))
+            } else {
+                None
+            },
+        )
     }
 
-    fn configure_per_world(&self, world: World, ctx: &mut PerWorldContext) {
-        // Allow FP/SIMD register accesses in every World.
-        ctx.cptr_el3 -= CptrEl3::TFP;
+    /// Declares that the secure world doesn't use FP/SIMD, SVE or SME.
+    ///
+    /// Secure world accesses to these registers are trapped to EL3, which injects an undefined
+    /// instruction exception back into the secure world. In return, EL3 doesn't need to save and
+    /// restore the secure world's registers on world switches, nor the normal world's if there is
+    /// no Realm world to switch to.
+    pub const fn without_secure_fp(mut self) -> Self {
+        assert!(
+            !self.secure_sve_sme,
+            "The secure world can't use SVE or SME without FP/SIMD"
+        );
+        self.secure_fp = false;
+        self
+    }
 
-        if self.sve.is_some() && Sve::<CORE_COUNT, PlatformImpl>::is_present() {
-            Sve::<CORE_COUNT, PlatformImpl>::configure_per_world(world, ctx);
-        }
-        if self.sme.is_some() && Sme::is_present() {
-            Sme::configure_per_world(world, ctx);
+    /// Lets the secure world use SVE and SME without Secure EL2, as well as the normal world.
+    ///
+    /// The secure world's SVE and SME registers are then saved and restored as well as its FP/SIMD
+    /// registers. With Secure EL2 the secure world may always use them, and the SPMC manages them.
+    pub const fn with_secure_sve_sme(mut self) -> Self {
+        assert!(
+            self.secure_fp,
+            "The secure world can't use SVE or SME without FP/SIMD"
+        );
+        self.secure_sve_sme = true;
+        self
+    }
+
+    /// Returns whether SVE and SME access must be permitted for the given world.
+    fn needs_sve_sme(&self, world: World) -> bool {
+        match world {
+            World::NonSecure => true,
+            World::Secure => cfg!(feature = "sel2") || self.secure_sve_sme,
+            #[cfg(feature = "rme")]
+            World::Realm => true,
         }
     }
 
+    /// Returns whether the SVE and SME registers of the given world must be saved and restored,
+    /// rather than only its FP/SIMD registers.
+    #[cfg(all(target_arch = "aarch64", not(feature = "sel2")))]
+    fn uses_sve_context(&self, world: World) -> bool {
+        self.sve.is_some() && Sve::is_present() && self.needs_sve_sme(world)
+    }
+
+    /// Returns whether the FP/SIMD context of the given world doesn't need to be saved and
+    /// restored.
     #[cfg(all(target_arch = "aarch64", not(feature = "sel2")))]
-    fn save_context(&self, world: World) {
-        use crate::platform::exception_free;
+    fn skip_context(&self, world: World) -> bool {
+        // If no other world touches the registers then they don't need to be saved. With RME the
+        // Realm world may still use them, so the normal world's registers need to be saved.
+        !self.secure_fp && (world == World::Secure || !cfg!(feature = "rme"))
+    }
 
+    /// Returns the world whose registers are loaded on this core, if any.
+    #[cfg(all(target_arch = "aarch64", not(feature = "sel2")))]
+    fn loaded_world(&self) -> Option<World> {
+        exception_free(|token| *self.loaded_world.get().borrow_mut(token))
+    }
+
+    #[cfg(all(target_arch = "aarch64", not(feature = "sel2")))]
+    fn set_loaded_world(&self, world: Option<World>) {
+        exception_free(|token| *self.loaded_world.get().borrow_mut(token) = world);
+    }
+
+    /// Saves the loaded FP/SIMD, SVE and SME registers as those of the given world.
+    #[cfg(all(target_arch = "aarch64", not(feature = "sel2")))]
+    fn save_registers(&self, world: World) {
         let has_sme = self.sme.is_some() && Sme::is_present();
 
         // Temporarily allow access to save context
@@ -273,12 +335,10 @@ impl<const CORE_COUNT: usize, PlatformImpl: Platform> CpuExtension
         }
         isb();
 
-        if world == World::NonSecure
-            && let Some(sve) = &self.sve
-            && Sve::<CORE_COUNT, PlatformImpl>::is_present()
-        {
+        if self.uses_sve_context(world) {
             exception_free(|token| {
-                sve.ns_context.get().borrow_mut(token).save(has_sme);
+                self.sve_context.get().borrow_mut(token)[world]
+                    .save(has_sme, has_sme && Sme::is_sme2_present());
             })
         } else {
             exception_free(|token| {
@@ -294,10 +354,9 @@ impl<const CORE_COUNT: usize, PlatformImpl: Platform> CpuExtension
         isb();
     }
 
+    /// Loads the saved FP/SIMD, SVE and SME registers of the given world.
     #[cfg(all(target_arch = "aarch64", not(feature = "sel2")))]
-    fn restore_context(&self, world: World) {
-        use crate::platform::exception_free;
-
+    fn restore_registers(&self, world: World) {
         let has_sme = self.sme.is_some() && Sme::is_present();
 
         // Temporarily allow access to restore context
@@ -308,14 +367,21 @@ impl<const CORE_COUNT: usize, PlatformImpl: Platform> CpuExtension
         }
         isb();
 
-        if world == World::NonSecure
-            && let Some(sve) = &self.sve
-            && Sve::<CORE_COUNT, PlatformImpl>::is_present()
-        {
+        if self.uses_sve_context(world) {
             exception_free(|token| {
-                sve.ns_context.get().borrow_mut(token).restore(has_sme);
+                self.sve_context.get().borrow_mut(token)[world]
+                    .restore(has_sme, has_sme && Sme::is_sme2_present());
             })
         } else {
+            if has_sme {
+                // The previous world may have left the core in Streaming SVE mode, where the
+                // FP/SIMD instructions used to restore the registers are illegal. This world can't
+                // access SVCR to enter it again.
+                // SAFETY: The registers are about to be restored.
+                unsafe {
+                    clear_svcr();
+                }
+            }
             exception_free(|token| {
                 self.context.get().borrow_mut(token)[world].restore();
             })
@@ -329,3 +395,130 @@ impl<const CORE_COUNT: usize, PlatformImpl: Platform> CpuExtension
         isb();
     }
 }
+
+impl<const CORE_COUNT: usize, PlatformImpl: Platform, const VL_BYTES: usize, const SVL_BYTES: usize>
+    CpuExtension for Simd<CORE_COUNT, PlatformImpl, VL_BYTES, SVL_BYTES>
+{
+    fn is_present(&self) -> bool {
+        // We assume that SVE or SME presence implies SIMD presence,
+        // so its sufficient to only check for the 'base' extension.
+        let id_aa64pfr0_el1 = read_id_aa64pfr0_el1();
+        id_aa64pfr0_el1.fp() != FP_NOT_SUPPORTED
+            && id_aa64pfr0_el1.advsimd() != ADVSIMD_NOT_SUPPORTED
+    }
+
+    fn init(&self) {
+        if let Some(sve) = &self.sve
+            && Sve::is_present()
+        {
+            sve.init();
+        }
+        if let Some(sme) = &self.sme
+            && Sme::is_present()
+        {
+            sme.init();
+        }
+    }
+
+    fn id_features(&self, world: World) -> IdFeatures {
+        let mut features = IdFeatures::empty();
+        if (world == World::Secure && !self.secure_fp) || !self.needs_sve_sme(world) {
+            return features;
+        }
+        if self.sve.is_some() {
+            features |= IdFeatures::SVE;
+        }
+        if self.sme.is_some() {
+            features |= IdFeatures::SME;
+        }
+        features
+    }
+
+    fn configure_per_world(&self, world: World, ctx: &mut PerWorldContext) {
+        if world == World::Secure && !self.secure_fp {
+            // Trap all FP/SIMD, SVE and SME register accesses from the secure world.
+            ctx.cptr_el3 |= CptrEl3::TFP;
+            return;
+        }
+
+        // Allow FP/SIMD register accesses in every other World.
+        ctx.cptr_el3 -= CptrEl3::TFP;
+
+        // Allow SVE and SME register access to normal world unconditionally, secure world if S-EL2
+        // is enabled or it has been allowed them, and realm world if enabled.
+        if !self.needs_sve_sme(world) {
+            return;
+        }
+        if self.sve.is_some() && Sve::is_present() {
+            Sve::configure_per_world(ctx);
+        }
+        if self.sme.is_some() && Sme::is_present() {
+            Sme::configure_per_world(ctx);
+        }
+    }
+
+    #[cfg(all(target_arch = "aarch64", not(feature = "sel2")))]
+    fn configure_per_cpu(&self, world: World, _context: &mut CpuContext) {
+        // This is called for the normal world whenever the core powers up, when the registers have
+        // lost their state.
+        if world == World::NonSecure {
+            self.set_loaded_world(None);
+        }
+    }
+
+    #[cfg(all(target_arch = "aarch64", not(feature = "sel2")))]
+    fn restore_context(&self, world: World) {
+        if self.skip_context(world) {
+            return;
+        }
+
+        // Nothing is saved when switching away from a world, so its registers may still be loaded.
+        // Otherwise trap the world's first access to them, which loads them.
+        if self.loaded_world() != Some(world) {
+            // SAFETY: This only traps accesses from lower ELs.
+            unsafe {
+                write_cptr_el3((read_cptr_el3() | CptrEl3::TFP) - CptrEl3::EZ - CptrEl3::ESM);
+            }
+            isb();
+        }
+    }
+
+    #[cfg(all(target_arch = "aarch64", not(feature = "sel2")))]
+    fn handle_feature_trap(&self, world: World, class: ExceptionClass) -> bool {
+        if !matches!(
+            class,
+            ExceptionClass::FpTrap | ExceptionClass::SveTrap | ExceptionClass::SmeTrap
+        ) || self.skip_context(world)
+        {
+            return false;
+        }
+
+        let loaded_world = self.loaded_world();
+        if loaded_world == Some(world) {
+            // The world's registers are already loaded, so the access is one which its own
+            // configuration traps.
+            return false;
+        }
+        if let Some(loaded_world) = loaded_world {
+            self.save_registers(loaded_world);
+        }
+        self.restore_registers(world);
+        self.set_loaded_world(Some(world));
+
+        // SAFETY: This is the configuration which the world would run with without lazy switching.
+        unsafe {
+            write_cptr_el3(world_context(world).cptr_el3);
+        }
+        isb();
+        true
+    }
+
+    #[cfg(all(target_arch = "aarch64", not(feature = "sel2")))]
+    fn save_context_before_suspend_to_powerdown(&self) {
+        // The loaded registers would be lost with the core's power, but stay loaded in case the
+        // powerdown is abandoned.
+        if let Some(loaded_world) = self.loaded_world() {
+            self.save_registers(loaded_world);
+        }
+    }
+}
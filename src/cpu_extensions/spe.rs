@@ -4,7 +4,7 @@
 
 //! Statistical Profiling Extension
 
-use super::CpuExtension;
+use super::{CpuExtension, id_registers::IdFeatures};
 use crate::context::{CpuContext, World};
 use arm_sysregs::{MdcrEl3, read_id_aa64dfr0_el1};
 
@@ -23,6 +23,14 @@ impl CpuExtension for StatisticalProfiling {
         read_id_aa64dfr0_el1().is_feat_spe_present()
     }
 
+    fn id_features(&self, world: World) -> IdFeatures {
+        if world == World::NonSecure {
+            IdFeatures::SPE
+        } else {
+            IdFeatures::empty()
+        }
+    }
+
     fn configure_per_cpu(&self, world: World, context: &mut CpuContext) {
         if world == World::NonSecure {
             // MDCR_EL3.NSPB (ARM v8.2): SPE enabled in Non-secure state and disabled in secure
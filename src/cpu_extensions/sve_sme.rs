@@ -0,0 +1,535 @@
+// Copyright The Rusted Firmware-A Contributors.
+//
+// SPDX-License-Identifier: BSD-3-Clause
+
+//! SVE and SME register context management for when Secure EL2 is not enabled.
+//!
+//! The buffers are sized at build time from the maximum vector lengths which EL3 programs into
+//! `ZCR_EL3` and `SMCR_EL3`, so that a world's registers can be saved at the longest vector length
+//! it can use. The registers are saved at whatever vector length is in effect at EL3 when they are
+//! saved, which is the streaming vector length if the world left the core in Streaming SVE mode.
+
+use crate::aarch64::isb;
+use arm_sysregs::{IdAa64smfr0El1, Svcr, read_id_aa64smfr0_el1, read_svcr, write_svcr};
+use core::arch::asm;
+
+/// The size of the `ZT0` register in bytes.
+const ZT0_BYTES: usize = 64;
+
+/// The SVE and SME registers of a world.
+///
+/// `VL_BYTES` is the maximum SVE vector length in bytes, which must be at least the maximum
+/// Streaming SVE vector length as the Z and P registers are saved at the streaming vector length in
+/// Streaming SVE mode. `SVL_BYTES` is the maximum Streaming SVE vector length in bytes, or 0 if SME
+/// isn't used.
+#[repr(C, align(16))]
+pub struct SveSmeContext<const VL_BYTES: usize, const SVL_BYTES: usize> {
+    /// The 32 Z registers.
+    vectors: [[u8; VL_BYTES]; 32],
+    /// The 16 P registers, each an eighth of the vector length.
+    predicates: [[u8; VL_BYTES]; 2],
+    /// The FFR register, which only uses the first eighth of this.
+    ffr: [u8; VL_BYTES],
+    /// The ZA array, one row of the streaming vector length for each byte of it.
+    za: [[u8; SVL_BYTES]; SVL_BYTES],
+    /// The ZT0 register, if SME2 is present.
+    zt0: [u8; ZT0_BYTES],
+    fpsr: u64,
+    fpcr: u64,
+    svcr: Svcr, // This is unused if SME is not present.
+}
+
+impl<const VL_BYTES: usize, const SVL_BYTES: usize> SveSmeContext<VL_BYTES, SVL_BYTES> {
+    /// An empty context, for initialising statics.
+    pub const EMPTY: Self = Self {
+        vectors: [[0; VL_BYTES]; 32],
+        predicates: [[0; VL_BYTES]; 2],
+        ffr: [0; VL_BYTES],
+        za: [[0; SVL_BYTES]; SVL_BYTES],
+        zt0: [0; ZT0_BYTES],
+        fpsr: 0,
+        fpcr: 0,
+        svcr: Svcr::empty(),
+    };
+
+    fn should_save_restore_ffr(&self, has_sme: bool) -> bool {
+        if has_sme {
+            let streaming = self.svcr.contains(Svcr::SM);
+            if streaming && !read_id_aa64smfr0_el1().contains(IdAa64smfr0El1::FA64) {
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Saves the FFR state.
+    ///
+    /// # Safety
+    ///
+    /// This function reads the value of `ffr` into the first predicate register (p0), so it must
+    /// be called after saving the state of predicate registers.
+    unsafe fn save_ffr(&mut self) {
+        // Get a mutable pointer to the start of the ffr storage.
+        let dest = self.ffr.as_mut_ptr();
+
+        // SAFETY: `dest` is a valid pointer to an array that can hold the FFR register at the
+        // maximum vector length. Caller guarantees that the predicate registers can be overwritten.
+        unsafe {
+            asm!(
+                ".arch_extension sve",
+                "rdffr p0.b",
+                "str p0, [{dest}]",
+                ".arch_extension nosve",
+                dest = in(reg) dest,
+                options(nostack, preserves_flags)
+            )
+        }
+    }
+
+    /// Restores the FFR state.
+    ///
+    /// # Safety
+    ///
+    /// This function writes the value of `ffr` into the first predicate register (p0), and then
+    /// writes FFR state from p0, so it must be called before restoring the state of predicate
+    /// registers.
+    unsafe fn restore_ffr(&self) {
+        // Get a pointer to the start of the ffr storage.
+        let src = self.ffr.as_ptr();
+
+        // SAFETY: `src` is a valid pointer to an array that can hold the FFR register at the
+        // maximum vector length. Caller guarantees that predicate registers can be overwritten at
+        // this point.
+        unsafe {
+            asm!(
+                ".arch_extension sve",
+                "ldr p0, [{src}]",
+                "wrffr p0.b",
+                ".arch_extension nosve",
+                src = in(reg) src,
+                options(nostack, readonly, preserves_flags)
+            )
+        }
+    }
+
+    fn save_predicates(&mut self) {
+        // Get a mutable pointer to the start of the predicates storage.
+        let dest = self.predicates.as_mut_ptr();
+
+        // SAFETY: `dest` is a 16B aligned valid pointer to an array that can hold SVE predicate
+        // registers at the maximum vector length.
+        unsafe {
+            asm!(
+                ".arch_extension sve",
+                "str p0, [{dest}, #0, MUL VL]",
+                "str p1, [{dest}, #1, MUL VL]",
+                "str p2, [{dest}, #2, MUL VL]",
+                "str p3, [{dest}, #3, MUL VL]",
+                "str p4, [{dest}, #4, MUL VL]",
+                "str p5, [{dest}, #5, MUL VL]",
+                "str p6, [{dest}, #6, MUL VL]",
+                "str p7, [{dest}, #7, MUL VL]",
+                "str p8, [{dest}, #8, MUL VL]",
+                "str p9, [{dest}, #9, MUL VL]",
+                "str p10, [{dest}, #10, MUL VL]",
+                "str p11, [{dest}, #11, MUL VL]",
+                "str p12, [{dest}, #12, MUL VL]",
+                "str p13, [{dest}, #13, MUL VL]",
+                "str p14, [{dest}, #14, MUL VL]",
+                "str p15, [{dest}, #15, MUL VL]",
+                ".arch_extension nosve",
+                dest = in(reg) dest,
+                options(nostack, preserves_flags)
+            )
+        }
+    }
+
+    fn restore_predicates(&self) {
+        // Get a pointer to the start of the predicate storage.
+        let src = self.predicates.as_ptr();
+
+        // SAFETY: `src` is a 16B aligned valid pointer to an array that can hold SVE predicate
+        // registers at the maximum vector length.
+        unsafe {
+            asm!(
+                ".arch_extension sve",
+                "ldr p0, [{src}, #0, MUL VL]",
+                "ldr p1, [{src}, #1, MUL VL]",
+                "ldr p2, [{src}, #2, MUL VL]",
+                "ldr p3, [{src}, #3, MUL VL]",
+                "ldr p4, [{src}, #4, MUL VL]",
+                "ldr p5, [{src}, #5, MUL VL]",
+                "ldr p6, [{src}, #6, MUL VL]",
+                "ldr p7, [{src}, #7, MUL VL]",
+                "ldr p8, [{src}, #8, MUL VL]",
+                "ldr p9, [{src}, #9, MUL VL]",
+                "ldr p10, [{src}, #10, MUL VL]",
+                "ldr p11, [{src}, #11, MUL VL]",
+                "ldr p12, [{src}, #12, MUL VL]",
+                "ldr p13, [{src}, #13, MUL VL]",
+                "ldr p14, [{src}, #14, MUL VL]",
+                "ldr p15, [{src}, #15, MUL VL]",
+                ".arch_extension nosve",
+                src = in(reg) src,
+                options(nostack, readonly, preserves_flags)
+            )
+        }
+    }
+
+    /// Saves the 32 SVE vector registers using optimized store instruction.
+    fn save_vectors(&mut self) {
+        // Get a mutable pointer to the start of the vector storage.
+        let dest = self.vectors.as_mut_ptr();
+
+        // SAFETY: `dest` is a 16B aligned valid pointer to an array that can hold SVE vectors at
+        // the maximum vector length.
+        unsafe {
+            asm!(
+                ".arch_extension sve",
+                "str z0, [{dest}, #0, MUL VL]",
+                "str z1, [{dest}, #1, MUL VL]",
+                "str z2, [{dest}, #2, MUL VL]",
+                "str z3, [{dest}, #3, MUL VL]",
+                "str z4, [{dest}, #4, MUL VL]",
+                "str z5, [{dest}, #5, MUL VL]",
+                "str z6, [{dest}, #6, MUL VL]",
+                "str z7, [{dest}, #7, MUL VL]",
+                "str z8, [{dest}, #8, MUL VL]",
+                "str z9, [{dest}, #9, MUL VL]",
+                "str z10, [{dest}, #10, MUL VL]",
+                "str z11, [{dest}, #11, MUL VL]",
+                "str z12, [{dest}, #12, MUL VL]",
+                "str z13, [{dest}, #13, MUL VL]",
+                "str z14, [{dest}, #14, MUL VL]",
+                "str z15, [{dest}, #15, MUL VL]",
+                "str z16, [{dest}, #16, MUL VL]",
+                "str z17, [{dest}, #17, MUL VL]",
+                "str z18, [{dest}, #18, MUL VL]",
+                "str z19, [{dest}, #19, MUL VL]",
+                "str z20, [{dest}, #20, MUL VL]",
+                "str z21, [{dest}, #21, MUL VL]",
+                "str z22, [{dest}, #22, MUL VL]",
+                "str z23, [{dest}, #23, MUL VL]",
+                "str z24, [{dest}, #24, MUL VL]",
+                "str z25, [{dest}, #25, MUL VL]",
+                "str z26, [{dest}, #26, MUL VL]",
+                "str z27, [{dest}, #27, MUL VL]",
+                "str z28, [{dest}, #28, MUL VL]",
+                "str z29, [{dest}, #29, MUL VL]",
+                "str z30, [{dest}, #30, MUL VL]",
+                "str z31, [{dest}, #31, MUL VL]",
+                ".arch_extension nosve",
+                dest = in(reg) dest,
+                options(nostack, preserves_flags)
+            )
+        }
+    }
+
+    /// Restores the 32 SVE vector registers using optimized load instruction.
+    fn restore_vectors(&self) {
+        // Get a pointer to the start of the vector storage.
+        let src = self.vectors.as_ptr();
+
+        // SAFETY: `src` is a 16B aligned valid pointer to an array that can hold SVE vectors at the
+        // maximum vector length.
+        unsafe {
+            asm!(
+                ".arch_extension sve",
+                "ldr z0, [{src}, #0, MUL VL]",
+                "ldr z1, [{src}, #1, MUL VL]",
+                "ldr z2, [{src}, #2, MUL VL]",
+                "ldr z3, [{src}, #3, MUL VL]",
+                "ldr z4, [{src}, #4, MUL VL]",
+                "ldr z5, [{src}, #5, MUL VL]",
+                "ldr z6, [{src}, #6, MUL VL]",
+                "ldr z7, [{src}, #7, MUL VL]",
+                "ldr z8, [{src}, #8, MUL VL]",
+                "ldr z9, [{src}, #9, MUL VL]",
+                "ldr z10, [{src}, #10, MUL VL]",
+                "ldr z11, [{src}, #11, MUL VL]",
+                "ldr z12, [{src}, #12, MUL VL]",
+                "ldr z13, [{src}, #13, MUL VL]",
+                "ldr z14, [{src}, #14, MUL VL]",
+                "ldr z15, [{src}, #15, MUL VL]",
+                "ldr z16, [{src}, #16, MUL VL]",
+                "ldr z17, [{src}, #17, MUL VL]",
+                "ldr z18, [{src}, #18, MUL VL]",
+                "ldr z19, [{src}, #19, MUL VL]",
+                "ldr z20, [{src}, #20, MUL VL]",
+                "ldr z21, [{src}, #21, MUL VL]",
+                "ldr z22, [{src}, #22, MUL VL]",
+                "ldr z23, [{src}, #23, MUL VL]",
+                "ldr z24, [{src}, #24, MUL VL]",
+                "ldr z25, [{src}, #25, MUL VL]",
+                "ldr z26, [{src}, #26, MUL VL]",
+                "ldr z27, [{src}, #27, MUL VL]",
+                "ldr z28, [{src}, #28, MUL VL]",
+                "ldr z29, [{src}, #29, MUL VL]",
+                "ldr z30, [{src}, #30, MUL VL]",
+                "ldr z31, [{src}, #31, MUL VL]",
+                ".arch_extension nosve",
+                src = in(reg) src,
+                options(nostack, readonly, preserves_flags)
+            );
+        }
+    }
+
+    /// Saves the ZA array, one horizontal slice at a time.
+    ///
+    /// # Safety
+    ///
+    /// `PSTATE.ZA` must be set, and the streaming vector length must be at most `SVL_BYTES`.
+    unsafe fn save_za(&mut self) {
+        // Get a mutable pointer to the start of the ZA storage.
+        let dest = self.za.as_mut_ptr();
+
+        // SAFETY: `dest` is a 16B aligned valid pointer to an array that can hold the ZA array, as
+        // our caller promised that the streaming vector length fits, and ZA is enabled.
+        unsafe {
+            asm!(
+                ".arch_extension sme",
+                "rdsvl {svl}, #1",
+                "mov w12, #0",
+                "0:",
+                "str za[w12, 0], [{dest}]",
+                "add {dest}, {dest}, {svl}",
+                "add w12, w12, #1",
+                "cmp x12, {svl}",
+                "b.ne 0b",
+                ".arch_extension nosme",
+                // inout because the pointer is advanced by a row each time.
+                dest = inout(reg) dest => _,
+                svl = out(reg) _,
+                out("x12") _,
+                options(nostack)
+            )
+        }
+    }
+
+    /// Restores the ZA array, one horizontal slice at a time.
+    ///
+    /// # Safety
+    ///
+    /// `PSTATE.ZA` must be set, and the streaming vector length must be at most `SVL_BYTES`.
+    unsafe fn restore_za(&self) {
+        // Get a pointer to the start of the ZA storage.
+        let src = self.za.as_ptr();
+
+        // SAFETY: `src` is a 16B aligned valid pointer to an array that can hold the ZA array, as
+        // our caller promised that the streaming vector length fits, and ZA is enabled.
+        unsafe {
+            asm!(
+                ".arch_extension sme",
+                "rdsvl {svl}, #1",
+                "mov w12, #0",
+                "0:",
+                "ldr za[w12, 0], [{src}]",
+                "add {src}, {src}, {svl}",
+                "add w12, w12, #1",
+                "cmp x12, {svl}",
+                "b.ne 0b",
+                ".arch_extension nosme",
+                // inout because the pointer is advanced by a row each time.
+                src = inout(reg) src => _,
+                svl = out(reg) _,
+                out("x12") _,
+                options(nostack, readonly)
+            )
+        }
+    }
+
+    /// Saves the ZT0 register.
+    ///
+    /// # Safety
+    ///
+    /// SME2 must be present, `PSTATE.ZA` must be set and `SMCR_EL3.EZT0` must allow access to ZT0.
+    unsafe fn save_zt0(&mut self) {
+        // SAFETY: `zt0` is large enough for the register, and our caller promised that it is
+        // accessible.
+        unsafe {
+            asm!(
+                ".arch_extension sme2",
+                "str zt0, [{dest}]",
+                ".arch_extension nosme2",
+                dest = in(reg) self.zt0.as_mut_ptr(),
+                options(nostack, preserves_flags)
+            )
+        }
+    }
+
+    /// Restores the ZT0 register.
+    ///
+    /// # Safety
+    ///
+    /// SME2 must be present, `PSTATE.ZA` must be set and `SMCR_EL3.EZT0` must allow access to ZT0.
+    unsafe fn restore_zt0(&self) {
+        // SAFETY: `zt0` is large enough for the register, and our caller promised that it is
+        // accessible.
+        unsafe {
+            asm!(
+                ".arch_extension sme2",
+                "ldr zt0, [{src}]",
+                ".arch_extension nosme2",
+                src = in(reg) self.zt0.as_ptr(),
+                options(nostack, readonly, preserves_flags)
+            )
+        }
+    }
+
+    /// Saves FP state registers.
+    fn save_fp_state(&mut self) {
+        let fpsr_value;
+        let fpcr_value;
+
+        // SAFETY: This asm only reads the fpsr and fpcr to registers
+        unsafe {
+            asm!(
+                ".arch_extension fp",
+                "mrs {fpsr_value}, fpsr",
+                "mrs {fpcr_value}, fpcr",
+                ".arch_extension nofp",
+                fpsr_value = out(reg) fpsr_value,
+                fpcr_value = out(reg) fpcr_value,
+                options(nostack, nomem, preserves_flags)
+            )
+        }
+
+        self.fpsr = fpsr_value;
+        self.fpcr = fpcr_value;
+    }
+
+    /// Restores FP state registers.
+    fn restore_fp_state(&self) {
+        // SAFETY: This asm only stores the fpsr and fpcr into registers.
+        unsafe {
+            asm!(
+                ".arch_extension fp",
+                "msr fpsr, {fpsr_value}",
+                "msr fpcr, {fpcr_value}",
+                ".arch_extension nofp",
+                fpsr_value = in(reg) self.fpsr,
+                fpcr_value = in(reg) self.fpcr,
+                // Option `preserves_flags` cannot be set as it assumes that the asm block does not
+                // modify `fpsr` which is restored here.
+                options(nostack, nomem)
+            );
+        }
+    }
+
+    fn save_svcr(&mut self) {
+        self.svcr = read_svcr();
+    }
+
+    /// Restores the saved SVCR configuration.
+    ///
+    /// # Safety
+    ///
+    /// Entering or leaving Streaming SVE mode (by restoring SVCR) wipes out the FP, SIMD and SVE
+    /// registers, and enabling ZA wipes out the ZA array, so the caller must guarantee that this
+    /// is the first step of the restoration process.
+    unsafe fn restore_svcr(&self) {
+        // SAFETY: Caller guarantees that its safe to wipe out FP state now.
+        unsafe {
+            write_svcr(self.svcr);
+        }
+    }
+
+    /// Saves the registers, at the vector length currently in effect.
+    ///
+    /// SVE and SME register access must be enabled at EL3. If `has_sme` is set, the streaming
+    /// vector length must be at most `SVL_BYTES`.
+    pub fn save(&mut self, has_sme: bool, has_sme2: bool) {
+        self.save_predicates();
+
+        if has_sme {
+            self.save_svcr();
+        }
+
+        if self.should_save_restore_ffr(has_sme) {
+            isb();
+            // SAFETY: This is done after saving the state of predicate registers.
+            unsafe {
+                self.save_ffr();
+            }
+        }
+
+        self.save_vectors();
+        self.save_fp_state();
+
+        if has_sme && self.svcr.contains(Svcr::ZA) {
+            // SAFETY: ZA is enabled, and our caller promised that the streaming vector length
+            // fits.
+            unsafe {
+                self.save_za();
+                if has_sme2 {
+                    self.save_zt0();
+                }
+            }
+        }
+    }
+
+    /// Restores the registers, entering or leaving Streaming SVE mode as they were saved in.
+    ///
+    /// SVE and SME register access must be enabled at EL3. If `has_sme` is set, the streaming
+    /// vector length must be at most `SVL_BYTES`.
+    pub fn restore(&self, has_sme: bool, has_sme2: bool) {
+        if has_sme {
+            // SAFETY: This is the first step of SVE state restoration.
+            unsafe { self.restore_svcr() };
+            isb();
+
+            if self.svcr.contains(Svcr::ZA) {
+                // SAFETY: ZA has just been enabled, and our caller promised that the streaming
+                // vector length fits.
+                unsafe {
+                    self.restore_za();
+                    if has_sme2 {
+                        self.restore_zt0();
+                    }
+                }
+            }
+        }
+
+        if self.should_save_restore_ffr(has_sme) {
+            // SAFETY: This is done before restoring the state of predicate registers.
+            unsafe { self.restore_ffr() };
+            isb();
+        }
+
+        self.restore_predicates();
+        self.restore_vectors();
+        self.restore_fp_state();
+    }
+}
+
+/// Leaves Streaming SVE mode and disables ZA, as for a world which can't use SME.
+///
+/// # Safety
+///
+/// This wipes out the FP, SIMD and SVE registers if the core was in Streaming SVE mode, so it must
+/// be called before restoring them.
+pub unsafe fn clear_svcr() {
+    // SAFETY: Our caller promised that the registers can be wiped out.
+    unsafe {
+        write_svcr(Svcr::empty());
+    }
+    isb();
+}
+
+/// Returns the effective streaming vector length at EL3 in bytes.
+///
+/// SME register access must be enabled at EL3.
+pub fn streaming_vector_length() -> usize {
+    let svl;
+    // SAFETY: `rdsvl` only reads the streaming vector length.
+    unsafe {
+        asm!(
+            ".arch_extension sme",
+            "rdsvl {svl}, #1",
+            ".arch_extension nosme",
+            svl = out(reg) svl,
+            options(nostack, nomem, preserves_flags)
+        );
+    }
+    svl
+}
@@ -13,7 +13,7 @@
 //! - Is defined by pointer registers.
 //! - Its operation is affected by external events like triggers.
 
-use super::CpuExtension;
+use super::{CpuExtension, id_registers::IdFeatures};
 
 use crate::context::{CpuContext, World};
 
@@ -72,6 +72,14 @@ impl CpuExtension for TraceBufferNonSecure {
         read_id_aa64dfr0_el1().is_feat_trbe_present()
     }
 
+    fn id_features(&self, world: World) -> IdFeatures {
+        if world == World::NonSecure {
+            IdFeatures::TRBE
+        } else {
+            IdFeatures::empty()
+        }
+    }
+
     fn configure_per_cpu(&self, world: World, ctx: &mut CpuContext) {
         if world == World::NonSecure {
             // TODO: CORTEX_A510, CORTEX_A520, CORTEX_X4 may need to disable TRBE
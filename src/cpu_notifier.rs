@@ -0,0 +1,131 @@
+// Copyright The Rusted Firmware-A Contributors.
+//
+// SPDX-License-Identifier: BSD-3-Clause
+
+//! Callbacks for drivers which need to set up or tear down per-core state whenever a core is turned
+//! on or off.
+
+use crate::spin_mutex::SpinMutex;
+use arrayvec::ArrayVec;
+
+/// The maximum number of notifiers which may be registered.
+pub const MAX_CPU_NOTIFIERS: usize = 8;
+
+/// A driver which needs to be told when a core comes online or goes offline.
+///
+/// Both methods are called on the core in question, with the PSCI power domain locks of that core
+/// and its ancestors held.
+pub trait CpuNotifier: Sync {
+    /// Initialises the driver's per-core state after the core has been turned on with `CPU_ON`,
+    /// before it enters a lower EL.
+    ///
+    /// This isn't called for the primary core on cold boot, which drivers should initialise as part
+    /// of their own cold boot initialisation.
+    fn cpu_online(&self);
+
+    /// Tears down the driver's per-core state before the core is turned off with `CPU_OFF`.
+    fn cpu_offline(&self);
+}
+
+/// The list of drivers to notify when a core is turned on or off.
+///
+/// Notifiers are registered on the primary core during cold boot. They are notified in the order
+/// they were registered when a core comes online, and in the reverse order when it goes offline.
+pub struct CpuNotifiers {
+    notifiers: SpinMutex<ArrayVec<&'static dyn CpuNotifier, MAX_CPU_NOTIFIERS>>,
+}
+
+impl CpuNotifiers {
+    /// Creates an empty list of notifiers.
+    pub const fn new() -> Self {
+        Self {
+            notifiers: SpinMutex::new(ArrayVec::new_const()),
+        }
+    }
+
+    /// Adds the given notifier to the list.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `MAX_CPU_NOTIFIERS` notifiers have already been registered.
+    pub fn register(&self, notifier: &'static dyn CpuNotifier) {
+        self.notifiers
+            .lock()
+            .try_push(notifier)
+            .expect("Too many CPU notifiers registered");
+    }
+
+    /// Notifies all registered drivers that the current core has come online.
+    pub(crate) fn cpu_online(&self) {
+        // Copy the list so that the lock isn't held while calling the notifiers.
+        let notifiers = self.notifiers.lock().clone();
+        for notifier in notifiers {
+            notifier.cpu_online();
+        }
+    }
+
+    /// Notifies all registered drivers that the current core is about to go offline.
+    pub(crate) fn cpu_offline(&self) {
+        let notifiers = self.notifiers.lock().clone();
+        for notifier in notifiers.into_iter().rev() {
+            notifier.cpu_offline();
+        }
+    }
+}
+
+impl Default for CpuNotifiers {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    struct RecordingNotifier {
+        name: &'static str,
+        events: &'static Mutex<Vec<String>>,
+    }
+
+    impl CpuNotifier for RecordingNotifier {
+        fn cpu_online(&self) {
+            self.events
+                .lock()
+                .unwrap()
+                .push(format!("{} online", self.name));
+        }
+
+        fn cpu_offline(&self) {
+            self.events
+                .lock()
+                .unwrap()
+                .push(format!("{} offline", self.name));
+        }
+    }
+
+    #[test]
+    fn notify_in_order() {
+        static EVENTS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+        static GIC: RecordingNotifier = RecordingNotifier {
+            name: "gic",
+            events: &EVENTS,
+        };
+        static TIMER: RecordingNotifier = RecordingNotifier {
+            name: "timer",
+            events: &EVENTS,
+        };
+
+        let notifiers = CpuNotifiers::new();
+        notifiers.register(&GIC);
+        notifiers.register(&TIMER);
+
+        notifiers.cpu_online();
+        notifiers.cpu_offline();
+        assert_eq!(
+            *EVENTS.lock().unwrap(),
+            ["gic online", "timer online", "timer offline", "gic offline"]
+        );
+    }
+}
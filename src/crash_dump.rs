@@ -0,0 +1,452 @@
+// Copyright The Rusted Firmware-A Contributors.
+//
+// SPDX-License-Identifier: BSD-3-Clause
+
+//! Structured dumps of the CPU state when EL3 crashes.
+//!
+//! A [`CrashDump`] holds the general-purpose registers and the EL3, EL2 and EL1 system registers
+//! of the crashing core. It is saved in the core's [`CpuData`](crate::context::CpuData) and
+//! printed over the crash console in a line-based format which is easy to parse:
+//!
+//! ```text
+//! RF-A CRASH BEGIN
+//! reason = panic
+//! core = 2
+//! x0 = 0x0000000000000000
+//! ...
+//! far_el3 = 0x0000000000000000
+//! ...
+//! RF-A CRASH END
+//! ```
+//!
+//! If EL3 faults again while it is handling a crash, e.g. because the crash console isn't mapped,
+//! it doesn't try to report the crash again. Instead the second-level handler only writes a
+//! [`DoubleFaultRecord`] to the core's `CpuData` and calls `Platform::crash_reset`.
+
+use arm_sysregs::{
+    read_cpacr_el1, read_cptr_el3, read_daif, read_elr_el1, read_esr_el1, read_esr_el3,
+    read_far_el1, read_isr_el1, read_mair_el1, read_mair_el3, read_mpidr_el1, read_par_el1,
+    read_scr_el3, read_sctlr_el1, read_sctlr_el3, read_sp_el1, read_spsr_el1, read_spsr_el3,
+    read_tcr_el1, read_tcr_el3, read_tpidr_el1, read_ttbr0_el1, read_ttbr0_el3, read_ttbr1_el1,
+    read_vbar_el1,
+};
+#[cfg(feature = "sel2")]
+use arm_sysregs::{
+    read_cptr_el2, read_elr_el2, read_esr_el2, read_far_el2, read_hcr_el2, read_mair_el2,
+    read_mdcr_el2, read_sctlr_el2, read_spsr_el2, read_tcr_el2, read_ttbr0_el2, read_vbar_el2,
+    read_vtcr_el2, read_vttbr_el2,
+};
+use core::fmt::{self, Display, Formatter};
+
+/// The number of general-purpose registers saved in a crash dump, x0-x30.
+pub const GP_REGISTER_COUNT: usize = 31;
+
+/// Names of the general-purpose registers, in order.
+const GP_REGISTER_NAMES: [&str; GP_REGISTER_COUNT] = [
+    "x0", "x1", "x2", "x3", "x4", "x5", "x6", "x7", "x8", "x9", "x10", "x11", "x12", "x13", "x14",
+    "x15", "x16", "x17", "x18", "x19", "x20", "x21", "x22", "x23", "x24", "x25", "x26", "x27",
+    "x28", "x29", "x30",
+];
+
+/// Names of the EL3 system registers saved in a crash dump, in order.
+const EL3_SYSREG_NAMES: [&str; 11] = [
+    "scr_el3",
+    "sctlr_el3",
+    "cptr_el3",
+    "tcr_el3",
+    "daif",
+    "mair_el3",
+    "spsr_el3",
+    "elr_el3",
+    "ttbr0_el3",
+    "esr_el3",
+    "far_el3",
+];
+
+/// Names of the EL2 system registers saved in a crash dump, in order.
+#[cfg(feature = "sel2")]
+const EL2_SYSREG_NAMES: [&str; 14] = [
+    "hcr_el2",
+    "sctlr_el2",
+    "cptr_el2",
+    "mdcr_el2",
+    "tcr_el2",
+    "mair_el2",
+    "ttbr0_el2",
+    "vtcr_el2",
+    "vttbr_el2",
+    "vbar_el2",
+    "spsr_el2",
+    "elr_el2",
+    "esr_el2",
+    "far_el2",
+];
+
+/// Names of the EL1 system registers saved in a crash dump, in order.
+const EL1_SYSREG_NAMES: [&str; 16] = [
+    "sctlr_el1",
+    "cpacr_el1",
+    "tcr_el1",
+    "mair_el1",
+    "ttbr0_el1",
+    "ttbr1_el1",
+    "vbar_el1",
+    "spsr_el1",
+    "elr_el1",
+    "esr_el1",
+    "far_el1",
+    "par_el1",
+    "sp_el1",
+    "tpidr_el1",
+    "mpidr_el1",
+    "isr_el1",
+];
+
+/// Why EL3 crashed.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[repr(u64)]
+pub enum CrashReason {
+    /// No crash has been recorded.
+    #[default]
+    None,
+    /// Rust code panicked.
+    Panic,
+    /// An exception was taken to EL3 which it doesn't handle.
+    UnhandledException,
+    /// An interrupt was taken to EL3 which it doesn't handle.
+    UnhandledInterrupt,
+}
+
+impl Display for CrashReason {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Self::None => "none",
+            Self::Panic => "panic",
+            Self::UnhandledException => "unhandled_exception",
+            Self::UnhandledInterrupt => "unhandled_interrupt",
+        })
+    }
+}
+
+/// The value of [`DoubleFaultRecord::signature`] once a double fault has been recorded.
+///
+/// This reads as `DBLFAULT` in a little-endian memory dump.
+pub const DOUBLE_FAULT_SIGNATURE: u64 = u64::from_le_bytes(*b"DBLFAULT");
+
+/// The minimal record written by the second-level crash handler when EL3 faults while it is already
+/// handling a crash.
+///
+/// This is written from assembly without a stack, so it only holds a few system registers.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+#[repr(C)]
+pub struct DoubleFaultRecord {
+    /// [`DOUBLE_FAULT_SIGNATURE`] if a double fault has been recorded, otherwise 0.
+    pub signature: u64,
+    /// `ESR_EL3` when the second-level handler was entered.
+    pub esr_el3: u64,
+    /// `ELR_EL3` when the second-level handler was entered.
+    pub elr_el3: u64,
+    /// `FAR_EL3` when the second-level handler was entered.
+    pub far_el3: u64,
+}
+
+impl DoubleFaultRecord {
+    /// An empty record, for initialising statics.
+    pub const EMPTY: Self = Self {
+        signature: 0,
+        esr_el3: 0,
+        elr_el3: 0,
+        far_el3: 0,
+    };
+
+    /// Returns whether a double fault has been recorded.
+    pub fn is_recorded(&self) -> bool {
+        self.signature == DOUBLE_FAULT_SIGNATURE
+    }
+}
+
+/// The register state of a core when it crashed.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[repr(C)]
+pub struct CrashDump {
+    /// Why the core crashed.
+    pub reason: CrashReason,
+    /// The linear index of the core.
+    pub core_index: usize,
+    /// General-purpose registers x0-x30.
+    pub gpregs: [u64; GP_REGISTER_COUNT],
+    /// EL3 system registers, in the order of `EL3_SYSREG_NAMES`.
+    pub el3_sysregs: [u64; EL3_SYSREG_NAMES.len()],
+    /// EL2 system registers, in the order of `EL2_SYSREG_NAMES`.
+    #[cfg(feature = "sel2")]
+    pub el2_sysregs: [u64; EL2_SYSREG_NAMES.len()],
+    /// EL1 system registers, in the order of `EL1_SYSREG_NAMES`.
+    pub el1_sysregs: [u64; EL1_SYSREG_NAMES.len()],
+}
+
+impl CrashDump {
+    /// An empty crash dump, for initialising statics.
+    pub const EMPTY: Self = Self {
+        reason: CrashReason::None,
+        core_index: 0,
+        gpregs: [0; GP_REGISTER_COUNT],
+        el3_sysregs: [0; EL3_SYSREG_NAMES.len()],
+        #[cfg(feature = "sel2")]
+        el2_sysregs: [0; EL2_SYSREG_NAMES.len()],
+        el1_sysregs: [0; EL1_SYSREG_NAMES.len()],
+    };
+
+    /// Captures the current system registers, along with the given general-purpose registers.
+    pub fn capture(
+        reason: CrashReason,
+        core_index: usize,
+        gpregs: [u64; GP_REGISTER_COUNT],
+    ) -> Self {
+        Self {
+            reason,
+            core_index,
+            gpregs,
+            el3_sysregs: [
+                read_scr_el3().bits(),
+                read_sctlr_el3().bits(),
+                read_cptr_el3().bits(),
+                read_tcr_el3().bits(),
+                read_daif().bits(),
+                read_mair_el3().bits(),
+                read_spsr_el3().bits(),
+                read_elr_el3(),
+                read_ttbr0_el3().bits(),
+                read_esr_el3().bits(),
+                read_far_el3(),
+            ],
+            #[cfg(feature = "sel2")]
+            el2_sysregs: [
+                read_hcr_el2().bits(),
+                read_sctlr_el2().bits(),
+                read_cptr_el2().bits(),
+                read_mdcr_el2().bits(),
+                read_tcr_el2().bits(),
+                read_mair_el2().bits(),
+                read_ttbr0_el2().bits(),
+                read_vtcr_el2().bits(),
+                read_vttbr_el2().bits(),
+                read_vbar_el2().bits(),
+                read_spsr_el2().bits(),
+                read_elr_el2().bits(),
+                read_esr_el2().bits(),
+                read_far_el2().bits(),
+            ],
+            el1_sysregs: [
+                read_sctlr_el1().bits(),
+                read_cpacr_el1().bits(),
+                read_tcr_el1().bits(),
+                read_mair_el1().bits(),
+                read_ttbr0_el1().bits(),
+                read_ttbr1_el1().bits(),
+                read_vbar_el1().bits(),
+                read_spsr_el1().bits(),
+                read_elr_el1().bits(),
+                read_esr_el1().bits(),
+                read_far_el1().bits(),
+                read_par_el1().bits(),
+                read_sp_el1().bits(),
+                read_tpidr_el1().bits(),
+                read_mpidr_el1().bits(),
+                read_isr_el1().bits(),
+            ],
+        }
+    }
+
+    /// Returns the name and value of every register in the dump, in the order they are printed.
+    pub fn registers(&self) -> impl Iterator<Item = (&'static str, u64)> + '_ {
+        let sysregs = EL3_SYSREG_NAMES.iter().zip(&self.el3_sysregs);
+        #[cfg(feature = "sel2")]
+        let sysregs = sysregs.chain(EL2_SYSREG_NAMES.iter().zip(&self.el2_sysregs));
+        let sysregs = sysregs.chain(EL1_SYSREG_NAMES.iter().zip(&self.el1_sysregs));
+        GP_REGISTER_NAMES
+            .iter()
+            .zip(&self.gpregs)
+            .chain(sysregs)
+            .map(|(name, value)| (*name, *value))
+    }
+}
+
+impl Display for CrashDump {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        writeln!(f, "RF-A CRASH BEGIN")?;
+        writeln!(f, "reason = {}", self.reason)?;
+        writeln!(f, "core = {}", self.core_index)?;
+        for (name, value) in self.registers() {
+            writeln!(f, "{name} = {value:#018x}")?;
+        }
+        writeln!(f, "RF-A CRASH END")
+    }
+}
+
+/// Reads `ELR_EL3`, which `arm_sysregs` doesn't provide.
+#[cfg(all(target_arch = "aarch64", not(any(test, feature = "fakes"))))]
+fn read_elr_el3() -> u64 {
+    let value;
+    // SAFETY: Reading ELR_EL3 has no side effects.
+    unsafe {
+        core::arch::asm!("mrs {}, elr_el3", out(reg) value, options(nomem, nostack, preserves_flags));
+    }
+    value
+}
+
+/// There is no fake `ELR_EL3`, so it always reads as 0.
+#[cfg(not(all(target_arch = "aarch64", not(any(test, feature = "fakes")))))]
+fn read_elr_el3() -> u64 {
+    0
+}
+
+/// Reads `FAR_EL3`, which `arm_sysregs` doesn't provide.
+#[cfg(all(target_arch = "aarch64", not(any(test, feature = "fakes"))))]
+fn read_far_el3() -> u64 {
+    let value;
+    // SAFETY: Reading FAR_EL3 has no side effects.
+    unsafe {
+        core::arch::asm!("mrs {}, far_el3", out(reg) value, options(nomem, nostack, preserves_flags));
+    }
+    value
+}
+
+/// There is no fake `FAR_EL3`, so it always reads as 0.
+#[cfg(not(all(target_arch = "aarch64", not(any(test, feature = "fakes")))))]
+fn read_far_el3() -> u64 {
+    0
+}
+
+/// Saves the general-purpose registers x0-x30 as they are at the point of the call.
+#[cfg(all(target_arch = "aarch64", not(any(test, feature = "fakes"))))]
+#[inline(always)]
+fn save_gpregs() -> [u64; GP_REGISTER_COUNT] {
+    let mut gpregs = [0; GP_REGISTER_COUNT];
+    // SAFETY: The stores only write to `gpregs`, which is large enough for 31 registers. The
+    // register holding the pointer is saved along with the rest, which is harmless.
+    unsafe {
+        core::arch::asm!(
+            "stp x0, x1, [{buf}, #0]",
+            "stp x2, x3, [{buf}, #16]",
+            "stp x4, x5, [{buf}, #32]",
+            "stp x6, x7, [{buf}, #48]",
+            "stp x8, x9, [{buf}, #64]",
+            "stp x10, x11, [{buf}, #80]",
+            "stp x12, x13, [{buf}, #96]",
+            "stp x14, x15, [{buf}, #112]",
+            "stp x16, x17, [{buf}, #128]",
+            "stp x18, x19, [{buf}, #144]",
+            "stp x20, x21, [{buf}, #160]",
+            "stp x22, x23, [{buf}, #176]",
+            "stp x24, x25, [{buf}, #192]",
+            "stp x26, x27, [{buf}, #208]",
+            "stp x28, x29, [{buf}, #224]",
+            "str x30, [{buf}, #240]",
+            buf = in(reg) gpregs.as_mut_ptr(),
+            options(nostack, preserves_flags),
+        );
+    }
+    gpregs
+}
+
+#[cfg(all(target_arch = "aarch64", not(any(test, feature = "fakes"))))]
+unsafe extern "C" {
+    /// The second-level crash handler, defined in `crash_reporting.S`.
+    ///
+    /// Writes a `DoubleFaultRecord` to the current core's `CpuData` and calls
+    /// `Platform::crash_reset`, without using the stack or the crash console.
+    fn report_double_fault() -> !;
+}
+
+/// Saves a crash dump of the current core to its `CpuData`, prints it over the crash console, and
+/// then calls `Platform::panic_handler`.
+///
+/// If the core is already handling a crash then this goes straight to the second-level handler
+/// instead, so that a panic while printing the crash dump doesn't recurse.
+#[cfg(all(target_arch = "aarch64", not(any(test, feature = "fakes"))))]
+#[cold]
+pub fn report_crash<PlatformImpl: crate::platform::Platform + crate::context::CpuDataIndex>(
+    reason: CrashReason,
+) -> ! {
+    use crate::{context::CoresImpl, debug::CrashConsoleWriter, platform::exception_free};
+    use core::{fmt::Write, marker::PhantomData};
+    use percore::Cores;
+
+    let gpregs = save_gpregs();
+    let core_index = CoresImpl::<PlatformImpl>::core_index();
+    exception_free(|token| {
+        PlatformImpl::update_cpu_data(token, |cpu_data| {
+            if cpu_data.enter_crash_handler() {
+                // SAFETY: `report_double_fault` doesn't return, and only writes to the current
+                // core's `CpuData`, which we aren't going to access again.
+                unsafe { report_double_fault() }
+            }
+            cpu_data.crash_dump = CrashDump::capture(reason, core_index, gpregs);
+            if PlatformImpl::crash_console_init() != 0 {
+                let _ = write!(
+                    CrashConsoleWriter::<PlatformImpl>(PhantomData),
+                    "{}",
+                    cpu_data.crash_dump
+                );
+                PlatformImpl::crash_console_flush();
+            }
+        });
+    });
+    PlatformImpl::panic_handler()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arm_sysregs::{EsrEl3, ScrEl3, SpsrEl3, fake::SYSREGS};
+
+    #[test]
+    fn format_dump() {
+        {
+            let mut sysregs = SYSREGS.lock().unwrap();
+            sysregs.reset();
+            sysregs.scr_el3 = ScrEl3::NS | ScrEl3::RW;
+            sysregs.esr_el3 = EsrEl3::from_bits_retain(0x5e00_0000);
+            sysregs.spsr_el3 = SpsrEl3::from_bits_retain(0x3c9);
+        }
+        let mut gpregs = [0; GP_REGISTER_COUNT];
+        gpregs[0] = 0xc400_0003;
+        gpregs[30] = 0x1234_5678;
+        let dump = CrashDump::capture(CrashReason::Panic, 2, gpregs);
+        let output = dump.to_string();
+
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines[0], "RF-A CRASH BEGIN");
+        assert_eq!(lines[1], "reason = panic");
+        assert_eq!(lines[2], "core = 2");
+        assert_eq!(lines[3], "x0 = 0x00000000c4000003");
+        assert_eq!(lines[33], "x30 = 0x0000000012345678");
+        assert_eq!(lines[34], "scr_el3 = 0x0000000000000401");
+        assert!(lines.contains(&"spsr_el3 = 0x00000000000003c9"));
+        assert!(lines.contains(&"esr_el3 = 0x000000005e000000"));
+        assert_eq!(*lines.last().unwrap(), "RF-A CRASH END");
+        assert_eq!(lines.len(), 3 + dump.registers().count() + 1);
+
+        // Every register line is `name = value`, with a unique name.
+        let mut names = Vec::new();
+        for line in &lines[3..lines.len() - 1] {
+            let (name, value) = line.split_once(" = ").unwrap();
+            assert!(value.starts_with("0x") && value.len() == 18, "{line}");
+            assert!(!names.contains(&name), "duplicate {name}");
+            names.push(name);
+        }
+    }
+
+    #[test]
+    fn double_fault_signature() {
+        assert_eq!(&DOUBLE_FAULT_SIGNATURE.to_le_bytes(), b"DBLFAULT");
+        // The second-level handler loads the signature with `mov_imm`, which must not see it as
+        // negative.
+        assert!(DOUBLE_FAULT_SIGNATURE <= i64::MAX as u64);
+
+        let mut record = DoubleFaultRecord::EMPTY;
+        assert!(!record.is_recorded());
+        record.signature = DOUBLE_FAULT_SIGNATURE;
+        assert!(record.is_recorded());
+    }
+}
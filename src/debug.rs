@@ -4,15 +4,17 @@
 
 //! Debug output.
 
+use crate::platform::Platform;
+use core::fmt::Arguments;
+#[cfg(all(target_arch = "aarch64", not(any(test, feature = "fakes"))))]
+use core::{fmt::Write, marker::PhantomData};
 #[cfg(all(target_arch = "aarch64", not(any(test, feature = "fakes"))))]
 use include_first::include_first;
 
 /// True if the build is configured with debug assertions on.
-#[cfg(not(any(test, feature = "fakes")))]
 pub const DEBUG: bool = cfg!(debug_assertions);
 
-/// Whether to enable assertions in assembly code.
-#[cfg(not(any(test, feature = "fakes")))]
+/// Whether to enable assertions in assembly code, and `early_assert!` in Rust code.
 pub const ENABLE_ASSERTIONS: bool = DEBUG;
 
 /// Whether to enable crash reporting in assembly code.
@@ -33,6 +35,98 @@ impl CrashBuffer {
     pub const EMPTY: Self = Self([0; CRASH_BUFFER_REGISTER_COUNT]);
 }
 
+/// Where `early_assert!` reports failures.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum EarlyConsole {
+    /// The platform's crash console.
+    CrashConsole,
+    /// The debug channel of a semihosting host, such as a model or emulator.
+    Semihosting,
+}
+
+/// Asserts that a condition is true, if `ENABLE_ASSERTIONS` is set.
+///
+/// Unlike `assert!`, this reports failures without relying on the logger, so it may be used during
+/// early boot before the logger has been initialised. Failures are written to the console selected
+/// by `Platform::EARLY_CONSOLE`, and then `Platform::panic_handler` is called.
+///
+/// The first argument is the platform type, followed by the condition and an optional message
+/// with format arguments, as for `assert!`.
+#[macro_export]
+macro_rules! early_assert {
+    ($platform:ty, $condition:expr $(,)?) => {
+        $crate::early_assert!($platform, $condition, "{}", stringify!($condition))
+    };
+    ($platform:ty, $condition:expr, $($arg:tt)+) => {
+        if $crate::debug::ENABLE_ASSERTIONS && !$condition {
+            $crate::debug::early_assert_failed::<$platform>(
+                file!(),
+                line!(),
+                format_args!($($arg)+),
+            );
+        }
+    };
+}
+
+/// Reports a failed `early_assert!` and then stops.
+#[cfg(all(target_arch = "aarch64", not(any(test, feature = "fakes"))))]
+#[cold]
+pub fn early_assert_failed<PlatformImpl: Platform>(file: &str, line: u32, message: Arguments) -> ! {
+    match PlatformImpl::EARLY_CONSOLE {
+        EarlyConsole::CrashConsole => {
+            if PlatformImpl::crash_console_init() != 0 {
+                let _ = writeln!(
+                    CrashConsoleWriter::<PlatformImpl>(PhantomData),
+                    "ASSERT: {file}:{line}: {message}"
+                );
+                PlatformImpl::crash_console_flush();
+            }
+        }
+        EarlyConsole::Semihosting => {
+            let _ = writeln!(SemihostingWriter, "ASSERT: {file}:{line}: {message}");
+        }
+    }
+    PlatformImpl::panic_handler()
+}
+
+/// Reports a failed `early_assert!` by panicking, as there is no crash console in tests.
+#[cfg(not(all(target_arch = "aarch64", not(any(test, feature = "fakes")))))]
+#[cold]
+pub fn early_assert_failed<PlatformImpl: Platform>(file: &str, line: u32, message: Arguments) -> ! {
+    panic!("ASSERT: {file}:{line}: {message}")
+}
+
+/// Writes to the platform's crash console, which must already have been initialised.
+#[cfg(all(target_arch = "aarch64", not(any(test, feature = "fakes"))))]
+pub(crate) struct CrashConsoleWriter<PlatformImpl>(pub(crate) PhantomData<PlatformImpl>);
+
+#[cfg(all(target_arch = "aarch64", not(any(test, feature = "fakes"))))]
+impl<PlatformImpl: Platform> Write for CrashConsoleWriter<PlatformImpl> {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        for byte in s.bytes() {
+            if byte == b'\n' {
+                PlatformImpl::crash_console_putc(b'\r'.into());
+            }
+            PlatformImpl::crash_console_putc(byte.into());
+        }
+        Ok(())
+    }
+}
+
+/// Writes to the semihosting debug channel.
+#[cfg(all(target_arch = "aarch64", not(any(test, feature = "fakes"))))]
+struct SemihostingWriter;
+
+#[cfg(all(target_arch = "aarch64", not(any(test, feature = "fakes"))))]
+impl Write for SemihostingWriter {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        for byte in s.bytes() {
+            crate::semihosting::semihosting_write_char(byte);
+        }
+        Ok(())
+    }
+}
+
 /// Generates a `global_asm!` block for debug-related assembly code.
 #[cfg(all(target_arch = "aarch64", not(any(test, feature = "fakes"))))]
 #[macro_export]
@@ -43,7 +137,7 @@ macro_rules! debug_asm {
 
         mod debug_asm {
             use super::PlatformImplDebug_ as PlatformImpl;
-            use $crate::platform::Platform;
+            use $crate::platform::{Board, Platform};
 
             core::arch::global_asm!(
                 include_str!("asm_macros_common.S"),
@@ -58,6 +152,9 @@ macro_rules! debug_asm {
                 ENABLE_PAUTH = const cfg!(feature = "pauth") as u32,
                 MODE_SP_ELX = const 1,
                 CPU_DATA_CRASH_BUFFER_OFFSET = const core::mem::offset_of!($crate::context::CpuData, crash_buffer),
+                CPU_DATA_IN_CRASH_HANDLER_OFFSET = const $crate::context::CPU_DATA_IN_CRASH_HANDLER_OFFSET,
+                CPU_DATA_DOUBLE_FAULT_OFFSET = const core::mem::offset_of!($crate::context::CpuData, double_fault),
+                DOUBLE_FAULT_SIGNATURE = const $crate::crash_dump::DOUBLE_FAULT_SIGNATURE,
                 CRASH_BUFFER_SIZE = const size_of::<$crate::debug::CrashBuffer>(),
                 REGSZ = const size_of::<u64>(),
                 plat_crash_console_init = sym PlatformImpl::crash_console_init,
@@ -65,6 +162,7 @@ macro_rules! debug_asm {
                 plat_crash_console_flush = sym PlatformImpl::crash_console_flush,
                 plat_crash_print_regs = sym PlatformImpl::dump_registers,
                 plat_panic_handler = sym PlatformImpl::panic_handler,
+                plat_crash_reset = sym PlatformImpl::crash_reset,
                 cpu_dump_registers = sym $crate::cpu::cpu_dump_registers::<PlatformImpl>,
             );
         }
@@ -73,3 +171,20 @@ macro_rules! debug_asm {
 #[allow(clippy::single_component_path_imports)]
 #[cfg(all(target_arch = "aarch64", not(any(test, feature = "fakes"))))]
 pub use debug_asm;
+
+#[cfg(test)]
+mod tests {
+    use crate::platform::test::TestPlatform;
+
+    #[test]
+    fn early_assert_passes() {
+        early_assert!(TestPlatform, 1 + 1 == 2);
+        early_assert!(TestPlatform, true, "message {}", 42);
+    }
+
+    #[test]
+    #[should_panic(expected = "ASSERT: src/debug.rs")]
+    fn early_assert_fails() {
+        early_assert!(TestPlatform, 1 + 1 == 3, "maths is broken");
+    }
+}
@@ -47,7 +47,7 @@ pub const fn const_zeroed<T: FromZeros>() -> T {
 macro_rules! zeroed_mut {
     ($(#[$attributes:meta])* $visibility:vis $name:ident, $t:ty $(, $raw_attributes:meta)*) => {
         $(#[$attributes])*
-        $visibility static $name: $crate::reexports::spin::mutex::SpinMutex<&'static mut $t> = $crate::reexports::spin::mutex::SpinMutex::new({
+        $visibility static $name: $crate::spin_mutex::SpinMutex<&'static mut $t> = $crate::spin_mutex::SpinMutex::new({
             $(#[$raw_attributes])*
             static mut RAW: $t = $crate::dram::const_zeroed();
             // SAFETY: This is the only place where we create a reference to the contents of this
@@ -109,10 +109,10 @@ mod tests {
     use crate::{
         context::PerCoreState,
         platform::{Platform, exception_free, test::TestPlatform},
+        spin_mutex::SpinMutex,
     };
     use core::cell::RefCell;
     use percore::{ExceptionLock, PerCore};
-    use spin::mutex::SpinMutex;
 
     #[test]
     fn use_zeroed() {
@@ -7,6 +7,10 @@
 #[cfg(all(target_arch = "aarch64", not(any(test, feature = "fakes"))))]
 pub mod dsu;
 
+use crate::cpu::CpuOps;
+use arm_sysregs::{MidrEl1, read_midr_el1};
+use log::debug;
+
 /// A unique identifier for an erratum.
 pub type ErratumId = u32;
 
@@ -30,6 +34,28 @@ impl RevisionVariant {
 
     /// A sentinel value for errata that are not yet fixed.
     pub const NOT_FIXED: Self = Self::new(u8::MAX, u8::MAX);
+
+    /// Returns the revision and variant fields of the given MIDR.
+    pub const fn from_midr(midr: MidrEl1) -> Self {
+        Self::new(midr.revision(), midr.variant())
+    }
+}
+
+/// Returns whether an erratum affecting the CPU identified by `cpu_midr`, from revision and variant
+/// `apply_from` up to but not including `fixed_in`, applies to a CPU with the given MIDR.
+///
+/// This is the same check as `implement_erratum_check!` does in assembly, for use from Rust code
+/// which runs with a stack.
+pub fn midr_in_range(
+    midr: MidrEl1,
+    cpu_midr: MidrEl1,
+    apply_from: RevisionVariant,
+    fixed_in: RevisionVariant,
+) -> bool {
+    let revision_variant = RevisionVariant::from_midr(midr);
+    midr & CpuOps::MIDR_MASK == cpu_midr & CpuOps::MIDR_MASK
+        && apply_from <= revision_variant
+        && revision_variant < fixed_in
 }
 
 /// Specifies when an erratum workaround should be applied.
@@ -75,6 +101,9 @@ pub struct ErratumEntry {
     /// The unique ID of the erratum workaround.
     pub id: ErratumId,
 
+    /// The CVE number of the erratum, or 0 if there is none.
+    pub cve: Cve,
+
     /// The time at which the erratum workaround should be applied.
     pub apply_on: ErratumType,
 
@@ -90,6 +119,7 @@ impl ErratumEntry {
     pub const fn from_erratum<T: Erratum>() -> Self {
         Self {
             id: T::ID,
+            cve: T::CVE,
             apply_on: T::APPLY_ON,
             check: T::check,
             workaround: T::workaround,
@@ -105,6 +135,31 @@ pub fn erratum_applies<PlatformImpl: PlatformErrata>(id: ErratumId) -> bool {
         .any(|erratum| erratum.id == id && (erratum.check)())
 }
 
+/// Logs which of the platform's errata workarounds apply to the current CPU.
+///
+/// Reset workarounds have already been applied by the time this is called, and runtime workarounds
+/// are applied where needed, so this is only informational. It should be called once on each core
+/// when it boots.
+pub fn report_errata<PlatformImpl: PlatformErrata>() {
+    let midr = read_midr_el1();
+    for erratum in PlatformImpl::ERRATA_LIST {
+        if (erratum.check)() {
+            let when = match erratum.apply_on {
+                ErratumType::Reset => "applied at reset",
+                ErratumType::Runtime => "applied at runtime",
+            };
+            if erratum.cve == 0 {
+                debug!("Erratum {} {when} on CPU {midr:#x}", erratum.id);
+            } else {
+                debug!(
+                    "Erratum {} (CVE {}) {when} on CPU {midr:#x}",
+                    erratum.id, erratum.cve
+                );
+            }
+        }
+    }
+}
+
 /// Methods to access the errata for the platform.
 ///
 /// Implemented for the platform by the `define_errata_list!` macro, platforms shouldn't implement
@@ -309,3 +364,74 @@ macro_rules! implement_erratum_check {
     };
 }
 pub use implement_erratum_check;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The MIDR_EL1 fields, as (shift, mask) pairs.
+    const MIDR_FIELDS: [(u32, u64); 5] = [
+        (MidrEl1::REVISION_SHIFT, MidrEl1::REVISION_MASK),
+        (MidrEl1::PARTNUM_SHIFT, MidrEl1::PARTNUM_MASK),
+        (MidrEl1::ARCHITECTURE_SHIFT, MidrEl1::ARCHITECTURE_MASK),
+        (MidrEl1::VARIANT_SHIFT, MidrEl1::VARIANT_MASK),
+        (MidrEl1::IMPLEMENTER_SHIFT, MidrEl1::IMPLEMENTER_MASK),
+    ];
+
+    #[test]
+    fn midr_field_layout() {
+        let mut covered = 0;
+        for (shift, mask) in MIDR_FIELDS {
+            // Masks must be contiguous from bit 0, and must not overlap any other field once
+            // shifted.
+            assert_eq!(mask & (mask + 1), 0);
+            assert_eq!(covered & (mask << shift), 0);
+            covered |= mask << shift;
+        }
+        assert_eq!(covered, 0xffff_ffff);
+
+        // `implement_erratum_check` inserts the revision from bit 0 of MIDR_EL1 with `bfi`, and
+        // packs the variant and revision together into a single byte.
+        assert_eq!(MidrEl1::REVISION_SHIFT, 0);
+        assert!(MidrEl1::VARIANT_MASK.count_ones() + MidrEl1::REVISION_MASK.count_ones() <= 8);
+    }
+
+    #[test]
+    fn midr_range() {
+        // Cortex-A710 with variant and revision both 1.
+        const CPU: MidrEl1 = MidrEl1::from_bits_retain(0x411F_D471);
+        let other_cpu = MidrEl1::from_bits_retain(0x411F_D491);
+
+        assert_eq!(RevisionVariant::from_midr(CPU), RevisionVariant::new(1, 1));
+        assert!(midr_in_range(
+            CPU,
+            CPU,
+            RevisionVariant::new(0, 0),
+            RevisionVariant::new(2, 0),
+        ));
+        assert!(midr_in_range(
+            CPU,
+            CPU,
+            RevisionVariant::new(1, 1),
+            RevisionVariant::NOT_FIXED,
+        ));
+        assert!(!midr_in_range(
+            CPU,
+            CPU,
+            RevisionVariant::new(0, 0),
+            RevisionVariant::new(1, 1),
+        ));
+        assert!(!midr_in_range(
+            CPU,
+            CPU,
+            RevisionVariant::new(1, 2),
+            RevisionVariant::NOT_FIXED,
+        ));
+        assert!(!midr_in_range(
+            other_cpu,
+            CPU,
+            RevisionVariant::new(0, 0),
+            RevisionVariant::NOT_FIXED,
+        ));
+    }
+}
@@ -3,31 +3,41 @@
 // SPDX-License-Identifier: BSD-3-Clause
 
 use crate::{
-    context::{CpuStateAccess, World, world_context},
-    platform::exception_free,
+    context::{CpuDataIndex, CpuStateAccess, World, handle_feature_trap, world_context},
+    platform::{Platform, exception_free},
     smccc::SmcReturn,
 };
 use arm_sysregs::{
     ElrEl1, ElrEl2, EsrEl1, EsrEl2, EsrEl3, ExceptionLevel, GcscrEl1, GcscrEl2, HcrEl2, ScrEl3,
     SctlrEl1, SctlrEl2, SpsrEl1, SpsrEl2, SpsrEl3, StackPointer, read_gcscr_el1, read_gcscr_el2,
-    read_hcr_el2, read_id_aa64dfr1_el1, read_id_aa64mmfr1_el1, read_id_aa64pfr1_el1,
-    read_sctlr_el1, read_sctlr_el2, read_vbar_el1, read_vbar_el2, write_elr_el1, write_elr_el2,
-    write_esr_el1, write_esr_el2, write_spsr_el1, write_spsr_el2,
+    read_hcr_el2, read_id_aa64dfr1_el1, read_id_aa64mmfr1_el1, read_id_aa64pfr0_el1,
+    read_id_aa64pfr1_el1, read_sctlr_el1, read_sctlr_el2, read_vbar_el1, read_vbar_el2,
+    write_elr_el1, write_elr_el2, write_esr_el1, write_esr_el2, write_spsr_el1, write_spsr_el2,
 };
 #[cfg(not(any(test, feature = "fakes")))]
 use core::arch::asm;
-use core::fmt::Debug;
+use core::{fmt::Debug, marker::PhantomData};
 use log::trace;
 
 // Exception vector offsets.
 const CURRENT_EL_SP0: usize = 0x0;
 const CURRENT_EL_SPX: usize = 0x200;
 const LOWER_EL_AARCH64: usize = 0x400;
+const LOWER_EL_AARCH32: usize = 0x600;
+
+/// The AArch32 DIT bit in `SPSR_EL3`, which is at a different position to the AArch64 one.
+const SPSR_AARCH32_DIT: u64 = 1 << 21;
+
+/// The bit of an SMC function ID which indicates the SMC64 calling convention.
+pub const FUNCID_CC_SHIFT: u32 = 30;
 
 /// Handler for injecting undefined exception to lower EL caused by the lower EL accessing system
 /// registers of which EL3 firmware is unaware.
 ///
-/// This is a safety net to avoid EL3 panics caused by system register access.
+/// This is a safety net to avoid EL3 panics caused by system register access. The lower EL may be
+/// in AArch32 state, but the exception is always taken in AArch64 state: if the EL which would
+/// normally take it is AArch32 EL1 then it is injected into the world's EL2 instead, as RF-A can't
+/// set up the AArch32 banked registers.
 pub fn inject_undef64<PlatformImpl: CpuStateAccess>(world: World) {
     exception_free(|token| {
         let mut cpu_state = PlatformImpl::cpu_state(token);
@@ -35,10 +45,12 @@ pub fn inject_undef64<PlatformImpl: CpuStateAccess>(world: World) {
 
         let elr_el3 = el3_state.elr_el3;
         let old_spsr = el3_state.spsr_el3;
-        let to_el = target_el(old_spsr.exception_level(), world_context(world).scr_el3);
-
-        if old_spsr.contains(SpsrEl3::M_4) {
-            panic!("Trying to inject undefined exception to lower EL in AArch32 mode")
+        let scr = world_context(world).scr_el3;
+        let from_el = spsr_exception_level(old_spsr);
+        let mut to_el = target_el(from_el, scr);
+        if to_el == ExceptionLevel::El1 && is_el1_aarch32(old_spsr, scr) {
+            // EL1 can only be in AArch32 state under an AArch64 EL2.
+            to_el = ExceptionLevel::El2;
         }
 
         let vbar;
@@ -74,6 +86,36 @@ pub fn inject_undef64<PlatformImpl: CpuStateAccess>(world: World) {
     });
 }
 
+/// Returns whether the given saved PSTATE is from AArch32 state.
+fn is_aarch32(spsr_el3: SpsrEl3) -> bool {
+    spsr_el3.contains(SpsrEl3::M_4)
+}
+
+/// Returns the exception level from which an exception with the given saved PSTATE was taken.
+///
+/// In AArch32 state the M field gives the mode rather than the EL: User mode is EL0, and as EL2 and
+/// EL3 are always AArch64 all other modes must be EL1.
+fn spsr_exception_level(spsr_el3: SpsrEl3) -> ExceptionLevel {
+    if !is_aarch32(spsr_el3) {
+        spsr_el3.exception_level()
+    } else if spsr_el3.m_3_0() == 0 {
+        ExceptionLevel::El0
+    } else {
+        ExceptionLevel::El1
+    }
+}
+
+/// Returns whether EL1 of the world with the given saved PSTATE and `SCR_EL3` is in AArch32 state.
+fn is_el1_aarch32(spsr_el3: SpsrEl3, scr: ScrEl3) -> bool {
+    if is_aarch32(spsr_el3) && spsr_exception_level(spsr_el3) == ExceptionLevel::El1 {
+        true
+    } else if read_id_aa64pfr0_el1().el2() != 0 && !is_secure_trap_without_sel2(scr) {
+        !read_hcr_el2().contains(HcrEl2::RW)
+    } else {
+        !scr.contains(ScrEl3::RW)
+    }
+}
+
 /// Returns the exception level at which an exception should be injected, based on the exception
 /// level which caused the original exception.
 fn target_el(from_el: ExceptionLevel, scr: ScrEl3) -> ExceptionLevel {
@@ -88,8 +130,10 @@ fn target_el(from_el: ExceptionLevel, scr: ScrEl3) -> ExceptionLevel {
 
 /// Calculates the exception vector which should be run at the lower EL.
 fn find_exception_vector(spsr_el3: SpsrEl3, vbar: usize, target_el: ExceptionLevel) -> usize {
-    let outgoing_el = spsr_el3.exception_level();
-    if outgoing_el == target_el {
+    let outgoing_el = spsr_exception_level(spsr_el3);
+    if is_aarch32(spsr_el3) {
+        vbar + LOWER_EL_AARCH32
+    } else if outgoing_el == target_el {
         if spsr_el3.stack_pointer() == StackPointer::ElX {
             vbar + CURRENT_EL_SPX
         } else {
@@ -121,7 +165,7 @@ fn is_secure_trap_without_sel2(scr: ScrEl3) -> bool {
 ///
 /// NOTE: This piece of code must be reviewed every release to ensure that we keep up with new ARCH
 /// features which introduces a new SPSR bit.
-fn create_spsr(old_spsr: SpsrEl3, target_el: ExceptionLevel) -> SpsrEl3 {
+pub(crate) fn create_spsr(old_spsr: SpsrEl3, target_el: ExceptionLevel) -> SpsrEl3 {
     let mut new_spsr = SpsrEl3::empty();
     let sctlr_el1 = read_sctlr_el1();
     let sctlr_el2 = read_sctlr_el2();
@@ -167,7 +211,13 @@ fn create_spsr(old_spsr: SpsrEl3, target_el: ExceptionLevel) -> SpsrEl3 {
     }
 
     // DIT bits are unchanged
-    new_spsr |= old_spsr & SpsrEl3::DIT;
+    if is_aarch32(old_spsr) {
+        if old_spsr.bits() & SPSR_AARCH32_DIT != 0 {
+            new_spsr |= SpsrEl3::DIT;
+        }
+    } else {
+        new_spsr |= old_spsr & SpsrEl3::DIT;
+    }
 
     // If FEAT_MTE is implemented, mask tag faults by setting TCO bit
     if read_id_aa64pfr1_el1().is_feat_mte_present() {
@@ -200,6 +250,111 @@ fn create_spsr(old_spsr: SpsrEl3, target_el: ExceptionLevel) -> SpsrEl3 {
     new_spsr
 }
 
+/// The exception class of a synchronous exception, from the EC field of `ESR_EL3`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ExceptionClass {
+    /// An FP/SIMD access trapped by `CPTR_EL3.TFP`.
+    FpTrap,
+    /// An `SMC` instruction executed in AArch32 state.
+    Smc32,
+    /// An `HVC` instruction executed in AArch64 state.
+    Hvc64,
+    /// An `SMC` instruction executed in AArch64 state.
+    Smc64,
+    /// A trapped `MSR`, `MRS` or system instruction executed in AArch64 state.
+    SysregTrap,
+    /// An SVE access trapped by `CPTR_EL3.EZ`.
+    SveTrap,
+    /// An SME access trapped by `CPTR_EL3.ESM`.
+    SmeTrap,
+    /// An IMPLEMENTATION DEFINED exception to EL3.
+    ImpDefEl3,
+    /// An instruction abort from a lower EL.
+    InstructionAbortLowerEl,
+    /// A data abort from a lower EL.
+    DataAbortLowerEl,
+    /// Any other exception class, which EL3 doesn't expect.
+    Other(u8),
+}
+
+impl ExceptionClass {
+    /// Decodes the exception class from the given syndrome.
+    pub const fn from_esr(esr: EsrEl3) -> Self {
+        match esr.ec() {
+            0x07 => Self::FpTrap,
+            0x13 => Self::Smc32,
+            0x16 => Self::Hvc64,
+            0x17 => Self::Smc64,
+            0x18 => Self::SysregTrap,
+            0x19 => Self::SveTrap,
+            0x1d => Self::SmeTrap,
+            0x1f => Self::ImpDefEl3,
+            0x20 => Self::InstructionAbortLowerEl,
+            0x24 => Self::DataAbortLowerEl,
+            ec => Self::Other(ec),
+        }
+    }
+
+    /// Returns the value of the EC field for this exception class.
+    pub const fn code(self) -> u8 {
+        match self {
+            Self::FpTrap => 0x07,
+            Self::Smc32 => 0x13,
+            Self::Hvc64 => 0x16,
+            Self::Smc64 => 0x17,
+            Self::SysregTrap => 0x18,
+            Self::SveTrap => 0x19,
+            Self::SmeTrap => 0x1d,
+            Self::ImpDefEl3 => 0x1f,
+            Self::InstructionAbortLowerEl => 0x20,
+            Self::DataAbortLowerEl => 0x24,
+            Self::Other(ec) => ec,
+        }
+    }
+}
+
+/// What the synchronous exception vector does with an exception taken from a lower EL.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SyncExceptionAction {
+    /// Return to Rust with [`RunResult::Smc`].
+    Smc,
+    /// Return `SMC_UNK` directly, because AArch32 state tried to make an SMC64 call.
+    SmcProhibited,
+    /// Return to Rust with [`RunResult::SysregTrap`].
+    SysregTrap,
+    /// Return to Rust with [`RunResult::Hvc`].
+    Hvc,
+    /// Return to Rust for [`enter_world`] to handle the trap with the CPU extensions.
+    FeatureTrap,
+    /// Handle an IMPLEMENTATION DEFINED exception to EL3.
+    ImpDef,
+    /// Report an unhandled exception and panic.
+    Unhandled,
+}
+
+impl SyncExceptionAction {
+    /// Decides how to handle a synchronous exception from a lower EL with the given syndrome and
+    /// value of x0.
+    ///
+    /// This must match the decisions made by the `handle_sync_exception` assembly macro and
+    /// `sync_exception_handler`, which run before any Rust code.
+    pub const fn decide(esr: EsrEl3, x0: u64) -> Self {
+        match ExceptionClass::from_esr(esr) {
+            ExceptionClass::Smc32 if x0 & (1 << FUNCID_CC_SHIFT) != 0 => Self::SmcProhibited,
+            ExceptionClass::Smc32 | ExceptionClass::Smc64 => Self::Smc,
+            ExceptionClass::SysregTrap => Self::SysregTrap,
+            ExceptionClass::Hvc64 => Self::Hvc,
+            ExceptionClass::FpTrap | ExceptionClass::SveTrap | ExceptionClass::SmeTrap => {
+                Self::FeatureTrap
+            }
+            ExceptionClass::ImpDefEl3 => Self::ImpDef,
+            ExceptionClass::InstructionAbortLowerEl
+            | ExceptionClass::DataAbortLowerEl
+            | ExceptionClass::Other(_) => Self::Unhandled,
+        }
+    }
+}
+
 /// Describes the reason why execution returned to EL3 after running a lower EL.
 #[derive(Debug)]
 pub enum RunResult {
@@ -209,12 +364,75 @@ pub enum RunResult {
     Interrupt,
     /// A lower EL tried to access a system register that was trapped to EL3.
     SysregTrap { esr: EsrEl3 },
+    /// A lower EL executed an HVC instruction which was taken to EL3. The architecture never routes
+    /// this to EL3, so it can only happen erroneously.
+    Hvc,
 }
 
 impl RunResult {
     pub const SMC: u64 = 0;
     pub const INTERRUPT: u64 = 1;
     pub const SYSREG_TRAP: u64 = 2;
+    pub const HVC: u64 = 3;
+    /// A trapped FP/SIMD, SVE or SME access, which [`enter_world`] handles itself rather than
+    /// returning.
+    pub const FEATURE_TRAP: u64 = 4;
+
+    /// Decodes the reason code, syndrome and lower EL x0 returned by `el3_exit`.
+    ///
+    /// Panics if the reason code isn't one of the values above, or the syndrome is for an
+    /// exception which the exception vectors should never have returned to Rust for.
+    pub fn decode(return_reason: u64, esr: u64, x0: u64) -> Self {
+        match return_reason {
+            Self::INTERRUPT => Self::Interrupt,
+            Self::SMC | Self::SYSREG_TRAP | Self::HVC => {
+                let esr = EsrEl3::from_bits_retain(esr);
+                let result = match SyncExceptionAction::decide(esr, x0) {
+                    SyncExceptionAction::Smc => Self::Smc,
+                    SyncExceptionAction::SysregTrap => Self::SysregTrap { esr },
+                    SyncExceptionAction::Hvc => Self::Hvc,
+                    action => panic!("unexpected {action:?} for exception {esr:?}"),
+                };
+                debug_assert_eq!(
+                    return_reason,
+                    match result {
+                        Self::SysregTrap { .. } => Self::SYSREG_TRAP,
+                        Self::Hvc => Self::HVC,
+                        _ => Self::SMC,
+                    }
+                );
+                result
+            }
+            r => panic!("unhandled enter world result: {r}"),
+        }
+    }
+}
+
+/// Marks the current core as handling an exception from a lower EL for as long as it exists, so
+/// that [`enter_world`] can limit how deeply EL3 enters lower ELs again while doing so.
+pub struct ExceptionHandlerScope<PlatformImpl: CpuDataIndex>(PhantomData<fn() -> PlatformImpl>);
+
+impl<PlatformImpl: CpuDataIndex> ExceptionHandlerScope<PlatformImpl> {
+    /// Records that the current core has started handling an exception from a lower EL, until the
+    /// returned scope is dropped.
+    pub fn enter() -> Self {
+        exception_free(|token| {
+            PlatformImpl::update_cpu_data(token, |cpu_data| {
+                cpu_data.enter_exception_handler();
+            })
+        });
+        Self(PhantomData)
+    }
+}
+
+impl<PlatformImpl: CpuDataIndex> Drop for ExceptionHandlerScope<PlatformImpl> {
+    fn drop(&mut self) {
+        exception_free(|token| {
+            PlatformImpl::update_cpu_data(token, |cpu_data| {
+                cpu_data.exit_exception_handler();
+            })
+        });
+    }
 }
 
 /// Enters a lower EL in the specified world.
@@ -225,9 +443,27 @@ impl RunResult {
 /// in the `in_regs` parameter, those values will be copied into the lower EL's saved context before
 /// the ERET. After execution returns to EL3 by any exception, the reason for returning is checked
 /// and the appropriate result will be returned by this function.
-pub fn enter_world<PlatformImpl: CpuStateAccess>(regs: &mut SmcReturn, world: World) -> RunResult {
+///
+/// FP/SIMD, SVE and SME accesses trapped to EL3 are handled here by the CPU extensions, which may
+/// switch those registers lazily, and the lower EL is entered again.
+///
+/// Panics if the current core is already handling more than `Board::MAX_EXCEPTION_NESTING`
+/// exceptions from lower ELs, as each one uses more of the EL3 stack.
+pub fn enter_world<PlatformImpl: CpuDataIndex + CpuStateAccess + Platform>(
+    regs: &mut SmcReturn,
+    world: World,
+) -> RunResult {
     trace!("Entering world {world:?} with args {regs:x?}");
 
+    let mut nesting = 0;
+    exception_free(|token| {
+        PlatformImpl::update_cpu_data(token, |cpu_data| nesting = cpu_data.exception_nesting());
+    });
+    assert!(
+        nesting <= PlatformImpl::MAX_EXCEPTION_NESTING,
+        "Exception nesting {nesting} too deep to enter {world:?}"
+    );
+
     if !regs.is_empty() {
         exception_free(|token| {
             PlatformImpl::cpu_state(token)[world]
@@ -239,68 +475,71 @@ pub fn enter_world<PlatformImpl: CpuStateAccess>(regs: &mut SmcReturn, world: Wo
     let context = PlatformImpl::world_cpu_context(world);
     let per_world_context = world_context(world);
     let out_values = regs.mark_all_used();
-    let return_reason: u64;
-    let esr: u64;
-
-    // SAFETY: The CPU context is always valid, and will only be used via this pointer by assembly
-    // code after the Rust code returns to prepare for the eret, and after the next exception before
-    // entering the Rust code again.
-    #[cfg(not(any(test, feature = "fakes")))]
-    unsafe {
-        asm!(
-            // Save x19 and x29 manually as Rust won't let us specify them as clobbers.
-            "stp x19, x29, [sp, #-16]!",
-            "bl el3_exit",
-            "ldp x19, x29, [sp], #16",
-            inout("x0") context => out_values[0],
-            inout("x1") per_world_context => out_values[1],
-            out("x2") out_values[2],
-            out("x3") out_values[3],
-            out("x4") out_values[4],
-            out("x5") out_values[5],
-            out("x6") out_values[6],
-            out("x7") out_values[7],
-            out("x8") out_values[8],
-            out("x9") out_values[9],
-            out("x10") out_values[10],
-            out("x11") out_values[11],
-            out("x12") out_values[12],
-            out("x13") out_values[13],
-            out("x14") out_values[14],
-            out("x15") out_values[15],
-            out("x16") out_values[16],
-            out("x17") out_values[17],
-            out("x18") return_reason,
-            out("x20") esr,
-            out("x21") _,
-            out("x22") _,
-            out("x23") _,
-            out("x24") _,
-            out("x25") _,
-            out("x26") _,
-            out("x27") _,
-            out("x28") _,
-            out("x30") _,
-        );
-    }
-    #[cfg(any(test, feature = "fakes"))]
-    {
-        let _ = context;
-        let _ = per_world_context;
-        out_values[0] = 42;
-        return_reason = RunResult::SMC;
-        esr = 0;
-    }
+    let (return_reason, esr) = loop {
+        let return_reason: u64;
+        let esr: u64;
+
+        // SAFETY: The CPU context is always valid, and will only be used via this pointer by
+        // assembly code after the Rust code returns to prepare for the eret, and after the next
+        // exception before entering the Rust code again.
+        #[cfg(not(any(test, feature = "fakes")))]
+        unsafe {
+            asm!(
+                // Save x19 and x29 manually as Rust won't let us specify them as clobbers.
+                "stp x19, x29, [sp, #-16]!",
+                "bl el3_exit",
+                "ldp x19, x29, [sp], #16",
+                inout("x0") context => out_values[0],
+                inout("x1") per_world_context => out_values[1],
+                out("x2") out_values[2],
+                out("x3") out_values[3],
+                out("x4") out_values[4],
+                out("x5") out_values[5],
+                out("x6") out_values[6],
+                out("x7") out_values[7],
+                out("x8") out_values[8],
+                out("x9") out_values[9],
+                out("x10") out_values[10],
+                out("x11") out_values[11],
+                out("x12") out_values[12],
+                out("x13") out_values[13],
+                out("x14") out_values[14],
+                out("x15") out_values[15],
+                out("x16") out_values[16],
+                out("x17") out_values[17],
+                out("x18") return_reason,
+                out("x20") esr,
+                out("x21") _,
+                out("x22") _,
+                out("x23") _,
+                out("x24") _,
+                out("x25") _,
+                out("x26") _,
+                out("x27") _,
+                out("x28") _,
+                out("x30") _,
+            );
+        }
+        #[cfg(any(test, feature = "fakes"))]
+        {
+            let _ = context;
+            let _ = per_world_context;
+            out_values[0] = 42;
+            return_reason = RunResult::SMC;
+            esr = u64::from(ExceptionClass::Smc64.code()) << 26;
+        }
 
-    let result = match return_reason {
-        RunResult::SMC => RunResult::Smc,
-        RunResult::INTERRUPT => RunResult::Interrupt,
-        RunResult::SYSREG_TRAP => RunResult::SysregTrap {
-            esr: EsrEl3::from_bits_retain(esr),
-        },
-        r => panic!("unhandled enter world result: {r}"),
+        if return_reason != RunResult::FEATURE_TRAP {
+            break (return_reason, esr);
+        }
+        // Once the registers which the lower EL tried to access have been made available to it,
+        // or an undefined instruction exception injected if it may not use them, run it again
+        // without the caller ever seeing the trap.
+        handle_feature_trap::<PlatformImpl>(world, EsrEl3::from_bits_retain(esr));
     };
 
+    let result = RunResult::decode(return_reason, esr, out_values[0]);
+
     trace!("Returned from world {world:?} with result {result:?}");
 
     result
@@ -324,4 +563,156 @@ mod tests {
             "SysregTrap { esr: EsrEl3(0x12345) }"
         );
     }
+
+    #[test]
+    fn decode_sync_exceptions() {
+        const SMC32_FID: u64 = 0x8400_0000;
+        const SMC64_FID: u64 = 0xC400_0000;
+
+        let cases = [
+            (
+                0x13,
+                SMC32_FID,
+                ExceptionClass::Smc32,
+                SyncExceptionAction::Smc,
+            ),
+            (
+                0x13,
+                SMC64_FID,
+                ExceptionClass::Smc32,
+                SyncExceptionAction::SmcProhibited,
+            ),
+            (0x16, 0, ExceptionClass::Hvc64, SyncExceptionAction::Hvc),
+            (
+                0x17,
+                SMC32_FID,
+                ExceptionClass::Smc64,
+                SyncExceptionAction::Smc,
+            ),
+            (
+                0x17,
+                SMC64_FID,
+                ExceptionClass::Smc64,
+                SyncExceptionAction::Smc,
+            ),
+            (
+                0x18,
+                0,
+                ExceptionClass::SysregTrap,
+                SyncExceptionAction::SysregTrap,
+            ),
+            (
+                0x07,
+                0,
+                ExceptionClass::FpTrap,
+                SyncExceptionAction::FeatureTrap,
+            ),
+            (
+                0x19,
+                0,
+                ExceptionClass::SveTrap,
+                SyncExceptionAction::FeatureTrap,
+            ),
+            (
+                0x1d,
+                0,
+                ExceptionClass::SmeTrap,
+                SyncExceptionAction::FeatureTrap,
+            ),
+            (
+                0x1f,
+                0,
+                ExceptionClass::ImpDefEl3,
+                SyncExceptionAction::ImpDef,
+            ),
+            (
+                0x20,
+                0,
+                ExceptionClass::InstructionAbortLowerEl,
+                SyncExceptionAction::Unhandled,
+            ),
+            (
+                0x24,
+                0,
+                ExceptionClass::DataAbortLowerEl,
+                SyncExceptionAction::Unhandled,
+            ),
+            (
+                0x00,
+                0,
+                ExceptionClass::Other(0),
+                SyncExceptionAction::Unhandled,
+            ),
+        ];
+
+        for (ec, x0, class, action) in cases {
+            // Set some ISS and IL bits too, which shouldn't affect the decoding.
+            let esr = EsrEl3::from_bits_retain(u64::from(ec) << 26 | 1 << 25 | 0x1234);
+            assert_eq!(ExceptionClass::from_esr(esr), class, "EC {ec:#x}");
+            assert_eq!(class.code(), ec);
+            assert_eq!(
+                SyncExceptionAction::decide(esr, x0),
+                action,
+                "EC {ec:#x}, x0 {x0:#x}"
+            );
+        }
+    }
+
+    #[test]
+    fn decode_run_result() {
+        let smc64 = 0x17 << 26;
+        assert!(matches!(
+            RunResult::decode(RunResult::SMC, smc64, 0xC400_0000),
+            RunResult::Smc
+        ));
+        assert!(matches!(
+            RunResult::decode(RunResult::INTERRUPT, 0, 0),
+            RunResult::Interrupt
+        ));
+        let esr = 0x18 << 26 | 0x1234;
+        assert!(matches!(
+            RunResult::decode(RunResult::SYSREG_TRAP, esr, 0),
+            RunResult::SysregTrap { esr: decoded } if decoded.bits() == esr
+        ));
+        assert!(matches!(
+            RunResult::decode(RunResult::HVC, 0x16 << 26, 0),
+            RunResult::Hvc
+        ));
+    }
+
+    #[test]
+    #[should_panic(expected = "unexpected Unhandled")]
+    fn decode_data_abort_run_result() {
+        RunResult::decode(RunResult::SMC, 0x24 << 26, 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "unhandled enter world result: 5")]
+    fn decode_invalid_run_result() {
+        RunResult::decode(5, 0, 0);
+    }
+
+    #[test]
+    fn aarch32_exception_vector() {
+        const VBAR: usize = 0x8000_0000;
+        let usr = SpsrEl3::from_bits_retain(0b10000);
+        let svc = SpsrEl3::from_bits_retain(0b10011);
+        let el0t = SpsrEl3::from_bits_retain(0b00000);
+
+        assert_eq!(spsr_exception_level(usr), ExceptionLevel::El0);
+        assert_eq!(spsr_exception_level(svc), ExceptionLevel::El1);
+        assert_eq!(spsr_exception_level(el0t), ExceptionLevel::El0);
+        assert_eq!(
+            find_exception_vector(usr, VBAR, ExceptionLevel::El1),
+            VBAR + LOWER_EL_AARCH32
+        );
+        assert_eq!(
+            find_exception_vector(svc, VBAR, ExceptionLevel::El2),
+            VBAR + LOWER_EL_AARCH32
+        );
+        assert_eq!(
+            find_exception_vector(el0t, VBAR, ExceptionLevel::El1),
+            VBAR + LOWER_EL_AARCH64
+        );
+    }
 }
@@ -0,0 +1,905 @@
+// Copyright The Rusted Firmware-A Contributors.
+//
+// SPDX-License-Identifier: BSD-3-Clause
+
+//! A minimal parser for flattened devicetree blobs, such as the FW_CONFIG, TOS_FW_CONFIG and
+//! HW_CONFIG passed in by earlier boot stages.
+//!
+//! The whole structure block is validated when the blob is first parsed with [`Fdt::new`], so
+//! lookups afterwards can't fail due to a malformed blob, only because what they are looking for
+//! isn't there.
+
+use arrayvec::ArrayVec;
+use zerocopy::{
+    FromBytes, Immutable, KnownLayout,
+    byteorder::{BigEndian, U32},
+};
+
+/// The magic number at the start of every devicetree blob.
+const FDT_MAGIC: u32 = 0xd00d_feed;
+
+/// The version of the devicetree blob format which this parser implements.
+const FDT_VERSION: u32 = 17;
+
+const FDT_BEGIN_NODE: u32 = 0x1;
+const FDT_END_NODE: u32 = 0x2;
+const FDT_PROP: u32 = 0x3;
+const FDT_NOP: u32 = 0x4;
+const FDT_END: u32 = 0x9;
+
+/// The size of a structure block token, and the alignment of everything in the structure block.
+const TOKEN_SIZE: usize = size_of::<u32>();
+
+/// The size of a cell in a property value.
+const CELL_SIZE: usize = size_of::<u32>();
+
+/// The maximum depth of nested nodes supported.
+const MAX_DEPTH: usize = 16;
+
+/// The size of the header at the start of a devicetree blob.
+pub const FDT_HEADER_SIZE: usize = size_of::<FdtHeader>();
+
+/// The header at the start of a devicetree blob.
+#[derive(Clone, Debug, FromBytes, Immutable, KnownLayout)]
+#[repr(C)]
+struct FdtHeader {
+    magic: U32<BigEndian>,
+    totalsize: U32<BigEndian>,
+    off_dt_struct: U32<BigEndian>,
+    off_dt_strings: U32<BigEndian>,
+    off_mem_rsvmap: U32<BigEndian>,
+    version: U32<BigEndian>,
+    last_comp_version: U32<BigEndian>,
+    boot_cpuid_phys: U32<BigEndian>,
+    size_dt_strings: U32<BigEndian>,
+    size_dt_struct: U32<BigEndian>,
+}
+
+/// An error parsing a devicetree blob.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum FdtError {
+    /// The blob doesn't start with the devicetree magic number.
+    BadMagic,
+    /// The blob's format version isn't compatible with this parser.
+    UnsupportedVersion(u32),
+    /// The blob is shorter than its header says it should be.
+    Truncated,
+    /// The structure block or strings block lies outside the blob.
+    BadLayout,
+    /// The structure block is malformed at the given offset.
+    BadStructure(usize),
+    /// Nodes are nested more deeply than this parser supports.
+    TooDeep,
+}
+
+/// A token from the structure block.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Token<'a> {
+    BeginNode(&'a str),
+    EndNode,
+    Property { name_offset: usize, value: &'a [u8] },
+    Nop,
+    End,
+}
+
+/// A parsed devicetree blob.
+#[derive(Clone, Copy, Debug)]
+pub struct Fdt<'a> {
+    structure: &'a [u8],
+    strings: &'a [u8],
+}
+
+impl<'a> Fdt<'a> {
+    /// Returns the total size of the devicetree blob whose header is at the start of `header`.
+    ///
+    /// This only checks the magic number, so callers can find out how much memory the blob covers
+    /// before passing all of it to [`Fdt::new`].
+    pub fn total_size(header: &[u8]) -> Result<usize, FdtError> {
+        let (header, _) = FdtHeader::ref_from_prefix(header).map_err(|_| FdtError::Truncated)?;
+        if header.magic.get() != FDT_MAGIC {
+            return Err(FdtError::BadMagic);
+        }
+        Ok(header.totalsize.get() as usize)
+    }
+
+    /// Parses and validates the devicetree blob at the start of `blob`.
+    ///
+    /// `blob` may be longer than the devicetree blob, in which case the rest is ignored.
+    pub fn new(blob: &'a [u8]) -> Result<Self, FdtError> {
+        let total_size = Self::total_size(blob)?;
+        let (header, _) = FdtHeader::ref_from_prefix(blob).map_err(|_| FdtError::Truncated)?;
+        let version = header.version.get();
+        if version < FDT_VERSION || header.last_comp_version.get() > FDT_VERSION {
+            return Err(FdtError::UnsupportedVersion(version));
+        }
+        let blob = blob.get(..total_size).ok_or(FdtError::Truncated)?;
+
+        let structure = block(
+            blob,
+            header.off_dt_struct.get(),
+            header.size_dt_struct.get(),
+        )?;
+        let strings = block(
+            blob,
+            header.off_dt_strings.get(),
+            header.size_dt_strings.get(),
+        )?;
+        if !(header.off_dt_struct.get() as usize).is_multiple_of(TOKEN_SIZE) {
+            return Err(FdtError::BadLayout);
+        }
+
+        let fdt = Self { structure, strings };
+        fdt.validate()?;
+        Ok(fdt)
+    }
+
+    /// Checks that the structure block is well-formed: a single root node, balanced and not too
+    /// deeply nested, with all property names in the strings block.
+    fn validate(&self) -> Result<(), FdtError> {
+        let mut offset = self.skip_nops(0)?;
+        let (Token::BeginNode(_), mut next) = self.read_token(offset)? else {
+            return Err(FdtError::BadStructure(offset));
+        };
+        let mut depth = 1;
+        while depth > 0 {
+            offset = next;
+            let (token, after) = self.read_token(offset)?;
+            match token {
+                Token::BeginNode(_) => {
+                    depth += 1;
+                    if depth > MAX_DEPTH {
+                        return Err(FdtError::TooDeep);
+                    }
+                }
+                Token::EndNode => depth -= 1,
+                Token::Property { name_offset, .. } => {
+                    self.string(name_offset)
+                        .ok_or(FdtError::BadStructure(offset))?;
+                }
+                Token::Nop => {}
+                Token::End => return Err(FdtError::BadStructure(offset)),
+            }
+            next = after;
+        }
+        offset = self.skip_nops(next)?;
+        match self.read_token(offset)? {
+            (Token::End, _) => Ok(()),
+            _ => Err(FdtError::BadStructure(offset)),
+        }
+    }
+
+    /// Returns the offset of the first token at or after `offset` which isn't a NOP.
+    fn skip_nops(&self, mut offset: usize) -> Result<usize, FdtError> {
+        loop {
+            match self.read_token(offset)? {
+                (Token::Nop, next) => offset = next,
+                _ => return Ok(offset),
+            }
+        }
+    }
+
+    /// Reads the token at the given offset in the structure block, and returns it along with the
+    /// offset of the following token.
+    fn read_token(&self, offset: usize) -> Result<(Token<'a>, usize), FdtError> {
+        let error = FdtError::BadStructure(offset);
+        let token = read_cell(self.structure, offset).ok_or(error)?;
+        let body = offset + TOKEN_SIZE;
+        match token {
+            FDT_BEGIN_NODE => {
+                let rest = self.structure.get(body..).ok_or(error)?;
+                let name = nul_terminated(rest).ok_or(error)?;
+                let next = (body + name.len() + 1).next_multiple_of(TOKEN_SIZE);
+                Ok((Token::BeginNode(name), next))
+            }
+            FDT_END_NODE => Ok((Token::EndNode, body)),
+            FDT_PROP => {
+                let len = read_cell(self.structure, body).ok_or(error)? as usize;
+                let name_offset = read_cell(self.structure, body + CELL_SIZE).ok_or(error)?;
+                let value_start = body + 2 * CELL_SIZE;
+                let value = self
+                    .structure
+                    .get(value_start..value_start + len)
+                    .ok_or(error)?;
+                let next = (value_start + len).next_multiple_of(TOKEN_SIZE);
+                Ok((
+                    Token::Property {
+                        name_offset: name_offset as usize,
+                        value,
+                    },
+                    next,
+                ))
+            }
+            FDT_NOP => Ok((Token::Nop, body)),
+            FDT_END => Ok((Token::End, body)),
+            _ => Err(error),
+        }
+    }
+
+    /// Returns the NUL-terminated string at the given offset in the strings block.
+    fn string(&self, offset: usize) -> Option<&'a str> {
+        nul_terminated(self.strings.get(offset..)?)
+    }
+
+    /// Returns the root node.
+    pub fn root(&self) -> Node<'a> {
+        let offset = self.skip_nops(0).unwrap_or_default();
+        let (name, body) = match self.read_token(offset) {
+            Ok((Token::BeginNode(name), body)) => (name, body),
+            // `validate` checked that the structure block starts with the root node.
+            _ => unreachable!(),
+        };
+        Node {
+            fdt: *self,
+            name,
+            body,
+        }
+    }
+
+    /// Returns the node with the given path.
+    ///
+    /// If the path doesn't start with `/` then its first component is looked up in `/aliases`.
+    pub fn find_node(&self, path: &str) -> Option<Node<'a>> {
+        let (mut node, rest) = if let Some(rest) = path.strip_prefix('/') {
+            (self.root(), rest)
+        } else {
+            let (alias, rest) = path.split_once('/').unwrap_or((path, ""));
+            let target = self.find_node("/aliases")?.property_str(alias)?;
+            if !target.starts_with('/') {
+                return None;
+            }
+            (self.find_node(target)?, rest)
+        };
+        for component in rest.split('/').filter(|component| !component.is_empty()) {
+            node = node.child(component)?;
+        }
+        Some(node)
+    }
+
+    /// Returns the first node in depth-first order which is compatible with the given string.
+    pub fn find_compatible(&self, compatible: &str) -> Option<Node<'a>> {
+        let mut offset = self.root().body;
+        loop {
+            let (token, next) = self.read_token(offset).ok()?;
+            if let Token::BeginNode(name) = token {
+                let node = Node {
+                    fdt: *self,
+                    name,
+                    body: next,
+                };
+                if node.is_compatible(compatible) {
+                    return Some(node);
+                }
+            } else if token == Token::End {
+                return None;
+            }
+            offset = next;
+        }
+    }
+
+    /// Returns the node referred to by `/chosen/stdout-path`, ignoring any options after the path.
+    pub fn stdout(&self) -> Option<Node<'a>> {
+        let stdout_path = self.find_node("/chosen")?.property_str("stdout-path")?;
+        let path = stdout_path
+            .split_once(':')
+            .map_or(stdout_path, |(path, _options)| path);
+        self.find_node(path)
+    }
+
+    /// Returns the ancestors of the given node, starting with the root node.
+    fn ancestors(&self, node: &Node<'a>) -> Option<ArrayVec<Node<'a>, MAX_DEPTH>> {
+        let mut stack = ArrayVec::<Node<'a>, MAX_DEPTH>::new();
+        let mut offset = 0;
+        loop {
+            let (token, next) = self.read_token(offset).ok()?;
+            match token {
+                Token::BeginNode(name) => {
+                    if next == node.body {
+                        return Some(stack);
+                    }
+                    stack
+                        .try_push(Node {
+                            fdt: *self,
+                            name,
+                            body: next,
+                        })
+                        .ok()?;
+                }
+                Token::EndNode => {
+                    stack.pop();
+                }
+                Token::End => return None,
+                Token::Property { .. } | Token::Nop => {}
+            }
+            offset = next;
+        }
+    }
+
+    /// Returns the given entry of the node's `reg` property, translated through the `ranges` of
+    /// its ancestors into a physical address.
+    ///
+    /// Returns `None` if there is no such entry, or it can't be translated, e.g. because some
+    /// ancestor doesn't have a `ranges` property or uses addresses wider than 64 bits.
+    pub fn reg(&self, node: &Node<'a>, index: usize) -> Option<RegEntry> {
+        let ancestors = self.ancestors(node)?;
+        let (parent, buses) = ancestors.split_last()?;
+        let address_cells = parent.address_cells();
+        let size_cells = parent.size_cells();
+        let entry_size = (address_cells + size_cells) * CELL_SIZE;
+        let reg = node.property("reg")?;
+        let entry = reg.get(index * entry_size..(index + 1) * entry_size)?;
+        let (address, size) = entry.split_at(address_cells * CELL_SIZE);
+        let mut address = read_cells(address)?;
+        let size = read_cells(size)?;
+
+        // Translate from the parent's address space up to the root's, one bus at a time.
+        let mut bus = parent;
+        for bus_parent in buses.iter().rev() {
+            address = bus.translate(address, bus_parent.address_cells())?;
+            bus = bus_parent;
+        }
+        Some(RegEntry { address, size })
+    }
+}
+
+/// A node in a devicetree.
+#[derive(Clone, Copy, Debug)]
+pub struct Node<'a> {
+    fdt: Fdt<'a>,
+    name: &'a str,
+    /// The offset in the structure block of the first token after the node's name.
+    body: usize,
+}
+
+impl<'a> Node<'a> {
+    /// Returns the name of the node, including any unit address.
+    pub fn name(&self) -> &'a str {
+        self.name
+    }
+
+    /// Returns an iterator over the node's properties, as `(name, value)` pairs.
+    pub fn properties(&self) -> Properties<'a> {
+        Properties {
+            fdt: self.fdt,
+            offset: self.body,
+        }
+    }
+
+    /// Returns the value of the property with the given name, if the node has one.
+    pub fn property(&self, name: &str) -> Option<&'a [u8]> {
+        self.properties()
+            .find(|(property_name, _)| *property_name == name)
+            .map(|(_, value)| value)
+    }
+
+    /// Returns the value of the given property as a single cell.
+    pub fn property_u32(&self, name: &str) -> Option<u32> {
+        let value = self.property(name)?;
+        if value.len() != CELL_SIZE {
+            return None;
+        }
+        read_cells(value).map(|value| value as u32)
+    }
+
+    /// Returns the value of the given property as one or two cells.
+    pub fn property_u64(&self, name: &str) -> Option<u64> {
+        read_cells(self.property(name)?)
+    }
+
+    /// Returns the value of the given property as a NUL-terminated string.
+    pub fn property_str(&self, name: &str) -> Option<&'a str> {
+        let value = self.property(name)?;
+        let (nul, string) = value.split_last()?;
+        if *nul != 0 {
+            return None;
+        }
+        core::str::from_utf8(string).ok()
+    }
+
+    /// Returns whether the node's `compatible` property includes the given string.
+    pub fn is_compatible(&self, compatible: &str) -> bool {
+        self.property("compatible").is_some_and(|value| {
+            value
+                .split(|byte| *byte == 0)
+                .any(|entry| entry == compatible.as_bytes())
+        })
+    }
+
+    /// Returns an iterator over the node's direct children.
+    pub fn children(&self) -> Children<'a> {
+        let mut offset = self.body;
+        // Skip over the properties to the first child.
+        while let Ok((Token::Property { .. } | Token::Nop, next)) = self.fdt.read_token(offset) {
+            offset = next;
+        }
+        Children {
+            fdt: self.fdt,
+            offset,
+        }
+    }
+
+    /// Returns the direct child with the given name.
+    ///
+    /// If `name` doesn't include a unit address then it also matches children with one, as long as
+    /// the rest of the name matches.
+    pub fn child(&self, name: &str) -> Option<Node<'a>> {
+        self.children().find(|child| {
+            child.name == name
+                || (!name.contains('@')
+                    && child
+                        .name
+                        .split_once('@')
+                        .is_some_and(|(base_name, _)| base_name == name))
+        })
+    }
+
+    /// Returns the number of cells used for addresses in the `reg` properties of the node's
+    /// children.
+    fn address_cells(&self) -> usize {
+        self.property_u32("#address-cells").unwrap_or(2) as usize
+    }
+
+    /// Returns the number of cells used for sizes in the `reg` properties of the node's children.
+    fn size_cells(&self) -> usize {
+        self.property_u32("#size-cells").unwrap_or(1) as usize
+    }
+
+    /// Translates an address on the bus represented by this node to the address space of its
+    /// parent, which uses `parent_address_cells` cells per address.
+    fn translate(&self, address: u64, parent_address_cells: usize) -> Option<u64> {
+        let ranges = self.property("ranges")?;
+        if ranges.is_empty() {
+            return Some(address);
+        }
+        let child_size = self.address_cells() * CELL_SIZE;
+        let parent_size = parent_address_cells * CELL_SIZE;
+        let size_size = self.size_cells() * CELL_SIZE;
+        ranges
+            .chunks_exact(child_size + parent_size + size_size)
+            .find_map(|range| {
+                let (child_address, rest) = range.split_at(child_size);
+                let (parent_address, size) = rest.split_at(parent_size);
+                let offset = address.checked_sub(read_cells(child_address)?)?;
+                if offset >= read_cells(size)? {
+                    return None;
+                }
+                read_cells(parent_address)?.checked_add(offset)
+            })
+    }
+}
+
+/// An entry from a node's `reg` property.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct RegEntry {
+    /// The physical address of the region.
+    pub address: u64,
+    /// The size of the region in bytes.
+    pub size: u64,
+}
+
+/// An iterator over the properties of a node.
+#[derive(Clone, Debug)]
+pub struct Properties<'a> {
+    fdt: Fdt<'a>,
+    offset: usize,
+}
+
+impl<'a> Iterator for Properties<'a> {
+    type Item = (&'a str, &'a [u8]);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (token, next) = self.fdt.read_token(self.offset).ok()?;
+            match token {
+                Token::Property { name_offset, value } => {
+                    self.offset = next;
+                    return Some((self.fdt.string(name_offset)?, value));
+                }
+                Token::Nop => self.offset = next,
+                _ => return None,
+            }
+        }
+    }
+}
+
+/// An iterator over the direct children of a node.
+#[derive(Clone, Debug)]
+pub struct Children<'a> {
+    fdt: Fdt<'a>,
+    offset: usize,
+}
+
+impl<'a> Iterator for Children<'a> {
+    type Item = Node<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (name, body) = loop {
+            match self.fdt.read_token(self.offset).ok()? {
+                (Token::BeginNode(name), body) => break (name, body),
+                (Token::Nop, next) => self.offset = next,
+                _ => return None,
+            }
+        };
+
+        // Skip over the child's subtree to its next sibling.
+        let mut depth = 1;
+        self.offset = body;
+        while depth > 0 {
+            let (token, next) = self.fdt.read_token(self.offset).ok()?;
+            match token {
+                Token::BeginNode(_) => depth += 1,
+                Token::EndNode => depth -= 1,
+                Token::End => return None,
+                Token::Property { .. } | Token::Nop => {}
+            }
+            self.offset = next;
+        }
+
+        Some(Node {
+            fdt: self.fdt,
+            name,
+            body,
+        })
+    }
+}
+
+/// Returns the block of `blob` with the given offset and size.
+fn block(blob: &[u8], offset: u32, size: u32) -> Result<&[u8], FdtError> {
+    let start = offset as usize;
+    let end = start
+        .checked_add(size as usize)
+        .ok_or(FdtError::BadLayout)?;
+    blob.get(start..end).ok_or(FdtError::BadLayout)
+}
+
+/// Reads the big-endian cell at the given offset.
+fn read_cell(bytes: &[u8], offset: usize) -> Option<u32> {
+    let cell = bytes.get(offset..offset + CELL_SIZE)?;
+    Some(u32::from_be_bytes(cell.try_into().unwrap()))
+}
+
+/// Reads a value made up of zero, one or two big-endian cells.
+fn read_cells(bytes: &[u8]) -> Option<u64> {
+    match bytes.len() {
+        0 => Some(0),
+        CELL_SIZE => read_cell(bytes, 0).map(u64::from),
+        8 => Some(u64::from_be_bytes(bytes.try_into().unwrap())),
+        _ => None,
+    }
+}
+
+/// Returns the NUL-terminated UTF-8 string at the start of `bytes`, without the NUL.
+fn nul_terminated(bytes: &[u8]) -> Option<&str> {
+    let len = bytes.iter().position(|byte| *byte == 0)?;
+    core::str::from_utf8(&bytes[..len]).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::vec::Vec;
+
+    /// Builds a devicetree blob in the same layout as `dtc` would.
+    #[derive(Default)]
+    struct FdtBuilder {
+        structure: Vec<u8>,
+        strings: Vec<u8>,
+    }
+
+    impl FdtBuilder {
+        fn token(&mut self, token: u32) -> &mut Self {
+            self.structure.extend_from_slice(&token.to_be_bytes());
+            self
+        }
+
+        fn pad(&mut self) {
+            self.structure
+                .resize(self.structure.len().next_multiple_of(TOKEN_SIZE), 0);
+        }
+
+        fn begin_node(&mut self, name: &str) -> &mut Self {
+            self.token(FDT_BEGIN_NODE);
+            self.structure.extend_from_slice(name.as_bytes());
+            self.structure.push(0);
+            self.pad();
+            self
+        }
+
+        fn end_node(&mut self) -> &mut Self {
+            self.token(FDT_END_NODE)
+        }
+
+        fn property(&mut self, name: &str, value: &[u8]) -> &mut Self {
+            let name_offset = self.strings.len() as u32;
+            self.strings.extend_from_slice(name.as_bytes());
+            self.strings.push(0);
+            self.token(FDT_PROP);
+            self.token(value.len() as u32);
+            self.token(name_offset);
+            self.structure.extend_from_slice(value);
+            self.pad();
+            self
+        }
+
+        fn property_cells(&mut self, name: &str, cells: &[u32]) -> &mut Self {
+            let value: Vec<u8> = cells.iter().flat_map(|cell| cell.to_be_bytes()).collect();
+            self.property(name, &value)
+        }
+
+        fn property_str(&mut self, name: &str, value: &str) -> &mut Self {
+            let mut bytes = value.as_bytes().to_vec();
+            bytes.push(0);
+            self.property(name, &bytes)
+        }
+
+        fn build(&mut self) -> Vec<u8> {
+            self.token(FDT_END);
+            // An empty memory reservation block follows the header.
+            let off_mem_rsvmap = FDT_HEADER_SIZE;
+            let off_dt_struct = off_mem_rsvmap + 16;
+            let off_dt_strings = off_dt_struct + self.structure.len();
+            let totalsize = off_dt_strings + self.strings.len();
+            let header = [
+                FDT_MAGIC,
+                totalsize as u32,
+                off_dt_struct as u32,
+                off_dt_strings as u32,
+                off_mem_rsvmap as u32,
+                FDT_VERSION,
+                16,
+                0,
+                self.strings.len() as u32,
+                self.structure.len() as u32,
+            ];
+            let mut blob: Vec<u8> = header.iter().flat_map(|cell| cell.to_be_bytes()).collect();
+            blob.extend_from_slice(&[0; 16]);
+            blob.extend_from_slice(&self.structure);
+            blob.extend_from_slice(&self.strings);
+            blob
+        }
+    }
+
+    /// A cut down version of the FVP's HW_CONFIG.
+    fn example() -> Vec<u8> {
+        FdtBuilder::default()
+            .begin_node("")
+            .property_cells("#address-cells", &[2])
+            .property_cells("#size-cells", &[2])
+            .property("compatible", b"arm,fvp-base\0arm,vexpress\0")
+            .begin_node("aliases")
+            .property_str(
+                "serial0",
+                "/bus@8000000/motherboard-bus/iofpga-bus@300000000/serial@90000",
+            )
+            .end_node()
+            .begin_node("chosen")
+            .property_str("stdout-path", "serial0:115200n8")
+            .end_node()
+            .begin_node("interrupt-controller@2f000000")
+            .property_str("compatible", "arm,gic-v3")
+            .property_cells(
+                "reg",
+                &[0, 0x2f00_0000, 0, 0x1_0000, 0, 0x2f10_0000, 0, 0x20_0000],
+            )
+            .end_node()
+            .begin_node("bus@8000000")
+            .property_cells("#address-cells", &[2])
+            .property_cells("#size-cells", &[1])
+            .property_cells("ranges", &[0, 0, 0, 0x0800_0000, 0x1800_0000])
+            .begin_node("motherboard-bus")
+            .property_cells("#address-cells", &[2])
+            .property_cells("#size-cells", &[1])
+            .property_cells("ranges", &[3, 0, 0, 0x1400_0000, 0x0020_0000])
+            .begin_node("iofpga-bus@300000000")
+            .property_cells("#address-cells", &[1])
+            .property_cells("#size-cells", &[1])
+            .property_cells("ranges", &[0, 3, 0, 0x0020_0000])
+            .begin_node("serial@90000")
+            .property_str("compatible", "arm,pl011")
+            .property_cells("reg", &[0x9_0000, 0x1000])
+            .end_node()
+            .begin_node("serial@a0000")
+            .property_str("compatible", "arm,pl011")
+            .property_cells("reg", &[0xa_0000, 0x1000])
+            .end_node()
+            .end_node()
+            .end_node()
+            .end_node()
+            .begin_node("unmapped-bus")
+            .property_cells("#address-cells", &[1])
+            .property_cells("#size-cells", &[1])
+            .begin_node("device@0")
+            .property_cells("reg", &[0, 0x100])
+            .end_node()
+            .end_node()
+            .end_node()
+            .build()
+    }
+
+    #[test]
+    fn header_errors() {
+        let blob = example();
+        assert_eq!(Fdt::total_size(&blob), Ok(blob.len()));
+        assert_eq!(
+            Fdt::total_size(&blob[..FDT_HEADER_SIZE - 1]).unwrap_err(),
+            FdtError::Truncated
+        );
+        assert_eq!(
+            Fdt::new(&blob[..blob.len() - 1]).unwrap_err(),
+            FdtError::Truncated
+        );
+
+        let mut bad_magic = blob.clone();
+        bad_magic[0] = 0;
+        assert_eq!(Fdt::new(&bad_magic).unwrap_err(), FdtError::BadMagic);
+
+        let mut old_version = blob.clone();
+        old_version[20..24].copy_from_slice(&16u32.to_be_bytes());
+        assert_eq!(
+            Fdt::new(&old_version).unwrap_err(),
+            FdtError::UnsupportedVersion(16)
+        );
+
+        let mut bad_layout = blob.clone();
+        // Make the strings block extend past the end of the blob.
+        bad_layout[32..36].copy_from_slice(&0x1000u32.to_be_bytes());
+        assert_eq!(Fdt::new(&bad_layout).unwrap_err(), FdtError::BadLayout);
+    }
+
+    #[test]
+    fn trailing_data_ignored() {
+        let mut blob = example();
+        blob.extend_from_slice(&[0xff; 64]);
+        let fdt = Fdt::new(&blob).unwrap();
+        assert!(fdt.find_node("/chosen").is_some());
+    }
+
+    #[test]
+    fn malformed_structure() {
+        let unbalanced = FdtBuilder::default()
+            .begin_node("")
+            .begin_node("child")
+            .end_node()
+            .build();
+        assert!(matches!(
+            Fdt::new(&unbalanced),
+            Err(FdtError::BadStructure(_))
+        ));
+
+        let two_roots = FdtBuilder::default()
+            .begin_node("")
+            .end_node()
+            .begin_node("")
+            .end_node()
+            .build();
+        assert!(matches!(
+            Fdt::new(&two_roots),
+            Err(FdtError::BadStructure(_))
+        ));
+
+        let mut bad_token = FdtBuilder::default();
+        bad_token.begin_node("").token(0x42).end_node();
+        assert!(matches!(
+            Fdt::new(&bad_token.build()),
+            Err(FdtError::BadStructure(_))
+        ));
+
+        let mut deep = FdtBuilder::default();
+        for _ in 0..=MAX_DEPTH {
+            deep.begin_node("node");
+        }
+        for _ in 0..=MAX_DEPTH {
+            deep.end_node();
+        }
+        assert_eq!(Fdt::new(&deep.build()).unwrap_err(), FdtError::TooDeep);
+    }
+
+    #[test]
+    fn find_nodes() {
+        let blob = example();
+        let fdt = Fdt::new(&blob).unwrap();
+
+        assert_eq!(fdt.root().name(), "");
+        assert_eq!(fdt.find_node("/").unwrap().name(), "");
+        assert_eq!(
+            fdt.find_node("/interrupt-controller").unwrap().name(),
+            "interrupt-controller@2f000000"
+        );
+        assert_eq!(
+            fdt.find_node("/bus/motherboard-bus/iofpga-bus@300000000/serial@a0000")
+                .unwrap()
+                .name(),
+            "serial@a0000"
+        );
+        assert!(fdt.find_node("/bus@1234").is_none());
+        assert!(fdt.find_node("/missing").is_none());
+        assert_eq!(fdt.find_node("serial0").unwrap().name(), "serial@90000");
+        assert!(fdt.find_node("serial1").is_none());
+
+        let children: Vec<_> = fdt.root().children().map(|child| child.name()).collect();
+        assert_eq!(
+            children,
+            [
+                "aliases",
+                "chosen",
+                "interrupt-controller@2f000000",
+                "bus@8000000",
+                "unmapped-bus"
+            ]
+        );
+    }
+
+    #[test]
+    fn properties() {
+        let blob = example();
+        let fdt = Fdt::new(&blob).unwrap();
+        let root = fdt.root();
+
+        assert_eq!(root.property_u32("#address-cells"), Some(2));
+        assert_eq!(root.property_u64("#address-cells"), Some(2));
+        assert_eq!(root.property_u32("missing"), None);
+        assert_eq!(root.property_str("#address-cells"), None);
+        assert!(root.is_compatible("arm,fvp-base"));
+        assert!(root.is_compatible("arm,vexpress"));
+        assert!(!root.is_compatible("arm,vexpress\0"));
+        assert!(!root.is_compatible("arm"));
+        assert_eq!(root.properties().count(), 3);
+        assert_eq!(
+            fdt.find_node("/chosen")
+                .unwrap()
+                .property_str("stdout-path"),
+            Some("serial0:115200n8")
+        );
+    }
+
+    #[test]
+    fn find_compatible() {
+        let blob = example();
+        let fdt = Fdt::new(&blob).unwrap();
+
+        assert_eq!(
+            fdt.find_compatible("arm,pl011").unwrap().name(),
+            "serial@90000"
+        );
+        assert_eq!(
+            fdt.find_compatible("arm,gic-v3").unwrap().name(),
+            "interrupt-controller@2f000000"
+        );
+        assert!(fdt.find_compatible("arm,gic-400").is_none());
+    }
+
+    #[test]
+    fn reg_translation() {
+        let blob = example();
+        let fdt = Fdt::new(&blob).unwrap();
+
+        let gic = fdt.find_compatible("arm,gic-v3").unwrap();
+        assert_eq!(
+            fdt.reg(&gic, 0),
+            Some(RegEntry {
+                address: 0x2f00_0000,
+                size: 0x1_0000
+            })
+        );
+        assert_eq!(
+            fdt.reg(&gic, 1),
+            Some(RegEntry {
+                address: 0x2f10_0000,
+                size: 0x20_0000
+            })
+        );
+        assert_eq!(fdt.reg(&gic, 2), None);
+
+        let uart = fdt.stdout().unwrap();
+        assert_eq!(
+            fdt.reg(&uart, 0),
+            Some(RegEntry {
+                address: 0x1c09_0000,
+                size: 0x1000
+            })
+        );
+
+        // Buses without a `ranges` property aren't mapped into the parent's address space.
+        let device = fdt.find_node("/unmapped-bus/device").unwrap();
+        assert_eq!(fdt.reg(&device, 0), None);
+    }
+}
@@ -5,17 +5,21 @@
 //! Code to initialise and configure the GIC, and to save and restore its state if necessary when
 //! powering cores on and off.
 
+mod sgi_registry;
+
 use crate::{
     aarch64::{dsb_sy, isb},
     context::{CoresImpl, World},
+    cpu_notifier::CpuNotifier,
     platform::Platform,
+    spin_mutex::SpinMutex,
 };
 use arm_gic::{
     IntId, InterruptGroup, Trigger, UniqueMmioPointer,
     gicv3::{
         GicCpuInterface, GicDistributor, GicDistributorContext, GicRedistributor,
         GicRedistributorContext, GicRedistributorIterator, Group, HIGHEST_NS_PRIORITY,
-        SecureIntGroup,
+        SecureIntGroup, SgiTarget, SgiTargetGroup,
         registers::{Gicd, GicdCtlr, GicrSgi},
     },
 };
@@ -23,7 +27,8 @@ use arm_sysregs::{MpidrEl1, ScrEl3, read_mpidr_el1};
 use core::{marker::PhantomData, panic, ptr::NonNull};
 use log::debug;
 use percore::Cores;
-use spin::mutex::SpinMutex;
+use sgi_registry::SGI_COUNT;
+pub use sgi_registry::{SgiClaimError, SgiRegistry, SgiUser};
 
 const GIC_PRI_MASK: u8 = 0xff;
 
@@ -60,22 +65,118 @@ pub type InterruptConfigEntry = (IntId, InterruptConfig);
 
 /// The configuration of platform's GIC.
 pub struct GicConfig {
-    /// This list specifies which interrupts will be configured to specified setup and enabled by
-    /// EL3.
+    /// The SGIs claimed by each subsystem, which will be configured and enabled by EL3.
+    pub sgis: SgiRegistry,
+    /// This list specifies which PPIs and SPIs will be configured to specified setup and enabled
+    /// by EL3. SGIs must be claimed in `sgis` instead.
     pub interrupts_config: &'static [InterruptConfigEntry],
 }
 
+/// A problem with a [`GicConfig`], found by [`GicConfig::validate`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum GicConfigError {
+    /// The INTID is not an SGI, PPI or SPI (including extended ranges).
+    InvalidIntId(IntId),
+    /// The INTID is configured more than once.
+    Duplicate(IntId),
+    /// A non-secure interrupt has a priority only accessible to the secure world.
+    SecurePriorityForNonSecure(IntId),
+    /// An SGI is configured as level triggered, but SGIs are always edge triggered.
+    LevelTriggeredSgi(IntId),
+    /// An SGI is configured directly rather than being claimed in the SGI registry.
+    UnclaimedSgi(IntId),
+}
+
 impl GicConfig {
+    /// Checks that the configuration is consistent.
+    ///
+    /// This is a const function so that it can be checked at build time, e.g. with
+    /// [`assert_valid`](Self::assert_valid).
+    pub const fn validate(&self) -> Result<(), GicConfigError> {
+        let mut sgi = 0;
+        while sgi < SGI_COUNT {
+            if let Some(config) = self.sgis.config(sgi)
+                && let Err(e) = Self::validate_entry(IntId::sgi(sgi as u32), config)
+            {
+                return Err(e);
+            }
+            sgi += 1;
+        }
+
+        let entries = self.interrupts_config;
+        let mut i = 0;
+        while i < entries.len() {
+            let (intid, config) = entries[i];
+
+            if intid.is_sgi() {
+                return Err(GicConfigError::UnclaimedSgi(intid));
+            }
+            if let Err(e) = Self::validate_entry(intid, config) {
+                return Err(e);
+            }
+
+            let mut j = 0;
+            while j < i {
+                if entries[j].0.raw_value() == intid.raw_value() {
+                    return Err(GicConfigError::Duplicate(intid));
+                }
+                j += 1;
+            }
+
+            i += 1;
+        }
+        Ok(())
+    }
+
+    /// Checks the configuration of a single interrupt.
+    const fn validate_entry(intid: IntId, config: InterruptConfig) -> Result<(), GicConfigError> {
+        if !(intid.is_private() || intid.is_spi() || intid.is_espi()) {
+            return Err(GicConfigError::InvalidIntId(intid));
+        }
+        if matches!(config.group, Group::Group1NS) && config.priority < HIGHEST_NS_PRIORITY {
+            return Err(GicConfigError::SecurePriorityForNonSecure(intid));
+        }
+        if intid.is_sgi() && matches!(config.trigger, Trigger::Level) {
+            return Err(GicConfigError::LevelTriggeredSgi(intid));
+        }
+        Ok(())
+    }
+
+    /// Panics if the configuration is not valid.
+    ///
+    /// When called in a const context this fails the build instead.
+    pub const fn assert_valid(&self) {
+        match self.validate() {
+            Ok(()) => {}
+            Err(GicConfigError::InvalidIntId(_)) => {
+                panic!("GIC config contains an INTID which is not an SGI, PPI or SPI")
+            }
+            Err(GicConfigError::Duplicate(_)) => panic!("GIC config contains a duplicate INTID"),
+            Err(GicConfigError::SecurePriorityForNonSecure(_)) => {
+                panic!("GIC config gives a non-secure interrupt a secure priority")
+            }
+            Err(GicConfigError::LevelTriggeredSgi(_)) => {
+                panic!("GIC config has a level triggered SGI")
+            }
+            Err(GicConfigError::UnclaimedSgi(_)) => {
+                panic!("GIC config configures an SGI which isn't claimed in the SGI registry")
+            }
+        }
+    }
+
     /// Get iterator for shared interrupts.
     fn shared(&self) -> impl Iterator<Item = &InterruptConfigEntry> {
         self.interrupts_config.iter().filter(|int| int.0.is_spi())
     }
 
-    /// Get iterator for private interrupts.
-    fn private(&self) -> impl Iterator<Item = &InterruptConfigEntry> {
-        self.interrupts_config
-            .iter()
-            .filter(|int| int.0.is_private())
+    /// Get iterator for private interrupts, including the claimed SGIs.
+    fn private(&self) -> impl Iterator<Item = InterruptConfigEntry> + '_ {
+        self.sgis.interrupts_config().chain(
+            self.interrupts_config
+                .iter()
+                .copied()
+                .filter(|int| int.0.is_private()),
+        )
     }
 }
 /// Specifies where an interrupt should be handled.
@@ -93,7 +194,12 @@ pub enum InterruptType {
 
 /// Registry for storing GIC redistributor instances.
 struct GicRedistributorRegistry<'a, const CORE_COUNT: usize, PlatformImpl: Platform> {
-    redistributors: [SpinMutex<GicRedistributor<'a>>; CORE_COUNT],
+    /// The redistributor of each core, by core index.
+    ///
+    /// These are kept as `Option`s, which take no extra space, rather than unwrapping them into a
+    /// second array while constructing the registry, to avoid needing stack space for two arrays
+    /// of `CORE_COUNT` entries on platforms with many cores.
+    redistributors: [Option<SpinMutex<GicRedistributor<'a>>>; CORE_COUNT],
     _platform: PhantomData<PlatformImpl>,
 }
 
@@ -110,22 +216,31 @@ impl<'a, const CORE_COUNT: usize, PlatformImpl: Platform>
         // Safety: The function propagates the safety requirements to the caller.
         for redist in unsafe { GicRedistributorIterator::new(base, gic_v4) } {
             let mpidr = MpidrEl1::from_psci_mpidr(redist.typer().core_mpidr());
-            assert!(PlatformImpl::mpidr_is_valid(mpidr));
+            if !PlatformImpl::mpidr_is_valid(mpidr) {
+                // The platform's topology may have holes, e.g. for cores which are fused off but
+                // still have a redistributor frame.
+                debug!("Ignoring GIC redistributor for unknown core {mpidr:#x?}");
+                continue;
+            }
 
             let core_index = PlatformImpl::core_position(mpidr.bits());
 
             redistributors[core_index] = Some(SpinMutex::new(redist));
         }
+        assert!(
+            redistributors.iter().all(Option::is_some),
+            "Missing GIC redistributor for some cores"
+        );
 
         Self {
-            redistributors: redistributors.map(|r| r.unwrap()),
+            redistributors,
             _platform: PhantomData,
         }
     }
 
     /// Get redistributor by linear index.
     pub fn redistributor(&self, index: usize) -> &SpinMutex<GicRedistributor<'a>> {
-        &self.redistributors[index]
+        self.redistributors[index].as_ref().unwrap()
     }
 
     /// Get the redistributor of the local core.
@@ -238,16 +353,16 @@ impl<'a, const CORE_COUNT: usize, PlatformImpl: Platform> Gic<'a, CORE_COUNT, Pl
         redist.configure_default_settings();
 
         for (intid, config) in config.private() {
-            redist.set_group(*intid, config.group).unwrap();
+            redist.set_group(intid, config.group).unwrap();
             if intid.is_ppi() {
                 // Set interrupt configuration for PPIs.
                 // Configurations for SGIs 0-15 are ignored.
-                redist.set_trigger(*intid, config.trigger).unwrap();
+                redist.set_trigger(intid, config.trigger).unwrap();
             }
             redist
-                .set_interrupt_priority(*intid, config.priority)
+                .set_interrupt_priority(intid, config.priority)
                 .unwrap();
-            redist.enable_interrupt(*intid, true).unwrap();
+            redist.enable_interrupt(intid, true).unwrap();
         }
     }
 
@@ -321,6 +436,20 @@ impl<'a, const CORE_COUNT: usize, PlatformImpl: Platform> Gic<'a, CORE_COUNT, Pl
     }
 }
 
+impl<const CORE_COUNT: usize, PlatformImpl: Platform> CpuNotifier
+    for Gic<'_, CORE_COUNT, PlatformImpl>
+{
+    fn cpu_online(&self) {
+        self.redistributor_init(&PlatformImpl::GIC_CONFIG);
+        self.cpu_interface_enable();
+    }
+
+    fn cpu_offline(&self) {
+        self.cpu_interface_disable();
+        self.redistributor_off();
+    }
+}
+
 /// Configures interrupt-routing related flags in `scr_el3` bitflags.
 ///
 /// While in NS-ELx:
@@ -360,6 +489,23 @@ pub fn get_pending_interrupt_type() -> InterruptType {
     }
 }
 
+/// Sends the given SGI to the non-secure world on the current core.
+///
+/// This must be called while SCR_EL3.NS is clear, i.e. before returning to the normal world from
+/// another world, so that the SGI is generated for the other security state. It will be taken once
+/// the normal world is entered.
+pub fn send_non_secure_sgi_to_self(intid: IntId) {
+    let mpidr = read_mpidr_el1();
+    let target = SgiTarget::List {
+        affinity3: mpidr.aff3(),
+        affinity2: mpidr.aff2(),
+        affinity1: mpidr.aff1(),
+        target_list: 1 << (mpidr.aff0() & 0xf),
+    };
+    GicCpuInterface::send_sgi(intid, target, SgiTargetGroup::OtherGroup1)
+        .expect("Tried to send an INTID which is not an SGI");
+}
+
 /// Wraps a platform-specific group 0 interrupt handler.
 pub fn handle_group0_interrupt<PlatformImpl: Platform>() {
     let int_id = GicCpuInterface::get_and_acknowledge_interrupt(InterruptGroup::Group0).unwrap();
@@ -426,4 +572,94 @@ mod tests {
         gic.redistributor_restore(&redistributor_context);
         gic.redistributor_off();
     }
+
+    const SECURE_EDGE: InterruptConfig = InterruptConfig {
+        priority: 0x10,
+        group: Group::Secure(SecureIntGroup::Group1S),
+        trigger: Trigger::Edge,
+    };
+
+    #[test]
+    fn validate_config() {
+        const VALID: GicConfig = GicConfig {
+            sgis: SgiRegistry::EMPTY.claim(8, SgiUser::Spmc, SECURE_EDGE),
+            interrupts_config: &[
+                (IntId::ppi(3), SECURE_EDGE),
+                (IntId::spi(10), InterruptConfig::DEFAULT),
+            ],
+        };
+        const { VALID.assert_valid() };
+        assert_eq!(
+            GicConfig {
+                sgis: SgiRegistry::EMPTY,
+                interrupts_config: &[]
+            }
+            .validate(),
+            Ok(())
+        );
+    }
+
+    /// Validates a config with the given entries.
+    fn validate(entries: Vec<InterruptConfigEntry>) -> Result<(), GicConfigError> {
+        GicConfig {
+            sgis: SgiRegistry::EMPTY,
+            interrupts_config: entries.leak(),
+        }
+        .validate()
+    }
+
+    #[test]
+    fn validate_invalid_config() {
+        assert_eq!(
+            validate(vec![(IntId::SPECIAL_NONE, SECURE_EDGE)]),
+            Err(GicConfigError::InvalidIntId(IntId::SPECIAL_NONE))
+        );
+        assert_eq!(
+            validate(vec![
+                (IntId::ppi(8), SECURE_EDGE),
+                (IntId::ppi(9), SECURE_EDGE),
+                (IntId::ppi(8), SECURE_EDGE),
+            ]),
+            Err(GicConfigError::Duplicate(IntId::ppi(8)))
+        );
+        assert_eq!(
+            validate(vec![(
+                IntId::spi(1),
+                InterruptConfig {
+                    priority: 0x10,
+                    ..InterruptConfig::DEFAULT
+                }
+            )]),
+            Err(GicConfigError::SecurePriorityForNonSecure(IntId::spi(1)))
+        );
+        assert_eq!(
+            GicConfig {
+                sgis: SgiRegistry::EMPTY.claim(
+                    15,
+                    SgiUser::Platform,
+                    InterruptConfig {
+                        trigger: Trigger::Level,
+                        ..SECURE_EDGE
+                    }
+                ),
+                interrupts_config: &[],
+            }
+            .validate(),
+            Err(GicConfigError::LevelTriggeredSgi(IntId::sgi(15)))
+        );
+        assert_eq!(
+            validate(vec![(IntId::sgi(15), SECURE_EDGE)]),
+            Err(GicConfigError::UnclaimedSgi(IntId::sgi(15)))
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "duplicate INTID")]
+    fn assert_valid_panics() {
+        const DUPLICATE: GicConfig = GicConfig {
+            sgis: SgiRegistry::EMPTY,
+            interrupts_config: &[(IntId::ppi(1), SECURE_EDGE), (IntId::ppi(1), SECURE_EDGE)],
+        };
+        DUPLICATE.assert_valid();
+    }
 }
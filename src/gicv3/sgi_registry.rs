@@ -0,0 +1,200 @@
+// Copyright The Rusted Firmware-A Contributors.
+//
+// SPDX-License-Identifier: BSD-3-Clause
+
+//! Registry of which subsystem uses each SGI, so that two subsystems can't use the same one.
+
+use super::{InterruptConfig, InterruptConfigEntry};
+use arm_gic::IntId;
+
+/// The number of SGIs.
+pub(super) const SGI_COUNT: usize = 16;
+
+/// A subsystem which uses SGIs.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SgiUser {
+    /// Secure SGIs used by the SPMC, e.g. for its own inter-processor interrupts.
+    Spmc,
+    /// The Schedule Receiver Interrupt used to signal pending FF-A notifications to the normal
+    /// world.
+    NotificationSri,
+    /// Dispatching SDEI events to the normal world.
+    Sdei,
+    /// Platform-specific uses.
+    Platform,
+}
+
+/// A failure to claim an SGI.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SgiClaimError {
+    /// The INTID is not an SGI.
+    NotSgi(u32),
+    /// The SGI has already been claimed.
+    Conflict {
+        /// The SGI which was requested.
+        sgi: u32,
+        /// The subsystem which already claimed it.
+        owner: SgiUser,
+    },
+}
+
+/// The SGIs claimed by each subsystem, and their configuration.
+///
+/// This is built up at compile time by chaining calls to [`claim`](Self::claim), and used as part
+/// of the platform's [`GicConfig`](super::GicConfig).
+#[derive(Clone, Copy, Debug)]
+pub struct SgiRegistry {
+    claims: [Option<(SgiUser, InterruptConfig)>; SGI_COUNT],
+}
+
+impl SgiRegistry {
+    /// A registry in which no SGIs have been claimed.
+    pub const EMPTY: Self = Self {
+        claims: [None; SGI_COUNT],
+    };
+
+    /// Claims the given SGI for the given subsystem, with the given configuration.
+    ///
+    /// Returns an error if the SGI is out of range or already claimed.
+    pub const fn try_claim(
+        mut self,
+        sgi: u32,
+        user: SgiUser,
+        config: InterruptConfig,
+    ) -> Result<Self, SgiClaimError> {
+        if sgi as usize >= SGI_COUNT {
+            return Err(SgiClaimError::NotSgi(sgi));
+        }
+        if let Some((owner, _)) = self.claims[sgi as usize] {
+            return Err(SgiClaimError::Conflict { sgi, owner });
+        }
+        self.claims[sgi as usize] = Some((user, config));
+        Ok(self)
+    }
+
+    /// Claims the given SGI for the given subsystem, with the given configuration.
+    ///
+    /// Panics if the SGI is out of range or already claimed. When called in a const context this
+    /// fails the build instead.
+    pub const fn claim(self, sgi: u32, user: SgiUser, config: InterruptConfig) -> Self {
+        match self.try_claim(sgi, user, config) {
+            Ok(registry) => registry,
+            Err(SgiClaimError::NotSgi(_)) => panic!("Tried to claim an INTID which is not an SGI"),
+            Err(SgiClaimError::Conflict { .. }) => panic!("SGI claimed by two subsystems"),
+        }
+    }
+
+    /// Claims each SGI from `first` to `last` inclusive for the given subsystem, with the given
+    /// configuration.
+    ///
+    /// Panics if any of the SGIs are out of range or already claimed.
+    pub const fn claim_range(
+        mut self,
+        first: u32,
+        last: u32,
+        user: SgiUser,
+        config: InterruptConfig,
+    ) -> Self {
+        let mut sgi = first;
+        while sgi <= last {
+            self = self.claim(sgi, user, config);
+            sgi += 1;
+        }
+        self
+    }
+
+    /// Returns the subsystem which has claimed the given SGI, if any.
+    pub const fn owner(&self, sgi: u32) -> Option<SgiUser> {
+        if sgi as usize >= SGI_COUNT {
+            return None;
+        }
+        match self.claims[sgi as usize] {
+            Some((owner, _)) => Some(owner),
+            None => None,
+        }
+    }
+
+    /// Returns the lowest SGI claimed by the given subsystem, if any.
+    pub fn find(&self, user: SgiUser) -> Option<IntId> {
+        self.claimed()
+            .find(|(_, owner, _)| *owner == user)
+            .map(|(intid, _, _)| intid)
+    }
+
+    /// Returns the configuration of every claimed SGI.
+    pub fn interrupts_config(&self) -> impl Iterator<Item = InterruptConfigEntry> + '_ {
+        self.claimed().map(|(intid, _, config)| (intid, config))
+    }
+
+    /// Returns the claimed SGIs in order, with their owners and configuration.
+    fn claimed(&self) -> impl Iterator<Item = (IntId, SgiUser, InterruptConfig)> + '_ {
+        self.claims
+            .iter()
+            .zip(0..)
+            .filter_map(|(claim, sgi)| claim.map(|(user, config)| (IntId::sgi(sgi), user, config)))
+    }
+
+    /// Returns the configuration of the given SGI, if it has been claimed.
+    pub(super) const fn config(&self, sgi: usize) -> Option<InterruptConfig> {
+        match self.claims[sgi] {
+            Some((_, config)) => Some(config),
+            None => None,
+        }
+    }
+}
+
+impl Default for SgiRegistry {
+    fn default() -> Self {
+        Self::EMPTY
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn claim() {
+        const REGISTRY: SgiRegistry = SgiRegistry::EMPTY
+            .claim_range(8, 9, SgiUser::Spmc, InterruptConfig::DEFAULT)
+            .claim(13, SgiUser::Sdei, InterruptConfig::DEFAULT);
+
+        assert_eq!(REGISTRY.owner(8), Some(SgiUser::Spmc));
+        assert_eq!(REGISTRY.owner(10), None);
+        assert_eq!(REGISTRY.owner(16), None);
+        assert_eq!(REGISTRY.find(SgiUser::Spmc), Some(IntId::sgi(8)));
+        assert_eq!(REGISTRY.find(SgiUser::Sdei), Some(IntId::sgi(13)));
+        assert_eq!(REGISTRY.find(SgiUser::NotificationSri), None);
+        assert_eq!(
+            REGISTRY
+                .interrupts_config()
+                .map(|(intid, _)| intid)
+                .collect::<Vec<_>>(),
+            [IntId::sgi(8), IntId::sgi(9), IntId::sgi(13)]
+        );
+
+        assert_eq!(
+            REGISTRY
+                .try_claim(9, SgiUser::NotificationSri, InterruptConfig::DEFAULT)
+                .unwrap_err(),
+            SgiClaimError::Conflict {
+                sgi: 9,
+                owner: SgiUser::Spmc
+            }
+        );
+        assert_eq!(
+            REGISTRY
+                .try_claim(16, SgiUser::Platform, InterruptConfig::DEFAULT)
+                .unwrap_err(),
+            SgiClaimError::NotSgi(16)
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "SGI claimed by two subsystems")]
+    fn claim_conflict_panics() {
+        SgiRegistry::EMPTY
+            .claim_range(8, 15, SgiUser::Spmc, InterruptConfig::DEFAULT)
+            .claim(13, SgiUser::Sdei, InterruptConfig::DEFAULT);
+    }
+}
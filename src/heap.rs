@@ -0,0 +1,423 @@
+// Copyright The Rusted Firmware-A Contributors.
+//
+// SPDX-License-Identifier: BSD-3-Clause
+
+//! A small fixed-arena heap for EL3, with explicit failure handling and per-user quotas.
+//!
+//! This deliberately isn't a global allocator: every allocation returns a `Result`, so running out
+//! of memory can never panic, and each user can only allocate up to the quota set by the platform
+//! so that one subsystem can't starve another.
+
+use crate::spin_mutex::SpinMutex;
+use core::{
+    cell::UnsafeCell,
+    fmt::{self, Debug, Formatter},
+    mem::MaybeUninit,
+    ops::{Deref, DerefMut, Range},
+    ptr::NonNull,
+    sync::atomic::{AtomicBool, Ordering},
+};
+use zerocopy::FromZeros;
+
+/// The granularity of allocations from the heap, and so the maximum alignment supported.
+pub const HEAP_BLOCK_SIZE: usize = 64;
+
+/// The maximum number of blocks in the heap.
+const MAX_BLOCKS: usize = 1024;
+
+/// The maximum size of the heap in bytes.
+pub const MAX_HEAP_SIZE: usize = MAX_BLOCKS * HEAP_BLOCK_SIZE;
+
+/// A subsystem which may allocate from the heap.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum HeapUser {
+    /// Device tree parsing.
+    DeviceTree = 0,
+    /// FF-A memory transaction descriptor validation.
+    FfaDescriptors = 1,
+    /// SPMC manifest handling.
+    SpmcManifest = 2,
+}
+
+impl HeapUser {
+    const COUNT: usize = 3;
+}
+
+/// The maximum number of bytes each user may have allocated from the heap at once.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct HeapQuotas {
+    /// Quota for [`HeapUser::DeviceTree`].
+    pub device_tree: usize,
+    /// Quota for [`HeapUser::FfaDescriptors`].
+    pub ffa_descriptors: usize,
+    /// Quota for [`HeapUser::SpmcManifest`].
+    pub spmc_manifest: usize,
+}
+
+impl HeapQuotas {
+    /// No heap at all.
+    pub const NONE: Self = Self {
+        device_tree: 0,
+        ffa_descriptors: 0,
+        spmc_manifest: 0,
+    };
+
+    /// Returns the total size of heap needed to satisfy all the quotas at once.
+    pub const fn total(&self) -> usize {
+        let total = self.device_tree.next_multiple_of(HEAP_BLOCK_SIZE)
+            + self.ffa_descriptors.next_multiple_of(HEAP_BLOCK_SIZE)
+            + self.spmc_manifest.next_multiple_of(HEAP_BLOCK_SIZE);
+        assert!(total <= MAX_HEAP_SIZE, "EL3 heap quotas are too large");
+        total
+    }
+
+    const fn as_array(&self) -> [usize; HeapUser::COUNT] {
+        [self.device_tree, self.ffa_descriptors, self.spmc_manifest]
+    }
+}
+
+/// An error allocating from the heap.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum HeapError {
+    /// The heap hasn't been enabled yet.
+    NotReady,
+    /// The allocation would take the user over its quota.
+    QuotaExceeded,
+    /// There is no free range of memory large enough in the heap.
+    OutOfMemory,
+    /// The type requires a greater alignment than [`HEAP_BLOCK_SIZE`].
+    UnsupportedAlignment,
+}
+
+/// The memory backing the heap.
+///
+/// This should be placed in the `.el3_heap` section, which the linker script reserves without
+/// loading or zeroing it.
+#[repr(C, align(4096))]
+pub struct HeapArena<const SIZE: usize>(UnsafeCell<MaybeUninit<[u8; SIZE]>>);
+
+// SAFETY: The arena is only accessed through a `Heap`, which ensures that each block is only
+// accessed through the single `HeapBox` to which it is allocated.
+unsafe impl<const SIZE: usize> Sync for HeapArena<SIZE> {}
+
+impl<const SIZE: usize> HeapArena<SIZE> {
+    /// Creates a new uninitialised arena.
+    pub const fn new() -> Self {
+        Self(UnsafeCell::new(MaybeUninit::uninit()))
+    }
+}
+
+impl<const SIZE: usize> Default for HeapArena<SIZE> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Debug)]
+struct HeapState {
+    /// Bitmap of blocks which are allocated.
+    allocated: [u64; MAX_BLOCKS / 64],
+    /// The number of bytes currently allocated by each user.
+    used: [usize; HeapUser::COUNT],
+}
+
+impl HeapState {
+    fn is_allocated(&self, block: usize) -> bool {
+        self.allocated[block / 64] & (1 << (block % 64)) != 0
+    }
+
+    fn set_allocated(&mut self, blocks: Range<usize>, allocated: bool) {
+        for block in blocks {
+            if allocated {
+                self.allocated[block / 64] |= 1 << (block % 64);
+            } else {
+                self.allocated[block / 64] &= !(1 << (block % 64));
+            }
+        }
+    }
+
+    /// Finds the first free run of the given number of blocks, out of the first `block_count`.
+    fn find_free(&self, block_count: usize, needed: usize) -> Option<usize> {
+        let mut start = 0;
+        for block in 0..block_count {
+            if self.is_allocated(block) {
+                start = block + 1;
+            } else if block + 1 - start == needed {
+                return Some(start);
+            }
+        }
+        None
+    }
+}
+
+/// A fixed-arena heap with per-user quotas.
+pub struct Heap {
+    base: NonNull<u8>,
+    block_count: usize,
+    quotas: [usize; HeapUser::COUNT],
+    enabled: AtomicBool,
+    state: SpinMutex<HeapState>,
+}
+
+// SAFETY: `base` points to a `HeapArena`, which is `Sync`, and all access to the arena is
+// coordinated by `state`.
+unsafe impl Send for Heap {}
+// SAFETY: As above.
+unsafe impl Sync for Heap {}
+
+impl Heap {
+    /// Creates a new heap using the given arena, with the given quotas.
+    pub const fn new<const SIZE: usize>(
+        arena: &'static HeapArena<SIZE>,
+        quotas: HeapQuotas,
+    ) -> Self {
+        assert!(SIZE <= MAX_HEAP_SIZE);
+        Self {
+            // SAFETY: The pointer comes from a reference so can't be null.
+            base: unsafe { NonNull::new_unchecked(arena.0.get().cast()) },
+            block_count: SIZE / HEAP_BLOCK_SIZE,
+            quotas: quotas.as_array(),
+            enabled: AtomicBool::new(false),
+            state: SpinMutex::new(HeapState {
+                allocated: [0; MAX_BLOCKS / 64],
+                used: [0; HeapUser::COUNT],
+            }),
+        }
+    }
+
+    /// Allows allocations to be made from the heap.
+    ///
+    /// This must be called once the arena is mapped by the runtime page table.
+    pub fn enable(&self) {
+        self.enabled.store(true, Ordering::Release);
+    }
+
+    /// Returns the total size of the heap in bytes.
+    pub fn size(&self) -> usize {
+        self.block_count * HEAP_BLOCK_SIZE
+    }
+
+    /// Returns the address range of the heap's arena.
+    pub fn arena_range(&self) -> Range<usize> {
+        let start = self.base.as_ptr() as usize;
+        start..start + self.size()
+    }
+
+    /// Returns the number of bytes currently allocated by the given user.
+    pub fn used(&self, user: HeapUser) -> usize {
+        self.state.lock().used[user as usize]
+    }
+
+    /// Allocates a range of blocks large enough for `size` bytes for the given user, returning the
+    /// index of the first block.
+    fn allocate_blocks(
+        &self,
+        user: HeapUser,
+        size: usize,
+        align: usize,
+    ) -> Result<usize, HeapError> {
+        if !self.enabled.load(Ordering::Acquire) {
+            return Err(HeapError::NotReady);
+        }
+        if align > HEAP_BLOCK_SIZE {
+            return Err(HeapError::UnsupportedAlignment);
+        }
+        let needed = size.div_ceil(HEAP_BLOCK_SIZE).max(1);
+        let bytes = needed * HEAP_BLOCK_SIZE;
+
+        let mut state = self.state.lock();
+        if state.used[user as usize] + bytes > self.quotas[user as usize] {
+            return Err(HeapError::QuotaExceeded);
+        }
+        let start = state
+            .find_free(self.block_count, needed)
+            .ok_or(HeapError::OutOfMemory)?;
+        state.set_allocated(start..start + needed, true);
+        state.used[user as usize] += bytes;
+        Ok(start)
+    }
+
+    fn free_blocks(&self, user: HeapUser, start: usize, size: usize) {
+        let needed = size.div_ceil(HEAP_BLOCK_SIZE).max(1);
+        let mut state = self.state.lock();
+        state.set_allocated(start..start + needed, false);
+        state.used[user as usize] -= needed * HEAP_BLOCK_SIZE;
+    }
+
+    fn block_pointer(&self, block: usize) -> NonNull<u8> {
+        // SAFETY: `allocate_blocks` only returns blocks within the arena.
+        unsafe { self.base.add(block * HEAP_BLOCK_SIZE) }
+    }
+
+    /// Moves the given value into the heap on behalf of the given user.
+    pub fn try_alloc<T>(&self, user: HeapUser, value: T) -> Result<HeapBox<'_, T>, HeapError> {
+        let block = self.allocate_blocks(user, size_of::<T>(), align_of::<T>())?;
+        let ptr = self.block_pointer(block).cast::<T>();
+        // SAFETY: The blocks were just allocated so nothing else is using them, they are large
+        // enough and suitably aligned for a `T`.
+        unsafe { ptr.write(value) };
+        Ok(HeapBox {
+            ptr,
+            heap: self,
+            user,
+            block,
+        })
+    }
+
+    /// Allocates a zeroed slice of the given length on behalf of the given user.
+    pub fn try_alloc_zeroed_slice<T: FromZeros>(
+        &self,
+        user: HeapUser,
+        len: usize,
+    ) -> Result<HeapBox<'_, [T]>, HeapError> {
+        let size = size_of::<T>()
+            .checked_mul(len)
+            .ok_or(HeapError::OutOfMemory)?;
+        let block = self.allocate_blocks(user, size, align_of::<T>())?;
+        let start = self.block_pointer(block);
+        // SAFETY: The blocks were just allocated so nothing else is using them, and they are large
+        // enough for `size` bytes.
+        unsafe { start.write_bytes(0, size) };
+        Ok(HeapBox {
+            ptr: NonNull::slice_from_raw_parts(start.cast::<T>(), len),
+            heap: self,
+            user,
+            block,
+        })
+    }
+}
+
+impl Debug for Heap {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_struct("Heap")
+            .field("size", &self.size())
+            .field("quotas", &self.quotas)
+            .field("state", &self.state)
+            .finish()
+    }
+}
+
+/// An owned allocation from a [`Heap`], which is freed when dropped.
+pub struct HeapBox<'a, T: ?Sized> {
+    ptr: NonNull<T>,
+    heap: &'a Heap,
+    user: HeapUser,
+    block: usize,
+}
+
+impl<T: ?Sized> Deref for HeapBox<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        // SAFETY: `ptr` was initialised when the allocation was made, and is uniquely owned by
+        // this `HeapBox`.
+        unsafe { self.ptr.as_ref() }
+    }
+}
+
+impl<T: ?Sized> DerefMut for HeapBox<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        // SAFETY: `ptr` was initialised when the allocation was made, and is uniquely owned by
+        // this `HeapBox`.
+        unsafe { self.ptr.as_mut() }
+    }
+}
+
+impl<T: ?Sized> Drop for HeapBox<'_, T> {
+    fn drop(&mut self) {
+        // SAFETY: `ptr` was initialised when the allocation was made, is uniquely owned by this
+        // `HeapBox`, and won't be used again.
+        let size = unsafe {
+            let size = size_of_val(self.ptr.as_ref());
+            self.ptr.drop_in_place();
+            size
+        };
+        self.heap.free_blocks(self.user, self.block, size);
+    }
+}
+
+impl<T: ?Sized + Debug> Debug for HeapBox<'_, T> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        (**self).fmt(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const QUOTAS: HeapQuotas = HeapQuotas {
+        device_tree: 256,
+        ffa_descriptors: 100,
+        spmc_manifest: 0,
+    };
+
+    fn new_heap() -> Heap {
+        let arena = Box::leak(Box::new(HeapArena::<{ QUOTAS.total() }>::new()));
+        let heap = Heap::new(arena, QUOTAS);
+        heap.enable();
+        heap
+    }
+
+    #[test]
+    fn quotas() {
+        assert_eq!(QUOTAS.total(), 384);
+        let heap = new_heap();
+        assert_eq!(heap.size(), 384);
+
+        let a = heap.try_alloc(HeapUser::DeviceTree, [1u8; 100]).unwrap();
+        assert_eq!(heap.used(HeapUser::DeviceTree), 128);
+        let b = heap.try_alloc(HeapUser::DeviceTree, 42u64).unwrap();
+        assert_eq!(
+            heap.try_alloc(HeapUser::DeviceTree, [0u8; 65]).unwrap_err(),
+            HeapError::QuotaExceeded
+        );
+        assert_eq!(
+            heap.try_alloc(HeapUser::SpmcManifest, 0u8).unwrap_err(),
+            HeapError::QuotaExceeded
+        );
+        let c = heap
+            .try_alloc_zeroed_slice::<u32>(HeapUser::FfaDescriptors, 16)
+            .unwrap();
+
+        assert_eq!(*a, [1; 100]);
+        assert_eq!(*b, 42);
+        assert_eq!(*c, [0; 16]);
+
+        drop(a);
+        assert_eq!(heap.used(HeapUser::DeviceTree), 64);
+        let d = heap.try_alloc(HeapUser::DeviceTree, [2u8; 128]).unwrap();
+        assert_eq!(*d, [2; 128]);
+    }
+
+    #[test]
+    fn out_of_memory() {
+        let arena = Box::leak(Box::new(HeapArena::<128>::new()));
+        let heap = Heap::new(arena, QUOTAS);
+        assert_eq!(
+            heap.try_alloc(HeapUser::DeviceTree, 0u8).unwrap_err(),
+            HeapError::NotReady
+        );
+        heap.enable();
+
+        let _a = heap.try_alloc(HeapUser::DeviceTree, 0u8).unwrap();
+        let _b = heap.try_alloc(HeapUser::FfaDescriptors, 0u8).unwrap();
+        assert_eq!(
+            heap.try_alloc(HeapUser::DeviceTree, 0u8).unwrap_err(),
+            HeapError::OutOfMemory
+        );
+    }
+
+    #[test]
+    fn alignment() {
+        #[derive(Debug)]
+        #[repr(align(128))]
+        struct Aligned;
+
+        let heap = new_heap();
+        assert_eq!(
+            heap.try_alloc(HeapUser::DeviceTree, Aligned).unwrap_err(),
+            HeapError::UnsupportedAlignment
+        );
+    }
+}
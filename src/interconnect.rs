@@ -0,0 +1,40 @@
+// Copyright The Rusted Firmware-A Contributors.
+//
+// SPDX-License-Identifier: BSD-3-Clause
+
+//! Cache coherent interconnect control for cluster power management.
+//!
+//! RF-A normally relies on hardware-assisted coherency, such as a DSU, to take a cluster in and out
+//! of the coherency domain as it is powered up and down. Some platforms instead connect their
+//! clusters through an interconnect such as CCI or CCN which must be told explicitly. Their PSCI
+//! implementations remove a cluster from the coherency domain after its caches have been flushed
+//! for a cluster power down, and add it back when the first core of the cluster powers up again.
+
+/// An interconnect which software adds clusters to or removes them from the coherency domain.
+pub trait Interconnect {
+    /// Adds the given cluster to the coherency domain, and waits for the change to complete.
+    ///
+    /// # Safety
+    ///
+    /// The cluster must not allocate shareable data into its caches until this returns.
+    unsafe fn enter_coherency(&mut self, cluster: usize);
+
+    /// Removes the given cluster from the coherency domain, and waits for the change to complete.
+    ///
+    /// # Safety
+    ///
+    /// The caches of the cluster must already have been cleaned and invalidated, and it must not
+    /// allocate shareable data into them again until it has been added back to the coherency
+    /// domain.
+    unsafe fn exit_coherency(&mut self, cluster: usize);
+}
+
+/// An interconnect whose coherency is managed by hardware, so there is nothing to do.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct HardwareCoherency;
+
+impl Interconnect for HardwareCoherency {
+    unsafe fn enter_coherency(&mut self, _cluster: usize) {}
+
+    unsafe fn exit_coherency(&mut self, _cluster: usize) {}
+}
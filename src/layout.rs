@@ -15,8 +15,15 @@ unsafe extern "C" {
     static __RODATA_END__: ();
     static __TEXT_START__: ();
     static __TEXT_END__: ();
+    static __RO_AFTER_INIT_START__: ();
+    static __RO_AFTER_INIT_END__: ();
+    static __DATA_START__: ();
     static __BSS2_START__: ();
     static __BSS2_END__: ();
+    static __EL3_HEAP_START__: ();
+    static __EL3_HEAP_END__: ();
+    static __EL3_RETAINED_START__: ();
+    static __EL3_RETAINED_END__: ();
 }
 
 /// Returns the address of the `__BL31_START__` symbol defined by the linker script.
@@ -49,6 +56,23 @@ pub fn bl_ro_data_end() -> usize {
     (&raw const __RODATA_END__) as usize
 }
 
+/// Returns the address of the `__RO_AFTER_INIT_START__` symbol defined by the linker script.
+pub fn ro_after_init_start() -> usize {
+    (&raw const __RO_AFTER_INIT_START__) as usize
+}
+
+/// Returns the address of the `__RO_AFTER_INIT_END__` symbol defined by the linker script.
+pub fn ro_after_init_end() -> usize {
+    (&raw const __RO_AFTER_INIT_END__) as usize
+}
+
+/// Returns the address of the `__DATA_START__` symbol defined by the linker script.
+///
+/// Everything from here to [`bl31_end`] is read-write data: `.data`, `.bss` and the EL3 heap.
+pub fn bl_data_start() -> usize {
+    (&raw const __DATA_START__) as usize
+}
+
 /// Returns the address of the `__BL31_SEC_DRAM_START__` symbol defined by the linker script.
 pub fn bss2_start() -> usize {
     (&raw const __BSS2_START__) as usize
@@ -58,3 +82,23 @@ pub fn bss2_start() -> usize {
 pub fn bss2_end() -> usize {
     (&raw const __BSS2_END__) as usize
 }
+
+/// Returns the address of the `__EL3_HEAP_START__` symbol defined by the linker script.
+pub fn el3_heap_start() -> usize {
+    (&raw const __EL3_HEAP_START__) as usize
+}
+
+/// Returns the address of the `__EL3_HEAP_END__` symbol defined by the linker script.
+pub fn el3_heap_end() -> usize {
+    (&raw const __EL3_HEAP_END__) as usize
+}
+
+/// Returns the address of the `__EL3_RETAINED_START__` symbol defined by the linker script.
+pub fn el3_retained_start() -> usize {
+    (&raw const __EL3_RETAINED_START__) as usize
+}
+
+/// Returns the address of the `__EL3_RETAINED_END__` symbol defined by the linker script.
+pub fn el3_retained_end() -> usize {
+    (&raw const __EL3_RETAINED_END__) as usize
+}
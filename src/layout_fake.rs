@@ -26,6 +26,18 @@ pub fn bl_ro_data_end() -> usize {
     0x4_0000
 }
 
+pub fn ro_after_init_start() -> usize {
+    0x4_0000
+}
+
+pub fn ro_after_init_end() -> usize {
+    0x4_1000
+}
+
+pub fn bl_data_start() -> usize {
+    0x8_0000
+}
+
 pub fn bss2_start() -> usize {
     0
 }
@@ -33,3 +45,19 @@ pub fn bss2_start() -> usize {
 pub fn bss2_end() -> usize {
     0
 }
+
+pub fn el3_heap_start() -> usize {
+    0
+}
+
+pub fn el3_heap_end() -> usize {
+    0
+}
+
+pub fn el3_retained_start() -> usize {
+    0x10_0000
+}
+
+pub fn el3_retained_end() -> usize {
+    0x12_0000
+}
@@ -8,39 +8,70 @@
 #![cfg_attr(docsrs, feature(doc_cfg))]
 
 pub mod aarch64;
+pub mod bl_params;
+pub mod build_info;
 pub mod context;
 pub mod cpu;
 pub mod cpu_extensions;
+pub mod cpu_notifier;
 #[cfg(not(any(test, feature = "fakes")))]
 mod crash_console;
+pub mod crash_dump;
 pub mod debug;
 pub mod dram;
 pub mod errata_framework;
 mod exceptions;
+pub mod fdt;
 pub mod gicv3;
 #[cfg(feature = "rme")]
 mod gpt;
+pub mod heap;
+pub mod interconnect;
 #[cfg_attr(test, path = "layout_fake.rs")]
 mod layout;
 pub mod logger;
+pub mod memory_budget;
+pub mod memory_init;
+pub mod mhu;
+pub mod nv_counter;
 pub mod pagetable;
 pub mod platform;
+pub mod pmf;
+pub mod ras_history;
 pub mod reexports;
+pub mod rng;
+pub mod ro_after_init;
+pub mod rse;
+pub mod runtime_config;
+pub mod scmi;
+pub mod scrub;
 pub mod semihosting;
 pub mod services;
 mod smccc;
+pub mod spin_mutex;
+#[cfg(feature = "stack_protector")]
+pub mod stack_protector;
 pub mod stacks;
+pub mod sysreg_trap;
+pub mod system_suspend_notifier;
+pub mod timer;
+pub mod watchdog;
 
 #[cfg(feature = "pauth")]
 use crate::cpu_extensions::pauth;
 use crate::{
-    context::{CoresImpl, CpuDataIndex, CpuStateAccess, initialise_contexts},
+    build_info::BUILD_INFO,
+    context::{CoresImpl, CpuData, CpuDataIndex, CpuStateAccess, CpuStates, initialise_contexts},
     cpu::PlatformCpuOps,
-    errata_framework::PlatformErrata,
+    cpu_extensions::bti,
+    errata_framework::{PlatformErrata, report_errata},
     gicv3::Gic,
+    heap::Heap,
+    memory_budget::MemoryBudget,
     pagetable::{IdMap, OncePageTable, PageHeap},
-    platform::Platform,
-    services::{Services, psci::PsciPlatformInterface, trng::TrngPlatformInterface},
+    platform::{Board, Platform},
+    pmf::BootStage,
+    services::{InitPhase, Services, psci::PsciPlatformInterface, trng::TrngPlatformInterface},
 };
 #[cfg(not(any(test, feature = "fakes")))]
 pub use asm::bl31_warm_entrypoint;
@@ -70,6 +101,7 @@ pub fn coldboot<
 >(
     page_table: &OncePageTable<PAGE_HEAP_PAGE_COUNT>,
     page_heap: &'static PageHeap<PAGE_HEAP_PAGE_COUNT>,
+    el3_heap: &'static Heap,
     gic: &'static Once<Gic<'static, CORE_COUNT, PlatformImpl>>,
     services: &'static Lazy<
         Services<
@@ -88,34 +120,130 @@ pub fn coldboot<
     arg3: u64,
 ) -> !
 where
-    <PlatformImpl as Platform>::PsciPlatformImpl: PsciPlatformInterface<
+    <PlatformImpl as Board>::PsciPlatformImpl: PsciPlatformInterface<
             PSCI_STATE_COUNT,
             PSCI_MAX_POWER_LEVEL,
             CORE_COUNT,
             NON_CPU_DOMAIN_COUNT,
         >,
-    <PlatformImpl as Platform>::TrngPlatformImpl: TrngPlatformInterface<REQ_WORDS>,
+    <PlatformImpl as Board>::TrngPlatformImpl: TrngPlatformInterface<REQ_WORDS>,
+    Services<
+        CORE_COUNT,
+        PSCI_STATE_COUNT,
+        PSCI_MAX_POWER_LEVEL,
+        NON_CPU_DOMAIN_COUNT,
+        REQ_WORDS,
+        WORDS_IN_POOL,
+        PlatformImpl,
+    >: Sync,
 {
+    // The services can't be constructed until the runtime mapping is set up, so keep the timestamps
+    // until then.
+    let bl31_entry_timestamp = timer::counter();
+
     PlatformImpl::init_with_early_mapping(arg0, arg1, arg2, arg3);
 
     page_table.init_runtime_mapping::<PlatformImpl>(page_heap);
+    let page_table_init_timestamp = timer::counter();
+
+    let el3_heap_arena = el3_heap.arena_range();
+    crate::early_assert!(
+        PlatformImpl,
+        el3_heap_arena.is_empty()
+            || (layout::el3_heap_start() <= el3_heap_arena.start
+                && el3_heap_arena.end <= layout::el3_heap_end()),
+        "EL3 heap arena is not in the .el3_heap section"
+    );
+    el3_heap.enable();
+
+    let config = runtime_config::init(PlatformImpl::runtime_config(arg0, arg1, arg2, arg3));
 
     PlatformImpl::init(arg0, arg1, arg2, arg3);
 
-    info!("Rust BL31 starting");
+    // Initialising the logger resets the maximum log level, so this must come after it.
+    log::set_max_level(config.log_level.min(log::STATIC_MAX_LEVEL));
+
+    info!("Rust BL31 {BUILD_INFO} starting");
     debug!("Parameters: {arg0:#0x} {arg1:#0x} {arg2:#0x} {arg3:#0x}");
+    report_errata::<PlatformImpl>();
+    // Computed at build time, so that the build fails if the structures don't fit.
+    let memory_budget = const {
+        let budget = MemoryBudget {
+            core_count: CORE_COUNT,
+            stacks: CORE_COUNT * stacks::STACK_SIZE,
+            cpu_states: size_of::<CpuStates<CORE_COUNT, PlatformImpl>>(),
+            cpu_data: CORE_COUNT * size_of::<CpuData>(),
+            gic: size_of::<Gic<'static, CORE_COUNT, PlatformImpl>>(),
+            services: size_of::<
+                Services<
+                    CORE_COUNT,
+                    PSCI_STATE_COUNT,
+                    PSCI_MAX_POWER_LEVEL,
+                    NON_CPU_DOMAIN_COUNT,
+                    REQ_WORDS,
+                    WORDS_IN_POOL,
+                    PlatformImpl,
+                >,
+            >(),
+        };
+        budget.assert_fits_stack();
+        budget
+    };
+    debug!("Memory budget: {memory_budget}");
+    debug!("Runtime configuration: {config:?}");
 
     debug!("Page table activated.");
+    debug!("EL3 heap: {} bytes", el3_heap.size());
 
     // SAFETY: This function never returns, so it is safe to enable PAuth part way through it.
     #[cfg(feature = "pauth")]
     unsafe {
         pauth::init::<PlatformImpl>();
     }
+    bti::init();
+
+    // SAFETY: This is the primary core during cold boot, no SMCs have been handled yet, and the
+    // `.ro_after_init` section is still writable.
+    unsafe {
+        services.init_registry();
+    }
+    services
+        .pmf()
+        .record_boot(BootStage::Bl31Entry, bl31_entry_timestamp);
+    services
+        .pmf()
+        .record_boot(BootStage::PageTableInit, page_table_init_timestamp);
+    services.init(InitPhase::Early);
+
+    // SAFETY: This function never returns, so it is safe to change the stack canary part way
+    // through it. No other cores have been started yet.
+    #[cfg(feature = "stack_protector")]
+    unsafe {
+        stack_protector::init(services.trng_entropy());
+    }
+
+    services.register_secrets();
+    #[cfg(feature = "pauth")]
+    services
+        .secrets()
+        .register(pauth::El3Keys::<PlatformImpl>::INSTANCE);
+    PlatformImpl::register_secrets(services.secrets());
+    services.register_vendor_handlers();
+    PlatformImpl::register_vendor_handlers(services.vendor_handlers());
 
     // Set up GIC.
-    gic.get().unwrap().init(&PlatformImpl::GIC_CONFIG);
+    const { PlatformImpl::GIC_CONFIG.assert_valid() };
+    let gic = gic.get().unwrap();
+    gic.init(&PlatformImpl::GIC_CONFIG);
+    services
+        .pmf()
+        .record_boot(BootStage::GicInit, timer::counter());
     debug!("GIC configured.");
+    services.cpu_notifiers().register(gic);
+    PlatformImpl::register_cpu_notifiers(services.cpu_notifiers());
+    PlatformImpl::register_system_suspend_notifiers(services.system_suspend_notifiers());
+
+    services.init(InitPhase::PostGic);
 
     let non_secure_entry_point = PlatformImpl::non_secure_entry_point();
     let secure_entry_point = PlatformImpl::secure_entry_point();
@@ -129,6 +257,15 @@ where
         &realm_entry_point,
     );
 
+    services.init(InitPhase::PostContext);
+
+    memory_init::init_memory::<PlatformImpl>();
+
+    page_table.make_ro_after_init_read_only();
+
+    services
+        .pmf()
+        .record_boot(BootStage::FirstEret, timer::counter());
     services.run_loop()
 }
 
@@ -152,6 +289,7 @@ extern "C" fn psci_warmboot_entrypoint<
     unsafe {
         pauth::init::<PlatformImpl>();
     }
+    bti::init();
 
     PlatformImpl::warmboot()
 }
@@ -165,7 +303,7 @@ mod asm {
         cpu_extensions::sctlr2::init_sctlr2_el3,
         debug::{DEBUG, ENABLE_ASSERTIONS},
         errata_framework::PlatformErrata,
-        pagetable::{PAGE_TABLE_ADDR, enable_mmu},
+        pagetable::{MMU_ENABLED, PAGE_TABLE_ADDR, enable_mmu},
         stacks::set_my_stack,
     };
     use arm_sysregs::{Dit, SctlrEl3};
@@ -211,6 +349,7 @@ mod asm {
             SCTLR_I_BIT = const SctlrEl3::I.bits(),
             DAIF_ABT_BIT = const DAIF_ABT_BIT,
             DIT_BIT = const Dit::DIT.bits(),
+            MMU_ENABLED = const MMU_ENABLED as u32,
             PAGE_TABLE_ADDR = sym PAGE_TABLE_ADDR,
             cpu_reset_handler = sym cpu_reset_handler::<PlatformImpl>,
             enable_mmu = sym enable_mmu::<PlatformImpl>,
@@ -245,7 +384,7 @@ macro_rules! main_asm {
 
         mod main_asm {
             use super::PlatformImplMain_ as PlatformImpl;
-            use $crate::platform::Platform;
+            use $crate::platform::{Board, Platform};
 
             /// ABT bit for DAIFClr.
             ///
@@ -271,6 +410,7 @@ macro_rules! main_asm {
                     SCTLR_I_BIT = const $crate::reexports::arm_sysregs::SctlrEl3::I.bits(),
                     DAIF_ABT_BIT = const DAIF_ABT_BIT,
                     DIT_BIT = const $crate::reexports::arm_sysregs::Dit::DIT.bits(),
+                    MMU_ENABLED = const $crate::pagetable::MMU_ENABLED as u32,
                     plat_cold_boot_handler = sym PlatformImpl::cold_boot_handler,
                     cpu_reset_handler = sym $crate::cpu::cpu_reset_handler::<PlatformImpl>,
                     init_early_page_tables = sym <PlatformImpl as $crate::pagetable::early_pagetable::PlatformEarlyPagetable>::init_early_page_tables,
@@ -362,7 +502,7 @@ macro_rules! statics {
             "The early page tables do not fit into the secondary core stack range."
         );
 
-        type LogSinkImpl_ = <$platform as $crate::platform::Platform>::LogSinkImpl;
+        type LogSinkImpl_ = <$platform as $crate::platform::Board>::LogSinkImpl;
 
         static GIC: $crate::reexports::spin::Once<
             $crate::gicv3::Gic<
@@ -375,18 +515,32 @@ macro_rules! statics {
 
         /// An array of pages which can be allocated for pagetables.
         pub static PAGE_HEAP: $crate::pagetable::PageHeap<
-            { <$platform as $crate::platform::Platform>::PAGE_HEAP_PAGE_COUNT },
+            { <$platform as $crate::platform::Board>::PAGE_HEAP_PAGE_COUNT },
         > = $crate::pagetable::PageHeap::new();
+        #[unsafe(link_section = ".el3_heap")]
+        static EL3_HEAP_ARENA: $crate::heap::HeapArena<
+            { <$platform as $crate::platform::Board>::EL3_HEAP_QUOTAS.total() },
+        > = $crate::heap::HeapArena::new();
+        /// Fixed arena from which subsystems can make fallible allocations within their quotas.
+        pub static EL3_HEAP: $crate::heap::Heap = $crate::heap::Heap::new(
+            &EL3_HEAP_ARENA,
+            <$platform as $crate::platform::Board>::EL3_HEAP_QUOTAS,
+        );
         static PAGE_TABLE: $crate::pagetable::OncePageTable<
-            { <$platform as $crate::platform::Platform>::PAGE_HEAP_PAGE_COUNT },
+            { <$platform as $crate::platform::Board>::PAGE_HEAP_PAGE_COUNT },
         > = $crate::pagetable::OncePageTable::new();
 
+        // The saved contexts are placed in memory which is retained across cluster power down. This
+        // section isn't loaded but is zeroed on cold boot, so anything in it must be all zeroes
+        // initially.
+        #[unsafe(link_section = ".el3_retained")]
         static CPU_STATES: $crate::context::CpuStates<
             { <$platform as $crate::platform::Platform>::CORE_COUNT },
             $platform,
         > = $crate::context::CpuStates::new();
 
         #[cfg_attr(test, allow(dead_code))]
+        #[unsafe(link_section = ".el3_retained")]
         static mut PERCPU_DATA: [$crate::context::CpuData;
             <$platform as $crate::platform::Platform>::CORE_COUNT] =
             [$crate::context::CpuData::EMPTY;
@@ -395,7 +549,7 @@ macro_rules! statics {
         const MAX_POWER_LEVEL_: usize = PSCI_STATE_COUNT - 1;
         /// The number of PSCI power domains other than CPUs.
         pub const NON_CPU_DOMAIN_COUNT: usize =
-            <$platform as $crate::platform::Platform>::PsciPlatformImpl::POWER_DOMAIN_COUNT
+            <$platform as $crate::platform::Board>::PsciPlatformImpl::POWER_DOMAIN_COUNT
                 - <$platform as $crate::platform::Platform>::CORE_COUNT;
         /// The number of 64-bit words to keep space for in the TRNG entropy pool.
         pub const TRNG_WORDS_IN_POOL: usize = $crate::services::trng::words_in_pool(TRNG_REQ_WORDS);
@@ -410,8 +564,50 @@ macro_rules! statics {
                 $platform,
             >,
         > = $crate::reexports::spin::Lazy::new(|| {
-            $crate::services::Services::new(|| &SERVICES.spmd)
+            $crate::services::Services::new(
+                || &SERVICES.spmd,
+                || SERVICES.suspend_stats(),
+                || SERVICES.psci_state(),
+                &SMC_AUDIT,
+                &MAILBOX_STATE,
+                &PSCI_POWER_STATS,
+                &SDEI_STATE,
+                &DEFERRED_WORK,
+                &PMF,
+                &SERVICE_REGISTRY,
+            )
         });
+        #[unsafe(link_section = ".ro_after_init")]
+        static SERVICE_REGISTRY: $crate::ro_after_init::RoAfterInit<
+            $crate::services::ServiceRegistry,
+        > = $crate::ro_after_init::RoAfterInit::new();
+        static SMC_AUDIT: $crate::services::debug::SmcAuditBuffer<
+            { <$platform as $crate::platform::Platform>::CORE_COUNT },
+        > = $crate::services::debug::SmcAuditBuffer::new();
+        static MAILBOX_STATE: $crate::services::ffa::spmd::MailboxState<
+            { <$platform as $crate::platform::Platform>::CORE_COUNT },
+        > = $crate::services::ffa::spmd::MailboxState::new();
+        static PSCI_POWER_STATS: $crate::services::psci::PowerDomainStatsTable<
+                            { <$platform as $crate::platform::Platform>::CORE_COUNT },
+                            NON_CPU_DOMAIN_COUNT,
+                            <<$platform as $crate::platform::Board>::PsciPlatformImpl as
+                                $crate::services::psci::PsciPlatformInterface<
+                                    PSCI_STATE_COUNT,
+                                    MAX_POWER_LEVEL_,
+                                    { <$platform as $crate::platform::Platform>::CORE_COUNT },
+                                    NON_CPU_DOMAIN_COUNT,
+                                >>::PlatformPowerState,
+                        > = $crate::services::psci::PowerDomainStatsTable::new();
+        static SDEI_STATE: $crate::services::sdei::SdeiState<
+            { <$platform as $crate::platform::Platform>::CORE_COUNT },
+            $platform,
+        > = $crate::services::sdei::SdeiState::new();
+        static DEFERRED_WORK: $crate::services::deferred::DeferredWorkQueue<
+            { <$platform as $crate::platform::Platform>::CORE_COUNT },
+            $platform,
+        > = $crate::services::deferred::DeferredWorkQueue::new();
+        static PMF: $crate::pmf::Pmf<{ <$platform as $crate::platform::Platform>::CORE_COUNT }> =
+            $crate::pmf::Pmf::new();
 
         // SAFETY: `world_cpu_context` just calls `CpuStates::world_cpu_context`, which is
         // guaranteed to return a valid pointer.
@@ -444,11 +640,12 @@ macro_rules! statics {
                 NON_CPU_DOMAIN_COUNT,
                 TRNG_REQ_WORDS,
                 TRNG_WORDS_IN_POOL,
-                { <$platform as $crate::platform::Platform>::PAGE_HEAP_PAGE_COUNT },
+                { <$platform as $crate::platform::Board>::PAGE_HEAP_PAGE_COUNT },
                 $platform,
             >(
                 &PAGE_TABLE,
                 &PAGE_HEAP,
+                &EL3_HEAP,
                 &GIC,
                 &SERVICES,
                 arg0,
@@ -460,10 +657,11 @@ macro_rules! statics {
     };
 }
 
-/// Generates a panic handler which will log the panic message to `LOGGER` then loop forever.
+/// Generates a panic handler which will log the panic message to `LOGGER`, then save and print a
+/// crash dump of the current core with `crash_dump::report_crash`.
 #[macro_export]
 macro_rules! panic_handler {
-    () => {
+    ($platform:ty) => {
         #[cfg(not(test))]
         #[panic_handler]
         fn panic(info: &core::panic::PanicInfo) -> ! {
@@ -472,7 +670,7 @@ macro_rules! panic_handler {
             if let Some(sink) = LOGGER.log_sink() {
                 writeln!(sink, "{info}");
             }
-            loop {}
+            $crate::crash_dump::report_crash::<$platform>($crate::crash_dump::CrashReason::Panic)
         }
     };
 }
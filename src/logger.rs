@@ -5,6 +5,7 @@
 //! Traits and implementations for loggers.
 
 pub mod inmemory;
+pub mod pl011;
 
 use core::{
     fmt::{Arguments, Write},
@@ -0,0 +1,169 @@
+// Copyright The Rusted Firmware-A Contributors.
+//
+// SPDX-License-Identifier: BSD-3-Clause
+
+//! Log sink for a PL011 UART, optionally configured by BL31 itself.
+
+use super::LogSink;
+use arm_pl011_uart::{
+    DataBits, Error, LineConfig, PL011Registers, Parity, StopBits, Uart, UniqueMmioPointer,
+};
+use core::fmt::{Arguments, Write};
+use spin::mutex::SpinMutex;
+
+/// Offset of the UARTCR register from the base of the PL011 register block.
+const UARTCR_OFFSET: usize = 0x30;
+/// UARTCR bit enabling RTS hardware flow control.
+const UARTCR_RTSEN: u32 = 1 << 14;
+/// UARTCR bit enabling CTS hardware flow control.
+const UARTCR_CTSEN: u32 = 1 << 15;
+
+/// Configuration to apply to a PL011 UART when BL31 is responsible for setting it up.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Pl011Config {
+    /// The frequency of the UART reference clock, in Hz.
+    pub clock_hz: u32,
+    /// The baud rate to use.
+    pub baud_rate: u32,
+    /// Whether to enable RTS/CTS hardware flow control.
+    pub flow_control: bool,
+}
+
+/// A [`LogSink`] writing to a PL011 UART.
+///
+/// Unlike wrapping a [`Uart`] in a [`LockedWriter`](super::LockedWriter), flushing waits until the
+/// UART has finished transmitting everything in its TX FIFO.
+pub struct Pl011Console {
+    uart: SpinMutex<Uart<'static>>,
+}
+
+impl Pl011Console {
+    /// Creates a console for a UART which has already been configured by earlier firmware.
+    pub fn new(regs: UniqueMmioPointer<'static, PL011Registers>) -> Self {
+        Self {
+            uart: SpinMutex::new(Uart::new(regs)),
+        }
+    }
+
+    /// Creates a console for the given UART, configuring it for 8N1 with the given clock, baud
+    /// rate and flow control.
+    ///
+    /// Returns an error if the baud rate can't be derived from the given clock.
+    pub fn with_config(
+        mut regs: UniqueMmioPointer<'static, PL011Registers>,
+        config: &Pl011Config,
+    ) -> Result<Self, Error> {
+        let control = regs
+            .ptr_mut()
+            .wrapping_byte_add(UARTCR_OFFSET)
+            .cast::<u32>();
+        let mut uart = Uart::new(regs);
+        uart.enable(
+            LineConfig {
+                data_bits: DataBits::Bits8,
+                parity: Parity::None,
+                stop_bits: StopBits::One,
+            },
+            config.baud_rate,
+            config.clock_hz,
+        )?;
+        if config.flow_control {
+            // SAFETY: `control` points to the UARTCR register of the PL011 which `uart` has unique
+            // access to, and we are not using `uart` concurrently.
+            unsafe { enable_flow_control(control) };
+        }
+        Ok(Self {
+            uart: SpinMutex::new(uart),
+        })
+    }
+}
+
+/// Sets the RTS and CTS enable bits in the given UARTCR register.
+///
+/// # Safety
+///
+/// `control` must point to the UARTCR register of a PL011 UART, and nothing else may access it
+/// concurrently.
+unsafe fn enable_flow_control(control: *mut u32) {
+    // SAFETY: The caller guarantees that `control` is a valid pointer to the UARTCR register, and
+    // that there are no concurrent accesses.
+    unsafe {
+        let value = control.read_volatile();
+        control.write_volatile(value | UARTCR_RTSEN | UARTCR_CTSEN);
+    }
+}
+
+impl LogSink for Pl011Console {
+    fn write_fmt(&self, args: Arguments) {
+        // Ignore errors.
+        let _ = self.uart.lock().write_fmt(args);
+    }
+
+    fn flush(&self) {
+        let uart = self.uart.lock();
+        while !uart.is_tx_fifo_empty() || uart.is_busy() {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use zerocopy::FromZeros;
+
+    const UARTFR_TXFE: u32 = 1 << 7;
+
+    /// Returns a leaked zeroed PL011 register block, and a pointer to it as an array of words.
+    fn fake_registers() -> (UniqueMmioPointer<'static, PL011Registers>, *mut u32) {
+        let regs = Box::leak(Box::new(PL011Registers::new_zeroed()));
+        let words = (regs as *mut PL011Registers).cast::<u32>();
+        (UniqueMmioPointer::from(regs), words)
+    }
+
+    #[test]
+    fn configure_with_flow_control() {
+        let (regs, words) = fake_registers();
+        let console = Pl011Console::with_config(
+            regs,
+            &Pl011Config {
+                clock_hz: 24_000_000,
+                baud_rate: 115_200,
+                flow_control: true,
+            },
+        )
+        .unwrap();
+
+        // SAFETY: `words` points to the leaked register block, which is only accessed by this
+        // test.
+        let (ibrd, fbrd, control) = unsafe {
+            (
+                words.add(0x24 / 4).read_volatile(),
+                words.add(0x28 / 4).read_volatile(),
+                words.add(UARTCR_OFFSET / 4).read_volatile(),
+            )
+        };
+        assert_eq!((ibrd, fbrd), (13, 1));
+        assert_ne!(control & UARTCR_RTSEN, 0);
+        assert_ne!(control & UARTCR_CTSEN, 0);
+
+        // SAFETY: As above.
+        unsafe { words.add(0x18 / 4).write_volatile(UARTFR_TXFE) };
+        console.flush();
+    }
+
+    #[test]
+    fn invalid_clock() {
+        let (regs, _) = fake_registers();
+        assert_eq!(
+            Pl011Console::with_config(
+                regs,
+                &Pl011Config {
+                    clock_hz: 1,
+                    baud_rate: 115_200,
+                    flow_control: false,
+                },
+            )
+            .err(),
+            Some(Error::InvalidParameter)
+        );
+    }
+}
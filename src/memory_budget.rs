@@ -0,0 +1,98 @@
+// Copyright The Rusted Firmware-A Contributors.
+//
+// SPDX-License-Identifier: BSD-3-Clause
+
+//! A build-time summary of the memory BL31 uses for state which scales with the number of cores.
+
+use crate::stacks::STACK_SIZE;
+use core::fmt::{self, Display, Formatter};
+
+/// The sizes in bytes of the main statically allocated structures, for a given platform.
+///
+/// This is computed at build time by [`coldboot`](crate::coldboot), and logged during boot.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct MemoryBudget {
+    /// The number of cores the platform has.
+    pub core_count: usize,
+    /// The stacks of all cores.
+    pub stacks: usize,
+    /// The saved lower EL contexts of all cores, for all worlds.
+    pub cpu_states: usize,
+    /// The per-core data used by assembly code.
+    pub cpu_data: usize,
+    /// The GIC driver, including the redistributors of all cores.
+    pub gic: usize,
+    /// All runtime services, including their per-core state.
+    pub services: usize,
+}
+
+impl MemoryBudget {
+    /// Returns the total of all the structures.
+    pub const fn total(&self) -> usize {
+        self.stacks + self.cpu_states + self.cpu_data + self.gic + self.services
+    }
+
+    /// Panics if any structure which is constructed on the stack of the primary core during cold
+    /// boot wouldn't fit in it.
+    ///
+    /// When called in a const context this fails the build instead of overflowing the stack at
+    /// runtime.
+    pub const fn assert_fits_stack(&self) {
+        assert!(
+            self.gic < STACK_SIZE / 2,
+            "GIC driver is too big to construct on the stack for this many cores"
+        );
+        assert!(
+            self.services < STACK_SIZE / 2,
+            "Services are too big to construct on the stack for this many cores"
+        );
+    }
+}
+
+impl Display for MemoryBudget {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} bytes for {} cores ({} bytes/core): stacks {}, CPU states {}, CPU data {}, GIC {}, \
+             services {}",
+            self.total(),
+            self.core_count,
+            self.total() / self.core_count,
+            self.stacks,
+            self.cpu_states,
+            self.cpu_data,
+            self.gic,
+            self.services,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BUDGET: MemoryBudget = MemoryBudget {
+        core_count: 4,
+        stacks: 4 * STACK_SIZE,
+        cpu_states: 4000,
+        cpu_data: 256,
+        gic: 100,
+        services: 1000,
+    };
+
+    #[test]
+    fn total() {
+        assert_eq!(BUDGET.total(), 4 * STACK_SIZE + 5356);
+        const { BUDGET.assert_fits_stack() };
+    }
+
+    #[test]
+    #[should_panic(expected = "Services are too big")]
+    fn services_too_big() {
+        MemoryBudget {
+            services: STACK_SIZE,
+            ..BUDGET
+        }
+        .assert_fits_stack();
+    }
+}
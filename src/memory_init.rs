@@ -0,0 +1,145 @@
+// Copyright The Rusted Firmware-A Contributors.
+//
+// SPDX-License-Identifier: BSD-3-Clause
+
+//! Platform-specific memory initialisation, such as scrubbing DRAM to initialise its ECC, which
+//! must be completed before the normal world is first entered.
+
+use crate::{
+    platform::Platform,
+    timer::{self, TimedOut},
+    watchdog,
+};
+use log::{info, warn};
+
+/// How often progress is logged, in percent of the total work.
+const LOG_INTERVAL_PERCENT: u64 = 10;
+
+/// Tracks the progress of [`Platform::init_memory`], logging it and checking it against the
+/// platform's time budget.
+#[derive(Debug)]
+pub struct MemoryInitProgress {
+    /// The generic timer counter value when initialisation started.
+    start: u64,
+    /// The number of generic timer ticks which initialisation may take, if limited.
+    budget_ticks: Option<u64>,
+    /// The percentage of the work which had been done when progress was last logged.
+    logged_percent: Option<u64>,
+    /// Refreshes the trusted watchdog if EL3 is responsible for it.
+    refresh_watchdog: fn(),
+}
+
+impl MemoryInitProgress {
+    fn new(start: u64, budget_ticks: Option<u64>, refresh_watchdog: fn()) -> Self {
+        Self {
+            start,
+            budget_ticks,
+            logged_percent: None,
+            refresh_watchdog,
+        }
+    }
+
+    /// Records that `done` out of `total` units of work, e.g. bytes scrubbed, have been completed.
+    ///
+    /// Progress is logged every 10%, and the trusted watchdog is refreshed if it is due and the
+    /// platform's `WATCHDOG_POLICY` makes EL3 responsible for it. Returns `TimedOut` if the
+    /// platform's time budget has been exceeded, in which case the platform should stop and return
+    /// the error.
+    pub fn update(&mut self, done: u64, total: u64) -> Result<(), TimedOut> {
+        self.update_at(done, total, timer::counter())
+    }
+
+    fn update_at(&mut self, done: u64, total: u64, now: u64) -> Result<(), TimedOut> {
+        let percent = if total == 0 {
+            100
+        } else {
+            (u128::from(done.min(total)) * 100 / u128::from(total)) as u64
+        };
+        let step = percent / LOG_INTERVAL_PERCENT * LOG_INTERVAL_PERCENT;
+        if self.logged_percent.is_none_or(|logged| step > logged) {
+            info!("Memory initialisation {percent}% done ({done}/{total})");
+            self.logged_percent = Some(step);
+        }
+        (self.refresh_watchdog)();
+
+        if self.exceeded_budget_at(now) {
+            Err(TimedOut)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn elapsed_at(&self, now: u64) -> u64 {
+        now.wrapping_sub(self.start)
+    }
+
+    fn exceeded_budget_at(&self, now: u64) -> bool {
+        self.budget_ticks
+            .is_some_and(|budget| self.elapsed_at(now) > budget)
+    }
+}
+
+/// Runs the platform's memory initialisation, panicking if it runs out of time.
+///
+/// This must be called once during cold boot, on the primary core, after the CPU contexts have
+/// been initialised and before any lower EL is entered.
+pub(crate) fn init_memory<PlatformImpl: Platform>() {
+    let budget_ms = PlatformImpl::MEMORY_INIT_BUDGET_MS;
+    let mut progress = MemoryInitProgress::new(
+        timer::counter(),
+        budget_ms.map(|budget_ms| timer::micros_to_ticks(budget_ms.saturating_mul(1000))),
+        watchdog::refresh_if_due::<PlatformImpl>,
+    );
+
+    if PlatformImpl::init_memory(&mut progress).is_err() {
+        panic!(
+            "Memory initialisation exceeded its budget of {} ms",
+            budget_ms.unwrap_or_default()
+        );
+    }
+
+    // Only log if the platform did anything.
+    if progress.logged_percent.is_some() {
+        let now = timer::counter();
+        let elapsed_ms = timer::ticks_to_micros(progress.elapsed_at(now)) / 1000;
+        if progress.exceeded_budget_at(now) {
+            warn!(
+                "Memory initialisation took {elapsed_ms} ms, over its budget of {} ms",
+                budget_ms.unwrap_or_default()
+            );
+        } else {
+            info!("Memory initialisation finished in {elapsed_ms} ms");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn logs_every_interval() {
+        let mut progress = MemoryInitProgress::new(0, None, || {});
+        assert_eq!(progress.update_at(0, 1000, 0), Ok(()));
+        assert_eq!(progress.logged_percent, Some(0));
+        assert_eq!(progress.update_at(95, 1000, 0), Ok(()));
+        assert_eq!(progress.logged_percent, Some(0));
+        assert_eq!(progress.update_at(250, 1000, 0), Ok(()));
+        assert_eq!(progress.logged_percent, Some(20));
+        // Going backwards or past the end doesn't confuse it.
+        assert_eq!(progress.update_at(100, 1000, 0), Ok(()));
+        assert_eq!(progress.logged_percent, Some(20));
+        assert_eq!(progress.update_at(2000, 1000, 0), Ok(()));
+        assert_eq!(progress.logged_percent, Some(100));
+    }
+
+    #[test]
+    fn budget() {
+        let mut progress = MemoryInitProgress::new(1000, Some(500), || {});
+        assert_eq!(progress.update_at(1, 4, 1500), Ok(()));
+        assert_eq!(progress.update_at(2, 4, 1501), Err(TimedOut));
+
+        let mut progress = MemoryInitProgress::new(1000, None, || {});
+        assert_eq!(progress.update_at(1, 4, u64::MAX), Ok(()));
+    }
+}
@@ -0,0 +1,866 @@
+// Copyright The Rusted Firmware-A Contributors.
+//
+// SPDX-License-Identifier: BSD-3-Clause
+
+//! Drivers for the Arm Message Handling Unit (MHU) doorbells, used to communicate with management
+//! processors such as an SCP or RSE.
+//!
+//! An MHU consists of a sender frame (called a postbox in MHUv3) and a receiver frame (called a
+//! mailbox in MHUv3). Each frame has a number of doorbell channels, each of which carries 32 flag
+//! bits. The sender sets flags on a channel, which raises an interrupt on the receiving side, and
+//! the receiver clears them once it has handled the doorbell.
+
+use core::hint::spin_loop;
+use safe_mmio::{
+    UniqueMmioPointer, field, field_shared,
+    fields::{ReadPure, ReadPureWrite, WriteOnly},
+};
+use zerocopy::{FromBytes, Immutable, IntoBytes, KnownLayout};
+
+/// The maximum number of doorbell channels in an MHUv2 frame.
+const MHUV2_MAX_CHANNELS: usize = 124;
+/// The maximum number of doorbell channels in an MHUv3 frame.
+const MHUV3_MAX_CHANNELS: usize = 128;
+
+const MHUV2_CFG_NUM_CH_MASK: u32 = 0x7f;
+const MHUV2_ACCESS_REQUEST: u32 = 1 << 0;
+const MHUV2_ACCESS_READY: u32 = 1 << 0;
+
+const MHUV3_FEAT_SPT0_DBE: u32 = 1 << 0;
+const MHUV3_DBCH_CFG0_NUM_DBCH_MASK: u32 = 0xff;
+const MHUV3_CTRL_OP_REQ: u32 = 1 << 0;
+
+/// An error communicating over an MHU.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum MhuError {
+    /// The channel index is beyond the number of channels implemented by the frame.
+    InvalidChannel(usize),
+    /// The channel is not owned by the secure world, so must not be used by EL3.
+    NotSecure(usize),
+    /// The MHU doesn't have enough channels to transfer messages.
+    TooFewChannels,
+    /// A received message of the given length didn't fit in the buffer provided.
+    MessageTooLong(usize),
+}
+
+/// The sending side of a set of MHU doorbell channels.
+pub trait DoorbellSender {
+    /// Returns the number of doorbell channels implemented.
+    fn channel_count(&self) -> usize;
+
+    /// Sets the given flags on the given channel, ringing the doorbell.
+    fn ring(&mut self, channel: usize, flags: u32) -> Result<(), MhuError>;
+
+    /// Returns the flags on the given channel which the receiver has not yet cleared.
+    fn status(&self, channel: usize) -> Result<u32, MhuError>;
+}
+
+/// The receiving side of a set of MHU doorbell channels.
+pub trait DoorbellReceiver {
+    /// Returns the number of doorbell channels implemented.
+    fn channel_count(&self) -> usize;
+
+    /// Returns the flags currently set on the given channel by the sender.
+    fn pending(&self, channel: usize) -> Result<u32, MhuError>;
+
+    /// Clears the given flags on the given channel, acknowledging the doorbell.
+    fn clear(&mut self, channel: usize, flags: u32) -> Result<(), MhuError>;
+}
+
+fn check_channel(channel: usize, channel_count: usize) -> Result<(), MhuError> {
+    if channel < channel_count {
+        Ok(())
+    } else {
+        Err(MhuError::InvalidChannel(channel))
+    }
+}
+
+/// MHUv2 sender frame channel window.
+#[derive(FromBytes, Immutable, IntoBytes, KnownLayout)]
+#[repr(C, align(4))]
+pub struct Mhuv2SenderChannel {
+    /// 0x00: Channel status.
+    ch_st: ReadPure<u32>,
+    /// 0x04 - 0x08
+    reserved_04: [u32; 2],
+    /// 0x0C: Channel flag set.
+    ch_set: WriteOnly<u32>,
+    /// 0x10: Channel interrupt status.
+    ch_int_st: ReadPure<u32>,
+    /// 0x14: Channel interrupt clear.
+    ch_int_clr: WriteOnly<u32>,
+    /// 0x18: Channel interrupt enable.
+    ch_int_en: ReadPureWrite<u32>,
+    /// 0x1C
+    reserved_1c: u32,
+}
+
+/// MHUv2 sender frame register map.
+#[derive(FromBytes, Immutable, IntoBytes, KnownLayout)]
+#[repr(C, align(4))]
+pub struct Mhuv2SenderRegisters {
+    /// 0x000 - 0xF7C: Channel windows.
+    channels: [Mhuv2SenderChannel; MHUV2_MAX_CHANNELS],
+    /// 0xF80: MHU configuration.
+    mhu_cfg: ReadPure<u32>,
+    /// 0xF84: Response configuration.
+    resp_cfg: ReadPureWrite<u32>,
+    /// 0xF88: Access request.
+    access_request: ReadPureWrite<u32>,
+    /// 0xF8C: Access ready.
+    access_ready: ReadPure<u32>,
+    /// 0xF90: Interrupt status.
+    int_st: ReadPure<u32>,
+    /// 0xF94: Interrupt clear.
+    int_clr: WriteOnly<u32>,
+    /// 0xF98: Interrupt enable.
+    int_en: ReadPureWrite<u32>,
+    /// 0xF9C
+    reserved_f9c: u32,
+    /// 0xFA0 - 0xFAC: Channel combined interrupt status.
+    chcomb_int_st: [ReadPure<u32>; 4],
+    /// 0xFB0 - 0xFC4
+    reserved_fb0: [u32; 6],
+    /// 0xFC8: Implementer identification.
+    iidr: ReadPure<u32>,
+    /// 0xFCC: Architecture identification.
+    aidr: ReadPure<u32>,
+    /// 0xFD0 - 0xFFC: Peripheral and component identification.
+    pid_cid: [ReadPure<u32>; 12],
+}
+
+/// MHUv2 receiver frame channel window.
+#[derive(FromBytes, Immutable, IntoBytes, KnownLayout)]
+#[repr(C, align(4))]
+pub struct Mhuv2ReceiverChannel {
+    /// 0x00: Channel status.
+    ch_st: ReadPure<u32>,
+    /// 0x04: Channel status masked.
+    ch_st_msk: ReadPure<u32>,
+    /// 0x08: Channel flag clear.
+    ch_clr: WriteOnly<u32>,
+    /// 0x0C
+    reserved_0c: u32,
+    /// 0x10: Channel mask status.
+    ch_msk_st: ReadPure<u32>,
+    /// 0x14: Channel mask set.
+    ch_msk_set: WriteOnly<u32>,
+    /// 0x18: Channel mask clear.
+    ch_msk_clr: WriteOnly<u32>,
+    /// 0x1C
+    reserved_1c: u32,
+}
+
+/// MHUv2 receiver frame register map.
+#[derive(FromBytes, Immutable, IntoBytes, KnownLayout)]
+#[repr(C, align(4))]
+pub struct Mhuv2ReceiverRegisters {
+    /// 0x000 - 0xF7C: Channel windows.
+    channels: [Mhuv2ReceiverChannel; MHUV2_MAX_CHANNELS],
+    /// 0xF80: MHU configuration.
+    mhu_cfg: ReadPure<u32>,
+    /// 0xF84 - 0xF8C
+    reserved_f84: [u32; 3],
+    /// 0xF90: Interrupt status.
+    int_st: ReadPure<u32>,
+    /// 0xF94: Interrupt clear.
+    int_clr: WriteOnly<u32>,
+    /// 0xF98: Interrupt enable.
+    int_en: ReadPureWrite<u32>,
+    /// 0xF9C
+    reserved_f9c: u32,
+    /// 0xFA0 - 0xFAC: Channel combined interrupt status.
+    chcomb_int_st: [ReadPure<u32>; 4],
+    /// 0xFB0 - 0xFC4
+    reserved_fb0: [u32; 6],
+    /// 0xFC8: Implementer identification.
+    iidr: ReadPure<u32>,
+    /// 0xFCC: Architecture identification.
+    aidr: ReadPure<u32>,
+    /// 0xFD0 - 0xFFC: Peripheral and component identification.
+    pid_cid: [ReadPure<u32>; 12],
+}
+
+const _: () = assert!(size_of::<Mhuv2SenderRegisters>() == 0x1000);
+const _: () = assert!(size_of::<Mhuv2ReceiverRegisters>() == 0x1000);
+
+/// Driver for an MHUv2 sender frame.
+pub struct Mhuv2Sender<'a> {
+    regs: UniqueMmioPointer<'a, Mhuv2SenderRegisters>,
+    channel_count: usize,
+}
+
+impl<'a> Mhuv2Sender<'a> {
+    /// Creates a driver for the given sender frame, requesting access to the receiver and waiting
+    /// until it is ready.
+    pub fn new(mut regs: UniqueMmioPointer<'a, Mhuv2SenderRegisters>) -> Self {
+        field!(regs, access_request).write(MHUV2_ACCESS_REQUEST);
+        while field_shared!(regs, access_ready).read() & MHUV2_ACCESS_READY == 0 {
+            spin_loop();
+        }
+        let channel_count = (field_shared!(regs, mhu_cfg).read() & MHUV2_CFG_NUM_CH_MASK) as usize;
+        Self {
+            regs,
+            channel_count,
+        }
+    }
+}
+
+impl DoorbellSender for Mhuv2Sender<'_> {
+    fn channel_count(&self) -> usize {
+        self.channel_count
+    }
+
+    fn ring(&mut self, channel: usize, flags: u32) -> Result<(), MhuError> {
+        check_channel(channel, self.channel_count)?;
+        let mut channels = field!(self.regs, channels);
+        let mut window = channels.get(channel).unwrap();
+        field!(window, ch_set).write(flags);
+        Ok(())
+    }
+
+    fn status(&self, channel: usize) -> Result<u32, MhuError> {
+        check_channel(channel, self.channel_count)?;
+        let channels = field_shared!(self.regs, channels);
+        Ok(field_shared!(channels.get(channel).unwrap(), ch_st).read())
+    }
+}
+
+/// Driver for an MHUv2 receiver frame.
+pub struct Mhuv2Receiver<'a> {
+    regs: UniqueMmioPointer<'a, Mhuv2ReceiverRegisters>,
+    channel_count: usize,
+}
+
+impl<'a> Mhuv2Receiver<'a> {
+    /// Creates a driver for the given receiver frame.
+    pub fn new(regs: UniqueMmioPointer<'a, Mhuv2ReceiverRegisters>) -> Self {
+        let channel_count = (field_shared!(regs, mhu_cfg).read() & MHUV2_CFG_NUM_CH_MASK) as usize;
+        Self {
+            regs,
+            channel_count,
+        }
+    }
+}
+
+impl DoorbellReceiver for Mhuv2Receiver<'_> {
+    fn channel_count(&self) -> usize {
+        self.channel_count
+    }
+
+    fn pending(&self, channel: usize) -> Result<u32, MhuError> {
+        check_channel(channel, self.channel_count)?;
+        let channels = field_shared!(self.regs, channels);
+        Ok(field_shared!(channels.get(channel).unwrap(), ch_st).read())
+    }
+
+    fn clear(&mut self, channel: usize, flags: u32) -> Result<(), MhuError> {
+        check_channel(channel, self.channel_count)?;
+        let mut channels = field!(self.regs, channels);
+        let mut window = channels.get(channel).unwrap();
+        field!(window, ch_clr).write(flags);
+        Ok(())
+    }
+}
+
+/// MHUv3 control page, common to postbox and mailbox frames.
+#[derive(FromBytes, Immutable, IntoBytes, KnownLayout)]
+#[repr(C, align(4))]
+pub struct Mhuv3ControlPage {
+    /// 0x000: Block identifier.
+    blk_id: ReadPure<u32>,
+    /// 0x004 - 0x00C
+    reserved_004: [u32; 3],
+    /// 0x010: Feature support 0.
+    feat_spt0: ReadPure<u32>,
+    /// 0x014: Feature support 1.
+    feat_spt1: ReadPure<u32>,
+    /// 0x018 - 0x01C
+    reserved_018: [u32; 2],
+    /// 0x020: Doorbell channel configuration 0.
+    dbch_cfg0: ReadPure<u32>,
+    /// 0x024 - 0x0FC
+    reserved_024: [u32; 55],
+    /// 0x100: Control.
+    ctrl: ReadPureWrite<u32>,
+    /// 0x104 - 0xFC4
+    reserved_104: [u32; 945],
+    /// 0xFC8: Implementer identification.
+    iidr: ReadPure<u32>,
+    /// 0xFCC: Architecture identification.
+    aidr: ReadPure<u32>,
+    /// 0xFD0 - 0xFFC: Peripheral and component identification.
+    pid_cid: [ReadPure<u32>; 12],
+}
+
+impl Mhuv3ControlPage {
+    fn channel_count(regs: &UniqueMmioPointer<Self>) -> usize {
+        if field_shared!(regs, feat_spt0).read() & MHUV3_FEAT_SPT0_DBE == 0 {
+            return 0;
+        }
+        (field_shared!(regs, dbch_cfg0).read() & MHUV3_DBCH_CFG0_NUM_DBCH_MASK) as usize + 1
+    }
+}
+
+/// MHUv3 postbox doorbell channel window.
+#[derive(FromBytes, Immutable, IntoBytes, KnownLayout)]
+#[repr(C, align(4))]
+pub struct Mhuv3PostboxChannel {
+    /// 0x00: Channel status.
+    st: ReadPure<u32>,
+    /// 0x04 - 0x08
+    reserved_04: [u32; 2],
+    /// 0x0C: Channel flag set.
+    set: WriteOnly<u32>,
+    /// 0x10: Channel interrupt status.
+    int_st: ReadPure<u32>,
+    /// 0x14: Channel interrupt clear.
+    int_clr: WriteOnly<u32>,
+    /// 0x18: Channel interrupt enable.
+    int_en: ReadPureWrite<u32>,
+    /// 0x1C: Channel control.
+    ctrl: ReadPureWrite<u32>,
+}
+
+/// The doorbell part of an MHUv3 postbox frame register map.
+#[derive(FromBytes, Immutable, IntoBytes, KnownLayout)]
+#[repr(C, align(4))]
+pub struct Mhuv3PostboxRegisters {
+    /// 0x0000 - 0x0FFC: Control page.
+    control: Mhuv3ControlPage,
+    /// 0x1000 - 0x1FFC: Doorbell channel windows.
+    channels: [Mhuv3PostboxChannel; MHUV3_MAX_CHANNELS],
+}
+
+/// MHUv3 mailbox doorbell channel window.
+#[derive(FromBytes, Immutable, IntoBytes, KnownLayout)]
+#[repr(C, align(4))]
+pub struct Mhuv3MailboxChannel {
+    /// 0x00: Channel status.
+    st: ReadPure<u32>,
+    /// 0x04: Channel status masked.
+    st_msk: ReadPure<u32>,
+    /// 0x08: Channel flag clear.
+    clr: WriteOnly<u32>,
+    /// 0x0C
+    reserved_0c: u32,
+    /// 0x10: Channel mask status.
+    msk_st: ReadPure<u32>,
+    /// 0x14: Channel mask set.
+    msk_set: WriteOnly<u32>,
+    /// 0x18: Channel mask clear.
+    msk_clr: WriteOnly<u32>,
+    /// 0x1C: Channel control.
+    ctrl: ReadPureWrite<u32>,
+}
+
+/// The doorbell part of an MHUv3 mailbox frame register map.
+#[derive(FromBytes, Immutable, IntoBytes, KnownLayout)]
+#[repr(C, align(4))]
+pub struct Mhuv3MailboxRegisters {
+    /// 0x0000 - 0x0FFC: Control page.
+    control: Mhuv3ControlPage,
+    /// 0x1000 - 0x1FFC: Doorbell channel windows.
+    channels: [Mhuv3MailboxChannel; MHUV3_MAX_CHANNELS],
+}
+
+const _: () = assert!(size_of::<Mhuv3ControlPage>() == 0x1000);
+const _: () = assert!(size_of::<Mhuv3PostboxRegisters>() == 0x2000);
+const _: () = assert!(size_of::<Mhuv3MailboxRegisters>() == 0x2000);
+
+/// Driver for the doorbell channels of an MHUv3 postbox frame.
+pub struct Mhuv3Postbox<'a> {
+    regs: UniqueMmioPointer<'a, Mhuv3PostboxRegisters>,
+    channel_count: usize,
+}
+
+impl<'a> Mhuv3Postbox<'a> {
+    /// Creates a driver for the given postbox frame, requesting that the MHU is kept operational.
+    pub fn new(mut regs: UniqueMmioPointer<'a, Mhuv3PostboxRegisters>) -> Self {
+        let mut control = field!(regs, control);
+        field!(control, ctrl).modify(|ctrl| ctrl | MHUV3_CTRL_OP_REQ);
+        let channel_count = Mhuv3ControlPage::channel_count(&control);
+        Self {
+            regs,
+            channel_count,
+        }
+    }
+}
+
+impl DoorbellSender for Mhuv3Postbox<'_> {
+    fn channel_count(&self) -> usize {
+        self.channel_count
+    }
+
+    fn ring(&mut self, channel: usize, flags: u32) -> Result<(), MhuError> {
+        check_channel(channel, self.channel_count)?;
+        let mut channels = field!(self.regs, channels);
+        let mut window = channels.get(channel).unwrap();
+        field!(window, set).write(flags);
+        Ok(())
+    }
+
+    fn status(&self, channel: usize) -> Result<u32, MhuError> {
+        check_channel(channel, self.channel_count)?;
+        let channels = field_shared!(self.regs, channels);
+        Ok(field_shared!(channels.get(channel).unwrap(), st).read())
+    }
+}
+
+/// Driver for the doorbell channels of an MHUv3 mailbox frame.
+pub struct Mhuv3Mailbox<'a> {
+    regs: UniqueMmioPointer<'a, Mhuv3MailboxRegisters>,
+    channel_count: usize,
+}
+
+impl<'a> Mhuv3Mailbox<'a> {
+    /// Creates a driver for the given mailbox frame.
+    pub fn new(mut regs: UniqueMmioPointer<'a, Mhuv3MailboxRegisters>) -> Self {
+        let channel_count = Mhuv3ControlPage::channel_count(&field!(regs, control));
+        Self {
+            regs,
+            channel_count,
+        }
+    }
+}
+
+impl DoorbellReceiver for Mhuv3Mailbox<'_> {
+    fn channel_count(&self) -> usize {
+        self.channel_count
+    }
+
+    fn pending(&self, channel: usize) -> Result<u32, MhuError> {
+        check_channel(channel, self.channel_count)?;
+        let channels = field_shared!(self.regs, channels);
+        Ok(field_shared!(channels.get(channel).unwrap(), st).read())
+    }
+
+    fn clear(&mut self, channel: usize, flags: u32) -> Result<(), MhuError> {
+        check_channel(channel, self.channel_count)?;
+        let mut channels = field!(self.regs, channels);
+        let mut window = channels.get(channel).unwrap();
+        field!(window, clr).write(flags);
+        Ok(())
+    }
+}
+
+/// MHUv1 channel window.
+///
+/// MHUv1 has separate windows for low priority, high priority and secure doorbells in each
+/// direction, each with a single channel.
+#[derive(FromBytes, Immutable, IntoBytes, KnownLayout)]
+#[repr(C, align(4))]
+pub struct Mhuv1Channel {
+    /// 0x00: Channel status.
+    stat: ReadPure<u32>,
+    /// 0x04
+    reserved_04: u32,
+    /// 0x08: Channel flag set.
+    set: WriteOnly<u32>,
+    /// 0x0C
+    reserved_0c: u32,
+    /// 0x10: Channel flag clear.
+    clear: WriteOnly<u32>,
+    /// 0x14 - 0x1C
+    reserved_14: [u32; 3],
+}
+
+const _: () = assert!(size_of::<Mhuv1Channel>() == 0x20);
+
+/// Driver for a single MHUv1 channel window on which the application processor sends doorbells.
+pub struct Mhuv1Sender<'a> {
+    regs: UniqueMmioPointer<'a, Mhuv1Channel>,
+}
+
+impl<'a> Mhuv1Sender<'a> {
+    /// Creates a driver for the given channel window, which is channel 0.
+    pub fn new(regs: UniqueMmioPointer<'a, Mhuv1Channel>) -> Self {
+        Self { regs }
+    }
+}
+
+impl DoorbellSender for Mhuv1Sender<'_> {
+    fn channel_count(&self) -> usize {
+        1
+    }
+
+    fn ring(&mut self, channel: usize, flags: u32) -> Result<(), MhuError> {
+        check_channel(channel, 1)?;
+        field!(self.regs, set).write(flags);
+        Ok(())
+    }
+
+    fn status(&self, channel: usize) -> Result<u32, MhuError> {
+        check_channel(channel, 1)?;
+        Ok(field_shared!(self.regs, stat).read())
+    }
+}
+
+/// Driver for a single MHUv1 channel window on which the application processor receives
+/// doorbells.
+pub struct Mhuv1Receiver<'a> {
+    regs: UniqueMmioPointer<'a, Mhuv1Channel>,
+}
+
+impl<'a> Mhuv1Receiver<'a> {
+    /// Creates a driver for the given channel window, which is channel 0.
+    pub fn new(regs: UniqueMmioPointer<'a, Mhuv1Channel>) -> Self {
+        Self { regs }
+    }
+}
+
+impl DoorbellReceiver for Mhuv1Receiver<'_> {
+    fn channel_count(&self) -> usize {
+        1
+    }
+
+    fn pending(&self, channel: usize) -> Result<u32, MhuError> {
+        check_channel(channel, 1)?;
+        Ok(field_shared!(self.regs, stat).read())
+    }
+
+    fn clear(&mut self, channel: usize, flags: u32) -> Result<(), MhuError> {
+        check_channel(channel, 1)?;
+        field!(self.regs, clear).write(flags);
+        Ok(())
+    }
+}
+
+/// A bitmap of the doorbell channels which are owned by the secure world.
+///
+/// Which channels are secure is fixed by the platform's system security configuration; this
+/// records it so that EL3 never rings or clears a doorbell belonging to another world.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct SecureChannels(pub u128);
+
+impl SecureChannels {
+    /// Returns whether the given channel is owned by the secure world.
+    pub const fn contains(self, channel: usize) -> bool {
+        channel < u128::BITS as usize && self.0 & (1 << channel) != 0
+    }
+}
+
+/// A bidirectional link to a management processor over a pair of MHU frames, one in each
+/// direction.
+///
+/// Requests are sent by ringing a doorbell on the sender frame, and the response is signalled by
+/// the management processor ringing the doorbell with the same channel index on the receiver frame.
+pub struct MhuLink<S: DoorbellSender, R: DoorbellReceiver> {
+    sender: S,
+    receiver: R,
+    secure_channels: SecureChannels,
+}
+
+impl<S: DoorbellSender, R: DoorbellReceiver> MhuLink<S, R> {
+    /// Creates a new link over the given frames, of which EL3 may only use the given channels.
+    pub fn new(sender: S, receiver: R, secure_channels: SecureChannels) -> Self {
+        Self {
+            sender,
+            receiver,
+            secure_channels,
+        }
+    }
+
+    fn check_secure(&self, channel: usize) -> Result<(), MhuError> {
+        check_channel(
+            channel,
+            self.sender
+                .channel_count()
+                .min(self.receiver.channel_count()),
+        )?;
+        if self.secure_channels.contains(channel) {
+            Ok(())
+        } else {
+            Err(MhuError::NotSecure(channel))
+        }
+    }
+
+    /// Rings the doorbell on the given channel with the given flags, without waiting for a
+    /// response.
+    pub fn notify(&mut self, channel: usize, flags: u32) -> Result<(), MhuError> {
+        self.check_secure(channel)?;
+        self.sender.ring(channel, flags)
+    }
+
+    /// Rings the doorbell on the given channel with the given flags, then waits for the response
+    /// doorbell on the same channel, acknowledges it and returns its flags.
+    pub fn transact(&mut self, channel: usize, flags: u32) -> Result<u32, MhuError> {
+        self.notify(channel, flags)?;
+        let response = loop {
+            let pending = self.receiver.pending(channel)?;
+            if pending != 0 {
+                break pending;
+            }
+            spin_loop();
+        };
+        self.receiver.clear(channel, response)?;
+        Ok(response)
+    }
+
+    /// Returns the number of channels usable for message transfer, checking that they are all
+    /// secure.
+    fn message_channel_count(&self) -> Result<usize, MhuError> {
+        let channel_count = self
+            .sender
+            .channel_count()
+            .min(self.receiver.channel_count());
+        if channel_count < 2 {
+            return Err(MhuError::TooFewChannels);
+        }
+        for channel in 0..channel_count {
+            self.check_secure(channel)?;
+        }
+        Ok(channel_count)
+    }
+
+    /// Sends a message of arbitrary length.
+    ///
+    /// The message is sent as a sequence of little-endian 32-bit words, the first of which is the
+    /// length of the message in bytes. The words are sent in batches, with one word on each
+    /// channel but the last, and then the last channel is rung to signal that the batch is ready.
+    /// Each batch is complete once the receiver has cleared the signal channel.
+    pub fn send_message(&mut self, message: &[u8]) -> Result<(), MhuError> {
+        let channel_count = self.message_channel_count()?;
+        let signal_channel = channel_count - 1;
+
+        let mut words =
+            [message.len() as u32]
+                .into_iter()
+                .chain(message.chunks(size_of::<u32>()).map(|chunk| {
+                    chunk
+                        .iter()
+                        .rev()
+                        .fold(0, |word, &byte| word << 8 | byte as u32)
+                }));
+        let mut word = words.next();
+        while word.is_some() {
+            for channel in 0..signal_channel {
+                let Some(value) = word else {
+                    break;
+                };
+                self.sender.ring(channel, value)?;
+                word = words.next();
+            }
+            self.sender.ring(signal_channel, 1)?;
+            while self.sender.status(signal_channel)? != 0 {
+                spin_loop();
+            }
+        }
+        Ok(())
+    }
+
+    /// Receives a message sent in the same format as by [`send_message`](Self::send_message) into
+    /// the given buffer, returning its length.
+    ///
+    /// If the message doesn't fit in the buffer then it is still received in full, but the excess
+    /// is discarded and an error returned.
+    pub fn receive_message(&mut self, buffer: &mut [u8]) -> Result<usize, MhuError> {
+        let channel_count = self.message_channel_count()?;
+        let signal_channel = channel_count - 1;
+
+        let mut length = None;
+        let mut offset = 0;
+        loop {
+            let signal = loop {
+                let pending = self.receiver.pending(signal_channel)?;
+                if pending != 0 {
+                    break pending;
+                }
+                spin_loop();
+            };
+            for channel in 0..signal_channel {
+                let value = self.receiver.pending(channel)?;
+                self.receiver.clear(channel, value)?;
+                match length {
+                    None => length = Some(value as usize),
+                    Some(length) if offset < length => {
+                        let bytes = value.to_le_bytes();
+                        let count = (length - offset).min(bytes.len());
+                        if let Some(destination) = buffer.get_mut(offset..offset + count) {
+                            destination.copy_from_slice(&bytes[..count]);
+                        }
+                        offset += count;
+                    }
+                    Some(_) => {}
+                }
+            }
+            self.receiver.clear(signal_channel, signal)?;
+
+            if let Some(length) = length
+                && offset >= length
+            {
+                return if length > buffer.len() {
+                    Err(MhuError::MessageTooLong(length))
+                } else {
+                    Ok(length)
+                };
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use zerocopy::FromZeros;
+
+    #[test]
+    fn mhuv2_ring() {
+        let mut regs = Mhuv2SenderRegisters::new_zeroed();
+        regs.mhu_cfg.0 = 2;
+        regs.access_ready.0 = MHUV2_ACCESS_READY;
+        {
+            let mut sender = Mhuv2Sender::new(UniqueMmioPointer::from(&mut regs));
+            assert_eq!(sender.channel_count(), 2);
+            sender.ring(1, 0x42).unwrap();
+            assert_eq!(sender.ring(2, 0x42), Err(MhuError::InvalidChannel(2)));
+        }
+        assert_eq!(regs.access_request.0, MHUV2_ACCESS_REQUEST);
+        assert_eq!(regs.channels[0].ch_set.0, 0);
+        assert_eq!(regs.channels[1].ch_set.0, 0x42);
+    }
+
+    /// Fake sender which records the batches of words sent, and acknowledges each immediately.
+    #[derive(Default)]
+    struct FakeSender {
+        current: Vec<u32>,
+        batches: Vec<Vec<u32>>,
+    }
+
+    impl DoorbellSender for FakeSender {
+        fn channel_count(&self) -> usize {
+            3
+        }
+
+        fn ring(&mut self, channel: usize, flags: u32) -> Result<(), MhuError> {
+            if channel == 2 {
+                self.batches.push(std::mem::take(&mut self.current));
+            } else {
+                assert_eq!(channel, self.current.len());
+                self.current.push(flags);
+            }
+            Ok(())
+        }
+
+        fn status(&self, _channel: usize) -> Result<u32, MhuError> {
+            Ok(0)
+        }
+    }
+
+    /// Fake receiver which presents the given batches of words in turn.
+    #[derive(Default)]
+    struct FakeReceiver {
+        batches: Vec<[u32; 2]>,
+    }
+
+    impl DoorbellReceiver for FakeReceiver {
+        fn channel_count(&self) -> usize {
+            3
+        }
+
+        fn pending(&self, channel: usize) -> Result<u32, MhuError> {
+            Ok(match channel {
+                2 => 1,
+                _ => self.batches[0][channel],
+            })
+        }
+
+        fn clear(&mut self, channel: usize, _flags: u32) -> Result<(), MhuError> {
+            if channel == 2 {
+                self.batches.remove(0);
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn send_message() {
+        let mut link = MhuLink::new(
+            FakeSender::default(),
+            FakeReceiver::default(),
+            SecureChannels(0b111),
+        );
+        link.send_message(&[1, 2, 3, 4, 5]).unwrap();
+        assert_eq!(
+            link.sender.batches,
+            [vec![5, 0x0403_0201], vec![0x0000_0005]]
+        );
+
+        link.secure_channels = SecureChannels(0b011);
+        assert_eq!(link.send_message(&[]), Err(MhuError::NotSecure(2)));
+    }
+
+    #[test]
+    fn receive_message() {
+        let mut link = MhuLink::new(
+            FakeSender::default(),
+            FakeReceiver {
+                batches: vec![[6, 0x0403_0201], [0x0000_0605, 0]],
+            },
+            SecureChannels(0b111),
+        );
+        let mut buffer = [0; 8];
+        assert_eq!(link.receive_message(&mut buffer), Ok(6));
+        assert_eq!(buffer, [1, 2, 3, 4, 5, 6, 0, 0]);
+        assert!(link.receiver.batches.is_empty());
+
+        link.receiver.batches = vec![[6, 0x0403_0201], [0x0000_0605, 0]];
+        assert_eq!(
+            link.receive_message(&mut buffer[..4]),
+            Err(MhuError::MessageTooLong(6))
+        );
+        assert!(link.receiver.batches.is_empty());
+    }
+
+    #[test]
+    fn mhuv3_channel_count() {
+        let mut regs = Mhuv3MailboxRegisters::new_zeroed();
+        regs.control.dbch_cfg0.0 = 3;
+        assert_eq!(
+            Mhuv3Mailbox::new(UniqueMmioPointer::from(&mut regs)).channel_count(),
+            0
+        );
+
+        regs.control.feat_spt0.0 = MHUV3_FEAT_SPT0_DBE;
+        assert_eq!(
+            Mhuv3Mailbox::new(UniqueMmioPointer::from(&mut regs)).channel_count(),
+            4
+        );
+    }
+
+    #[test]
+    fn mhuv1_ring() {
+        let mut regs = Mhuv1Channel::new_zeroed();
+        regs.stat.0 = 0x3;
+        {
+            let mut sender = Mhuv1Sender::new(UniqueMmioPointer::from(&mut regs));
+            assert_eq!(sender.status(0), Ok(0x3));
+            sender.ring(0, 0x1).unwrap();
+            assert_eq!(sender.ring(1, 0x1), Err(MhuError::InvalidChannel(1)));
+        }
+        assert_eq!(regs.set.0, 0x1);
+    }
+
+    #[test]
+    fn link_transact() {
+        let mut sender_regs = Mhuv2SenderRegisters::new_zeroed();
+        sender_regs.mhu_cfg.0 = 4;
+        sender_regs.access_ready.0 = MHUV2_ACCESS_READY;
+        let mut receiver_regs = Mhuv2ReceiverRegisters::new_zeroed();
+        receiver_regs.mhu_cfg.0 = 4;
+        receiver_regs.channels[1].ch_st.0 = 0x5;
+        {
+            let mut link = MhuLink::new(
+                Mhuv2Sender::new(UniqueMmioPointer::from(&mut sender_regs)),
+                Mhuv2Receiver::new(UniqueMmioPointer::from(&mut receiver_regs)),
+                SecureChannels(0b0010),
+            );
+            assert_eq!(link.transact(1, 0x1), Ok(0x5));
+            assert_eq!(link.notify(0, 0x1), Err(MhuError::NotSecure(0)));
+            assert_eq!(link.notify(4, 0x1), Err(MhuError::InvalidChannel(4)));
+        }
+        assert_eq!(sender_regs.channels[0].ch_set.0, 0);
+        assert_eq!(sender_regs.channels[1].ch_set.0, 0x1);
+        assert_eq!(receiver_regs.channels[1].ch_clr.0, 0x5);
+    }
+}
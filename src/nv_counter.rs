@@ -0,0 +1,215 @@
+// Copyright The Rusted Firmware-A Contributors.
+//
+// SPDX-License-Identifier: BSD-3-Clause
+
+//! Trusted non-volatile counters, which hold anti-rollback state such as the minimum version of
+//! firmware which may be run.
+//!
+//! Counters may only ever increase. Callers should update them with [`NvCounters::advance`] or
+//! [`NvCounters::increment`], which enforce this, rather than writing them directly.
+//!
+//! Platforms may also have some non-volatile scratch storage, which is used to pass a
+//! [`BootRequest`] to the next boot stage across a system reset.
+
+use safe_mmio::{UniqueMmioPointer, field, field_shared, fields::ReadPureWrite};
+use zerocopy::{FromBytes, Immutable, IntoBytes, KnownLayout};
+
+/// Identifies one of the platform's trusted non-volatile counters.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum NvCounterId {
+    /// The counter for trusted firmware images.
+    TrustedFirmware,
+    /// The counter for non-trusted firmware images.
+    NonTrustedFirmware,
+}
+
+/// A request to the next boot stage, recorded in non-volatile scratch storage before a reset.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[repr(u32)]
+pub enum BootRequest {
+    /// Boot normally.
+    Normal = 0,
+    /// Enter firmware update mode, e.g. to recover from a failed update.
+    FirmwareUpdate = 0x4657_5550,
+}
+
+/// An error reading or updating a non-volatile counter.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum NvCounterError {
+    /// The platform doesn't implement the counter.
+    NotSupported,
+    /// The requested value is lower than the current value of the counter.
+    Rollback {
+        /// The current value of the counter.
+        current: u32,
+        /// The value which was requested.
+        requested: u32,
+    },
+    /// The counter is already at its maximum value.
+    Overflow,
+    /// The counter didn't take the new value, e.g. because it is read-only.
+    WriteFailed,
+}
+
+/// A backend for the platform's trusted non-volatile counters.
+pub trait NvCounters {
+    /// Returns the current value of the given counter.
+    fn read(&self, counter: NvCounterId) -> Result<u32, NvCounterError>;
+
+    /// Sets the given counter to the given value.
+    ///
+    /// This doesn't check that the counter doesn't decrease, so should only be called by
+    /// [`advance`](Self::advance).
+    fn write(&mut self, counter: NvCounterId, value: u32) -> Result<(), NvCounterError>;
+
+    /// Raises the given counter to the given value, if it isn't already there.
+    ///
+    /// Returns [`NvCounterError::Rollback`] if the counter is already higher.
+    fn advance(&mut self, counter: NvCounterId, value: u32) -> Result<(), NvCounterError> {
+        let current = self.read(counter)?;
+        if value < current {
+            Err(NvCounterError::Rollback {
+                current,
+                requested: value,
+            })
+        } else if value == current {
+            Ok(())
+        } else {
+            self.write(counter, value)
+        }
+    }
+
+    /// Increments the given counter by one, and returns its new value.
+    fn increment(&mut self, counter: NvCounterId) -> Result<u32, NvCounterError> {
+        let value = self
+            .read(counter)?
+            .checked_add(1)
+            .ok_or(NvCounterError::Overflow)?;
+        self.advance(counter, value)?;
+        Ok(value)
+    }
+
+    /// Records the given request for the next boot stage in non-volatile scratch storage, which
+    /// must be preserved across a system reset.
+    ///
+    /// The default implementation returns [`NvCounterError::NotSupported`].
+    fn write_boot_request(&mut self, _request: BootRequest) -> Result<(), NvCounterError> {
+        Err(NvCounterError::NotSupported)
+    }
+}
+
+/// Backend for platforms which don't have trusted non-volatile counters.
+pub struct NotSupportedNvCounters;
+
+impl NvCounters for NotSupportedNvCounters {
+    fn read(&self, _counter: NvCounterId) -> Result<u32, NvCounterError> {
+        Err(NvCounterError::NotSupported)
+    }
+
+    fn write(&mut self, _counter: NvCounterId, _value: u32) -> Result<(), NvCounterError> {
+        Err(NvCounterError::NotSupported)
+    }
+}
+
+/// Register map of a memory-mapped trusted non-volatile counters peripheral, as on the Arm FVP.
+#[derive(FromBytes, Immutable, IntoBytes, KnownLayout)]
+#[repr(C, align(4))]
+pub struct NvCounterRegisters {
+    /// 0x00: Trusted firmware counter.
+    trusted_firmware: ReadPureWrite<u32>,
+    /// 0x04: Non-trusted firmware counter.
+    non_trusted_firmware: ReadPureWrite<u32>,
+}
+
+/// Driver for a memory-mapped trusted non-volatile counters peripheral.
+pub struct MmioNvCounters<'a> {
+    regs: UniqueMmioPointer<'a, NvCounterRegisters>,
+}
+
+impl<'a> MmioNvCounters<'a> {
+    /// Creates a driver for the given counter registers.
+    pub fn new(regs: UniqueMmioPointer<'a, NvCounterRegisters>) -> Self {
+        Self { regs }
+    }
+}
+
+impl NvCounters for MmioNvCounters<'_> {
+    fn read(&self, counter: NvCounterId) -> Result<u32, NvCounterError> {
+        Ok(match counter {
+            NvCounterId::TrustedFirmware => field_shared!(self.regs, trusted_firmware).read(),
+            NvCounterId::NonTrustedFirmware => {
+                field_shared!(self.regs, non_trusted_firmware).read()
+            }
+        })
+    }
+
+    fn write(&mut self, counter: NvCounterId, value: u32) -> Result<(), NvCounterError> {
+        match counter {
+            NvCounterId::TrustedFirmware => field!(self.regs, trusted_firmware).write(value),
+            NvCounterId::NonTrustedFirmware => field!(self.regs, non_trusted_firmware).write(value),
+        }
+        // Some implementations of the peripheral are read-only, so check that the write took.
+        if self.read(counter)? == value {
+            Ok(())
+        } else {
+            Err(NvCounterError::WriteFailed)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use zerocopy::FromZeros;
+
+    #[test]
+    fn mmio_advance() {
+        let mut regs = NvCounterRegisters::new_zeroed();
+        regs.non_trusted_firmware.0 = 5;
+        {
+            let mut counters = MmioNvCounters::new(UniqueMmioPointer::from(&mut regs));
+            assert_eq!(counters.read(NvCounterId::TrustedFirmware), Ok(0));
+            assert_eq!(counters.advance(NvCounterId::TrustedFirmware, 3), Ok(()));
+            assert_eq!(counters.increment(NvCounterId::TrustedFirmware), Ok(4));
+
+            assert_eq!(counters.advance(NvCounterId::NonTrustedFirmware, 5), Ok(()));
+            assert_eq!(
+                counters.advance(NvCounterId::NonTrustedFirmware, 4),
+                Err(NvCounterError::Rollback {
+                    current: 5,
+                    requested: 4
+                })
+            );
+        }
+        assert_eq!(regs.trusted_firmware.0, 4);
+        assert_eq!(regs.non_trusted_firmware.0, 5);
+    }
+
+    #[test]
+    fn mmio_increment_overflow() {
+        let mut regs = NvCounterRegisters::new_zeroed();
+        regs.trusted_firmware.0 = u32::MAX;
+        let mut counters = MmioNvCounters::new(UniqueMmioPointer::from(&mut regs));
+        assert_eq!(
+            counters.increment(NvCounterId::TrustedFirmware),
+            Err(NvCounterError::Overflow)
+        );
+    }
+
+    #[test]
+    fn not_supported() {
+        let mut counters = NotSupportedNvCounters;
+        assert_eq!(
+            counters.read(NvCounterId::TrustedFirmware),
+            Err(NvCounterError::NotSupported)
+        );
+        assert_eq!(
+            counters.increment(NvCounterId::NonTrustedFirmware),
+            Err(NvCounterError::NotSupported)
+        );
+        assert_eq!(
+            counters.write_boot_request(BootRequest::FirmwareUpdate),
+            Err(NvCounterError::NotSupported)
+        );
+    }
+}
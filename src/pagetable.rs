@@ -9,10 +9,12 @@ pub mod early_pagetable;
 use crate::{
     aarch64::{dsb_sy, isb, tlbi_alle3},
     layout::{
-        bl_code_base, bl_code_end, bl_ro_data_base, bl_ro_data_end, bl31_end, bl31_start, bss2_end,
-        bss2_start,
+        bl_code_base, bl_code_end, bl_data_start, bl_ro_data_base, bl_ro_data_end, bl31_end,
+        bl31_start, bss2_end, bss2_start, el3_retained_end, el3_retained_start, ro_after_init_end,
+        ro_after_init_start,
     },
     platform::Platform,
+    spin_mutex::{SpinMutex, SpinMutexGuard},
 };
 #[cfg(feature = "rme")]
 use crate::{
@@ -20,7 +22,7 @@ use crate::{
     services::rmmd::RMM_SHARED_BUFFER_SIZE,
 };
 use aarch64_paging::{
-    Mapping,
+    MapError, Mapping,
     descriptor::{El23Attributes, PhysicalAddress, VirtualAddress},
     mair::{Mair, MairAttribute, NormalMemory},
     paging::{Constraints, El3, MemoryRegion, PageTable, Translation},
@@ -31,10 +33,7 @@ use core::{
     ptr::NonNull,
 };
 use log::{debug, trace};
-use spin::{
-    Once,
-    mutex::{SpinMutex, SpinMutexGuard},
-};
+use spin::Once;
 
 /// An error with an address for RME.
 #[cfg(feature = "rme")]
@@ -45,6 +44,17 @@ pub enum Error {
     InvalidGPI,
 }
 
+/// Whether BL31 enables the MMU.
+///
+/// This is false if the `mmu_off` feature is enabled, for bring-up of early silicon. In that case no
+/// page tables are created, and all data accesses from EL3 are treated as Device-nGnRnE.
+pub const MMU_ENABLED: bool = !cfg!(feature = "mmu_off");
+
+// The realm PAS and GPT handling rely on memory attributes and cache maintenance which are only
+// meaningful with the MMU enabled.
+#[cfg(all(feature = "mmu_off", feature = "rme"))]
+compile_error!("The mmu_off feature is not supported together with rme");
+
 const ROOT_LEVEL: usize = 1;
 
 // Indices of entries in the Memory Attribute Indirection Register.
@@ -102,6 +112,9 @@ const BASE_EL3: El23Attributes = if cfg!(feature = "rme") {
     BASE
 };
 
+/// The attribute bits which control whether EL3 may write to or execute from a mapping.
+const PERMISSIONS: El23Attributes = El23Attributes::READ_ONLY.union(El23Attributes::XN);
+
 /// Attributes used for device mappings.
 ///
 /// Device memory is always mapped as execute-never to avoid the possibility of a speculative
@@ -294,16 +307,23 @@ impl<const PAGE_HEAP_PAGE_COUNT: usize> OncePageTable<PAGE_HEAP_PAGE_COUNT> {
     /// At this point the early page tables are active with the required MAIR and TCR values, so the
     /// function only switches the TTBR value and updates SCTLR to add WXN.
     ///
-    /// This should be called once in the startup sequence of the primary core.
+    /// This should be called once in the startup sequence of the primary core. It does nothing if
+    /// [`MMU_ENABLED`] is false.
     pub fn init_runtime_mapping<PlatformImpl: Platform<IdMap = IdMap<PAGE_HEAP_PAGE_COUNT>>>(
         &self,
         page_heap: &'static PageHeap<PAGE_HEAP_PAGE_COUNT>,
     ) {
+        if !MMU_ENABLED {
+            debug!("MMU disabled, not creating page tables");
+            return;
+        }
+
         self.page_table.call_once(|| {
             let page_heap = page_heap.take();
             let mut idmap = init_page_table::<PAGE_HEAP_PAGE_COUNT, PlatformImpl>(page_heap);
 
             trace!("Page table: {idmap:?}");
+            idmap.check_image_permissions();
 
             // Safety: `PAGE_TABLE_ADDR` is only written once here and then its value is flushed from
             // the cache to make it visible to other core's early boot sequence.
@@ -355,6 +375,85 @@ impl<const PAGE_HEAP_PAGE_COUNT: usize> OncePageTable<PAGE_HEAP_PAGE_COUNT> {
             SpinMutex::new(idmap)
         });
     }
+
+    /// Remaps the `.ro_after_init` section read-only, so that nothing written to it during cold
+    /// boot can be modified afterwards.
+    ///
+    /// This should be called once on the primary core at the end of cold boot, before any lower EL
+    /// runs. It does nothing if [`MMU_ENABLED`] is false.
+    pub fn make_ro_after_init_read_only(&self) {
+        if !MMU_ENABLED {
+            return;
+        }
+
+        let mut idmap = self
+            .page_table
+            .get()
+            .expect("Runtime page table not initialised")
+            .lock();
+        // SAFETY: Nothing in the section is written after cold boot, and only its permissions are
+        // changed so no break-before-make is needed. `aarch64-paging` invalidates the TLB entries
+        // as the page table is active.
+        unsafe {
+            idmap.map_region(
+                &MemoryRegion::new(ro_after_init_start(), ro_after_init_end()),
+                MT_RO_DATA_EL3,
+            );
+        }
+    }
+
+    /// Maps the given region with the given attributes in the live runtime page table, for memory
+    /// such as a buffer shared by another world which EL3 only needs to access for a while.
+    ///
+    /// If any part of the region is already mapped it is unmapped and its TLB entries invalidated
+    /// before the new mapping is written, so that the break-before-make rules are followed. This
+    /// fails if the region is covered by a larger block mapping, as that can't be split while live.
+    /// It does nothing if [`MMU_ENABLED`] is false.
+    ///
+    /// # Safety
+    ///
+    /// Memory in the region must not be accessed by RF-A while it is being remapped, and must not
+    /// be memory which RF-A relies on being mapped with other attributes.
+    pub unsafe fn map_region_at_runtime(
+        &self,
+        region: &MemoryRegion,
+        attributes: El23Attributes,
+    ) -> Result<(), MapError> {
+        if !MMU_ENABLED {
+            return Ok(());
+        }
+
+        let mut idmap = self
+            .page_table
+            .get()
+            .expect("Runtime page table not initialised")
+            .lock();
+        // SAFETY: The caller promises that the region isn't in use while it is remapped.
+        unsafe { idmap.remap_region(region, attributes) }
+    }
+
+    /// Unmaps a region previously mapped by [`map_region_at_runtime`](Self::map_region_at_runtime)
+    /// from the live runtime page table, and invalidates its TLB entries on all cores.
+    ///
+    /// Subtables are kept rather than freed, as a table walk might still be using them. It does
+    /// nothing if [`MMU_ENABLED`] is false.
+    ///
+    /// # Safety
+    ///
+    /// The region must not include any memory which is still used by RF-A after this point.
+    pub unsafe fn unmap_region(&self, region: &MemoryRegion) -> Result<(), MapError> {
+        if !MMU_ENABLED {
+            return Ok(());
+        }
+
+        let mut idmap = self
+            .page_table
+            .get()
+            .expect("Runtime page table not initialised")
+            .lock();
+        // SAFETY: The caller promises that the region is no longer used.
+        unsafe { idmap.unmap_region(region) }
+    }
 }
 
 /// A set of pages which may be used to construct a pagetable.
@@ -438,14 +537,22 @@ fn init_page_table<
 
     // If the BL32 entry point is in the middle of our memory range then something is misconfigured.
     let secure_entry_pc = PlatformImpl::secure_entry_point().pc;
-    assert!(secure_entry_pc < bl31_start() || secure_entry_pc >= bl31_end());
-    assert!(secure_entry_pc < bss2_start() || secure_entry_pc >= bss2_end());
+    assert!(
+        [
+            (bl31_start(), bl31_end()),
+            (bss2_start(), bss2_end()),
+            (el3_retained_start(), el3_retained_end()),
+        ]
+        .into_iter()
+        .all(|(start, end)| secure_entry_pc < start || secure_entry_pc >= end),
+        "BL32 entry point inside BL31"
+    );
 
     // SAFETY: Nothing is being unmapped, and the regions being mapped have the correct attributes.
     unsafe {
         // Corresponds to `bl_regions` in C TF-A, `plat/arm/common/arm_bl31_setup.c`.
-        // BL31_TOTAL
-        idmap.map_region(&MemoryRegion::new(bl31_start(), bl31_end()), MT_MEMORY_EL3);
+        // BL31_TOTAL, which is execute-never apart from the code mapped below.
+        idmap.map_region(&MemoryRegion::new(bl31_start(), bl31_end()), MT_RW_DATA_EL3);
         // BL31_RO
         idmap.map_region(
             &MemoryRegion::new(bl_code_base(), bl_code_end()),
@@ -455,11 +562,22 @@ fn init_page_table<
             &MemoryRegion::new(bl_ro_data_base(), bl_ro_data_end()),
             MT_RO_DATA_EL3,
         );
+        // Data which is made read-only at the end of cold boot. This is mapped separately so that
+        // changing its permissions later won't need to split a live block mapping.
+        idmap.map_region(
+            &MemoryRegion::new(ro_after_init_start(), ro_after_init_end()),
+            MT_RW_DATA_EL3,
+        );
         let bss2_start = bss2_start();
         let bss2_end = bss2_end();
         if bss2_start != bss2_end {
             idmap.map_region(&MemoryRegion::new(bss2_start, bss2_end), MT_RW_DATA_EL3);
         }
+        // Saved contexts and stacks, which may be in a separate retained memory region.
+        idmap.map_region(
+            &MemoryRegion::new(el3_retained_start(), el3_retained_end()),
+            MT_RW_DATA_EL3,
+        );
 
         #[cfg(feature = "rme")]
         idmap.map_region(
@@ -591,6 +709,95 @@ impl<const PAGE_HEAP_PAGE_COUNT: usize> IdMap<PAGE_HEAP_PAGE_COUNT> {
             .expect("Error mapping memory range");
     }
 
+    /// Checks that the BL31 image is mapped so that no memory is both writable and executable: code
+    /// read-only and executable, read-only data execute-never, and all other data, including the
+    /// saved contexts and stacks, writable and execute-never.
+    ///
+    /// # Panics
+    ///
+    /// Panics if any part of the image is unmapped or mapped with other permissions.
+    fn check_image_permissions(&self) {
+        let rw_data = [
+            (ro_after_init_start(), ro_after_init_end()),
+            (bl_data_start(), bl31_end()),
+            (bss2_start(), bss2_end()),
+            (el3_retained_start(), el3_retained_end()),
+        ];
+        assert!(
+            self.has_permissions(
+                &MemoryRegion::new(bl_code_base(), bl_code_end()),
+                El23Attributes::READ_ONLY
+            ) && self.has_permissions(
+                &MemoryRegion::new(bl_ro_data_base(), bl_ro_data_end()),
+                PERMISSIONS
+            ) && rw_data
+                .into_iter()
+                .filter(|(start, end)| start != end)
+                .all(|(start, end)| {
+                    self.has_permissions(&MemoryRegion::new(start, end), El23Attributes::XN)
+                }),
+            "BL31 not mapped W^X"
+        );
+    }
+
+    /// Returns whether all of the given region is mapped with exactly the given [`PERMISSIONS`]
+    /// bits set.
+    fn has_permissions(&self, region: &MemoryRegion, permissions: El23Attributes) -> bool {
+        self.mapping
+            .walk_range(region, &mut |_, descriptor, _| {
+                let flags = descriptor.flags();
+                if flags.contains(El23Attributes::VALID)
+                    && flags.intersection(PERMISSIONS) == permissions
+                {
+                    Ok(())
+                } else {
+                    Err(())
+                }
+            })
+            .is_ok()
+    }
+
+    /// Maps the given region with the given attributes while the page table may be active, first
+    /// unmapping it so that any existing mapping with different attributes is replaced following
+    /// break-before-make.
+    ///
+    /// # Safety
+    ///
+    /// Memory in the region must not be accessed while it is remapped, and memory which is still
+    /// used by RF-A must not be mapped with incorrect attributes.
+    unsafe fn remap_region(
+        &mut self,
+        region: &MemoryRegion,
+        attributes: El23Attributes,
+    ) -> Result<(), MapError> {
+        debug!("Remapping {region} as {attributes:?}.");
+        assert!(attributes.contains(El23Attributes::VALID));
+        // SAFETY: The caller promises that the region isn't accessed until it is mapped again.
+        unsafe {
+            self.unmap_region(region)?;
+        }
+        let pa = IdTranslation::<PAGE_HEAP_PAGE_COUNT>::virtual_to_physical(region.start());
+        self.mapping
+            .map_range(region, pa, attributes, Constraints::empty())
+    }
+
+    /// Unmaps the given region from the page table, without freeing any subtables.
+    ///
+    /// `aarch64-paging` invalidates the TLB entries for the region and waits for the invalidation
+    /// to complete if the page table is active.
+    ///
+    /// # Safety
+    ///
+    /// The region must not include any memory which is still used by RF-A after this point.
+    unsafe fn unmap_region(&mut self, region: &MemoryRegion) -> Result<(), MapError> {
+        self.mapping.map_range(
+            region,
+            PhysicalAddress(0),
+            El23Attributes::empty(),
+            Constraints::empty(),
+        )
+    }
+
     /// Unmaps the given memory regions from the page table, and removes any subtables which are no
     /// longer needed as a result.
     ///
@@ -658,7 +865,10 @@ mod asm {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::platform::test::{PAGE_HEAP, TestPlatform};
+    use crate::platform::{
+        Board,
+        test::{PAGE_HEAP, TestPlatform},
+    };
 
     #[test]
     fn create_page_table() {
@@ -669,7 +879,51 @@ mod tests {
         let mut idmap =
             init_page_table::<{ TestPlatform::PAGE_HEAP_PAGE_COUNT }, TestPlatform>(page_heap);
         assert_ne!(idmap.root_address().0, 0);
+        idmap.check_image_permissions();
         idmap.mark_active();
+
+        // Making the `.ro_after_init` section read-only must be possible on the live page table.
+        // SAFETY: The page table isn't really in use.
+        unsafe {
+            idmap.map_region(
+                &MemoryRegion::new(ro_after_init_start(), ro_after_init_end()),
+                MT_RO_DATA_EL3,
+            );
+        }
+        // A buffer may be mapped and unmapped on demand while the page table is live, and remapped
+        // with different attributes. It shares its subtables with the device region so that no more
+        // pages are needed from the heap.
+        let buffer = MemoryRegion::new(0x0200_2000, 0x0200_4000);
+        // SAFETY: The page table isn't really in use, and the region isn't otherwise mapped.
+        unsafe {
+            idmap.remap_region(&buffer, MT_DEVICE).unwrap();
+            assert!(idmap.has_permissions(&buffer, El23Attributes::XN));
+            idmap.remap_region(&buffer, MT_RO_DATA_EL3).unwrap();
+            assert!(idmap.has_permissions(&buffer, PERMISSIONS));
+            idmap.unmap_region(&buffer).unwrap();
+            assert!(!idmap.has_permissions(&buffer, El23Attributes::XN));
+            idmap.remap_region(&buffer, MT_DEVICE).unwrap();
+        }
+        assert!(!idmap.has_permissions(&buffer, El23Attributes::empty()));
+
+        // Part of a live block mapping can't be remapped, as splitting the block would break the
+        // break-before-make rules, and the block must be left as it was.
+        let block = MemoryRegion::new(0x0240_0000, 0x0260_0000);
+        let page = MemoryRegion::new(0x0240_1000, 0x0240_2000);
+        // SAFETY: The page table isn't really in use, and the regions aren't otherwise mapped.
+        unsafe {
+            idmap.remap_region(&block, MT_DEVICE).unwrap();
+            assert!(matches!(
+                idmap.remap_region(&page, MT_RO_DATA_EL3),
+                Err(MapError::BreakBeforeMakeViolation(_))
+            ));
+            assert!(matches!(
+                idmap.unmap_region(&page),
+                Err(MapError::BreakBeforeMakeViolation(_))
+            ));
+        }
+        assert!(idmap.has_permissions(&block, El23Attributes::XN));
+
         // `aarch64-paging` will detect the dropped idmap and panic
         core::mem::forget(idmap);
     }
@@ -6,20 +6,37 @@
 
 #[cfg(test)]
 pub mod test;
+pub mod topology;
 
+#[cfg(feature = "spmc_el3")]
+use crate::services::ffa::spmc_el3::El3SpmcManifest;
 #[cfg(feature = "rme")]
 use crate::services::rmmd::{
     RMM_SHARED_BUFFER_SIZE,
     svc::{EccCurve, RmmCommandReturnCode},
 };
 use crate::{
-    context::EntryPointInfo,
-    cpu_extensions::CpuExtension,
+    context::{CPU_DATA_CONTEXT_NUM, EntryPointInfo, InitialPstate, PerWorld, TimerAccess, World},
+    cpu_extensions::{CpuExtension, mpmm::MpmmGear},
+    cpu_notifier::CpuNotifiers,
+    debug::EarlyConsole,
     gicv3,
+    heap::HeapQuotas,
     logger::LogSink,
+    memory_init::MemoryInitProgress,
+    nv_counter::NvCounters,
     pagetable::MAIR_IWBRWA_OWBRWA_NTR,
-    services::{Service, arch::WorkaroundSupport},
+    runtime_config::RuntimeConfig,
+    scrub::Secrets,
+    services::{
+        Service, arch::WorkaroundSupport, ffa::spmd::SpmcManifest, psci::VendorResetType,
+        vendor::VendorHandlers,
+    },
     smccc::FunctionId,
+    sysreg_trap::{SysregAccess, SysregTrapAction},
+    system_suspend_notifier::SystemSuspendNotifiers,
+    timer::TimedOut,
+    watchdog::{TrustedWatchdog, WatchdogPolicy},
 };
 use aarch64_paging::mair::MairAttribute;
 use arm_gic::IntId;
@@ -43,6 +60,15 @@ pub fn exception_free<T>(f: impl FnOnce(ExceptionFree) -> T) -> T {
     f(token)
 }
 
+/// The platform-specific (SiP) service, with anything it provides to other services.
+pub trait PlatformService: Service {
+    /// The vendor specific `SYSTEM_RESET2` reset types which the platform supports.
+    ///
+    /// PSCI rejects any other vendor specific reset type, or a cookie which the reset type doesn't
+    /// accept, before calling `PsciPlatformInterface::system_reset2`.
+    const VENDOR_RESET_TYPES: &'static [VendorResetType] = &[];
+}
+
 /// For platforms that do not want to implement any custom SMC handlers.
 pub struct DummyService;
 
@@ -53,7 +79,24 @@ impl Service for DummyService {
     }
 }
 
-/// The hooks implemented by all platforms.
+impl PlatformService for DummyService {}
+
+/// What to do when an `HVC` instruction executed at a lower EL is taken to EL3.
+///
+/// The architecture never routes such an exception to EL3: `HVC` is taken to EL2, or is UNDEFINED
+/// and taken to EL1 if EL2 is not implemented or `SCR_EL3.HCE` is clear. It can therefore only
+/// reach EL3 erroneously, e.g. because of a CPU or model defect.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum UnknownHvcPolicy {
+    /// Inject an undefined instruction exception into the caller, as the architecture specifies for
+    /// `HVC` when `SCR_EL3.HCE` is clear.
+    Undefined,
+    /// Return `SMC_UNKNOWN` in x0 to the caller, as if it had made an unknown SMC call.
+    NotSupported,
+}
+
+/// The hooks implemented by a system on chip, which may be shared by several boards built around
+/// it.
 ///
 /// # Safety
 ///
@@ -62,19 +105,14 @@ impl Service for DummyService {
 /// `CORE_COUNT`, and must return a different index for different MPIDR values. The index must be 0
 /// for the primary core that boots first on cold boot.
 ///
-/// The implementations of `cold_boot_handler`, `crash_console_init`, `crash_console_putc`,
-/// `crash_console_flush`, `dump_registers` and `panic_handler` must be naked functions which
-/// doesn't use the stack, and only clobber the registers they are documented to clobber.
+/// The implementation of `dump_registers` must be a naked function which doesn't use the stack, and
+/// only clobbers the registers it is documented to clobber.
 ///
 /// `NORMAL_MEMORY_MAIR_ATTRIBUTE` must be a normal memory type with cache enabled, so that atomic
 /// operations work correctly.
 ///
-/// The implementations of all functions receiving the buffer shared between EL3 and R-EL2 (RMM) as
-/// parameter must never directly access that buffer other than through the reference provided and
-/// must not yield into R-EL2.
-///
 /// (These requirements don't apply to the test platform, as it is only used in unit tests.)
-pub unsafe trait Platform: Sized + Send + Sync {
+pub unsafe trait Soc: Send + Sync {
     /// The number of CPU cores.
     const CORE_COUNT: usize;
 
@@ -84,18 +122,151 @@ pub unsafe trait Platform: Sized + Send + Sync {
     /// The GIC configuration.
     const GIC_CONFIG: gicv3::GicConfig;
 
+    /// The MAIR attribute value to use for normal memory.
+    ///
+    /// The default value here is correct in most cases, but may need to be overridden if the
+    /// platform doesn't have a DSU. In any case, it must be a normal memory type with cache
+    /// enabled so that atomics operations work correctly.
+    const NORMAL_MEMORY_MAIR_ATTRIBUTE: MairAttribute = MAIR_IWBRWA_OWBRWA_NTR;
+
+    /// Returns whether the given MPIDR is valid for this platform.
+    fn mpidr_is_valid(mpidr: MpidrEl1) -> bool;
+
+    /// Handles a lower EL access to an IMPLEMENTATION DEFINED system register which was trapped to
+    /// EL3, e.g. because the CPU traps some of its IMPDEF registers to EL3 by default.
+    ///
+    /// The default implementation injects an undefined instruction exception, as if the register
+    /// didn't exist.
+    fn handle_impdef_sysreg_trap(_world: World, _access: &SysregAccess) -> SysregTrapAction {
+        SysregTrapAction::Undefined
+    }
+
+    /// Returns whether this platform supports the arch WORKAROUND_1 SMC.
+    fn arch_workaround_1_supported() -> WorkaroundSupport;
+
+    /// If safe and necessary, performs the workaround specified for the WORKAROUND_1 SMC.
+    fn arch_workaround_1();
+
+    /// Returns whether this platform supports the arch WORKAROUND_2 SMC.
+    fn arch_workaround_2_supported() -> WorkaroundSupport;
+
+    /// If safe and necessary, performs the workaround specified for the WORKAROUND_2 SMC.
+    fn arch_workaround_2();
+
+    /// Returns whether this platform supports the arch WORKAROUND_3 SMC.
+    fn arch_workaround_3_supported() -> WorkaroundSupport;
+
+    /// If safe and necessary, performs the workaround specified for the WORKAROUND_3 SMC.
+    fn arch_workaround_3();
+
+    /// Returns whether this platform supports the arch WORKAROUND_4 SMC.
+    fn arch_workaround_4_supported() -> WorkaroundSupport;
+
+    /// Returns the SoC version reported by the arch SOC_ID SMC, or `None` if the platform doesn't
+    /// support SoC identification.
+    ///
+    /// This is made up of the JEP-106 code of the SoC implementer and an implementation defined SoC
+    /// ID, and can be constructed with [`soc_id_version`](crate::services::arch::soc_id_version).
+    fn soc_id_version() -> Option<u32> {
+        None
+    }
+
+    /// Returns the SoC revision reported by the arch SOC_ID SMC. This is only called if
+    /// `soc_id_version` returns `Some`.
+    ///
+    /// Together with the version this must uniquely identify the SoC. Only the low 31 bits are used.
+    fn soc_id_revision() -> u32 {
+        0
+    }
+
+    /// Given a valid MPIDR value, returns the corresponding linear core index.
+    ///
+    /// The implementation must never return the same index for two different valid MPIDR values,
+    /// and must never return a value greater than or equal to the corresponding
+    /// `Platform::CORE_COUNT`.
+    ///
+    /// For an invalid MPIDR value no guarantees are made about the return value.
+    ///
+    /// The [`topology`] module has helpers to implement this for both dense and sparse topologies.
+    extern "C" fn core_position(mpidr: u64) -> usize;
+
+    /// Dumps platform-specific registers, e.g. for the GIC, for a crash dump.
+    ///
+    /// This may be called without a Rust runtime, e.g. with no stack.
+    ///
+    /// May clobber x0-x11, x16, x17, sp.
+    ///
+    /// # Safety
+    ///
+    /// Should only be called from assembly as it doesn't follow the standard calling convention.
+    #[cfg_attr(test, allow(unused))]
+    unsafe extern "C" fn dump_registers();
+}
+
+/// The hooks implemented by a board, i.e. a SoC together with the devices, memory and firmware
+/// images around it.
+///
+/// # Safety
+///
+/// The implementations of `cold_boot_handler`, `crash_console_init`, `crash_console_putc`,
+/// `crash_console_flush` and `panic_handler` must be naked functions which don't use the stack, and
+/// only clobber the registers they are documented to clobber.
+///
+/// The implementations of all functions receiving the buffer shared between EL3 and R-EL2 (RMM) as
+/// parameter must never directly access that buffer other than through the reference provided and
+/// must not yield into R-EL2.
+///
+/// (These requirements don't apply to the test platform, as it is only used in unit tests.)
+pub unsafe trait Board: Sized + Send + Sync {
+    /// The SoC which the board is built around.
+    type Soc: Soc;
+
     /// The CPU extensions enabled by this platform.
     const CPU_EXTENSIONS: &'static [&'static dyn CpuExtension];
 
     /// The number of pages to reserve for the page heap.
     const PAGE_HEAP_PAGE_COUNT: usize = 5;
 
-    /// The MAIR attribute value to use for normal memory.
+    /// The quota of each user of the EL3 heap. The heap is sized to fit all of them.
+    const EL3_HEAP_QUOTAS: HeapQuotas = HeapQuotas::NONE;
+
+    /// Where to report `early_assert!` failures.
+    const EARLY_CONSOLE: EarlyConsole = EarlyConsole::CrashConsole;
+
+    /// How to handle an `HVC` from a lower EL which is taken to EL3.
+    const UNKNOWN_HVC_POLICY: UnknownHvcPolicy = UnknownHvcPolicy::Undefined;
+
+    /// How many levels deep EL3 may enter a lower EL while already handling an exception from a
+    /// lower EL, such as when the SPMD forwards a PSCI call to the SPMC while handling it.
     ///
-    /// The default value here is correct in most cases, but may need to be overridden if the
-    /// platform doesn't have a DSU. In any case, it must be a normal memory type with cache
-    /// enabled so that atomics operations work correctly.
-    const NORMAL_MEMORY_MAIR_ATTRIBUTE: MairAttribute = MAIR_IWBRWA_OWBRWA_NTR;
+    /// Each level uses more of the EL3 stack, so `enter_world` panics if this is exceeded rather
+    /// than risking a stack overflow.
+    const MAX_EXCEPTION_NESTING: u64 = 1;
+
+    /// The numbers of the private SDEI events, other than event 0, which EL3 handlers may dispatch
+    /// to the normal world.
+    const SDEI_EVENTS: &'static [u32] = &[];
+
+    /// The ID of the secure partition to which RAS errors recorded in `Services::ras_error_history`
+    /// are delivered with FF-A direct message requests, if any.
+    const RAS_DIAGNOSTICS_PARTITION: Option<u16> = None;
+
+    /// The time which `init_memory` may take before cold boot fails, in milliseconds, or `None`
+    /// for no limit.
+    const MEMORY_INIT_BUDGET_MS: Option<u64> = None;
+
+    /// Whether EL3 or the secure world is responsible for refreshing the trusted watchdog returned
+    /// by `trusted_watchdog`.
+    const WATCHDOG_POLICY: WatchdogPolicy = WatchdogPolicy::SecureWorld;
+
+    /// The optional PSTATE bits to set on the initial entry to each world.
+    const INITIAL_PSTATE: PerWorld<InitialPstate> =
+        PerWorld([InitialPstate::DEFAULT; CPU_DATA_CONTEXT_NUM]);
+
+    /// Which of the generic timer's physical counter and timers the lower ELs of each world may
+    /// access directly.
+    const TIMER_ACCESS: PerWorld<TimerAccess> =
+        PerWorld([TimerAccess::DEFAULT; CPU_DATA_CONTEXT_NUM]);
 
     /// Base address for the EL3 - RMM shared area.
     #[cfg(feature = "rme")]
@@ -110,13 +281,16 @@ pub unsafe trait Platform: Sized + Send + Sync {
     type IdMap: 'static;
 
     /// Platform dependent `PsciPlatformInterface` implementation type.
-    type PsciPlatformImpl;
+    type PsciPlatformImpl: Sync;
 
     /// Platform dependent `TrngPlatformInterface` implementation type.
     type TrngPlatformImpl;
 
     /// Service that handles platform-specific SMC calls.
-    type PlatformServiceImpl: Service;
+    type PlatformServiceImpl: PlatformService + Sync;
+
+    /// Platform dependent trusted non-volatile counters backend.
+    type NvCountersImpl: NvCounters + Send;
 
     /// Performs early platform-specific initialisation. This will be called while the early
     /// pagetable mapping defined by `define_early_mapping!` is active, so anything only mapped by
@@ -136,6 +310,83 @@ pub unsafe trait Platform: Sized + Send + Sync {
     /// arg0-arg3 are the first four function arguments passed to bl31_main.
     fn init(_arg0: u64, _arg1: u64, _arg2: u64, _arg3: u64) {}
 
+    /// Initialises memory before any lower EL is first entered on cold boot, e.g. by scrubbing DRAM
+    /// to initialise its ECC or by enabling memory repair.
+    ///
+    /// This is called once on the primary core, after the CPU contexts have been initialised. The
+    /// platform should report how much of the work it has done through `progress`, which logs it
+    /// and checks it against `MEMORY_INIT_BUDGET_MS`, and return any error from it.
+    ///
+    /// The default implementation does nothing.
+    fn init_memory(_progress: &mut MemoryInitProgress) -> Result<(), TimedOut> {
+        Ok(())
+    }
+
+    /// Returns the platform's trusted watchdog, if it has one which EL3 may refresh according to
+    /// `WATCHDOG_POLICY`.
+    ///
+    /// The default implementation returns `None`.
+    fn trusted_watchdog() -> Option<&'static dyn TrustedWatchdog> {
+        None
+    }
+
+    /// Returns the MPMM gear to select on the core with the given index, or `None` if the core
+    /// doesn't support MPMM.
+    ///
+    /// This is only used if `CPU_EXTENSIONS` includes [`Mpmm`](crate::cpu_extensions::mpmm::Mpmm),
+    /// on every core as it boots or resumes. It must return `None` for any core which doesn't
+    /// implement the MPMM control registers, as accessing them would trap.
+    ///
+    /// The default implementation returns `None`.
+    fn mpmm_gear(_core_index: usize) -> Option<MpmmGear> {
+        None
+    }
+
+    /// Registers any platform drivers which need per-core initialisation on every `CPU_ON` and
+    /// teardown on every `CPU_OFF`.
+    ///
+    /// This is called once on the primary core during cold boot, after the GIC has been configured
+    /// and registered itself.
+    ///
+    /// The default implementation registers nothing.
+    fn register_cpu_notifiers(_notifiers: &CpuNotifiers) {}
+
+    /// Registers any platform drivers which need to save state before the system power domain is
+    /// powered down by `SYSTEM_SUSPEND` or `CPU_SUSPEND`, or re-initialise their peripherals when it
+    /// resumes.
+    ///
+    /// This is called once on the primary core during cold boot, after `register_cpu_notifiers`.
+    ///
+    /// The default implementation registers nothing.
+    fn register_system_suspend_notifiers(_notifiers: &SystemSuspendNotifiers) {}
+
+    /// Registers any secrets held by platform drivers, such as a cached attestation token, which
+    /// must be zeroed before the system is turned off or reset.
+    ///
+    /// This is called once on the primary core during cold boot, after the secrets held by the
+    /// services and the EL3 PAuth keys have been registered.
+    ///
+    /// The default implementation registers nothing.
+    fn register_secrets(_secrets: &Secrets) {}
+
+    /// Registers any platform-specific handlers for vendor-specific EL3 monitor SMCs.
+    ///
+    /// This is called once on the primary core during cold boot, after RF-A's own handlers have
+    /// been registered. See `RfaVendorHandler` for an example handler.
+    ///
+    /// The default implementation registers nothing.
+    fn register_vendor_handlers(_handlers: &VendorHandlers) {}
+
+    /// Returns the runtime configuration, e.g. parsed from FW_CONFIG or a transfer list.
+    ///
+    /// This is called once during cold boot, with the main pagetable enabled but before `init`.
+    /// arg0-arg3 are the first four function arguments passed to bl31_main.
+    ///
+    /// The default implementation returns [`RuntimeConfig::DEFAULT`].
+    fn runtime_config(_arg0: u64, _arg1: u64, _arg2: u64, _arg3: u64) -> RuntimeConfig {
+        RuntimeConfig::DEFAULT
+    }
+
     /// Maps device memory and any other regions specific to the platform, before the MMU is
     /// enabled.
     fn map_extra_regions(idmap: &mut Self::IdMap);
@@ -166,6 +417,21 @@ pub unsafe trait Platform: Sized + Send + Sync {
     /// Returns the entry point for the secure world, i.e. BL32.
     fn secure_entry_point() -> EntryPointInfo;
 
+    /// Returns the SPMC attributes parsed from the SPMC manifest.
+    ///
+    /// The default implementation returns the attributes of the reference SPMC.
+    fn spmc_manifest() -> SpmcManifest {
+        SpmcManifest::DEFAULT
+    }
+
+    /// Returns the attributes of the SPMC at EL3 and its logical partitions.
+    ///
+    /// The default implementation has no partitions.
+    #[cfg(feature = "spmc_el3")]
+    fn el3_spmc_manifest() -> El3SpmcManifest {
+        El3SpmcManifest::DEFAULT
+    }
+
     /// Returns the entry point for the non-secure world, i.e. BL33.
     fn non_secure_entry_point() -> EntryPointInfo;
 
@@ -173,42 +439,15 @@ pub unsafe trait Platform: Sized + Send + Sync {
     #[cfg(feature = "rme")]
     fn realm_entry_point() -> EntryPointInfo;
 
-    /// Returns whether the given MPIDR is valid for this platform.
-    fn mpidr_is_valid(mpidr: MpidrEl1) -> bool;
-
     /// Returns an option with a PSCI platform implementation handle. The function should only be
     /// called once, when it returns `Some`. All subsequent calls must return `None`.
     fn psci_platform() -> Option<Self::PsciPlatformImpl>;
 
-    /// Returns whether this platform supports the arch WORKAROUND_1 SMC.
-    fn arch_workaround_1_supported() -> WorkaroundSupport;
-
-    /// If safe and necessary, performs the workaround specified for the WORKAROUND_1 SMC.
-    fn arch_workaround_1();
-
-    /// Returns whether this platform supports the arch WORKAROUND_2 SMC.
-    fn arch_workaround_2_supported() -> WorkaroundSupport;
-
-    /// If safe and necessary, performs the workaround specified for the WORKAROUND_2 SMC.
-    fn arch_workaround_2();
-
-    /// Returns whether this platform supports the arch WORKAROUND_3 SMC.
-    fn arch_workaround_3_supported() -> WorkaroundSupport;
-
-    /// If safe and necessary, performs the workaround specified for the WORKAROUND_3 SMC.
-    fn arch_workaround_3();
-
-    /// Returns whether this platform supports the arch WORKAROUND_4 SMC.
-    fn arch_workaround_4_supported() -> WorkaroundSupport;
-
-    /// Given a valid MPIDR value, returns the corresponding linear core index.
+    /// Returns an option with the trusted non-volatile counters backend. The function should only
+    /// be called once, when it returns `Some`. All subsequent calls must return `None`.
     ///
-    /// The implementation must never return the same index for two different valid MPIDR values,
-    /// and must never return a value greater than or equal to the corresponding
-    /// `Platform::CORE_COUNT`.
-    ///
-    /// For an invalid MPIDR value no guarantees are made about the return value.
-    extern "C" fn core_position(mpidr: u64) -> usize;
+    /// Platforms without trusted non-volatile counters should use `NotSupportedNvCounters`.
+    fn nv_counters() -> Option<Self::NvCountersImpl>;
 
     /// Performs platform-specific initialisation on early cold boot before running Rust code.
     ///
@@ -254,17 +493,16 @@ pub unsafe trait Platform: Sized + Send + Sync {
         crate::naked_asm!("1:", "wfi", "b 1b");
     }
 
-    /// Dumps platform-specific registers, e.g. for the GIC, for a crash dump.
-    ///
-    /// This may be called without a Rust runtime, e.g. with no stack.
-    ///
-    /// May clobber x0-x11, x16, x17, sp.
-    ///
-    /// # Safety
+    /// Resets the system after EL3 faulted while it was already handling a crash.
     ///
-    /// Should only be called from assembly as it doesn't follow the standard calling convention.
-    #[cfg_attr(test, allow(unused))]
-    unsafe extern "C" fn dump_registers();
+    /// This is called by the second-level crash handler without a valid stack, and the crash
+    /// console may be what faulted, so it must use neither. The default implementation calls
+    /// `panic_handler`, but platforms which can reset with a few register writes should do so.
+    #[cfg(all(target_arch = "aarch64", not(any(test, feature = "fakes"))))]
+    #[unsafe(naked)]
+    extern "C" fn crash_reset() -> ! {
+        crate::naked_asm!("b {panic_handler}", panic_handler = sym Self::panic_handler);
+    }
 
     /// Platform dependent part of the RMM Boot Manifest. Entries within the range `0..RMM_<NAME>`
     /// (see above) are allocated to be filled by this function. Any extra entry is reserved for
@@ -288,6 +526,147 @@ pub unsafe trait Platform: Sized + Send + Sync {
     ) -> Result<(usize, usize), RmmCommandReturnCode>;
 }
 
+/// The hooks used by the rest of RF-A, combining a [`Board`] with the [`Soc`] it is built around.
+///
+/// This is implemented for every `Board`, with the SoC hooks forwarded to its `Soc`.
+///
+/// # Safety
+///
+/// The requirements on the corresponding items of [`Soc`] and [`Board`] apply.
+pub unsafe trait Platform: Board {
+    /// See [`Soc::CORE_COUNT`].
+    const CORE_COUNT: usize;
+
+    /// See [`Soc::CACHE_WRITEBACK_GRANULE`].
+    const CACHE_WRITEBACK_GRANULE: usize;
+
+    /// See [`Soc::GIC_CONFIG`].
+    const GIC_CONFIG: gicv3::GicConfig;
+
+    /// See [`Soc::NORMAL_MEMORY_MAIR_ATTRIBUTE`].
+    const NORMAL_MEMORY_MAIR_ATTRIBUTE: MairAttribute;
+
+    /// See [`Soc::mpidr_is_valid`].
+    fn mpidr_is_valid(mpidr: MpidrEl1) -> bool;
+
+    /// See [`Soc::handle_impdef_sysreg_trap`].
+    fn handle_impdef_sysreg_trap(world: World, access: &SysregAccess) -> SysregTrapAction;
+
+    /// See [`Soc::arch_workaround_1_supported`].
+    fn arch_workaround_1_supported() -> WorkaroundSupport;
+
+    /// See [`Soc::arch_workaround_1`].
+    fn arch_workaround_1();
+
+    /// See [`Soc::arch_workaround_2_supported`].
+    fn arch_workaround_2_supported() -> WorkaroundSupport;
+
+    /// See [`Soc::arch_workaround_2`].
+    fn arch_workaround_2();
+
+    /// See [`Soc::arch_workaround_3_supported`].
+    fn arch_workaround_3_supported() -> WorkaroundSupport;
+
+    /// See [`Soc::arch_workaround_3`].
+    fn arch_workaround_3();
+
+    /// See [`Soc::arch_workaround_4_supported`].
+    fn arch_workaround_4_supported() -> WorkaroundSupport;
+
+    /// See [`Soc::soc_id_version`].
+    fn soc_id_version() -> Option<u32>;
+
+    /// See [`Soc::soc_id_revision`].
+    fn soc_id_revision() -> u32;
+
+    /// See [`Soc::core_position`].
+    extern "C" fn core_position(mpidr: u64) -> usize;
+
+    /// See [`Soc::dump_registers`].
+    ///
+    /// # Safety
+    ///
+    /// Should only be called from assembly as it doesn't follow the standard calling convention.
+    #[cfg_attr(test, allow(unused))]
+    unsafe extern "C" fn dump_registers();
+}
+
+// SAFETY: The SoC and the board uphold the requirements on their items, and the naked functions of
+// the SoC are forwarded with a tail call which doesn't use the stack or clobber any registers.
+unsafe impl<B: Board> Platform for B {
+    const CORE_COUNT: usize = B::Soc::CORE_COUNT;
+    const CACHE_WRITEBACK_GRANULE: usize = B::Soc::CACHE_WRITEBACK_GRANULE;
+    const GIC_CONFIG: gicv3::GicConfig = B::Soc::GIC_CONFIG;
+    const NORMAL_MEMORY_MAIR_ATTRIBUTE: MairAttribute = B::Soc::NORMAL_MEMORY_MAIR_ATTRIBUTE;
+
+    fn mpidr_is_valid(mpidr: MpidrEl1) -> bool {
+        B::Soc::mpidr_is_valid(mpidr)
+    }
+
+    fn handle_impdef_sysreg_trap(world: World, access: &SysregAccess) -> SysregTrapAction {
+        B::Soc::handle_impdef_sysreg_trap(world, access)
+    }
+
+    fn arch_workaround_1_supported() -> WorkaroundSupport {
+        B::Soc::arch_workaround_1_supported()
+    }
+
+    fn arch_workaround_1() {
+        B::Soc::arch_workaround_1()
+    }
+
+    fn arch_workaround_2_supported() -> WorkaroundSupport {
+        B::Soc::arch_workaround_2_supported()
+    }
+
+    fn arch_workaround_2() {
+        B::Soc::arch_workaround_2()
+    }
+
+    fn arch_workaround_3_supported() -> WorkaroundSupport {
+        B::Soc::arch_workaround_3_supported()
+    }
+
+    fn arch_workaround_3() {
+        B::Soc::arch_workaround_3()
+    }
+
+    fn arch_workaround_4_supported() -> WorkaroundSupport {
+        B::Soc::arch_workaround_4_supported()
+    }
+
+    fn soc_id_version() -> Option<u32> {
+        B::Soc::soc_id_version()
+    }
+
+    fn soc_id_revision() -> u32 {
+        B::Soc::soc_id_revision()
+    }
+
+    #[cfg(all(target_arch = "aarch64", not(any(test, feature = "fakes"))))]
+    #[unsafe(naked)]
+    extern "C" fn core_position(mpidr: u64) -> usize {
+        crate::naked_asm!("b {core_position}", core_position = sym B::Soc::core_position);
+    }
+
+    #[cfg(not(all(target_arch = "aarch64", not(any(test, feature = "fakes")))))]
+    extern "C" fn core_position(mpidr: u64) -> usize {
+        B::Soc::core_position(mpidr)
+    }
+
+    #[cfg(all(target_arch = "aarch64", not(any(test, feature = "fakes"))))]
+    #[unsafe(naked)]
+    unsafe extern "C" fn dump_registers() {
+        crate::naked_asm!("b {dump_registers}", dump_registers = sym B::Soc::dump_registers);
+    }
+
+    #[cfg(not(all(target_arch = "aarch64", not(any(test, feature = "fakes")))))]
+    unsafe extern "C" fn dump_registers() {
+        // SAFETY: Our caller promised to uphold the SoC's requirements.
+        unsafe { B::Soc::dump_registers() }
+    }
+}
+
 #[cfg(all(target_arch = "aarch64", not(any(test, feature = "fakes"))))]
 mod asm {
     use super::*;
@@ -4,49 +4,58 @@
 
 //! Fake platform for testing.
 
-use super::{DummyService, Platform};
+use super::{Board, Platform, PlatformService, Soc, UnknownHvcPolicy, topology::SparseTopology};
 #[cfg(feature = "rme")]
 use crate::services::rmmd::svc::{EccCurve, RmmCommandReturnCode};
 use crate::{
     aarch64::sev,
-    context::{CoresImpl, CpuData, CpuDataIndex, EntryPointInfo},
+    context::{CoresImpl, CpuData, CpuDataIndex, EntryPointInfo, World},
     cpu::{Cpu, CpuOps, PlatformCpuOps},
-    cpu_extensions::CpuExtension,
+    cpu_extensions::{CpuExtension, mpmm::MpmmGear},
     errata_framework::{Cve, Erratum, ErratumId, ErratumType, define_errata_list},
-    gicv3::GicConfig,
+    gicv3::{GicConfig, InterruptConfig, SgiRegistry, SgiUser},
     logger::LogSink,
+    nv_counter::{BootRequest, NvCounterError, NvCounterId, NvCounters},
     pagetable::{IdMap, MT_DEVICE, disable_mmu_el3, early_pagetable::define_early_mapping},
     services::{
-        arch::WorkaroundSupport,
+        Service,
+        arch::{
+            ARM_JEP106_CONTINUATION_CODE, ARM_JEP106_IDENTIFICATION_CODE, WorkaroundSupport,
+            soc_id_version,
+        },
         psci::{
             PlatformPowerStateInterface, PowerStateType, PsciCompositePowerState,
-            PsciPlatformInterface, PsciPlatformOptionalFeatures,
+            PsciPlatformInterface, PsciPlatformOptionalFeatures, VendorResetType,
         },
         trng::{TrngError, TrngPlatformInterface},
     },
+    smccc::FunctionId,
     statics,
+    sysreg_trap::{SysregAccess, SysregEncoding, SysregTrapAction},
 };
 use aarch64_paging::paging::MemoryRegion;
-use arm_gic::IntId;
+use arm_gic::{IntId, Trigger};
 use arm_psci::{Cookie, ErrorCode, HwState, Mpidr, PowerState, SystemOff2Type};
 use arm_sysregs::{MidrEl1, MpidrEl1};
 use core::fmt;
 use percore::Cores;
 use percore::ExceptionFree;
-use std::io::{Write, stdout};
+use std::{
+    io::{Write, stdout},
+    sync::{
+        Mutex,
+        atomic::{AtomicU32, AtomicUsize, Ordering},
+    },
+};
 use uuid::Uuid;
 
 const DEVICE0_BASE: usize = 0x0200_0000;
 const DEVICE0_SIZE: usize = 0x1000;
 const DEVICE0: MemoryRegion = MemoryRegion::new(DEVICE0_BASE, DEVICE0_BASE + DEVICE0_SIZE);
 
-// The levels of the power topology System, SoC, Cluster, Core.
-const SYSTEM_DOMAIN_INDEX: u8 = 0;
-const SOCS_PER_SYSTEM: u8 = 2;
-const CLUSTERS_PER_SOC: usize = 2;
-// Each cluster has 3 cores except the last one which has 4.
-const CORES_PER_CLUSTER: usize = 3;
-const CORES_PER_CLUSTER_LAST: usize = 4;
+// The levels of the power topology System, SoC, Cluster, Core. There are 2 SoCs with 2 clusters
+// each. Each cluster has 3 cores except the last one which has 4.
+const TOPOLOGY: SparseTopology = SparseTopology::new(&TestPlatform::MPIDR_VALUES);
 
 define_early_mapping!(TestPlatform, []);
 define_errata_list!(TestPlatform, [TestMitigatedErratum, TestUnneededErratum]);
@@ -55,6 +64,11 @@ define_errata_list!(TestPlatform, [TestMitigatedErratum, TestUnneededErratum]);
 pub struct TestPlatform;
 
 impl TestPlatform {
+    /// An IMPDEF system register which the test platform emulates, reading as 0x1234.
+    pub const EMULATED_IMPDEF_SYSREG: SysregEncoding = SysregEncoding::new(3, 0, 15, 2, 1);
+    /// An IMPDEF system register which the test platform treats as RAZ/WI.
+    pub const RAZ_WI_IMPDEF_SYSREG: SysregEncoding = SysregEncoding::new(3, 1, 11, 0, 2);
+
     /// The MPIDR values for each core, for use in tests.
     pub const MPIDR_VALUES: [MpidrEl1; Self::CORE_COUNT] = [
         MpidrEl1::from_bits_retain(0x0000_0000_0000_0000),
@@ -73,15 +87,92 @@ impl TestPlatform {
     ];
 }
 
+/// The fake SoC of the test platform.
+pub struct TestSoc;
+
 // SAFETY: The test platform is exempt from the usual safety requirements on `core_position`,
-// because it is only used in unit tests and so `TestPlatform::core_position` is never called from
+// because it is only used in unit tests and so `TestSoc::core_position` is never called from
 // assembly code.
-unsafe impl Platform for TestPlatform {
+unsafe impl Soc for TestSoc {
     const CORE_COUNT: usize = 13;
     const CACHE_WRITEBACK_GRANULE: usize = 1 << 6;
 
+    const GIC_CONFIG: GicConfig = GicConfig {
+        sgis: SgiRegistry::EMPTY.claim(
+            9,
+            SgiUser::NotificationSri,
+            InterruptConfig {
+                trigger: Trigger::Edge,
+                ..InterruptConfig::DEFAULT
+            },
+        ),
+        interrupts_config: &[],
+    };
+
+    fn handle_impdef_sysreg_trap(_world: World, access: &SysregAccess) -> SysregTrapAction {
+        match access.encoding {
+            TestPlatform::EMULATED_IMPDEF_SYSREG => SysregTrapAction::Emulated(0x1234),
+            TestPlatform::RAZ_WI_IMPDEF_SYSREG => SysregTrapAction::RazWi,
+            _ => SysregTrapAction::Undefined,
+        }
+    }
+
+    fn mpidr_is_valid(mpidr: MpidrEl1) -> bool {
+        TOPOLOGY.mpidr_is_valid(mpidr)
+    }
+
+    fn arch_workaround_1_supported() -> WorkaroundSupport {
+        WorkaroundSupport::SafeButNotRequired
+    }
+
+    fn arch_workaround_1() {}
+
+    fn arch_workaround_2_supported() -> WorkaroundSupport {
+        WorkaroundSupport::SafeButNotRequired
+    }
+
+    fn arch_workaround_2() {}
+
+    fn arch_workaround_3_supported() -> WorkaroundSupport {
+        WorkaroundSupport::SafeButNotRequired
+    }
+
+    fn arch_workaround_3() {}
+
+    fn arch_workaround_4_supported() -> WorkaroundSupport {
+        WorkaroundSupport::SafeButNotRequired
+    }
+
+    fn soc_id_version() -> Option<u32> {
+        Some(soc_id_version(
+            ARM_JEP106_CONTINUATION_CODE,
+            ARM_JEP106_IDENTIFICATION_CODE,
+            0x1234,
+        ))
+    }
+
+    fn soc_id_revision() -> u32 {
+        2
+    }
+
+    extern "C" fn core_position(mpidr: u64) -> usize {
+        TOPOLOGY
+            .core_position(MpidrEl1::from_bits_retain(mpidr))
+            .unwrap()
+    }
+
+    unsafe extern "C" fn dump_registers() {}
+}
+
+// SAFETY: The test platform is exempt from the usual safety requirements on naked functions,
+// because it is only used in unit tests and so they are never called from assembly code.
+unsafe impl Board for TestPlatform {
+    type Soc = TestSoc;
+
     const PAGE_HEAP_PAGE_COUNT: usize = 6;
 
+    const UNKNOWN_HVC_POLICY: UnknownHvcPolicy = UnknownHvcPolicy::NotSupported;
+
     #[cfg(feature = "rme")]
     const RMM_SHARED_BUFFER_START: usize = 0xffbf_f000;
 
@@ -95,6 +186,7 @@ unsafe impl Platform for TestPlatform {
     ) -> Result<usize, RmmCommandReturnCode> {
         Ok(0)
     }
+
     #[cfg(feature = "rme")]
     fn read_attestation_token(
         _buf: &mut [u8],
@@ -105,15 +197,16 @@ unsafe impl Platform for TestPlatform {
     }
 
     type LogSinkImpl = StdOutSink;
+
     type IdMap = IdMap<{ Self::PAGE_HEAP_PAGE_COUNT }>;
+
     type PsciPlatformImpl = TestPsciPlatformImpl;
+
     type TrngPlatformImpl = TestTrngPlatformImpl;
 
-    type PlatformServiceImpl = DummyService;
+    type PlatformServiceImpl = TestPlatformService;
 
-    const GIC_CONFIG: GicConfig = GicConfig {
-        interrupts_config: &[],
-    };
+    type NvCountersImpl = TestNvCounters;
 
     const CPU_EXTENSIONS: &'static [&'static dyn CpuExtension] = &[];
 
@@ -123,6 +216,10 @@ unsafe impl Platform for TestPlatform {
             .expect("Failed to initialise logger");
     }
 
+    fn mpmm_gear(core_index: usize) -> Option<MpmmGear> {
+        (core_index == 0).then_some(MpmmGear::Gear1)
+    }
+
     fn map_extra_regions(idmap: &mut Self::IdMap) {
         // SAFETY: The pagetable isn't actually used in unit tests.
         unsafe {
@@ -136,7 +233,7 @@ unsafe impl Platform for TestPlatform {
     }
 
     fn create_service() -> Self::PlatformServiceImpl {
-        DummyService
+        TestPlatformService
     }
 
     fn handle_group0_interrupt(int_id: IntId) {
@@ -165,66 +262,12 @@ unsafe impl Platform for TestPlatform {
         }
     }
 
-    fn mpidr_is_valid(mpidr: MpidrEl1) -> bool {
-        let system_index = mpidr.aff3();
-        let soc_index = mpidr.aff2();
-        let cluster_index = usize::from(mpidr.aff1());
-        let core_index = usize::from(mpidr.aff0());
-
-        // Validate System, SoC and Cluster indexes
-        if system_index != SYSTEM_DOMAIN_INDEX
-            || soc_index >= SOCS_PER_SYSTEM
-            || cluster_index >= CLUSTERS_PER_SOC
-        {
-            return false;
-        }
-
-        // Validate Core index
-        let is_last_cluster =
-            soc_index == SOCS_PER_SYSTEM - 1 && cluster_index == CLUSTERS_PER_SOC - 1;
-        if is_last_cluster {
-            core_index < CORES_PER_CLUSTER_LAST
-        } else {
-            core_index < CORES_PER_CLUSTER
-        }
-    }
-
     fn psci_platform() -> Option<Self::PsciPlatformImpl> {
         Some(TestPsciPlatformImpl::new())
     }
 
-    fn arch_workaround_1_supported() -> WorkaroundSupport {
-        WorkaroundSupport::SafeButNotRequired
-    }
-
-    fn arch_workaround_1() {}
-
-    fn arch_workaround_2_supported() -> WorkaroundSupport {
-        WorkaroundSupport::SafeButNotRequired
-    }
-
-    fn arch_workaround_2() {}
-
-    fn arch_workaround_3_supported() -> WorkaroundSupport {
-        WorkaroundSupport::SafeButNotRequired
-    }
-
-    fn arch_workaround_3() {}
-
-    fn arch_workaround_4_supported() -> WorkaroundSupport {
-        WorkaroundSupport::SafeButNotRequired
-    }
-
-    extern "C" fn core_position(mpidr: u64) -> usize {
-        let mpidr = MpidrEl1::from_bits_retain(mpidr);
-
-        assert!(TestPlatform::mpidr_is_valid(mpidr));
-
-        let soc_index = usize::from(mpidr.aff2());
-        let cluster_index = usize::from(mpidr.aff1());
-        let core_index = usize::from(mpidr.aff0());
-
-        ((soc_index * CLUSTERS_PER_SOC) + cluster_index) * CORES_PER_CLUSTER + core_index
+    fn nv_counters() -> Option<Self::NvCountersImpl> {
+        Some(TestNvCounters)
     }
 
     unsafe extern "C" fn cold_boot_handler() {}
@@ -238,8 +281,6 @@ unsafe impl Platform for TestPlatform {
     }
 
     extern "C" fn crash_console_flush() {}
-
-    unsafe extern "C" fn dump_registers() {}
 }
 
 // SAFETY: The safety requirement for `cpu_data_by_index` to be a naked function doesn't apply for
@@ -258,6 +299,60 @@ unsafe impl CpuDataIndex for TestPlatform {
     }
 }
 
+/// Fake platform service for tests, which doesn't handle any SMCs but registers a vendor reset.
+pub struct TestPlatformService;
+
+impl TestPlatformService {
+    /// A vendor specific `SYSTEM_RESET2` reset type which only accepts a zero cookie.
+    pub const RECOVERY_RESET_TYPE: u32 = 0x1;
+    /// A vendor specific `SYSTEM_RESET2` reset type which requests firmware update mode.
+    pub const FIRMWARE_UPDATE_RESET_TYPE: u32 = 0x2;
+}
+
+impl Service for TestPlatformService {
+    fn owns(&self, _function: FunctionId) -> bool {
+        false
+    }
+}
+
+impl PlatformService for TestPlatformService {
+    const VENDOR_RESET_TYPES: &'static [VendorResetType] = &[
+        VendorResetType {
+            reset_type: Self::RECOVERY_RESET_TYPE,
+            name: "reset to recovery",
+            validate_cookie: |cookie| matches!(cookie, Cookie::Cookie32(0) | Cookie::Cookie64(0)),
+            boot_request: None,
+        },
+        VendorResetType {
+            reset_type: Self::FIRMWARE_UPDATE_RESET_TYPE,
+            name: "reset to firmware update",
+            validate_cookie: |_| true,
+            boot_request: Some(BootRequest::FirmwareUpdate),
+        },
+    ];
+}
+
+/// The last boot request recorded by [`TestNvCounters`], or 0 if none.
+pub static TEST_BOOT_REQUEST: AtomicU32 = AtomicU32::new(0);
+
+/// Fake trusted non-volatile storage for tests, which only supports recording boot requests.
+pub struct TestNvCounters;
+
+impl NvCounters for TestNvCounters {
+    fn read(&self, _counter: NvCounterId) -> Result<u32, NvCounterError> {
+        Err(NvCounterError::NotSupported)
+    }
+
+    fn write(&mut self, _counter: NvCounterId, _value: u32) -> Result<(), NvCounterError> {
+        Err(NvCounterError::NotSupported)
+    }
+
+    fn write_boot_request(&mut self, request: BootRequest) -> Result<(), NvCounterError> {
+        TEST_BOOT_REQUEST.store(request as u32, Ordering::SeqCst);
+        Ok(())
+    }
+}
+
 /// A log sink for tests which writes logs to standard output.
 pub struct StdOutSink;
 
@@ -307,9 +402,136 @@ impl From<TestPowerState> for usize {
     }
 }
 
+/// The state of a single core in the [`SimulatedPowerController`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SimulatedCoreState {
+    /// The core is powered off.
+    Off,
+    /// The core has been asked to power on, and will be on after the given number of steps.
+    PendingOn {
+        /// The number of steps remaining before the core is on.
+        remaining: u32,
+    },
+    /// The core is powered on.
+    On,
+    /// The core has requested to be powered down but hasn't yet reached the point where the
+    /// power controller turns it off.
+    PendingOff {
+        /// Whether a power on request arrived while the power down was still in progress. If so
+        /// the core will start powering on again as soon as it is off.
+        wake_requested: bool,
+    },
+}
+
+/// A simulated power controller for the fake PSCI platform.
+///
+/// This keeps track of the power state of each core, so that tests can check what the PSCI
+/// implementation asked the hardware to do, and can control when requested transitions actually
+/// complete by calling [`step`](Self::step). In particular this allows testing a `CPU_ON` which
+/// arrives while the target core is still in the middle of powering down.
+#[derive(Debug)]
+pub struct SimulatedPowerController {
+    cores: Mutex<[SimulatedCoreState; TestPlatform::CORE_COUNT]>,
+    wakeup_latencies: Mutex<[u32; TestPlatform::CORE_COUNT]>,
+}
+
+impl SimulatedPowerController {
+    /// Returns a new power controller where only the current core is on, and all cores power on
+    /// immediately when requested.
+    pub fn new() -> Self {
+        let mut cores = [SimulatedCoreState::Off; TestPlatform::CORE_COUNT];
+        cores[CoresImpl::<TestPlatform>::core_index()] = SimulatedCoreState::On;
+        Self {
+            cores: Mutex::new(cores),
+            wakeup_latencies: Mutex::new([0; TestPlatform::CORE_COUNT]),
+        }
+    }
+
+    /// Returns the current state of the given core.
+    pub fn core_state(&self, core_index: usize) -> SimulatedCoreState {
+        self.cores.lock().unwrap()[core_index]
+    }
+
+    /// Sets the number of steps which the given core takes to power on once it is off.
+    pub fn set_wakeup_latency(&self, core_index: usize, steps: u32) {
+        self.wakeup_latencies.lock().unwrap()[core_index] = steps;
+    }
+
+    /// Advances all pending power transitions by one step.
+    ///
+    /// Cores which are pending off finish powering down, and then start powering on if a power on
+    /// request arrived in the meantime. Cores which are pending on get one step closer to being
+    /// on.
+    pub fn step(&self) {
+        let wakeup_latencies = self.wakeup_latencies.lock().unwrap();
+        for (state, latency) in self
+            .cores
+            .lock()
+            .unwrap()
+            .iter_mut()
+            .zip(&*wakeup_latencies)
+        {
+            *state = match *state {
+                SimulatedCoreState::PendingOff {
+                    wake_requested: false,
+                } => SimulatedCoreState::Off,
+                SimulatedCoreState::PendingOff {
+                    wake_requested: true,
+                } => Self::powering_on(*latency),
+                SimulatedCoreState::PendingOn { remaining } => Self::powering_on(remaining - 1),
+                SimulatedCoreState::Off | SimulatedCoreState::On => *state,
+            };
+        }
+    }
+
+    /// Requests that the given core be powered on.
+    fn power_on(&self, core_index: usize) -> Result<(), ErrorCode> {
+        let latency = self.wakeup_latencies.lock().unwrap()[core_index];
+        let mut cores = self.cores.lock().unwrap();
+        cores[core_index] = match cores[core_index] {
+            SimulatedCoreState::Off => Self::powering_on(latency),
+            SimulatedCoreState::PendingOff { .. } => SimulatedCoreState::PendingOff {
+                wake_requested: true,
+            },
+            // The PSCI implementation should never ask to power on a core which is already on.
+            SimulatedCoreState::PendingOn { .. } | SimulatedCoreState::On => {
+                return Err(ErrorCode::InternalFailure);
+            }
+        };
+        Ok(())
+    }
+
+    /// Requests that the given core be powered down once it executes `wfi`.
+    fn power_off(&self, core_index: usize) {
+        let mut cores = self.cores.lock().unwrap();
+        assert_eq!(cores[core_index], SimulatedCoreState::On);
+        cores[core_index] = SimulatedCoreState::PendingOff {
+            wake_requested: false,
+        };
+    }
+
+    fn powering_on(remaining: u32) -> SimulatedCoreState {
+        if remaining == 0 {
+            SimulatedCoreState::On
+        } else {
+            SimulatedCoreState::PendingOn { remaining }
+        }
+    }
+}
+
+impl Default for SimulatedPowerController {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 /// Fake PSCI platform implementation for tests.
 #[derive(Debug, Default)]
-pub struct TestPsciPlatformImpl;
+pub struct TestPsciPlatformImpl {
+    power_controller: SimulatedPowerController,
+    retained_context_flushes: AtomicUsize,
+    suspend_veto: Mutex<Option<ErrorCode>>,
+}
 
 impl TestPsciPlatformImpl {
     // Functions that normally do not return make it impossible to test any PSCI call which ends in
@@ -331,7 +553,27 @@ impl TestPsciPlatformImpl {
 
     /// Returns a new instance of the fake PSCI platform.
     pub fn new() -> Self {
-        Self
+        Self::default()
+    }
+
+    /// Returns the simulated power controller of the fake platform.
+    pub fn power_controller(&self) -> &SimulatedPowerController {
+        &self.power_controller
+    }
+
+    /// Returns the number of times the retained context has been flushed before a power down.
+    pub fn retained_context_flushes(&self) -> usize {
+        self.retained_context_flushes.load(Ordering::Relaxed)
+    }
+
+    /// Sets the error which `power_domain_validate_suspend` returns to veto suspend requests, or
+    /// `None` to allow them.
+    pub fn set_suspend_veto(&self, veto: Option<ErrorCode>) {
+        *self.suspend_veto.lock().unwrap() = veto;
+    }
+
+    fn core_index(mpidr: Mpidr) -> usize {
+        TestPlatform::core_position(MpidrEl1::from_psci_mpidr(mpidr.into()).bits())
     }
 }
 
@@ -357,7 +599,8 @@ impl
     type NodeIndex = u8;
 
     fn topology() -> &'static [usize] {
-        &[1, 2, 2, 2, 3, 3, 3, 4]
+        const POWER_DOMAIN_TOPOLOGY: [usize; 8] = TOPOLOGY.power_domain_topology(2);
+        &POWER_DOMAIN_TOPOLOGY
     }
 
     fn try_parse_power_state(
@@ -474,7 +717,10 @@ impl
             TestPowerState,
         >,
     ) -> Result<(), ErrorCode> {
-        Ok(())
+        match *self.suspend_veto.lock().unwrap() {
+            Some(error) => Err(error),
+            None => Ok(()),
+        }
     }
 
     fn power_domain_off(
@@ -489,6 +735,8 @@ impl
         >,
     ) {
         assert_eq!(target_state.cpu_level_state(), TestPowerState::PowerDown);
+        self.power_controller
+            .power_off(CoresImpl::<TestPlatform>::core_index());
     }
 
     fn power_domain_power_down(
@@ -510,7 +758,24 @@ impl
         panic!("{}", Self::POWER_DOWN_WFI_MAGIC);
     }
 
-    fn power_domain_on(&self, _mpidr: Mpidr) -> Result<(), ErrorCode> {
+    fn flush_retained_context(
+        &self,
+        target_state: &PsciCompositePowerState<
+            PSCI_STATE_COUNT,
+            PSCI_MAX_POWER_LEVEL,
+            { TestPlatform::CORE_COUNT },
+            PSCI_NON_CPU_DOMAIN_COUNT,
+            Self::NodeIndex,
+            TestPowerState,
+        >,
+    ) {
+        assert!(target_state.find_highest_power_down_level().unwrap() > 0);
+        self.retained_context_flushes
+            .fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn power_domain_on(&self, mpidr: Mpidr) -> Result<(), ErrorCode> {
+        self.power_controller.power_on(Self::core_index(mpidr))?;
         sev();
         Ok(())
     }
@@ -526,6 +791,12 @@ impl
             TestPowerState,
         >,
     ) {
+        assert_eq!(
+            self.power_controller
+                .core_state(CoresImpl::<TestPlatform>::core_index()),
+            SimulatedCoreState::On,
+            "Core booted before the power controller turned it on"
+        );
     }
 
     fn system_off(&self) -> ! {
@@ -564,8 +835,12 @@ impl
         PowerState::StandbyOrRetention(0)
     }
 
-    fn node_hw_state(&self, _mpidr: Mpidr, _power_level: u32) -> Result<HwState, ErrorCode> {
-        Ok(HwState::Off)
+    fn node_hw_state(&self, mpidr: Mpidr, _power_level: u32) -> Result<HwState, ErrorCode> {
+        // A core which is pending off is still powered until it reaches `wfi`.
+        match self.power_controller.core_state(Self::core_index(mpidr)) {
+            SimulatedCoreState::On | SimulatedCoreState::PendingOff { .. } => Ok(HwState::On),
+            SimulatedCoreState::Off | SimulatedCoreState::PendingOn { .. } => Ok(HwState::Off),
+        }
     }
 
     fn sys_suspend_power_state(
@@ -0,0 +1,399 @@
+// Copyright The Rusted Firmware-A Contributors.
+//
+// SPDX-License-Identifier: BSD-3-Clause
+
+//! Shared helpers for mapping MPIDR values to linear core indices.
+//!
+//! Most platforms number their cores densely by affinity level, and can describe this with an
+//! [`AffinityTopology`] and implement `Platform::core_position` with `affinity_core_position!`.
+//! Platforms whose MPIDR values don't follow such a pattern, for example because some clusters are
+//! missing or have fewer cores than others, can instead list them in a [`SparseTopology`] and use
+//! `lookup_core_position!`.
+
+use arm_sysregs::MpidrEl1;
+
+/// The mask of the affinity fields of an MPIDR value.
+pub const MPIDR_AFFINITY_MASK: u64 = (MpidrEl1::AFF3_MASK << MpidrEl1::AFF3_SHIFT)
+    | (MpidrEl1::AFF2_MASK << MpidrEl1::AFF2_SHIFT)
+    | (MpidrEl1::AFF1_MASK << MpidrEl1::AFF1_SHIFT)
+    | (MpidrEl1::AFF0_MASK << MpidrEl1::AFF0_SHIFT);
+
+/// A CPU topology with a fixed number of clusters, cores per cluster and threads per core.
+///
+/// If `MPIDR_EL1.MT` is set then Aff0 is the thread, Aff1 the core and Aff2 the cluster, otherwise
+/// Aff0 is the core and Aff1 the cluster. Cores are numbered as
+/// `(cluster * cores_per_cluster + core) * threads_per_core + thread`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct AffinityTopology {
+    /// The number of clusters.
+    pub clusters: usize,
+    /// The maximum number of cores in each cluster.
+    pub cores_per_cluster: usize,
+    /// The maximum number of threads (PEs) in each core.
+    pub threads_per_core: usize,
+}
+
+impl AffinityTopology {
+    /// Returns the number of cores, i.e. the value to use for `Platform::CORE_COUNT`.
+    pub const fn core_count(&self) -> usize {
+        self.clusters * self.cores_per_cluster * self.threads_per_core
+    }
+
+    /// Returns whether the given MPIDR value is valid for this topology.
+    pub const fn mpidr_is_valid(&self, mpidr: MpidrEl1) -> bool {
+        self.core_position(mpidr).is_some()
+    }
+
+    /// Returns the linear core index for the given MPIDR value, or `None` if it is not valid for
+    /// this topology.
+    ///
+    /// This gives the same result as `affinity_core_position!` for valid MPIDR values.
+    pub const fn core_position(&self, mpidr: MpidrEl1) -> Option<usize> {
+        let (cluster, core, thread) = if mpidr.contains(MpidrEl1::MT) {
+            if mpidr.aff3() != 0 {
+                return None;
+            }
+            (
+                mpidr.aff2() as usize,
+                mpidr.aff1() as usize,
+                mpidr.aff0() as usize,
+            )
+        } else {
+            if mpidr.aff3() != 0 || mpidr.aff2() != 0 {
+                return None;
+            }
+            (mpidr.aff1() as usize, mpidr.aff0() as usize, 0)
+        };
+        if cluster < self.clusters
+            && core < self.cores_per_cluster
+            && thread < self.threads_per_core
+        {
+            Some((cluster * self.cores_per_cluster + core) * self.threads_per_core + thread)
+        } else {
+            None
+        }
+    }
+}
+
+/// A CPU topology given by an explicit list of the MPIDR values of all cores.
+///
+/// The index of each core is its position in the list. The list must be sorted by affinity, with
+/// no duplicates, so that the cores of each cluster are contiguous and the primary core must have
+/// the lowest MPIDR value.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct SparseTopology<'a> {
+    mpidrs: &'a [MpidrEl1],
+}
+
+impl<'a> SparseTopology<'a> {
+    /// Creates a new topology with the given MPIDR values.
+    ///
+    /// Panics if the list is empty, not sorted by affinity, or contains bits other than the
+    /// affinity fields.
+    pub const fn new(mpidrs: &'a [MpidrEl1]) -> Self {
+        assert!(!mpidrs.is_empty(), "No cores in topology");
+        let mut i = 0;
+        while i < mpidrs.len() {
+            assert!(
+                mpidrs[i].bits() & !MPIDR_AFFINITY_MASK == 0,
+                "Topology MPIDR has non-affinity bits set"
+            );
+            assert!(
+                i == 0 || affinity(mpidrs[i - 1]) < affinity(mpidrs[i]),
+                "Topology MPIDRs must be sorted and unique"
+            );
+            i += 1;
+        }
+        Self { mpidrs }
+    }
+
+    /// Returns the number of cores, i.e. the value to use for `Platform::CORE_COUNT`.
+    pub const fn core_count(&self) -> usize {
+        self.mpidrs.len()
+    }
+
+    /// Returns the MPIDR affinity value of the core with the given index.
+    pub const fn mpidr(&self, core_index: usize) -> MpidrEl1 {
+        self.mpidrs[core_index]
+    }
+
+    /// Returns whether the given MPIDR value is one of the cores in this topology.
+    pub const fn mpidr_is_valid(&self, mpidr: MpidrEl1) -> bool {
+        self.core_position(mpidr).is_some()
+    }
+
+    /// Returns the linear core index for the given MPIDR value, or `None` if it is not one of the
+    /// cores in this topology.
+    ///
+    /// Bits of the MPIDR other than the affinity fields are ignored. This gives the same result as
+    /// `lookup_core_position!` for valid MPIDR values.
+    pub const fn core_position(&self, mpidr: MpidrEl1) -> Option<usize> {
+        let mpidr = affinity(mpidr);
+        let mut i = 0;
+        while i < self.mpidrs.len() {
+            if affinity(self.mpidrs[i]) == mpidr {
+                return Some(i);
+            }
+            i += 1;
+        }
+        None
+    }
+
+    /// Returns the power domain topology for PSCI, in the format expected by
+    /// `PsciPlatformInterface::topology`.
+    ///
+    /// `levels` is the number of affinity levels between the system and the cores, e.g. 1 if the
+    /// cores are grouped only into clusters by Aff1, or 2 if clusters are further grouped by Aff2.
+    ///
+    /// Panics if `N` isn't the length of the resulting topology.
+    pub const fn power_domain_topology<const N: usize>(&self, levels: usize) -> [usize; N] {
+        let mut topology = [0; N];
+        // The system node.
+        topology[0] = 1;
+        let mut n = 1;
+
+        // For each level from the system down to just above the cores, count the children of each
+        // node. As the MPIDRs are sorted, the descendants of each node are contiguous.
+        let mut level = levels + 1;
+        while level > 0 {
+            let mut i = 0;
+            while i < self.mpidrs.len() {
+                let node = self.node(i, level, levels);
+                let mut children = 0;
+                let mut j = i;
+                while j < self.mpidrs.len() && self.node(j, level, levels) == node {
+                    if j == i
+                        || self.node(j, level - 1, levels) != self.node(j - 1, level - 1, levels)
+                    {
+                        children += 1;
+                    }
+                    j += 1;
+                }
+                assert!(n < N, "Power domain topology is longer than expected");
+                topology[n] = children;
+                n += 1;
+                i = j;
+            }
+            level -= 1;
+        }
+        assert!(n == N, "Power domain topology is shorter than expected");
+        topology
+    }
+
+    /// Returns an identifier for the node at the given affinity level containing the core with the
+    /// given index, where any level above `levels` is the whole system.
+    const fn node(&self, core_index: usize, level: usize, levels: usize) -> u64 {
+        if level > levels {
+            0
+        } else {
+            affinity(self.mpidrs[core_index]) >> (level * MpidrEl1::AFFINITY_BITS)
+        }
+    }
+}
+
+/// Returns the affinity fields of the given MPIDR value packed together, so that they can be
+/// compared in order.
+const fn affinity(mpidr: MpidrEl1) -> u64 {
+    (mpidr.aff3() as u64) << (3 * MpidrEl1::AFFINITY_BITS)
+        | (mpidr.aff2() as u64) << (2 * MpidrEl1::AFFINITY_BITS)
+        | (mpidr.aff1() as u64) << MpidrEl1::AFFINITY_BITS
+        | mpidr.aff0() as u64
+}
+
+/// Generates the body of a naked `Platform::core_position` implementation for the given
+/// [`AffinityTopology`] constant.
+///
+/// The generated code doesn't use the stack and only clobbers x0-x5.
+#[cfg(all(target_arch = "aarch64", not(any(test, feature = "fakes"))))]
+#[macro_export]
+macro_rules! affinity_core_position {
+    ($topology:path) => {
+        $crate::naked_asm!(
+            // Check for MT bit in MPIDR. If not set, shift MPIDR to left to make it look as if in a
+            // multi-threaded implementation.
+            "tst	x0, #{MPIDR_MT_MASK}",
+            "lsl	x3, x0, #{MPIDR_AFFINITY_BITS}",
+            "csel	x3, x3, x0, eq",
+            // Extract individual affinity fields from MPIDR.
+            "ubfx	x0, x3, #{MPIDR_AFF0_SHIFT}, #{MPIDR_AFFINITY_BITS}",
+            "ubfx	x1, x3, #{MPIDR_AFF1_SHIFT}, #{MPIDR_AFFINITY_BITS}",
+            "ubfx	x2, x3, #{MPIDR_AFF2_SHIFT}, #{MPIDR_AFFINITY_BITS}",
+            // Compute linear position.
+            "mov	x4, #{CORES_PER_CLUSTER}",
+            "madd	x1, x2, x4, x1",
+            "mov	x5, #{THREADS_PER_CORE}",
+            "madd	x0, x1, x5, x0",
+            "ret",
+            MPIDR_MT_MASK = const $crate::reexports::arm_sysregs::MpidrEl1::MT.bits(),
+            MPIDR_AFF0_SHIFT = const $crate::reexports::arm_sysregs::MpidrEl1::AFF0_SHIFT,
+            MPIDR_AFF1_SHIFT = const $crate::reexports::arm_sysregs::MpidrEl1::AFF1_SHIFT,
+            MPIDR_AFF2_SHIFT = const $crate::reexports::arm_sysregs::MpidrEl1::AFF2_SHIFT,
+            MPIDR_AFFINITY_BITS = const $crate::reexports::arm_sysregs::MpidrEl1::AFFINITY_BITS,
+            CORES_PER_CLUSTER = const $topology.cores_per_cluster,
+            THREADS_PER_CORE = const $topology.threads_per_core,
+        )
+    };
+}
+
+/// Generates the body of a naked `Platform::core_position` implementation which looks up the MPIDR
+/// in the given static array of MPIDR affinity values, and returns its index in the array.
+///
+/// The array may contain `u64` or `MpidrEl1` values. Each entry must only contain the bits in
+/// [`MPIDR_AFFINITY_MASK`], and entries must be unique. The array used for a [`SparseTopology`]
+/// meets these requirements.
+/// For an MPIDR value not in the array, returns the length of the array.
+///
+/// The generated code doesn't use the stack and only clobbers x0-x3.
+#[cfg(all(target_arch = "aarch64", not(any(test, feature = "fakes"))))]
+#[macro_export]
+macro_rules! lookup_core_position {
+    ($table:path) => {
+        $crate::naked_asm!(
+            // Mask out everything except the affinity fields.
+            "and	x1, x0, #{AFF0_2_MASK}",
+            "and	x0, x0, #{AFF3_MASK}",
+            "orr	x0, x0, x1",
+            "adrp	x1, {table}",
+            "add	x1, x1, :lo12:{table}",
+            "mov	x2, #0",
+            "1:",
+            "cmp	x2, #{COUNT}",
+            "b.eq	2f",
+            "ldr	x3, [x1, x2, lsl #3]",
+            "cmp	x3, x0",
+            "b.eq	2f",
+            "add	x2, x2, #1",
+            "b	1b",
+            "2:",
+            "mov	x0, x2",
+            "ret",
+            AFF0_2_MASK = const $crate::platform::topology::MPIDR_AFFINITY_MASK & 0xff_ffff,
+            AFF3_MASK = const $crate::platform::topology::MPIDR_AFFINITY_MASK & !0xff_ffff,
+            COUNT = const $table.len(),
+            table = sym $table,
+        )
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TOPOLOGY: AffinityTopology = AffinityTopology {
+        clusters: 2,
+        cores_per_cluster: 4,
+        threads_per_core: 2,
+    };
+
+    #[test]
+    fn core_position_multithreaded() {
+        assert_eq!(TOPOLOGY.core_count(), 16);
+        assert_eq!(
+            TOPOLOGY.core_position(MpidrEl1::MT | MpidrEl1::from_bits_retain(0x00_0000)),
+            Some(0)
+        );
+        assert_eq!(
+            TOPOLOGY.core_position(MpidrEl1::MT | MpidrEl1::from_bits_retain(0x01_0301)),
+            Some(15)
+        );
+        assert_eq!(
+            TOPOLOGY.core_position(MpidrEl1::MT | MpidrEl1::from_bits_retain(0x00_0201)),
+            Some(5)
+        );
+        assert_eq!(
+            TOPOLOGY.core_position(MpidrEl1::MT | MpidrEl1::from_bits_retain(0x00_0002)),
+            None
+        );
+        assert_eq!(
+            TOPOLOGY.core_position(MpidrEl1::MT | MpidrEl1::from_bits_retain(0x02_0000)),
+            None
+        );
+    }
+
+    #[test]
+    fn core_position_single_threaded() {
+        assert_eq!(
+            TOPOLOGY.core_position(MpidrEl1::from_bits_retain(0x0103)),
+            Some(14)
+        );
+        assert!(TOPOLOGY.mpidr_is_valid(MpidrEl1::from_bits_retain(0x0002)));
+        assert!(!TOPOLOGY.mpidr_is_valid(MpidrEl1::from_bits_retain(0x0004)));
+        assert!(!TOPOLOGY.mpidr_is_valid(MpidrEl1::from_bits_retain(0x01_0000)));
+        assert!(!TOPOLOGY.mpidr_is_valid(MpidrEl1::from_bits_retain(0x01_0000_0000)));
+    }
+
+    #[test]
+    fn indices_unique_and_dense() {
+        let mut seen = [false; 16];
+        for cluster in 0..2 {
+            for core in 0..4 {
+                for thread in 0..2 {
+                    let mpidr = MpidrEl1::MT
+                        | MpidrEl1::from_bits_retain(cluster << 16 | core << 8 | thread);
+                    let index = TOPOLOGY.core_position(mpidr).unwrap();
+                    assert!(!seen[index]);
+                    seen[index] = true;
+                }
+            }
+        }
+        assert!(seen.iter().all(|&seen| seen));
+    }
+
+    /// Clusters 0 and 2 of two cores each with cluster 1 missing, then a cluster with a single core
+    /// in a second Aff2 group.
+    const SPARSE_MPIDRS: [MpidrEl1; 5] = [
+        MpidrEl1::from_bits_retain(0x00_0000),
+        MpidrEl1::from_bits_retain(0x00_0001),
+        MpidrEl1::from_bits_retain(0x00_0200),
+        MpidrEl1::from_bits_retain(0x00_0201),
+        MpidrEl1::from_bits_retain(0x01_0000),
+    ];
+    const SPARSE: SparseTopology = SparseTopology::new(&SPARSE_MPIDRS);
+
+    #[test]
+    fn sparse_core_position() {
+        assert_eq!(SPARSE.core_count(), 5);
+        assert_eq!(
+            SPARSE.core_position(MpidrEl1::from_bits_retain(0x0000)),
+            Some(0)
+        );
+        assert_eq!(
+            SPARSE.core_position(MpidrEl1::from_bits_retain(0x0201)),
+            Some(3)
+        );
+        assert_eq!(
+            SPARSE.core_position(MpidrEl1::from_bits_retain(0x01_0000)),
+            Some(4)
+        );
+        assert_eq!(
+            SPARSE.core_position(MpidrEl1::U | MpidrEl1::from_bits_retain(0x0200)),
+            Some(2)
+        );
+        assert_eq!(
+            SPARSE.core_position(MpidrEl1::from_bits_retain(0x0100)),
+            None
+        );
+        assert!(!SPARSE.mpidr_is_valid(MpidrEl1::from_bits_retain(0x0002)));
+        assert_eq!(SPARSE.mpidr(3), MpidrEl1::from_bits_retain(0x0201));
+    }
+
+    #[test]
+    fn sparse_power_domain_topology() {
+        assert_eq!(SPARSE.power_domain_topology::<5>(1), [1, 3, 2, 2, 1]);
+        assert_eq!(SPARSE.power_domain_topology::<7>(2), [1, 2, 2, 1, 2, 2, 1]);
+        assert_eq!(
+            SparseTopology::new(&crate::platform::test::TestPlatform::MPIDR_VALUES)
+                .power_domain_topology::<8>(2),
+            [1, 2, 2, 2, 3, 3, 3, 4]
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "sorted and unique")]
+    fn sparse_unsorted() {
+        SparseTopology::new(&[
+            MpidrEl1::from_bits_retain(0x0100),
+            MpidrEl1::from_bits_retain(0x0000),
+        ]);
+    }
+}
@@ -0,0 +1,215 @@
+// Copyright The Rusted Firmware-A Contributors.
+//
+// SPDX-License-Identifier: BSD-3-Clause
+
+//! Performance Measurement Framework, which captures generic timer timestamps at points of interest
+//! so that boot and runtime latency can be measured without a debugger.
+//!
+//! The primary core records when it reaches each [`BootStage`] of cold boot, and each core records
+//! when it last entered and left the PSCI service. The normal world can read them back through
+//! vendor-specific EL3 monitor SMCs.
+//!
+//! With the `rt_instr` feature each core also records when it last entered and left a low power
+//! state and flushed its caches in `CPU_SUSPEND`, equivalent to TF-A's runtime instrumentation. These
+//! are read back through the TF-A compatible PMF SMCs of the [`rt_instr`](crate::services::rt_instr)
+//! service.
+
+use crate::{
+    services::vendor::VendorHandler,
+    smccc::{FunctionId, INVALID_PARAMETER, NOT_SUPPORTED, SUCCESS, SetFrom, SmcReturn},
+    timer,
+};
+use core::{
+    ops::RangeInclusive,
+    sync::atomic::{AtomicU64, Ordering::Relaxed},
+};
+use num_enum::TryFromPrimitive;
+
+const PMF_BOOT_TIMESTAMP: u32 = 0xC700_0030;
+const PMF_PSCI_TIMESTAMPS: u32 = 0xC700_0031;
+
+/// A stage of cold boot at which the primary core records a timestamp.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, TryFromPrimitive)]
+#[repr(u64)]
+pub enum BootStage {
+    /// Entry to BL31's Rust code.
+    Bl31Entry = 0,
+    /// The runtime page table has been set up.
+    PageTableInit = 1,
+    /// The GIC has been configured.
+    GicInit = 2,
+    /// Just before the primary core first returns to a lower EL.
+    FirstEret = 3,
+}
+
+impl BootStage {
+    const COUNT: usize = 4;
+}
+
+/// A point in the PSCI service at which each core records a timestamp.
+///
+/// The values match TF-A's runtime instrumentation timestamp IDs.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, TryFromPrimitive)]
+#[repr(u16)]
+pub enum PsciTimestamp {
+    /// The PSCI service started handling an SMC.
+    Entry = 0,
+    /// The PSCI service finished handling an SMC, or a core finished waking up from a powerdown
+    /// suspend.
+    Exit = 1,
+    /// The core is about to execute the WFI which enters a low power state.
+    #[cfg(feature = "rt_instr")]
+    EnterLowPower = 2,
+    /// The core woke up from a low power state, either after its WFI or on warm boot.
+    #[cfg(feature = "rt_instr")]
+    ExitLowPower = 3,
+    /// The core is about to flush its caches before powering down.
+    #[cfg(feature = "rt_instr")]
+    EnterCacheFlush = 4,
+    /// The core finished flushing its caches before powering down.
+    #[cfg(feature = "rt_instr")]
+    ExitCacheFlush = 5,
+}
+
+impl PsciTimestamp {
+    #[cfg(not(feature = "rt_instr"))]
+    const COUNT: usize = 2;
+    #[cfg(feature = "rt_instr")]
+    const COUNT: usize = 6;
+}
+
+/// Timestamps captured during boot and at runtime.
+///
+/// A timestamp of 0 means that it hasn't been recorded yet.
+#[derive(Debug)]
+pub struct Pmf<const CORE_COUNT: usize> {
+    boot: [AtomicU64; BootStage::COUNT],
+    psci: [[AtomicU64; PsciTimestamp::COUNT]; CORE_COUNT],
+}
+
+impl<const CORE_COUNT: usize> Pmf<CORE_COUNT> {
+    /// Creates a new set of timestamps, none of which have been recorded.
+    pub const fn new() -> Self {
+        Self {
+            boot: [const { AtomicU64::new(0) }; BootStage::COUNT],
+            psci: [const { [const { AtomicU64::new(0) }; PsciTimestamp::COUNT] }; CORE_COUNT],
+        }
+    }
+
+    /// Records that cold boot reached the given stage at `timestamp`, as returned by
+    /// [`timer::counter`].
+    pub fn record_boot(&self, stage: BootStage, timestamp: u64) {
+        self.boot[stage as usize].store(timestamp, Relaxed);
+    }
+
+    /// Returns the timestamp at which cold boot reached the given stage.
+    pub fn boot_timestamp(&self, stage: BootStage) -> u64 {
+        self.boot[stage as usize].load(Relaxed)
+    }
+
+    /// Records the current time as the given PSCI timestamp of the given core.
+    pub fn record_psci(&self, core_index: usize, event: PsciTimestamp) {
+        self.psci[core_index][event as usize].store(timer::counter(), Relaxed);
+    }
+
+    /// Returns the given PSCI timestamp of the given core, or `None` if the core index is out of
+    /// range.
+    pub fn psci_timestamp(&self, core_index: usize, event: PsciTimestamp) -> Option<u64> {
+        Some(self.psci.get(core_index)?[event as usize].load(Relaxed))
+    }
+}
+
+impl<const CORE_COUNT: usize> Default for Pmf<CORE_COUNT> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const CORE_COUNT: usize> VendorHandler for Pmf<CORE_COUNT> {
+    fn function_numbers(&self) -> RangeInclusive<u16> {
+        0x0030..=0x003F
+    }
+
+    fn handle_non_secure_smc(&self, function: FunctionId, regs: &mut SmcReturn) {
+        let arg = regs.values()[1];
+        match function.0 {
+            PMF_BOOT_TIMESTAMP => match BootStage::try_from(arg) {
+                Ok(stage) => regs.set_args2(SUCCESS as u64, self.boot_timestamp(stage)),
+                Err(_) => regs.set_from(INVALID_PARAMETER),
+            },
+            PMF_PSCI_TIMESTAMPS => {
+                let core_index = arg as usize;
+                match (
+                    self.psci_timestamp(core_index, PsciTimestamp::Entry),
+                    self.psci_timestamp(core_index, PsciTimestamp::Exit),
+                ) {
+                    (Some(entry), Some(exit)) => regs.set_args3(SUCCESS as u64, entry, exit),
+                    _ => regs.set_from(INVALID_PARAMETER),
+                }
+            }
+            _ => regs.set_from(NOT_SUPPORTED),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arm_sysregs::{CntpctEl0, fake::SYSREGS};
+
+    fn call(pmf: &Pmf<2>, function: u32, arg: u64) -> SmcReturn {
+        let mut regs = SmcReturn::EMPTY;
+        regs.set_args2(function.into(), arg);
+        pmf.handle_non_secure_smc(FunctionId(function), &mut regs);
+        regs
+    }
+
+    #[test]
+    fn boot_timestamps() {
+        let pmf = Pmf::<2>::new();
+        pmf.record_boot(BootStage::Bl31Entry, 100);
+        pmf.record_boot(BootStage::GicInit, 300);
+
+        assert_eq!(
+            call(&pmf, PMF_BOOT_TIMESTAMP, BootStage::Bl31Entry as u64).values(),
+            [SUCCESS as u64, 100]
+        );
+        assert_eq!(
+            call(&pmf, PMF_BOOT_TIMESTAMP, BootStage::PageTableInit as u64).values(),
+            [SUCCESS as u64, 0]
+        );
+        assert_eq!(
+            call(&pmf, PMF_BOOT_TIMESTAMP, BootStage::GicInit as u64).values(),
+            [SUCCESS as u64, 300]
+        );
+        assert_eq!(
+            call(&pmf, PMF_BOOT_TIMESTAMP, 4).values(),
+            [INVALID_PARAMETER as u64]
+        );
+    }
+
+    #[test]
+    fn psci_timestamps() {
+        let pmf = Pmf::<2>::new();
+
+        SYSREGS.lock().unwrap().cntpct_el0 = CntpctEl0::from_bits_retain(1000);
+        pmf.record_psci(1, PsciTimestamp::Entry);
+        SYSREGS.lock().unwrap().cntpct_el0 = CntpctEl0::from_bits_retain(1200);
+        pmf.record_psci(1, PsciTimestamp::Exit);
+        SYSREGS.lock().unwrap().reset();
+
+        assert_eq!(
+            call(&pmf, PMF_PSCI_TIMESTAMPS, 1).values(),
+            [SUCCESS as u64, 1000, 1200]
+        );
+        assert_eq!(
+            call(&pmf, PMF_PSCI_TIMESTAMPS, 0).values(),
+            [SUCCESS as u64, 0, 0]
+        );
+        assert_eq!(
+            call(&pmf, PMF_PSCI_TIMESTAMPS, 2).values(),
+            [INVALID_PARAMETER as u64]
+        );
+        assert_eq!(call(&pmf, 0xC700_003F, 0).values(), [NOT_SUPPORTED as u64]);
+    }
+}
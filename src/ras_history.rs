@@ -0,0 +1,259 @@
+// Copyright The Rusted Firmware-A Contributors.
+//
+// SPDX-License-Identifier: BSD-3-Clause
+
+//! History of the RAS errors handled at EL3, which the normal world can read back and acknowledge
+//! through vendor-specific EL3 monitor SMCs.
+//!
+//! RF-A doesn't route RAS errors to EL3 itself, so events are recorded through
+//! `Services::ras_error_history` by platform code which does, such as a handler for a fault
+//! handling interrupt in `Board::handle_group0_interrupt`. This lets an OS or management agent
+//! find out about errors which EL3 handled without reporting them in-band with an SError, or whose
+//! SError it missed.
+//!
+//! If the platform sets `Board::RAS_DIAGNOSTICS_PARTITION`, each event is also sent to that secure
+//! partition in a direct message request before the normal world is next entered, with the record
+//! index, syndrome and timestamp in the first three arguments.
+
+use crate::{
+    services::vendor::VendorHandler,
+    smccc::{FunctionId, INVALID_PARAMETER, NOT_SUPPORTED, SUCCESS, SetFrom, SmcReturn},
+    spin_mutex::SpinMutex,
+    timer,
+};
+use arrayvec::ArrayVec;
+use core::ops::RangeInclusive;
+
+const RAS_ERROR_HISTORY_INFO: u32 = 0xC700_0050;
+const RAS_ERROR_HISTORY_GET: u32 = 0xC700_0051;
+const RAS_ERROR_HISTORY_CLEAR: u32 = 0xC700_0052;
+
+/// The maximum number of unacknowledged events which are kept.
+pub const MAX_RAS_ERROR_EVENTS: usize = 16;
+
+/// A RAS error which was handled at EL3.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct RasErrorEvent {
+    /// The generic timer count when the error was recorded.
+    pub timestamp: u64,
+    /// The index of the error record which reported the error.
+    pub record_index: u64,
+    /// A summary of the error syndrome, usually the value of the record's `ERR<n>STATUS`.
+    pub syndrome: u64,
+}
+
+#[derive(Debug)]
+struct History {
+    events: ArrayVec<RasErrorEvent, MAX_RAS_ERROR_EVENTS>,
+    /// The number of events which didn't fit since the normal world last acknowledged any.
+    dropped: u64,
+    /// The number of events at the start of `events` which have been taken for delivery to the
+    /// diagnostics partition.
+    delivered: usize,
+}
+
+/// The RAS errors recorded at EL3 which the normal world hasn't acknowledged yet, oldest first.
+///
+/// Once the history is full further events are counted but not kept, so that the index of each
+/// event stays the same until the normal world acknowledges it.
+#[derive(Debug)]
+pub struct RasErrorHistory {
+    history: SpinMutex<History>,
+}
+
+impl RasErrorHistory {
+    /// Creates an empty history.
+    pub const fn new() -> Self {
+        Self {
+            history: SpinMutex::new(History {
+                events: ArrayVec::new_const(),
+                dropped: 0,
+                delivered: 0,
+            }),
+        }
+    }
+
+    /// Records that the error record with the given index reported an error with the given
+    /// syndrome, timestamped with the current generic timer count.
+    pub fn record(&self, record_index: u64, syndrome: u64) {
+        let event = RasErrorEvent {
+            timestamp: timer::counter(),
+            record_index,
+            syndrome,
+        };
+        let mut history = self.history.lock();
+        if history.events.try_push(event).is_err() {
+            history.dropped += 1;
+        }
+    }
+
+    /// Returns the number of unacknowledged events, and the number which were dropped because the
+    /// history was full.
+    pub fn info(&self) -> (usize, u64) {
+        let history = self.history.lock();
+        (history.events.len(), history.dropped)
+    }
+
+    /// Returns the unacknowledged event with the given index, counting from the oldest.
+    pub fn get(&self, index: usize) -> Option<RasErrorEvent> {
+        self.history.lock().events.get(index).copied()
+    }
+
+    /// Acknowledges the oldest `count` events, removing them from the history and resetting the
+    /// count of dropped events.
+    ///
+    /// Returns false without removing anything if there are fewer than `count` events.
+    pub fn acknowledge(&self, count: usize) -> bool {
+        let mut history = self.history.lock();
+        if count > history.events.len() {
+            return false;
+        }
+        history.events.drain(..count);
+        history.dropped = 0;
+        history.delivered = history.delivered.saturating_sub(count);
+        true
+    }
+
+    /// Returns the oldest event which hasn't been taken for delivery to the diagnostics partition
+    /// yet, and marks it as taken.
+    pub fn take_undelivered(&self) -> Option<RasErrorEvent> {
+        let mut history = self.history.lock();
+        let event = *history.events.get(history.delivered)?;
+        history.delivered += 1;
+        Some(event)
+    }
+}
+
+impl Default for RasErrorHistory {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl VendorHandler for RasErrorHistory {
+    fn function_numbers(&self) -> RangeInclusive<u16> {
+        0x0050..=0x005F
+    }
+
+    fn handle_non_secure_smc(&self, function: FunctionId, regs: &mut SmcReturn) {
+        let arg = regs.values()[1];
+        match function.0 {
+            RAS_ERROR_HISTORY_INFO => {
+                let (count, dropped) = self.info();
+                regs.set_args3(SUCCESS as u64, count as u64, dropped);
+            }
+            RAS_ERROR_HISTORY_GET => match usize::try_from(arg).ok().and_then(|i| self.get(i)) {
+                Some(event) => regs.set_args4(
+                    SUCCESS as u64,
+                    event.timestamp,
+                    event.record_index,
+                    event.syndrome,
+                ),
+                None => regs.set_from(INVALID_PARAMETER),
+            },
+            RAS_ERROR_HISTORY_CLEAR => {
+                if usize::try_from(arg).is_ok_and(|count| self.acknowledge(count)) {
+                    regs.set_from(SUCCESS);
+                } else {
+                    regs.set_from(INVALID_PARAMETER);
+                }
+            }
+            _ => regs.set_from(NOT_SUPPORTED),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arm_sysregs::{CntpctEl0, fake::SYSREGS};
+
+    fn call(history: &RasErrorHistory, function: u32, arg: u64) -> SmcReturn {
+        let mut regs = SmcReturn::EMPTY;
+        regs.set_args2(function.into(), arg);
+        history.handle_non_secure_smc(FunctionId(function), &mut regs);
+        regs
+    }
+
+    #[test]
+    fn query_and_acknowledge() {
+        let history = RasErrorHistory::new();
+        assert_eq!(
+            call(&history, RAS_ERROR_HISTORY_INFO, 0).values(),
+            [SUCCESS as u64, 0, 0]
+        );
+
+        SYSREGS.lock().unwrap().cntpct_el0 = CntpctEl0::from_bits_retain(1000);
+        history.record(3, 0xC400_0000);
+        SYSREGS.lock().unwrap().cntpct_el0 = CntpctEl0::from_bits_retain(1200);
+        history.record(5, 0x8000_0001);
+        SYSREGS.lock().unwrap().reset();
+
+        assert_eq!(
+            call(&history, RAS_ERROR_HISTORY_INFO, 0).values(),
+            [SUCCESS as u64, 2, 0]
+        );
+        assert_eq!(
+            call(&history, RAS_ERROR_HISTORY_GET, 0).values(),
+            [SUCCESS as u64, 1000, 3, 0xC400_0000]
+        );
+        assert_eq!(
+            call(&history, RAS_ERROR_HISTORY_GET, 1).values(),
+            [SUCCESS as u64, 1200, 5, 0x8000_0001]
+        );
+        assert_eq!(
+            call(&history, RAS_ERROR_HISTORY_GET, 2).values(),
+            [INVALID_PARAMETER as u64]
+        );
+
+        assert_eq!(
+            call(&history, RAS_ERROR_HISTORY_CLEAR, 3).values(),
+            [INVALID_PARAMETER as u64]
+        );
+        assert_eq!(
+            call(&history, RAS_ERROR_HISTORY_CLEAR, 1).values(),
+            [SUCCESS as u64]
+        );
+        assert_eq!(
+            call(&history, RAS_ERROR_HISTORY_GET, 0).values(),
+            [SUCCESS as u64, 1200, 5, 0x8000_0001]
+        );
+        assert_eq!(
+            call(&history, 0xC700_005F, 0).values(),
+            [NOT_SUPPORTED as u64]
+        );
+    }
+
+    #[test]
+    fn full_history() {
+        let history = RasErrorHistory::new();
+        for record_index in 0..MAX_RAS_ERROR_EVENTS as u64 + 2 {
+            history.record(record_index, 0);
+        }
+        assert_eq!(history.info(), (MAX_RAS_ERROR_EVENTS, 2));
+        // The oldest events are kept.
+        assert_eq!(history.get(0).unwrap().record_index, 0);
+
+        assert!(history.acknowledge(MAX_RAS_ERROR_EVENTS));
+        assert_eq!(history.info(), (0, 0));
+    }
+
+    #[test]
+    fn delivery() {
+        let history = RasErrorHistory::new();
+        history.record(1, 0);
+        history.record(2, 0);
+        assert_eq!(history.take_undelivered().unwrap().record_index, 1);
+
+        // Acknowledging events doesn't make the remaining ones be delivered again.
+        assert!(history.acknowledge(1));
+        history.record(3, 0);
+        assert_eq!(history.take_undelivered().unwrap().record_index, 2);
+        assert!(history.acknowledge(2));
+        assert_eq!(history.take_undelivered(), None);
+
+        history.record(4, 0);
+        assert_eq!(history.take_undelivered().unwrap().record_index, 4);
+        assert_eq!(history.take_undelivered(), None);
+    }
+}
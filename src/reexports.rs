@@ -11,3 +11,87 @@ pub use arm_sysregs;
 pub use log;
 pub use percore;
 pub use spin;
+
+/// Round-trips random values through the field accessors of the `arm_sysregs` registers which we
+/// rely on, to check that their masks and shifts neither overlap nor truncate.
+#[cfg(test)]
+mod tests {
+    use arm_sysregs::{CacheLevel, CsselrEl1, MidrEl1, SpsrEl3};
+    use proptest::prelude::*;
+
+    proptest! {
+        #[test]
+        fn midr_fields_round_trip(
+            bits: u64,
+            revision in 0..=MidrEl1::REVISION_MASK as u8,
+            partnum in 0..=MidrEl1::PARTNUM_MASK as u16,
+            architecture in 0..=MidrEl1::ARCHITECTURE_MASK as u8,
+            variant in 0..=MidrEl1::VARIANT_MASK as u8,
+            implementer in 0..=MidrEl1::IMPLEMENTER_MASK as u8,
+        ) {
+            let original = MidrEl1::from_bits_retain(bits);
+            let midr = original
+                .with_revision(revision)
+                .with_partnum(partnum)
+                .with_architecture(architecture)
+                .with_variant(variant)
+                .with_implementer(implementer);
+
+            prop_assert_eq!(midr.revision(), revision);
+            prop_assert_eq!(midr.partnum(), partnum);
+            prop_assert_eq!(midr.architecture(), architecture);
+            prop_assert_eq!(midr.variant(), variant);
+            prop_assert_eq!(midr.implementer(), implementer);
+            // Bits outside the fields are untouched.
+            prop_assert_eq!(midr.bits() >> 32, bits >> 32);
+        }
+
+        #[test]
+        fn midr_fields_extract(bits: u64) {
+            let midr = MidrEl1::from_bits_retain(bits);
+
+            prop_assert_eq!(u64::from(midr.revision()), bits & 0xf);
+            prop_assert_eq!(u64::from(midr.partnum()), (bits >> 4) & 0xfff);
+            prop_assert_eq!(u64::from(midr.architecture()), (bits >> 16) & 0xf);
+            prop_assert_eq!(u64::from(midr.variant()), (bits >> 20) & 0xf);
+            prop_assert_eq!(u64::from(midr.implementer()), (bits >> 24) & 0xff);
+        }
+
+
+        #[test]
+        fn spsr_el3_fields_round_trip(
+            bits: u64,
+            m_3_0 in 0..=SpsrEl3::M_3_0_MASK as u8,
+            btype in 0..=SpsrEl3::BTYPE_MASK as u8,
+            ge in 0..=SpsrEl3::GE_MASK as u8,
+        ) {
+            let spsr = SpsrEl3::from_bits_retain(bits)
+                .with_m_3_0(m_3_0)
+                .with_btype(btype)
+                .with_ge(ge);
+
+            prop_assert_eq!(spsr.m_3_0(), m_3_0);
+            prop_assert_eq!(spsr.btype(), btype);
+            prop_assert_eq!(spsr.ge(), ge);
+            let fields = SpsrEl3::M_3_0_MASK << SpsrEl3::M_3_0_SHIFT
+                | SpsrEl3::BTYPE_MASK << SpsrEl3::BTYPE_SHIFT
+                | SpsrEl3::GE_MASK << SpsrEl3::GE_SHIFT;
+            prop_assert_eq!(spsr.bits() & !fields, bits & !fields);
+        }
+
+        #[test]
+        fn csselr_el1_fields_round_trip(level in 1..=7u8, ind: bool, tnd: bool) {
+            let csselr = CsselrEl1::new(tnd, CacheLevel::new(level), ind);
+
+            prop_assert_eq!(csselr.cache_level(), CacheLevel::new(level));
+            prop_assert_eq!(csselr.level(), level - 1);
+            prop_assert_eq!(csselr.contains(CsselrEl1::IND), ind);
+            prop_assert_eq!(csselr.contains(CsselrEl1::TND), tnd && !ind);
+            prop_assert_eq!(
+                CsselrEl1::empty().with_level(csselr.level()).bits(),
+                csselr.bits() & (CsselrEl1::LEVEL_MASK << CsselrEl1::LEVEL_SHIFT)
+            );
+        }
+
+    }
+}
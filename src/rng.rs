@@ -0,0 +1,230 @@
+// Copyright The Rusted Firmware-A Contributors.
+//
+// SPDX-License-Identifier: BSD-3-Clause
+
+//! Access to the architectural random number generator provided by FEAT_RNG.
+//!
+//! `arm-sysregs` doesn't provide `ID_AA64ISAR0_EL1`, `RNDR` or `RNDRRS`, so they are accessed here
+//! with the same API shape, including fakes for unit tests.
+
+/// How many times to retry reading `RNDR` or `RNDRRS` before giving up.
+///
+/// Reads may fail if the hardware couldn't generate a random number in a reasonable time, in which
+/// case software is expected to retry.
+const RNG_RETRIES: usize = 16;
+
+/// No random number could be generated, either because FEAT_RNG isn't implemented or because it
+/// kept failing.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct NoEntropy;
+
+/// The value of the `ID_AA64ISAR0_EL1` system register.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[repr(transparent)]
+pub struct IdAa64isar0El1(u64);
+
+impl IdAa64isar0El1 {
+    /// Offset of the `RNDR` field.
+    pub const RNDR_SHIFT: u32 = 60;
+    /// Mask for the `RNDR` field.
+    pub const RNDR_MASK: u64 = 0b1111;
+
+    /// Creates a new value from the given raw register bits.
+    pub const fn from_bits_retain(bits: u64) -> Self {
+        Self(bits)
+    }
+
+    /// Returns the raw register bits.
+    pub const fn bits(self) -> u64 {
+        self.0
+    }
+
+    /// Returns the value of the `RNDR` field.
+    pub const fn rndr(self) -> u8 {
+        ((self.0 >> Self::RNDR_SHIFT) & Self::RNDR_MASK) as u8
+    }
+
+    /// Returns whether FEAT_RNG is implemented.
+    pub const fn is_feat_rng_present(self) -> bool {
+        self.rndr() >= 1
+    }
+}
+
+/// Returns the value of the `ID_AA64ISAR0_EL1` system register.
+pub fn read_id_aa64isar0_el1() -> IdAa64isar0El1 {
+    IdAa64isar0El1::from_bits_retain(raw::read_id_aa64isar0_el1())
+}
+
+/// Returns whether FEAT_RNG is implemented on this core.
+pub fn is_feat_rng_present() -> bool {
+    read_id_aa64isar0_el1().is_feat_rng_present()
+}
+
+/// Reads a random number from `RNDR`, retrying a few times if the hardware fails to generate one.
+pub fn read_rndr() -> Result<u64, NoEntropy> {
+    retry(raw::try_read_rndr)
+}
+
+/// Reads a random number from `RNDRRS`, which reseeds the generator before generating it, retrying
+/// a few times if the hardware fails to generate one.
+pub fn read_rndrrs() -> Result<u64, NoEntropy> {
+    retry(raw::try_read_rndrrs)
+}
+
+/// Reads a 128-bit random number from `RNDR`, e.g. to use as a pointer authentication key.
+pub fn read_rndr_u128() -> Result<u128, NoEntropy> {
+    let low = read_rndr()?;
+    let high = read_rndr()?;
+    Ok(u128::from(high) << 64 | u128::from(low))
+}
+
+fn retry(mut read: impl FnMut() -> Option<u64>) -> Result<u64, NoEntropy> {
+    if !is_feat_rng_present() {
+        return Err(NoEntropy);
+    }
+    (0..RNG_RETRIES).find_map(|_| read()).ok_or(NoEntropy)
+}
+
+#[cfg(all(target_arch = "aarch64", not(any(test, feature = "fakes"))))]
+mod raw {
+    use core::arch::asm;
+
+    pub fn read_id_aa64isar0_el1() -> u64 {
+        let value;
+        // SAFETY: Reading ID_AA64ISAR0_EL1 has no side effects.
+        unsafe {
+            asm!(
+                "mrs {}, id_aa64isar0_el1",
+                out(reg) value,
+                options(nomem, nostack, preserves_flags),
+            );
+        }
+        value
+    }
+
+    pub fn try_read_rndr() -> Option<u64> {
+        let value: u64;
+        let failed: u64;
+        // SAFETY: Reading RNDR only affects the condition flags, which we tell the compiler about by
+        // not specifying `preserves_flags`. The register is encoded by number so that this doesn't
+        // need the `rand` target feature. Our caller has checked that FEAT_RNG is present.
+        unsafe {
+            asm!(
+                "mrs {value}, s3_3_c2_c4_0",
+                "cset {failed}, eq",
+                value = out(reg) value,
+                failed = out(reg) failed,
+                options(nomem, nostack),
+            );
+        }
+        if failed == 0 { Some(value) } else { None }
+    }
+
+    pub fn try_read_rndrrs() -> Option<u64> {
+        let value: u64;
+        let failed: u64;
+        // SAFETY: As for `try_read_rndr`.
+        unsafe {
+            asm!(
+                "mrs {value}, s3_3_c2_c4_1",
+                "cset {failed}, eq",
+                value = out(reg) value,
+                failed = out(reg) failed,
+                options(nomem, nostack),
+            );
+        }
+        if failed == 0 { Some(value) } else { None }
+    }
+}
+
+#[cfg(not(all(target_arch = "aarch64", not(any(test, feature = "fakes")))))]
+mod raw {
+    use super::fake::with_fake_rng;
+
+    pub fn read_id_aa64isar0_el1() -> u64 {
+        with_fake_rng(|fake| fake.id_aa64isar0_el1.bits())
+    }
+
+    pub fn try_read_rndr() -> Option<u64> {
+        with_fake_rng(|fake| fake.next())
+    }
+
+    pub fn try_read_rndrrs() -> Option<u64> {
+        with_fake_rng(|fake| fake.next())
+    }
+}
+
+/// Fake FEAT_RNG for unit tests.
+#[cfg(not(all(target_arch = "aarch64", not(any(test, feature = "fakes")))))]
+pub mod fake {
+    use super::IdAa64isar0El1;
+    use std::cell::RefCell;
+
+    /// The state of the fake FEAT_RNG.
+    #[derive(Clone, Debug, Eq, PartialEq)]
+    pub struct FakeRng {
+        /// The value returned for `ID_AA64ISAR0_EL1`. FEAT_RNG isn't present by default.
+        pub id_aa64isar0_el1: IdAa64isar0El1,
+        /// The number of upcoming reads of `RNDR` or `RNDRRS` which should fail.
+        pub failures: usize,
+        /// The value returned by the next successful read, which is incremented after each read.
+        pub value: u64,
+    }
+
+    impl FakeRng {
+        const RESET: Self = Self {
+            id_aa64isar0_el1: IdAa64isar0El1::from_bits_retain(0),
+            failures: 0,
+            value: 0,
+        };
+
+        pub(super) fn next(&mut self) -> Option<u64> {
+            if self.failures > 0 {
+                self.failures -= 1;
+                None
+            } else {
+                let value = self.value;
+                self.value = self.value.wrapping_add(1);
+                Some(value)
+            }
+        }
+    }
+
+    std::thread_local! {
+        static FAKE_RNG: RefCell<FakeRng> = const { RefCell::new(FakeRng::RESET) };
+    }
+
+    /// Calls the given function with the fake FEAT_RNG state used instead of the real registers.
+    ///
+    /// Each thread has its own state, so that tests running in parallel don't interfere.
+    pub fn with_fake_rng<R>(f: impl FnOnce(&mut FakeRng) -> R) -> R {
+        FAKE_RNG.with_borrow_mut(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{fake::with_fake_rng, *};
+
+    const RNG_PRESENT: IdAa64isar0El1 = IdAa64isar0El1::from_bits_retain(1 << 60);
+
+    #[test]
+    fn rng_retries() {
+        assert!(!is_feat_rng_present());
+        assert_eq!(read_rndr(), Err(NoEntropy));
+
+        with_fake_rng(|fake| {
+            fake.id_aa64isar0_el1 = RNG_PRESENT;
+            fake.failures = RNG_RETRIES - 1;
+            fake.value = 42;
+        });
+        assert!(is_feat_rng_present());
+        assert_eq!(read_rndrrs(), Ok(42));
+        assert_eq!(read_rndr(), Ok(43));
+
+        with_fake_rng(|fake| fake.failures = RNG_RETRIES);
+        assert_eq!(read_rndr(), Err(NoEntropy));
+        assert_eq!(read_rndr(), Ok(44));
+        assert_eq!(read_rndr_u128(), Ok(46 << 64 | 45));
+    }
+}
@@ -0,0 +1,88 @@
+// Copyright The Rusted Firmware-A Contributors.
+//
+// SPDX-License-Identifier: BSD-3-Clause
+
+//! Data which is written once during cold boot and then made read-only.
+//!
+//! Statics of type [`RoAfterInit`] should be placed in the `.ro_after_init` linker section. This
+//! is mapped read-write while the primary core initialises BL31, and is then remapped read-only by
+//! [`OncePageTable::make_ro_after_init_read_only`](crate::pagetable::OncePageTable::make_ro_after_init_read_only)
+//! before any lower EL runs, so that a memory corruption bug at runtime can't be used to modify
+//! it.
+
+use core::cell::UnsafeCell;
+
+/// A value which is set once during cold boot, and only read afterwards.
+pub struct RoAfterInit<T> {
+    value: UnsafeCell<Option<T>>,
+}
+
+// SAFETY: The value is only written by `init`, whose safety requirements ensure that no other core
+// can access it at the same time. After that, only shared references to it are given out.
+unsafe impl<T: Send + Sync> Sync for RoAfterInit<T> {}
+
+impl<T> RoAfterInit<T> {
+    /// Creates a new, uninitialised value.
+    pub const fn new() -> Self {
+        Self {
+            value: UnsafeCell::new(None),
+        }
+    }
+
+    /// Sets the value.
+    ///
+    /// # Safety
+    ///
+    /// This must only be called once, on the primary core during cold boot, before any other cores
+    /// are started, before `get` is called and before the `.ro_after_init` section is made
+    /// read-only.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the value has already been set.
+    pub unsafe fn init(&self, value: T) {
+        // SAFETY: Our caller guarantees that nothing else is accessing the value at the same time.
+        let slot = unsafe { &mut *self.value.get() };
+        assert!(slot.is_none(), "RoAfterInit value already initialised");
+        *slot = Some(value);
+    }
+
+    /// Returns the value.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the value hasn't been set yet.
+    pub fn get(&self) -> &T {
+        // SAFETY: The value is only written by `init`, which must not be called after `get`.
+        unsafe { &*self.value.get() }
+            .as_ref()
+            .expect("RoAfterInit value used before initialisation")
+    }
+}
+
+impl<T> Default for RoAfterInit<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn init_once() {
+        let value = RoAfterInit::new();
+        // SAFETY: Nothing else has access to `value`, and `get` hasn't been called yet.
+        unsafe {
+            value.init(42);
+        }
+        assert_eq!(*value.get(), 42);
+    }
+
+    #[test]
+    #[should_panic(expected = "used before initialisation")]
+    fn get_before_init() {
+        RoAfterInit::<u32>::new().get();
+    }
+}
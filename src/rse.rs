@@ -0,0 +1,454 @@
+// Copyright The Rusted Firmware-A Contributors.
+//
+// SPDX-License-Identifier: BSD-3-Clause
+
+//! Client for the services provided by a Runtime Security Engine (RSE), such as measured boot and
+//! delegated attestation.
+//!
+//! Requests are PSA client calls, serialised using the RSE communication protocol's embedded
+//! message format and sent over an MHU.
+
+use crate::mhu::{DoorbellReceiver, DoorbellSender, MhuError, MhuLink};
+#[cfg(feature = "rme")]
+use crate::{
+    scrub::{Secret, zeroize},
+    services::rmmd::svc::{EccCurve, RmmCommandReturnCode},
+    spin_mutex::SpinMutex,
+};
+
+/// The maximum size of a serialised request or reply, including headers.
+pub const RSE_COMMS_MAX_MESSAGE_SIZE: usize = 0x1000;
+
+/// The maximum total number of input and output vectors in a PSA call.
+const PSA_MAX_IOVEC: usize = 4;
+
+/// Protocol version for messages with their payload embedded in the message.
+const PROTOCOL_EMBED: u8 = 0;
+
+/// Size of the common header: protocol version, sequence number and client ID.
+const HEADER_SIZE: usize = 4;
+/// Offset of the payload in a request: header, handle, control parameter and I/O sizes.
+const REQUEST_PAYLOAD_OFFSET: usize = HEADER_SIZE + 8 + 2 * PSA_MAX_IOVEC;
+/// Offset of the payload in a reply: header, return value and output sizes.
+const REPLY_PAYLOAD_OFFSET: usize = HEADER_SIZE + 4 + 2 * PSA_MAX_IOVEC;
+
+const RSE_MEASURED_BOOT_HANDLE: i32 = 0x4000_0110;
+const RSE_MEASURED_BOOT_EXTEND: i16 = 1002;
+
+const RSE_DELEGATED_SERVICE_HANDLE: i32 = 0x4000_0111;
+const RSE_DELEGATED_ATTEST_GET_DELEGATED_KEY: i16 = 1001;
+const RSE_DELEGATED_ATTEST_GET_PLATFORM_TOKEN: i16 = 1002;
+
+/// The maximum length of the software type string in a measurement.
+pub const SW_TYPE_MAX_SIZE: usize = 20;
+
+/// PSA ECC family identifier for the SECP R1 curves.
+pub const PSA_ECC_FAMILY_SECP_R1: u8 = 0x12;
+/// PSA algorithm identifier for SHA-256.
+pub const PSA_ALG_SHA_256: u32 = 0x0200_0009;
+/// PSA algorithm identifier for SHA-384.
+pub const PSA_ALG_SHA_384: u32 = 0x0200_000a;
+
+/// An error communicating with the RSE.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum RseError {
+    /// Error from the underlying MHU transport.
+    Mhu(MhuError),
+    /// The request doesn't fit in a message.
+    RequestTooLong,
+    /// The reply was malformed, or didn't match the request.
+    InvalidReply,
+    /// The RSE service returned the given PSA error status.
+    Psa(i32),
+}
+
+impl From<MhuError> for RseError {
+    fn from(e: MhuError) -> Self {
+        Self::Mhu(e)
+    }
+}
+
+#[cfg(feature = "rme")]
+impl From<RseError> for RmmCommandReturnCode {
+    fn from(e: RseError) -> Self {
+        match e {
+            RseError::Mhu(MhuError::MessageTooLong(_)) => Self::NoMemory,
+            _ => Self::Unknown,
+        }
+    }
+}
+
+/// A means of exchanging messages with the RSE.
+pub trait RseTransport {
+    /// Sends the given request and waits for the reply, returning its length.
+    fn exchange(&mut self, request: &[u8], reply: &mut [u8]) -> Result<usize, RseError>;
+}
+
+impl<S: DoorbellSender, R: DoorbellReceiver> RseTransport for MhuLink<S, R> {
+    fn exchange(&mut self, request: &[u8], reply: &mut [u8]) -> Result<usize, RseError> {
+        self.send_message(request)?;
+        Ok(self.receive_message(reply)?)
+    }
+}
+
+/// A measurement to be extended into one of the RSE's measurement slots.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Measurement<'a> {
+    /// The index of the measurement slot.
+    pub slot: u8,
+    /// Whether to lock the slot against further extension.
+    pub lock: bool,
+    /// The PSA algorithm identifier of the hash algorithm used.
+    pub algorithm: u32,
+    /// The software type, at most `SW_TYPE_MAX_SIZE` bytes.
+    pub sw_type: &'a [u8],
+    /// The signer ID of the measured component.
+    pub signer_id: &'a [u8],
+    /// The version of the measured component.
+    pub version: &'a [u8],
+    /// The measurement value itself.
+    pub value: &'a [u8],
+}
+
+/// Client for RSE services.
+pub struct RseClient<T: RseTransport> {
+    transport: T,
+    sequence: u8,
+    buffer: [u8; RSE_COMMS_MAX_MESSAGE_SIZE],
+}
+
+impl<T: RseTransport> RseClient<T> {
+    /// Creates a new client communicating over the given transport.
+    pub const fn new(transport: T) -> Self {
+        Self {
+            transport,
+            sequence: 0,
+            buffer: [0; RSE_COMMS_MAX_MESSAGE_SIZE],
+        }
+    }
+
+    /// Makes a PSA call to the RSE, returning the lengths written to each output vector.
+    fn psa_call(
+        &mut self,
+        handle: i32,
+        call_type: i16,
+        in_vecs: &[&[u8]],
+        out_vecs: &mut [&mut [u8]],
+    ) -> Result<[usize; PSA_MAX_IOVEC], RseError> {
+        assert!(in_vecs.len() + out_vecs.len() <= PSA_MAX_IOVEC);
+
+        let sequence = self.sequence;
+        self.sequence = self.sequence.wrapping_add(1);
+
+        let request_length =
+            REQUEST_PAYLOAD_OFFSET + in_vecs.iter().map(|in_vec| in_vec.len()).sum::<usize>();
+        if request_length > self.buffer.len() {
+            return Err(RseError::RequestTooLong);
+        }
+        let control =
+            (call_type as u16 as u32) << 16 | (in_vecs.len() as u32) << 8 | out_vecs.len() as u32;
+        self.buffer[..REQUEST_PAYLOAD_OFFSET].fill(0);
+        self.buffer[0] = PROTOCOL_EMBED;
+        self.buffer[1] = sequence;
+        self.buffer[4..8].copy_from_slice(&handle.to_le_bytes());
+        self.buffer[8..12].copy_from_slice(&control.to_le_bytes());
+        let sizes = in_vecs
+            .iter()
+            .map(|in_vec| in_vec.len())
+            .chain(out_vecs.iter().map(|out_vec| out_vec.len()));
+        for (i, size) in sizes.enumerate() {
+            let size = u16::try_from(size).map_err(|_| RseError::RequestTooLong)?;
+            self.buffer[12 + 2 * i..14 + 2 * i].copy_from_slice(&size.to_le_bytes());
+        }
+        let mut offset = REQUEST_PAYLOAD_OFFSET;
+        for in_vec in in_vecs {
+            self.buffer[offset..offset + in_vec.len()].copy_from_slice(in_vec);
+            offset += in_vec.len();
+        }
+
+        let (request, reply) = self.buffer.split_at_mut(request_length);
+        let reply_length = self.transport.exchange(request, reply)?;
+        let reply = &reply[..reply_length];
+
+        if reply_length < REPLY_PAYLOAD_OFFSET || reply[0] != PROTOCOL_EMBED || reply[1] != sequence
+        {
+            return Err(RseError::InvalidReply);
+        }
+        let status = i32::from_le_bytes(reply[4..8].try_into().unwrap());
+        if status < 0 {
+            return Err(RseError::Psa(status));
+        }
+        let mut out_sizes = [0; PSA_MAX_IOVEC];
+        let mut offset = REPLY_PAYLOAD_OFFSET;
+        for (i, out_vec) in out_vecs.iter_mut().enumerate() {
+            let size =
+                u16::from_le_bytes(reply[8 + 2 * i..10 + 2 * i].try_into().unwrap()) as usize;
+            let data = reply
+                .get(offset..offset + size)
+                .ok_or(RseError::InvalidReply)?;
+            out_vec
+                .get_mut(..size)
+                .ok_or(RseError::InvalidReply)?
+                .copy_from_slice(data);
+            out_sizes[i] = size;
+            offset += size;
+        }
+        Ok(out_sizes)
+    }
+
+    /// Extends the given measurement into the RSE's measurement slot.
+    pub fn extend_measurement(&mut self, measurement: &Measurement) -> Result<(), RseError> {
+        if measurement.sw_type.len() > SW_TYPE_MAX_SIZE {
+            return Err(RseError::RequestTooLong);
+        }
+        // Packed struct of slot index, lock flag, algorithm, software type and its length.
+        let mut parameters = [0; 2 + 4 + SW_TYPE_MAX_SIZE + 1];
+        parameters[0] = measurement.slot;
+        parameters[1] = measurement.lock.into();
+        parameters[2..6].copy_from_slice(&measurement.algorithm.to_le_bytes());
+        parameters[6..6 + measurement.sw_type.len()].copy_from_slice(measurement.sw_type);
+        parameters[6 + SW_TYPE_MAX_SIZE] = measurement.sw_type.len() as u8;
+
+        self.psa_call(
+            RSE_MEASURED_BOOT_HANDLE,
+            RSE_MEASURED_BOOT_EXTEND,
+            &[
+                &parameters,
+                measurement.signer_id,
+                measurement.version,
+                measurement.value,
+            ],
+            &mut [],
+        )?;
+        Ok(())
+    }
+
+    /// Fetches the public part of the delegated attestation key into the given buffer, returning
+    /// its length.
+    pub fn get_delegated_key(
+        &mut self,
+        ecc_family: u8,
+        key_bits: u32,
+        hash_algorithm: u32,
+        key: &mut [u8],
+    ) -> Result<usize, RseError> {
+        let [length, ..] = self.psa_call(
+            RSE_DELEGATED_SERVICE_HANDLE,
+            RSE_DELEGATED_ATTEST_GET_DELEGATED_KEY,
+            &[
+                &[ecc_family],
+                &key_bits.to_le_bytes(),
+                &hash_algorithm.to_le_bytes(),
+            ],
+            &mut [key],
+        )?;
+        Ok(length)
+    }
+
+    /// Fetches the platform attestation token bound to the given hash of the delegated
+    /// attestation public key into the given buffer, returning its length.
+    pub fn get_platform_token(
+        &mut self,
+        dak_public_key_hash: &[u8],
+        token: &mut [u8],
+    ) -> Result<usize, RseError> {
+        let [length, ..] = self.psa_call(
+            RSE_DELEGATED_SERVICE_HANDLE,
+            RSE_DELEGATED_ATTEST_GET_PLATFORM_TOKEN,
+            &[dak_public_key_hash],
+            &mut [token],
+        )?;
+        Ok(length)
+    }
+
+    /// Reads the Realm Attestation Key into the given buffer, returning the key size on success.
+    ///
+    /// This is suitable for implementing `Platform::read_attestation_key`.
+    #[cfg(feature = "rme")]
+    pub fn read_attestation_key(
+        &mut self,
+        buf: &mut [u8],
+        curve: EccCurve,
+    ) -> Result<usize, RmmCommandReturnCode> {
+        let EccCurve::EccSecp384r1 = curve;
+        Ok(self.get_delegated_key(PSA_ECC_FAMILY_SECP_R1, 384, PSA_ALG_SHA_256, buf)?)
+    }
+}
+
+/// Cache of the platform attestation token fetched from the RSE, so that RMM can read it in
+/// several chunks.
+#[cfg(feature = "rme")]
+pub struct PlatformTokenCache<const N: usize> {
+    token: [u8; N],
+    length: usize,
+}
+
+#[cfg(feature = "rme")]
+impl<const N: usize> PlatformTokenCache<N> {
+    /// Creates a new empty cache.
+    pub const fn new() -> Self {
+        Self {
+            token: [0; N],
+            length: 0,
+        }
+    }
+
+    /// Writes the slice of the platform attestation token starting at `start_index` into `buf`,
+    /// returning the number of bytes written and the number remaining. The token is fetched from
+    /// the RSE when `start_index` is 0.
+    ///
+    /// This is suitable for implementing `Platform::read_attestation_token`.
+    pub fn read<T: RseTransport>(
+        &mut self,
+        client: &mut RseClient<T>,
+        buf: &mut [u8],
+        hash: &[u8],
+        start_index: usize,
+    ) -> Result<(usize, usize), RmmCommandReturnCode> {
+        if start_index == 0 {
+            self.length = 0;
+            self.length = client.get_platform_token(hash, &mut self.token)?;
+        }
+        if start_index > self.length {
+            return Err(RmmCommandReturnCode::InvalidValue);
+        }
+
+        let hunk_size = buf.len().min(self.length - start_index);
+        let end_index = start_index + hunk_size;
+        buf[..hunk_size].copy_from_slice(&self.token[start_index..end_index]);
+
+        Ok((hunk_size, self.length - end_index))
+    }
+
+    /// Discards the cached token, zeroing it.
+    pub fn scrub(&mut self) {
+        zeroize(&mut self.token);
+        self.length = 0;
+    }
+}
+
+/// A shared token cache can be registered with `Secrets` so that the token is zeroed before the
+/// system is turned off or reset.
+#[cfg(feature = "rme")]
+impl<const N: usize> Secret for SpinMutex<PlatformTokenCache<N>> {
+    fn scrub(&self) {
+        self.lock().scrub();
+    }
+}
+
+#[cfg(feature = "rme")]
+impl<const N: usize> Default for PlatformTokenCache<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Fake transport which records the last request and returns a fixed reply, with the sequence
+    /// number patched to match the request.
+    struct FakeTransport {
+        request: Vec<u8>,
+        reply: Vec<u8>,
+    }
+
+    impl FakeTransport {
+        fn new(status: i32, outputs: &[&[u8]]) -> Self {
+            let mut reply = vec![PROTOCOL_EMBED, 0, 0, 0];
+            reply.extend_from_slice(&status.to_le_bytes());
+            for i in 0..PSA_MAX_IOVEC {
+                let size = outputs.get(i).map_or(0, |output| output.len()) as u16;
+                reply.extend_from_slice(&size.to_le_bytes());
+            }
+            for output in outputs {
+                reply.extend_from_slice(output);
+            }
+            Self {
+                request: Vec::new(),
+                reply,
+            }
+        }
+    }
+
+    impl RseTransport for FakeTransport {
+        fn exchange(&mut self, request: &[u8], reply: &mut [u8]) -> Result<usize, RseError> {
+            self.request = request.to_vec();
+            reply[..self.reply.len()].copy_from_slice(&self.reply);
+            reply[1] = request[1];
+            Ok(self.reply.len())
+        }
+    }
+
+    #[test]
+    fn extend_measurement() {
+        let mut client = RseClient::new(FakeTransport::new(0, &[]));
+        client
+            .extend_measurement(&Measurement {
+                slot: 3,
+                lock: true,
+                algorithm: PSA_ALG_SHA_256,
+                sw_type: b"BL31",
+                signer_id: &[0xaa; 2],
+                version: b"1.0",
+                value: &[0x55; 4],
+            })
+            .unwrap();
+
+        let request = &client.transport.request;
+        assert_eq!(request[..4], [PROTOCOL_EMBED, 0, 0, 0]);
+        assert_eq!(request[4..8], RSE_MEASURED_BOOT_HANDLE.to_le_bytes());
+        assert_eq!(request[8..12], [0, 4, 0xea, 0x03]);
+        assert_eq!(request[12..20], [27, 0, 2, 0, 3, 0, 4, 0]);
+        assert_eq!(request[20..26], [3, 1, 0x09, 0, 0, 0x02]);
+        assert_eq!(request[26..30], *b"BL31");
+        assert_eq!(request[46], 4);
+        assert_eq!(
+            request[47..],
+            [0xaa, 0xaa, b'1', b'.', b'0', 0x55, 0x55, 0x55, 0x55]
+        );
+    }
+
+    #[test]
+    fn get_platform_token() {
+        let mut client = RseClient::new(FakeTransport::new(0, &[&[1, 2, 3]]));
+        let mut token = [0; 8];
+        assert_eq!(client.get_platform_token(&[0x11; 32], &mut token), Ok(3));
+        assert_eq!(token, [1, 2, 3, 0, 0, 0, 0, 0]);
+        assert_eq!(
+            client.get_platform_token(&[0x11; 32], &mut token[..2]),
+            Err(RseError::InvalidReply)
+        );
+
+        let mut client = RseClient::new(FakeTransport::new(-135, &[]));
+        assert_eq!(
+            client.get_platform_token(&[0x11; 32], &mut token),
+            Err(RseError::Psa(-135))
+        );
+    }
+
+    #[cfg(feature = "rme")]
+    #[test]
+    fn read_token_in_chunks() {
+        let mut client = RseClient::new(FakeTransport::new(0, &[&[1, 2, 3, 4, 5]]));
+        let mut cache = PlatformTokenCache::<8>::new();
+        let mut buf = [0; 3];
+
+        assert_eq!(cache.read(&mut client, &mut buf, &[0; 32], 0), Ok((3, 2)));
+        assert_eq!(buf, [1, 2, 3]);
+        assert_eq!(cache.read(&mut client, &mut buf, &[], 3), Ok((2, 0)));
+        assert_eq!(buf[..2], [4, 5]);
+        assert_eq!(
+            cache.read(&mut client, &mut buf, &[], 6),
+            Err(RmmCommandReturnCode::InvalidValue)
+        );
+
+        cache.scrub();
+        assert_eq!(cache.token, [0; 8]);
+        assert_eq!(
+            cache.read(&mut client, &mut buf, &[], 3),
+            Err(RmmCommandReturnCode::InvalidValue)
+        );
+    }
+}
@@ -0,0 +1,132 @@
+// Copyright The Rusted Firmware-A Contributors.
+//
+// SPDX-License-Identifier: BSD-3-Clause
+
+//! Tunables which are chosen once during cold boot, rather than at compile time.
+//!
+//! The platform builds a [`RuntimeConfig`] from whatever its earlier boot stages passed in, such as
+//! FW_CONFIG or a transfer list, and it is then fixed for the rest of the lifetime of BL31.
+
+use crate::{
+    context::{CPU_DATA_CONTEXT_NUM, PerWorld},
+    cpu_extensions::id_registers::IdFeatures,
+    services::psci::PsciPlatformOptionalFeatures,
+};
+use log::LevelFilter;
+use spin::Once;
+
+static RUNTIME_CONFIG: Once<RuntimeConfig> = Once::new();
+
+/// Which console BL31 should use for its runtime logs.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ConsoleSelection {
+    /// The console which is shared with the normal world.
+    Shared,
+    /// A console which is only accessible to the secure world.
+    SecureOnly,
+}
+
+/// Configuration fixed during cold boot which services consult at runtime.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct RuntimeConfig {
+    /// The maximum level of log messages to output.
+    ///
+    /// This can't enable messages above `log::STATIC_MAX_LEVEL`, as they are compiled out.
+    pub log_level: LevelFilter,
+    /// Which console to use for runtime logs.
+    pub console: ConsoleSelection,
+    /// Whether there is an SPMC in the secure world.
+    ///
+    /// If not, the secure world is never entered and FF-A calls from the normal world are not
+    /// supported, unless they are handled by the SPMC at EL3. This must be false if the
+    /// `spmc_el3` feature is enabled.
+    pub spmc_present: bool,
+    /// Whether to boot the RMM, if RME support is built in.
+    pub rme_enabled: bool,
+    /// Optional PSCI features to offer.
+    ///
+    /// Features which the platform's PSCI implementation doesn't support are never offered,
+    /// regardless of this mask.
+    pub psci_features: PsciPlatformOptionalFeatures,
+    /// Whether to measure the latency added by EL3 to interrupt handling.
+    ///
+    /// This reads the generic timer on entry and exit of each interrupt, and exposes the results
+    /// through the debug service.
+    pub measure_interrupt_latency: bool,
+    /// Features to hide from each world's view of the ID registers.
+    ///
+    /// If any features are hidden from a world, its reads of the ID group 3 registers are trapped
+    /// to EL3 and emulated. Features which the platform's CPU extensions don't make available to
+    /// the world are then also hidden.
+    pub hidden_id_features: PerWorld<IdFeatures>,
+}
+
+impl RuntimeConfig {
+    /// The configuration used if the platform doesn't provide one, matching the behaviour of BL31
+    /// before these options were configurable.
+    pub const DEFAULT: Self = Self {
+        log_level: log::STATIC_MAX_LEVEL,
+        console: ConsoleSelection::Shared,
+        spmc_present: !cfg!(feature = "spmc_el3"),
+        rme_enabled: true,
+        psci_features: PsciPlatformOptionalFeatures::all(),
+        measure_interrupt_latency: false,
+        hidden_id_features: PerWorld([IdFeatures::empty(); CPU_DATA_CONTEXT_NUM]),
+    };
+
+    /// Returns the optional PSCI features to offer, given those supported by the platform.
+    pub fn psci_features(
+        &self,
+        supported: PsciPlatformOptionalFeatures,
+    ) -> PsciPlatformOptionalFeatures {
+        supported & self.psci_features
+    }
+}
+
+/// Sets the runtime configuration, and returns a reference to it.
+///
+/// This must be called exactly once, during cold boot before any services are initialised.
+pub fn init(config: RuntimeConfig) -> &'static RuntimeConfig {
+    let mut initialised = false;
+    let config = RUNTIME_CONFIG.call_once(|| {
+        initialised = true;
+        config
+    });
+    assert!(initialised, "Runtime configuration already initialised");
+    config
+}
+
+/// Returns the runtime configuration.
+///
+/// Before [`init`] has been called this returns [`RuntimeConfig::DEFAULT`].
+pub fn runtime_config() -> &'static RuntimeConfig {
+    RUNTIME_CONFIG.get().unwrap_or(&RuntimeConfig::DEFAULT)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_offers_all_supported_psci_features() {
+        let supported = PsciPlatformOptionalFeatures::SYSTEM_SUSPEND
+            | PsciPlatformOptionalFeatures::OS_INITIATED_MODE;
+        assert_eq!(RuntimeConfig::DEFAULT.psci_features(supported), supported);
+    }
+
+    #[test]
+    fn psci_features_masked() {
+        let config = RuntimeConfig {
+            psci_features: PsciPlatformOptionalFeatures::SYSTEM_SUSPEND
+                | PsciPlatformOptionalFeatures::MEM_PROTECT,
+            ..RuntimeConfig::DEFAULT
+        };
+        assert_eq!(
+            config.psci_features(
+                PsciPlatformOptionalFeatures::SYSTEM_SUSPEND
+                    | PsciPlatformOptionalFeatures::OS_INITIATED_MODE
+            ),
+            PsciPlatformOptionalFeatures::SYSTEM_SUSPEND
+        );
+    }
+}
@@ -0,0 +1,527 @@
+// Copyright The Rusted Firmware-A Contributors.
+//
+// SPDX-License-Identifier: BSD-3-Clause
+
+//! Client for the Arm System Control and Management Interface (SCMI), used to ask a System
+//! Control Processor (SCP) to manage power.
+//!
+//! Commands are exchanged through a shared memory area in the format of the SCMI Shared Memory
+//! Transport (SMT), and the SCP is notified of each command by ringing an MHU doorbell. Responses
+//! are polled for, as EL3 doesn't take the completion interrupt.
+//!
+//! The base, power domain management and system power management protocols are supported.
+
+pub mod psci;
+
+use crate::{
+    mhu::{DoorbellReceiver, DoorbellSender, MhuError, MhuLink},
+    timer::poll_until,
+};
+use safe_mmio::{UniqueMmioPointer, field, field_shared, fields::ReadPureWrite};
+use zerocopy::{FromBytes, Immutable, IntoBytes, KnownLayout};
+
+/// The maximum number of 32-bit words in a command or response payload.
+pub const SCMI_MAX_PAYLOAD_WORDS: usize = 32;
+
+/// How long to wait for the SCP to free the channel or respond to a command.
+const SCMI_TIMEOUT_MS: u64 = 100;
+
+/// The base protocol, for discovery of the platform and the other protocols.
+const PROTOCOL_BASE: u8 = 0x10;
+/// The power domain management protocol.
+const PROTOCOL_POWER_DOMAIN: u8 = 0x11;
+/// The system power management protocol.
+const PROTOCOL_SYSTEM_POWER: u8 = 0x12;
+
+/// Command common to all protocols, returning the protocol version.
+const MSG_PROTOCOL_VERSION: u8 = 0x0;
+/// Power domain management command to set the power state of a domain.
+const MSG_POWER_STATE_SET: u8 = 0x4;
+/// Power domain management command to get the power state of a domain.
+const MSG_POWER_STATE_GET: u8 = 0x5;
+/// System power management command to shut down, reset or suspend the system.
+const MSG_SYSTEM_POWER_STATE_SET: u8 = 0x3;
+
+/// `channel_status` bit set by the SCP when the channel is free for the agent to use.
+const SMT_STATUS_FREE: u32 = 1 << 0;
+/// `channel_status` bit set by the SCP when the channel has an error.
+const SMT_STATUS_ERROR: u32 = 1 << 1;
+
+const HEADER_MESSAGE_ID_MASK: u32 = 0xff;
+const HEADER_PROTOCOL_ID_SHIFT: u32 = 10;
+const HEADER_PROTOCOL_ID_MASK: u32 = 0xff;
+const HEADER_TOKEN_SHIFT: u32 = 18;
+const HEADER_TOKEN_MASK: u32 = 0x3ff;
+
+/// A status code returned by the SCP in response to a command.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ScmiStatus {
+    /// The command or protocol isn't supported.
+    NotSupported,
+    /// A parameter was invalid.
+    InvalidParameters,
+    /// The agent isn't allowed to make the request.
+    Denied,
+    /// The requested entity doesn't exist.
+    NotFound,
+    /// A parameter was out of range.
+    OutOfRange,
+    /// The platform is busy.
+    Busy,
+    /// There was an error in the communication channel.
+    CommsError,
+    /// Some other error occurred.
+    GenericError,
+    /// There was a hardware error.
+    HardwareError,
+    /// The command violated the protocol.
+    ProtocolError,
+    /// A status code not defined by the specification.
+    Other(i32),
+}
+
+impl ScmiStatus {
+    /// Converts the given raw status to a `Result`, with `Ok` for `SUCCESS`.
+    fn check(status: i32) -> Result<(), Self> {
+        Err(match status {
+            0 => return Ok(()),
+            -1 => Self::NotSupported,
+            -2 => Self::InvalidParameters,
+            -3 => Self::Denied,
+            -4 => Self::NotFound,
+            -5 => Self::OutOfRange,
+            -6 => Self::Busy,
+            -7 => Self::CommsError,
+            -8 => Self::GenericError,
+            -9 => Self::HardwareError,
+            -10 => Self::ProtocolError,
+            status => Self::Other(status),
+        })
+    }
+}
+
+/// An error communicating with the SCP over SCMI.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ScmiError {
+    /// Error from the underlying MHU doorbell.
+    Mhu(MhuError),
+    /// The SCP didn't free the channel or respond in time.
+    TimedOut,
+    /// The SCP flagged an error on the channel.
+    ChannelError,
+    /// The response was malformed, or didn't match the command.
+    InvalidResponse,
+    /// The command failed with the given status.
+    Status(ScmiStatus),
+}
+
+impl From<MhuError> for ScmiError {
+    fn from(e: MhuError) -> Self {
+        Self::Mhu(e)
+    }
+}
+
+impl From<ScmiStatus> for ScmiError {
+    fn from(status: ScmiStatus) -> Self {
+        Self::Status(status)
+    }
+}
+
+/// Builds the header of an SCMI command message.
+const fn message_header(protocol_id: u8, message_id: u8, token: u16) -> u32 {
+    (message_id as u32 & HEADER_MESSAGE_ID_MASK)
+        | (protocol_id as u32 & HEADER_PROTOCOL_ID_MASK) << HEADER_PROTOCOL_ID_SHIFT
+        | (token as u32 & HEADER_TOKEN_MASK) << HEADER_TOKEN_SHIFT
+}
+
+/// A means of exchanging SCMI messages with the SCP.
+pub trait ScmiTransport {
+    /// Sends a command with the given header and payload, waits for the response, and copies its
+    /// payload into `response`, returning the number of words copied.
+    fn exchange(
+        &mut self,
+        header: u32,
+        payload: &[u32],
+        response: &mut [u32],
+    ) -> Result<usize, ScmiError>;
+}
+
+/// A shared memory area in the format of the SCMI Shared Memory Transport.
+#[derive(FromBytes, Immutable, IntoBytes, KnownLayout)]
+#[repr(C, align(4))]
+pub struct ScmiSharedMemory {
+    /// 0x00
+    reserved_00: u32,
+    /// 0x04: Channel status, written by the SCP.
+    channel_status: ReadPureWrite<u32>,
+    /// 0x08 - 0x0C
+    reserved_08: [u32; 2],
+    /// 0x10: Channel flags, for enabling the completion interrupt.
+    channel_flags: ReadPureWrite<u32>,
+    /// 0x14: Length in bytes of the message header and payload.
+    length: ReadPureWrite<u32>,
+    /// 0x18: Message header.
+    message_header: ReadPureWrite<u32>,
+    /// 0x1C: Message payload.
+    payload: [ReadPureWrite<u32>; SCMI_MAX_PAYLOAD_WORDS],
+}
+
+/// An SCMI transport over a shared memory area, with an MHU doorbell to notify the SCP of
+/// commands.
+pub struct MhuScmiTransport<'a, S: DoorbellSender, R: DoorbellReceiver> {
+    shared_memory: UniqueMmioPointer<'a, ScmiSharedMemory>,
+    link: MhuLink<S, R>,
+    channel: usize,
+}
+
+impl<'a, S: DoorbellSender, R: DoorbellReceiver> MhuScmiTransport<'a, S, R> {
+    /// Creates a new transport over the given shared memory area, which rings the doorbell on the
+    /// given channel of the MHU link to send each command.
+    pub fn new(
+        shared_memory: UniqueMmioPointer<'a, ScmiSharedMemory>,
+        link: MhuLink<S, R>,
+        channel: usize,
+    ) -> Self {
+        Self {
+            shared_memory,
+            link,
+            channel,
+        }
+    }
+
+    /// Returns whether the SCP has freed the channel.
+    fn channel_free(&self) -> bool {
+        field_shared!(self.shared_memory, channel_status).read() & SMT_STATUS_FREE != 0
+    }
+
+    /// Writes a command to the shared memory and marks the channel as busy.
+    fn write_command(&mut self, header: u32, payload: &[u32]) -> Result<(), ScmiError> {
+        if payload.len() > SCMI_MAX_PAYLOAD_WORDS {
+            return Err(ScmiError::InvalidResponse);
+        }
+        field!(self.shared_memory, message_header).write(header);
+        let mut payload_words = field!(self.shared_memory, payload);
+        for (i, word) in payload.iter().enumerate() {
+            payload_words.get(i).unwrap().write(*word);
+        }
+        field!(self.shared_memory, length).write(((1 + payload.len()) * size_of::<u32>()) as u32);
+        // Poll for completion rather than taking an interrupt.
+        field!(self.shared_memory, channel_flags).write(0);
+        field!(self.shared_memory, channel_status).write(0);
+        Ok(())
+    }
+
+    /// Reads the response to the command with the given header from the shared memory.
+    fn read_response(&self, header: u32, response: &mut [u32]) -> Result<usize, ScmiError> {
+        if field_shared!(self.shared_memory, channel_status).read() & SMT_STATUS_ERROR != 0 {
+            return Err(ScmiError::ChannelError);
+        }
+        if field_shared!(self.shared_memory, message_header).read() != header {
+            return Err(ScmiError::InvalidResponse);
+        }
+        let length = field_shared!(self.shared_memory, length).read() as usize;
+        let word_count = (length / size_of::<u32>())
+            .checked_sub(1)
+            .filter(|&count| count <= SCMI_MAX_PAYLOAD_WORDS)
+            .ok_or(ScmiError::InvalidResponse)?;
+        let word_count = word_count.min(response.len());
+        let payload = field_shared!(self.shared_memory, payload);
+        for (i, word) in response[..word_count].iter_mut().enumerate() {
+            *word = payload.get(i).unwrap().read();
+        }
+        Ok(word_count)
+    }
+}
+
+impl<S: DoorbellSender, R: DoorbellReceiver> ScmiTransport for MhuScmiTransport<'_, S, R> {
+    fn exchange(
+        &mut self,
+        header: u32,
+        payload: &[u32],
+        response: &mut [u32],
+    ) -> Result<usize, ScmiError> {
+        poll_until(SCMI_TIMEOUT_MS, || self.channel_free()).map_err(|_| ScmiError::TimedOut)?;
+        self.write_command(header, payload)?;
+        self.link.notify(self.channel, 1)?;
+        poll_until(SCMI_TIMEOUT_MS, || self.channel_free()).map_err(|_| ScmiError::TimedOut)?;
+        self.read_response(header, response)
+    }
+}
+
+/// A system power state which can be requested with [`ScmiClient::system_power_state_set`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[repr(u32)]
+pub enum SystemPowerState {
+    /// Power off the system.
+    Shutdown = 0,
+    /// Reset the whole system, as for a power-on reset.
+    ColdReset = 1,
+    /// Reset the system, preserving some state.
+    WarmReset = 2,
+    /// Suspend the system.
+    Suspend = 4,
+}
+
+/// Client for SCMI services provided by the SCP.
+pub struct ScmiClient<T: ScmiTransport> {
+    transport: T,
+    token: u16,
+}
+
+impl<T: ScmiTransport> ScmiClient<T> {
+    /// Creates a new client communicating over the given transport.
+    pub const fn new(transport: T) -> Self {
+        Self {
+            transport,
+            token: 0,
+        }
+    }
+
+    /// Sends the given command and returns the response payload after the status, which must be
+    /// `SUCCESS`.
+    fn command<const N: usize>(
+        &mut self,
+        protocol_id: u8,
+        message_id: u8,
+        payload: &[u32],
+    ) -> Result<[u32; N], ScmiError> {
+        let token = self.token;
+        self.token = (self.token + 1) & HEADER_TOKEN_MASK as u16;
+
+        let mut response = [0; SCMI_MAX_PAYLOAD_WORDS];
+        let length = self.transport.exchange(
+            message_header(protocol_id, message_id, token),
+            payload,
+            &mut response,
+        )?;
+        let (&status, values) = response[..length]
+            .split_first()
+            .ok_or(ScmiError::InvalidResponse)?;
+        ScmiStatus::check(status as i32)?;
+        values
+            .get(..N)
+            .ok_or(ScmiError::InvalidResponse)
+            .map(|values| values.try_into().unwrap())
+    }
+
+    /// Returns the version of the base protocol implemented by the SCP.
+    pub fn base_protocol_version(&mut self) -> Result<u32, ScmiError> {
+        let [version] = self.command(PROTOCOL_BASE, MSG_PROTOCOL_VERSION, &[])?;
+        Ok(version)
+    }
+
+    /// Returns the version of the power domain management protocol implemented by the SCP.
+    pub fn power_domain_protocol_version(&mut self) -> Result<u32, ScmiError> {
+        let [version] = self.command(PROTOCOL_POWER_DOMAIN, MSG_PROTOCOL_VERSION, &[])?;
+        Ok(version)
+    }
+
+    /// Returns the version of the system power management protocol implemented by the SCP.
+    pub fn system_power_protocol_version(&mut self) -> Result<u32, ScmiError> {
+        let [version] = self.command(PROTOCOL_SYSTEM_POWER, MSG_PROTOCOL_VERSION, &[])?;
+        Ok(version)
+    }
+
+    /// Sets the power state of the given power domain, and waits for the SCP to accept the
+    /// request.
+    ///
+    /// The state is in a format defined by the platform. If `asynchronous` is set, the SCP may
+    /// respond before the state change has completed.
+    pub fn power_state_set(
+        &mut self,
+        domain_id: u32,
+        power_state: u32,
+        asynchronous: bool,
+    ) -> Result<(), ScmiError> {
+        let [] = self.command(
+            PROTOCOL_POWER_DOMAIN,
+            MSG_POWER_STATE_SET,
+            &[asynchronous.into(), domain_id, power_state],
+        )?;
+        Ok(())
+    }
+
+    /// Returns the current power state of the given power domain.
+    pub fn power_state_get(&mut self, domain_id: u32) -> Result<u32, ScmiError> {
+        let [power_state] =
+            self.command(PROTOCOL_POWER_DOMAIN, MSG_POWER_STATE_GET, &[domain_id])?;
+        Ok(power_state)
+    }
+
+    /// Asks the SCP to change the system power state.
+    ///
+    /// If `graceful` is set, the SCP may notify other agents and wait for them to prepare before
+    /// changing the state.
+    pub fn system_power_state_set(
+        &mut self,
+        state: SystemPowerState,
+        graceful: bool,
+    ) -> Result<(), ScmiError> {
+        let [] = self.command(
+            PROTOCOL_SYSTEM_POWER,
+            MSG_SYSTEM_POWER_STATE_SET,
+            &[graceful.into(), state as u32],
+        )?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mhu::SecureChannels;
+    use zerocopy::FromZeros;
+
+    /// Fake transport which records the last command and returns a fixed response.
+    pub struct FakeTransport {
+        pub commands: Vec<(u32, Vec<u32>)>,
+        pub response: Vec<u32>,
+    }
+
+    impl FakeTransport {
+        pub fn new(response: &[u32]) -> Self {
+            Self {
+                commands: Vec::new(),
+                response: response.to_vec(),
+            }
+        }
+    }
+
+    impl ScmiTransport for FakeTransport {
+        fn exchange(
+            &mut self,
+            header: u32,
+            payload: &[u32],
+            response: &mut [u32],
+        ) -> Result<usize, ScmiError> {
+            self.commands.push((header, payload.to_vec()));
+            response[..self.response.len()].copy_from_slice(&self.response);
+            Ok(self.response.len())
+        }
+    }
+
+    #[test]
+    fn header() {
+        assert_eq!(
+            message_header(PROTOCOL_POWER_DOMAIN, MSG_POWER_STATE_SET, 0x3ff),
+            0x0ffc_4404
+        );
+    }
+
+    #[test]
+    fn protocol_version() {
+        let mut client = ScmiClient::new(FakeTransport::new(&[0, 0x20000]));
+        assert_eq!(client.base_protocol_version(), Ok(0x20000));
+        assert_eq!(client.power_domain_protocol_version(), Ok(0x20000));
+        assert_eq!(
+            client.transport.commands,
+            [
+                (message_header(PROTOCOL_BASE, 0, 0), vec![]),
+                (message_header(PROTOCOL_POWER_DOMAIN, 0, 1), vec![]),
+            ]
+        );
+    }
+
+    #[test]
+    fn power_state_set() {
+        let mut client = ScmiClient::new(FakeTransport::new(&[0]));
+        assert_eq!(client.power_state_set(3, 0x1_0011, false), Ok(()));
+        assert_eq!(
+            client.transport.commands,
+            [(
+                message_header(PROTOCOL_POWER_DOMAIN, MSG_POWER_STATE_SET, 0),
+                vec![0, 3, 0x1_0011]
+            )]
+        );
+
+        let mut client = ScmiClient::new(FakeTransport::new(&[-3i32 as u32]));
+        assert_eq!(
+            client.power_state_set(3, 0, true),
+            Err(ScmiError::Status(ScmiStatus::Denied))
+        );
+    }
+
+    #[test]
+    fn invalid_response() {
+        let mut client = ScmiClient::new(FakeTransport::new(&[]));
+        assert_eq!(
+            client.system_power_state_set(SystemPowerState::Shutdown, false),
+            Err(ScmiError::InvalidResponse)
+        );
+        // The version is missing.
+        let mut client = ScmiClient::new(FakeTransport::new(&[0]));
+        assert_eq!(
+            client.system_power_protocol_version(),
+            Err(ScmiError::InvalidResponse)
+        );
+    }
+
+    /// Fake doorbell which is never rung by these tests.
+    struct UnusedDoorbell;
+
+    impl DoorbellSender for UnusedDoorbell {
+        fn channel_count(&self) -> usize {
+            1
+        }
+
+        fn ring(&mut self, _channel: usize, _flags: u32) -> Result<(), MhuError> {
+            unreachable!()
+        }
+
+        fn status(&self, _channel: usize) -> Result<u32, MhuError> {
+            unreachable!()
+        }
+    }
+
+    impl DoorbellReceiver for UnusedDoorbell {
+        fn channel_count(&self) -> usize {
+            1
+        }
+
+        fn pending(&self, _channel: usize) -> Result<u32, MhuError> {
+            unreachable!()
+        }
+
+        fn clear(&mut self, _channel: usize, _flags: u32) -> Result<(), MhuError> {
+            unreachable!()
+        }
+    }
+
+    #[test]
+    fn shared_memory() {
+        let mut shared_memory = ScmiSharedMemory::new_zeroed();
+        shared_memory.channel_status.0 = SMT_STATUS_FREE;
+        let header = message_header(PROTOCOL_SYSTEM_POWER, MSG_SYSTEM_POWER_STATE_SET, 5);
+        {
+            let mut transport = MhuScmiTransport::new(
+                UniqueMmioPointer::from(&mut shared_memory),
+                MhuLink::new(UnusedDoorbell, UnusedDoorbell, SecureChannels(1)),
+                0,
+            );
+            assert!(transport.channel_free());
+            transport.write_command(header, &[1, 4]).unwrap();
+            assert!(!transport.channel_free());
+        }
+        assert_eq!(shared_memory.message_header.0, header);
+        assert_eq!(shared_memory.length.0, 12);
+        assert_eq!(shared_memory.payload[0].0, 1);
+        assert_eq!(shared_memory.payload[1].0, 4);
+
+        // Fill in the response as the SCP would.
+        shared_memory.length.0 = 8;
+        shared_memory.payload[0].0 = 0;
+        shared_memory.channel_status.0 = SMT_STATUS_FREE;
+        let transport = MhuScmiTransport::new(
+            UniqueMmioPointer::from(&mut shared_memory),
+            MhuLink::new(UnusedDoorbell, UnusedDoorbell, SecureChannels(1)),
+            0,
+        );
+        let mut response = [0xff; 4];
+        assert_eq!(transport.read_response(header, &mut response), Ok(1));
+        assert_eq!(response, [0, 0xff, 0xff, 0xff]);
+        assert_eq!(
+            transport.read_response(header + 1, &mut response),
+            Err(ScmiError::InvalidResponse)
+        );
+    }
+}
@@ -0,0 +1,460 @@
+// Copyright The Rusted Firmware-A Contributors.
+//
+// SPDX-License-Identifier: BSD-3-Clause
+
+//! PSCI platform backend for platforms where the SCP manages power, and is asked to power CPUs
+//! and clusters up or down with SCMI.
+//!
+//! The power domain topology is fixed at three levels: CPU, cluster and system.
+
+use super::{ScmiClient, ScmiError, ScmiStatus, ScmiTransport, SystemPowerState};
+use crate::{
+    aarch64::{dsb_ish, wfi},
+    services::psci::{
+        PlatformPowerStateInterface, PowerStateTable, PowerStateTableEntry, PowerStateType,
+        PsciCompositePowerState, PsciPlatformInterface, PsciPlatformOptionalFeatures,
+    },
+    spin_mutex::SpinMutex,
+};
+use arm_psci::{ErrorCode, Mpidr, PowerState};
+use arm_sysregs::read_mpidr_el1;
+use core::marker::PhantomData;
+use log::error;
+
+/// The highest power level, for the whole system.
+pub const SCMI_PSCI_MAX_POWER_LEVEL: usize = 2;
+/// The number of power levels.
+pub const SCMI_PSCI_STATE_COUNT: usize = SCMI_PSCI_MAX_POWER_LEVEL + 1;
+
+/// The power level of clusters.
+const CLUSTER_POWER_LEVEL: usize = 1;
+
+/// Bit offset of the highest level to change in an SCMI composite power state.
+const SCMI_POWER_STATE_MAX_LEVEL_SHIFT: u32 = 16;
+/// Width in bits of the local state of each level in an SCMI composite power state.
+const SCMI_POWER_STATE_LEVEL_WIDTH: u32 = 4;
+
+/// SCMI local power state of a powered down domain.
+const SCMI_LOCAL_STATE_OFF: u32 = 0;
+/// SCMI local power state of a running domain.
+const SCMI_LOCAL_STATE_ON: u32 = 1;
+/// SCMI local power state of a domain in retention.
+const SCMI_LOCAL_STATE_SLEEP: u32 = 2;
+
+/// Composite power state type used by [`ScmiPsciPlatformImpl`].
+pub type ScmiCompositePowerState<const CPU_DOMAIN_COUNT: usize, const NON_CPU_DOMAIN_COUNT: usize> =
+    PsciCompositePowerState<
+        SCMI_PSCI_STATE_COUNT,
+        SCMI_PSCI_MAX_POWER_LEVEL,
+        CPU_DOMAIN_COUNT,
+        NON_CPU_DOMAIN_COUNT,
+        u8,
+        ScmiPowerState,
+    >;
+
+/// The `CPU_SUSPEND` power states supported by [`ScmiPsciPlatformImpl`].
+///
+/// The system level can't be suspended with `CPU_SUSPEND`, only with `SYSTEM_SUSPEND`.
+const SCMI_PSCI_POWER_STATES: PowerStateTable<ScmiPowerState, SCMI_PSCI_STATE_COUNT> =
+    PowerStateTable::new(&[
+        PowerStateTableEntry {
+            power_state: PowerState::StandbyOrRetention(0x001),
+            states: [
+                ScmiPowerState::Retention,
+                ScmiPowerState::Run,
+                ScmiPowerState::Run,
+            ],
+        },
+        PowerStateTableEntry {
+            power_state: PowerState::PowerDown(0x002),
+            states: [
+                ScmiPowerState::Off,
+                ScmiPowerState::Run,
+                ScmiPowerState::Run,
+            ],
+        },
+        PowerStateTableEntry {
+            power_state: PowerState::PowerDown(0x022),
+            states: [
+                ScmiPowerState::Off,
+                ScmiPowerState::Off,
+                ScmiPowerState::Run,
+            ],
+        },
+    ]);
+
+/// Local power state of a power domain managed over SCMI.
+#[derive(PartialEq, PartialOrd, Debug, Eq, Ord, Clone, Copy)]
+pub enum ScmiPowerState {
+    /// The domain is running.
+    Run = 0,
+    /// The domain is in retention, and keeps its state.
+    Retention = 1,
+    /// The domain is powered down.
+    Off = 2,
+}
+
+impl ScmiPowerState {
+    /// Returns the SCMI local power state corresponding to this state.
+    fn scmi_local_state(self) -> u32 {
+        match self {
+            Self::Run => SCMI_LOCAL_STATE_ON,
+            Self::Retention => SCMI_LOCAL_STATE_SLEEP,
+            Self::Off => SCMI_LOCAL_STATE_OFF,
+        }
+    }
+}
+
+impl PlatformPowerStateInterface for ScmiPowerState {
+    const OFF: Self = Self::Off;
+    const RUN: Self = Self::Run;
+
+    fn power_state_type(&self) -> PowerStateType {
+        match self {
+            Self::Run => PowerStateType::Run,
+            Self::Retention => PowerStateType::StandbyOrRetention,
+            Self::Off => PowerStateType::PowerDown,
+        }
+    }
+}
+
+impl From<ScmiPowerState> for usize {
+    fn from(value: ScmiPowerState) -> Self {
+        value as usize
+    }
+}
+
+/// Encodes the given local states of the CPU level and the levels above it as an SCMI composite
+/// power state for the CPU's power domain.
+///
+/// The highest level to change is the last one given.
+fn scmi_power_state(states: &[ScmiPowerState]) -> u32 {
+    let max_level = states.len() as u32 - 1;
+    states.iter().enumerate().fold(
+        max_level << SCMI_POWER_STATE_MAX_LEVEL_SHIFT,
+        |value, (level, state)| {
+            value | state.scmi_local_state() << (SCMI_POWER_STATE_LEVEL_WIDTH * level as u32)
+        },
+    )
+}
+
+/// Platform-specific parts of a PSCI backend using SCMI.
+pub trait ScmiPsciPlatform<const CPU_DOMAIN_COUNT: usize, const NON_CPU_DOMAIN_COUNT: usize> {
+    /// Returns the power domain topology as the count of child nodes in a BFS traversal order.
+    ///
+    /// This must have a single system node, then clusters, then CPUs.
+    fn topology() -> &'static [usize];
+
+    /// Returns the SCMI power domain ID of the CPU with the given MPIDR, or `None` if there is no
+    /// such CPU.
+    fn cpu_domain_id(mpidr: Mpidr) -> Option<u32>;
+
+    /// Performs platform-specific actions before the current CPU is turned off, e.g. disabling the
+    /// GIC CPU interface.
+    fn power_domain_off(
+        _target_state: &ScmiCompositePowerState<CPU_DOMAIN_COUNT, NON_CPU_DOMAIN_COUNT>,
+    ) {
+    }
+
+    /// Performs platform-specific actions before the current CPU is powered down for suspend.
+    fn power_domain_suspend(
+        _target_state: &ScmiCompositePowerState<CPU_DOMAIN_COUNT, NON_CPU_DOMAIN_COUNT>,
+    ) {
+    }
+
+    /// Performs platform-specific actions after the current CPU has been turned on.
+    fn power_domain_on_finish(
+        _previous_state: &ScmiCompositePowerState<CPU_DOMAIN_COUNT, NON_CPU_DOMAIN_COUNT>,
+    ) {
+    }
+
+    /// Performs platform-specific actions after the current CPU has woken up from a powerdown
+    /// suspend.
+    fn power_domain_suspend_finish(
+        _previous_state: &ScmiCompositePowerState<CPU_DOMAIN_COUNT, NON_CPU_DOMAIN_COUNT>,
+    ) {
+    }
+}
+
+/// PSCI platform implementation which asks the SCP to change power states with SCMI.
+pub struct ScmiPsciPlatformImpl<
+    const CPU_DOMAIN_COUNT: usize,
+    const NON_CPU_DOMAIN_COUNT: usize,
+    P,
+    T: ScmiTransport,
+> {
+    client: SpinMutex<ScmiClient<T>>,
+    _platform: PhantomData<P>,
+}
+
+impl<const CPU_DOMAIN_COUNT: usize, const NON_CPU_DOMAIN_COUNT: usize, P, T: ScmiTransport>
+    ScmiPsciPlatformImpl<CPU_DOMAIN_COUNT, NON_CPU_DOMAIN_COUNT, P, T>
+{
+    /// Creates a new PSCI platform implementation using the given SCMI client.
+    pub const fn new(client: ScmiClient<T>) -> Self {
+        Self {
+            client: SpinMutex::new(client),
+            _platform: PhantomData,
+        }
+    }
+}
+
+impl<
+    const CPU_DOMAIN_COUNT: usize,
+    const NON_CPU_DOMAIN_COUNT: usize,
+    P: ScmiPsciPlatform<CPU_DOMAIN_COUNT, NON_CPU_DOMAIN_COUNT>,
+    T: ScmiTransport,
+> ScmiPsciPlatformImpl<CPU_DOMAIN_COUNT, NON_CPU_DOMAIN_COUNT, P, T>
+{
+    /// Asks the SCP to change the power state of the CPU with the given MPIDR, and the levels
+    /// above it.
+    fn set_cpu_power_state(
+        &self,
+        mpidr: Mpidr,
+        states: &[ScmiPowerState],
+    ) -> Result<(), ErrorCode> {
+        let domain_id = P::cpu_domain_id(mpidr).ok_or(ErrorCode::InvalidParameters)?;
+        // The SCP can't power down the CPU until it has entered WFI, so it mustn't wait for the
+        // change to complete before responding.
+        self.client
+            .lock()
+            .power_state_set(domain_id, scmi_power_state(states), true)
+            .map_err(|e| {
+                error!("Failed to set power state of CPU {mpidr:?}: {e:?}");
+                match e {
+                    ScmiError::Status(ScmiStatus::Denied) => ErrorCode::Denied,
+                    ScmiError::Status(ScmiStatus::InvalidParameters) => {
+                        ErrorCode::InvalidParameters
+                    }
+                    _ => ErrorCode::InternalFailure,
+                }
+            })
+    }
+
+    /// Asks the SCP to power down the current CPU, along with its cluster if that is also being
+    /// powered down.
+    ///
+    /// The system level is never changed with the power domain protocol.
+    fn power_down_current_cpu(
+        &self,
+        target_state: &ScmiCompositePowerState<CPU_DOMAIN_COUNT, NON_CPU_DOMAIN_COUNT>,
+    ) {
+        let max_level = target_state
+            .find_highest_non_run_level()
+            .unwrap_or_default()
+            .min(CLUSTER_POWER_LEVEL);
+        let mpidr = Mpidr::from_register_value(read_mpidr_el1().bits());
+        if self
+            .set_cpu_power_state(mpidr, &target_state.states[..=max_level])
+            .is_err()
+        {
+            panic!("Failed to power down CPU {mpidr:?}");
+        }
+    }
+}
+
+impl<
+    const CPU_DOMAIN_COUNT: usize,
+    const NON_CPU_DOMAIN_COUNT: usize,
+    P: ScmiPsciPlatform<CPU_DOMAIN_COUNT, NON_CPU_DOMAIN_COUNT>,
+    T: ScmiTransport,
+>
+    PsciPlatformInterface<
+        SCMI_PSCI_STATE_COUNT,
+        SCMI_PSCI_MAX_POWER_LEVEL,
+        CPU_DOMAIN_COUNT,
+        NON_CPU_DOMAIN_COUNT,
+    > for ScmiPsciPlatformImpl<CPU_DOMAIN_COUNT, NON_CPU_DOMAIN_COUNT, P, T>
+{
+    const POWER_DOMAIN_COUNT: usize = CPU_DOMAIN_COUNT + NON_CPU_DOMAIN_COUNT;
+
+    const FEATURES: PsciPlatformOptionalFeatures = PsciPlatformOptionalFeatures::empty();
+
+    type PlatformPowerState = ScmiPowerState;
+
+    type NodeIndex = u8;
+
+    fn topology() -> &'static [usize] {
+        P::topology()
+    }
+
+    fn try_parse_power_state(
+        power_state: PowerState,
+    ) -> Option<ScmiCompositePowerState<CPU_DOMAIN_COUNT, NON_CPU_DOMAIN_COUNT>> {
+        SCMI_PSCI_POWER_STATES.parse(power_state)
+    }
+
+    fn cpu_standby(&self, cpu_state: ScmiPowerState) {
+        assert!(cpu_state.power_state_type() == PowerStateType::StandbyOrRetention);
+
+        // Enter standby state. DSB is good practice before using WFI to enter low power states.
+        dsb_ish();
+        wfi();
+    }
+
+    fn power_domain_suspend(
+        &self,
+        target_state: &ScmiCompositePowerState<CPU_DOMAIN_COUNT, NON_CPU_DOMAIN_COUNT>,
+    ) {
+        // Nothing to be done for retention at CPU level.
+        if target_state.cpu_level_state() == ScmiPowerState::Retention {
+            return;
+        }
+
+        P::power_domain_suspend(target_state);
+        self.power_down_current_cpu(target_state);
+    }
+
+    fn power_domain_suspend_finish(
+        &self,
+        previous_state: &ScmiCompositePowerState<CPU_DOMAIN_COUNT, NON_CPU_DOMAIN_COUNT>,
+    ) {
+        // Nothing to be done on waking up from retention at CPU level.
+        if previous_state.cpu_level_state() == ScmiPowerState::Retention {
+            return;
+        }
+
+        P::power_domain_suspend_finish(previous_state);
+    }
+
+    fn power_domain_off(
+        &self,
+        target_state: &ScmiCompositePowerState<CPU_DOMAIN_COUNT, NON_CPU_DOMAIN_COUNT>,
+    ) {
+        assert_eq!(ScmiPowerState::Off, target_state.cpu_level_state());
+
+        P::power_domain_off(target_state);
+        self.power_down_current_cpu(target_state);
+    }
+
+    fn power_domain_power_down(
+        &self,
+        _target_state: &ScmiCompositePowerState<CPU_DOMAIN_COUNT, NON_CPU_DOMAIN_COUNT>,
+    ) {
+    }
+
+    fn power_domain_on(&self, mpidr: Mpidr) -> Result<(), ErrorCode> {
+        // Power on the cluster along with the CPU, in case it was off.
+        self.set_cpu_power_state(mpidr, &[ScmiPowerState::Run, ScmiPowerState::Run])
+    }
+
+    fn power_domain_on_finish(
+        &self,
+        previous_state: &ScmiCompositePowerState<CPU_DOMAIN_COUNT, NON_CPU_DOMAIN_COUNT>,
+    ) {
+        P::power_domain_on_finish(previous_state);
+    }
+
+    fn system_off(&self) -> ! {
+        if let Err(e) = self
+            .client
+            .lock()
+            .system_power_state_set(SystemPowerState::Shutdown, false)
+        {
+            error!("Failed to shut down system: {e:?}");
+        }
+        wfi();
+        unreachable!("expected system off did not happen");
+    }
+
+    fn system_reset(&self) -> ! {
+        if let Err(e) = self
+            .client
+            .lock()
+            .system_power_state_set(SystemPowerState::ColdReset, false)
+        {
+            error!("Failed to reset system: {e:?}");
+        }
+        wfi();
+        unreachable!("expected system reset did not happen");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scmi::tests::FakeTransport;
+
+    struct TestScmiPlatform;
+
+    impl ScmiPsciPlatform<4, 3> for TestScmiPlatform {
+        fn topology() -> &'static [usize] {
+            &[1, 2, 2, 2]
+        }
+
+        fn cpu_domain_id(mpidr: Mpidr) -> Option<u32> {
+            (mpidr.aff1 < 2 && mpidr.aff0 < 2).then_some((mpidr.aff1 * 2 + mpidr.aff0).into())
+        }
+    }
+
+    type TestImpl = ScmiPsciPlatformImpl<4, 3, TestScmiPlatform, FakeTransport>;
+
+    #[test]
+    fn encode_power_state() {
+        assert_eq!(scmi_power_state(&[ScmiPowerState::Off]), 0x0_0000);
+        assert_eq!(
+            scmi_power_state(&[ScmiPowerState::Off, ScmiPowerState::Off]),
+            0x1_0000
+        );
+        assert_eq!(
+            scmi_power_state(&[ScmiPowerState::Run, ScmiPowerState::Run]),
+            0x1_0011
+        );
+        assert_eq!(
+            scmi_power_state(&[ScmiPowerState::Retention, ScmiPowerState::Run]),
+            0x1_0012
+        );
+    }
+
+    #[test]
+    fn power_domain_on() {
+        let platform = TestImpl::new(ScmiClient::new(FakeTransport::new(&[0])));
+        assert_eq!(
+            platform.power_domain_on(Mpidr::from_aff3210(0, 0, 1, 1)),
+            Ok(())
+        );
+        assert_eq!(
+            platform.client.lock().transport.commands[0].1,
+            [1, 3, 0x1_0011]
+        );
+        assert_eq!(
+            platform.power_domain_on(Mpidr::from_aff3210(0, 0, 2, 0)),
+            Err(ErrorCode::InvalidParameters)
+        );
+
+        let platform = TestImpl::new(ScmiClient::new(FakeTransport::new(&[-3i32 as u32])));
+        assert_eq!(
+            platform.power_domain_on(Mpidr::from_aff3210(0, 0, 0, 1)),
+            Err(ErrorCode::Denied)
+        );
+    }
+
+    #[test]
+    fn power_domain_off() {
+        let platform = TestImpl::new(ScmiClient::new(FakeTransport::new(&[0])));
+        platform.power_domain_off(&PsciCompositePowerState::new([
+            ScmiPowerState::Off,
+            ScmiPowerState::Off,
+            ScmiPowerState::Off,
+        ]));
+        // The system level is left alone.
+        let mpidr = Mpidr::from_register_value(read_mpidr_el1().bits());
+        assert_eq!(
+            platform.client.lock().transport.commands[0].1,
+            [1, TestScmiPlatform::cpu_domain_id(mpidr).unwrap(), 0x1_0000]
+        );
+    }
+
+    #[test]
+    fn parse_power_state() {
+        assert_eq!(
+            TestImpl::try_parse_power_state(PowerState::PowerDown(0x022)).map(|state| state.states),
+            Some([
+                ScmiPowerState::Off,
+                ScmiPowerState::Off,
+                ScmiPowerState::Run
+            ])
+        );
+        assert!(TestImpl::try_parse_power_state(PowerState::PowerDown(0x222)).is_none());
+    }
+}
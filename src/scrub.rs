@@ -0,0 +1,148 @@
+// Copyright The Rusted Firmware-A Contributors.
+//
+// SPDX-License-Identifier: BSD-3-Clause
+
+//! Zeroisation of secrets held by EL3 before the system is turned off or reset.
+//!
+//! Some memory used by EL3, such as SRAM or retained memory, may keep its contents across a reset,
+//! so keys, entropy and attestation material must be cleared before handing over to the platform's
+//! off or reset handler.
+
+use crate::spin_mutex::SpinMutex;
+use arrayvec::ArrayVec;
+use core::{
+    hint::black_box,
+    sync::atomic::{Ordering, compiler_fence},
+};
+use zerocopy::FromZeros;
+
+/// The maximum number of secrets which may be registered.
+pub const MAX_SECRETS: usize = 8;
+
+/// Some sensitive state held by EL3 which must be zeroed before the system is turned off or reset.
+pub trait Secret: Sync {
+    /// Zeroes the secret.
+    ///
+    /// This is called on the core handling `SYSTEM_OFF` or `SYSTEM_RESET`, while other cores may
+    /// still be running, so it must take whatever locks are needed to do so safely. If the secret
+    /// is [replaceable](Self::is_replaceable) it's also called before the platform's
+    /// `SYSTEM_OFF2` or `SYSTEM_RESET2` handler, which may fail.
+    fn scrub(&self);
+
+    /// Returns whether the owner of the secret can carry on after it has been scrubbed, e.g. by
+    /// generating a new one when it is next needed.
+    ///
+    /// Secrets which can't are only scrubbed when the system is certain to go down.
+    fn is_replaceable(&self) -> bool {
+        true
+    }
+}
+
+/// The list of secrets to zero before the system is turned off or reset.
+///
+/// Secrets are registered on the primary core during cold boot, and scrubbed in the order they were
+/// registered.
+pub struct Secrets {
+    secrets: SpinMutex<ArrayVec<&'static dyn Secret, MAX_SECRETS>>,
+}
+
+impl Secrets {
+    /// Creates an empty list of secrets.
+    pub const fn new() -> Self {
+        Self {
+            secrets: SpinMutex::new(ArrayVec::new_const()),
+        }
+    }
+
+    /// Adds the given secret to the list.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `MAX_SECRETS` secrets have already been registered.
+    pub fn register(&self, secret: &'static dyn Secret) {
+        self.secrets
+            .lock()
+            .try_push(secret)
+            .expect("Too many secrets registered");
+    }
+
+    /// Zeroes all registered secrets, before the system is turned off or reset.
+    pub(crate) fn scrub_all(&self) {
+        self.scrub_matching(|_| true);
+    }
+
+    /// Zeroes the registered secrets which are replaceable, before attempting an operation which
+    /// should turn off or reset the system but may fail.
+    pub(crate) fn scrub_replaceable(&self) {
+        self.scrub_matching(|secret| secret.is_replaceable());
+    }
+
+    fn scrub_matching(&self, filter: impl Fn(&dyn Secret) -> bool) {
+        // Copy the list so that the lock isn't held while scrubbing.
+        let secrets = self.secrets.lock().clone();
+        for secret in secrets {
+            if filter(secret) {
+                secret.scrub();
+            }
+        }
+    }
+}
+
+impl Default for Secrets {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Overwrites the given value with zeroes, in a way which the compiler won't optimise away even if
+/// the value is never read again.
+pub fn zeroize<T: FromZeros>(value: &mut T) {
+    value.zero();
+    compiler_fence(Ordering::SeqCst);
+    black_box(value);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    struct FakeSecret {
+        key: Mutex<[u64; 2]>,
+        replaceable: bool,
+    }
+
+    impl Secret for FakeSecret {
+        fn scrub(&self) {
+            zeroize(&mut *self.key.lock().unwrap());
+        }
+
+        fn is_replaceable(&self) -> bool {
+            self.replaceable
+        }
+    }
+
+    #[test]
+    fn scrub_registered_secrets() {
+        static FIRST: FakeSecret = FakeSecret {
+            key: Mutex::new([1, 2]),
+            replaceable: true,
+        };
+        static SECOND: FakeSecret = FakeSecret {
+            key: Mutex::new([3, 4]),
+            replaceable: false,
+        };
+
+        let secrets = Secrets::new();
+        secrets.register(&FIRST);
+        secrets.register(&SECOND);
+
+        secrets.scrub_replaceable();
+        assert_eq!(*FIRST.key.lock().unwrap(), [0, 0]);
+        assert_eq!(*SECOND.key.lock().unwrap(), [3, 4]);
+
+        secrets.scrub_all();
+        assert_eq!(*FIRST.key.lock().unwrap(), [0, 0]);
+        assert_eq!(*SECOND.key.lock().unwrap(), [0, 0]);
+    }
+}
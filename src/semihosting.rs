@@ -123,3 +123,12 @@ pub fn semihosting_exit(reason: AdpStopped, subcode: u64) {
         semihosting_call(Operation::Exit, parameters.as_ptr());
     }
 }
+
+/// Writes a single character to the debug channel of the host.
+#[cfg(all(target_arch = "aarch64", not(any(test, feature = "fakes"))))]
+pub fn semihosting_write_char(c: u8) {
+    // SAFETY: `SYS_WRITEC` takes a pointer to the character to write, which is valid.
+    unsafe {
+        semihosting_call(Operation::Writec, (&raw const c).cast());
+    }
+}
@@ -5,36 +5,70 @@
 //! Runtime services which handle SMCs from lower ELs.
 
 pub mod arch;
+pub mod debug;
+pub mod deferred;
 mod errata_management;
 pub mod ffa;
 pub mod psci;
 #[cfg(feature = "rme")]
 pub mod rmmd;
+#[cfg(feature = "rt_instr")]
+pub mod rt_instr;
+pub mod sdei;
 pub mod trng;
+pub mod vendor;
 
+#[cfg(feature = "spmc_el3")]
+use crate::services::ffa::spmc_el3::{MemoryTransactions, SpmcEl3};
 #[cfg(feature = "rme")]
 use crate::services::rmmd::Rmmd;
+#[cfg(feature = "rt_instr")]
+use crate::services::rt_instr::RtInstr;
 use crate::{
     context::{
-        CpuStateAccess, World, initialise_contexts, set_initial_world, switch_world,
-        update_contexts_suspend,
+        CoresImpl, CpuData, CpuDataIndex, CpuStateAccess, World, initialise_contexts,
+        set_initial_world, switch_world, update_contexts_suspend,
     },
     cpu::PlatformCpuOps,
-    errata_framework::PlatformErrata,
-    exceptions::{RunResult, enter_world, inject_undef64},
+    cpu_extensions::id_registers,
+    cpu_notifier::CpuNotifiers,
+    errata_framework::{PlatformErrata, report_errata},
+    exceptions::{ExceptionHandlerScope, RunResult, enter_world, inject_undef64},
     gicv3::{self, InterruptType},
-    platform::{Platform, exception_free},
+    nv_counter::NvCounterId,
+    platform::{Board, Platform, UnknownHvcPolicy, exception_free},
+    pmf::Pmf,
+    ras_history::RasErrorHistory,
+    ro_after_init::RoAfterInit,
+    runtime_config::runtime_config,
+    scrub::Secrets,
     services::{
         arch::Arch,
+        debug::{
+            DebugService, InterruptLatencyPhase, InterruptLatencyStats, SmcAuditBuffer,
+            SuspendStats,
+        },
+        deferred::{DeferredWork, DeferredWorkQueue, QueueFull},
         errata_management::ErrataManagement,
-        ffa::spmd::Spmd,
-        psci::{Psci, PsciPlatformInterface, WakeUpReason},
+        ffa::spmd::{DirectRequestError, MailboxState, Spmd},
+        psci::{PowerDomainStatsTable, Psci, PsciPlatformInterface, WakeUpReason},
+        sdei::{Sdei, SdeiState},
         trng::{Trng, TrngPlatformInterface},
+        vendor::{RfaVendorHandler, VendorEl3Service, VendorHandlers},
     },
     smccc::{FunctionId, NOT_SUPPORTED, SetFrom, SmcReturn},
+    sysreg_trap::{SysregAccess, SysregDirection, SysregTrapAction},
+    system_suspend_notifier::SystemSuspendNotifiers,
+    timer::Timeout,
 };
 use arm_sysregs::EsrEl3;
-use log::debug;
+use arrayvec::ArrayVec;
+use core::{
+    fmt::Debug,
+    sync::atomic::{AtomicU8, Ordering::Relaxed},
+};
+use log::{debug, warn};
+use percore::Cores;
 
 /// Helper macro to define the range of SMC function ID values covered by a service
 macro_rules! owns {
@@ -65,6 +99,25 @@ macro_rules! owns {
 }
 pub(crate) use owns;
 
+/// A phase of the cold boot sequence, at which services may perform initialisation.
+///
+/// Phases happen in the order they are declared here, each one exactly once.
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
+#[repr(u8)]
+pub enum InitPhase {
+    /// The runtime page table is active and the platform has been initialised, but the GIC hasn't
+    /// been configured yet.
+    Early = 1,
+    /// The GIC distributor and the primary core's redistributor and CPU interface have been
+    /// configured.
+    PostGic = 2,
+    /// The initial CPU contexts of all worlds have been initialised on the primary core.
+    PostContext = 3,
+    /// The secure world (and realm world, if enabled) have finished booting, and the normal world
+    /// is about to be entered for the first time.
+    Late = 4,
+}
+
 /// A service which handles some range of SMC calls.
 ///
 /// According to SMCCC v1.3+ the implementation must disregard the SVE hint bit in the function ID
@@ -73,6 +126,11 @@ pub trait Service {
     /// Returns whether this service is intended to handle the given function ID.
     fn owns(&self, function: FunctionId) -> bool;
 
+    /// Performs any initialisation needed at the given phase of cold boot.
+    ///
+    /// This is called once for each phase, on the primary core.
+    fn init(&self, _phase: InitPhase) {}
+
     /// Handles the given SMC call from Normal World.
     fn handle_non_secure_smc(&self, regs: &mut SmcReturn) -> World {
         regs.set_from(NOT_SUPPORTED);
@@ -93,6 +151,110 @@ pub trait Service {
     }
 }
 
+/// The memory transactions of the EL3 SPMC, kept outside the services so that they don't need to
+/// fit on the stack while they are constructed.
+#[cfg(feature = "spmc_el3")]
+static EL3_SPMC_MEMORY: MemoryTransactions = MemoryTransactions::new();
+
+/// The history of RAS errors handled at EL3, kept outside the services so that it doesn't need to
+/// fit on the stack while they are constructed.
+static RAS_ERROR_HISTORY: RasErrorHistory = RasErrorHistory::new();
+
+/// How long the diagnostics partition may take to handle each RAS error delivered to it, before
+/// the normal world is entered anyway, in microseconds.
+const RAS_DELIVERY_TIMEOUT_MICROS: u64 = 1000;
+
+/// The maximum number of services which may be in the `ServiceRegistry`.
+const MAX_SERVICES: usize = 11;
+
+/// The table used to dispatch SMCs to the service which owns their function ID.
+///
+/// This is built once during cold boot and stored in a [`RoAfterInit`] static, so it is read-only
+/// at runtime. The services themselves keep any mutable state behind their own locks.
+pub struct ServiceRegistry {
+    /// The services in the order in which their ownership of a function ID is checked.
+    services: ArrayVec<&'static (dyn Service + Sync), MAX_SERVICES>,
+}
+
+impl ServiceRegistry {
+    /// Returns the service which owns the given function ID, if any.
+    fn service_for(&self, function: FunctionId) -> Option<&'static (dyn Service + Sync)> {
+        self.services
+            .iter()
+            .copied()
+            .find(|service| service.owns(function))
+    }
+}
+
+/// Handles a trapped system register access from the given world, by emulating it or injecting an
+/// undefined instruction exception.
+pub(crate) fn handle_sysreg_trap<PlatformImpl: CpuStateAccess + Platform>(
+    esr: EsrEl3,
+    world: World,
+) {
+    let access = SysregAccess::from_esr(esr, |rt| {
+        exception_free(|token| {
+            PlatformImpl::cpu_state(token)[world].gpregs.registers[usize::from(rt)]
+        })
+    });
+
+    let action = if access.encoding.is_impdef() {
+        PlatformImpl::handle_impdef_sysreg_trap(world, &access)
+    } else if access.direction == SysregDirection::Read
+        && id_registers::is_id_group3(access.encoding)
+    {
+        SysregTrapAction::Emulated(id_registers::emulate_read::<PlatformImpl>(
+            access.encoding,
+            world,
+            runtime_config().hidden_id_features[world],
+        ))
+    } else {
+        SysregTrapAction::Undefined
+    };
+
+    let read_value = match action {
+        SysregTrapAction::Emulated(value) => value,
+        SysregTrapAction::RazWi => 0,
+        SysregTrapAction::Undefined => {
+            inject_undef64::<PlatformImpl>(world);
+            return;
+        }
+    };
+
+    exception_free(|token| {
+        let context = &mut PlatformImpl::cpu_state(token)[world];
+        // Writes to XZR are discarded.
+        if access.direction == SysregDirection::Read && access.rt != 31 {
+            context.gpregs.registers[usize::from(access.rt)] = read_value;
+        }
+        context.skip_lower_el_instruction();
+    });
+}
+
+/// Handles an `HVC` instruction from the given world which was taken to EL3, according to the
+/// platform's [`UnknownHvcPolicy`].
+///
+/// `regs` are updated with the values to return to the world, if any.
+pub(crate) fn handle_unknown_hvc<PlatformImpl: CpuStateAccess + Platform>(
+    regs: &mut SmcReturn,
+    world: World,
+) {
+    warn!("HVC from {world:?} world taken to EL3");
+
+    match PlatformImpl::UNKNOWN_HVC_POLICY {
+        UnknownHvcPolicy::Undefined => {
+            // ELR_EL3 points after the HVC, but the undefined instruction exception should report
+            // the HVC itself.
+            exception_free(|token| {
+                PlatformImpl::cpu_state(token)[world].rewind_lower_el_instruction();
+            });
+            inject_undef64::<PlatformImpl>(world);
+            regs.mark_empty();
+        }
+        UnknownHvcPolicy::NotSupported => regs.set_from(NOT_SUPPORTED),
+    }
+}
+
 /// Contains an instance of all of the currently implemented services.
 pub struct Services<
     const CORE_COUNT: usize,
@@ -101,15 +263,15 @@ pub struct Services<
     const NON_CPU_DOMAIN_COUNT: usize,
     const TRNG_REQ_WORDS: usize,
     const TRNG_WORDS_IN_POOL: usize,
-    PlatformImpl: CpuStateAccess + Platform + PlatformErrata + 'static,
+    PlatformImpl: CpuDataIndex + CpuStateAccess + Platform + PlatformErrata + 'static,
 > where
-    <PlatformImpl as Platform>::PsciPlatformImpl: PsciPlatformInterface<
+    <PlatformImpl as Board>::PsciPlatformImpl: PsciPlatformInterface<
             PSCI_STATE_COUNT,
             PSCI_MAX_POWER_LEVEL,
             CORE_COUNT,
             NON_CPU_DOMAIN_COUNT,
         >,
-    <PlatformImpl as Platform>::TrngPlatformImpl: TrngPlatformInterface<TRNG_REQ_WORDS>,
+    <PlatformImpl as Board>::TrngPlatformImpl: TrngPlatformInterface<TRNG_REQ_WORDS>,
 {
     arch: Arch<PlatformImpl>,
     psci: Psci<
@@ -124,11 +286,27 @@ pub struct Services<
     platform: PlatformImpl::PlatformServiceImpl,
     /// The FF-A SPMD service.
     pub spmd: Spmd<CORE_COUNT, PlatformImpl>,
+    /// The FF-A SPMC at EL3, which handles FF-A calls from the normal world instead of the SPMD.
+    #[cfg(feature = "spmc_el3")]
+    pub spmc_el3: SpmcEl3<CORE_COUNT>,
     /// The CCA service for communication with TF-RMM.
     #[cfg(feature = "rme")]
     pub rmmd: Rmmd<CORE_COUNT, PlatformImpl>,
+    #[cfg(feature = "rt_instr")]
+    rt_instr: RtInstr<CORE_COUNT, PlatformImpl>,
     trng: Trng<TRNG_REQ_WORDS, TRNG_WORDS_IN_POOL, PlatformImpl::TrngPlatformImpl>,
+    sdei: Sdei<CORE_COUNT, PlatformImpl>,
     errata_management: ErrataManagement<PlatformImpl>,
+    debug: DebugService<CORE_COUNT, PlatformImpl>,
+    vendor: VendorEl3Service,
+    rfa_vendor_handler: RfaVendorHandler<CORE_COUNT, PlatformImpl>,
+    deferred_work: &'static DeferredWorkQueue<CORE_COUNT, PlatformImpl>,
+    pmf: &'static Pmf<CORE_COUNT>,
+    ras_error_history: &'static RasErrorHistory,
+    /// The last `InitPhase` which was completed, or 0 if none.
+    init_phase: AtomicU8,
+    /// The SMC dispatch table, built by `init_registry`.
+    registry: &'static RoAfterInit<ServiceRegistry>,
 }
 
 impl<
@@ -138,7 +316,7 @@ impl<
     const NON_CPU_DOMAIN_COUNT: usize,
     const TRNG_REQ_WORDS: usize,
     const TRNG_WORDS_IN_POOL: usize,
-    PlatformImpl: CpuStateAccess + Platform + PlatformCpuOps + PlatformErrata,
+    PlatformImpl: CpuDataIndex + CpuStateAccess + Platform + PlatformCpuOps + PlatformErrata,
 >
     Services<
         CORE_COUNT,
@@ -150,24 +328,218 @@ impl<
         PlatformImpl,
     >
 where
-    <PlatformImpl as Platform>::PsciPlatformImpl:
+    <PlatformImpl as Board>::PsciPlatformImpl:
         PsciPlatformInterface<STATE_COUNT, MAX_POWER_LEVEL, CORE_COUNT, NON_CPU_DOMAIN_COUNT>,
-    <PlatformImpl as Platform>::TrngPlatformImpl: TrngPlatformInterface<TRNG_REQ_WORDS>,
+    <PlatformImpl as Board>::TrngPlatformImpl: TrngPlatformInterface<TRNG_REQ_WORDS>,
 {
     /// Constructs a new instance of the services.
-    pub fn new(get_spm: fn() -> &'static Spmd<CORE_COUNT, PlatformImpl>) -> Self {
+    ///
+    /// `get_spm`, `get_suspend_stats` and `get_psci_state` must return the SPMD, `suspend_stats()`
+    /// and `psci_state()` of this same instance, once it has been constructed. `smc_audit`,
+    /// `mailbox_state`, `psci_power_stats`, `sdei_state`, `deferred_work` and `pmf` are kept
+    /// outside the services so that they don't need to fit on the stack while they are
+    /// constructed. `registry` should be placed in the `.ro_after_init` section, and is filled in
+    /// by `init_registry`.
+    #[expect(clippy::too_many_arguments)]
+    pub fn new(
+        get_spm: fn() -> &'static Spmd<CORE_COUNT, PlatformImpl>,
+        get_suspend_stats: fn() -> &'static SuspendStats,
+        get_psci_state: fn() -> &'static dyn Debug,
+        smc_audit: &'static SmcAuditBuffer<CORE_COUNT>,
+        mailbox_state: &'static MailboxState<CORE_COUNT>,
+        psci_power_stats: &'static PowerDomainStatsTable<
+            CORE_COUNT,
+            NON_CPU_DOMAIN_COUNT,
+            <PlatformImpl::PsciPlatformImpl as PsciPlatformInterface<
+                STATE_COUNT,
+                MAX_POWER_LEVEL,
+                CORE_COUNT,
+                NON_CPU_DOMAIN_COUNT,
+            >>::PlatformPowerState,
+        >,
+        sdei_state: &'static SdeiState<CORE_COUNT, PlatformImpl>,
+        deferred_work: &'static DeferredWorkQueue<CORE_COUNT, PlatformImpl>,
+        pmf: &'static Pmf<CORE_COUNT>,
+        registry: &'static RoAfterInit<ServiceRegistry>,
+    ) -> Self {
         Self {
             arch: Arch::new(),
-            psci: Psci::new(PlatformImpl::psci_platform().unwrap(), get_spm),
+            psci: Psci::new(
+                PlatformImpl::psci_platform().unwrap(),
+                get_spm,
+                psci_power_stats,
+                pmf,
+            ),
             platform: PlatformImpl::create_service(),
-            spmd: Spmd::new(),
+            spmd: Spmd::new(smc_audit, deferred_work, mailbox_state),
+            #[cfg(feature = "spmc_el3")]
+            spmc_el3: SpmcEl3::new(PlatformImpl::el3_spmc_manifest(), &EL3_SPMC_MEMORY),
             #[cfg(feature = "rme")]
             rmmd: Rmmd::new(),
+            #[cfg(feature = "rt_instr")]
+            rt_instr: RtInstr::new(pmf),
             trng: Trng::new(),
+            sdei: Sdei::new(sdei_state),
             errata_management: ErrataManagement::new(),
+            debug: DebugService::new(get_spm, get_suspend_stats, get_psci_state),
+            vendor: VendorEl3Service::new(),
+            rfa_vendor_handler: RfaVendorHandler::new(get_spm),
+            deferred_work,
+            pmf,
+            ras_error_history: &RAS_ERROR_HISTORY,
+            init_phase: AtomicU8::new(0),
+            registry,
         }
     }
 
+    /// Builds the table used to dispatch SMCs to the services.
+    ///
+    /// # Safety
+    ///
+    /// This must be called once, on the primary core during cold boot before any SMCs are handled,
+    /// and before the `.ro_after_init` section is made read-only.
+    pub unsafe fn init_registry(&'static self) {
+        let mut services = ArrayVec::new();
+        services.extend([
+            &self.arch as &(dyn Service + Sync),
+            &self.psci,
+            // Takes TF-A's PMF SiP function IDs before the platform service.
+            #[cfg(feature = "rt_instr")]
+            &self.rt_instr,
+            &self.platform,
+            self.ffa_service(),
+            &self.errata_management,
+            &self.trng,
+            &self.sdei,
+            &self.vendor,
+            #[cfg(feature = "rme")]
+            &self.rmmd,
+        ]);
+        // SAFETY: Our caller guarantees that this is only called once, during cold boot.
+        unsafe {
+            self.registry.init(ServiceRegistry { services });
+        }
+    }
+
+    /// Returns the list of handlers for vendor-specific EL3 monitor SMCs.
+    pub fn vendor_handlers(&self) -> &VendorHandlers {
+        self.vendor.handlers()
+    }
+
+    /// Registers RF-A's own vendor-specific EL3 monitor SMC handlers.
+    ///
+    /// This should be called once on the primary core during cold boot.
+    pub fn register_vendor_handlers(&'static self) {
+        self.rfa_vendor_handler
+            .set_trusted_firmware_counter(self.psci.read_nv_counter(NvCounterId::TrustedFirmware));
+        self.vendor_handlers().register(&self.debug);
+        self.vendor_handlers().register(&self.rfa_vendor_handler);
+        self.vendor_handlers().register(self.pmf);
+        self.vendor_handlers().register(self.ras_error_history);
+    }
+
+    /// Returns the timestamps captured by the Performance Measurement Framework.
+    pub fn pmf(&self) -> &Pmf<CORE_COUNT> {
+        self.pmf
+    }
+
+    /// Returns the history of RAS errors handled at EL3, into which platform error handlers
+    /// should record the errors they handle.
+    pub fn ras_error_history(&self) -> &RasErrorHistory {
+        self.ras_error_history
+    }
+
+    /// Returns the statistics about `CPU_SUSPEND` calls collected by the PSCI service.
+    pub fn suspend_stats(&self) -> &SuspendStats {
+        self.psci.suspend_stats()
+    }
+
+    /// Returns the list of drivers to notify when a core is turned on or off with PSCI.
+    pub fn cpu_notifiers(&self) -> &CpuNotifiers {
+        self.psci.cpu_notifiers()
+    }
+
+    /// Returns the list of drivers to notify when the system power domain is suspended or resumes
+    /// with PSCI.
+    pub fn system_suspend_notifiers(&self) -> &SystemSuspendNotifiers {
+        self.psci.system_suspend_notifiers()
+    }
+
+    /// Returns the list of secrets which PSCI zeroes before the system is turned off or reset.
+    pub fn secrets(&self) -> &Secrets {
+        self.psci.secrets()
+    }
+
+    /// Returns a word of entropy from the platform's TRNG backend, or `None` if it has no entropy
+    /// source or it is out of entropy.
+    pub fn trng_entropy(&self) -> Option<u64> {
+        self.trng.entropy_u64()
+    }
+
+    /// Registers the secrets held by the services themselves, such as the TRNG entropy pool.
+    ///
+    /// This should be called once on the primary core during cold boot.
+    pub fn register_secrets(&'static self) {
+        self.secrets().register(&self.trng);
+    }
+
+    /// Returns the state of the PSCI service, including the power domain tree, for the debug
+    /// service to dump.
+    pub fn psci_state(&self) -> &dyn Debug {
+        &self.psci
+    }
+
+    /// Defers the given work to be run at EL3 on the current core, after the current exception has
+    /// been handled but before next entering the work's world.
+    ///
+    /// This allows exception handlers to stay short while heavier work still happens promptly.
+    pub fn defer(&self, work: DeferredWork) -> Result<(), QueueFull> {
+        self.deferred_work.push(work)
+    }
+
+    /// Runs the given cold boot phase of all services.
+    ///
+    /// Services are initialised in dependency order: a service may rely on any service before it
+    /// in the list having completed the same phase.
+    pub fn init(&self, phase: InitPhase) {
+        let previous_phase = self.init_phase.swap(phase as u8, Relaxed);
+        assert!(
+            previous_phase < phase as u8,
+            "Init phase {phase:?} run out of order, after phase {previous_phase}"
+        );
+        debug!("Services init phase {phase:?}");
+
+        let services: [&dyn Service; _] = [
+            &self.arch,
+            #[cfg(feature = "rme")]
+            &self.rmmd,
+            &self.spmd,
+            #[cfg(feature = "spmc_el3")]
+            &self.spmc_el3,
+            &self.psci,
+            &self.trng,
+            &self.sdei,
+            &self.errata_management,
+            &self.vendor,
+            &self.platform,
+        ];
+        for service in services {
+            service.init(phase);
+        }
+    }
+
+    /// Returns the service which handles FF-A calls.
+    fn ffa_service(&'static self) -> &'static (dyn Service + Sync) {
+        #[cfg(feature = "spmc_el3")]
+        return &self.spmc_el3;
+        #[cfg(not(feature = "spmc_el3"))]
+        &self.spmd
+    }
+
+    fn init_phase_done(&self, phase: InitPhase) -> bool {
+        self.init_phase.load(Relaxed) >= phase as u8
+    }
+
     fn handle_smc(&self, regs: &mut SmcReturn, world: World) -> World {
         let function = FunctionId(regs.values()[0] as u32);
 
@@ -176,32 +548,9 @@ where
             return world;
         }
 
-        let service: &dyn Service = if self.arch.owns(function) {
-            &self.arch
-        } else if self.psci.owns(function) {
-            &self.psci
-        } else if self.platform.owns(function) {
-            &self.platform
-        } else if self.spmd.owns(function) {
-            &self.spmd
-        } else if self.errata_management.owns(function) {
-            &self.errata_management
-        } else if self.trng.owns(function) {
-            &self.trng
-        } else {
-            #[cfg(feature = "rme")]
-            if self.rmmd.owns(function) {
-                &self.rmmd
-            } else {
-                regs.set_from(NOT_SUPPORTED);
-                return world;
-            }
-
-            #[cfg(not(feature = "rme"))]
-            {
-                regs.set_from(NOT_SUPPORTED);
-                return world;
-            }
+        let Some(service) = self.registry.get().service_for(function) else {
+            regs.set_from(NOT_SUPPORTED);
+            return world;
         };
 
         match world {
@@ -238,26 +587,35 @@ where
         }
     }
 
-    fn handle_sysreg_trap(&self, esr: EsrEl3, world: World) {
-        // Default behaviour is to repeat the same instruction, unless the trap handler requests
-        // stepping to the next one.
-        #[allow(unused)]
-        let mut step_to_next_instr = false;
+    /// Sends the RAS errors which haven't been delivered yet to the platform's diagnostics
+    /// partition, if it has one.
+    ///
+    /// This must be called before entering the normal world, while its context is current.
+    fn deliver_ras_errors(&self) {
+        let Some(partition) = PlatformImpl::RAS_DIAGNOSTICS_PARTITION else {
+            return;
+        };
+        let timeout = Timeout::from_micros(RAS_DELIVERY_TIMEOUT_MICROS);
 
-        #[allow(clippy::match_single_binding)]
-        match esr & EsrEl3::ISS_SYSREG_OPCODE_MASK {
-            // TODO: add trap handlers, should set step_to_next_instr as necessary
-            _ => {
-                inject_undef64::<PlatformImpl>(world);
-                return;
-            }
+        // An earlier error which the partition didn't finish handling must be resumed first.
+        if let Some(Err(DirectRequestError::TimedOut | DirectRequestError::Interrupted)) =
+            self.spmd.resume_direct_request(Some(timeout))
+        {
+            return;
         }
 
-        #[allow(unreachable_code)]
-        if step_to_next_instr {
-            exception_free(|token| {
-                PlatformImpl::cpu_state(token)[world].skip_lower_el_instruction();
-            })
+        while let Some(event) = self.ras_error_history.take_undelivered() {
+            let args = [event.record_index, event.syndrome, event.timestamp, 0, 0];
+            match self
+                .spmd
+                .send_direct_request(partition, args, Some(timeout))
+            {
+                Ok(_) => {}
+                Err(DirectRequestError::TimedOut | DirectRequestError::Interrupted) => return,
+                Err(error) => {
+                    warn!("Failed to deliver RAS error to partition {partition:#x}: {error:?}");
+                }
+            }
         }
     }
 
@@ -265,14 +623,38 @@ where
         let mut next_world;
 
         loop {
-            next_world = match enter_world::<PlatformImpl>(regs, world) {
+            self.deferred_work.run_pending(world);
+            if world == World::NonSecure {
+                self.deliver_ras_errors();
+                self.sdei.deliver_pending(regs);
+            }
+
+            // Nothing may stay locked while a lower EL runs, as it may never come back to EL3 on
+            // this core.
+            self.psci.assert_no_locks_held();
+            let result = enter_world::<PlatformImpl>(regs, world);
+            let _scope = ExceptionHandlerScope::<PlatformImpl>::enter();
+            next_world = match result {
                 RunResult::Smc => self.handle_smc(regs, world),
-                RunResult::Interrupt => self.handle_interrupt(regs, world),
+                RunResult::Interrupt => {
+                    let start = InterruptLatencyStats::<CORE_COUNT>::start();
+                    let next_world = self.handle_interrupt(regs, world);
+                    self.spmd.interrupt_latency_stats().record(
+                        CoresImpl::<PlatformImpl>::core_index(),
+                        InterruptLatencyPhase::El3Handling,
+                        start,
+                    );
+                    next_world
+                }
                 RunResult::SysregTrap { esr } => {
-                    self.handle_sysreg_trap(esr, world);
+                    handle_sysreg_trap::<PlatformImpl>(esr, world);
                     regs.mark_empty();
                     world
                 }
+                RunResult::Hvc => {
+                    handle_unknown_hvc::<PlatformImpl>(regs, world);
+                    world
+                }
             };
 
             if next_world != world {
@@ -291,14 +673,27 @@ where
     /// `bl31_main()`. This method doesn't return, it should be called on each core as the last step
     /// of the boot process, i.e. after setting up MMU, GIC, etc.
     pub fn run_loop(&self) -> ! {
-        let mut current_world = World::Secure;
+        let mut current_world;
         let mut regs = SmcReturn::EMPTY;
 
-        debug!("Booting Secure World");
-        set_initial_world::<PlatformImpl>(World::Secure);
-        // TODO: implement separate boot loop for Secure World
-        let next_world = self.per_world_loop(&mut regs, World::Secure);
-        assert_eq!(next_world, World::NonSecure);
+        // This starts on an empty stack, even if the core powered down part way through handling
+        // an exception from a lower EL.
+        exception_free(|token| {
+            PlatformImpl::update_cpu_data(token, CpuData::clear_exception_nesting);
+        });
+
+        if runtime_config().spmc_present {
+            debug!("Booting Secure World");
+            current_world = World::Secure;
+            set_initial_world::<PlatformImpl>(World::Secure);
+            // TODO: implement separate boot loop for Secure World
+            let next_world = self.per_world_loop(&mut regs, World::Secure);
+            assert_eq!(next_world, World::NonSecure);
+        } else {
+            debug!("No SPMC, not booting Secure World");
+            current_world = World::NonSecure;
+            set_initial_world::<PlatformImpl>(World::NonSecure);
+        }
 
         #[cfg(feature = "rme")]
         {
@@ -314,12 +709,21 @@ where
             }
         }
 
+        // This is only needed on cold boot, not when a core is warm booted.
+        if !self.init_phase_done(InitPhase::Late) {
+            self.init(InitPhase::Late);
+        }
+
         regs.mark_empty();
         let mut next_world = World::NonSecure;
         debug!("Booting Normal World");
 
         loop {
-            switch_world::<PlatformImpl>(current_world, next_world);
+            // The normal world may already be current if no other world was booted first.
+            if current_world != next_world {
+                switch_world::<PlatformImpl>(current_world, next_world);
+            }
+            self.spmd.finish_world_switch();
             current_world = next_world;
             next_world = self.per_world_loop(&mut regs, current_world);
             assert_ne!(current_world, next_world);
@@ -335,6 +739,8 @@ where
             WakeUpReason::CpuOn(psci_entrypoint) => {
                 // Power on for the first time or after CPU_OFF
                 debug!("Wakeup from CPU_OFF");
+                report_errata::<PlatformImpl>();
+                self.sdei.reset_core();
 
                 // TODO: Refactor handling of entrypoints to provide the warm boot entrypoints as well.
                 // Also, at least some parts of the entrypoint should be provided by the service that
@@ -349,6 +755,8 @@ where
                 secure_entry_point.args.fill(0);
                 self.spmd.handle_wake_from_cpu_off();
 
+                #[cfg(feature = "rme")]
+                self.rmmd.handle_wake_from_cpu_off();
                 #[cfg(feature = "rme")]
                 let realm_entry_point = PlatformImpl::realm_entry_point();
 
@@ -386,21 +794,54 @@ where
 mod tests {
     use super::*;
     use crate::{
-        platform::test::{NON_CPU_DOMAIN_COUNT, TRNG_WORDS_IN_POOL, TestPlatform},
+        platform::test::{NON_CPU_DOMAIN_COUNT, TRNG_WORDS_IN_POOL, TestPlatform, TestPowerState},
         services::arch::{SMCCC_VERSION, SMCCC_VERSION_1_5},
         smccc::FunctionId,
+        sysreg_trap::SysregEncoding,
     };
 
+    static SMC_AUDIT: SmcAuditBuffer<{ TestPlatform::CORE_COUNT }> = SmcAuditBuffer::new();
+    static MAILBOX_STATE: MailboxState<{ TestPlatform::CORE_COUNT }> = MailboxState::new();
+    static PSCI_POWER_STATS: PowerDomainStatsTable<
+        { TestPlatform::CORE_COUNT },
+        NON_CPU_DOMAIN_COUNT,
+        TestPowerState,
+    > = PowerDomainStatsTable::new();
+    static SDEI_STATE: SdeiState<{ TestPlatform::CORE_COUNT }, TestPlatform> = SdeiState::new();
+    static DEFERRED_WORK: DeferredWorkQueue<{ TestPlatform::CORE_COUNT }, TestPlatform> =
+        DeferredWorkQueue::new();
+    static PMF: Pmf<{ TestPlatform::CORE_COUNT }> = Pmf::new();
+
     /// Tests the SMCCC arch version call as a simple example of SMC dispatch.
     ///
     /// The point of this isn't to test every individual SMC call, just that the common code in
     /// `handle_smc` works. Individual SMC calls can be tested directly within their modules.
     #[test]
     fn handle_smc_arch_version() {
-        let services =
-            Services::<_, _, _, NON_CPU_DOMAIN_COUNT, _, TRNG_WORDS_IN_POOL, TestPlatform>::new(
-                || unimplemented!(),
-            );
+        let services = Box::leak(Box::new(Services::<
+            _,
+            _,
+            _,
+            NON_CPU_DOMAIN_COUNT,
+            _,
+            TRNG_WORDS_IN_POOL,
+            TestPlatform,
+        >::new(
+            || unimplemented!(),
+            || unimplemented!(),
+            || unimplemented!(),
+            &SMC_AUDIT,
+            &MAILBOX_STATE,
+            &PSCI_POWER_STATS,
+            &SDEI_STATE,
+            &DEFERRED_WORK,
+            &PMF,
+            Box::leak(Box::new(RoAfterInit::new())),
+        )));
+        // SAFETY: The registry isn't shared with anything else.
+        unsafe {
+            services.init_registry();
+        }
 
         let mut function = FunctionId(SMCCC_VERSION);
 
@@ -415,4 +856,138 @@ mod tests {
         assert_eq!(new_world, World::NonSecure);
         assert_eq!(regs.values(), [SMCCC_VERSION_1_5 as u64]);
     }
+
+    #[test]
+    fn handle_impdef_sysreg_trap() {
+        let set_context = |x3, elr| {
+            exception_free(|token| {
+                let context = &mut TestPlatform::cpu_state(token)[World::NonSecure];
+                context.gpregs.registers[3] = x3;
+                context.el3_state.elr_el3 = elr;
+            })
+        };
+        let get_context = || {
+            exception_free(|token| {
+                let context = &TestPlatform::cpu_state(token)[World::NonSecure];
+                (context.gpregs.registers[3], context.el3_state.elr_el3)
+            })
+        };
+        let esr = |encoding: SysregEncoding, read: bool| {
+            EsrEl3::from_bits_retain(
+                0x18 << 26
+                    | u64::from(encoding.op0) << 20
+                    | u64::from(encoding.op2) << 17
+                    | u64::from(encoding.op1) << 14
+                    | u64::from(encoding.crn) << 10
+                    | 3 << 5
+                    | u64::from(encoding.crm) << 1
+                    | u64::from(read),
+            )
+        };
+
+        // Emulated read.
+        set_context(0xffff, 0x1000);
+        handle_sysreg_trap::<TestPlatform>(
+            esr(TestPlatform::EMULATED_IMPDEF_SYSREG, true),
+            World::NonSecure,
+        );
+        assert_eq!(get_context(), (0x1234, 0x1004));
+
+        // RAZ read.
+        set_context(0xffff, 0x1000);
+        handle_sysreg_trap::<TestPlatform>(
+            esr(TestPlatform::RAZ_WI_IMPDEF_SYSREG, true),
+            World::NonSecure,
+        );
+        assert_eq!(get_context(), (0, 0x1004));
+
+        // Ignored write.
+        set_context(0xffff, 0x1000);
+        handle_sysreg_trap::<TestPlatform>(
+            esr(TestPlatform::RAZ_WI_IMPDEF_SYSREG, false),
+            World::NonSecure,
+        );
+        assert_eq!(get_context(), (0xffff, 0x1004));
+
+        set_context(0, 0);
+    }
+
+    #[test]
+    fn unknown_hvc() {
+        assert_eq!(
+            TestPlatform::UNKNOWN_HVC_POLICY,
+            UnknownHvcPolicy::NotSupported
+        );
+
+        exception_free(|token| {
+            TestPlatform::cpu_state(token)[World::NonSecure]
+                .el3_state
+                .elr_el3 = 0x1004;
+        });
+        let mut regs = SmcReturn::EMPTY;
+        handle_unknown_hvc::<TestPlatform>(&mut regs, World::NonSecure);
+
+        assert_eq!(regs.values(), [NOT_SUPPORTED as u64]);
+        // The caller continues after the HVC.
+        assert_eq!(
+            exception_free(|token| {
+                TestPlatform::cpu_state(token)[World::NonSecure]
+                    .el3_state
+                    .elr_el3
+            }),
+            0x1004
+        );
+
+        exception_free(|token| {
+            TestPlatform::cpu_state(token)[World::NonSecure]
+                .el3_state
+                .elr_el3 = 0;
+        });
+    }
+
+    #[test]
+    fn init_phases() {
+        let services =
+            Services::<_, _, _, NON_CPU_DOMAIN_COUNT, _, TRNG_WORDS_IN_POOL, TestPlatform>::new(
+                || unimplemented!(),
+                || unimplemented!(),
+                || unimplemented!(),
+                &SMC_AUDIT,
+                &MAILBOX_STATE,
+                &PSCI_POWER_STATS,
+                &SDEI_STATE,
+                &DEFERRED_WORK,
+                &PMF,
+                Box::leak(Box::new(RoAfterInit::new())),
+            );
+
+        services.init(InitPhase::Early);
+        services.init(InitPhase::PostGic);
+        assert!(services.init_phase_done(InitPhase::PostGic));
+        assert!(!services.init_phase_done(InitPhase::PostContext));
+        services.init(InitPhase::PostContext);
+        services.init(InitPhase::Late);
+        assert!(services.init_phase_done(InitPhase::Late));
+    }
+
+    #[test]
+    #[should_panic(expected = "run out of order")]
+    fn init_phase_out_of_order() {
+        let services =
+            Services::<_, _, _, NON_CPU_DOMAIN_COUNT, _, TRNG_WORDS_IN_POOL, TestPlatform>::new(
+                || unimplemented!(),
+                || unimplemented!(),
+                || unimplemented!(),
+                &SMC_AUDIT,
+                &MAILBOX_STATE,
+                &PSCI_POWER_STATS,
+                &SDEI_STATE,
+                &DEFERRED_WORK,
+                &PMF,
+                Box::leak(Box::new(RoAfterInit::new())),
+            );
+
+        services.init(InitPhase::PostGic);
+        services.init(InitPhase::Early);
+    }
 }
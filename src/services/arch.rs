@@ -5,20 +5,23 @@
 //! Service for Arm architecture SMCs.
 
 use crate::{
-    context::World,
-    platform::Platform,
+    context::{CpuStateAccess, PerWorldContext, World, world_context},
+    platform::{Platform, exception_free},
+    rng::is_feat_rng_present,
     services::{Service, owns},
     smccc::{
         FunctionId, INVALID_PARAMETER, NOT_SUPPORTED, OwningEntityNumber, SUCCESS, SetFrom,
         SmcReturn, SmcccCallType,
     },
 };
+use arm_sysregs::{CptrEl3, MdcrEl3, ScrEl3};
 use core::marker::PhantomData;
 
 pub(crate) const SMCCC_VERSION: u32 = 0x8000_0000;
 const SMCCC_ARCH_FEATURES: u32 = 0x8000_0001;
 const SMCCC_ARCH_SOC_ID_32: u32 = 0x8000_0002;
 const SMCCC_ARCH_SOC_ID_64: u32 = 0xc000_0002;
+const SMCCC_ARCH_FEATURE_AVAILABILITY: u32 = 0xc000_0003;
 const SMCCC_ARCH_SOC_ID_VERSION: u32 = 0x0;
 const SMCCC_ARCH_SOC_ID_REVISION: u32 = 0x1;
 const SMCCC_ARCH_SOC_ID_NAME: u32 = 0x2;
@@ -27,6 +30,89 @@ const SMCCC_ARCH_WORKAROUND_2: u32 = 0x8000_7FFF;
 const SMCCC_ARCH_WORKAROUND_3: u32 = 0x8000_3FFF;
 const SMCCC_ARCH_WORKAROUND_4: u32 = 0x8000_0004;
 
+/// The SoC version and revision returned by SMCCC_ARCH_SOC_ID are non-negative 32-bit integers.
+const SOC_ID_MASK: u32 = 0x7fff_ffff;
+
+/// The JEP-106 continuation code of Arm Ltd.
+pub const ARM_JEP106_CONTINUATION_CODE: u8 = 0x4;
+/// The JEP-106 identification code of Arm Ltd, without its parity bit.
+pub const ARM_JEP106_IDENTIFICATION_CODE: u8 = 0x3b;
+
+// Encodings of the registers which SMCCC_ARCH_FEATURE_AVAILABILITY reports on, as
+// op0:op1:CRn:CRm:op2.
+const SCR_EL3_ENCODING: u32 = 0x1e_1100;
+const CPTR_EL3_ENCODING: u32 = 0x1e_1140;
+const MDCR_EL3_ENCODING: u32 = 0x1e_1320;
+
+/// The bits of `SCR_EL3` which control whether a feature is available to lower ELs.
+const SCR_EL3_FEATURES: FeatureBits = FeatureBits {
+    features: ScrEl3::TLOR.bits()
+        | ScrEl3::TERR.bits()
+        | ScrEl3::APK.bits()
+        | ScrEl3::API.bits()
+        | ScrEl3::EEL2.bits()
+        | ScrEl3::POE2EN.bits()
+        | ScrEl3::ENSCXT.bits()
+        | ScrEl3::ATA.bits()
+        | ScrEl3::FGTEN.bits()
+        | ScrEl3::ECVEN.bits()
+        | ScrEl3::TWEDEN.bits()
+        | ScrEl3::AMVOFFEN.bits()
+        | ScrEl3::ENAS0.bits()
+        | ScrEl3::ADEN.bits()
+        | ScrEl3::HXEN.bits()
+        | ScrEl3::GCSEN.bits()
+        | ScrEl3::ENTP2.bits()
+        | ScrEl3::RCWMASKEN.bits()
+        | ScrEl3::TCR2EN.bits()
+        | ScrEl3::SCTLR2EN.bits()
+        | ScrEl3::PIEN.bits()
+        | ScrEl3::AIEN.bits()
+        | ScrEl3::D128EN.bits()
+        | ScrEl3::MECEN.bits()
+        | ScrEl3::ENFPM.bits()
+        | ScrEl3::PFAREN.bits()
+        | ScrEl3::SRMASKEN.bits()
+        | ScrEl3::ENIDCP128.bits()
+        | ScrEl3::ENDSE.bits()
+        | ScrEl3::FGTEN2.bits()
+        | ScrEl3::HDBSSEN.bits()
+        | ScrEl3::HACDBSEN.bits()
+        | ScrEl3::TPLIMEN.bits(),
+    inverted: ScrEl3::TLOR.bits() | ScrEl3::TERR.bits(),
+};
+
+/// The bits of `CPTR_EL3` which control whether a feature is available to lower ELs.
+const CPTR_EL3_FEATURES: FeatureBits = FeatureBits {
+    features: CptrEl3::EZ.bits()
+        | CptrEl3::TFP.bits()
+        | CptrEl3::ESM.bits()
+        | CptrEl3::TTA.bits()
+        | CptrEl3::TAM.bits(),
+    inverted: CptrEl3::TFP.bits() | CptrEl3::TTA.bits() | CptrEl3::TAM.bits(),
+};
+
+/// The bits of `MDCR_EL3` which control whether a feature is available to lower ELs.
+const MDCR_EL3_FEATURES: FeatureBits = FeatureBits {
+    features: MdcrEl3::TPM.bits()
+        | MdcrEl3::NSPBE.bits()
+        | MdcrEl3::NSPB_NS.bits()
+        | MdcrEl3::TTRF.bits()
+        | MdcrEl3::NSTB_EN.bits()
+        | MdcrEl3::NSTB_SS.bits()
+        | MdcrEl3::NSTBE.bits()
+        | MdcrEl3::MTPME.bits()
+        | MdcrEl3::ENPMSN.bits()
+        | MdcrEl3::ENTB2.bits()
+        | MdcrEl3::ENPMS3.bits()
+        | MdcrEl3::EBWE.bits()
+        | MdcrEl3::ENPMSS.bits()
+        | MdcrEl3::ENITE.bits()
+        | MdcrEl3::ENSTEPOP.bits()
+        | MdcrEl3::ENPMS4.bits(),
+    inverted: MdcrEl3::TPM.bits() | MdcrEl3::TTRF.bits(),
+};
+
 pub(crate) const SMCCC_VERSION_1_5: i32 = 0x0001_0005;
 
 /// Arm architecture SMCs.
@@ -34,34 +120,34 @@ pub struct Arch<PlatformImpl: Platform> {
     _platform: PhantomData<PlatformImpl>,
 }
 
-impl<PlatformImpl: Platform> Service for Arch<PlatformImpl> {
+impl<PlatformImpl: CpuStateAccess + Platform> Service for Arch<PlatformImpl> {
     owns!(OwningEntityNumber::ARM_ARCHITECTURE);
 
     fn handle_non_secure_smc(&self, regs: &mut SmcReturn) -> World {
-        Self::handle_common_smc(regs);
+        Self::handle_common_smc(regs, World::NonSecure);
         World::NonSecure
     }
 
     fn handle_secure_smc(&self, regs: &mut SmcReturn) -> World {
-        Self::handle_common_smc(regs);
+        Self::handle_common_smc(regs, World::Secure);
         World::Secure
     }
 
     #[cfg(feature = "rme")]
     fn handle_realm_smc(&self, regs: &mut SmcReturn) -> World {
-        Self::handle_common_smc(regs);
+        Self::handle_common_smc(regs, World::Realm);
         World::Realm
     }
 }
 
-impl<PlatformImpl: Platform> Arch<PlatformImpl> {
+impl<PlatformImpl: CpuStateAccess + Platform> Arch<PlatformImpl> {
     pub(super) fn new() -> Self {
         Self {
             _platform: PhantomData,
         }
     }
 
-    fn handle_common_smc(regs: &mut SmcReturn) {
+    fn handle_common_smc(regs: &mut SmcReturn, world: World) {
         let in_regs = regs.values();
         let mut function = FunctionId(in_regs[0] as u32);
         function.clear_sve_hint();
@@ -70,8 +156,9 @@ impl<PlatformImpl: Platform> Arch<PlatformImpl> {
             SMCCC_VERSION => regs.set_from(version()),
             SMCCC_ARCH_FEATURES => Self::arch_features(regs),
             SMCCC_ARCH_SOC_ID_32 | SMCCC_ARCH_SOC_ID_64 => {
-                arch_soc_id(regs, function.call_type());
+                Self::arch_soc_id(regs, function.call_type());
             }
+            SMCCC_ARCH_FEATURE_AVAILABILITY => Self::arch_feature_availability(regs, world),
             SMCCC_ARCH_WORKAROUND_1 => {
                 Self::arch_workaround_1();
                 regs.mark_empty();
@@ -92,8 +179,13 @@ impl<PlatformImpl: Platform> Arch<PlatformImpl> {
         let arch_func_id = regs.values()[1] as u32;
 
         let result = match arch_func_id {
-            SMCCC_VERSION | SMCCC_ARCH_FEATURES | SMCCC_ARCH_SOC_ID_32 | SMCCC_ARCH_SOC_ID_64 => {
-                SUCCESS
+            SMCCC_VERSION | SMCCC_ARCH_FEATURES | SMCCC_ARCH_FEATURE_AVAILABILITY => SUCCESS,
+            SMCCC_ARCH_SOC_ID_32 | SMCCC_ARCH_SOC_ID_64 => {
+                if PlatformImpl::soc_id_version().is_some() {
+                    SUCCESS
+                } else {
+                    NOT_SUPPORTED
+                }
             }
             SMCCC_ARCH_WORKAROUND_1 => PlatformImpl::arch_workaround_1_supported() as i32,
             SMCCC_ARCH_WORKAROUND_2 => PlatformImpl::arch_workaround_2_supported() as i32,
@@ -105,6 +197,50 @@ impl<PlatformImpl: Platform> Arch<PlatformImpl> {
         regs.set_from(result);
     }
 
+    /// Reports the SoC version, revision or name, as specified in §7.4 of [the Arm SMC Calling
+    /// Convention](https://developer.arm.com/documentation/den0028/galp1/?lang=en).
+    fn arch_soc_id(regs: &mut SmcReturn, call_type: SmcccCallType) {
+        let Some(soc_version) = PlatformImpl::soc_id_version() else {
+            regs.set_from(NOT_SUPPORTED);
+            return;
+        };
+        let soc_id_type = regs.values()[1] as u32;
+
+        // According to the SMCCC spec, section 7.4.6, the SoC version and revision must uniquely
+        // identify the SoC, and the SoC name must not contain any identifying information not
+        // captured by them.
+        match soc_id_type {
+            SMCCC_ARCH_SOC_ID_VERSION => regs.set_from((soc_version & SOC_ID_MASK) as i32),
+            SMCCC_ARCH_SOC_ID_REVISION => {
+                regs.set_from((PlatformImpl::soc_id_revision() & SOC_ID_MASK) as i32)
+            }
+            SMCCC_ARCH_SOC_ID_NAME if call_type == SmcccCallType::Fast64 => {
+                regs.set_args5(
+                    // TODO: Implement this properly.
+                    0u64, // w0
+                    u64::from_le_bytes([b'm', b'I', b' ', b':', b'O', b'D', b'O', b'T']),
+                    u64::from_le_bytes([b' ', b't', b'n', b'e', b'm', b'e', b'l', b'p']),
+                    u64::from_le_bytes([b'o', b'r', b'p', b' ', b's', b'i', b'h', b't']),
+                    u64::from_le_bytes([0x00, 0x00, b'.', b'y', b'l', b'r', b'e', b'p']),
+                );
+            }
+            _ => regs.set_from(INVALID_PARAMETER),
+        }
+    }
+
+    /// Reports which features controlled by the given EL3 register EL3 has enabled for the calling
+    /// world, as specified by the SMCCC.
+    fn arch_feature_availability(regs: &mut SmcReturn, world: World) {
+        let register = regs.values()[1] as u32;
+        let mdcr_el3 =
+            exception_free(|token| PlatformImpl::cpu_state(token)[world].el3_state.mdcr_el3);
+
+        match feature_availability(register, world_context(world), mdcr_el3) {
+            Some(available) => regs.set_args2(SUCCESS as u64, available),
+            None => regs.set_from(INVALID_PARAMETER),
+        }
+    }
+
     /// Execute the mitigation for CVE-2017-5715 on the calling PE.
     fn arch_workaround_1() {
         if PlatformImpl::arch_workaround_1_supported() == WorkaroundSupport::Required {
@@ -138,32 +274,130 @@ pub enum WorkaroundSupport {
     SafeButNotRequired = 1,
 }
 
+/// The bits of an EL3 control register which control whether a feature is available to lower ELs.
+struct FeatureBits {
+    /// All bits which control a feature.
+    features: u64,
+    /// The subset of `features` which make the feature unavailable when set, such as trap bits.
+    inverted: u64,
+}
+
+impl FeatureBits {
+    /// Returns the given register value with each feature bit set if the feature it controls is
+    /// available, and all other bits clear.
+    const fn available(&self, value: u64) -> u64 {
+        (value ^ self.inverted) & self.features
+    }
+}
+
+/// Returns a bitmask of the features controlled by the register with the given encoding which are
+/// available to a world with the given EL3 configuration, or `None` if the register isn't supported.
+fn feature_availability(
+    register: u32,
+    per_world_context: &PerWorldContext,
+    mdcr_el3: MdcrEl3,
+) -> Option<u64> {
+    match register {
+        SCR_EL3_ENCODING => {
+            let scr_el3 = per_world_context.scr_el3;
+            let mut available = SCR_EL3_FEATURES.available(scr_el3.bits());
+            // RNDR is available either directly, or emulated by EL3 if it traps.
+            if scr_el3.contains(ScrEl3::TRNDR) || is_feat_rng_present() {
+                available |= ScrEl3::TRNDR.bits();
+            }
+            Some(available)
+        }
+        CPTR_EL3_ENCODING => Some(CPTR_EL3_FEATURES.available(per_world_context.cptr_el3.bits())),
+        MDCR_EL3_ENCODING => Some(MDCR_EL3_FEATURES.available(mdcr_el3.bits())),
+        _ => None,
+    }
+}
+
+/// Returns the SoC version to report for SMCCC_ARCH_SOC_ID, given the JEP-106 continuation code and
+/// identification code of the SoC implementer, and an implementation defined SoC ID.
+pub const fn soc_id_version(
+    jep106_continuation_code: u8,
+    jep106_identification_code: u8,
+    soc_id: u16,
+) -> u32 {
+    ((jep106_continuation_code as u32 & 0x7f) << 24)
+        | ((jep106_identification_code as u32 & 0x7f) << 16)
+        | soc_id as u32
+}
+
 fn version() -> i32 {
     SMCCC_VERSION_1_5
 }
 
-/// This SMC is specified in §7.4 of [the Arm SMC Calling
-/// Convention](https://developer.arm.com/documentation/den0028/galp1/?lang=en).
-fn arch_soc_id(regs: &mut SmcReturn, call_type: SmcccCallType) {
-    let soc_id_type = regs.values()[1] as u32;
-
-    // TODO/NOTE: Note that according to the SMCCC spec, section 7.4.6: we "must
-    // ensure that SoC version and revision uniquely identify the SoC", and "SoC
-    // name must not contain SoC identifying information not captured by <SoC
-    // version, SoC revision>."
-    match soc_id_type {
-        SMCCC_ARCH_SOC_ID_VERSION => regs.set_from(0), // TODO: Implement this properly.
-        SMCCC_ARCH_SOC_ID_REVISION => regs.set_from(0), // TODO: Implement this properly.
-        SMCCC_ARCH_SOC_ID_NAME if call_type == SmcccCallType::Fast64 => {
-            regs.set_args5(
-                // TODO: Implement this properly.
-                0u64, // w0
-                u64::from_le_bytes([b'm', b'I', b' ', b':', b'O', b'D', b'O', b'T']),
-                u64::from_le_bytes([b' ', b't', b'n', b'e', b'm', b'e', b'l', b'p']),
-                u64::from_le_bytes([b'o', b'r', b'p', b' ', b's', b'i', b'h', b't']),
-                u64::from_le_bytes([0x00, 0x00, b'.', b'y', b'l', b'r', b'e', b'p']),
-            );
-        }
-        _ => regs.set_from(INVALID_PARAMETER),
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::platform::test::TestPlatform;
+
+    #[test]
+    fn soc_id() {
+        let mut regs = SmcReturn::EMPTY;
+
+        regs.set_args2(SMCCC_ARCH_FEATURES.into(), SMCCC_ARCH_SOC_ID_32.into());
+        Arch::<TestPlatform>::handle_common_smc(&mut regs, World::NonSecure);
+        assert_eq!(regs.values(), [SUCCESS as u64]);
+
+        regs.set_args2(
+            SMCCC_ARCH_SOC_ID_32.into(),
+            SMCCC_ARCH_SOC_ID_VERSION.into(),
+        );
+        Arch::<TestPlatform>::handle_common_smc(&mut regs, World::NonSecure);
+        assert_eq!(regs.values(), [0x043b_1234]);
+
+        regs.set_args2(
+            SMCCC_ARCH_SOC_ID_64.into(),
+            SMCCC_ARCH_SOC_ID_REVISION.into(),
+        );
+        Arch::<TestPlatform>::handle_common_smc(&mut regs, World::NonSecure);
+        assert_eq!(regs.values(), [2]);
+
+        regs.set_args2(SMCCC_ARCH_SOC_ID_32.into(), 3);
+        Arch::<TestPlatform>::handle_common_smc(&mut regs, World::NonSecure);
+        assert_eq!(regs.values(), [INVALID_PARAMETER as u64]);
+    }
+
+    #[test]
+    fn soc_id_version_encoding() {
+        assert_eq!(
+            soc_id_version(
+                ARM_JEP106_CONTINUATION_CODE,
+                ARM_JEP106_IDENTIFICATION_CODE,
+                0
+            ),
+            0x043b_0000
+        );
+        assert_eq!(soc_id_version(0xff, 0xff, 0xffff), 0x7f7f_ffff);
+    }
+
+    #[test]
+    fn feature_availability_inverts_trap_bits() {
+        let per_world_context = PerWorldContext {
+            cptr_el3: CptrEl3::EZ | CptrEl3::TAM,
+            scr_el3: ScrEl3::NS | ScrEl3::FGTEN | ScrEl3::TERR,
+            ..Default::default()
+        };
+        let mdcr_el3 = MdcrEl3::SDD | MdcrEl3::TTRF | MdcrEl3::NSPB_NS;
+
+        assert_eq!(
+            feature_availability(SCR_EL3_ENCODING, &per_world_context, mdcr_el3),
+            Some((ScrEl3::TLOR | ScrEl3::FGTEN).bits())
+        );
+        assert_eq!(
+            feature_availability(CPTR_EL3_ENCODING, &per_world_context, mdcr_el3),
+            Some((CptrEl3::EZ | CptrEl3::TFP | CptrEl3::TTA).bits())
+        );
+        assert_eq!(
+            feature_availability(MDCR_EL3_ENCODING, &per_world_context, mdcr_el3),
+            Some((MdcrEl3::TPM | MdcrEl3::NSPB_NS).bits())
+        );
+        assert_eq!(
+            feature_availability(0x1e_1000, &per_world_context, mdcr_el3),
+            None
+        );
     }
 }
@@ -0,0 +1,748 @@
+// Copyright The Rusted Firmware-A Contributors.
+//
+// SPDX-License-Identifier: BSD-3-Clause
+
+//! Vendor-specific debug service, exposing runtime statistics collected by EL3 to the normal world.
+//!
+//! The service is a [`VendorHandler`] registered with the vendor-specific EL3 monitor service like
+//! any other, so its function numbers are checked against those of the other handlers.
+
+use crate::{
+    build_info::BUILD_INFO,
+    platform::Platform,
+    runtime_config::runtime_config,
+    services::{ffa::spmd::Spmd, vendor::VendorHandler},
+    smccc::{FunctionId, INVALID_PARAMETER, NOT_SUPPORTED, SUCCESS, SetFrom, SmcReturn},
+    spin_mutex::SpinMutex,
+};
+use arm_sysregs::read_cntpct_el0;
+use arrayvec::ArrayVec;
+use core::{
+    fmt::{self, Debug, Display, Formatter},
+    ops::RangeInclusive,
+    sync::atomic::{AtomicU64, Ordering::Relaxed},
+};
+use log::info;
+use num_enum::TryFromPrimitive;
+
+const FUNCTION_NUMBER_MIN: u16 = 0x0010;
+const FUNCTION_NUMBER_MAX: u16 = 0x001F;
+
+const DEBUG_VERSION: u32 = 0x8700_0010;
+const DEBUG_WORLD_SWITCH_STATS: u32 = 0xC700_0011;
+const DEBUG_SUSPEND_STATS: u32 = 0xC700_0012;
+const DEBUG_INTERRUPT_LATENCY_STATS: u32 = 0xC700_0013;
+const DEBUG_BUILD_INFO: u32 = 0xC700_0014;
+const DEBUG_DUMP_POWER_DOMAINS: u32 = 0xC700_0015;
+
+/// The maximum number of distinct `CPU_SUSPEND` power states for which statistics are kept.
+pub const SUSPEND_STATS_MAX_STATES: usize = 16;
+
+/// The number of FF-A calls forwarded to the SPMC which are remembered for each core.
+pub const SMC_AUDIT_DEPTH: usize = 8;
+
+const VERSION_1_0: u32 = 0x0001_0000;
+
+/// The reason for a world switch between the normal and secure worlds.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, TryFromPrimitive)]
+#[repr(u64)]
+pub enum WorldSwitchReason {
+    /// An SMC from the normal world forwarded to the secure world.
+    NonSecureSmc = 0,
+    /// A secure interrupt taken while in the normal world.
+    SecureInterrupt = 1,
+    /// The secure world completing an FF-A call, returning to the normal world.
+    FfaCompletion = 2,
+    /// A PSCI event forwarded to or completed by the secure world.
+    PsciEvent = 3,
+}
+
+impl WorldSwitchReason {
+    const COUNT: usize = 4;
+}
+
+/// The number of world switches for some reason, and the total number of counter ticks they spent
+/// at EL3.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct WorldSwitchCounter {
+    /// The number of world switches.
+    pub count: u64,
+    /// The total number of generic timer ticks spent at EL3 performing the world switches.
+    pub ticks: u64,
+}
+
+#[derive(Debug)]
+struct AtomicWorldSwitchCounter {
+    count: AtomicU64,
+    ticks: AtomicU64,
+}
+
+impl AtomicWorldSwitchCounter {
+    const fn new() -> Self {
+        Self {
+            count: AtomicU64::new(0),
+            ticks: AtomicU64::new(0),
+        }
+    }
+}
+
+/// Per-core world switch counters, by reason.
+///
+/// Each core only updates its own counters, but any core may read them.
+#[derive(Debug)]
+pub struct WorldSwitchStats<const CORE_COUNT: usize> {
+    counters: [[AtomicWorldSwitchCounter; WorldSwitchReason::COUNT]; CORE_COUNT],
+}
+
+impl<const CORE_COUNT: usize> WorldSwitchStats<CORE_COUNT> {
+    /// Creates a new set of counters, all zero.
+    pub const fn new() -> Self {
+        Self {
+            counters: [const { [const { AtomicWorldSwitchCounter::new() }; WorldSwitchReason::COUNT] };
+                CORE_COUNT],
+        }
+    }
+
+    /// Returns the current value of the generic timer counter, to be passed to `record` once the
+    /// world switch is complete.
+    pub fn timestamp() -> u64 {
+        read_cntpct_el0().physicalcount()
+    }
+
+    /// Records a world switch on the given core, which started at `start` as returned by
+    /// `timestamp`.
+    pub fn record(&self, core_index: usize, reason: WorldSwitchReason, start: u64) {
+        let counter = &self.counters[core_index][reason as usize];
+        counter.count.fetch_add(1, Relaxed);
+        counter
+            .ticks
+            .fetch_add(Self::timestamp().wrapping_sub(start), Relaxed);
+    }
+
+    /// Returns the counter for the given core and reason, or `None` if the core index is out of
+    /// range.
+    pub fn get(&self, core_index: usize, reason: WorldSwitchReason) -> Option<WorldSwitchCounter> {
+        let counter = &self.counters.get(core_index)?[reason as usize];
+        Some(WorldSwitchCounter {
+            count: counter.count.load(Relaxed),
+            ticks: counter.ticks.load(Relaxed),
+        })
+    }
+}
+
+impl<const CORE_COUNT: usize> Default for WorldSwitchStats<CORE_COUNT> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A part of the handling of an interrupt taken to EL3, whose latency is measured separately.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, TryFromPrimitive)]
+#[repr(u64)]
+pub enum InterruptLatencyPhase {
+    /// From EL3 taking the interrupt until it is ready to return to a lower EL, either having
+    /// handled the interrupt itself or having prepared to forward it to the secure world.
+    El3Handling = 0,
+    /// From the SPMD forwarding a secure interrupt to the SPMC until the SPMC asks to resume the
+    /// normal world.
+    SpmcDelegation = 1,
+}
+
+impl InterruptLatencyPhase {
+    const COUNT: usize = 2;
+}
+
+/// The number of interrupts handled in some phase, and the total and maximum number of counter
+/// ticks they took.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct LatencyCounter {
+    /// The number of interrupts measured.
+    pub count: u64,
+    /// The total number of generic timer ticks taken.
+    pub ticks: u64,
+    /// The largest number of generic timer ticks taken by any single interrupt.
+    pub max_ticks: u64,
+}
+
+#[derive(Debug)]
+struct AtomicLatencyCounter {
+    count: AtomicU64,
+    ticks: AtomicU64,
+    max_ticks: AtomicU64,
+}
+
+impl AtomicLatencyCounter {
+    const fn new() -> Self {
+        Self {
+            count: AtomicU64::new(0),
+            ticks: AtomicU64::new(0),
+            max_ticks: AtomicU64::new(0),
+        }
+    }
+}
+
+/// Per-core interrupt latency counters, by phase.
+///
+/// Measurements are only taken if enabled by `RuntimeConfig::measure_interrupt_latency`, so that
+/// the generic timer isn't read on every interrupt otherwise. Each core only updates its own
+/// counters, but any core may read them.
+#[derive(Debug)]
+pub struct InterruptLatencyStats<const CORE_COUNT: usize> {
+    counters: [[AtomicLatencyCounter; InterruptLatencyPhase::COUNT]; CORE_COUNT],
+}
+
+impl<const CORE_COUNT: usize> InterruptLatencyStats<CORE_COUNT> {
+    /// Creates a new set of counters, all zero.
+    pub const fn new() -> Self {
+        Self {
+            counters: [const { [const { AtomicLatencyCounter::new() }; InterruptLatencyPhase::COUNT] };
+                CORE_COUNT],
+        }
+    }
+
+    /// Returns the current value of the generic timer counter if interrupt latency measurement is
+    /// enabled, to be passed to `record` at the end of the phase being measured.
+    pub fn start() -> Option<u64> {
+        runtime_config()
+            .measure_interrupt_latency
+            .then(|| read_cntpct_el0().physicalcount())
+    }
+
+    /// Records the end of a phase of interrupt handling on the given core, which started at
+    /// `start` as returned by `start`. Does nothing if `start` is `None`.
+    pub fn record(&self, core_index: usize, phase: InterruptLatencyPhase, start: Option<u64>) {
+        let Some(start) = start else {
+            return;
+        };
+        let ticks = read_cntpct_el0().physicalcount().wrapping_sub(start);
+        let counter = &self.counters[core_index][phase as usize];
+        counter.count.fetch_add(1, Relaxed);
+        counter.ticks.fetch_add(ticks, Relaxed);
+        counter.max_ticks.fetch_max(ticks, Relaxed);
+    }
+
+    /// Returns the counter for the given core and phase, or `None` if the core index is out of
+    /// range.
+    pub fn get(&self, core_index: usize, phase: InterruptLatencyPhase) -> Option<LatencyCounter> {
+        let counter = &self.counters.get(core_index)?[phase as usize];
+        Some(LatencyCounter {
+            count: counter.count.load(Relaxed),
+            ticks: counter.ticks.load(Relaxed),
+            max_ticks: counter.max_ticks.load(Relaxed),
+        })
+    }
+}
+
+impl<const CORE_COUNT: usize> Default for InterruptLatencyStats<CORE_COUNT> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// An FF-A call forwarded from the normal world to the SPMC.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct AuditedCall {
+    /// The FF-A function ID, from x0.
+    pub function_id: u32,
+    /// The first three arguments of the call, from x1 to x3.
+    pub args: [u64; 3],
+}
+
+impl Display for AuditedCall {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:#010x}({:#x}, {:#x}, {:#x})",
+            self.function_id, self.args[0], self.args[1], self.args[2]
+        )
+    }
+}
+
+#[derive(Debug)]
+struct AuditRing {
+    calls: [AuditedCall; SMC_AUDIT_DEPTH],
+    /// The total number of calls ever recorded, so the next one goes at this index modulo the
+    /// depth.
+    recorded: usize,
+}
+
+/// Per-core ring buffers of the last [`SMC_AUDIT_DEPTH`] FF-A calls forwarded to the SPMC.
+///
+/// These are dumped if the SPMC reports a fatal error, to show what the normal world asked it to do
+/// just before.
+#[derive(Debug)]
+pub struct SmcAuditBuffer<const CORE_COUNT: usize> {
+    cores: [SpinMutex<AuditRing>; CORE_COUNT],
+}
+
+impl<const CORE_COUNT: usize> SmcAuditBuffer<CORE_COUNT> {
+    /// Creates a new set of empty buffers.
+    pub const fn new() -> Self {
+        Self {
+            cores: [const {
+                SpinMutex::new(AuditRing {
+                    calls: [AuditedCall {
+                        function_id: 0,
+                        args: [0; 3],
+                    }; SMC_AUDIT_DEPTH],
+                    recorded: 0,
+                })
+            }; CORE_COUNT],
+        }
+    }
+
+    /// Records a call forwarded to the SPMC on the given core, given the registers passed to it.
+    pub fn record(&self, core_index: usize, regs: &[u64]) {
+        let mut ring = self.cores[core_index].lock();
+        let index = ring.recorded % SMC_AUDIT_DEPTH;
+        ring.calls[index] = AuditedCall {
+            function_id: regs[0] as u32,
+            args: [regs[1], regs[2], regs[3]],
+        };
+        ring.recorded += 1;
+    }
+
+    /// Returns the calls most recently forwarded to the SPMC on the given core, oldest first.
+    pub fn recent(&self, core_index: usize) -> ArrayVec<AuditedCall, SMC_AUDIT_DEPTH> {
+        let ring = self.cores[core_index].lock();
+        (ring.recorded.saturating_sub(SMC_AUDIT_DEPTH)..ring.recorded)
+            .map(|i| ring.calls[i % SMC_AUDIT_DEPTH])
+            .collect()
+    }
+}
+
+impl<const CORE_COUNT: usize> Default for SmcAuditBuffer<CORE_COUNT> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The number of times some `CPU_SUSPEND` power state was requested, and how many of those requests
+/// were aborted because an interrupt was already pending.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct SuspendCounter {
+    /// The number of valid `CPU_SUSPEND` calls requesting the power state.
+    pub requested: u64,
+    /// The number of those calls which returned immediately due to a pending interrupt.
+    pub aborted: u64,
+}
+
+/// `CPU_SUSPEND` counters for each distinct power state requested, shared by all cores.
+///
+/// Statistics are kept for the first [`SUSPEND_STATS_MAX_STATES`] distinct power states requested;
+/// requests for any further states are only counted in `untracked`.
+#[derive(Debug, Default)]
+pub struct SuspendStats {
+    states: SpinMutex<ArrayVec<(u32, SuspendCounter), SUSPEND_STATS_MAX_STATES>>,
+    untracked: AtomicU64,
+}
+
+impl SuspendStats {
+    /// Creates a new empty set of counters.
+    pub const fn new() -> Self {
+        Self {
+            states: SpinMutex::new(ArrayVec::new_const()),
+            untracked: AtomicU64::new(0),
+        }
+    }
+
+    /// Records a `CPU_SUSPEND` request for the given power state.
+    pub fn record_request(&self, power_state: u32) {
+        self.update(power_state, |counter| counter.requested += 1);
+    }
+
+    /// Records that a `CPU_SUSPEND` request for the given power state was aborted due to a pending
+    /// interrupt.
+    pub fn record_abort(&self, power_state: u32) {
+        self.update(power_state, |counter| counter.aborted += 1);
+    }
+
+    fn update(&self, power_state: u32, f: impl FnOnce(&mut SuspendCounter)) {
+        let mut states = self.states.lock();
+        if let Some((_, counter)) = states.iter_mut().find(|(state, _)| *state == power_state) {
+            f(counter);
+        } else {
+            let mut counter = SuspendCounter::default();
+            f(&mut counter);
+            if states.try_push((power_state, counter)).is_err() {
+                self.untracked.fetch_add(1, Relaxed);
+            }
+        }
+    }
+
+    /// Returns the power state and counter in the given slot, in the order in which power states
+    /// were first requested, or `None` if there aren't that many distinct states yet.
+    pub fn get(&self, index: usize) -> Option<(u32, SuspendCounter)> {
+        self.states.lock().get(index).copied()
+    }
+
+    /// Returns the number of requests or aborts for power states which didn't fit in the table.
+    pub fn untracked(&self) -> u64 {
+        self.untracked.load(Relaxed)
+    }
+}
+
+/// Vendor-specific EL3 monitor handler for querying debug information.
+pub struct DebugService<const CORE_COUNT: usize, PlatformImpl: Platform + 'static> {
+    spm: fn() -> &'static Spmd<CORE_COUNT, PlatformImpl>,
+    suspend_stats: fn() -> &'static SuspendStats,
+    psci_state: fn() -> &'static dyn Debug,
+}
+
+impl<const CORE_COUNT: usize, PlatformImpl: Platform> DebugService<CORE_COUNT, PlatformImpl> {
+    pub(super) fn new(
+        spm: fn() -> &'static Spmd<CORE_COUNT, PlatformImpl>,
+        suspend_stats: fn() -> &'static SuspendStats,
+        psci_state: fn() -> &'static dyn Debug,
+    ) -> Self {
+        Self {
+            spm,
+            suspend_stats,
+            psci_state,
+        }
+    }
+
+    fn world_switch_stats(&self, regs: &mut SmcReturn) {
+        let in_regs = regs.values();
+        let core_index = in_regs[1] as usize;
+        let reason = in_regs[2];
+
+        let Some(counter) = WorldSwitchReason::try_from(reason)
+            .ok()
+            .and_then(|reason| (self.spm)().world_switch_stats().get(core_index, reason))
+        else {
+            regs.set_from(INVALID_PARAMETER);
+            return;
+        };
+
+        regs.set_args3(SUCCESS as u64, counter.count, counter.ticks);
+    }
+
+    fn interrupt_latency_stats(&self, regs: &mut SmcReturn) {
+        let in_regs = regs.values();
+        let core_index = in_regs[1] as usize;
+        let phase = in_regs[2];
+
+        let Some(counter) = InterruptLatencyPhase::try_from(phase)
+            .ok()
+            .and_then(|phase| {
+                (self.spm)()
+                    .interrupt_latency_stats()
+                    .get(core_index, phase)
+            })
+        else {
+            regs.set_from(INVALID_PARAMETER);
+            return;
+        };
+
+        regs.set_args4(
+            SUCCESS as u64,
+            counter.count,
+            counter.ticks,
+            counter.max_ticks,
+        );
+    }
+
+    fn suspend_stats(&self, regs: &mut SmcReturn) {
+        let index = regs.values()[1] as usize;
+        let stats = (self.suspend_stats)();
+
+        let Some((power_state, counter)) = stats.get(index) else {
+            regs.set_from(INVALID_PARAMETER);
+            return;
+        };
+
+        regs.set_args5(
+            SUCCESS as u64,
+            power_state.into(),
+            counter.requested,
+            counter.aborted,
+            stats.untracked(),
+        );
+    }
+}
+
+impl<const CORE_COUNT: usize, PlatformImpl: Platform> VendorHandler
+    for DebugService<CORE_COUNT, PlatformImpl>
+{
+    fn function_numbers(&self) -> RangeInclusive<u16> {
+        FUNCTION_NUMBER_MIN..=FUNCTION_NUMBER_MAX
+    }
+
+    fn handle_non_secure_smc(&self, function: FunctionId, regs: &mut SmcReturn) {
+        match function.0 {
+            DEBUG_VERSION => regs.set_from(VERSION_1_0),
+            DEBUG_WORLD_SWITCH_STATS => self.world_switch_stats(regs),
+            DEBUG_SUSPEND_STATS => self.suspend_stats(regs),
+            DEBUG_INTERRUPT_LATENCY_STATS => self.interrupt_latency_stats(regs),
+            DEBUG_BUILD_INFO => regs.set_args5(
+                SUCCESS as u64,
+                BUILD_INFO.version(),
+                BUILD_INFO.git_revision,
+                BUILD_INFO.config_hash,
+                BUILD_INFO.git_dirty.into(),
+            ),
+            // The dump is long and written to the console synchronously, so only debug builds let
+            // the normal world trigger it.
+            DEBUG_DUMP_POWER_DOMAINS if cfg!(debug_assertions) => {
+                // Log the power domain tree, to help diagnose cores which are stuck in some power
+                // state.
+                info!("PSCI state:\n{:?}", (self.psci_state)());
+                regs.set_from(SUCCESS);
+            }
+            _ => regs.set_from(NOT_SUPPORTED),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        platform::test::TestPlatform,
+        services::{deferred::DeferredWorkQueue, ffa::spmd::MailboxState},
+    };
+    use arm_sysregs::{CntpctEl0, fake::SYSREGS};
+    use std::sync::LazyLock;
+
+    static SMC_AUDIT: SmcAuditBuffer<{ TestPlatform::CORE_COUNT }> = SmcAuditBuffer::new();
+    static DEFERRED_WORK: DeferredWorkQueue<{ TestPlatform::CORE_COUNT }, TestPlatform> =
+        DeferredWorkQueue::new();
+    static MAILBOX_STATE: MailboxState<{ TestPlatform::CORE_COUNT }> = MailboxState::new();
+    static SPMD: LazyLock<Spmd<{ TestPlatform::CORE_COUNT }, TestPlatform>> =
+        LazyLock::new(|| Spmd::new(&SMC_AUDIT, &DEFERRED_WORK, &MAILBOX_STATE));
+    static SUSPEND_STATS: SuspendStats = SuspendStats::new();
+
+    fn call(
+        service: &DebugService<{ TestPlatform::CORE_COUNT }, TestPlatform>,
+        regs: &mut SmcReturn,
+    ) {
+        service.handle_non_secure_smc(FunctionId(regs.values()[0] as u32), regs);
+    }
+
+    fn set_counter(value: u64) {
+        SYSREGS.lock().unwrap().cntpct_el0 = CntpctEl0::from_bits_retain(value);
+    }
+
+    #[test]
+    fn record_world_switch() {
+        let stats = WorldSwitchStats::<2>::new();
+
+        set_counter(100);
+        stats.record(1, WorldSwitchReason::SecureInterrupt, 30);
+        set_counter(200);
+        stats.record(1, WorldSwitchReason::SecureInterrupt, 190);
+
+        assert_eq!(
+            stats.get(1, WorldSwitchReason::SecureInterrupt),
+            Some(WorldSwitchCounter {
+                count: 2,
+                ticks: 80
+            })
+        );
+        assert_eq!(
+            stats.get(0, WorldSwitchReason::SecureInterrupt),
+            Some(WorldSwitchCounter::default())
+        );
+        assert_eq!(
+            stats.get(1, WorldSwitchReason::NonSecureSmc),
+            Some(WorldSwitchCounter::default())
+        );
+        assert_eq!(stats.get(2, WorldSwitchReason::NonSecureSmc), None);
+
+        SYSREGS.lock().unwrap().reset();
+    }
+
+    #[test]
+    fn query_world_switch_stats() {
+        let service = DebugService::new(|| &SPMD, || &SUSPEND_STATS, || &());
+
+        let mut regs = SmcReturn::EMPTY;
+        regs.mark_all_used()[..3].copy_from_slice(&[
+            DEBUG_WORLD_SWITCH_STATS.into(),
+            0,
+            WorldSwitchReason::PsciEvent as u64,
+        ]);
+        call(&service, &mut regs);
+        assert_eq!(regs.values(), [SUCCESS as u64, 0, 0]);
+
+        let mut regs = SmcReturn::EMPTY;
+        regs.mark_all_used()[..3].copy_from_slice(&[
+            DEBUG_WORLD_SWITCH_STATS.into(),
+            TestPlatform::CORE_COUNT as u64,
+            WorldSwitchReason::PsciEvent as u64,
+        ]);
+        call(&service, &mut regs);
+        assert_eq!(regs.values(), [INVALID_PARAMETER as u64]);
+
+        let mut regs = SmcReturn::EMPTY;
+        regs.mark_all_used()[..3].copy_from_slice(&[DEBUG_WORLD_SWITCH_STATS.into(), 0, 4]);
+        call(&service, &mut regs);
+        assert_eq!(regs.values(), [INVALID_PARAMETER as u64]);
+    }
+
+    #[test]
+    fn record_interrupt_latency() {
+        let stats = InterruptLatencyStats::<2>::new();
+
+        // Measurement is disabled by default.
+        assert_eq!(InterruptLatencyStats::<2>::start(), None);
+        stats.record(0, InterruptLatencyPhase::El3Handling, None);
+
+        set_counter(100);
+        stats.record(0, InterruptLatencyPhase::El3Handling, Some(60));
+        set_counter(200);
+        stats.record(0, InterruptLatencyPhase::El3Handling, Some(190));
+
+        assert_eq!(
+            stats.get(0, InterruptLatencyPhase::El3Handling),
+            Some(LatencyCounter {
+                count: 2,
+                ticks: 50,
+                max_ticks: 40,
+            })
+        );
+        assert_eq!(
+            stats.get(1, InterruptLatencyPhase::El3Handling),
+            Some(LatencyCounter::default())
+        );
+        assert_eq!(
+            stats.get(0, InterruptLatencyPhase::SpmcDelegation),
+            Some(LatencyCounter::default())
+        );
+        assert_eq!(stats.get(2, InterruptLatencyPhase::El3Handling), None);
+
+        SYSREGS.lock().unwrap().reset();
+    }
+
+    #[test]
+    fn query_interrupt_latency_stats() {
+        let service = DebugService::new(|| &SPMD, || &SUSPEND_STATS, || &());
+
+        let mut regs = SmcReturn::EMPTY;
+        regs.mark_all_used()[..3].copy_from_slice(&[
+            DEBUG_INTERRUPT_LATENCY_STATS.into(),
+            0,
+            InterruptLatencyPhase::SpmcDelegation as u64,
+        ]);
+        call(&service, &mut regs);
+        assert_eq!(regs.values(), [SUCCESS as u64, 0, 0, 0]);
+
+        let mut regs = SmcReturn::EMPTY;
+        regs.mark_all_used()[..3].copy_from_slice(&[DEBUG_INTERRUPT_LATENCY_STATS.into(), 0, 2]);
+        call(&service, &mut regs);
+        assert_eq!(regs.values(), [INVALID_PARAMETER as u64]);
+    }
+
+    #[test]
+    fn smc_audit_ring() {
+        let audit = SmcAuditBuffer::<2>::new();
+        assert!(audit.recent(1).is_empty());
+
+        for i in 0..SMC_AUDIT_DEPTH as u64 + 2 {
+            audit.record(1, &[0x8400_006f, i, 0, 0, 42]);
+        }
+        let recent = audit.recent(1);
+        assert_eq!(recent.len(), SMC_AUDIT_DEPTH);
+        assert_eq!(
+            recent[0],
+            AuditedCall {
+                function_id: 0x8400_006f,
+                args: [2, 0, 0],
+            }
+        );
+        assert_eq!(
+            recent[SMC_AUDIT_DEPTH - 1].args[0],
+            SMC_AUDIT_DEPTH as u64 + 1
+        );
+        assert!(audit.recent(0).is_empty());
+
+        assert_eq!(recent[0].to_string(), "0x8400006f(0x2, 0x0, 0x0)");
+    }
+
+    #[test]
+    fn record_suspend() {
+        let stats = SuspendStats::new();
+
+        stats.record_request(0x4001_0000);
+        stats.record_request(0x0000_0001);
+        stats.record_request(0x4001_0000);
+        stats.record_abort(0x4001_0000);
+
+        assert_eq!(
+            stats.get(0),
+            Some((
+                0x4001_0000,
+                SuspendCounter {
+                    requested: 2,
+                    aborted: 1
+                }
+            ))
+        );
+        assert_eq!(
+            stats.get(1),
+            Some((
+                0x0000_0001,
+                SuspendCounter {
+                    requested: 1,
+                    aborted: 0
+                }
+            ))
+        );
+        assert_eq!(stats.get(2), None);
+
+        for state in 2..=SUSPEND_STATS_MAX_STATES as u32 {
+            stats.record_request(state);
+        }
+        assert_eq!(stats.untracked(), 1);
+    }
+
+    #[test]
+    fn query_suspend_stats() {
+        let service = DebugService::new(|| &SPMD, || &SUSPEND_STATS, || &());
+        SUSPEND_STATS.record_request(0x4000_0002);
+
+        let mut regs = SmcReturn::EMPTY;
+        regs.mark_all_used()[..2].copy_from_slice(&[DEBUG_SUSPEND_STATS.into(), 0]);
+        call(&service, &mut regs);
+        assert_eq!(regs.values(), [SUCCESS as u64, 0x4000_0002, 1, 0, 0]);
+
+        let mut regs = SmcReturn::EMPTY;
+        regs.mark_all_used()[..2].copy_from_slice(&[DEBUG_SUSPEND_STATS.into(), 1]);
+        call(&service, &mut regs);
+        assert_eq!(regs.values(), [INVALID_PARAMETER as u64]);
+    }
+
+    #[test]
+    fn query_build_info() {
+        let service = DebugService::new(|| &SPMD, || &SUSPEND_STATS, || &());
+
+        let mut regs = SmcReturn::EMPTY;
+        regs.mark_all_used()[..1].copy_from_slice(&[DEBUG_BUILD_INFO.into()]);
+        call(&service, &mut regs);
+        assert_eq!(
+            regs.values(),
+            [
+                SUCCESS as u64,
+                BUILD_INFO.version(),
+                BUILD_INFO.git_revision,
+                BUILD_INFO.config_hash,
+                BUILD_INFO.git_dirty.into(),
+            ]
+        );
+        assert_eq!(
+            BUILD_INFO.version() >> 32,
+            env!("CARGO_PKG_VERSION_MAJOR").parse().unwrap()
+        );
+    }
+
+    #[test]
+    fn dump_power_domains() {
+        let service = DebugService::new(|| &SPMD, || &SUSPEND_STATS, || &());
+
+        let mut regs = SmcReturn::EMPTY;
+        regs.mark_all_used()[..1].copy_from_slice(&[DEBUG_DUMP_POWER_DOMAINS.into()]);
+        call(&service, &mut regs);
+        assert_eq!(regs.values(), [SUCCESS as u64]);
+    }
+}
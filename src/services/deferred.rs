@@ -0,0 +1,167 @@
+// Copyright The Rusted Firmware-A Contributors.
+//
+// SPDX-License-Identifier: BSD-3-Clause
+
+//! Per-core queue of work deferred by exception handlers, to be run at EL3 before entering a given
+//! world.
+
+use crate::{
+    context::{PerCoreState, World},
+    platform::{Platform, exception_free},
+};
+use arrayvec::ArrayVec;
+use core::cell::RefCell;
+use percore::{ExceptionLock, PerCore};
+
+/// The maximum number of work items which may be pending on each core.
+pub const DEFERRED_WORK_CAPACITY: usize = 4;
+
+/// An item of deferred work: a function to call at EL3 before next entering some world, and the
+/// argument to pass to it.
+#[derive(Clone, Copy, Debug)]
+pub struct DeferredWork {
+    /// The world before whose next entry on the current core the work should run.
+    pub world: World,
+    /// The function to call.
+    pub function: fn(u64),
+    /// The argument to pass to `function`.
+    pub arg: u64,
+}
+
+/// Error returned when trying to defer work on a core whose queue is already full.
+#[derive(Clone, Copy, Debug)]
+pub struct QueueFull(pub DeferredWork);
+
+/// Per-core FIFO queues of deferred work.
+///
+/// This is kept outside the services so that it doesn't need to fit on the stack while they are
+/// constructed.
+pub struct DeferredWorkQueue<const CORE_COUNT: usize, PlatformImpl: Platform> {
+    core_local:
+        PerCoreState<CORE_COUNT, PlatformImpl, ArrayVec<DeferredWork, DEFERRED_WORK_CAPACITY>>,
+}
+
+impl<const CORE_COUNT: usize, PlatformImpl: Platform> DeferredWorkQueue<CORE_COUNT, PlatformImpl> {
+    /// Creates a new set of empty queues.
+    pub const fn new() -> Self {
+        Self {
+            core_local: PerCore::new(
+                [const { ExceptionLock::new(RefCell::new(ArrayVec::new_const())) }; CORE_COUNT],
+            ),
+        }
+    }
+
+    /// Adds the given work to the current core's queue, to be run before the core next enters the
+    /// work's world.
+    pub fn push(&self, work: DeferredWork) -> Result<(), QueueFull> {
+        exception_free(|token| {
+            self.core_local
+                .get()
+                .borrow_mut(token)
+                .try_push(work)
+                .map_err(|e| QueueFull(e.element()))
+        })
+    }
+
+    /// Runs all the work pending on the current core for the given world, in the order it was
+    /// added, including any work added while doing so.
+    ///
+    /// Work for other worlds stays queued.
+    pub fn run_pending(&self, world: World) {
+        while let Some(work) = self.pop(world) {
+            (work.function)(work.arg);
+        }
+    }
+
+    /// Returns whether any work is pending on the current core for the given world.
+    pub fn is_pending(&self, world: World) -> bool {
+        exception_free(|token| {
+            self.core_local
+                .get()
+                .borrow(token)
+                .borrow()
+                .iter()
+                .any(|work| work.world == world)
+        })
+    }
+
+    /// Removes the oldest work pending on the current core for the given world.
+    fn pop(&self, world: World) -> Option<DeferredWork> {
+        exception_free(|token| {
+            let mut queue = self.core_local.get().borrow_mut(token);
+            let index = queue.iter().position(|work| work.world == world)?;
+            Some(queue.remove(index))
+        })
+    }
+}
+
+impl<const CORE_COUNT: usize, PlatformImpl: Platform> Default
+    for DeferredWorkQueue<CORE_COUNT, PlatformImpl>
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::platform::test::TestPlatform;
+    use std::sync::Mutex;
+
+    static CALLS: Mutex<Vec<u64>> = Mutex::new(Vec::new());
+
+    fn record(arg: u64) {
+        CALLS.lock().unwrap().push(arg);
+    }
+
+    #[test]
+    fn run_in_order() {
+        CALLS.lock().unwrap().clear();
+        let queue = DeferredWorkQueue::<{ TestPlatform::CORE_COUNT }, TestPlatform>::new();
+
+        for (world, arg) in [
+            (World::NonSecure, 1),
+            (World::Secure, 2),
+            (World::NonSecure, 3),
+        ] {
+            queue
+                .push(DeferredWork {
+                    world,
+                    function: record,
+                    arg,
+                })
+                .unwrap();
+        }
+        assert_eq!(*CALLS.lock().unwrap(), []);
+
+        queue.run_pending(World::NonSecure);
+        assert_eq!(*CALLS.lock().unwrap(), [1, 3]);
+        assert!(!queue.is_pending(World::NonSecure));
+        assert!(queue.is_pending(World::Secure));
+
+        queue.run_pending(World::NonSecure);
+        assert_eq!(*CALLS.lock().unwrap(), [1, 3]);
+
+        queue.run_pending(World::Secure);
+        assert_eq!(*CALLS.lock().unwrap(), [1, 3, 2]);
+    }
+
+    #[test]
+    fn full() {
+        let queue = DeferredWorkQueue::<{ TestPlatform::CORE_COUNT }, TestPlatform>::new();
+        let work = DeferredWork {
+            world: World::NonSecure,
+            function: record,
+            arg: 42,
+        };
+
+        for _ in 0..DEFERRED_WORK_CAPACITY {
+            queue.push(work).unwrap();
+        }
+        assert!(matches!(
+            queue.push(work),
+            Err(QueueFull(DeferredWork { arg: 42, .. }))
+        ));
+    }
+}
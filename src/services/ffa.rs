@@ -4,4 +4,11 @@
 
 //! Firmware Framework for A-Profile.
 
+mod mailbox;
+pub mod secure_interrupts;
+#[cfg(feature = "spmc_el3")]
+pub mod spmc_el3;
 pub mod spmd;
+
+/// The endpoint ID of the normal world when there is no hypervisor.
+const NS_EP_ID: u16 = 0;
@@ -0,0 +1,257 @@
+// Copyright The Rusted Firmware-A Contributors.
+//
+// SPDX-License-Identifier: BSD-3-Clause
+
+//! The RX/TX buffer pair which the normal world maps with `FFA_RXTX_MAP`, for the FF-A services
+//! which access it from EL3.
+
+use super::NS_EP_ID;
+use crate::spin_mutex::SpinMutex;
+use arm_ffa::FfaError;
+use core::{ops::Range, ptr};
+
+/// The unit in which `FFA_RXTX_MAP` gives the size of the buffers, and their required alignment.
+const FFA_PAGE_SIZE: usize = 4096;
+
+/// Which endpoint currently owns the RX buffer.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum RxOwner {
+    /// The SPM may write a message to the RX buffer. If the SPMC is in the secure world, the SPMD
+    /// must first acquire the buffer from it with `FFA_RX_ACQUIRE`.
+    Producer,
+    /// The normal world is reading a message from the RX buffer, and must release it with
+    /// `FFA_RX_RELEASE` before the SPM can write another.
+    NormalWorld,
+}
+
+#[derive(Debug)]
+struct Buffers {
+    rx: usize,
+    tx: usize,
+    size: usize,
+    rx_owner: RxOwner,
+}
+
+/// The normal world's RX/TX buffers, if it has mapped them.
+#[derive(Debug)]
+pub(super) struct Mailbox {
+    /// The range of non-secure memory which the platform maps into EL3, within which the buffers
+    /// must lie.
+    ns_region: Range<usize>,
+    buffers: SpinMutex<Option<Buffers>>,
+}
+
+impl Mailbox {
+    pub(super) const fn new(ns_region: Range<usize>) -> Self {
+        Self {
+            ns_region,
+            buffers: SpinMutex::new(None),
+        }
+    }
+
+    /// Handles `FFA_RXTX_MAP` from the normal world.
+    pub(super) fn map(&self, rx: u64, tx: u64, page_count: u32) -> Result<(), FfaError> {
+        let size = usize::try_from(page_count)
+            .ok()
+            .and_then(|page_count| page_count.checked_mul(FFA_PAGE_SIZE))
+            .filter(|&size| size != 0)
+            .ok_or(FfaError::InvalidParameters)?;
+        let rx = self.buffer_range(rx, size)?;
+        let tx = self.buffer_range(tx, size)?;
+        if rx.start < tx.end && tx.start < rx.end {
+            return Err(FfaError::InvalidParameters);
+        }
+
+        let mut buffers = self.buffers.lock();
+        if buffers.is_some() {
+            return Err(FfaError::Denied);
+        }
+        *buffers = Some(Buffers {
+            rx: rx.start,
+            tx: tx.start,
+            size,
+            rx_owner: RxOwner::Producer,
+        });
+        Ok(())
+    }
+
+    /// Handles `FFA_RXTX_UNMAP` from the normal world.
+    pub(super) fn unmap(&self, id: u16) -> Result<(), FfaError> {
+        if id != NS_EP_ID {
+            return Err(FfaError::InvalidParameters);
+        }
+        self.buffers
+            .lock()
+            .take()
+            .map(|_| ())
+            .ok_or(FfaError::InvalidParameters)
+    }
+
+    /// Handles `FFA_RX_RELEASE` from the normal world.
+    pub(super) fn release(&self, id: u16) -> Result<(), FfaError> {
+        if id != NS_EP_ID {
+            return Err(FfaError::InvalidParameters);
+        }
+        let mut buffers = self.buffers.lock();
+        match buffers.as_mut() {
+            Some(buffers) if buffers.rx_owner == RxOwner::NormalWorld => {
+                buffers.rx_owner = RxOwner::Producer;
+                Ok(())
+            }
+            _ => Err(FfaError::Denied),
+        }
+    }
+
+    /// Copies `message` to the start of the RX buffer, and passes ownership of it to the normal
+    /// world.
+    pub(super) fn write_rx(&self, message: &[u8]) -> Result<(), FfaError> {
+        let mut buffers = self.buffers.lock();
+        let buffers = buffers.as_mut().ok_or(FfaError::Denied)?;
+        if buffers.rx_owner != RxOwner::Producer {
+            return Err(FfaError::Busy);
+        }
+        if message.len() > buffers.size {
+            return Err(FfaError::NoMemory);
+        }
+
+        // SAFETY: The RX buffer was checked to lie within `ns_region` when it was mapped, which the
+        // platform maps into EL3's address space as non-secure memory and doesn't use for anything
+        // else. It is never referenced, only copied to, as the normal world may access it
+        // concurrently.
+        unsafe {
+            ptr::copy_nonoverlapping(message.as_ptr(), buffers.rx as *mut u8, message.len());
+        }
+        buffers.rx_owner = RxOwner::NormalWorld;
+        Ok(())
+    }
+
+    /// Returns whether the normal world has mapped its buffers.
+    pub(super) fn is_mapped(&self) -> bool {
+        self.buffers.lock().is_some()
+    }
+
+    /// Copies the start of the TX buffer into `message`.
+    ///
+    /// The normal world may change the TX buffer at any time, so its contents must only be
+    /// validated after they have been copied.
+    // Only the EL3 SPMC reads messages from the TX buffer.
+    #[cfg_attr(not(feature = "spmc_el3"), allow(dead_code))]
+    pub(super) fn read_tx(&self, message: &mut [u8]) -> Result<(), FfaError> {
+        let buffers = self.buffers.lock();
+        let buffers = buffers.as_ref().ok_or(FfaError::Denied)?;
+        if message.len() > buffers.size {
+            return Err(FfaError::InvalidParameters);
+        }
+
+        // SAFETY: The TX buffer was checked to lie within `ns_region` when it was mapped, which the
+        // platform maps into EL3's address space as non-secure memory and doesn't use for anything
+        // else. It is never referenced, only copied from, as the normal world may access it
+        // concurrently.
+        unsafe {
+            ptr::copy_nonoverlapping(buffers.tx as *const u8, message.as_mut_ptr(), message.len());
+        }
+        Ok(())
+    }
+
+    /// Checks that a buffer of the given size at the given address is correctly aligned and lies
+    /// within the non-secure region.
+    fn buffer_range(&self, address: u64, size: usize) -> Result<Range<usize>, FfaError> {
+        let start = usize::try_from(address).map_err(|_| FfaError::InvalidParameters)?;
+        let end = start.checked_add(size).ok_or(FfaError::InvalidParameters)?;
+        if start % FFA_PAGE_SIZE != 0 || start < self.ns_region.start || end > self.ns_region.end {
+            return Err(FfaError::InvalidParameters);
+        }
+        Ok(start..end)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[repr(C, align(4096))]
+    struct Page([u8; FFA_PAGE_SIZE]);
+
+    /// Returns a mailbox whose non-secure region is four pages of leaked host memory.
+    fn mailbox() -> (Mailbox, u64) {
+        let pages = Box::leak(Box::new([const { Page([0; FFA_PAGE_SIZE]) }; 4]));
+        let start = pages.as_mut_ptr() as usize;
+        (Mailbox::new(start..start + 4 * FFA_PAGE_SIZE), start as u64)
+    }
+
+    #[test]
+    fn map_unmap() {
+        let (mailbox, base) = mailbox();
+        let page = FFA_PAGE_SIZE as u64;
+
+        assert_eq!(
+            mailbox.map(base, base + page, 0),
+            Err(FfaError::InvalidParameters)
+        );
+        assert_eq!(
+            mailbox.map(base + 1, base + page, 1),
+            Err(FfaError::InvalidParameters)
+        );
+        assert_eq!(
+            mailbox.map(base, base + page, 2),
+            Err(FfaError::InvalidParameters)
+        );
+        assert_eq!(
+            mailbox.map(base, base + 4 * page, 1),
+            Err(FfaError::InvalidParameters)
+        );
+
+        assert_eq!(mailbox.map(base, base + 2 * page, 2), Ok(()));
+        assert_eq!(mailbox.map(base, base + 2 * page, 2), Err(FfaError::Denied));
+
+        assert_eq!(mailbox.unmap(1), Err(FfaError::InvalidParameters));
+        assert_eq!(mailbox.unmap(NS_EP_ID), Ok(()));
+        assert_eq!(mailbox.unmap(NS_EP_ID), Err(FfaError::InvalidParameters));
+    }
+
+    #[test]
+    fn rx_ownership() {
+        let (mailbox, base) = mailbox();
+
+        assert_eq!(mailbox.write_rx(&[1, 2, 3]), Err(FfaError::Denied));
+        assert_eq!(mailbox.release(NS_EP_ID), Err(FfaError::Denied));
+
+        assert!(!mailbox.is_mapped());
+        mailbox.map(base, base + FFA_PAGE_SIZE as u64, 1).unwrap();
+        assert!(mailbox.is_mapped());
+        assert_eq!(mailbox.release(NS_EP_ID), Err(FfaError::Denied));
+        assert_eq!(
+            mailbox.write_rx(&[0; FFA_PAGE_SIZE + 1]),
+            Err(FfaError::NoMemory)
+        );
+        assert_eq!(mailbox.write_rx(&[1, 2, 3]), Ok(()));
+        // SAFETY: The RX buffer is host memory owned by this test.
+        assert_eq!(unsafe { *(base as *const [u8; 3]) }, [1, 2, 3]);
+
+        // The normal world must release the buffer before another message is written.
+        assert_eq!(mailbox.write_rx(&[4]), Err(FfaError::Busy));
+        assert_eq!(mailbox.release(NS_EP_ID), Ok(()));
+        assert_eq!(mailbox.write_rx(&[4]), Ok(()));
+        // SAFETY: The RX buffer is host memory owned by this test.
+        assert_eq!(unsafe { *(base as *const [u8; 3]) }, [4, 2, 3]);
+    }
+
+    #[test]
+    fn read_tx() {
+        let (mailbox, base) = mailbox();
+        let tx = base + FFA_PAGE_SIZE as u64;
+        let mut message = [0; 4];
+
+        assert_eq!(mailbox.read_tx(&mut message), Err(FfaError::Denied));
+
+        mailbox.map(base, tx, 1).unwrap();
+        // SAFETY: The TX buffer is host memory owned by this test.
+        unsafe { *(tx as *mut [u8; 4]) = [5, 6, 7, 8] };
+        assert_eq!(mailbox.read_tx(&mut message), Ok(()));
+        assert_eq!(message, [5, 6, 7, 8]);
+        assert_eq!(
+            mailbox.read_tx(&mut [0; FFA_PAGE_SIZE + 1]),
+            Err(FfaError::InvalidParameters)
+        );
+    }
+}
@@ -0,0 +1,181 @@
+// Copyright The Rusted Firmware-A Contributors.
+//
+// SPDX-License-Identifier: BSD-3-Clause
+
+//! Ownership of secure interrupts by secure partitions, as assigned by their manifests.
+
+use arm_ffa::FfaError;
+use arm_gic::IntId;
+
+/// A secure interrupt assigned to a secure partition by its manifest.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct SecureInterruptAssignment {
+    /// The INTID of the interrupt.
+    pub interrupt_id: IntId,
+    /// The FF-A endpoint ID of the secure partition which owns the interrupt.
+    pub endpoint_id: u16,
+}
+
+/// A problem with a [`SecureInterruptOwnership`] table, found by
+/// [`SecureInterruptOwnership::validate`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SecureInterruptOwnershipError {
+    /// The INTID is not a PPI or SPI (including extended ranges).
+    InvalidIntId(IntId),
+    /// The INTID is assigned more than once.
+    Duplicate(IntId),
+    /// The INTID is assigned to an endpoint ID which isn't a secure endpoint ID.
+    InvalidEndpoint(IntId),
+}
+
+/// A table mapping secure interrupts to the secure partitions which own them.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct SecureInterruptOwnership {
+    assignments: &'static [SecureInterruptAssignment],
+}
+
+impl SecureInterruptOwnership {
+    /// A table in which no interrupts are owned by any partition.
+    pub const EMPTY: Self = Self::new(&[]);
+
+    /// Creates a table from the interrupt assignments in the SP manifests.
+    pub const fn new(assignments: &'static [SecureInterruptAssignment]) -> Self {
+        Self { assignments }
+    }
+
+    /// Checks that the table is consistent.
+    pub const fn validate(&self) -> Result<(), SecureInterruptOwnershipError> {
+        let assignments = self.assignments;
+        let mut i = 0;
+        while i < assignments.len() {
+            let assignment = assignments[i];
+            let intid = assignment.interrupt_id;
+
+            if !(intid.is_ppi() || intid.is_eppi() || intid.is_spi() || intid.is_espi()) {
+                return Err(SecureInterruptOwnershipError::InvalidIntId(intid));
+            }
+            if assignment.endpoint_id & 0x8000 == 0 {
+                return Err(SecureInterruptOwnershipError::InvalidEndpoint(intid));
+            }
+
+            let mut j = 0;
+            while j < i {
+                if assignments[j].interrupt_id.raw_value() == intid.raw_value() {
+                    return Err(SecureInterruptOwnershipError::Duplicate(intid));
+                }
+                j += 1;
+            }
+
+            i += 1;
+        }
+        Ok(())
+    }
+
+    /// Panics if the table is not valid.
+    #[track_caller]
+    pub const fn assert_valid(&self) {
+        assert!(
+            self.validate().is_ok(),
+            "SP manifests assign secure interrupts inconsistently"
+        );
+    }
+
+    /// Returns the assignment of the given interrupt, if any partition owns it.
+    pub fn owner(&self, interrupt_id: IntId) -> Option<&SecureInterruptAssignment> {
+        self.assignments
+            .iter()
+            .find(|assignment| assignment.interrupt_id == interrupt_id)
+    }
+
+    /// Checks whether the given endpoint may configure the given interrupt.
+    ///
+    /// Returns `InvalidParameters` if no partition owns the interrupt, or `Denied` if another
+    /// partition owns it.
+    pub fn check_config_request(
+        &self,
+        endpoint_id: u16,
+        interrupt_id: IntId,
+    ) -> Result<(), FfaError> {
+        match self.owner(interrupt_id) {
+            None => Err(FfaError::InvalidParameters),
+            Some(assignment) if assignment.endpoint_id != endpoint_id => Err(FfaError::Denied),
+            Some(_) => Ok(()),
+        }
+    }
+}
+
+impl Default for SecureInterruptOwnership {
+    fn default() -> Self {
+        Self::EMPTY
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ASSIGNMENTS: [SecureInterruptAssignment; 2] = [
+        SecureInterruptAssignment {
+            interrupt_id: IntId::spi(10),
+            endpoint_id: 0x8001,
+        },
+        SecureInterruptAssignment {
+            interrupt_id: IntId::ppi(3),
+            endpoint_id: 0x8002,
+        },
+    ];
+
+    #[test]
+    fn lookup() {
+        let table = SecureInterruptOwnership::new(&ASSIGNMENTS);
+        assert_eq!(table.validate(), Ok(()));
+
+        assert_eq!(table.owner(IntId::spi(10)), Some(&ASSIGNMENTS[0]));
+        assert_eq!(table.owner(IntId::spi(11)), None);
+
+        assert_eq!(table.check_config_request(0x8001, IntId::spi(10)), Ok(()));
+        assert_eq!(
+            table.check_config_request(0x8002, IntId::spi(10)),
+            Err(FfaError::Denied)
+        );
+        assert_eq!(
+            table.check_config_request(0x8002, IntId::spi(11)),
+            Err(FfaError::InvalidParameters)
+        );
+    }
+
+    #[test]
+    fn validate_invalid() {
+        static SGI: [SecureInterruptAssignment; 1] = [SecureInterruptAssignment {
+            interrupt_id: IntId::sgi(1),
+            endpoint_id: 0x8001,
+        }];
+        assert_eq!(
+            SecureInterruptOwnership::new(&SGI).validate(),
+            Err(SecureInterruptOwnershipError::InvalidIntId(IntId::sgi(1)))
+        );
+
+        static NON_SECURE: [SecureInterruptAssignment; 1] = [SecureInterruptAssignment {
+            interrupt_id: IntId::spi(1),
+            endpoint_id: 0x0001,
+        }];
+        assert_eq!(
+            SecureInterruptOwnership::new(&NON_SECURE).validate(),
+            Err(SecureInterruptOwnershipError::InvalidEndpoint(IntId::spi(
+                1
+            )))
+        );
+
+        static DUPLICATE: [SecureInterruptAssignment; 2] = [
+            ASSIGNMENTS[0],
+            SecureInterruptAssignment {
+                endpoint_id: 0x8002,
+                ..ASSIGNMENTS[0]
+            },
+        ];
+        assert_eq!(
+            SecureInterruptOwnership::new(&DUPLICATE).validate(),
+            Err(SecureInterruptOwnershipError::Duplicate(IntId::spi(10)))
+        );
+    }
+}
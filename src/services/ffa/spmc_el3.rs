@@ -0,0 +1,896 @@
+// Copyright The Rusted Firmware-A Contributors.
+//
+// SPDX-License-Identifier: BSD-3-Clause
+
+//! FF-A Secure Partition Manager Core at EL3.
+//!
+//! With the `spmc_el3` feature, FF-A calls from the normal world are handled by this service rather
+//! than being forwarded by the SPMD to an SPMC in the secure world. The secure partitions are
+//! logical partitions, which run as part of EL3 and are described by the platform's
+//! [`El3SpmcManifest`]. No lower EL of the secure world is booted.
+
+mod memory;
+
+pub use memory::{
+    MAX_CONSTITUENTS, MAX_RECEIVERS, MemoryTransactionKind, MemoryTransactions, RetrievedMemory,
+};
+
+use crate::{
+    context::World,
+    runtime_config::runtime_config,
+    services::{
+        InitPhase, Service,
+        ffa::{NS_EP_ID, mailbox::Mailbox, spmd::get_smc_regs},
+        owns,
+    },
+    smccc::{OwningEntityNumber, SmcReturn},
+};
+use arm_ffa::{
+    FfaError, FuncId, Interface, Uuid, Version, VersionOut,
+    interface_args::{
+        DirectMsg2Args, DirectMsgArgs, Feature, MemOpBuf, RxTxAddr, SuccessArgsIdGet,
+        SuccessArgsSpmIdGet, TargetInfo,
+    },
+    memory_management::{Handle, SuccessArgsMemOp},
+    partition_info::{
+        PartitionIdType, PartitionInfo, PartitionInfoGetFlags, PartitionProperties,
+        SuccessArgsPartitionInfoGet, SuccessArgsPartitionInfoGetRegs,
+    },
+};
+use arrayvec::ArrayVec;
+use core::{
+    fmt::{self, Debug, Formatter},
+    ops::Range,
+};
+use log::{debug, error, trace, warn};
+
+const FUNCTION_NUMBER_MIN: u16 = 0x0060;
+const FUNCTION_NUMBER_MAX: u16 = 0x00EF;
+
+/// The maximum number of logical partitions which the EL3 SPMC supports.
+pub const MAX_PARTITIONS: usize = 8;
+
+/// The maximum size of a memory transaction descriptor which the normal world may send.
+const MAX_DESCRIPTOR_SIZE: usize = 512;
+
+/// The number of partition information descriptors which fit in the registers of a single
+/// `FFA_PARTITION_INFO_GET_REGS` response.
+const PARTITION_INFO_REGS_COUNT: usize = 120 / PartitionInfo::DESC_SIZE;
+
+/// The version used to encode partition information descriptors, whose layout hasn't changed
+/// since.
+const PARTITION_INFO_VERSION: Version = Version(1, 2);
+
+/// A secure partition which runs as part of EL3.
+pub trait LogicalPartition: Sync {
+    /// Handles a direct message request from the normal world endpoint `source`, and returns the
+    /// arguments of the response.
+    ///
+    /// The response must use the same register width as the request. `memory` may be used to
+    /// retrieve and relinquish memory which the normal world has sent to the partition.
+    fn handle_direct_request(
+        &self,
+        source: u16,
+        args: DirectMsgArgs,
+        memory: &MemoryTransactions,
+    ) -> Result<DirectMsgArgs, FfaError>;
+
+    /// Handles an `FFA_MSG_SEND_DIRECT_REQ2` request for the service identified by `uuid`.
+    ///
+    /// The default implementation doesn't support it. Partitions which override this should set
+    /// [`El3Partition::direct_req2`].
+    fn handle_direct_request2(
+        &self,
+        _source: u16,
+        _uuid: Uuid,
+        _args: DirectMsg2Args,
+        _memory: &MemoryTransactions,
+    ) -> Result<DirectMsg2Args, FfaError> {
+        Err(FfaError::NotSupported)
+    }
+}
+
+/// A logical partition, as described by its manifest.
+#[derive(Clone, Copy)]
+pub struct El3Partition {
+    /// The FF-A endpoint ID of the partition.
+    pub id: u16,
+    /// The UUID of the partition.
+    pub uuid: Uuid,
+    /// Whether the partition accepts `FFA_MSG_SEND_DIRECT_REQ2`.
+    pub direct_req2: bool,
+    /// The implementation of the partition.
+    pub partition: &'static dyn LogicalPartition,
+}
+
+impl Debug for El3Partition {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_struct("El3Partition")
+            .field("id", &self.id)
+            .field("uuid", &self.uuid)
+            .field("direct_req2", &self.direct_req2)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Attributes of the EL3 SPMC and the logical partitions which it manages.
+#[derive(Clone, Debug)]
+pub struct El3SpmcManifest {
+    /// The FF-A endpoint ID of the SPMC.
+    pub spmc_id: u16,
+    /// The logical partitions.
+    pub partitions: &'static [El3Partition],
+    /// A range of non-secure memory which the platform maps into EL3's address space for the normal
+    /// world's RX/TX buffers, and doesn't use for anything else. `FFA_RXTX_MAP` rejects buffers
+    /// outside it.
+    pub ns_buffer_region: Range<usize>,
+}
+
+impl El3SpmcManifest {
+    /// No partitions and nowhere for RX/TX buffers, used unless the platform provides its own
+    /// manifest.
+    pub const DEFAULT: Self = Self {
+        spmc_id: 0x8000,
+        partitions: &[],
+        ns_buffer_region: 0..0,
+    };
+}
+
+/// FF-A Secure Partition Manager Core, running at EL3.
+pub struct SpmcEl3<const CORE_COUNT: usize> {
+    spmc_id: u16,
+    partitions: &'static [El3Partition],
+    mailbox: Mailbox,
+    memory: &'static MemoryTransactions,
+}
+
+impl<const CORE_COUNT: usize> Service for SpmcEl3<CORE_COUNT> {
+    owns!(
+        OwningEntityNumber::STANDARD_SECURE,
+        FUNCTION_NUMBER_MIN..=FUNCTION_NUMBER_MAX
+    );
+
+    fn init(&self, phase: InitPhase) {
+        if phase == InitPhase::Early {
+            assert!(
+                !runtime_config().spmc_present,
+                "The EL3 SPMC can't be used with an SPMC in the secure world"
+            );
+        }
+    }
+
+    fn handle_non_secure_smc(&self, regs: &mut SmcReturn) -> World {
+        let smc_regs = get_smc_regs(regs);
+
+        let response = match Interface::from_regs(Self::VERSION, smc_regs) {
+            Ok(msg) => {
+                trace!("Handle FF-A call from NWd {msg:x?}");
+                self.handle_call(msg)
+                    .unwrap_or_else(|error| Interface::error(error, true))
+            }
+            Err(error) => {
+                error!("Invalid FF-A call from Normal World {error}");
+                match error {
+                    // If the FFA_VERSION decoding failed, we have to use a different error encoding
+                    arm_ffa::Error::InvalidVersion(_)
+                    | arm_ffa::Error::InvalidVersionFlags(_)
+                    | arm_ffa::Error::InvalidVersionQueryType(_) => Interface::VersionOut {
+                        output_version: VersionOut::NotSupported,
+                    },
+                    error => Interface::error(error.into(), true),
+                }
+            }
+        };
+        response.to_regs(Self::VERSION, smc_regs);
+
+        World::NonSecure
+    }
+}
+
+impl<const CORE_COUNT: usize> SpmcEl3<CORE_COUNT> {
+    const VERSION: Version = Version(1, 3);
+
+    /// Creates the EL3 SPMC for the partitions described by the given manifest, keeping track of
+    /// memory transactions in `memory`.
+    ///
+    /// Panics if the manifest is invalid.
+    pub fn new(manifest: El3SpmcManifest, memory: &'static MemoryTransactions) -> Self {
+        debug!("Initializing EL3 SPMC");
+
+        let El3SpmcManifest {
+            spmc_id,
+            partitions,
+            ns_buffer_region,
+        } = manifest;
+
+        assert!(is_secure_id(spmc_id), "Invalid SPMC ID {spmc_id:#x}");
+        assert!(
+            partitions.len() <= MAX_PARTITIONS,
+            "Too many logical partitions"
+        );
+        for (i, partition) in partitions.iter().enumerate() {
+            let id = partition.id;
+            assert!(
+                is_secure_id(id) && id != spmc_id && id != u16::MAX,
+                "Invalid logical partition ID {id:#x}"
+            );
+            assert!(
+                partitions[..i].iter().all(|other| other.id != id),
+                "Duplicate logical partition ID {id:#x}"
+            );
+        }
+
+        Self {
+            spmc_id,
+            partitions,
+            mailbox: Mailbox::new(ns_buffer_region),
+            memory,
+        }
+    }
+
+    /// Handles an FF-A call from the normal world, returning the response to send back.
+    fn handle_call(&self, msg: Interface) -> Result<Interface, FfaError> {
+        Ok(match msg {
+            Interface::Version { .. } => Interface::VersionOut {
+                output_version: VersionOut::Version(Self::VERSION),
+            },
+            Interface::Features {
+                feat_id: Feature::FuncId(func_id),
+                ..
+            } if Self::supports(func_id) => Interface::success32_noargs(),
+            Interface::Features { .. } => return Err(FfaError::NotSupported),
+            Interface::IdGet => Interface::Success {
+                target_info: TargetInfo::default(),
+                args: SuccessArgsIdGet { id: NS_EP_ID }.into(),
+            },
+            Interface::SpmIdGet => Interface::Success {
+                target_info: TargetInfo::default(),
+                args: SuccessArgsSpmIdGet { id: self.spmc_id }.into(),
+            },
+            Interface::RxTxMap { addr, page_cnt } => {
+                let (rx, tx) = match addr {
+                    RxTxAddr::Addr32 { rx, tx } => (rx.into(), tx.into()),
+                    RxTxAddr::Addr64 { rx, tx } => (rx, tx),
+                };
+                self.mailbox.map(rx, tx, page_cnt)?;
+                Interface::success32_noargs()
+            }
+            Interface::RxTxUnmap { id } => {
+                self.mailbox.unmap(id)?;
+                Interface::success32_noargs()
+            }
+            Interface::RxRelease { vm_id } => {
+                self.mailbox.release(vm_id)?;
+                Interface::success32_noargs()
+            }
+            Interface::PartitionInfoGet { uuid, flags } => self.partition_info_get(uuid, flags)?,
+            Interface::PartitionInfoGetRegs {
+                uuid,
+                start_index,
+                info_tag,
+            } => self.partition_info_get_regs(uuid, start_index, info_tag)?,
+            Interface::MsgSendDirectReq {
+                src_id,
+                dst_id,
+                args,
+            } => {
+                let partition = self.direct_request_target(src_id, dst_id)?;
+                let is_32bit = match args {
+                    DirectMsgArgs::Args32(_) => true,
+                    DirectMsgArgs::Args64(_) => false,
+                    // Framework messages are only exchanged between the SPMD and an SPMC.
+                    _ => return Err(FfaError::InvalidParameters),
+                };
+                let args = partition
+                    .partition
+                    .handle_direct_request(src_id, args, self.memory)?;
+                if matches!(args, DirectMsgArgs::Args32(_)) != is_32bit {
+                    warn!("Logical partition {dst_id:#x} responded with different register width");
+                    return Err(FfaError::Aborted);
+                }
+                Interface::MsgSendDirectResp {
+                    src_id: dst_id,
+                    dst_id: src_id,
+                    args,
+                }
+            }
+            Interface::MsgSendDirectReq2 {
+                src_id,
+                dst_id,
+                uuid,
+                args,
+            } => {
+                let partition = self.direct_request_target(src_id, dst_id)?;
+                if !partition.direct_req2 || (!uuid.is_nil() && uuid != partition.uuid) {
+                    return Err(FfaError::InvalidParameters);
+                }
+                let args =
+                    partition
+                        .partition
+                        .handle_direct_request2(src_id, uuid, args, self.memory)?;
+                Interface::MsgSendDirectResp2 {
+                    src_id: dst_id,
+                    dst_id: src_id,
+                    args,
+                }
+            }
+            Interface::MemShare {
+                total_len,
+                frag_len,
+                buf,
+            } => self.mem_send(MemoryTransactionKind::Share, total_len, frag_len, buf)?,
+            Interface::MemLend {
+                total_len,
+                frag_len,
+                buf,
+            } => self.mem_send(MemoryTransactionKind::Lend, total_len, frag_len, buf)?,
+            Interface::MemDonate {
+                total_len,
+                frag_len,
+                buf,
+            } => self.mem_send(MemoryTransactionKind::Donate, total_len, frag_len, buf)?,
+            Interface::MemReclaim { handle, .. } => {
+                self.memory.reclaim(NS_EP_ID, handle)?;
+                Interface::success32_noargs()
+            }
+            _ => {
+                warn!("Unsupported FF-A call from Normal World: {msg:x?}");
+                return Err(FfaError::NotSupported);
+            }
+        })
+    }
+
+    /// Returns whether the normal world may use the given FF-A function.
+    fn supports(func_id: FuncId) -> bool {
+        matches!(
+            func_id,
+            FuncId::Version
+                | FuncId::Features
+                | FuncId::IdGet
+                | FuncId::SpmIdGet
+                | FuncId::RxTxMap32
+                | FuncId::RxTxMap64
+                | FuncId::RxTxUnmap
+                | FuncId::RxRelease
+                | FuncId::PartitionInfoGet
+                | FuncId::PartitionInfoGetRegs
+                | FuncId::MsgSendDirectReq32
+                | FuncId::MsgSendDirectReq64
+                | FuncId::MsgSendDirectReq64_2
+                | FuncId::MemShare32
+                | FuncId::MemShare64
+                | FuncId::MemLend32
+                | FuncId::MemLend64
+                | FuncId::MemDonate32
+                | FuncId::MemDonate64
+                | FuncId::MemReclaim
+        )
+    }
+
+    /// Returns the logical partition with the given ID, if there is one.
+    fn partition(&self, id: u16) -> Option<&El3Partition> {
+        self.partitions.iter().find(|partition| partition.id == id)
+    }
+
+    /// Checks that a direct request from the normal world endpoint `src_id` may be sent to
+    /// `dst_id`, and returns the partition to send it to.
+    fn direct_request_target(&self, src_id: u16, dst_id: u16) -> Result<&El3Partition, FfaError> {
+        if src_id != NS_EP_ID {
+            return Err(FfaError::InvalidParameters);
+        }
+        self.partition(dst_id).ok_or(FfaError::InvalidParameters)
+    }
+
+    /// Returns the partition information descriptors of the partitions matching the given UUID,
+    /// or all partitions if it is nil.
+    fn partition_info(&self, uuid: Uuid) -> ArrayVec<PartitionInfo, MAX_PARTITIONS> {
+        self.partitions
+            .iter()
+            .filter(|partition| uuid.is_nil() || partition.uuid == uuid)
+            .map(|partition| PartitionInfo {
+                uuid: partition.uuid,
+                partition_id: partition.id,
+                partition_id_type: PartitionIdType::PeEndpoint {
+                    execution_ctx_count: CORE_COUNT as u16,
+                },
+                props: PartitionProperties {
+                    support_direct_req_rec: true,
+                    support_direct_req2_rec: Some(partition.direct_req2),
+                    support_direct_req2_send: Some(false),
+                    is_aarch64: true,
+                    ..Default::default()
+                },
+            })
+            .collect()
+    }
+
+    /// Handles `FFA_PARTITION_INFO_GET`, writing the descriptors to the normal world's RX buffer
+    /// unless only the count is requested.
+    fn partition_info_get(
+        &self,
+        uuid: Uuid,
+        flags: PartitionInfoGetFlags,
+    ) -> Result<Interface, FfaError> {
+        let info = self.partition_info(uuid);
+        if info.is_empty() {
+            return Err(FfaError::InvalidParameters);
+        }
+
+        let size = if flags.count_only {
+            None
+        } else {
+            let mut buf = [0; MAX_PARTITIONS * PartitionInfo::DESC_SIZE];
+            let buf = &mut buf[..info.len() * PartitionInfo::DESC_SIZE];
+            PartitionInfo::pack(PARTITION_INFO_VERSION, &info, buf, uuid.is_nil());
+            self.mailbox.write_rx(buf)?;
+            Some(PartitionInfo::DESC_SIZE as u32)
+        };
+
+        Ok(Interface::Success {
+            target_info: TargetInfo::default(),
+            args: SuccessArgsPartitionInfoGet {
+                count: info.len() as u32,
+                size,
+            }
+            .into(),
+        })
+    }
+
+    /// Handles `FFA_PARTITION_INFO_GET_REGS`. The list of partitions never changes, so the
+    /// information tag is always 0.
+    fn partition_info_get_regs(
+        &self,
+        uuid: Uuid,
+        start_index: u16,
+        info_tag: u16,
+    ) -> Result<Interface, FfaError> {
+        let info = self.partition_info(uuid);
+        let start_index = usize::from(start_index);
+        if info_tag != 0 || start_index >= info.len() {
+            return Err(FfaError::InvalidParameters);
+        }
+
+        let end_index = info.len().min(start_index + PARTITION_INFO_REGS_COUNT);
+        let mut descriptor_data = [0; 120];
+        PartitionInfo::pack(
+            PARTITION_INFO_VERSION,
+            &info[start_index..end_index],
+            &mut descriptor_data,
+            uuid.is_nil(),
+        );
+
+        Ok(Interface::Success {
+            target_info: TargetInfo::default(),
+            args: SuccessArgsPartitionInfoGetRegs {
+                last_index: (info.len() - 1) as u16,
+                current_index: (end_index - 1) as u16,
+                info_tag: 0,
+                descriptor_data,
+            }
+            .into(),
+        })
+    }
+
+    /// Handles `FFA_MEM_SHARE`, `FFA_MEM_LEND` or `FFA_MEM_DONATE`, whose descriptor must be in
+    /// the normal world's TX buffer.
+    fn mem_send(
+        &self,
+        kind: MemoryTransactionKind,
+        total_len: u32,
+        frag_len: u32,
+        buf: Option<MemOpBuf>,
+    ) -> Result<Interface, FfaError> {
+        // Neither fragmented descriptors nor descriptors outside the TX buffer are supported.
+        if buf.is_some() || frag_len != total_len {
+            return Err(FfaError::NotSupported);
+        }
+        let len = usize::try_from(total_len).map_err(|_| FfaError::NoMemory)?;
+        if len > MAX_DESCRIPTOR_SIZE {
+            return Err(FfaError::NoMemory);
+        }
+
+        let mut descriptor = [0; MAX_DESCRIPTOR_SIZE];
+        let descriptor = &mut descriptor[..len];
+        self.mailbox.read_tx(descriptor)?;
+        let handle: Handle = self.memory.send(kind, NS_EP_ID, descriptor, |id| {
+            self.partition(id).is_some()
+        })?;
+
+        Ok(Interface::Success {
+            target_info: TargetInfo::default(),
+            args: SuccessArgsMemOp { handle }.into(),
+        })
+    }
+}
+
+/// Returns whether the given FF-A endpoint ID belongs to the secure world.
+const fn is_secure_id(id: u16) -> bool {
+    id & 0x8000 != 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arm_ffa::{
+        memory_management::{ConstituentMemRegion, MemAccessPerm, MemTransactionDesc},
+        partition_info::PartitionInfoIterator,
+    };
+
+    type TestSpmc = SpmcEl3<4>;
+
+    const ECHO_ID: u16 = 0x8001;
+    const MEMORY_ID: u16 = 0x8002;
+    const ECHO_UUID: Uuid = Uuid::from_u128(0x1111_2222_3333_4444_5555_6666_7777_8888);
+    const MEMORY_UUID: Uuid = Uuid::from_u128(0x9999_aaaa_bbbb_cccc_dddd_eeee_ffff_0000);
+
+    /// Responds with the arguments it was sent.
+    struct Echo;
+
+    impl LogicalPartition for Echo {
+        fn handle_direct_request(
+            &self,
+            _source: u16,
+            args: DirectMsgArgs,
+            _memory: &MemoryTransactions,
+        ) -> Result<DirectMsgArgs, FfaError> {
+            Ok(args)
+        }
+
+        fn handle_direct_request2(
+            &self,
+            _source: u16,
+            _uuid: Uuid,
+            args: DirectMsg2Args,
+            _memory: &MemoryTransactions,
+        ) -> Result<DirectMsg2Args, FfaError> {
+            Ok(args)
+        }
+    }
+
+    /// Retrieves the memory with the handle given in the first argument, and responds with the
+    /// address of its first constituent.
+    struct Retriever;
+
+    impl LogicalPartition for Retriever {
+        fn handle_direct_request(
+            &self,
+            _source: u16,
+            args: DirectMsgArgs,
+            memory: &MemoryTransactions,
+        ) -> Result<DirectMsgArgs, FfaError> {
+            let DirectMsgArgs::Args64([handle, ..]) = args else {
+                return Err(FfaError::InvalidParameters);
+            };
+            let retrieved = memory.retrieve(MEMORY_ID, Handle(handle))?;
+            let mut response = [0; 15];
+            response[0] = retrieved.constituents[0].address;
+            Ok(DirectMsgArgs::Args64(response))
+        }
+    }
+
+    static PARTITIONS: [El3Partition; 2] = [
+        El3Partition {
+            id: ECHO_ID,
+            uuid: ECHO_UUID,
+            direct_req2: true,
+            partition: &Echo,
+        },
+        El3Partition {
+            id: MEMORY_ID,
+            uuid: MEMORY_UUID,
+            direct_req2: false,
+            partition: &Retriever,
+        },
+    ];
+
+    #[repr(C, align(4096))]
+    struct Page([u8; 4096]);
+
+    /// Returns an SPMC with the test partitions, and the addresses of two pages of host memory
+    /// which it allows to be used as RX/TX buffers.
+    fn spmc() -> (TestSpmc, u64, u64) {
+        let pages = Box::leak(Box::new([const { Page([0; 4096]) }; 2]));
+        let start = pages.as_mut_ptr() as usize;
+        let spmc = TestSpmc::new(
+            El3SpmcManifest {
+                spmc_id: 0x8000,
+                partitions: &PARTITIONS,
+                ns_buffer_region: start..start + 2 * 4096,
+            },
+            Box::leak(Box::new(MemoryTransactions::new())),
+        );
+        (spmc, start as u64, start as u64 + 4096)
+    }
+
+    #[test]
+    fn ids() {
+        let (spmc, ..) = spmc();
+        assert_eq!(
+            spmc.handle_call(Interface::IdGet),
+            Ok(Interface::Success {
+                target_info: TargetInfo::default(),
+                args: SuccessArgsIdGet { id: NS_EP_ID }.into(),
+            })
+        );
+        assert_eq!(
+            spmc.handle_call(Interface::SpmIdGet),
+            Ok(Interface::Success {
+                target_info: TargetInfo::default(),
+                args: SuccessArgsSpmIdGet { id: 0x8000 }.into(),
+            })
+        );
+    }
+
+    #[test]
+    fn features() {
+        let (spmc, ..) = spmc();
+        let features = |func_id| {
+            spmc.handle_call(Interface::Features {
+                feat_id: Feature::FuncId(func_id),
+                input_properties: 0,
+            })
+        };
+        assert_eq!(
+            features(FuncId::MsgSendDirectReq64),
+            Ok(Interface::success32_noargs())
+        );
+        assert_eq!(features(FuncId::MsgWait32), Err(FfaError::NotSupported));
+    }
+
+    #[test]
+    #[should_panic(expected = "Duplicate logical partition ID")]
+    fn duplicate_partition_id() {
+        static DUPLICATES: [El3Partition; 2] = [PARTITIONS[0], PARTITIONS[0]];
+        TestSpmc::new(
+            El3SpmcManifest {
+                partitions: &DUPLICATES,
+                ..El3SpmcManifest::DEFAULT
+            },
+            Box::leak(Box::new(MemoryTransactions::new())),
+        );
+    }
+
+    #[test]
+    fn direct_request() {
+        let (spmc, ..) = spmc();
+        let args = DirectMsgArgs::Args32([1, 2, 3, 4, 5]);
+
+        assert_eq!(
+            spmc.handle_call(Interface::MsgSendDirectReq {
+                src_id: NS_EP_ID,
+                dst_id: ECHO_ID,
+                args,
+            }),
+            Ok(Interface::MsgSendDirectResp {
+                src_id: ECHO_ID,
+                dst_id: NS_EP_ID,
+                args,
+            })
+        );
+        for (src_id, dst_id) in [(ECHO_ID, ECHO_ID), (NS_EP_ID, 0x8003), (NS_EP_ID, 0x8000)] {
+            assert_eq!(
+                spmc.handle_call(Interface::MsgSendDirectReq {
+                    src_id,
+                    dst_id,
+                    args,
+                }),
+                Err(FfaError::InvalidParameters)
+            );
+        }
+    }
+
+    #[test]
+    fn direct_request2() {
+        let (spmc, ..) = spmc();
+        let args = DirectMsg2Args(core::array::from_fn(|i| i as u64));
+
+        assert_eq!(
+            spmc.handle_call(Interface::MsgSendDirectReq2 {
+                src_id: NS_EP_ID,
+                dst_id: ECHO_ID,
+                uuid: ECHO_UUID,
+                args,
+            }),
+            Ok(Interface::MsgSendDirectResp2 {
+                src_id: ECHO_ID,
+                dst_id: NS_EP_ID,
+                args,
+            })
+        );
+        // Wrong UUID, or a partition which doesn't support DIRECT_REQ2.
+        for (dst_id, uuid) in [(ECHO_ID, MEMORY_UUID), (MEMORY_ID, MEMORY_UUID)] {
+            assert_eq!(
+                spmc.handle_call(Interface::MsgSendDirectReq2 {
+                    src_id: NS_EP_ID,
+                    dst_id,
+                    uuid,
+                    args,
+                }),
+                Err(FfaError::InvalidParameters)
+            );
+        }
+    }
+
+    #[test]
+    fn partition_info_get() {
+        let (spmc, rx, tx) = spmc();
+        let count_only = PartitionInfoGetFlags { count_only: true };
+        let with_info = PartitionInfoGetFlags { count_only: false };
+
+        assert_eq!(
+            spmc.handle_call(Interface::PartitionInfoGet {
+                uuid: Uuid::nil(),
+                flags: count_only,
+            }),
+            Ok(Interface::Success {
+                target_info: TargetInfo::default(),
+                args: SuccessArgsPartitionInfoGet {
+                    count: 2,
+                    size: None
+                }
+                .into(),
+            })
+        );
+        assert_eq!(
+            spmc.handle_call(Interface::PartitionInfoGet {
+                uuid: Uuid::from_u128(1),
+                flags: count_only,
+            }),
+            Err(FfaError::InvalidParameters)
+        );
+
+        // The descriptors can't be returned until there is an RX buffer.
+        assert_eq!(
+            spmc.handle_call(Interface::PartitionInfoGet {
+                uuid: MEMORY_UUID,
+                flags: with_info,
+            }),
+            Err(FfaError::Denied)
+        );
+        spmc.handle_call(Interface::RxTxMap {
+            addr: RxTxAddr::Addr64 { rx, tx },
+            page_cnt: 1,
+        })
+        .unwrap();
+        assert_eq!(
+            spmc.handle_call(Interface::PartitionInfoGet {
+                uuid: MEMORY_UUID,
+                flags: with_info,
+            }),
+            Ok(Interface::Success {
+                target_info: TargetInfo::default(),
+                args: SuccessArgsPartitionInfoGet {
+                    count: 1,
+                    size: Some(PartitionInfo::DESC_SIZE as u32)
+                }
+                .into(),
+            })
+        );
+        // SAFETY: The RX buffer is host memory owned by this test.
+        let rx_buffer = unsafe { &*(rx as *const [u8; 4096]) };
+        let info = PartitionInfoIterator::new(PARTITION_INFO_VERSION, rx_buffer, 1)
+            .unwrap()
+            .next()
+            .unwrap()
+            .unwrap();
+        assert_eq!(info.partition_id, MEMORY_ID);
+
+        // The RX buffer must be released before it can be used again.
+        assert_eq!(
+            spmc.handle_call(Interface::PartitionInfoGet {
+                uuid: Uuid::nil(),
+                flags: with_info,
+            }),
+            Err(FfaError::Busy)
+        );
+        assert_eq!(
+            spmc.handle_call(Interface::RxRelease { vm_id: NS_EP_ID }),
+            Ok(Interface::success32_noargs())
+        );
+    }
+
+    #[test]
+    fn partition_info_get_regs() {
+        let (spmc, ..) = spmc();
+
+        let Ok(Interface::Success { args, .. }) =
+            spmc.handle_call(Interface::PartitionInfoGetRegs {
+                uuid: Uuid::nil(),
+                start_index: 1,
+                info_tag: 0,
+            })
+        else {
+            panic!("PARTITION_INFO_GET_REGS failed");
+        };
+        let args = SuccessArgsPartitionInfoGetRegs::try_from(args).unwrap();
+        assert_eq!(args.last_index, 1);
+        assert_eq!(args.current_index, 1);
+        let info = PartitionInfoIterator::new(PARTITION_INFO_VERSION, &args.descriptor_data, 1)
+            .unwrap()
+            .next()
+            .unwrap()
+            .unwrap();
+        assert_eq!(info.partition_id, MEMORY_ID);
+        assert_eq!(info.uuid, MEMORY_UUID);
+
+        assert_eq!(
+            spmc.handle_call(Interface::PartitionInfoGetRegs {
+                uuid: Uuid::nil(),
+                start_index: 2,
+                info_tag: 0,
+            }),
+            Err(FfaError::InvalidParameters)
+        );
+    }
+
+    #[test]
+    fn lend_retrieve_reclaim() {
+        let (spmc, rx, tx) = spmc();
+        spmc.handle_call(Interface::RxTxMap {
+            addr: RxTxAddr::Addr64 { rx, tx },
+            page_cnt: 1,
+        })
+        .unwrap();
+
+        // SAFETY: The TX buffer is host memory owned by this test.
+        let tx_buffer = unsafe { &mut *(tx as *mut [u8; 4096]) };
+        let total_len = MemTransactionDesc {
+            sender_id: NS_EP_ID,
+            ..Default::default()
+        }
+        .pack(
+            &[ConstituentMemRegion {
+                address: 0x8800_0000,
+                page_cnt: 1,
+            }],
+            &[MemAccessPerm {
+                endpoint_id: MEMORY_ID,
+                ..Default::default()
+            }],
+            tx_buffer,
+        ) as u32;
+
+        let Ok(Interface::Success { args, .. }) = spmc.handle_call(Interface::MemLend {
+            total_len,
+            frag_len: total_len,
+            buf: None,
+        }) else {
+            panic!("MEM_LEND failed");
+        };
+        let handle = SuccessArgsMemOp::try_from(args).unwrap().handle;
+
+        let mut request = [0; 15];
+        request[0] = handle.0;
+        let Ok(Interface::MsgSendDirectResp {
+            args: DirectMsgArgs::Args64(response),
+            ..
+        }) = spmc.handle_call(Interface::MsgSendDirectReq {
+            src_id: NS_EP_ID,
+            dst_id: MEMORY_ID,
+            args: DirectMsgArgs::Args64(request),
+        })
+        else {
+            panic!("Direct request to retrieve memory failed");
+        };
+        assert_eq!(response[0], 0x8800_0000);
+
+        // The partition still has the memory retrieved.
+        let reclaim = Interface::MemReclaim {
+            handle,
+            flags: Default::default(),
+        };
+        assert_eq!(spmc.handle_call(reclaim), Err(FfaError::Denied));
+        spmc.memory.relinquish(MEMORY_ID, handle).unwrap();
+        assert_eq!(spmc.handle_call(reclaim), Ok(Interface::success32_noargs()));
+
+        // Fragmented descriptors aren't supported.
+        assert_eq!(
+            spmc.handle_call(Interface::MemShare {
+                total_len,
+                frag_len: total_len - 16,
+                buf: None,
+            }),
+            Err(FfaError::NotSupported)
+        );
+    }
+}
@@ -0,0 +1,509 @@
+// Copyright The Rusted Firmware-A Contributors.
+//
+// SPDX-License-Identifier: BSD-3-Clause
+
+//! State of memory which the normal world has shared, lent or donated to logical partitions.
+
+use crate::spin_mutex::SpinMutex;
+use arm_ffa::{
+    FfaError,
+    memory_management::{ConstituentMemRegion, Handle, MemTransactionDesc, MemTransactionFlags},
+};
+use arrayvec::ArrayVec;
+
+/// The maximum number of memory transactions which may be outstanding at once.
+const MAX_TRANSACTIONS: usize = 16;
+
+/// The maximum number of receivers of a single memory transaction.
+pub const MAX_RECEIVERS: usize = 4;
+
+/// The maximum number of constituent memory regions in a single memory transaction.
+pub const MAX_CONSTITUENTS: usize = 8;
+
+/// Set in handles allocated by the SPMC, rather than by a hypervisor.
+const HANDLE_ALLOCATOR_SPMC: u64 = 1 << 63;
+
+/// The granule in which memory regions are shared.
+const FFA_PAGE_SIZE: u64 = 4096;
+
+/// Mask of the transaction type field in the memory transaction flags.
+const TRANSACTION_TYPE_MASK: u32 = 0b11 << 3;
+
+/// The kind of a memory transaction, i.e. which FF-A call started it.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum MemoryTransactionKind {
+    /// `FFA_MEM_SHARE`: the sender keeps access to the memory.
+    Share,
+    /// `FFA_MEM_LEND`: the sender loses access to the memory until it reclaims it.
+    Lend,
+    /// `FFA_MEM_DONATE`: ownership of the memory passes to the receiver once it is retrieved.
+    Donate,
+}
+
+impl MemoryTransactionKind {
+    /// Returns the transaction type field which the descriptor may contain for this kind.
+    fn type_flag(self) -> u32 {
+        match self {
+            Self::Share => MemTransactionFlags::TYPE_SHARE,
+            Self::Lend => MemTransactionFlags::TYPE_LEND,
+            Self::Donate => MemTransactionFlags::TYPE_DONATE,
+        }
+    }
+}
+
+/// Memory which a logical partition has retrieved.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RetrievedMemory {
+    /// How the memory was sent.
+    pub kind: MemoryTransactionKind,
+    /// The endpoint ID of the sender.
+    pub sender: u16,
+    /// The memory regions sent.
+    pub constituents: ArrayVec<ConstituentMemRegion, MAX_CONSTITUENTS>,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+struct Receiver {
+    id: u16,
+    retrieved: bool,
+}
+
+#[derive(Clone, Debug)]
+struct MemoryTransaction {
+    handle: Handle,
+    kind: MemoryTransactionKind,
+    sender: u16,
+    receivers: ArrayVec<Receiver, MAX_RECEIVERS>,
+    constituents: ArrayVec<ConstituentMemRegion, MAX_CONSTITUENTS>,
+}
+
+impl MemoryTransaction {
+    fn overlaps(&self, region: &ConstituentMemRegion) -> bool {
+        self.constituents.iter().any(|constituent| {
+            region.address < constituent_end(constituent)
+                && constituent.address < constituent_end(region)
+        })
+    }
+
+    fn receiver(&mut self, id: u16) -> Result<&mut Receiver, FfaError> {
+        self.receivers
+            .iter_mut()
+            .find(|receiver| receiver.id == id)
+            .ok_or(FfaError::InvalidParameters)
+    }
+}
+
+#[derive(Debug)]
+struct TransactionsState {
+    transactions: ArrayVec<MemoryTransaction, MAX_TRANSACTIONS>,
+    /// The counter from which the next handle is allocated.
+    next_handle: u64,
+}
+
+impl TransactionsState {
+    fn find(&mut self, handle: Handle) -> Result<usize, FfaError> {
+        self.transactions
+            .iter()
+            .position(|transaction| transaction.handle == handle)
+            .ok_or(FfaError::InvalidParameters)
+    }
+}
+
+/// The memory transactions from the normal world which haven't yet been reclaimed.
+#[derive(Debug)]
+pub struct MemoryTransactions {
+    state: SpinMutex<TransactionsState>,
+}
+
+impl MemoryTransactions {
+    /// Creates a new, empty set of memory transactions.
+    pub const fn new() -> Self {
+        Self {
+            state: SpinMutex::new(TransactionsState {
+                transactions: ArrayVec::new_const(),
+                next_handle: 0,
+            }),
+        }
+    }
+
+    /// Validates the memory transaction descriptor which `sender` sent with `FFA_MEM_SHARE`,
+    /// `FFA_MEM_LEND` or `FFA_MEM_DONATE`, and records the transaction.
+    ///
+    /// `is_partition` returns whether the given endpoint ID is a partition which may receive
+    /// memory. Returns the handle allocated to the transaction.
+    pub(super) fn send(
+        &self,
+        kind: MemoryTransactionKind,
+        sender: u16,
+        descriptor: &[u8],
+        is_partition: impl Fn(u16) -> bool,
+    ) -> Result<Handle, FfaError> {
+        let (transaction_desc, access_descs, constituent_descs) =
+            MemTransactionDesc::unpack(descriptor)?;
+        let transaction_type = transaction_desc.flags.0 & TRANSACTION_TYPE_MASK;
+        if transaction_desc.sender_id != sender
+            || transaction_desc.handle != Handle(0)
+            || (transaction_type != 0 && transaction_type != kind.type_flag())
+        {
+            return Err(FfaError::InvalidParameters);
+        }
+
+        let mut receivers = ArrayVec::<Receiver, MAX_RECEIVERS>::new();
+        for access_desc in access_descs {
+            let id = access_desc?.endpoint_id;
+            if !is_partition(id) || receivers.iter().any(|receiver| receiver.id == id) {
+                return Err(FfaError::InvalidParameters);
+            }
+            receivers
+                .try_push(Receiver {
+                    id,
+                    retrieved: false,
+                })
+                .map_err(|_| FfaError::NoMemory)?;
+        }
+        if kind == MemoryTransactionKind::Donate && receivers.len() != 1 {
+            return Err(FfaError::InvalidParameters);
+        }
+
+        let mut constituents = ArrayVec::<ConstituentMemRegion, MAX_CONSTITUENTS>::new();
+        for constituent in constituent_descs.ok_or(FfaError::InvalidParameters)? {
+            let constituent = constituent?;
+            if constituent.page_cnt == 0
+                || constituent.address % FFA_PAGE_SIZE != 0
+                || constituent
+                    .address
+                    .checked_add(u64::from(constituent.page_cnt) * FFA_PAGE_SIZE)
+                    .is_none()
+            {
+                return Err(FfaError::InvalidParameters);
+            }
+            constituents
+                .try_push(constituent)
+                .map_err(|_| FfaError::NoMemory)?;
+        }
+        if constituents.is_empty() {
+            return Err(FfaError::InvalidParameters);
+        }
+
+        let mut state = self.state.lock();
+        if state.transactions.iter().any(|transaction| {
+            constituents
+                .iter()
+                .any(|constituent| transaction.overlaps(constituent))
+        }) {
+            return Err(FfaError::Denied);
+        }
+        if state.transactions.is_full() {
+            return Err(FfaError::NoMemory);
+        }
+
+        let handle = Handle(HANDLE_ALLOCATOR_SPMC | state.next_handle);
+        state.next_handle += 1;
+        state.transactions.push(MemoryTransaction {
+            handle,
+            kind,
+            sender,
+            receivers,
+            constituents,
+        });
+        Ok(handle)
+    }
+
+    /// Retrieves the memory sent in the transaction with the given handle for the logical
+    /// partition `receiver`.
+    ///
+    /// Once donated memory has been retrieved the transaction is complete, and the partition owns
+    /// the memory.
+    pub fn retrieve(&self, receiver: u16, handle: Handle) -> Result<RetrievedMemory, FfaError> {
+        let mut state = self.state.lock();
+        let index = state.find(handle)?;
+        let transaction = &mut state.transactions[index];
+        let receiver = transaction.receiver(receiver)?;
+        if receiver.retrieved {
+            return Err(FfaError::Denied);
+        }
+        receiver.retrieved = true;
+
+        let retrieved = RetrievedMemory {
+            kind: transaction.kind,
+            sender: transaction.sender,
+            constituents: transaction.constituents.clone(),
+        };
+        if transaction.kind == MemoryTransactionKind::Donate {
+            state.transactions.swap_remove(index);
+        }
+        Ok(retrieved)
+    }
+
+    /// Gives up the logical partition `receiver`'s access to memory which it previously
+    /// retrieved, so that the sender may reclaim it.
+    pub fn relinquish(&self, receiver: u16, handle: Handle) -> Result<(), FfaError> {
+        let mut state = self.state.lock();
+        let index = state.find(handle)?;
+        let receiver = state.transactions[index].receiver(receiver)?;
+        if !receiver.retrieved {
+            return Err(FfaError::Denied);
+        }
+        receiver.retrieved = false;
+        Ok(())
+    }
+
+    /// Handles `FFA_MEM_RECLAIM` from `sender`, ending the transaction if no receiver still has
+    /// the memory retrieved.
+    pub(super) fn reclaim(&self, sender: u16, handle: Handle) -> Result<(), FfaError> {
+        let mut state = self.state.lock();
+        let index = state.find(handle)?;
+        let transaction = &state.transactions[index];
+        if transaction.sender != sender {
+            return Err(FfaError::InvalidParameters);
+        }
+        if transaction
+            .receivers
+            .iter()
+            .any(|receiver| receiver.retrieved)
+        {
+            return Err(FfaError::Denied);
+        }
+        state.transactions.swap_remove(index);
+        Ok(())
+    }
+}
+
+impl Default for MemoryTransactions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Returns the address just past the end of the given region.
+fn constituent_end(region: &ConstituentMemRegion) -> u64 {
+    region.address + u64::from(region.page_cnt) * FFA_PAGE_SIZE
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arm_ffa::memory_management::MemAccessPerm;
+
+    const SENDER: u16 = 0;
+    const PARTITION_A: u16 = 0x8001;
+    const PARTITION_B: u16 = 0x8002;
+
+    fn is_partition(id: u16) -> bool {
+        id == PARTITION_A || id == PARTITION_B
+    }
+
+    fn descriptor(
+        sender: u16,
+        flags: u32,
+        receivers: &[u16],
+        constituents: &[ConstituentMemRegion],
+    ) -> ([u8; 512], usize) {
+        let access: ArrayVec<MemAccessPerm, 4> = receivers
+            .iter()
+            .map(|&endpoint_id| MemAccessPerm {
+                endpoint_id,
+                ..Default::default()
+            })
+            .collect();
+        let mut buf = [0; 512];
+        let len = MemTransactionDesc {
+            sender_id: sender,
+            flags: MemTransactionFlags(flags),
+            ..Default::default()
+        }
+        .pack(constituents, &access, &mut buf);
+        (buf, len)
+    }
+
+    fn region(address: u64, page_cnt: u32) -> ConstituentMemRegion {
+        ConstituentMemRegion { address, page_cnt }
+    }
+
+    fn send(
+        transactions: &MemoryTransactions,
+        kind: MemoryTransactionKind,
+        receivers: &[u16],
+        constituents: &[ConstituentMemRegion],
+    ) -> Result<Handle, FfaError> {
+        let (buf, len) = descriptor(SENDER, 0, receivers, constituents);
+        transactions.send(kind, SENDER, &buf[..len], is_partition)
+    }
+
+    #[test]
+    fn share_retrieve_relinquish_reclaim() {
+        let transactions = MemoryTransactions::new();
+        let handle = send(
+            &transactions,
+            MemoryTransactionKind::Share,
+            &[PARTITION_A, PARTITION_B],
+            &[region(0x8000_0000, 2)],
+        )
+        .unwrap();
+        assert_eq!(handle.0 & HANDLE_ALLOCATOR_SPMC, HANDLE_ALLOCATOR_SPMC);
+
+        let retrieved = transactions.retrieve(PARTITION_A, handle).unwrap();
+        assert_eq!(retrieved.kind, MemoryTransactionKind::Share);
+        assert_eq!(retrieved.sender, SENDER);
+        assert_eq!(retrieved.constituents.as_slice(), [region(0x8000_0000, 2)]);
+        assert_eq!(
+            transactions.retrieve(PARTITION_A, handle),
+            Err(FfaError::Denied)
+        );
+        assert_eq!(
+            transactions.retrieve(0x8003, handle),
+            Err(FfaError::InvalidParameters)
+        );
+
+        // The memory can't be reclaimed while a receiver has it retrieved.
+        assert_eq!(transactions.reclaim(SENDER, handle), Err(FfaError::Denied));
+        assert_eq!(transactions.relinquish(PARTITION_A, handle), Ok(()));
+        assert_eq!(
+            transactions.relinquish(PARTITION_A, handle),
+            Err(FfaError::Denied)
+        );
+        assert_eq!(
+            transactions.reclaim(PARTITION_A, handle),
+            Err(FfaError::InvalidParameters)
+        );
+        assert_eq!(transactions.reclaim(SENDER, handle), Ok(()));
+        assert_eq!(
+            transactions.reclaim(SENDER, handle),
+            Err(FfaError::InvalidParameters)
+        );
+    }
+
+    #[test]
+    fn donate_completes_on_retrieve() {
+        let transactions = MemoryTransactions::new();
+        assert_eq!(
+            send(
+                &transactions,
+                MemoryTransactionKind::Donate,
+                &[PARTITION_A, PARTITION_B],
+                &[region(0x8000_0000, 1)],
+            ),
+            Err(FfaError::InvalidParameters)
+        );
+
+        let handle = send(
+            &transactions,
+            MemoryTransactionKind::Donate,
+            &[PARTITION_A],
+            &[region(0x8000_0000, 1)],
+        )
+        .unwrap();
+        assert!(transactions.retrieve(PARTITION_A, handle).is_ok());
+        assert_eq!(
+            transactions.reclaim(SENDER, handle),
+            Err(FfaError::InvalidParameters)
+        );
+    }
+
+    #[test]
+    fn invalid_descriptors() {
+        let transactions = MemoryTransactions::new();
+
+        // Wrong sender.
+        let (buf, len) = descriptor(1, 0, &[PARTITION_A], &[region(0x8000_0000, 1)]);
+        assert_eq!(
+            transactions.send(
+                MemoryTransactionKind::Lend,
+                SENDER,
+                &buf[..len],
+                is_partition
+            ),
+            Err(FfaError::InvalidParameters)
+        );
+
+        // Transaction type doesn't match the call.
+        let (buf, len) = descriptor(
+            SENDER,
+            MemTransactionFlags::TYPE_SHARE,
+            &[PARTITION_A],
+            &[region(0x8000_0000, 1)],
+        );
+        assert_eq!(
+            transactions.send(
+                MemoryTransactionKind::Lend,
+                SENDER,
+                &buf[..len],
+                is_partition
+            ),
+            Err(FfaError::InvalidParameters)
+        );
+
+        // Receiver isn't a partition, or is repeated.
+        for receivers in [&[0x8003][..], &[PARTITION_A, PARTITION_A]] {
+            assert_eq!(
+                send(
+                    &transactions,
+                    MemoryTransactionKind::Lend,
+                    receivers,
+                    &[region(0x8000_0000, 1)],
+                ),
+                Err(FfaError::InvalidParameters)
+            );
+        }
+
+        // Misaligned or empty regions.
+        for constituents in [
+            &[][..],
+            &[region(0x8000_0800, 1)],
+            &[region(0x8000_0000, 0)],
+        ] {
+            assert_eq!(
+                send(
+                    &transactions,
+                    MemoryTransactionKind::Lend,
+                    &[PARTITION_A],
+                    constituents,
+                ),
+                Err(FfaError::InvalidParameters)
+            );
+        }
+
+        // Truncated descriptor.
+        let (buf, len) = descriptor(SENDER, 0, &[PARTITION_A], &[region(0x8000_0000, 1)]);
+        assert_eq!(
+            transactions.send(
+                MemoryTransactionKind::Lend,
+                SENDER,
+                &buf[..len - 16],
+                is_partition
+            ),
+            Err(FfaError::InvalidParameters)
+        );
+    }
+
+    #[test]
+    fn overlapping_regions() {
+        let transactions = MemoryTransactions::new();
+        send(
+            &transactions,
+            MemoryTransactionKind::Lend,
+            &[PARTITION_A],
+            &[region(0x8000_0000, 4)],
+        )
+        .unwrap();
+
+        assert_eq!(
+            send(
+                &transactions,
+                MemoryTransactionKind::Share,
+                &[PARTITION_B],
+                &[region(0x8000_3000, 2)],
+            ),
+            Err(FfaError::Denied)
+        );
+        assert!(
+            send(
+                &transactions,
+                MemoryTransactionKind::Share,
+                &[PARTITION_B],
+                &[region(0x8000_4000, 2)],
+            )
+            .is_ok()
+        );
+    }
+}
@@ -4,45 +4,231 @@
 
 //! FF-A Secure Partition Manager Dispatcher.
 
+#[cfg(feature = "sel2")]
+use crate::context::{SecureEl2Sysregs, Stage2Config};
 use crate::{
-    context::{CpuStateAccess, PerCoreState, World, switch_world},
+    context::{CoresImpl, CpuDataIndex, CpuStateAccess, PerCoreState, World, switch_world},
     errata_framework::PlatformErrata,
     exceptions::{RunResult, enter_world},
+    gicv3::{SgiUser, send_non_secure_sgi_to_self},
     platform::{Platform, exception_free},
-    services::{Service, owns, psci::PsciSpmInterface},
-    smccc::{FunctionId, OwningEntityNumber, SmcReturn, SmcccCallType},
+    runtime_config::runtime_config,
+    services::{
+        Service,
+        debug::{
+            InterruptLatencyPhase, InterruptLatencyStats, SmcAuditBuffer, WorldSwitchReason,
+            WorldSwitchStats,
+        },
+        deferred::{DeferredWork, DeferredWorkQueue},
+        ffa::{
+            NS_EP_ID,
+            mailbox::Mailbox,
+            secure_interrupts::{SecureInterruptAssignment, SecureInterruptOwnership},
+        },
+        handle_sysreg_trap, handle_unknown_hvc, owns,
+        psci::PsciSpmInterface,
+    },
+    smccc::{FunctionId, NOT_SUPPORTED, OwningEntityNumber, SetFrom, SmcReturn, SmcccCallType},
+    spin_mutex::SpinMutex,
+    timer::Timeout,
 };
 use arm_ffa::{
-    FfaError, Interface, Version, VersionOut,
+    FfaError, Interface, Uuid, UuidHelper, Version, VersionOut,
     interface_args::{
-        DirectMsgArgs, SecondaryEpRegisterAddr, SuccessArgsIdGet, SuccessArgsSpmIdGet, TargetInfo,
-        VersionQueryType, WarmBootType,
+        DirectMsgArgs, Feature, FeatureId, RxTxAddr, SecondaryEpRegisterAddr, SuccessArgs,
+        SuccessArgsFeatures, SuccessArgsIdGet, SuccessArgsSpmIdGet, TargetInfo, VersionQueryType,
+        WarmBootType,
+    },
+    partition_info::{
+        PartitionInfo, PartitionInfoGetFlags, SuccessArgsPartitionInfoGet,
+        SuccessArgsPartitionInfoGetRegs,
     },
 };
-use arm_psci::{ErrorCode, Function, ReturnCode};
+use arm_gic::IntId;
+use arm_psci::{ErrorCode, Function, Mpidr, ReturnCode};
+#[cfg(feature = "sel2")]
+use arm_sysregs::{HcrEl2, read_hcr_el2, read_vtcr_el2, read_vttbr_el2};
+use arrayvec::ArrayVec;
 use core::{
     cell::RefCell,
-    sync::atomic::{AtomicUsize, Ordering::Relaxed},
+    ops::Range,
+    sync::atomic::{
+        AtomicBool, AtomicU32, AtomicUsize,
+        Ordering::{Acquire, Relaxed, Release},
+    },
 };
 use log::{debug, error, trace, warn};
-use percore::{ExceptionLock, PerCore};
+use percore::{Cores, ExceptionLock, PerCore};
 
 const FUNCTION_NUMBER_MIN: u16 = 0x0060;
 const FUNCTION_NUMBER_MAX: u16 = 0x00EF;
 
+/// The maximum number of normal world VMs which may have a notification bitmap at the same time.
+const MAX_NOTIFICATION_BITMAPS: usize = 64;
+
+/// The maximum number of partition information descriptors which the SPMD caches. If the SPMC
+/// reports more partitions than this, `FFA_PARTITION_INFO_GET` is always forwarded to it.
+const MAX_CACHED_PARTITIONS: usize = 32;
+
+/// A partition information descriptor, encoded as for `FFA_PARTITION_INFO_GET` since FF-A v1.1.
+/// `FFA_PARTITION_INFO_GET_REGS` returns descriptors in the same layout.
+type PartitionInfoDescriptor = [u8; PartitionInfo::DESC_SIZE];
+
+/// The offset of the partition's UUID within a [`PartitionInfoDescriptor`].
+const PARTITION_INFO_UUID_OFFSET: usize = 8;
+
+/// The implementation defined direct message request which the SPMC sends to the SPMD during boot,
+/// before configuring a secure interrupt on behalf of a partition, with the partition's endpoint ID
+/// and the INTID in the next two arguments. The SPMD responds with 0 if the partition owns the
+/// interrupt, or an FF-A error code otherwise.
+const INTERRUPT_CONFIG_REQ: u32 = 0x1;
+
+/// The number of 64-bit arguments which EL3 passes in a direct message request, or receives in the
+/// response.
+pub const DIRECT_MSG_ARG_COUNT: usize = 5;
+
+/// An error sending a direct message request from EL3 to a secure partition.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum DirectRequestError {
+    /// The SPMC or the SPMD returned an FF-A error.
+    Ffa(FfaError),
+    /// The partition yielded after the timeout expired, without responding.
+    ///
+    /// The request is still outstanding, and must be resumed with
+    /// [`Spmd::resume_direct_request`] before another one can be sent from this core.
+    TimedOut,
+    /// The partition was preempted by a non-secure interrupt, which the normal world must handle.
+    ///
+    /// The request is still outstanding, as for [`DirectRequestError::TimedOut`].
+    Interrupted,
+    /// The SPMC returned something other than a direct message response from the partition.
+    InvalidResponse,
+}
+
+/// What to do with a message from the secure world while a direct request from EL3 is outstanding.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum DirectRequestStep {
+    /// Send the given message to the secure world, and wait for it to return again.
+    Resume(Interface),
+    /// The partition yielded, so should be resumed with `FFA_RUN` unless the timeout has expired.
+    Yielded,
+    /// The partition was preempted by a non-secure interrupt.
+    Interrupted,
+    /// The request has completed.
+    Done(Result<[u64; DIRECT_MSG_ARG_COUNT], DirectRequestError>),
+}
+
+/// SPMC attributes described by the SPMC manifest.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SpmcManifest {
+    /// The FF-A endpoint ID of the SPMC.
+    pub spmc_id: u16,
+    /// The FF-A version implemented by the SPMC.
+    pub version: Version,
+    /// The secure interrupts assigned to each secure partition by its manifest.
+    pub secure_interrupts: &'static [SecureInterruptAssignment],
+    /// Whether the SPMC can resume from its saved state when a core wakes from a power down
+    /// suspend state, rather than having to re-initialise its state for the core.
+    pub resume_after_suspend: bool,
+    /// A range of non-secure memory which the platform maps into EL3's address space for the normal
+    /// world's RX/TX buffers. If the normal world maps its buffers within it, the SPMD writes the
+    /// cached partition information descriptors to the RX buffer itself rather than forwarding
+    /// `FFA_PARTITION_INFO_GET` to the SPMC.
+    pub ns_buffer_region: Range<usize>,
+}
+
+impl SpmcManifest {
+    /// The attributes of the reference SPMC, used unless the platform provides its own manifest.
+    pub const DEFAULT: Self = Self {
+        spmc_id: 0x8000,
+        version: Version(1, 3),
+        secure_interrupts: &[],
+        resume_after_suspend: false,
+        ns_buffer_region: 0..0,
+    };
+}
+
 /// Core-local state of the SPMD service
 struct SpmdLocal {
     spmc_state: SpmcState,
+    /// Notification bitmap operation forwarded to the SPMC on this core, whose result hasn't been
+    /// returned to the normal world yet.
+    pending_bitmap_op: Option<NotificationBitmapOp>,
+    /// The reason and start timestamp of a world switch which has been decided but not yet
+    /// performed.
+    pending_world_switch: Option<(WorldSwitchReason, u64)>,
+    /// The timestamp at which a secure interrupt was forwarded to the SPMC, if it is still being
+    /// handled and latency is being measured.
+    secure_interrupt_start: Option<u64>,
+    /// The partition which hasn't yet responded to a direct request sent from EL3 on this core,
+    /// after it timed out or was interrupted.
+    el3_direct_request: Option<u16>,
+    /// Whether the SPMC's state was saved when this core was last powered down for a suspend, so
+    /// it can be resumed when the core wakes up.
+    suspend_context_saved: bool,
+    /// The secure-only EL2 system registers saved before this core was powered down for a suspend.
+    #[cfg(feature = "sel2")]
+    suspended_el2_sysregs: SecureEl2Sysregs,
 }
 
 impl SpmdLocal {
     const fn new() -> Self {
         Self {
             spmc_state: SpmcState::Off,
+            pending_bitmap_op: None,
+            pending_world_switch: None,
+            secure_interrupt_start: None,
+            el3_direct_request: None,
+            suspend_context_saved: false,
+            #[cfg(feature = "sel2")]
+            suspended_el2_sysregs: SecureEl2Sysregs::EMPTY,
         }
     }
 }
 
+/// The state of a normal world VM's notification bitmap in the SPMC.
+///
+/// The state is changed under the `notification_bitmaps` lock before a creation or destruction
+/// request is forwarded to the SPMC, so that calls on other cores which use the bitmap can't race
+/// with the request while it is in flight.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum NotificationBitmapState {
+    /// A request to create the bitmap has been forwarded to the SPMC.
+    Creating,
+    /// The SPMC has created the bitmap.
+    Created,
+    /// A request to destroy the bitmap has been forwarded to the SPMC.
+    Destroying,
+}
+
+/// A notification bitmap operation requested by the normal world for the given VM ID.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum NotificationBitmapOp {
+    Create(u16),
+    Destroy(u16),
+}
+
+/// A call affecting the normal world's RX/TX buffers which has been forwarded to the SPMC, whose
+/// response the SPMD needs to see.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum MailboxOp {
+    /// `FFA_RXTX_MAP` of the given buffers.
+    Map { rx: u64, tx: u64, page_count: u32 },
+    /// `FFA_RXTX_UNMAP` of the normal world's buffers.
+    Unmap,
+    /// `FFA_RX_RELEASE` of the normal world's RX buffer.
+    Release,
+    /// `FFA_PARTITION_INFO_GET_REGS` for all partitions, sent in place of `FFA_PARTITION_INFO_GET`
+    /// for the given UUID and flags to fill the cache before answering it.
+    FetchPartitionInfo {
+        uuid: Uuid,
+        flags: PartitionInfoGetFlags,
+    },
+    /// `FFA_RX_ACQUIRE`, sent in place of `FFA_PARTITION_INFO_GET` for the given UUID so that the
+    /// SPMD can write the cached descriptors to the RX buffer.
+    WritePartitionInfo(Uuid),
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum SpmcState {
     Off,
@@ -50,24 +236,169 @@ enum SpmcState {
     Runtime,
     SecureInterrupt,
     PsciEventHandling,
+    /// An EL3 service is waiting for a response to a direct message request.
+    El3DirectRequest,
+}
+
+/// The partition information descriptors which the SPMD has cached from the SPMC.
+///
+/// The descriptors are fetched with `FFA_PARTITION_INFO_GET_REGS`, so they come straight from the
+/// SPMC's registers rather than through memory which the normal world can modify.
+struct PartitionInfoCache {
+    state: SpinMutex<PartitionInfoState>,
+}
+
+struct PartitionInfoState {
+    /// The index of the core which is fetching the descriptors from the SPMC, if any.
+    fetching_core: Option<usize>,
+    /// Whether `descriptors` holds those of all partitions, rather than only those fetched so far.
+    complete: bool,
+    descriptors: ArrayVec<PartitionInfoDescriptor, MAX_CACHED_PARTITIONS>,
+}
+
+impl PartitionInfoState {
+    /// Returns the descriptors of all partitions, if they have been cached.
+    fn cached(&self) -> Option<&[PartitionInfoDescriptor]> {
+        self.complete.then_some(&self.descriptors)
+    }
+
+    fn clear(&mut self) {
+        self.fetching_core = None;
+        self.complete = false;
+        self.descriptors.clear();
+    }
+}
+
+impl PartitionInfoCache {
+    /// Creates a new, empty cache.
+    const fn new() -> Self {
+        Self {
+            state: SpinMutex::new(PartitionInfoState {
+                fetching_core: None,
+                complete: false,
+                descriptors: ArrayVec::new_const(),
+            }),
+        }
+    }
+
+    /// Starts fetching the descriptors on the given core, unless another core is already doing so.
+    fn start_fetch(&self, core_index: usize) -> bool {
+        let mut state = self.state.lock();
+        if state.fetching_core.is_some_and(|core| core != core_index) {
+            return false;
+        }
+        state.clear();
+        state.fetching_core = Some(core_index);
+        true
+    }
+
+    /// Discards the descriptors being fetched on the given core, if any.
+    fn abandon_fetch(&self, core_index: usize) {
+        let mut state = self.state.lock();
+        if state.fetching_core == Some(core_index) {
+            state.clear();
+        }
+    }
+
+    /// Discards the cached descriptors and any being fetched, so that they are fetched again the
+    /// next time they are needed.
+    fn invalidate(&self) {
+        self.state.lock().clear();
+    }
+}
+
+/// The SPMD's state for the normal world's RX/TX buffers.
+///
+/// This is kept outside the [`Spmd`] so that it doesn't need to fit on the stack while the services
+/// are constructed.
+pub struct MailboxState<const CORE_COUNT: usize> {
+    /// The RX/TX buffer operation forwarded to the SPMC on each core, whose result hasn't been
+    /// returned to the normal world yet.
+    pending_ops: [SpinMutex<Option<MailboxOp>>; CORE_COUNT],
+    /// The partition information descriptors which the SPMD writes to the RX buffer.
+    partition_info: PartitionInfoCache,
+}
+
+impl<const CORE_COUNT: usize> MailboxState<CORE_COUNT> {
+    /// Creates a new state with no operations pending and nothing cached.
+    pub const fn new() -> Self {
+        Self {
+            pending_ops: [const { SpinMutex::new(None) }; CORE_COUNT],
+            partition_info: PartitionInfoCache::new(),
+        }
+    }
+}
+
+impl<const CORE_COUNT: usize> Default for MailboxState<CORE_COUNT> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The progress of fetching the partition information descriptors from the SPMC.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PartitionInfoFetch {
+    /// The descriptors from the given index onwards are still to be fetched.
+    More { start_index: u16, info_tag: u16 },
+    /// All the descriptors have been fetched and cached.
+    Done,
+    /// The SPMC's response was invalid, or the cache was invalidated while it was being filled.
+    Failed,
 }
 
 /// Secure Partition Manager Dispatcher, defined by Arm Firmware Framework for A-Profile (FF-A)
-pub struct Spmd<const CORE_COUNT: usize, PlatformImpl: Platform> {
+pub struct Spmd<const CORE_COUNT: usize, PlatformImpl: Platform + 'static> {
     spmc_id: u16,
     spmc_version: Version,
     spmc_primary_ep: usize,
     spmc_secondary_ep: AtomicUsize,
+    /// Whether the SPMC has finished its cold boot initialisation, so secondary cores may be
+    /// powered on.
+    spmc_booted: AtomicBool,
+    /// Whether the SPMC can resume from its saved state after a power down suspend.
+    resume_after_suspend: bool,
+    /// The secure partitions which own each secure interrupt.
+    secure_interrupts: SecureInterruptOwnership,
+    /// IDs of the normal world VMs which have a notification bitmap in the SPMC, or are having one
+    /// created, along with its state.
+    notification_bitmaps:
+        SpinMutex<ArrayVec<(u16, NotificationBitmapState), MAX_NOTIFICATION_BITMAPS>>,
+    /// The normal world's RX/TX buffers, if it has mapped them within the platform's
+    /// `ns_buffer_region`.
+    mailbox: Mailbox,
+    /// RX/TX buffer calls awaiting the SPMC's response, and the cached partition information.
+    mailbox_state: &'static MailboxState<CORE_COUNT>,
+    /// The FF-A version which the normal world last asked to negotiate, encoded as for
+    /// `FFA_VERSION`, or 0 if it hasn't yet.
+    ns_version: AtomicU32,
+    /// The SGI which the platform has claimed as the Schedule Receiver Interrupt, if any.
+    schedule_receiver_interrupt: Option<IntId>,
+    world_switch_stats: WorldSwitchStats<CORE_COUNT>,
+    interrupt_latency_stats: InterruptLatencyStats<CORE_COUNT>,
+    /// The last FF-A calls forwarded from the normal world to the SPMC on each core.
+    smc_audit: &'static SmcAuditBuffer<CORE_COUNT>,
+    /// Work to run before next entering a world, such as sending a delayed Schedule Receiver
+    /// Interrupt.
+    deferred_work: &'static DeferredWorkQueue<CORE_COUNT, PlatformImpl>,
     core_local: PerCoreState<CORE_COUNT, PlatformImpl, SpmdLocal>,
 }
 
-impl<const CORE_COUNT: usize, PlatformImpl: Platform> Service for Spmd<CORE_COUNT, PlatformImpl> {
+impl<const CORE_COUNT: usize, PlatformImpl: Platform + 'static> Service
+    for Spmd<CORE_COUNT, PlatformImpl>
+{
     owns!(
         OwningEntityNumber::STANDARD_SECURE,
         FUNCTION_NUMBER_MIN..=FUNCTION_NUMBER_MAX
     );
 
     fn handle_non_secure_smc(&self, regs: &mut SmcReturn) -> World {
+        if !runtime_config().spmc_present {
+            regs.set_from(NOT_SUPPORTED);
+            return World::NonSecure;
+        }
+
+        let start = WorldSwitchStats::<CORE_COUNT>::timestamp();
+
         // TODO: forward SVE hint bit
 
         // TODO: should we use a different version for NWd?
@@ -85,9 +416,17 @@ impl<const CORE_COUNT: usize, PlatformImpl: Platform> Service for Spmd<CORE_COUN
                 assert_eq!(spmc_state, SpmcState::Runtime);
 
                 let next_world = self.handle_non_secure_call(msg);
+                if next_world == World::Secure {
+                    self.start_world_switch(WorldSwitchReason::NonSecureSmc, start);
+                }
 
                 msg.to_regs(version, smc_regs);
 
+                if next_world == World::Secure {
+                    self.smc_audit
+                        .record(CoresImpl::<PlatformImpl>::core_index(), smc_regs);
+                }
+
                 next_world
             }
             Err(error) => {
@@ -109,6 +448,7 @@ impl<const CORE_COUNT: usize, PlatformImpl: Platform> Service for Spmd<CORE_COUN
     }
 
     fn handle_secure_smc(&self, regs: &mut SmcReturn) -> World {
+        let start = WorldSwitchStats::<CORE_COUNT>::timestamp();
         let version = self.spmc_version;
 
         let smc_regs = get_smc_regs(regs);
@@ -122,12 +462,28 @@ impl<const CORE_COUNT: usize, PlatformImpl: Platform> Service for Spmd<CORE_COUN
 
                 let (has_msg, next_world) = match spmc_state {
                     SpmcState::Off => panic!(),
+                    // Direct requests from EL3 handle secure world calls themselves until they
+                    // complete.
+                    SpmcState::El3DirectRequest => unreachable!(),
                     SpmcState::Boot => self.handle_secure_call_boot(msg),
                     SpmcState::Runtime => self.handle_secure_call_runtime(msg),
                     SpmcState::SecureInterrupt => self.handle_secure_call_interrupt(msg),
                     SpmcState::PsciEventHandling => self.handle_secure_call_psci_event(msg),
                 };
 
+                if next_world == World::NonSecure {
+                    match spmc_state {
+                        SpmcState::Runtime | SpmcState::SecureInterrupt => {
+                            self.start_world_switch(WorldSwitchReason::FfaCompletion, start)
+                        }
+                        SpmcState::PsciEventHandling => {
+                            self.start_world_switch(WorldSwitchReason::PsciEvent, start)
+                        }
+                        // Switches at the end of SPMC initialisation are not runtime overhead.
+                        SpmcState::Off | SpmcState::Boot | SpmcState::El3DirectRequest => {}
+                    }
+                }
+
                 if has_msg {
                     msg.to_regs(version, smc_regs);
                 } else {
@@ -145,7 +501,33 @@ impl<const CORE_COUNT: usize, PlatformImpl: Platform> Service for Spmd<CORE_COUN
     }
 }
 
-fn get_smc_regs(regs: &mut SmcReturn) -> &mut [u64] {
+/// Logs the stage 2 configuration which the SPMC has set up, warning if it is invalid.
+///
+/// This must be called while the secure world's EL2 registers are live.
+#[cfg(feature = "sel2")]
+fn check_spmc_stage2_config() {
+    if !read_hcr_el2().contains(HcrEl2::VM) {
+        return;
+    }
+    match Stage2Config::decode(read_vtcr_el2(), read_vttbr_el2()) {
+        Ok(config) => debug!("SPMC stage 2 configuration: {config:x?}"),
+        Err(error) => warn!("SPMC stage 2 configuration is invalid: {error:?}"),
+    }
+}
+
+/// Sends the Schedule Receiver Interrupt with the given SGI index to the normal world, as deferred
+/// work.
+fn send_sri(sgi: u64) {
+    send_non_secure_sgi_to_self(IntId::sgi(sgi as u32));
+}
+
+/// Returns whether the given partition information descriptor matches the given UUID, which
+/// matches all partitions if it is nil.
+fn partition_info_matches(descriptor: &PartitionInfoDescriptor, uuid: Uuid) -> bool {
+    uuid.is_nil() || descriptor[PARTITION_INFO_UUID_OFFSET..] == UuidHelper::to_bytes(uuid)
+}
+
+pub(super) fn get_smc_regs(regs: &mut SmcReturn) -> &mut [u64] {
     match FunctionId(regs.values_mut()[0] as u32).call_type() {
         SmcccCallType::Fast32 => &mut regs.mark_used::<8>()[..],
         SmcccCallType::Fast64 => &mut regs.mark_used::<18>()[..],
@@ -153,7 +535,7 @@ fn get_smc_regs(regs: &mut SmcReturn) -> &mut [u64] {
     }
 }
 
-impl<const CORE_COUNT: usize, PlatformImpl: Platform> Spmd<CORE_COUNT, PlatformImpl> {
+impl<const CORE_COUNT: usize, PlatformImpl: Platform + 'static> Spmd<CORE_COUNT, PlatformImpl> {
     const OWN_ID: u16 = 0xffff;
     const VERSION: Version = Version(1, 3);
     const NS_EP_ID: u16 = 0; // TODO: this should come from arm_ffa
@@ -161,18 +543,32 @@ impl<const CORE_COUNT: usize, PlatformImpl: Platform> Spmd<CORE_COUNT, PlatformI
     /// Initialises the SPMD state.
     ///
     /// This should be called exactly once, before any other SPMD methods are called or any
-    /// secondary CPUs are started.
-    #[allow(clippy::new_without_default)]
-    pub fn new() -> Self {
+    /// secondary CPUs are started. Forwarded calls are recorded in `smc_audit`, work which must
+    /// wait until a world is next entered is pushed to `deferred_work`, and RX/TX buffer calls and
+    /// partition information are tracked in `mailbox_state`.
+    pub fn new(
+        smc_audit: &'static SmcAuditBuffer<CORE_COUNT>,
+        deferred_work: &'static DeferredWorkQueue<CORE_COUNT, PlatformImpl>,
+        mailbox_state: &'static MailboxState<CORE_COUNT>,
+    ) -> Self {
         debug!("Initializing SPMD");
 
-        // TODO: read these attributes from the SPMC manifest
-        let spmc_id = 0x8000;
-        let spmc_version = Version(1, 3);
-        let spmc_primary_ep = 0x0600_0000;
+        let SpmcManifest {
+            spmc_id,
+            version: spmc_version,
+            secure_interrupts,
+            resume_after_suspend,
+            ns_buffer_region,
+        } = PlatformImpl::spmc_manifest();
+        let spmc_primary_ep = PlatformImpl::secure_entry_point().pc;
+
+        assert!(Self::is_secure_id(spmc_id), "Invalid SPMC ID {spmc_id:#x}");
 
         assert!(spmc_version.is_compatible_to(Self::VERSION));
 
+        let secure_interrupts = SecureInterruptOwnership::new(secure_interrupts);
+        secure_interrupts.assert_valid();
+
         let core_local = PerCore::new(
             [const { ExceptionLock::new(RefCell::new(SpmdLocal::new())) }; CORE_COUNT],
         );
@@ -183,6 +579,20 @@ impl<const CORE_COUNT: usize, PlatformImpl: Platform> Spmd<CORE_COUNT, PlatformI
             spmc_primary_ep,
             // By default the secondary EP is same as primary
             spmc_secondary_ep: spmc_primary_ep.into(),
+            spmc_booted: AtomicBool::new(false),
+            resume_after_suspend,
+            secure_interrupts,
+            notification_bitmaps: SpinMutex::new(ArrayVec::new()),
+            mailbox: Mailbox::new(ns_buffer_region),
+            mailbox_state,
+            ns_version: AtomicU32::new(0),
+            schedule_receiver_interrupt: PlatformImpl::GIC_CONFIG
+                .sgis
+                .find(SgiUser::NotificationSri),
+            world_switch_stats: WorldSwitchStats::new(),
+            interrupt_latency_stats: InterruptLatencyStats::new(),
+            smc_audit,
+            deferred_work,
             core_local,
         };
 
@@ -220,10 +630,17 @@ impl<const CORE_COUNT: usize, PlatformImpl: Platform> Spmd<CORE_COUNT, PlatformI
     /// the registers. The second return value specifies the next world to be called.
     fn handle_secure_call_common(&self, msg: &mut Interface) -> (bool, World) {
         *msg = match msg {
-            Interface::Features { .. } => {
-                // TODO: add list of supported features
-                Interface::success32_noargs()
-            }
+            Interface::Features { feat_id, .. } => match feat_id {
+                // All FF-A functions the SPMC may call are either handled here or forwarded to the
+                // normal world.
+                Feature::FuncId(_) => Interface::success32_noargs(),
+                // Feature IDs such as the managed exit interrupt describe partitions, which are
+                // owned by the SPMC. It reports them to the normal world itself based on the SP
+                // manifests, so the SPMD has nothing to report to the SPMC.
+                Feature::FeatureId(_) | Feature::Unknown(_) => {
+                    Interface::error(FfaError::NotSupported, true)
+                }
+            },
             Interface::IdGet => Interface::Success {
                 target_info: TargetInfo::default(),
                 args: SuccessArgsIdGet { id: self.spmc_id }.into(),
@@ -247,6 +664,7 @@ impl<const CORE_COUNT: usize, PlatformImpl: Platform> Spmd<CORE_COUNT, PlatformI
     fn handle_secure_call_boot(&self, msg: &mut Interface) -> (bool, World) {
         match msg {
             Interface::Error { error_code, .. } => {
+                self.report_spmc_fatal_error(*error_code);
                 // TODO: should we return an error instead of panic?
                 panic!("SPMC init failed with error {error_code}");
             }
@@ -272,7 +690,10 @@ impl<const CORE_COUNT: usize, PlatformImpl: Platform> Spmd<CORE_COUNT, PlatformI
             }
             Interface::MsgWait { .. } => {
                 // Receiving this message for the first time means that SPMC init succeeded
+                #[cfg(feature = "sel2")]
+                check_spmc_stage2_config();
                 self.switch_spmc_local_state(SpmcState::Boot, SpmcState::Runtime);
+                self.spmc_booted.store(true, Release);
 
                 // In this case the FFA_MSG_WAIT message shouldn't be forwarded, because this is not
                 // a response to a call made by NWd.
@@ -288,6 +709,13 @@ impl<const CORE_COUNT: usize, PlatformImpl: Platform> Spmd<CORE_COUNT, PlatformI
                 self.spmc_secondary_ep.store(secondary_ep, Relaxed);
                 *msg = Interface::success32_noargs()
             }
+            Interface::MsgSendDirectReq {
+                src_id,
+                dst_id: Self::OWN_ID,
+                args,
+            } if *src_id == self.spmc_id => {
+                *msg = self.handle_spmc_direct_request(args);
+            }
             Interface::Features { .. }
             | Interface::IdGet
             | Interface::SpmIdGet
@@ -350,14 +778,38 @@ impl<const CORE_COUNT: usize, PlatformImpl: Platform> Spmd<CORE_COUNT, PlatformI
                     next_world = World::NonSecure;
                 }
             }
+            Interface::NotificationSet {
+                sender_id,
+                receiver_id,
+                flags,
+                ..
+            } => {
+                *msg = match self.schedule_receiver(
+                    *sender_id,
+                    *receiver_id,
+                    flags.delay_schedule_receiver,
+                ) {
+                    Ok(()) => Interface::success32_noargs(),
+                    Err(error) => Interface::error(error, true),
+                };
+            }
             Interface::Features { .. }
             | Interface::IdGet
             | Interface::SpmIdGet
             | Interface::PartitionInfoGetRegs { .. } => {
                 return self.handle_secure_call_common(msg);
             }
-            Interface::Error { .. }
-            | Interface::Success { .. }
+            Interface::Error { error_code, .. } => {
+                if *error_code == FfaError::Aborted {
+                    self.report_spmc_fatal_error(*error_code);
+                    // The SPMC may come back with different partitions.
+                    self.mailbox_state.partition_info.invalidate();
+                }
+                self.complete_notification_bitmap_op(false);
+                // Forward to NWd, unless the SPMD has more to ask the SPMC first.
+                next_world = self.complete_mailbox_op(msg);
+            }
+            Interface::Success { .. }
             | Interface::Interrupt { .. }
             | Interface::MsgWait { .. }
             | Interface::Yield { .. }
@@ -365,8 +817,9 @@ impl<const CORE_COUNT: usize, PlatformImpl: Platform> Spmd<CORE_COUNT, PlatformI
             | Interface::MemOpPause { .. }
             | Interface::MemFragRx { .. }
             | Interface::MemFragTx { .. } => {
-                // Forward to NWd
-                next_world = World::NonSecure;
+                self.complete_notification_bitmap_op(matches!(msg, Interface::Success { .. }));
+                // Forward to NWd, unless the SPMD has more to ask the SPMC first.
+                next_world = self.complete_mailbox_op(msg);
             }
             _ => {
                 warn!("Unsupported FF-A call from Secure World: {msg:x?}");
@@ -384,6 +837,18 @@ impl<const CORE_COUNT: usize, PlatformImpl: Platform> Spmd<CORE_COUNT, PlatformI
         match msg {
             Interface::NormalWorldResume { .. } => {
                 self.switch_spmc_local_state(SpmcState::SecureInterrupt, SpmcState::Runtime);
+                let interrupt_start = exception_free(|token| {
+                    self.core_local
+                        .get()
+                        .borrow_mut(token)
+                        .secure_interrupt_start
+                        .take()
+                });
+                self.interrupt_latency_stats.record(
+                    CoresImpl::<PlatformImpl>::core_index(),
+                    InterruptLatencyPhase::SpmcDelegation,
+                    interrupt_start,
+                );
 
                 // Interrupt was handled, return to NWd which was preempted by a secure interrupt.
                 // Instead of forwarding the FFA_NORMAL_WORLD_RESUME message, NWd must be resumed
@@ -438,6 +903,9 @@ impl<const CORE_COUNT: usize, PlatformImpl: Platform> Spmd<CORE_COUNT, PlatformI
                 input_version,
                 flags,
             } => {
+                if flags.query_type == VersionQueryType::Negotiate {
+                    self.ns_version.store((*input_version).into(), Relaxed);
+                }
                 // Forward version call to the SPMC
                 next_world = World::Secure;
                 *msg = Interface::MsgSendDirectReq {
@@ -462,14 +930,25 @@ impl<const CORE_COUNT: usize, PlatformImpl: Platform> Spmd<CORE_COUNT, PlatformI
                     args: SuccessArgsSpmIdGet { id: self.spmc_id }.into(),
                 };
             }
-            Interface::MsgSendDirectReq { src_id, dst_id, .. }
-            | Interface::MsgSendDirectReq2 { src_id, dst_id, .. } => {
+            Interface::MsgSendDirectReq { src_id, dst_id, .. } => {
                 if Self::is_secure_id(*src_id) || !Self::is_secure_id(*dst_id) {
                     *msg = Interface::error(FfaError::InvalidParameters, true);
                 } else {
                     next_world = World::Secure;
                 }
             }
+            Interface::MsgSendDirectReq2 { src_id, dst_id, .. } => {
+                // The SPMD doesn't implement any services over FFA_MSG_SEND_DIRECT_REQ2, so the
+                // destination must be a partition managed by the SPMC. x4-x17 are forwarded as-is.
+                if Self::is_secure_id(*src_id)
+                    || !Self::is_secure_id(*dst_id)
+                    || *dst_id == Self::OWN_ID
+                {
+                    *msg = Interface::error(FfaError::InvalidParameters, true);
+                } else {
+                    next_world = World::Secure;
+                }
+            }
             Interface::MsgSend2 {
                 sender_vm_id: src_id,
                 ..
@@ -480,22 +959,93 @@ impl<const CORE_COUNT: usize, PlatformImpl: Platform> Spmd<CORE_COUNT, PlatformI
                     next_world = World::Secure;
                 }
             }
+            Interface::NotificationBitmapCreate { vm_id, .. } => {
+                match self.reserve_notification_bitmap(*vm_id) {
+                    Ok(()) => {
+                        self.start_notification_bitmap_op(NotificationBitmapOp::Create(*vm_id));
+                        next_world = World::Secure;
+                    }
+                    Err(error) => *msg = Interface::error(error, true),
+                }
+            }
+            Interface::NotificationBitmapDestroy { vm_id } => {
+                match self.start_destroying_notification_bitmap(*vm_id) {
+                    Ok(()) => {
+                        self.start_notification_bitmap_op(NotificationBitmapOp::Destroy(*vm_id));
+                        next_world = World::Secure;
+                    }
+                    Err(error) => *msg = Interface::error(error, true),
+                }
+            }
+            Interface::NotificationSet { receiver_id, .. } => {
+                // Notifications for a VM are pended in its notification bitmap, so it must have
+                // one. Those for a partition are checked by the SPMC.
+                let check = if Self::is_secure_id(*receiver_id) {
+                    Ok(())
+                } else {
+                    self.check_notification_bitmap(*receiver_id, FfaError::InvalidParameters)
+                };
+                match check {
+                    Ok(()) => next_world = World::Secure,
+                    Err(error) => *msg = Interface::error(error, true),
+                }
+            }
+            Interface::NotificationGet { endpoint_id, .. } => {
+                match self.check_notification_bitmap(*endpoint_id, FfaError::InvalidParameters) {
+                    Ok(()) => next_world = World::Secure,
+                    Err(error) => *msg = Interface::error(error, true),
+                }
+            }
+            Interface::Features {
+                feat_id: Feature::FeatureId(FeatureId::ScheduleReceiverInterrupt),
+                ..
+            } if self.schedule_receiver_interrupt.is_some() => {
+                // The SPMD sends the SRI itself, so reports its ID rather than the SPMC.
+                let intid = self.schedule_receiver_interrupt.unwrap();
+                *msg = Interface::Success {
+                    target_info: TargetInfo::default(),
+                    args: SuccessArgsFeatures {
+                        properties: [intid.into(), 0],
+                    }
+                    .into(),
+                };
+            }
+            Interface::RxTxMap { addr, page_cnt } => {
+                let (rx, tx) = match *addr {
+                    RxTxAddr::Addr32 { rx, tx } => (rx.into(), tx.into()),
+                    RxTxAddr::Addr64 { rx, tx } => (rx, tx),
+                };
+                self.start_mailbox_op(MailboxOp::Map {
+                    rx,
+                    tx,
+                    page_count: *page_cnt,
+                });
+                next_world = World::Secure;
+            }
+            Interface::RxTxUnmap { id } => {
+                if *id == NS_EP_ID {
+                    self.start_mailbox_op(MailboxOp::Unmap);
+                }
+                next_world = World::Secure;
+            }
+            Interface::RxRelease { vm_id } => {
+                if *vm_id == NS_EP_ID {
+                    self.start_mailbox_op(MailboxOp::Release);
+                }
+                next_world = World::Secure;
+            }
+            Interface::PartitionInfoGet { uuid, flags } => {
+                let (uuid, flags) = (*uuid, *flags);
+                next_world = self.partition_info_get(msg, uuid, flags);
+            }
             Interface::Error { .. }
             | Interface::Success { .. }
             | Interface::Features { .. }
             | Interface::RxAcquire { .. }
-            | Interface::RxRelease { .. }
-            | Interface::RxTxMap { .. }
-            | Interface::RxTxUnmap { .. }
-            | Interface::PartitionInfoGet { .. }
             | Interface::PartitionInfoGetRegs { .. }
             | Interface::Run { .. }
-            | Interface::NotificationBitmapCreate { .. }
-            | Interface::NotificationBitmapDestroy { .. }
             | Interface::NotificationBind { .. }
             | Interface::NotificationUnbind { .. }
-            | Interface::NotificationSet { .. }
-            | Interface::NotificationGet { .. }
             | Interface::NotificationInfoGet { .. }
             | Interface::MemDonate { .. }
             | Interface::MemLend { .. }
@@ -517,8 +1067,432 @@ impl<const CORE_COUNT: usize, PlatformImpl: Platform> Spmd<CORE_COUNT, PlatformI
         next_world
     }
 
+    /// Records a notification bitmap for the given normal world VM, before the creation request is
+    /// forwarded to the SPMC.
+    fn reserve_notification_bitmap(&self, vm_id: u16) -> Result<(), FfaError> {
+        if Self::is_secure_id(vm_id) {
+            return Err(FfaError::InvalidParameters);
+        }
+
+        let mut bitmaps = self.notification_bitmaps.lock();
+        if bitmaps.iter().any(|(id, _)| *id == vm_id) {
+            return Err(FfaError::Denied);
+        }
+        bitmaps
+            .try_push((vm_id, NotificationBitmapState::Creating))
+            .map_err(|_| FfaError::NoMemory)
+    }
+
+    /// Marks the given normal world VM's notification bitmap as being destroyed, before the
+    /// destruction request is forwarded to the SPMC.
+    ///
+    /// Returns `Denied` if the VM has no bitmap, or it is still being created or destroyed.
+    fn start_destroying_notification_bitmap(&self, vm_id: u16) -> Result<(), FfaError> {
+        if Self::is_secure_id(vm_id) {
+            return Err(FfaError::InvalidParameters);
+        }
+
+        let mut bitmaps = self.notification_bitmaps.lock();
+        match bitmaps.iter_mut().find(|(id, _)| *id == vm_id) {
+            Some((_, state @ NotificationBitmapState::Created)) => {
+                *state = NotificationBitmapState::Destroying;
+                Ok(())
+            }
+            Some((_, state)) => {
+                warn!("VM {vm_id:#x} notification bitmap is busy: {state:?}");
+                Err(FfaError::Denied)
+            }
+            None => {
+                warn!("VM {vm_id:#x} has no notification bitmap");
+                Err(FfaError::Denied)
+            }
+        }
+    }
+
+    /// Checks that the given normal world VM has a notification bitmap which isn't being created or
+    /// destroyed, returning `error` if it doesn't.
+    fn check_notification_bitmap(&self, vm_id: u16, error: FfaError) -> Result<(), FfaError> {
+        if Self::is_secure_id(vm_id) {
+            return Err(FfaError::InvalidParameters);
+        }
+
+        if self
+            .notification_bitmaps
+            .lock()
+            .contains(&(vm_id, NotificationBitmapState::Created))
+        {
+            Ok(())
+        } else {
+            warn!("VM {vm_id:#x} has no notification bitmap");
+            Err(error)
+        }
+    }
+
+    /// Handles a request from the SPMC to signal to the normal world that `sender_id` has set
+    /// notifications for `receiver_id`, by sending it the Schedule Receiver Interrupt.
+    ///
+    /// If `delay` is set the SRI is sent before the normal world is next entered on the current
+    /// core, otherwise it is sent immediately.
+    fn schedule_receiver(
+        &self,
+        sender_id: u16,
+        receiver_id: u16,
+        delay: bool,
+    ) -> Result<(), FfaError> {
+        if !Self::is_secure_id(sender_id) {
+            return Err(FfaError::InvalidParameters);
+        }
+        self.check_notification_bitmap(receiver_id, FfaError::InvalidParameters)?;
+        let sri = self
+            .schedule_receiver_interrupt
+            .ok_or(FfaError::NotSupported)?;
+
+        if delay {
+            let work = DeferredWork {
+                world: World::NonSecure,
+                function: send_sri,
+                arg: sri.sgi_index().unwrap().into(),
+            };
+            if self.deferred_work.push(work).is_err() {
+                // The normal world only needs to see the SRI once, so sending it early is better
+                // than losing it.
+                warn!("Deferred work queue full, sending SRI immediately");
+                send_non_secure_sgi_to_self(sri);
+            }
+        } else {
+            send_non_secure_sgi_to_self(sri);
+        }
+        Ok(())
+    }
+
+    /// Records a notification bitmap operation forwarded to the SPMC on the current core.
+    fn start_notification_bitmap_op(&self, op: NotificationBitmapOp) {
+        exception_free(|token| {
+            self.core_local.get().borrow_mut(token).pending_bitmap_op = Some(op);
+        });
+    }
+
+    /// Updates the notification bitmap bookkeeping once the SPMC has responded to the pending
+    /// operation on the current core, if any.
+    fn complete_notification_bitmap_op(&self, success: bool) {
+        let Some(op) = exception_free(|token| {
+            self.core_local
+                .get()
+                .borrow_mut(token)
+                .pending_bitmap_op
+                .take()
+        }) else {
+            return;
+        };
+
+        let (NotificationBitmapOp::Create(vm_id) | NotificationBitmapOp::Destroy(vm_id)) = op;
+        let mut bitmaps = self.notification_bitmaps.lock();
+        let Some(index) = bitmaps.iter().position(|(id, _)| *id == vm_id) else {
+            return;
+        };
+        match (op, success) {
+            (NotificationBitmapOp::Create(_), true) | (NotificationBitmapOp::Destroy(_), false) => {
+                bitmaps[index].1 = NotificationBitmapState::Created;
+            }
+            (NotificationBitmapOp::Create(_), false) | (NotificationBitmapOp::Destroy(_), true) => {
+                bitmaps.remove(index);
+            }
+        }
+    }
+
+    /// Records an RX/TX buffer operation which is about to be forwarded to the SPMC on the current
+    /// core.
+    fn start_mailbox_op(&self, op: MailboxOp) {
+        *self.mailbox_state.pending_ops[CoresImpl::<PlatformImpl>::core_index()].lock() = Some(op);
+    }
+
+    /// Updates the RX/TX buffer bookkeeping once the SPMC has responded to the pending operation on
+    /// the current core, if any, rewriting the response if it was to a call which the SPMD sent in
+    /// place of the normal world's. Returns the world to forward the response to, which is the
+    /// secure world if the SPMD has another call to make to the SPMC first.
+    fn complete_mailbox_op(&self, msg: &mut Interface) -> World {
+        let Some(op) = self.mailbox_state.pending_ops[CoresImpl::<PlatformImpl>::core_index()]
+            .lock()
+            .take()
+        else {
+            return World::NonSecure;
+        };
+
+        match (op, &*msg) {
+            (MailboxOp::Map { rx, tx, page_count }, Interface::Success { .. }) => {
+                if let Err(error) = self.mailbox.map(rx, tx, page_count) {
+                    debug!("Normal world RX/TX buffers not accessible to the SPMD: {error}");
+                }
+            }
+            (MailboxOp::Unmap, Interface::Success { .. }) => {
+                // The buffers may not have been within the region mapped into EL3.
+                let _ = self.mailbox.unmap(NS_EP_ID);
+            }
+            (MailboxOp::Release, Interface::Success { .. }) => {
+                // The buffer may have been filled by the SPMC rather than the SPMD.
+                let _ = self.mailbox.release(NS_EP_ID);
+            }
+            (MailboxOp::FetchPartitionInfo { uuid, flags }, Interface::Success { args, .. }) => {
+                return match self.stage_partition_info(*args) {
+                    PartitionInfoFetch::More {
+                        start_index,
+                        info_tag,
+                    } => {
+                        self.start_mailbox_op(op);
+                        *msg = Interface::PartitionInfoGetRegs {
+                            uuid: Uuid::nil(),
+                            start_index,
+                            info_tag,
+                        };
+                        World::Secure
+                    }
+                    PartitionInfoFetch::Done => {
+                        *msg = Interface::PartitionInfoGet { uuid, flags };
+                        self.partition_info_get(msg, uuid, flags)
+                    }
+                    PartitionInfoFetch::Failed => {
+                        *msg = Interface::PartitionInfoGet { uuid, flags };
+                        World::Secure
+                    }
+                };
+            }
+            (
+                MailboxOp::FetchPartitionInfo { uuid, flags },
+                Interface::Error { error_code, .. },
+            ) if *error_code != FfaError::Aborted => {
+                // The SPMC may not implement `FFA_PARTITION_INFO_GET_REGS`, or the partitions may
+                // have changed while they were being fetched, so let it answer the original call.
+                debug!("SPMC refused FFA_PARTITION_INFO_GET_REGS: {error_code}");
+                self.mailbox_state
+                    .partition_info
+                    .abandon_fetch(CoresImpl::<PlatformImpl>::core_index());
+                *msg = Interface::PartitionInfoGet { uuid, flags };
+                return World::Secure;
+            }
+            (MailboxOp::FetchPartitionInfo { .. }, _) => {
+                self.mailbox_state
+                    .partition_info
+                    .abandon_fetch(CoresImpl::<PlatformImpl>::core_index());
+            }
+            (MailboxOp::WritePartitionInfo(uuid), Interface::Success { .. }) => {
+                *msg = match self.write_partition_info(uuid) {
+                    Ok(count) => Interface::Success {
+                        target_info: TargetInfo::default(),
+                        args: SuccessArgsPartitionInfoGet {
+                            count,
+                            size: Some(PartitionInfo::DESC_SIZE as u32),
+                        }
+                        .into(),
+                    },
+                    Err(error) => Interface::error(error, true),
+                };
+            }
+            (MailboxOp::WritePartitionInfo(_), Interface::Error { error_code, .. }) => {
+                // The SPMC wouldn't give up the RX buffer, so the normal world must still own it.
+                debug!("SPMC refused FFA_RX_ACQUIRE: {error_code}");
+                *msg = Interface::error(FfaError::Busy, true);
+            }
+            _ => {}
+        }
+        World::NonSecure
+    }
+
+    /// Handles `FFA_PARTITION_INFO_GET` from the normal world, and returns the world to run next.
+    ///
+    /// If the partition information isn't cached yet, it is first fetched from the SPMC with
+    /// `FFA_PARTITION_INFO_GET_REGS`. Once it is cached, count only requests are answered directly.
+    /// Other requests are answered by the SPMD writing the descriptors to the RX buffer, if it is
+    /// mapped into EL3, after acquiring the buffer from the SPMC. Anything else is forwarded to the
+    /// SPMC, as are all requests from a normal world which hasn't negotiated FF-A v1.1 or later, as
+    /// the descriptors it expects have a different layout.
+    fn partition_info_get(
+        &self,
+        msg: &mut Interface,
+        uuid: Uuid,
+        flags: PartitionInfoGetFlags,
+    ) -> World {
+        let ns_version = Version::try_from(self.ns_version.load(Relaxed));
+        if self.spmc_version < Version(1, 2) || !ns_version.is_ok_and(|v| v >= Version(1, 1)) {
+            return World::Secure;
+        }
+
+        let count = self
+            .mailbox_state
+            .partition_info
+            .state
+            .lock()
+            .cached()
+            .map(|info| {
+                info.iter()
+                    .filter(|descriptor| partition_info_matches(descriptor, uuid))
+                    .count() as u32
+            });
+
+        match count {
+            None => {
+                if self
+                    .mailbox_state
+                    .partition_info
+                    .start_fetch(CoresImpl::<PlatformImpl>::core_index())
+                {
+                    self.start_mailbox_op(MailboxOp::FetchPartitionInfo { uuid, flags });
+                    *msg = Interface::PartitionInfoGetRegs {
+                        uuid: Uuid::nil(),
+                        start_index: 0,
+                        info_tag: 0,
+                    };
+                }
+                World::Secure
+            }
+            Some(0) => {
+                *msg = Interface::error(FfaError::InvalidParameters, true);
+                World::NonSecure
+            }
+            Some(count) if flags.count_only => {
+                *msg = Interface::Success {
+                    target_info: TargetInfo::default(),
+                    args: SuccessArgsPartitionInfoGet { count, size: None }.into(),
+                };
+                World::NonSecure
+            }
+            Some(_) if self.mailbox.is_mapped() => {
+                self.start_mailbox_op(MailboxOp::WritePartitionInfo(uuid));
+                *msg = Interface::RxAcquire { vm_id: NS_EP_ID };
+                World::Secure
+            }
+            Some(_) => World::Secure,
+        }
+    }
+
+    /// Adds the descriptors which the SPMC returned in response to `FFA_PARTITION_INFO_GET_REGS` to
+    /// those fetched so far on the current core, and caches them all once the last has been
+    /// returned.
+    fn stage_partition_info(&self, args: SuccessArgs) -> PartitionInfoFetch {
+        let core_index = CoresImpl::<PlatformImpl>::core_index();
+        let mut state = self.mailbox_state.partition_info.state.lock();
+        if state.fetching_core != Some(core_index) {
+            // The cache was invalidated while the descriptors were being fetched.
+            return PartitionInfoFetch::Failed;
+        }
+
+        // The SPMC returns as many descriptors as fit from the requested start index onwards.
+        let fetched = state.descriptors.len();
+        let response = SuccessArgsPartitionInfoGetRegs::try_from(args)
+            .ok()
+            .filter(|response| {
+                let returned = response.descriptor_data.len() / PartitionInfo::DESC_SIZE;
+                (fetched..fetched + returned).contains(&usize::from(response.current_index))
+            });
+        let Some(response) = response else {
+            warn!("SPMC returned invalid partition information descriptors");
+            state.clear();
+            return PartitionInfoFetch::Failed;
+        };
+        if usize::from(response.last_index) >= MAX_CACHED_PARTITIONS {
+            state.clear();
+            return PartitionInfoFetch::Failed;
+        }
+        let (received, _) = response.descriptor_data.as_chunks();
+        let received = &received[..=usize::from(response.current_index) - fetched];
+        state.descriptors.extend(received.iter().copied());
+
+        if response.current_index < response.last_index {
+            return PartitionInfoFetch::More {
+                start_index: response.current_index + 1,
+                info_tag: response.info_tag,
+            };
+        }
+        state.fetching_core = None;
+        state.complete = true;
+        PartitionInfoFetch::Done
+    }
+
+    /// Writes the cached descriptors of the partitions matching the given UUID, or all partitions
+    /// if it is nil, to the RX buffer which the SPMD has acquired. Returns the number written.
+    fn write_partition_info(&self, uuid: Uuid) -> Result<u32, FfaError> {
+        let mut buf = [0; MAX_CACHED_PARTITIONS * PartitionInfo::DESC_SIZE];
+        let mut count = 0;
+        let state = self.mailbox_state.partition_info.state.lock();
+        for descriptor in state.cached().into_iter().flatten() {
+            if partition_info_matches(descriptor, uuid) {
+                let written =
+                    &mut buf[count * PartitionInfo::DESC_SIZE..][..PartitionInfo::DESC_SIZE];
+                written.copy_from_slice(descriptor);
+                // The UUID is only returned when the information of all partitions is requested.
+                if !uuid.is_nil() {
+                    written[PARTITION_INFO_UUID_OFFSET..].fill(0);
+                }
+                count += 1;
+            }
+        }
+
+        self.mailbox
+            .write_rx(&buf[..count * PartitionInfo::DESC_SIZE])?;
+        Ok(count as u32)
+    }
+
+    /// Logs a fatal error reported by the SPMC on the current core, along with the FF-A calls
+    /// which were most recently forwarded to it on the core.
+    fn report_spmc_fatal_error(&self, error_code: FfaError) {
+        let core_index = CoresImpl::<PlatformImpl>::core_index();
+        error!("SPMC reported fatal error {error_code} on core {core_index}");
+        let recent = self.smc_audit.recent(core_index);
+        if recent.is_empty() {
+            error!("No FF-A calls were forwarded to the SPMC on this core");
+        }
+        for call in recent {
+            error!("  Forwarded FF-A call {call}");
+        }
+    }
+
+    /// Returns the world switch counters recorded by the SPMD.
+    pub fn world_switch_stats(&self) -> &WorldSwitchStats<CORE_COUNT> {
+        &self.world_switch_stats
+    }
+
+    /// Returns the interrupt latency statistics collected on all cores.
+    pub fn interrupt_latency_stats(&self) -> &InterruptLatencyStats<CORE_COUNT> {
+        &self.interrupt_latency_stats
+    }
+
+    /// Records the reason for the world switch which is about to be performed on the current core.
+    fn start_world_switch(&self, reason: WorldSwitchReason, start: u64) {
+        exception_free(|token| {
+            self.core_local.get().borrow_mut(token).pending_world_switch = Some((reason, start));
+        });
+    }
+
+    /// Records the completion of the world switch started on the current core, if any.
+    ///
+    /// This should be called once the contexts have been switched, right before entering the new
+    /// world.
+    pub fn finish_world_switch(&self) {
+        if let Some((reason, start)) = exception_free(|token| {
+            self.core_local
+                .get()
+                .borrow_mut(token)
+                .pending_world_switch
+                .take()
+        }) {
+            self.world_switch_stats
+                .record(CoresImpl::<PlatformImpl>::core_index(), reason, start);
+        }
+    }
+
     /// Forwards a secure interrupt to secure world.
     pub fn forward_secure_interrupt(&self, regs: &mut SmcReturn) -> World {
+        self.start_world_switch(
+            WorldSwitchReason::SecureInterrupt,
+            WorldSwitchStats::<CORE_COUNT>::timestamp(),
+        );
+        let interrupt_start = InterruptLatencyStats::<CORE_COUNT>::start();
+        exception_free(|token| {
+            self.core_local
+                .get()
+                .borrow_mut(token)
+                .secure_interrupt_start = interrupt_start;
+        });
+
         let msg = Interface::Interrupt {
             // The endpoint and vCPU ID fields MBZ in this case
             target_info: TargetInfo {
@@ -538,21 +1512,70 @@ impl<const CORE_COUNT: usize, PlatformImpl: Platform> Spmd<CORE_COUNT, PlatformI
         World::Secure
     }
 
+    /// Handles a direct message request sent by the SPMC to the SPMD, returning the response.
+    fn handle_spmc_direct_request(&self, args: &DirectMsgArgs) -> Interface {
+        let DirectMsgArgs::Args32([INTERRUPT_CONFIG_REQ, endpoint_id, interrupt_id, ..]) = *args
+        else {
+            return Interface::error(FfaError::InvalidParameters, true);
+        };
+        let status = match self.check_interrupt_config(endpoint_id, interrupt_id) {
+            Ok(()) => 0,
+            Err(error) => i32::from(error) as u32,
+        };
+        Interface::MsgSendDirectResp {
+            src_id: Self::OWN_ID,
+            dst_id: self.spmc_id,
+            args: DirectMsgArgs::Args32([status, 0, 0, 0, 0]),
+        }
+    }
+
+    /// Checks whether the secure partition with the given endpoint ID may configure the given
+    /// interrupt, i.e. whether its manifest assigns the interrupt to it.
+    fn check_interrupt_config(&self, endpoint_id: u32, interrupt_id: u32) -> Result<(), FfaError> {
+        let endpoint_id = u16::try_from(endpoint_id).map_err(|_| FfaError::InvalidParameters)?;
+        let interrupt_id =
+            IntId::try_from(interrupt_id).map_err(|_| FfaError::InvalidParameters)?;
+        self.secure_interrupts
+            .check_config_request(endpoint_id, interrupt_id)
+    }
+
     /// Notify the SPM that the current core was turned on for the first time or after CPU_OFF.
     pub fn handle_wake_from_cpu_off(&self) {
+        if !runtime_config().spmc_present {
+            return;
+        }
         self.switch_spmc_local_state(SpmcState::Off, SpmcState::Boot);
     }
 
     /// Notify the SPM that the current core woke up from suspend (CPU_SUSPEND, CPU_DEFAULT_SUSPEND
     /// or SYSTEM_SUSPEND). Only applies for power down suspend states.
+    ///
+    /// If the SPMC's state was saved before the core was powered down then it is restored, and the
+    /// SPMC is told that it is resuming from suspend to RAM. Otherwise it is told that the core is
+    /// exiting a low power state, so that it re-initialises its state for the core.
     pub fn handle_wake_from_cpu_suspend(&self) -> SmcReturn {
+        if !runtime_config().spmc_present {
+            return SmcReturn::EMPTY;
+        }
+
+        let context_saved = exception_free(|token| {
+            let mut local = self.core_local.get().borrow_mut(token);
+            #[cfg(feature = "sel2")]
+            if local.suspend_context_saved {
+                local.suspended_el2_sysregs.restore();
+            }
+            core::mem::take(&mut local.suspend_context_saved)
+        });
+
         let msg = Interface::MsgSendDirectReq {
             src_id: Self::OWN_ID,
             dst_id: self.spmc_id,
             args: DirectMsgArgs::PowerWarmBootReq {
-                // TODO: Add handling for WarmBootType::ExitFromSuspend (which really should be
-                // ExitFromSuspendToRam). Note: C TF-A does not have any handling for this.
-                boot_type: WarmBootType::ExitFromLowPower,
+                boot_type: if context_saved {
+                    WarmBootType::ExitFromSuspendToRam
+                } else {
+                    WarmBootType::ExitFromLowPower
+                },
             },
         };
 
@@ -568,12 +1591,209 @@ impl<const CORE_COUNT: usize, PlatformImpl: Platform> Spmd<CORE_COUNT, PlatformI
     pub const fn is_secure_id(id: u16) -> bool {
         id & 0x8000 != 0
     }
+
+    /// Decides how to handle a message from the secure world while a direct request from EL3 to
+    /// the partition `dst_id` is outstanding.
+    fn direct_request_step(
+        &self,
+        dst_id: u16,
+        msg: Result<Interface, arm_ffa::Error>,
+    ) -> DirectRequestStep {
+        let mut msg = match msg {
+            Ok(msg) => msg,
+            Err(error) => {
+                error!("Invalid FF-A call from Secure World: {error}");
+                return DirectRequestStep::Resume(Interface::error(error.into(), true));
+            }
+        };
+
+        match msg {
+            Interface::MsgSendDirectResp {
+                src_id,
+                dst_id: Self::OWN_ID,
+                args,
+            } if src_id == dst_id => DirectRequestStep::Done(match args {
+                DirectMsgArgs::Args64(args) => Ok(args[..DIRECT_MSG_ARG_COUNT].try_into().unwrap()),
+                DirectMsgArgs::Args32(args) => Ok(args.map(u64::from)),
+                _ => Err(DirectRequestError::InvalidResponse),
+            }),
+            Interface::MsgSendDirectResp { .. } => {
+                DirectRequestStep::Done(Err(DirectRequestError::InvalidResponse))
+            }
+            Interface::Error { error_code, .. } => {
+                DirectRequestStep::Done(Err(DirectRequestError::Ffa(error_code)))
+            }
+            Interface::Interrupt { .. } => DirectRequestStep::Interrupted,
+            Interface::Yield { .. } => DirectRequestStep::Yielded,
+            Interface::Features { .. }
+            | Interface::IdGet
+            | Interface::SpmIdGet
+            | Interface::PartitionInfoGetRegs { .. } => {
+                self.handle_secure_call_common(&mut msg);
+                DirectRequestStep::Resume(msg)
+            }
+            _ => {
+                warn!("Denied FF-A call from Secure World: {msg:x?}");
+                DirectRequestStep::Resume(Interface::error(FfaError::Denied, true))
+            }
+        }
+    }
+
+    /// Returns the `FFA_RUN` message to resume the current core's vCPU of the partition `dst_id`.
+    fn run_message(dst_id: u16) -> Interface {
+        Interface::Run {
+            target_info: TargetInfo {
+                endpoint_id: dst_id,
+                vcpu_id: CoresImpl::<PlatformImpl>::core_index() as u16,
+            },
+            is_32bit: false,
+        }
+    }
 }
 
-impl<const CORE_COUNT: usize, PlatformImpl: CpuStateAccess + Platform + PlatformErrata>
-    PsciSpmInterface for Spmd<CORE_COUNT, PlatformImpl>
+impl<
+    const CORE_COUNT: usize,
+    PlatformImpl: CpuDataIndex + CpuStateAccess + Platform + PlatformErrata + 'static,
+> Spmd<CORE_COUNT, PlatformImpl>
+{
+    /// Sends a direct message request from the SPMD to the secure partition `dst_id`, and waits for
+    /// it to respond.
+    ///
+    /// This lets EL3 services signal secure partitions, e.g. to deliver RAS errors to a diagnostics
+    /// partition. It must only be called on the way into the normal world, while its context is
+    /// current, and runs the secure world until the partition responds.
+    ///
+    /// EL3 can't preempt the secure world, so `timeout` is only checked when the partition yields.
+    /// If it has expired then, or the partition is preempted by a non-secure interrupt, the request
+    /// stays outstanding and this returns [`DirectRequestError::TimedOut`] or
+    /// [`DirectRequestError::Interrupted`]. The caller must then resume it with
+    /// [`Self::resume_direct_request`] each time it is about to enter the normal world until the
+    /// partition responds, so that the partition isn't left blocked.
+    pub fn send_direct_request(
+        &self,
+        dst_id: u16,
+        args: [u64; DIRECT_MSG_ARG_COUNT],
+        timeout: Option<Timeout>,
+    ) -> Result<[u64; DIRECT_MSG_ARG_COUNT], DirectRequestError> {
+        if !runtime_config().spmc_present {
+            return Err(DirectRequestError::Ffa(FfaError::NotSupported));
+        }
+        if !Self::is_secure_id(dst_id) || dst_id == Self::OWN_ID {
+            return Err(DirectRequestError::Ffa(FfaError::InvalidParameters));
+        }
+        if exception_free(|token| {
+            self.core_local
+                .get()
+                .borrow(token)
+                .borrow()
+                .el3_direct_request
+                .is_some()
+        }) {
+            return Err(DirectRequestError::Ffa(FfaError::Busy));
+        }
+
+        let mut msg_args = [0; 15];
+        msg_args[..DIRECT_MSG_ARG_COUNT].copy_from_slice(&args);
+        let msg = Interface::MsgSendDirectReq {
+            src_id: Self::OWN_ID,
+            dst_id,
+            args: DirectMsgArgs::Args64(msg_args),
+        };
+        self.run_direct_request(dst_id, msg, timeout)
+    }
+
+    /// Resumes the direct request from EL3 which is outstanding on the current core, if any, and
+    /// waits for the partition to respond.
+    ///
+    /// Returns `None` if there is no outstanding request, otherwise the same as
+    /// [`Self::send_direct_request`].
+    pub fn resume_direct_request(
+        &self,
+        timeout: Option<Timeout>,
+    ) -> Option<Result<[u64; DIRECT_MSG_ARG_COUNT], DirectRequestError>> {
+        let dst_id = exception_free(|token| {
+            self.core_local
+                .get()
+                .borrow(token)
+                .borrow()
+                .el3_direct_request
+        })?;
+        Some(self.run_direct_request(dst_id, Self::run_message(dst_id), timeout))
+    }
+
+    /// Sends `msg` to the secure world, and handles its calls until the partition `dst_id` responds
+    /// to the direct request from EL3, is interrupted or yields after `timeout` expires.
+    fn run_direct_request(
+        &self,
+        dst_id: u16,
+        mut msg: Interface,
+        timeout: Option<Timeout>,
+    ) -> Result<[u64; DIRECT_MSG_ARG_COUNT], DirectRequestError> {
+        let version = self.spmc_version;
+
+        self.switch_spmc_local_state(SpmcState::Runtime, SpmcState::El3DirectRequest);
+        switch_world::<PlatformImpl>(World::NonSecure, World::Secure);
+
+        let mut regs = SmcReturn::EMPTY;
+        let result = loop {
+            msg.to_regs(version, regs.mark_all_used());
+            loop {
+                match enter_world::<PlatformImpl>(&mut regs, World::Secure) {
+                    RunResult::Smc => break,
+                    // Interrupts shouldn't be routed to EL3 from SWd
+                    RunResult::Interrupt => panic!(
+                        "Unexpected interrupt during direct request from EL3 - Interrupts shouldn't be routed to EL3 from SWd"
+                    ),
+                    RunResult::SysregTrap { esr } => {
+                        handle_sysreg_trap::<PlatformImpl>(esr, World::Secure);
+                        regs.mark_empty();
+                    }
+                    RunResult::Hvc => handle_unknown_hvc::<PlatformImpl>(&mut regs, World::Secure),
+                }
+            }
+
+            msg = match self
+                .direct_request_step(dst_id, Interface::from_regs(version, regs.values()))
+            {
+                DirectRequestStep::Done(result) => break result,
+                DirectRequestStep::Resume(next) => next,
+                DirectRequestStep::Yielded => {
+                    if timeout.is_some_and(|timeout| timeout.expired()) {
+                        warn!("Direct request from EL3 to partition {dst_id:#x} timed out");
+                        break Err(DirectRequestError::TimedOut);
+                    }
+                    Self::run_message(dst_id)
+                }
+                DirectRequestStep::Interrupted => break Err(DirectRequestError::Interrupted),
+            };
+        };
+
+        let outstanding = matches!(
+            result,
+            Err(DirectRequestError::TimedOut | DirectRequestError::Interrupted)
+        );
+        exception_free(|token| {
+            self.core_local.get().borrow_mut(token).el3_direct_request =
+                outstanding.then_some(dst_id);
+        });
+
+        switch_world::<PlatformImpl>(World::Secure, World::NonSecure);
+        self.switch_spmc_local_state(SpmcState::El3DirectRequest, SpmcState::Runtime);
+
+        result
+    }
+}
+
+impl<
+    const CORE_COUNT: usize,
+    PlatformImpl: CpuDataIndex + CpuStateAccess + Platform + PlatformErrata + 'static,
+> PsciSpmInterface for Spmd<CORE_COUNT, PlatformImpl>
 {
     fn forward_psci_request(&self, function: Function) -> ReturnCode {
+        if !runtime_config().spmc_present {
+            return ReturnCode::Success;
+        }
+
         let version = self.spmc_version;
         let mut regs = SmcReturn::EMPTY;
 
@@ -590,7 +1810,12 @@ impl<const CORE_COUNT: usize, PlatformImpl: CpuStateAccess + Platform + Platform
 
         msg.to_regs(version, regs.mark_all_used());
 
+        self.start_world_switch(
+            WorldSwitchReason::PsciEvent,
+            WorldSwitchStats::<CORE_COUNT>::timestamp(),
+        );
         switch_world::<PlatformImpl>(World::NonSecure, World::Secure);
+        self.finish_world_switch();
 
         let ret: i32 = loop {
             match enter_world::<PlatformImpl>(&mut regs, World::Secure) {
@@ -607,10 +1832,16 @@ impl<const CORE_COUNT: usize, PlatformImpl: CpuStateAccess + Platform + Platform
                     "Unexpected SMC return from forwarding a PSCI request - Interrupts shouldn't be routed to EL3 from SWd"
                 ),
                 RunResult::SysregTrap { .. } => todo!("Handle SysregTrap"),
+                RunResult::Hvc => handle_unknown_hvc::<PlatformImpl>(&mut regs, World::Secure),
             }
         };
 
+        self.start_world_switch(
+            WorldSwitchReason::PsciEvent,
+            WorldSwitchStats::<CORE_COUNT>::timestamp(),
+        );
         switch_world::<PlatformImpl>(World::Secure, World::NonSecure);
+        self.finish_world_switch();
 
         ReturnCode::try_from(ret).unwrap_or_else(|e| {
             error!("SPMD returned unrecognised PSCI code {ret}: {e:?}");
@@ -618,14 +1849,52 @@ impl<const CORE_COUNT: usize, PlatformImpl: CpuStateAccess + Platform + Platform
         })
     }
 
+    fn prepare_cpu_on(&self, target_cpu: Mpidr) -> Result<(), ErrorCode> {
+        if !runtime_config().spmc_present || self.spmc_booted.load(Acquire) {
+            return Ok(());
+        }
+
+        warn!("Denying CPU_ON for {target_cpu:?} as the SPMC hasn't finished booting");
+        Err(ErrorCode::Denied)
+    }
+
     fn notify_cpu_off(&self) {
+        if !runtime_config().spmc_present {
+            return;
+        }
         self.switch_spmc_local_state(SpmcState::Runtime, SpmcState::Off);
     }
 
+    fn notify_cpu_suspend_powerdown(&self) {
+        if !runtime_config().spmc_present || !self.resume_after_suspend {
+            return;
+        }
+
+        // The rest of the SPMC's context was saved when we last switched away from the secure
+        // world, and is kept in memory which is retained across the suspend.
+        exception_free(|token| {
+            let mut local = self.core_local.get().borrow_mut(token);
+            #[cfg(feature = "sel2")]
+            {
+                local.suspended_el2_sysregs = SecureEl2Sysregs::save();
+            }
+            local.suspend_context_saved = true;
+        });
+    }
+
     fn notify_cpu_suspend_powerdown_abandoned(&self) {
+        if !runtime_config().spmc_present {
+            return;
+        }
+
         let mut regs = self.handle_wake_from_cpu_suspend();
 
+        self.start_world_switch(
+            WorldSwitchReason::PsciEvent,
+            WorldSwitchStats::<CORE_COUNT>::timestamp(),
+        );
         switch_world::<PlatformImpl>(World::NonSecure, World::Secure);
+        self.finish_world_switch();
         let _ret: i32 = loop {
             match enter_world::<PlatformImpl>(&mut regs, World::Secure) {
                 RunResult::Smc => match Interface::from_regs(self.spmc_version, regs.values()) {
@@ -641,13 +1910,19 @@ impl<const CORE_COUNT: usize, PlatformImpl: CpuStateAccess + Platform + Platform
                     "Unexpected SMC return from PowerWarmBootReq- Interrupts shouldn't be routed to EL3 from SWd"
                 ),
                 RunResult::SysregTrap { .. } => todo!("Handle SysregTrap"),
+                RunResult::Hvc => handle_unknown_hvc::<PlatformImpl>(&mut regs, World::Secure),
             }
         };
 
         // The PSCI request was sent and a response was received in enter_world. As such, revert
         // the state back to Runtime.
         self.switch_spmc_local_state(SpmcState::PsciEventHandling, SpmcState::Runtime);
+        self.start_world_switch(
+            WorldSwitchReason::PsciEvent,
+            WorldSwitchStats::<CORE_COUNT>::timestamp(),
+        );
         switch_world::<PlatformImpl>(World::Secure, World::NonSecure);
+        self.finish_world_switch();
     }
 }
 
@@ -661,7 +1936,616 @@ impl PsciSpmInterface for TestSpm {
         ReturnCode::Success
     }
 
+    fn prepare_cpu_on(&self, _target_cpu: Mpidr) -> Result<(), ErrorCode> {
+        Ok(())
+    }
+
     fn notify_cpu_off(&self) {}
 
+    fn notify_cpu_suspend_powerdown(&self) {}
+
     fn notify_cpu_suspend_powerdown_abandoned(&self) {}
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::platform::test::TestPlatform;
+    use arm_ffa::{
+        Uuid,
+        interface_args::{DirectMsg2Args, VersionFlags},
+        notification::{NotificationGetFlags, NotificationSetFlags},
+        partition_info::{PartitionIdType, PartitionInfoIterator, PartitionProperties},
+    };
+
+    type TestSpmd = Spmd<{ TestPlatform::CORE_COUNT }, TestPlatform>;
+
+    const SP_ID: u16 = 0x8001;
+
+    /// A version whose partition information descriptors have the layout which the SPMD caches.
+    const PARTITION_INFO_VERSION: Version = Version(1, 2);
+
+    static SMC_AUDIT: SmcAuditBuffer<{ TestPlatform::CORE_COUNT }> = SmcAuditBuffer::new();
+    static DEFERRED_WORK: DeferredWorkQueue<{ TestPlatform::CORE_COUNT }, TestPlatform> =
+        DeferredWorkQueue::new();
+    static MAILBOX_STATE: MailboxState<{ TestPlatform::CORE_COUNT }> = MailboxState::new();
+
+    fn response(
+        src_id: u16,
+        dst_id: u16,
+        args: DirectMsgArgs,
+    ) -> Result<Interface, arm_ffa::Error> {
+        Ok(Interface::MsgSendDirectResp {
+            src_id,
+            dst_id,
+            args,
+        })
+    }
+
+    #[test]
+    fn direct_request_response() {
+        let spmd = TestSpmd::new(&SMC_AUDIT, &DEFERRED_WORK, &MAILBOX_STATE);
+
+        let mut args = [0; 15];
+        args[..6].copy_from_slice(&[1, 2, 3, 4, 5, 6]);
+        assert_eq!(
+            spmd.direct_request_step(
+                SP_ID,
+                response(SP_ID, TestSpmd::OWN_ID, DirectMsgArgs::Args64(args))
+            ),
+            DirectRequestStep::Done(Ok([1, 2, 3, 4, 5]))
+        );
+        assert_eq!(
+            spmd.direct_request_step(
+                SP_ID,
+                response(
+                    SP_ID,
+                    TestSpmd::OWN_ID,
+                    DirectMsgArgs::Args32([6, 7, 8, 9, 10])
+                )
+            ),
+            DirectRequestStep::Done(Ok([6, 7, 8, 9, 10]))
+        );
+
+        // A response from a different partition isn't the one we are waiting for.
+        assert_eq!(
+            spmd.direct_request_step(
+                SP_ID,
+                response(0x8002, TestSpmd::OWN_ID, DirectMsgArgs::Args64(args))
+            ),
+            DirectRequestStep::Done(Err(DirectRequestError::InvalidResponse))
+        );
+        assert_eq!(
+            spmd.direct_request_step(SP_ID, Ok(Interface::error(FfaError::Busy, true))),
+            DirectRequestStep::Done(Err(DirectRequestError::Ffa(FfaError::Busy)))
+        );
+    }
+
+    #[test]
+    fn direct_request_resume() {
+        let spmd = TestSpmd::new(&SMC_AUDIT, &DEFERRED_WORK, &MAILBOX_STATE);
+
+        assert_eq!(
+            spmd.direct_request_step(SP_ID, Ok(Interface::Yield { is_32bit: false })),
+            DirectRequestStep::Yielded
+        );
+        assert_eq!(
+            TestSpmd::run_message(SP_ID),
+            Interface::Run {
+                target_info: TargetInfo {
+                    endpoint_id: SP_ID,
+                    vcpu_id: 0,
+                },
+                is_32bit: false,
+            }
+        );
+        assert_eq!(
+            spmd.direct_request_step(
+                SP_ID,
+                Ok(Interface::Interrupt {
+                    target_info: TargetInfo::default(),
+                    interrupt_id: 0,
+                    is_32bit: false,
+                })
+            ),
+            DirectRequestStep::Interrupted
+        );
+        assert_eq!(
+            spmd.direct_request_step(SP_ID, Ok(Interface::SpmIdGet)),
+            DirectRequestStep::Resume(Interface::Success {
+                target_info: TargetInfo::default(),
+                args: SuccessArgsSpmIdGet {
+                    id: TestSpmd::OWN_ID
+                }
+                .into(),
+            })
+        );
+        assert_eq!(
+            spmd.direct_request_step(SP_ID, Ok(Interface::NormalWorldResume { is_32bit: false })),
+            DirectRequestStep::Resume(Interface::error(FfaError::Denied, true))
+        );
+    }
+
+    #[test]
+    fn direct_request2_forwarding() {
+        let spmd = TestSpmd::new(&SMC_AUDIT, &DEFERRED_WORK, &MAILBOX_STATE);
+        let args = DirectMsg2Args(core::array::from_fn(|i| i as u64 + 4));
+        let uuid = Uuid::from_u128(0x1234_5678_9abc_def0_0fed_cba9_8765_4321);
+
+        let mut msg = Interface::MsgSendDirectReq2 {
+            src_id: TestSpmd::NS_EP_ID,
+            dst_id: SP_ID,
+            uuid,
+            args,
+        };
+        let expected = msg;
+        assert_eq!(spmd.handle_non_secure_call(&mut msg), World::Secure);
+        assert_eq!(msg, expected);
+
+        let mut msg = Interface::MsgSendDirectResp2 {
+            src_id: SP_ID,
+            dst_id: TestSpmd::NS_EP_ID,
+            args,
+        };
+        let expected = msg;
+        assert_eq!(
+            spmd.handle_secure_call_runtime(&mut msg),
+            (true, World::NonSecure)
+        );
+        assert_eq!(msg, expected);
+    }
+
+    #[test]
+    fn direct_request2_invalid_ids() {
+        let spmd = TestSpmd::new(&SMC_AUDIT, &DEFERRED_WORK, &MAILBOX_STATE);
+        let args = DirectMsg2Args([0; 14]);
+
+        for (src_id, dst_id) in [
+            (SP_ID, SP_ID),
+            (TestSpmd::NS_EP_ID, 0x0001),
+            (TestSpmd::NS_EP_ID, TestSpmd::OWN_ID),
+        ] {
+            let mut msg = Interface::MsgSendDirectReq2 {
+                src_id,
+                dst_id,
+                uuid: Uuid::nil(),
+                args,
+            };
+            assert_eq!(spmd.handle_non_secure_call(&mut msg), World::NonSecure);
+            assert_eq!(msg, Interface::error(FfaError::InvalidParameters, true));
+        }
+
+        for (src_id, dst_id) in [
+            (TestSpmd::NS_EP_ID, TestSpmd::NS_EP_ID),
+            (SP_ID, 0x8002),
+            (SP_ID, TestSpmd::OWN_ID),
+        ] {
+            let mut msg = Interface::MsgSendDirectResp2 {
+                src_id,
+                dst_id,
+                args,
+            };
+            assert_eq!(
+                spmd.handle_secure_call_runtime(&mut msg),
+                (true, World::Secure)
+            );
+            assert_eq!(msg, Interface::error(FfaError::InvalidParameters, true));
+        }
+    }
+
+    #[test]
+    fn interrupt_config_request() {
+        static ASSIGNMENTS: [SecureInterruptAssignment; 1] = [SecureInterruptAssignment {
+            interrupt_id: IntId::spi(42),
+            endpoint_id: SP_ID,
+        }];
+
+        let mut spmd = TestSpmd::new(&SMC_AUDIT, &DEFERRED_WORK, &MAILBOX_STATE);
+        spmd.secure_interrupts = SecureInterruptOwnership::new(&ASSIGNMENTS);
+
+        for (endpoint_id, interrupt_id, status) in [
+            (u32::from(SP_ID), IntId::spi(42).raw_value(), 0),
+            (
+                0x8002,
+                IntId::spi(42).raw_value(),
+                FfaError::Denied as i32 as u32,
+            ),
+            (
+                u32::from(SP_ID),
+                IntId::spi(43).raw_value(),
+                FfaError::InvalidParameters as i32 as u32,
+            ),
+            (
+                0x10000 | u32::from(SP_ID),
+                IntId::spi(42).raw_value(),
+                FfaError::InvalidParameters as i32 as u32,
+            ),
+        ] {
+            let mut msg = Interface::MsgSendDirectReq {
+                src_id: spmd.spmc_id,
+                dst_id: TestSpmd::OWN_ID,
+                args: DirectMsgArgs::Args32([
+                    INTERRUPT_CONFIG_REQ,
+                    endpoint_id,
+                    interrupt_id,
+                    0,
+                    0,
+                ]),
+            };
+            assert_eq!(
+                spmd.handle_secure_call_boot(&mut msg),
+                (true, World::Secure)
+            );
+            assert_eq!(
+                msg,
+                Interface::MsgSendDirectResp {
+                    src_id: TestSpmd::OWN_ID,
+                    dst_id: spmd.spmc_id,
+                    args: DirectMsgArgs::Args32([status, 0, 0, 0, 0]),
+                }
+            );
+        }
+    }
+
+    #[test]
+    fn schedule_receiver_interrupt_id() {
+        let spmd = TestSpmd::new(&SMC_AUDIT, &DEFERRED_WORK, &MAILBOX_STATE);
+        let mut msg = Interface::Features {
+            feat_id: Feature::FeatureId(FeatureId::ScheduleReceiverInterrupt),
+            input_properties: 0,
+        };
+
+        assert_eq!(spmd.handle_non_secure_call(&mut msg), World::NonSecure);
+        assert_eq!(
+            msg,
+            Interface::Success {
+                target_info: TargetInfo::default(),
+                args: SuccessArgsFeatures { properties: [9, 0] }.into(),
+            }
+        );
+    }
+
+    #[test]
+    fn schedule_receiver() {
+        const VM_ID: u16 = 1;
+        let spmd = TestSpmd::new(&SMC_AUDIT, &DEFERRED_WORK, &MAILBOX_STATE);
+        let notification_set = |sender_id, delay_schedule_receiver| {
+            let mut msg = Interface::NotificationSet {
+                sender_id,
+                receiver_id: VM_ID,
+                flags: NotificationSetFlags {
+                    delay_schedule_receiver,
+                    vcpu_id: None,
+                },
+                bitmap: 1,
+            };
+            assert_eq!(
+                spmd.handle_secure_call_runtime(&mut msg),
+                (true, World::Secure)
+            );
+            msg
+        };
+
+        // The receiver must have a notification bitmap.
+        assert_eq!(
+            notification_set(SP_ID, false),
+            Interface::error(FfaError::InvalidParameters, true)
+        );
+        let mut msg = Interface::NotificationBitmapCreate {
+            vm_id: VM_ID,
+            vcpu_cnt: 1,
+        };
+        assert_eq!(spmd.handle_non_secure_call(&mut msg), World::Secure);
+        spmd.complete_notification_bitmap_op(true);
+
+        // Only the SPMC may ask for the SRI to be sent.
+        assert_eq!(
+            notification_set(2, false),
+            Interface::error(FfaError::InvalidParameters, true)
+        );
+
+        assert_eq!(
+            notification_set(SP_ID, false),
+            Interface::success32_noargs()
+        );
+        assert!(!DEFERRED_WORK.is_pending(World::NonSecure));
+
+        // A delayed SRI waits until the normal world is next entered.
+        assert_eq!(notification_set(SP_ID, true), Interface::success32_noargs());
+        assert!(DEFERRED_WORK.is_pending(World::NonSecure));
+        DEFERRED_WORK.run_pending(World::NonSecure);
+        assert!(!DEFERRED_WORK.is_pending(World::NonSecure));
+    }
+
+    #[test]
+    fn notification_bitmap_in_flight() {
+        const VM_ID: u16 = 1;
+        let spmd = TestSpmd::new(&SMC_AUDIT, &DEFERRED_WORK, &MAILBOX_STATE);
+        let call = |mut msg: Interface| {
+            let world = spmd.handle_non_secure_call(&mut msg);
+            (world, msg)
+        };
+        let create = || {
+            call(Interface::NotificationBitmapCreate {
+                vm_id: VM_ID,
+                vcpu_cnt: 1,
+            })
+        };
+        let destroy = || call(Interface::NotificationBitmapDestroy { vm_id: VM_ID });
+        let get = || {
+            call(Interface::NotificationGet {
+                vcpu_id: 0,
+                endpoint_id: VM_ID,
+                flags: NotificationGetFlags {
+                    sp_bitmap_id: true,
+                    vm_bitmap_id: false,
+                    spm_bitmap_id: false,
+                    hyp_bitmap_id: false,
+                },
+            })
+        };
+        let denied = (World::NonSecure, Interface::error(FfaError::Denied, true));
+        let no_bitmap = (
+            World::NonSecure,
+            Interface::error(FfaError::InvalidParameters, true),
+        );
+
+        // While the creation request is in flight the bitmap can't be used or destroyed.
+        assert_eq!(create().0, World::Secure);
+        assert_eq!(get(), no_bitmap);
+        assert_eq!(create(), denied);
+        assert_eq!(destroy(), denied);
+        spmd.complete_notification_bitmap_op(true);
+        assert_eq!(get().0, World::Secure);
+
+        // Nor while the destruction request is in flight, and it is restored if that fails.
+        assert_eq!(destroy().0, World::Secure);
+        assert_eq!(get(), no_bitmap);
+        assert_eq!(destroy(), denied);
+        assert_eq!(create(), denied);
+        spmd.complete_notification_bitmap_op(false);
+        assert_eq!(get().0, World::Secure);
+
+        assert_eq!(destroy().0, World::Secure);
+        spmd.complete_notification_bitmap_op(true);
+        assert_eq!(get(), no_bitmap);
+        assert_eq!(destroy(), denied);
+
+        // A failed creation releases the VM ID.
+        assert_eq!(create().0, World::Secure);
+        spmd.complete_notification_bitmap_op(false);
+        assert_eq!(get(), no_bitmap);
+        assert_eq!(create().0, World::Secure);
+    }
+
+    // With the EL3 SPMC there is no SPMC in the secure world to send warm boot messages to.
+    #[cfg(not(feature = "spmc_el3"))]
+    #[test]
+    fn wake_from_suspend_boot_type() {
+        let mut spmd = TestSpmd::new(&SMC_AUDIT, &DEFERRED_WORK, &MAILBOX_STATE);
+        spmd.switch_spmc_local_state(SpmcState::Boot, SpmcState::Runtime);
+        let boot_type =
+            |regs: SmcReturn| match Interface::from_regs(spmd.spmc_version, regs.values()) {
+                Ok(Interface::MsgSendDirectReq {
+                    args: DirectMsgArgs::PowerWarmBootReq { boot_type },
+                    ..
+                }) => boot_type,
+                msg => panic!("Unexpected warm boot message {msg:?}"),
+            };
+
+        // An SPMC which can't resume must re-initialise its state after the core lost it.
+        spmd.notify_cpu_suspend_powerdown();
+        assert_eq!(
+            boot_type(spmd.handle_wake_from_cpu_suspend()),
+            WarmBootType::ExitFromLowPower
+        );
+        spmd.switch_spmc_local_state(SpmcState::PsciEventHandling, SpmcState::Runtime);
+
+        spmd.resume_after_suspend = true;
+        spmd.notify_cpu_suspend_powerdown();
+        assert_eq!(
+            boot_type(spmd.handle_wake_from_cpu_suspend()),
+            WarmBootType::ExitFromSuspendToRam
+        );
+        spmd.switch_spmc_local_state(SpmcState::PsciEventHandling, SpmcState::Runtime);
+
+        // The saved state is only used once.
+        assert_eq!(
+            boot_type(spmd.handle_wake_from_cpu_suspend()),
+            WarmBootType::ExitFromLowPower
+        );
+    }
+
+    #[test]
+    fn partition_info_cache() {
+        const FFA_PAGE_SIZE: usize = 4096;
+        const PARTITION_COUNT: usize = 6;
+        const SP_UUID: Uuid = Uuid::from_u128(0x1111_2222_3333_4444_5555_6666_7777_8888);
+
+        #[repr(C, align(4096))]
+        struct Page([u8; FFA_PAGE_SIZE]);
+
+        let pages = Box::leak(Box::new([const { Page([0; FFA_PAGE_SIZE]) }; 2]));
+        let rx = pages.as_mut_ptr() as usize;
+        let mut spmd = TestSpmd::new(
+            &SMC_AUDIT,
+            &DEFERRED_WORK,
+            Box::leak(Box::new(MailboxState::new())),
+        );
+        spmd.mailbox = Mailbox::new(rx..rx + 2 * FFA_PAGE_SIZE);
+        spmd.switch_spmc_local_state(SpmcState::Boot, SpmcState::Runtime);
+
+        let info: [_; PARTITION_COUNT] = core::array::from_fn(|i| PartitionInfo {
+            uuid: if i == 0 {
+                SP_UUID
+            } else {
+                Uuid::from_u128(i as u128)
+            },
+            partition_id: SP_ID + i as u16,
+            partition_id_type: PartitionIdType::PeEndpoint {
+                execution_ctx_count: TestPlatform::CORE_COUNT as u16,
+            },
+            props: PartitionProperties {
+                support_direct_req_rec: true,
+                support_direct_req2_rec: Some(false),
+                support_direct_req2_send: Some(false),
+                is_aarch64: true,
+                ..Default::default()
+            },
+        });
+        let version = |input_version| Interface::Version {
+            input_version,
+            flags: VersionFlags {
+                query_type: VersionQueryType::Negotiate,
+            },
+        };
+        let partition_info_get = |uuid, count_only| Interface::PartitionInfoGet {
+            uuid,
+            flags: PartitionInfoGetFlags { count_only },
+        };
+        let partition_info_get_regs = |start_index, info_tag| Interface::PartitionInfoGetRegs {
+            uuid: Uuid::nil(),
+            start_index,
+            info_tag,
+        };
+        // Returns the SPMC's response to `FFA_PARTITION_INFO_GET_REGS` with the given descriptors.
+        let regs_success = |range: Range<usize>| {
+            let mut descriptor_data = [0; 120];
+            let len = range.len() * PartitionInfo::DESC_SIZE;
+            PartitionInfo::pack(
+                PARTITION_INFO_VERSION,
+                &info[range.clone()],
+                &mut descriptor_data[..len],
+                true,
+            );
+            Interface::Success {
+                target_info: TargetInfo::default(),
+                args: SuccessArgsPartitionInfoGetRegs {
+                    last_index: PARTITION_COUNT as u16 - 1,
+                    current_index: range.end as u16 - 1,
+                    info_tag: 42,
+                    descriptor_data,
+                }
+                .into(),
+            }
+        };
+        let success = |count, size| Interface::Success {
+            target_info: TargetInfo::default(),
+            args: SuccessArgsPartitionInfoGet { count, size }.into(),
+        };
+        // Returns the world which the SPMD runs next in response to the given message from the
+        // SPMC, and the message which it sends there.
+        let respond = |mut msg| {
+            let (valid, world) = spmd.handle_secure_call_runtime(&mut msg);
+            assert!(valid);
+            (world, msg)
+        };
+
+        // The descriptors have a different layout in FF-A v1.0, so such a normal world's requests
+        // are left to the SPMC.
+        spmd.handle_non_secure_call(&mut version(Version(1, 0)));
+        let mut msg = partition_info_get(Uuid::nil(), false);
+        assert_eq!(spmd.handle_non_secure_call(&mut msg), World::Secure);
+        assert_eq!(msg, partition_info_get(Uuid::nil(), false));
+        respond(success(PARTITION_COUNT as u32, Some(8)));
+
+        // If the SPMC doesn't implement `FFA_PARTITION_INFO_GET_REGS`, it gets the original call.
+        spmd.handle_non_secure_call(&mut version(Version(1, 2)));
+        let mut msg = partition_info_get(Uuid::nil(), true);
+        assert_eq!(spmd.handle_non_secure_call(&mut msg), World::Secure);
+        assert_eq!(msg, partition_info_get_regs(0, 0));
+        assert_eq!(
+            respond(Interface::error(FfaError::NotSupported, true)),
+            (World::Secure, partition_info_get(Uuid::nil(), true))
+        );
+        respond(success(PARTITION_COUNT as u32, None));
+
+        // Otherwise the SPMD fetches the descriptors from the SPMC's registers until it has them
+        // all, and then answers the original call itself.
+        let mut msg = partition_info_get(Uuid::nil(), true);
+        assert_eq!(spmd.handle_non_secure_call(&mut msg), World::Secure);
+        assert_eq!(msg, partition_info_get_regs(0, 0));
+        assert_eq!(
+            respond(regs_success(0..5)),
+            (World::Secure, partition_info_get_regs(5, 42))
+        );
+        assert_eq!(
+            respond(regs_success(5..6)),
+            (World::NonSecure, success(PARTITION_COUNT as u32, None))
+        );
+
+        // Count only requests are answered from the cache.
+        let mut msg = partition_info_get(SP_UUID, true);
+        assert_eq!(spmd.handle_non_secure_call(&mut msg), World::NonSecure);
+        assert_eq!(msg, success(1, None));
+        let mut msg = partition_info_get(Uuid::from_u128(0xff), true);
+        assert_eq!(spmd.handle_non_secure_call(&mut msg), World::NonSecure);
+        assert_eq!(msg, Interface::error(FfaError::InvalidParameters, true));
+
+        // The SPMD can't write to RX/TX buffers which aren't mapped into EL3.
+        let mut msg = partition_info_get(Uuid::nil(), false);
+        assert_eq!(spmd.handle_non_secure_call(&mut msg), World::Secure);
+        assert_eq!(msg, partition_info_get(Uuid::nil(), false));
+        respond(success(PARTITION_COUNT as u32, Some(24)));
+
+        let mut msg = Interface::RxTxMap {
+            addr: RxTxAddr::Addr64 {
+                rx: rx as u64,
+                tx: (rx + FFA_PAGE_SIZE) as u64,
+            },
+            page_cnt: 1,
+        };
+        assert_eq!(spmd.handle_non_secure_call(&mut msg), World::Secure);
+        respond(Interface::success32_noargs());
+        assert!(spmd.mailbox.is_mapped());
+
+        // The SPMD acquires the RX buffer from the SPMC before writing the descriptors to it.
+        let mut msg = partition_info_get(Uuid::nil(), false);
+        assert_eq!(spmd.handle_non_secure_call(&mut msg), World::Secure);
+        assert_eq!(msg, Interface::RxAcquire { vm_id: NS_EP_ID });
+        assert_eq!(
+            respond(Interface::success32_noargs()),
+            (
+                World::NonSecure,
+                success(
+                    PARTITION_COUNT as u32,
+                    Some(PartitionInfo::DESC_SIZE as u32)
+                )
+            )
+        );
+        let written =
+            PartitionInfoIterator::new(PARTITION_INFO_VERSION, &pages[0].0, PARTITION_COUNT)
+                .unwrap()
+                .collect::<Result<Vec<_>, _>>()
+                .unwrap();
+        assert_eq!(written, info);
+
+        // The normal world hasn't released the buffer, so the SPMC refuses to give it up.
+        let mut msg = partition_info_get(Uuid::nil(), false);
+        assert_eq!(spmd.handle_non_secure_call(&mut msg), World::Secure);
+        assert_eq!(
+            respond(Interface::error(FfaError::Denied, true)),
+            (World::NonSecure, Interface::error(FfaError::Busy, true))
+        );
+
+        // The cache is invalidated if the SPMC fails, and fetched again the next time it is needed.
+        respond(Interface::error(FfaError::Aborted, true));
+        let mut msg = partition_info_get(SP_UUID, true);
+        assert_eq!(spmd.handle_non_secure_call(&mut msg), World::Secure);
+        assert_eq!(msg, partition_info_get_regs(0, 0));
+
+        // A response which doesn't continue from the requested index is rejected.
+        assert_eq!(
+            respond(regs_success(0..2)),
+            (World::Secure, partition_info_get_regs(2, 42))
+        );
+        assert_eq!(
+            respond(regs_success(0..1)),
+            (World::Secure, partition_info_get(SP_UUID, true))
+        );
+        assert_eq!(
+            spmd.mailbox_state.partition_info.state.lock().cached(),
+            None
+        );
+    }
+}
@@ -5,14 +5,24 @@
 //! Service implementing the Arm Power State Coordination Interface.
 
 mod power_domain_tree;
+mod power_state_table;
 
 use crate::{
     aarch64::{dsb_sy, wfi},
     context::{CoresImpl, World},
     cpu::{PlatformCpuOps, cpu_handle_power_down_abandon, cpu_power_down},
-    platform::Platform,
-    services::{Service, owns},
+    cpu_notifier::CpuNotifiers,
+    nv_counter::{BootRequest, NvCounterError, NvCounterId, NvCounters},
+    platform::{Platform, PlatformService},
+    pmf::{Pmf, PsciTimestamp},
+    runtime_config::runtime_config,
+    scrub::Secrets,
+    services::{Service, debug::SuspendStats, owns},
     smccc::{FunctionId as SmcFunctionId, OwningEntityNumber, SetFrom, SmcReturn},
+    spin_mutex::SpinMutex,
+    system_suspend_notifier::SystemSuspendNotifiers,
+    timer::ticks_to_micros,
+    watchdog,
 };
 use arm_psci::{
     AffinityInfo, Cookie, EntryPoint, ErrorCode, FeatureFlagsCpuSuspend, FeatureFlagsSystemOff2,
@@ -26,10 +36,13 @@ use core::{
     marker::PhantomData,
     ops::{Add, AddAssign, Sub},
 };
-use log::debug;
+use log::{debug, error, info};
 use percore::Cores;
-use power_domain_tree::{AncestorPowerDomains, CpuPowerNode, PowerDomainTree};
-use spin::mutex::SpinMutex;
+pub use power_domain_tree::PowerDomainStatsTable;
+use power_domain_tree::{
+    AncestorPowerDomains, CoreLockTracking, CpuPowerNode, PowerDomainTree, PowerStateStats,
+};
+pub use power_state_table::{PowerStateTable, PowerStateTableEntry};
 
 const FUNCTION_NUMBER_MIN: u16 = 0x0000;
 const FUNCTION_NUMBER_MAX: u16 = 0x001F;
@@ -61,6 +74,21 @@ bitflags! {
     }
 }
 
+/// A vendor specific `SYSTEM_RESET2` reset type, registered by the platform service.
+#[derive(Clone, Copy, Debug)]
+pub struct VendorResetType {
+    /// The reset type, without the vendor specific bit.
+    pub reset_type: u32,
+    /// A short description of the reset, e.g. "reset to recovery".
+    pub name: &'static str,
+    /// Returns whether the given cookie is a valid parameter for this reset type.
+    pub validate_cookie: fn(Cookie) -> bool,
+    /// If set, PSCI handles the reset itself by recording this request for the next boot stage
+    /// with `NvCounters::write_boot_request` and then doing a cold reset, rather than passing it to
+    /// the platform's `system_reset2`.
+    pub boot_request: Option<BootRequest>,
+}
+
 /// Trait for defining node indices in the power domain tree. The type must support conversion to
 /// and from `usize`, and it must also define a maximum value. This limits the maximum number of CPU
 /// or non-CPU nodes in the tree. The trait is implemented for `u8` and `u16`, which realistically
@@ -75,6 +103,7 @@ pub trait NodeIndexInterface:
     + Into<usize>
     + Ord
     + PartialOrd
+    + Send
     + Sub<Output = Self>
     + TryFrom<usize, Error: Debug>
     + 'static
@@ -99,7 +128,7 @@ impl NodeIndexInterface for u16 {
 /// The type has to implement the `Ord` trait in a way the states are in ascending order from
 /// running state to power down state.
 pub trait PlatformPowerStateInterface:
-    Debug + Clone + Copy + PartialEq + Ord + Into<usize>
+    Debug + Clone + Copy + PartialEq + Ord + Into<usize> + Send + 'static
 {
     /// The power state for a CPU turned off.
     const OFF: Self;
@@ -187,8 +216,12 @@ pub trait PsciPlatformInterface<
         >,
     );
 
-    /// Allows the platform to perform platform specific validations based on hardware states.
-    /// This function is optional since it is only used in OS-Initiated mode.
+    /// Allows the platform to veto a suspend request based on the current hardware state, e.g. an
+    /// ongoing DMA transfer which the requested state would disrupt, optional.
+    ///
+    /// This is called with the requested state in both platform-coordinated and OS-initiated mode,
+    /// before any power domain state is changed. Returning an error aborts the suspend and returns
+    /// the error to the caller.
     fn power_domain_validate_suspend(
         &self,
         _target_state: &PsciCompositePowerState<
@@ -200,7 +233,7 @@ pub trait PsciPlatformInterface<
             Self::PlatformPowerState,
         >,
     ) -> Result<(), ErrorCode> {
-        unimplemented!("OSI mode's suspend state validation is not implemented for the platform")
+        Ok(())
     }
 
     /// Callback for platform housekeeping before turning off the CPU, optional.
@@ -248,6 +281,27 @@ pub trait PsciPlatformInterface<
         >,
     );
 
+    /// Makes sure that the saved contexts and stacks in the retained memory region survive the power
+    /// domains in `target_state` being powered down, e.g. by cleaning a system level cache which
+    /// the region is cached in.
+    ///
+    /// This is called after the CPU power down sequence has flushed the core's caches, and only if
+    /// a power domain above the core is being powered down. The default implementation does
+    /// nothing, which is enough if the CPU power down sequence already flushes every cache in
+    /// front of the retained memory.
+    fn flush_retained_context(
+        &self,
+        _target_state: &PsciCompositePowerState<
+            STATE_COUNT,
+            MAX_POWER_LEVEL,
+            CPU_DOMAIN_COUNT,
+            NON_CPU_DOMAIN_COUNT,
+            Self::NodeIndex,
+            Self::PlatformPowerState,
+        >,
+    ) {
+    }
+
     /// Turn on power domain, which is identified by its MPIDR.
     fn power_domain_on(&self, mpidr: Mpidr) -> Result<(), ErrorCode>;
 
@@ -276,6 +330,9 @@ pub trait PsciPlatformInterface<
     fn system_reset(&self) -> !;
 
     /// Architectural or vendor specific reset function, optional.
+    ///
+    /// Vendor specific reset types are only passed here if they are registered in
+    /// `PlatformService::VENDOR_RESET_TYPES` and their cookie has been validated.
     fn system_reset2(&self, _reset_type: ResetType, _cookie: Cookie) -> Result<(), ErrorCode> {
         unimplemented!("SYSTEM_RESET2 is not implemented for the platform")
     }
@@ -342,6 +399,13 @@ pub trait PsciSpmInterface {
     /// exception level, or forwarded by the SPMC in EL3 to the SPs.
     fn forward_psci_request(&self, function: Function) -> ReturnCode;
 
+    /// Gives the SPM a chance to veto a CPU_ON request before the target core is powered on.
+    ///
+    /// An S-EL2 SPMC must be initialised on the primary core before any secondary core enters it
+    /// through its secondary cold boot entry point, so the SPM may reject the request until it is
+    /// ready. The error is returned to the caller of CPU_ON.
+    fn prepare_cpu_on(&self, target_cpu: Mpidr) -> Result<(), ErrorCode>;
+
     /// Notify the SPM about a CPU_OFF event.
     ///
     /// The PSCI service has received a CPU_OFF request, so the current core will be turned off.
@@ -349,6 +413,13 @@ pub trait PsciSpmInterface {
     /// forward_psci_request()
     fn notify_cpu_off(&self);
 
+    /// Notify the SPM that the current core is about to be powered down for a suspend.
+    ///
+    /// This is called after the suspend request has been forwarded to the SPM, just before the
+    /// core's register state is lost, so that the SPM can save any secure state which isn't
+    /// already saved on a world switch.
+    fn notify_cpu_suspend_powerdown(&self);
+
     /// Notify the SPM about a powerdown abandon.
     ///
     /// This function should be called to unwind state on the SPM side after a powerdown abandon.
@@ -771,9 +842,16 @@ pub struct Psci<
         MAX_POWER_LEVEL,
         PsciPlatformImpl::NodeIndex,
         PsciPlatformImpl::PlatformPowerState,
+        CoreLockTracking<CoresImpl<PlatformImpl>>,
     >,
     suspend_mode: SpinMutex<SuspendMode>,
+    suspend_stats: SuspendStats,
     spm: fn() -> &'static Spm,
+    nv_counters: SpinMutex<Option<PlatformImpl::NvCountersImpl>>,
+    cpu_notifiers: CpuNotifiers,
+    system_suspend_notifiers: SystemSuspendNotifiers,
+    secrets: Secrets,
+    pmf: &'static Pmf<CPU_DOMAIN_COUNT>,
     _platform: PhantomData<PlatformImpl>,
 }
 
@@ -799,8 +877,18 @@ impl<
     /// Initialises the PSCI state.
     ///
     /// This should be called exactly once, before any other PSCI methods are called or any
-    /// secondary CPUs are started.
-    pub(super) fn new(platform: PsciPlatformImpl, spm: fn() -> &'static Spm) -> Self {
+    /// secondary CPUs are started. The residency statistics of each power domain are recorded in
+    /// `power_stats`, and each core's PSCI entry and exit timestamps in `pmf`.
+    pub(super) fn new(
+        platform: PsciPlatformImpl,
+        spm: fn() -> &'static Spm,
+        power_stats: &'static PowerDomainStatsTable<
+            CPU_DOMAIN_COUNT,
+            NON_CPU_DOMAIN_COUNT,
+            PsciPlatformImpl::PlatformPowerState,
+        >,
+        pmf: &'static Pmf<CPU_DOMAIN_COUNT>,
+    ) -> Self {
         const {
             assert!(STATE_COUNT == MAX_POWER_LEVEL + 1);
             assert!(
@@ -810,7 +898,8 @@ impl<
 
         debug!("Initializing PSCI");
 
-        let power_domain_tree = PowerDomainTree::new(PsciPlatformImpl::topology());
+        let power_domain_tree = PowerDomainTree::new(PsciPlatformImpl::topology(), power_stats)
+            .with_lock_tracking::<CoresImpl<PlatformImpl>>();
 
         {
             // Init primary CPU
@@ -830,11 +919,46 @@ impl<
             platform,
             power_domain_tree,
             suspend_mode,
+            suspend_stats: SuspendStats::new(),
             spm,
+            nv_counters: SpinMutex::new(PlatformImpl::nv_counters()),
+            cpu_notifiers: CpuNotifiers::new(),
+            system_suspend_notifiers: SystemSuspendNotifiers::new(),
+            secrets: Secrets::new(),
+            pmf,
             _platform: PhantomData,
         }
     }
 
+    /// Returns the statistics about `CPU_SUSPEND` calls.
+    pub fn suspend_stats(&self) -> &SuspendStats {
+        &self.suspend_stats
+    }
+
+    /// Returns the list of drivers to notify when a core is turned on or off.
+    pub fn cpu_notifiers(&self) -> &CpuNotifiers {
+        &self.cpu_notifiers
+    }
+
+    /// Returns the list of drivers to notify when the system power domain is suspended or resumes.
+    pub fn system_suspend_notifiers(&self) -> &SystemSuspendNotifiers {
+        &self.system_suspend_notifiers
+    }
+
+    /// Returns the list of secrets to zero before the system is turned off or reset.
+    pub fn secrets(&self) -> &Secrets {
+        &self.secrets
+    }
+
+    /// Returns the current value of the given trusted non-volatile counter.
+    pub fn read_nv_counter(&self, counter: NvCounterId) -> Result<u32, NvCounterError> {
+        self.nv_counters
+            .lock()
+            .as_ref()
+            .ok_or(NvCounterError::NotSupported)
+            .and_then(|nv_counters| nv_counters.read(counter))
+    }
+
     /// Handles `CPU_SUSPEND` PSCI call by following the steps below.
     /// * If the a standby power state is requested which only affects the CPU level, the wait for
     ///   interrupts by calling `cpu_standby` and then return after an interrupt.
@@ -848,6 +972,7 @@ impl<
         let cpu_index = Self::cpu_index();
         let composite_state = PsciPlatformImpl::try_parse_power_state(power_state)
             .ok_or(ErrorCode::InvalidParameters)?;
+        self.suspend_stats.record_request(power_state.into());
 
         let is_power_down_state = matches!(power_state, PowerState::PowerDown(_));
 
@@ -897,6 +1022,8 @@ impl<
     /// * Return immediately if there's a pending interrupt.
     /// * Otherwise determine the valid state for each level without violating any power domain
     ///   rules.
+    /// * Let the platform veto the requested state (`power_domain_validate_suspend`), returning its
+    ///   error without changing any state if it does.
     /// * Request this power state from the platform layer (`power_domain_suspend`). This step does
     ///   not trigger an immediate shutdown of the power domain.
     /// * Prepare for core domain power down by calling `power_domain_power_down` if this is a power
@@ -962,6 +1089,9 @@ impl<
                         resolved_state,
                     );
                 } else {
+                    self.platform
+                        .power_domain_validate_suspend(&composite_state)?;
+
                     composite_state.coordinate_state(cpu_index, &mut ancestors);
                     cpu.set_local_state(composite_state.cpu_level_state());
                 }
@@ -974,27 +1104,44 @@ impl<
                     }
                     cpu.set_entry_point(entry);
 
+                    #[cfg(feature = "rt_instr")]
+                    self.pmf
+                        .record_psci(cpu_index.into(), PsciTimestamp::EnterCacheFlush);
                     cpu_power_down::<PlatformImpl>(
                         composite_state.find_highest_power_down_level().unwrap(),
                     );
+                    #[cfg(feature = "rt_instr")]
+                    self.pmf
+                        .record_psci(cpu_index.into(), PsciTimestamp::ExitCacheFlush);
                 }
 
+                if Self::powers_down_system(&composite_state) {
+                    self.system_suspend_notifiers.system_suspend();
+                }
                 self.platform.power_domain_suspend(&composite_state);
                 Ok(false)
             },
         )?;
-        drop(cpu); // Unlock CPU before entering suspend state
+        cpu.unlock_before_noreturn(); // Unlock CPU before entering suspend state
 
         if has_pending_interrupt {
             // Has pending interrupts, do not suspend
+            if let Some(power_state) = power_state {
+                self.suspend_stats.record_abort(power_state.into());
+            }
             return Ok(());
         }
 
         if is_power_down_state {
+            (self.spm)().notify_cpu_suspend_powerdown();
             for ext in PlatformImpl::CPU_EXTENSIONS {
                 ext.save_context_before_suspend_to_powerdown();
             }
 
+            // The secure world won't run again until after the wake up, so refresh the watchdog
+            // before the potentially long flush.
+            watchdog::refresh_if_due::<PlatformImpl>();
+            self.flush_retained_context(&composite_state);
             self.platform.power_domain_power_down(&composite_state);
             // This WFI will trigger core powerdown attempt. If successful, the core will lose all
             // state and must restart from its reset vector which is typically bl31_warm_entrypoint.
@@ -1005,10 +1152,24 @@ impl<
             //   - If the core does not support powerdown abandon, the powerdown attempt will
             //     always succeed.
             dsb_sy();
+            self.power_domain_tree.assert_no_locks_held();
+            #[cfg(feature = "rt_instr")]
+            self.pmf
+                .record_psci(cpu_index.into(), PsciTimestamp::EnterLowPower);
             wfi();
+            #[cfg(feature = "rt_instr")]
+            self.pmf
+                .record_psci(cpu_index.into(), PsciTimestamp::ExitLowPower);
             cpu_handle_power_down_abandon::<PlatformImpl>();
         } else {
+            self.power_domain_tree.assert_no_locks_held();
+            #[cfg(feature = "rt_instr")]
+            self.pmf
+                .record_psci(cpu_index.into(), PsciTimestamp::EnterLowPower);
             wfi();
+            #[cfg(feature = "rt_instr")]
+            self.pmf
+                .record_psci(cpu_index.into(), PsciTimestamp::ExitLowPower);
         }
 
         // Restore running state after wake-up.
@@ -1017,7 +1178,7 @@ impl<
             .with_ancestors_locked(&mut cpu, |cpu, mut ancestors| {
                 composite_state.set_local_states_from_nodes(cpu, &ancestors);
 
-                self.platform.power_domain_suspend_finish(&composite_state);
+                self.power_domain_suspend_finish(&composite_state);
                 if is_power_down_state {
                     (self.spm)().notify_cpu_suspend_powerdown_abandoned();
                     // Since this is a powerdown abandon, the entry point will never be consumed by
@@ -1031,6 +1192,60 @@ impl<
         Ok(())
     }
 
+    /// Returns whether the given state powers down the highest power level, i.e. the system.
+    fn powers_down_system(
+        composite_state: &PsciCompositePowerState<
+            STATE_COUNT,
+            MAX_POWER_LEVEL,
+            CPU_DOMAIN_COUNT,
+            NON_CPU_DOMAIN_COUNT,
+            PsciPlatformImpl::NodeIndex,
+            PsciPlatformImpl::PlatformPowerState,
+        >,
+    ) -> bool {
+        composite_state.highest_level_state().power_state_type() == PowerStateType::PowerDown
+    }
+
+    /// Asks the platform to finish waking up from the given suspend state, then re-initialises the
+    /// drivers' peripherals if the system was powered down.
+    fn power_domain_suspend_finish(
+        &self,
+        composite_state: &PsciCompositePowerState<
+            STATE_COUNT,
+            MAX_POWER_LEVEL,
+            CPU_DOMAIN_COUNT,
+            NON_CPU_DOMAIN_COUNT,
+            PsciPlatformImpl::NodeIndex,
+            PsciPlatformImpl::PlatformPowerState,
+        >,
+    ) {
+        self.platform.power_domain_suspend_finish(composite_state);
+        if Self::powers_down_system(composite_state) {
+            self.system_suspend_notifiers.system_resume();
+        }
+    }
+
+    /// Asks the platform to flush the saved contexts to retained memory if the power down affects
+    /// more than just the current core.
+    fn flush_retained_context(
+        &self,
+        composite_state: &PsciCompositePowerState<
+            STATE_COUNT,
+            MAX_POWER_LEVEL,
+            CPU_DOMAIN_COUNT,
+            NON_CPU_DOMAIN_COUNT,
+            PsciPlatformImpl::NodeIndex,
+            PsciPlatformImpl::PlatformPowerState,
+        >,
+    ) {
+        if composite_state
+            .find_highest_power_down_level()
+            .is_some_and(|level| level > CPU_POWER_LEVEL)
+        {
+            self.platform.flush_retained_context(composite_state);
+        }
+    }
+
     /// Handles `CPU_OFF` PSCI call.
     /// On success, turns off the current CPU and does not return.
     fn cpu_off(&self) -> Result<(), ErrorCode> {
@@ -1047,18 +1262,26 @@ impl<
                 cpu.set_local_state(PsciPlatformImpl::PlatformPowerState::OFF);
                 composite_state.coordinate_state(cpu_index, &mut ancestors);
 
+                #[cfg(feature = "rt_instr")]
+                self.pmf
+                    .record_psci(cpu_index.into(), PsciTimestamp::EnterCacheFlush);
                 cpu_power_down::<PlatformImpl>(
                     composite_state.find_highest_power_down_level().unwrap(),
                 );
+                #[cfg(feature = "rt_instr")]
+                self.pmf
+                    .record_psci(cpu_index.into(), PsciTimestamp::ExitCacheFlush);
 
+                self.cpu_notifiers.cpu_offline();
                 self.platform.power_domain_off(&composite_state);
             });
 
         cpu.set_affinity_info(AffinityInfo::Off);
 
         // Unlock CPU before actually turning it off
-        drop(cpu);
+        cpu.unlock_before_noreturn();
 
+        self.flush_retained_context(&composite_state);
         self.platform.power_domain_power_down(&composite_state);
         dsb_sy();
 
@@ -1068,6 +1291,10 @@ impl<
          * be denied. Hopefully this is transient, retrying a few times should
          * power down.
          */
+        self.power_domain_tree.assert_no_locks_held();
+        #[cfg(feature = "rt_instr")]
+        self.pmf
+            .record_psci(cpu_index.into(), PsciTimestamp::EnterLowPower);
         for _ in 0..CPU_OFF_WFI_RETRY_COUNT {
             wfi();
         }
@@ -1094,6 +1321,8 @@ impl<
             AffinityInfo::Off => {}
         }
 
+        (self.spm)().prepare_cpu_on(target_cpu)?;
+
         cpu.set_affinity_info(AffinityInfo::OnPending);
 
         match self.platform.power_domain_on(target_cpu) {
@@ -1112,13 +1341,16 @@ impl<
     /// point and the reason why the CPU was powered up.
     pub fn handle_cpu_boot(&self) -> WakeUpReason {
         let cpu_index = Self::cpu_index();
+        #[cfg(feature = "rt_instr")]
+        self.pmf
+            .record_psci(cpu_index.into(), PsciTimestamp::ExitLowPower);
         let mut cpu = self.power_domain_tree.locked_cpu_node(cpu_index);
         let mut composite_state = PsciCompositePowerState::RUN;
         let mut wake_from_suspend = false;
 
         let affinity_info = cpu.affinity_info();
         if affinity_info == AffinityInfo::Off {
-            drop(cpu);
+            cpu.unlock_before_noreturn();
             panic!("Unexpected affinity info state");
         }
 
@@ -1129,6 +1361,7 @@ impl<
                 if affinity_info == AffinityInfo::OnPending {
                     // Finishing CPU_ON
                     self.platform.power_domain_on_finish(&composite_state);
+                    self.cpu_notifiers.cpu_online();
 
                     cpu.set_affinity_info(AffinityInfo::On);
                 } else {
@@ -1139,7 +1372,7 @@ impl<
                         PowerStateType::PowerDown
                     );
 
-                    self.platform.power_domain_suspend_finish(&composite_state);
+                    self.power_domain_suspend_finish(&composite_state);
 
                     wake_from_suspend = true;
                 }
@@ -1150,11 +1383,13 @@ impl<
             });
 
         let entry_point = cpu.pop_entry_point();
-        drop(cpu); // Unlock before possible panic
+        cpu.unlock_before_noreturn(); // Unlock before possible panic
 
         let entry_point = entry_point.expect("entry point not set for booting CPU");
 
         if wake_from_suspend {
+            // The `CPU_SUSPEND` call which powered the core down returns here.
+            self.pmf.record_psci(cpu_index.into(), PsciTimestamp::Exit);
             WakeUpReason::SuspendFinished(entry_point)
         } else {
             WakeUpReason::CpuOn(entry_point)
@@ -1185,17 +1420,20 @@ impl<
     /// Turns off the system and does not return.
     fn system_off(&self) -> ! {
         self.forward_to_spm(Function::SystemOff);
+        self.secrets.scrub_all();
         self.platform.system_off();
     }
 
     /// Handles `SYSTEM_OFF2` PSCI call.
     /// Suspends system to disk and never returns on success.
     fn system_off2(&self, off_type: SystemOff2Type, cookie: Cookie) -> Result<(), ErrorCode> {
-        if !PsciPlatformImpl::FEATURES.contains(PsciPlatformOptionalFeatures::SYSTEM_OFF2) {
+        if !Self::features().contains(PsciPlatformOptionalFeatures::SYSTEM_OFF2) {
             return Err(ErrorCode::NotSupported);
         }
 
         self.forward_to_spm(Function::SystemOff2 { off_type, cookie });
+        // The platform may fail to turn off the system, so only scrub what can be replaced.
+        self.secrets.scrub_replaceable();
         self.platform.system_off2(off_type, cookie)
     }
 
@@ -1203,23 +1441,42 @@ impl<
     /// Resets the system and does not return.
     fn system_reset(&self) -> ! {
         self.forward_to_spm(Function::SystemReset);
+        self.secrets.scrub_all();
         self.platform.system_reset();
     }
 
     /// Handles `SYSTEM_RESET2` PSCI call.
     /// Initiates an architectural or vendor specific system reset. Does not return on success.
     fn system_reset2(&self, reset_type: ResetType, cookie: Cookie) -> Result<(), ErrorCode> {
-        if !PsciPlatformImpl::FEATURES.contains(PsciPlatformOptionalFeatures::SYSTEM_RESET2) {
+        if !Self::features().contains(PsciPlatformOptionalFeatures::SYSTEM_RESET2) {
             return Err(ErrorCode::NotSupported);
         }
 
+        if let ResetType::VendorSpecific(vendor_reset_type) = reset_type {
+            let vendor_reset =
+                Self::vendor_reset_type(vendor_reset_type).ok_or(ErrorCode::InvalidParameters)?;
+            if !(vendor_reset.validate_cookie)(cookie) {
+                return Err(ErrorCode::InvalidParameters);
+            }
+            info!("SYSTEM_RESET2: {} ({cookie:?})", vendor_reset.name);
+
+            if let Some(request) = vendor_reset.boot_request {
+                self.record_boot_request(request)?;
+                self.forward_to_spm(Function::SystemReset2 { reset_type, cookie });
+                self.secrets.scrub_all();
+                self.platform.system_reset();
+            }
+        }
+
         self.forward_to_spm(Function::SystemReset2 { reset_type, cookie });
+        // The platform may fail to reset the system, so only scrub what can be replaced.
+        self.secrets.scrub_replaceable();
         self.platform.system_reset2(reset_type, cookie)
     }
 
     /// Handles `MEM_PROTECT` PSCI call.
     fn mem_protect(&self, enabled: bool) -> Result<bool, ErrorCode> {
-        if !PsciPlatformImpl::FEATURES.contains(PsciPlatformOptionalFeatures::MEM_PROTECT) {
+        if !Self::features().contains(PsciPlatformOptionalFeatures::MEM_PROTECT) {
             return Err(ErrorCode::NotSupported);
         }
 
@@ -1228,9 +1485,7 @@ impl<
 
     /// Handles `MEM_PROTECT_CHECK_RANGE` PSCI call.
     fn mem_protect_check_range(&self, range: MemProtectRange) -> Result<(), ErrorCode> {
-        if !PsciPlatformImpl::FEATURES
-            .contains(PsciPlatformOptionalFeatures::MEM_PROTECT_CHECK_RANGE)
-        {
+        if !Self::features().contains(PsciPlatformOptionalFeatures::MEM_PROTECT_CHECK_RANGE) {
             return Err(ErrorCode::NotSupported);
         }
 
@@ -1242,7 +1497,7 @@ impl<
         const SUCCESS: u64 = 0;
 
         let check_optional_feature = |feature| {
-            if PsciPlatformImpl::FEATURES.contains(feature) {
+            if Self::features().contains(feature) {
                 Ok(SUCCESS)
             } else {
                 Err(ErrorCode::NotSupported)
@@ -1265,7 +1520,7 @@ impl<
                 // CPU suspend features
                 FunctionId::CpuSuspend32 | FunctionId::CpuSuspend64 => {
                     let flags = FeatureFlagsCpuSuspend::EXTENDED_POWER_STATE
-                        | (if PsciPlatformImpl::FEATURES
+                        | (if Self::features()
                             .contains(PsciPlatformOptionalFeatures::OS_INITIATED_MODE)
                         {
                             FeatureFlagsCpuSuspend::OS_INITIATED_MODE
@@ -1282,9 +1537,7 @@ impl<
                 | FunctionId::MigrateInfoUpCpu64 => Err(ErrorCode::NotSupported),
                 FunctionId::MigrateInfoType => Ok(SUCCESS),
                 FunctionId::SystemOff232 | FunctionId::SystemOff264 => {
-                    if PsciPlatformImpl::FEATURES
-                        .contains(PsciPlatformOptionalFeatures::SYSTEM_OFF2)
-                    {
+                    if Self::features().contains(PsciPlatformOptionalFeatures::SYSTEM_OFF2) {
                         let flags = FeatureFlagsSystemOff2::HIBERNATE_OFF;
                         Ok(u32::from(flags) as u64)
                     } else {
@@ -1315,21 +1568,41 @@ impl<
                 FunctionId::PsciSetSuspendMode => {
                     check_optional_feature(PsciPlatformOptionalFeatures::OS_INITIATED_MODE)
                 }
-                FunctionId::PsciStatResidency32 | FunctionId::PsciStatResidency64 => {
-                    Err(ErrorCode::NotSupported)
-                }
-                FunctionId::PsciStatCount32 | FunctionId::PsciStatCount64 => {
-                    Err(ErrorCode::NotSupported)
-                }
+                FunctionId::PsciStatResidency32
+                | FunctionId::PsciStatResidency64
+                | FunctionId::PsciStatCount32
+                | FunctionId::PsciStatCount64 => Ok(SUCCESS),
             },
             PsciFeature::SmcccVersion => Ok(SUCCESS),
         }
     }
 
+    /// Handles the common part of `PSCI_STAT_RESIDENCY` and `PSCI_STAT_COUNT` PSCI calls.
+    ///
+    /// Returns the statistics of the highest power domain affected by `power_state` which contains
+    /// `target_cpu`, for its local state in `power_state`.
+    fn power_state_stats(
+        &self,
+        target_cpu: Mpidr,
+        power_state: PowerState,
+    ) -> Result<PowerStateStats, ErrorCode> {
+        let cpu_index = try_get_cpu_index_by_mpidr::<PlatformImpl, _>(target_cpu)
+            .ok_or(ErrorCode::InvalidParameters)?;
+        let composite_state = PsciPlatformImpl::try_parse_power_state(power_state)
+            .ok_or(ErrorCode::InvalidParameters)?;
+        let power_level = composite_state
+            .find_highest_non_run_level()
+            .ok_or(ErrorCode::InvalidParameters)?;
+
+        self.power_domain_tree
+            .power_state_stats(cpu_index, power_level, composite_state.states[power_level])
+            .ok_or(ErrorCode::InvalidParameters)
+    }
+
     /// Handles `CPU_FREEZE` PSCI call.
     /// Does not return on success.
     fn cpu_freeze(&self) -> Result<(), ErrorCode> {
-        if !PsciPlatformImpl::FEATURES.contains(PsciPlatformOptionalFeatures::CPU_FREEZE) {
+        if !Self::features().contains(PsciPlatformOptionalFeatures::CPU_FREEZE) {
             return Err(ErrorCode::NotSupported);
         }
 
@@ -1340,7 +1613,7 @@ impl<
     /// Places a core into an implementation defined low-power state. It might not return if the
     /// default state is a power down state.
     fn cpu_default_suspend(&self, entry: EntryPoint) -> Result<(), ErrorCode> {
-        if !PsciPlatformImpl::FEATURES.contains(PsciPlatformOptionalFeatures::CPU_DEFAULT_SUSPEND) {
+        if !Self::features().contains(PsciPlatformOptionalFeatures::CPU_DEFAULT_SUSPEND) {
             return Err(ErrorCode::NotSupported);
         }
 
@@ -1350,7 +1623,7 @@ impl<
 
     /// Handles `NODE_HW_STATE` PSCI call.
     fn node_hw_state(&self, target_cpu: Mpidr, power_level: u32) -> Result<HwState, ErrorCode> {
-        if !PsciPlatformImpl::FEATURES.contains(PsciPlatformOptionalFeatures::NODE_HW_STATE) {
+        if !Self::features().contains(PsciPlatformOptionalFeatures::NODE_HW_STATE) {
             return Err(ErrorCode::NotSupported);
         }
 
@@ -1367,7 +1640,7 @@ impl<
     /// Handles `SYSTEM_SUSPEND` PSCI call.
     /// Suspends system into RAM, does not return on success.
     fn system_suspend(&self, entry: EntryPoint) -> Result<(), ErrorCode> {
-        if !PsciPlatformImpl::FEATURES.contains(PsciPlatformOptionalFeatures::SYSTEM_SUSPEND) {
+        if !Self::features().contains(PsciPlatformOptionalFeatures::SYSTEM_SUSPEND) {
             return Err(ErrorCode::NotSupported);
         }
 
@@ -1396,7 +1669,7 @@ impl<
     }
 
     fn set_suspend_mode(&self, mode: SuspendMode) -> Result<u64, ErrorCode> {
-        if !PsciPlatformImpl::FEATURES.contains(PsciPlatformOptionalFeatures::OS_INITIATED_MODE) {
+        if !Self::features().contains(PsciPlatformOptionalFeatures::OS_INITIATED_MODE) {
             return Err(ErrorCode::NotSupported);
         }
         if *self.suspend_mode.lock() == mode {
@@ -1423,7 +1696,7 @@ impl<
 
     /// Returns true if this Psci instance is in OS-initiated mode.
     fn is_in_osi_mode(&self) -> bool {
-        if !PsciPlatformImpl::FEATURES.contains(PsciPlatformOptionalFeatures::OS_INITIATED_MODE) {
+        if !Self::features().contains(PsciPlatformOptionalFeatures::OS_INITIATED_MODE) {
             return false;
         }
         *self.suspend_mode.lock() == SuspendMode::OsInitiated
@@ -1502,8 +1775,17 @@ impl<
                 Ok(SUCCESS)
             }
             Function::SetSuspendMode { mode } => self.set_suspend_mode(mode),
-            Function::StatResidency { .. } => Err(ErrorCode::NotSupported),
-            Function::StatCount { .. } => Err(ErrorCode::NotSupported),
+            Function::StatResidency {
+                target_cpu,
+                power_state,
+            } => {
+                let stats = self.power_state_stats(target_cpu, power_state)?;
+                Ok(ticks_to_micros(stats.residency))
+            }
+            Function::StatCount {
+                target_cpu,
+                power_state,
+            } => Ok(self.power_state_stats(target_cpu, power_state)?.count),
         }
     }
 
@@ -1517,9 +1799,40 @@ impl<
         }
     }
 
+    /// Returns the optional features to offer, i.e. those which the platform supports and which
+    /// are enabled in the runtime configuration.
+    fn features() -> PsciPlatformOptionalFeatures {
+        runtime_config().psci_features(PsciPlatformImpl::FEATURES)
+    }
+
+    /// Records the given request for the next boot stage in the platform's non-volatile storage.
+    fn record_boot_request(&self, request: BootRequest) -> Result<(), ErrorCode> {
+        self.nv_counters
+            .lock()
+            .as_mut()
+            .ok_or(NvCounterError::NotSupported)
+            .and_then(|nv_counters| nv_counters.write_boot_request(request))
+            .map_err(|e| {
+                error!("Failed to record boot request {request:?}: {e:?}");
+                ErrorCode::InvalidParameters
+            })
+    }
+
+    /// Looks up a vendor specific `SYSTEM_RESET2` reset type registered by the platform service.
+    fn vendor_reset_type(reset_type: u32) -> Option<&'static VendorResetType> {
+        <PlatformImpl::PlatformServiceImpl as PlatformService>::VENDOR_RESET_TYPES
+            .iter()
+            .find(|vendor_reset| vendor_reset.reset_type == reset_type)
+    }
+
     fn cpu_index() -> PsciPlatformImpl::NodeIndex {
         CoresImpl::<PlatformImpl>::core_index().try_into().unwrap()
     }
+
+    /// Panics in debug builds if the current core holds any power domain locks.
+    pub(crate) fn assert_no_locks_held(&self) {
+        self.power_domain_tree.assert_no_locks_held();
+    }
 }
 
 impl<
@@ -1547,6 +1860,9 @@ impl<
     );
 
     fn handle_non_secure_smc(&self, regs: &mut SmcReturn) -> World {
+        let core_index = CoresImpl::<PlatformImpl>::core_index();
+        self.pmf.record_psci(core_index, PsciTimestamp::Entry);
+
         let in_regs: &mut [u64; 4] = (&mut regs.values_mut()[..4]).try_into().unwrap();
         let mut function = SmcFunctionId(in_regs[0] as u32);
         function.clear_sve_hint();
@@ -1559,6 +1875,7 @@ impl<
 
         regs.set_from(result);
 
+        self.pmf.record_psci(core_index, PsciTimestamp::Exit);
         World::NonSecure
     }
 }
@@ -1583,6 +1900,11 @@ impl<
     >
 {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        if let Some(suspend_mode) = self.suspend_mode.try_lock() {
+            writeln!(f, "Suspend mode: {:?}", *suspend_mode)?;
+        } else {
+            writeln!(f, "Suspend mode: locked")?;
+        }
         self.power_domain_tree.fmt(f)
     }
 }
@@ -1613,19 +1935,24 @@ mod tests {
     use super::*;
     use crate::{
         platform::test::{
-            PSCI_MAX_POWER_LEVEL, TestPlatform, TestPowerState, TestPsciPlatformImpl,
+            PSCI_MAX_POWER_LEVEL, SimulatedCoreState, TEST_BOOT_REQUEST, TestPlatform,
+            TestPlatformService, TestPowerState, TestPsciPlatformImpl,
         },
-        services::ffa::spmd::TestSpm,
+        scrub::Secret,
+        services::{debug::SuspendCounter, ffa::spmd::TestSpm},
     };
     use arm_psci::ArchitecturalResetType;
-    use arm_sysregs::fake::SYSREGS;
-    use power_domain_tree::test_helpers::set_cpu_power_state_by_index;
+    use arm_sysregs::{CntfrqEl0, CntpctEl0, IsrEl1, fake::SYSREGS};
+    use power_domain_tree::test_helpers::{set_cpu_power_state_by_index, stats_table};
     use std::panic::{AssertUnwindSafe, catch_unwind, resume_unwind};
+    use std::sync::atomic::{AtomicBool, Ordering};
 
     const PSCI_STATE_COUNT: usize = PSCI_MAX_POWER_LEVEL + 1;
     const NON_CPU_DOMAIN_COUNT: usize =
         TestPsciPlatformImpl::POWER_DOMAIN_COUNT - TestPlatform::CORE_COUNT;
 
+    static PMF: Pmf<{ TestPlatform::CORE_COUNT }> = Pmf::new();
+
     const ENTRY_POINT: EntryPoint = EntryPoint::Entry64 {
         entry_point_address: 0x0123_4567_89ab_cdef,
         context_id: 0xfedc_ba98_7654_3210,
@@ -1811,7 +2138,12 @@ mod tests {
         TestPsciPlatformImpl,
         TestSpm,
     > {
-        let psci = Psci::new(TestPsciPlatformImpl::new(), || &TestSpm);
+        let psci = Psci::new(
+            TestPsciPlatformImpl::new(),
+            || &TestSpm,
+            stats_table(),
+            &PMF,
+        );
         assert_eq!(psci.set_suspend_mode(SuspendMode::OsInitiated), Ok(0));
 
         for mpidr in &CPU_MPIDRS[1..] {
@@ -1970,7 +2302,7 @@ mod tests {
             PSCI_MAX_POWER_LEVEL,
             u8,
             _,
-        >::new(TestPsciPlatformImpl::topology());
+        >::new(TestPsciPlatformImpl::topology(), stats_table());
 
         let mut cpu = tree.locked_cpu_node(2);
         tree.with_ancestors_locked(&mut cpu, |cpu, mut ancestors| {
@@ -2004,7 +2336,7 @@ mod tests {
             PSCI_MAX_POWER_LEVEL,
             u8,
             _,
-        >::new(TestPsciPlatformImpl::topology());
+        >::new(TestPsciPlatformImpl::topology(), stats_table());
 
         let mut cpu = tree.locked_cpu_node(0);
         tree.with_ancestors_locked(&mut cpu, |cpu, mut ancestors| {
@@ -2099,7 +2431,7 @@ mod tests {
             PSCI_MAX_POWER_LEVEL,
             u8,
             _,
-        >::new(TestPsciPlatformImpl::topology());
+        >::new(TestPsciPlatformImpl::topology(), stats_table());
 
         let mut cpu = tree.locked_cpu_node(2);
         tree.with_ancestors_locked(&mut cpu, |_cpu, mut ancestors| {
@@ -2115,7 +2447,7 @@ mod tests {
             PSCI_MAX_POWER_LEVEL,
             u8,
             _,
-        >::new(TestPsciPlatformImpl::topology());
+        >::new(TestPsciPlatformImpl::topology(), stats_table());
 
         for index in 0..TestPlatform::CORE_COUNT {
             set_cpu_power_state_by_index(&tree, index, TestPowerState::RUN);
@@ -2143,7 +2475,7 @@ mod tests {
             PSCI_MAX_POWER_LEVEL,
             u8,
             _,
-        >::new(TestPsciPlatformImpl::topology());
+        >::new(TestPsciPlatformImpl::topology(), stats_table());
 
         for index in 0..TestPlatform::CORE_COUNT {
             set_cpu_power_state_by_index(&tree, index, TestPowerState::RUN);
@@ -2172,7 +2504,7 @@ mod tests {
             PSCI_MAX_POWER_LEVEL,
             u8,
             _,
-        >::new(TestPsciPlatformImpl::topology());
+        >::new(TestPsciPlatformImpl::topology(), stats_table());
 
         for index in 0..TestPlatform::CORE_COUNT {
             set_cpu_power_state_by_index(&tree, index, TestPowerState::RUN);
@@ -2196,7 +2528,7 @@ mod tests {
 
     #[test]
     fn psci_composite_power_state_validate_state_coordination_shallower_lvl1_state_is_valid() {
-        let mut tree = PowerDomainTree::new(TestPsciPlatformImpl::topology());
+        let mut tree = PowerDomainTree::new(TestPsciPlatformImpl::topology(), stats_table());
 
         for index in 0..TestPlatform::CORE_COUNT {
             set_cpu_power_state_by_index(&tree, index, TestPowerState::RUN);
@@ -2220,7 +2552,7 @@ mod tests {
 
     #[test]
     fn psci_composite_power_state_validate_state_coordination_all_off_is_valid() {
-        let mut tree = PowerDomainTree::new(TestPsciPlatformImpl::topology());
+        let mut tree = PowerDomainTree::new(TestPsciPlatformImpl::topology(), stats_table());
 
         for index in 0..TestPlatform::CORE_COUNT {
             set_cpu_power_state_by_index(&tree, index, TestPowerState::RUN);
@@ -2261,7 +2593,7 @@ mod tests {
 
     #[test]
     fn psci_composite_power_state_validate_state_coordination_denies_if_not_last_at_level() {
-        let mut tree = PowerDomainTree::new(TestPsciPlatformImpl::topology());
+        let mut tree = PowerDomainTree::new(TestPsciPlatformImpl::topology(), stats_table());
 
         for index in 0..TestPlatform::CORE_COUNT {
             set_cpu_power_state_by_index(&tree, index, TestPowerState::RUN);
@@ -2287,7 +2619,7 @@ mod tests {
 
     #[test]
     fn psci_composite_power_state_validate_state_coordination_denies_if_peer_still_running() {
-        let mut tree = PowerDomainTree::new(TestPsciPlatformImpl::topology());
+        let mut tree = PowerDomainTree::new(TestPsciPlatformImpl::topology(), stats_table());
 
         for index in 0..TestPlatform::CORE_COUNT {
             set_cpu_power_state_by_index(&tree, index, TestPowerState::RUN);
@@ -2310,7 +2642,7 @@ mod tests {
 
     #[test]
     fn psci_composite_power_state_validate_state_coordination_denies_lvl2_even_if_lv1_is_valid() {
-        let mut tree = PowerDomainTree::new(TestPsciPlatformImpl::topology());
+        let mut tree = PowerDomainTree::new(TestPsciPlatformImpl::topology(), stats_table());
 
         for index in 0..TestPlatform::CORE_COUNT {
             set_cpu_power_state_by_index(&tree, index, TestPowerState::RUN);
@@ -2337,7 +2669,7 @@ mod tests {
     #[test]
     fn psci_composite_power_state_validate_state_coordination_invalid_params_for_non_run_shallower_peer()
      {
-        let mut tree = PowerDomainTree::new(TestPsciPlatformImpl::topology());
+        let mut tree = PowerDomainTree::new(TestPsciPlatformImpl::topology(), stats_table());
 
         for index in 0..TestPlatform::CORE_COUNT {
             set_cpu_power_state_by_index(&tree, index, TestPowerState::RUN);
@@ -2364,7 +2696,7 @@ mod tests {
     #[test]
     fn psci_composite_power_state_validate_state_coordination_invalid_params_for_state_without_last_at_power_level()
      {
-        let tree = PowerDomainTree::new(TestPsciPlatformImpl::topology());
+        let tree = PowerDomainTree::new(TestPsciPlatformImpl::topology(), stats_table());
 
         for index in 0..TestPlatform::CORE_COUNT {
             set_cpu_power_state_by_index(&tree, index, TestPowerState::RUN);
@@ -2553,7 +2885,12 @@ mod tests {
             TestPlatform,
             _,
             _,
-        >::new(TestPsciPlatformImpl::new(), || &TestSpm);
+        >::new(
+            TestPsciPlatformImpl::new(),
+            || &TestSpm,
+            stats_table(),
+            &PMF,
+        );
 
         assert_eq!(
             Err(ErrorCode::InvalidParameters),
@@ -2578,6 +2915,199 @@ mod tests {
         assert_eq!(wakeup_reason, WakeUpReason::SuspendFinished(ENTRY_POINT));
     }
 
+    #[test]
+    fn psci_cpu_suspend_flush_retained_context() {
+        let psci = Psci::<
+            PSCI_STATE_COUNT,
+            PSCI_MAX_POWER_LEVEL,
+            { TestPlatform::CORE_COUNT },
+            NON_CPU_DOMAIN_COUNT,
+            TestPlatform,
+            _,
+            _,
+        >::new(
+            TestPsciPlatformImpl::new(),
+            || &TestSpm,
+            stats_table(),
+            &PMF,
+        );
+
+        // Powering down only the core doesn't need the retained context to be flushed.
+        expect_cpu_power_down_wfi(|| {
+            let _ = psci.cpu_suspend(PowerState::PowerDown(0x3), ENTRY_POINT);
+        });
+        psci.handle_cpu_boot();
+        assert_eq!(psci.platform.retained_context_flushes(), 0);
+
+        expect_cpu_power_down_wfi(|| {
+            let _ = psci.cpu_suspend(PowerState::PowerDown(0x3333), ENTRY_POINT);
+        });
+        psci.handle_cpu_boot();
+        assert_eq!(psci.platform.retained_context_flushes(), 1);
+    }
+
+    #[test]
+    fn psci_cpu_suspend_vetoed_by_platform() {
+        let psci = Psci::<
+            PSCI_STATE_COUNT,
+            PSCI_MAX_POWER_LEVEL,
+            { TestPlatform::CORE_COUNT },
+            NON_CPU_DOMAIN_COUNT,
+            TestPlatform,
+            _,
+            _,
+        >::new(
+            TestPsciPlatformImpl::new(),
+            || &TestSpm,
+            stats_table(),
+            &PMF,
+        );
+
+        psci.platform.set_suspend_veto(Some(ErrorCode::Denied));
+        assert_eq!(
+            Err(ErrorCode::Denied),
+            psci.cpu_suspend(PowerState::PowerDown(0x3333), ENTRY_POINT)
+        );
+        assert_eq!(
+            Err(ErrorCode::Denied),
+            psci.cpu_suspend(PowerState::StandbyOrRetention(1), ENTRY_POINT)
+        );
+        // Nothing was changed in the power domain tree.
+        let cpu_index = CoresImpl::<TestPlatform>::core_index().try_into().unwrap();
+        assert_eq!(
+            psci.power_domain_tree
+                .locked_cpu_node(cpu_index)
+                .local_state(),
+            TestPowerState::On
+        );
+
+        psci.platform.set_suspend_veto(None);
+        expect_cpu_power_down_wfi(|| {
+            let _ = psci.cpu_suspend(PowerState::PowerDown(0x3333), ENTRY_POINT);
+        });
+    }
+
+    #[test]
+    fn psci_cpu_suspend_stats() {
+        let psci = Psci::<
+            PSCI_STATE_COUNT,
+            PSCI_MAX_POWER_LEVEL,
+            { TestPlatform::CORE_COUNT },
+            NON_CPU_DOMAIN_COUNT,
+            TestPlatform,
+            _,
+            _,
+        >::new(
+            TestPsciPlatformImpl::new(),
+            || &TestSpm,
+            stats_table(),
+            &PMF,
+        );
+        let _reset_sysregs = SysregsResetter;
+
+        assert_eq!(
+            Err(ErrorCode::InvalidParameters),
+            psci.cpu_suspend(PowerState::StandbyOrRetention(100), ENTRY_POINT)
+        );
+        assert_eq!(
+            Ok(()),
+            psci.cpu_suspend(PowerState::StandbyOrRetention(0), ENTRY_POINT)
+        );
+
+        // A power down request with an interrupt already pending returns without suspending.
+        SYSREGS.lock().unwrap().isr_el1 = IsrEl1::I;
+        assert_eq!(
+            Ok(()),
+            psci.cpu_suspend(PowerState::PowerDown(0x3333), ENTRY_POINT)
+        );
+
+        let stats = psci.suspend_stats();
+        assert_eq!(
+            stats.get(0),
+            Some((
+                PowerState::StandbyOrRetention(0).into(),
+                SuspendCounter {
+                    requested: 1,
+                    aborted: 0
+                }
+            ))
+        );
+        assert_eq!(
+            stats.get(1),
+            Some((
+                PowerState::PowerDown(0x3333).into(),
+                SuspendCounter {
+                    requested: 1,
+                    aborted: 1
+                }
+            ))
+        );
+        assert_eq!(stats.get(2), None);
+    }
+
+    #[test]
+    fn psci_stat_residency_and_count() {
+        let psci = Psci::<
+            PSCI_STATE_COUNT,
+            PSCI_MAX_POWER_LEVEL,
+            { TestPlatform::CORE_COUNT },
+            NON_CPU_DOMAIN_COUNT,
+            TestPlatform,
+            _,
+            _,
+        >::new(
+            TestPsciPlatformImpl::new(),
+            || &TestSpm,
+            stats_table(),
+            &PMF,
+        );
+        let _reset_sysregs = SysregsResetter;
+        let cpu_index = CoresImpl::<TestPlatform>::core_index();
+        let mpidr = mpidr_from_cpu_index(cpu_index);
+
+        // Put the core and its ancestors into standby for 2 ms.
+        {
+            let mut sysregs = SYSREGS.lock().unwrap();
+            sysregs.cntfrq_el0 = CntfrqEl0::from_bits_retain(1_000_000);
+            sysregs.cntpct_el0 = CntpctEl0::from_bits_retain(1000);
+        }
+        set_cpu_power_state_by_index(&psci.power_domain_tree, cpu_index, TestPowerState::Standby0);
+        SYSREGS.lock().unwrap().cntpct_el0 = CntpctEl0::from_bits_retain(3000);
+        set_cpu_power_state_by_index(&psci.power_domain_tree, cpu_index, TestPowerState::On);
+
+        // A standby which only affects the core is counted at the core level.
+        assert_eq!(
+            Ok(()),
+            psci.cpu_suspend(PowerState::StandbyOrRetention(0), ENTRY_POINT)
+        );
+
+        let core_stats = psci
+            .power_state_stats(mpidr, PowerState::StandbyOrRetention(0))
+            .unwrap();
+        assert_eq!(core_stats.count, 2);
+        assert_eq!(ticks_to_micros(core_stats.residency), 2000);
+
+        // The level 1 state of `StandbyOrRetention(1)` is `Standby0`.
+        let cluster_stats = psci
+            .power_state_stats(mpidr, PowerState::StandbyOrRetention(1))
+            .unwrap();
+        assert_eq!(cluster_stats.count, 1);
+        assert_eq!(ticks_to_micros(cluster_stats.residency), 2000);
+
+        assert_eq!(
+            psci.power_state_stats(mpidr, PowerState::PowerDown(0x3)),
+            Ok(PowerStateStats::default())
+        );
+        assert_eq!(
+            psci.power_state_stats(INVALID_MPIDR, PowerState::StandbyOrRetention(0)),
+            Err(ErrorCode::InvalidParameters)
+        );
+        assert_eq!(
+            psci.power_state_stats(mpidr, PowerState::StandbyOrRetention(100)),
+            Err(ErrorCode::InvalidParameters)
+        );
+    }
+
     #[test]
     fn psci_cpu_on() {
         let psci = Psci::<
@@ -2588,7 +3118,12 @@ mod tests {
             TestPlatform,
             _,
             _,
-        >::new(TestPsciPlatformImpl::new(), || &TestSpm);
+        >::new(
+            TestPsciPlatformImpl::new(),
+            || &TestSpm,
+            stats_table(),
+            &PMF,
+        );
         let _reset_sysregs = SysregsResetter;
 
         assert_eq!(
@@ -2625,7 +3160,12 @@ mod tests {
             TestPlatform,
             _,
             _,
-        >::new(TestPsciPlatformImpl::new(), || &TestSpm);
+        >::new(
+            TestPsciPlatformImpl::new(),
+            || &TestSpm,
+            stats_table(),
+            &PMF,
+        );
         let _reset_sysregs = SysregsResetter;
 
         assert_eq!(Ok(()), psci.cpu_on(mpidr_from_cpu_index(1), ENTRY_POINT));
@@ -2639,6 +3179,114 @@ mod tests {
         });
     }
 
+    #[test]
+    fn psci_cpu_on_during_cpu_off() {
+        let psci = Psci::<
+            PSCI_STATE_COUNT,
+            PSCI_MAX_POWER_LEVEL,
+            { TestPlatform::CORE_COUNT },
+            NON_CPU_DOMAIN_COUNT,
+            TestPlatform,
+            _,
+            _,
+        >::new(
+            TestPsciPlatformImpl::new(),
+            || &TestSpm,
+            stats_table(),
+            &PMF,
+        );
+        let _reset_sysregs = SysregsResetter;
+        let power_controller = psci.platform.power_controller();
+        power_controller.set_wakeup_latency(1, 2);
+
+        assert_eq!(Ok(()), psci.cpu_on(mpidr_from_cpu_index(1), ENTRY_POINT));
+        assert_eq!(
+            power_controller.core_state(1),
+            SimulatedCoreState::PendingOn { remaining: 2 }
+        );
+        power_controller.step();
+        power_controller.step();
+        assert_eq!(power_controller.core_state(1), SimulatedCoreState::On);
+
+        SYSREGS.lock().unwrap().mpidr_el1 =
+            MpidrEl1::from_psci_mpidr(mpidr_from_cpu_index(1).into());
+        psci.handle_cpu_boot();
+        expect_cpu_power_down_wfi(|| {
+            let _ = psci.cpu_off();
+        });
+
+        // CPU 1 has given up its PSCI state but hasn't been turned off by the power controller yet,
+        // when CPU 0 asks for it to be turned back on.
+        SYSREGS.lock().unwrap().mpidr_el1 =
+            MpidrEl1::from_psci_mpidr(mpidr_from_cpu_index(0).into());
+        assert_eq!(
+            power_controller.core_state(1),
+            SimulatedCoreState::PendingOff {
+                wake_requested: false
+            }
+        );
+        assert_eq!(
+            Ok(HwState::On),
+            psci.node_hw_state(mpidr_from_cpu_index(1), CPU_POWER_LEVEL as u32)
+        );
+        assert_eq!(Ok(()), psci.cpu_on(mpidr_from_cpu_index(1), ENTRY_POINT));
+        assert_eq!(
+            power_controller.core_state(1),
+            SimulatedCoreState::PendingOff {
+                wake_requested: true
+            }
+        );
+        assert_eq!(
+            Ok(AffinityInfo::OnPending),
+            psci.affinity_info(mpidr_from_cpu_index(1), CPU_POWER_LEVEL as u32)
+        );
+
+        // The power down completes, then the core starts powering back on.
+        power_controller.step();
+        assert_eq!(
+            power_controller.core_state(1),
+            SimulatedCoreState::PendingOn { remaining: 2 }
+        );
+        assert_eq!(
+            Ok(HwState::Off),
+            psci.node_hw_state(mpidr_from_cpu_index(1), CPU_POWER_LEVEL as u32)
+        );
+        power_controller.step();
+        power_controller.step();
+        assert_eq!(power_controller.core_state(1), SimulatedCoreState::On);
+
+        SYSREGS.lock().unwrap().mpidr_el1 =
+            MpidrEl1::from_psci_mpidr(mpidr_from_cpu_index(1).into());
+        assert_eq!(psci.handle_cpu_boot(), WakeUpReason::CpuOn(ENTRY_POINT));
+    }
+
+    #[test]
+    #[should_panic(expected = "Core booted before the power controller turned it on")]
+    fn psci_cpu_boot_before_power_on() {
+        let psci = Psci::<
+            PSCI_STATE_COUNT,
+            PSCI_MAX_POWER_LEVEL,
+            { TestPlatform::CORE_COUNT },
+            NON_CPU_DOMAIN_COUNT,
+            TestPlatform,
+            _,
+            _,
+        >::new(
+            TestPsciPlatformImpl::new(),
+            || &TestSpm,
+            stats_table(),
+            &PMF,
+        );
+        let _reset_sysregs = SysregsResetter;
+        psci.platform.power_controller().set_wakeup_latency(1, 1);
+
+        assert_eq!(Ok(()), psci.cpu_on(mpidr_from_cpu_index(1), ENTRY_POINT));
+
+        SYSREGS.lock().unwrap().mpidr_el1 =
+            MpidrEl1::from_psci_mpidr(mpidr_from_cpu_index(1).into());
+        psci.handle_cpu_boot();
+    }
+
     #[test]
     fn psci_affinity_info() {
         let psci = Psci::<
@@ -2649,7 +3297,12 @@ mod tests {
             TestPlatform,
             _,
             _,
-        >::new(TestPsciPlatformImpl::new(), || &TestSpm);
+        >::new(
+            TestPsciPlatformImpl::new(),
+            || &TestSpm,
+            stats_table(),
+            &PMF,
+        );
         let _reset_sysregs = SysregsResetter;
 
         assert_eq!(
@@ -2733,7 +3386,12 @@ mod tests {
             (1, 1, 3),
         ];
 
-        let psci = Psci::new(TestPsciPlatformImpl::new(), || &TestSpm);
+        let psci = Psci::new(
+            TestPsciPlatformImpl::new(),
+            || &TestSpm,
+            stats_table(),
+            &PMF,
+        );
         let _reset_sysregs = SysregsResetter;
 
         assert_eq!(
@@ -2982,7 +3640,12 @@ mod tests {
 
     #[test]
     fn psci_cpu_suspend_osi_single_core_mixed_with_offline_cores() {
-        let psci = Psci::new(TestPsciPlatformImpl::new(), || &TestSpm);
+        let psci = Psci::new(
+            TestPsciPlatformImpl::new(),
+            || &TestSpm,
+            stats_table(),
+            &PMF,
+        );
         assert_eq!(psci.set_suspend_mode(SuspendMode::OsInitiated), Ok(0));
 
         expect_cpu_power_down_wfi(|| {
@@ -3302,7 +3965,12 @@ mod tests {
 
     #[test]
     fn psci_cpu_suspend_osi_with_non_cpu_running() {
-        let psci = Psci::new(TestPsciPlatformImpl::new(), || &TestSpm);
+        let psci = Psci::new(
+            TestPsciPlatformImpl::new(),
+            || &TestSpm,
+            stats_table(),
+            &PMF,
+        );
         assert_eq!(psci.set_suspend_mode(SuspendMode::OsInitiated), Ok(0));
 
         // Cluster 0 CPU 0
@@ -3357,7 +4025,12 @@ mod tests {
 
     #[test]
     fn psci_cpu_suspend_osi_with_non_cpu_running_mixed_cpu_off() {
-        let psci = Psci::new(TestPsciPlatformImpl::new(), || &TestSpm);
+        let psci = Psci::new(
+            TestPsciPlatformImpl::new(),
+            || &TestSpm,
+            stats_table(),
+            &PMF,
+        );
         assert_eq!(psci.set_suspend_mode(SuspendMode::OsInitiated), Ok(0));
 
         // Cluster 0 CPU 0
@@ -3428,9 +4101,24 @@ mod tests {
             TestPlatform,
             _,
             _,
-        >::new(TestPsciPlatformImpl::new(), || &TestSpm);
+        >::new(
+            TestPsciPlatformImpl::new(),
+            || &TestSpm,
+            stats_table(),
+            &PMF,
+        );
+
+        static SCRUBBED: AtomicBool = AtomicBool::new(false);
+        struct TestSecret;
+        impl Secret for TestSecret {
+            fn scrub(&self) {
+                SCRUBBED.store(true, Ordering::SeqCst);
+            }
+        }
+        psci.secrets().register(&TestSecret);
 
         expect_cpu_power_down(TestPsciPlatformImpl::SYSTEM_OFF_MAGIC, || psci.system_off());
+        assert!(SCRUBBED.load(Ordering::SeqCst));
     }
 
     #[test]
@@ -3443,7 +4131,12 @@ mod tests {
             TestPlatform,
             _,
             _,
-        >::new(TestPsciPlatformImpl::new(), || &TestSpm);
+        >::new(
+            TestPsciPlatformImpl::new(),
+            || &TestSpm,
+            stats_table(),
+            &PMF,
+        );
 
         let off_type = SystemOff2Type::HibernateOff;
         let cookie = Cookie::Cookie64(0);
@@ -3455,9 +4148,33 @@ mod tests {
             cookie
         );
 
+        static SCRUBBED: AtomicBool = AtomicBool::new(false);
+        static KEY_SCRUBBED: AtomicBool = AtomicBool::new(false);
+        struct TestSecret;
+        impl Secret for TestSecret {
+            fn scrub(&self) {
+                SCRUBBED.store(true, Ordering::SeqCst);
+            }
+        }
+        struct TestKey;
+        impl Secret for TestKey {
+            fn scrub(&self) {
+                KEY_SCRUBBED.store(true, Ordering::SeqCst);
+            }
+
+            fn is_replaceable(&self) -> bool {
+                false
+            }
+        }
+        psci.secrets().register(&TestSecret);
+        psci.secrets().register(&TestKey);
+
         expect_cpu_power_down(magic.as_str(), || {
             let _ = psci.system_off2(off_type, cookie);
         });
+        // SYSTEM_OFF2 may fail, so secrets which can't be replaced must be kept.
+        assert!(SCRUBBED.load(Ordering::SeqCst));
+        assert!(!KEY_SCRUBBED.load(Ordering::SeqCst));
     }
 
     #[test]
@@ -3470,7 +4187,12 @@ mod tests {
             TestPlatform,
             _,
             _,
-        >::new(TestPsciPlatformImpl::new(), || &TestSpm);
+        >::new(
+            TestPsciPlatformImpl::new(),
+            || &TestSpm,
+            stats_table(),
+            &PMF,
+        );
 
         expect_cpu_power_down(TestPsciPlatformImpl::SYSTEM_RESET_MAGIC, || {
             psci.system_reset()
@@ -3487,7 +4209,12 @@ mod tests {
             TestPlatform,
             _,
             _,
-        >::new(TestPsciPlatformImpl::new(), || &TestSpm);
+        >::new(
+            TestPsciPlatformImpl::new(),
+            || &TestSpm,
+            stats_table(),
+            &PMF,
+        );
 
         expect_cpu_power_down(TestPsciPlatformImpl::SYSTEM_RESET2_MAGIC, || {
             let _ = psci.system_reset2(
@@ -3497,6 +4224,70 @@ mod tests {
         });
     }
 
+    #[test]
+    fn psci_system_reset2_vendor() {
+        let psci = Psci::<
+            PSCI_STATE_COUNT,
+            PSCI_MAX_POWER_LEVEL,
+            { TestPlatform::CORE_COUNT },
+            NON_CPU_DOMAIN_COUNT,
+            TestPlatform,
+            _,
+            _,
+        >::new(
+            TestPsciPlatformImpl::new(),
+            || &TestSpm,
+            stats_table(),
+            &PMF,
+        );
+        let recovery = ResetType::VendorSpecific(TestPlatformService::RECOVERY_RESET_TYPE);
+
+        // Unregistered vendor reset type.
+        assert_eq!(
+            psci.system_reset2(ResetType::VendorSpecific(0x1234), Cookie::Cookie64(0)),
+            Err(ErrorCode::InvalidParameters)
+        );
+        // Cookie rejected by the registered reset type.
+        assert_eq!(
+            psci.system_reset2(recovery, Cookie::Cookie64(1)),
+            Err(ErrorCode::InvalidParameters)
+        );
+
+        expect_cpu_power_down(TestPsciPlatformImpl::SYSTEM_RESET2_MAGIC, || {
+            let _ = psci.system_reset2(recovery, Cookie::Cookie32(0));
+        });
+    }
+
+    #[test]
+    fn psci_system_reset2_firmware_update() {
+        let psci = Psci::<
+            PSCI_STATE_COUNT,
+            PSCI_MAX_POWER_LEVEL,
+            { TestPlatform::CORE_COUNT },
+            NON_CPU_DOMAIN_COUNT,
+            TestPlatform,
+            _,
+            _,
+        >::new(
+            TestPsciPlatformImpl::new(),
+            || &TestSpm,
+            stats_table(),
+            &PMF,
+        );
+
+        // The request is recorded, then the system is reset with a normal cold reset.
+        expect_cpu_power_down(TestPsciPlatformImpl::SYSTEM_RESET_MAGIC, || {
+            let _ = psci.system_reset2(
+                ResetType::VendorSpecific(TestPlatformService::FIRMWARE_UPDATE_RESET_TYPE),
+                Cookie::Cookie64(0x1234),
+            );
+        });
+        assert_eq!(
+            TEST_BOOT_REQUEST.load(Ordering::SeqCst),
+            BootRequest::FirmwareUpdate as u32
+        );
+    }
+
     #[test]
     fn psci_mem_protect() {
         let psci = Psci::<
@@ -3507,7 +4298,12 @@ mod tests {
             TestPlatform,
             _,
             _,
-        >::new(TestPsciPlatformImpl::new(), || &TestSpm);
+        >::new(
+            TestPsciPlatformImpl::new(),
+            || &TestSpm,
+            stats_table(),
+            &PMF,
+        );
 
         assert_eq!(Ok(true), psci.mem_protect(true));
         assert_eq!(
@@ -3526,7 +4322,12 @@ mod tests {
             TestPlatform,
             _,
             _,
-        >::new(TestPsciPlatformImpl::new(), || &TestSpm);
+        >::new(
+            TestPsciPlatformImpl::new(),
+            || &TestSpm,
+            stats_table(),
+            &PMF,
+        );
 
         let supported_functions = [
             FunctionId::PsciVersion,
@@ -3551,6 +4352,10 @@ mod tests {
             FunctionId::NodeHwState64,
             FunctionId::SystemSuspend32,
             FunctionId::SystemSuspend64,
+            FunctionId::PsciStatResidency32,
+            FunctionId::PsciStatResidency64,
+            FunctionId::PsciStatCount32,
+            FunctionId::PsciStatCount64,
         ];
 
         let not_supported_functions = [
@@ -3558,10 +4363,6 @@ mod tests {
             FunctionId::Migrate64,
             FunctionId::MigrateInfoUpCpu32,
             FunctionId::MigrateInfoUpCpu64,
-            FunctionId::PsciStatResidency32,
-            FunctionId::PsciStatResidency64,
-            FunctionId::PsciStatCount32,
-            FunctionId::PsciStatCount64,
         ];
 
         assert_eq!(Ok(0), psci.handle_features(PsciFeature::SmcccVersion));
@@ -3610,7 +4411,12 @@ mod tests {
             TestPlatform,
             _,
             _,
-        >::new(TestPsciPlatformImpl::new(), || &TestSpm);
+        >::new(
+            TestPsciPlatformImpl::new(),
+            || &TestSpm,
+            stats_table(),
+            &PMF,
+        );
         expect_cpu_power_down(TestPsciPlatformImpl::CPU_FREEZE_MAGIC, || {
             let _ = psci.cpu_freeze();
         });
@@ -3626,7 +4432,12 @@ mod tests {
             TestPlatform,
             _,
             _,
-        >::new(TestPsciPlatformImpl::new(), || &TestSpm);
+        >::new(
+            TestPsciPlatformImpl::new(),
+            || &TestSpm,
+            stats_table(),
+            &PMF,
+        );
         assert_eq!(Ok(()), psci.cpu_default_suspend(ENTRY_POINT));
     }
 
@@ -3640,7 +4451,12 @@ mod tests {
             TestPlatform,
             _,
             _,
-        >::new(TestPsciPlatformImpl::new(), || &TestSpm);
+        >::new(
+            TestPsciPlatformImpl::new(),
+            || &TestSpm,
+            stats_table(),
+            &PMF,
+        );
 
         assert_eq!(
             Err(ErrorCode::InvalidParameters),
@@ -3668,7 +4484,12 @@ mod tests {
             TestPlatform,
             _,
             _,
-        >::new(TestPsciPlatformImpl::new(), || &TestSpm);
+        >::new(
+            TestPsciPlatformImpl::new(),
+            || &TestSpm,
+            stats_table(),
+            &PMF,
+        );
 
         expect_cpu_power_down_wfi(|| {
             let _ = psci.system_suspend(ENTRY_POINT);
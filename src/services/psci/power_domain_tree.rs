@@ -5,14 +5,141 @@
 //! Collection of structures for describing the power domain tree.
 
 use super::{CPU_POWER_LEVEL, NodeIndexInterface, PlatformPowerStateInterface};
+use crate::{
+    debug::DEBUG,
+    spin_mutex::{SpinMutex, SpinMutexGuard},
+};
 use arm_psci::{AffinityInfo, EntryPoint};
+use arm_sysregs::read_cntpct_el0;
 use arrayvec::ArrayVec;
 use core::{
     fmt::{self, Debug, Formatter},
-    ops::Range,
+    marker::PhantomData,
+    ops::{Deref, DerefMut, Range},
     slice::{Iter, IterMut},
+    sync::atomic::{AtomicU8, Ordering},
 };
-use spin::mutex::{SpinMutex, SpinMutexGuard};
+use log::warn;
+use percore::Cores;
+
+/// The maximum number of distinct non-running local power states of each power domain for which
+/// statistics are kept.
+pub const STAT_MAX_STATES: usize = 4;
+
+/// Statistics about a local power state of a power domain.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct PowerStateStats {
+    /// The total number of generic timer ticks the power domain has spent in the state.
+    pub residency: u64,
+    /// The number of times the power domain has entered and then left the state.
+    pub count: u64,
+}
+
+/// Residency statistics for the non-running local power states of a power domain.
+///
+/// A period in a state is only counted once the power domain leaves it again.
+#[derive(Debug)]
+pub struct PowerDomainStats<PlatformPowerState: PlatformPowerStateInterface> {
+    /// The non-running state the power domain is in, and the generic timer count when it entered
+    /// it.
+    current: Option<(PlatformPowerState, u64)>,
+    states: ArrayVec<(PlatformPowerState, PowerStateStats), STAT_MAX_STATES>,
+}
+
+impl<PlatformPowerState: PlatformPowerStateInterface> PowerDomainStats<PlatformPowerState> {
+    const fn new() -> Self {
+        Self {
+            current: None,
+            states: ArrayVec::new_const(),
+        }
+    }
+
+    /// Records that the power domain changed to `new_state` at generic timer count `now`.
+    fn transition(&mut self, new_state: PlatformPowerState, now: u64) {
+        if let Some((state, entered)) = self.current {
+            if state == new_state {
+                return;
+            }
+            self.current = None;
+
+            let residency = now.wrapping_sub(entered);
+            if let Some((_, stats)) = self.states.iter_mut().find(|(s, _)| *s == state) {
+                stats.residency += residency;
+                stats.count += 1;
+            } else if self
+                .states
+                .try_push((
+                    state,
+                    PowerStateStats {
+                        residency,
+                        count: 1,
+                    },
+                ))
+                .is_err()
+            {
+                warn!("Too many power states to keep statistics, dropping {state:?}");
+            }
+        }
+
+        if new_state != PlatformPowerState::RUN {
+            self.current = Some((new_state, now));
+        }
+    }
+
+    /// Returns the statistics for the given local power state.
+    pub fn get(&self, state: PlatformPowerState) -> PowerStateStats {
+        self.states
+            .iter()
+            .find(|(s, _)| *s == state)
+            .map(|(_, stats)| *stats)
+            .unwrap_or_default()
+    }
+}
+
+/// Residency statistics for every power domain in the power domain tree.
+///
+/// This is kept outside the tree so that it doesn't need to fit on the stack while the PSCI service
+/// is constructed.
+#[derive(Debug)]
+pub struct PowerDomainStatsTable<
+    const CPU_DOMAIN_COUNT: usize,
+    const NON_CPU_DOMAIN_COUNT: usize,
+    PlatformPowerState: PlatformPowerStateInterface,
+> {
+    cpus: [SpinMutex<PowerDomainStats<PlatformPowerState>>; CPU_DOMAIN_COUNT],
+    non_cpus: [SpinMutex<PowerDomainStats<PlatformPowerState>>; NON_CPU_DOMAIN_COUNT],
+}
+
+impl<
+    const CPU_DOMAIN_COUNT: usize,
+    const NON_CPU_DOMAIN_COUNT: usize,
+    PlatformPowerState: PlatformPowerStateInterface,
+> PowerDomainStatsTable<CPU_DOMAIN_COUNT, NON_CPU_DOMAIN_COUNT, PlatformPowerState>
+{
+    /// Creates a table with no statistics recorded.
+    pub const fn new() -> Self {
+        Self {
+            cpus: [const { SpinMutex::new(PowerDomainStats::new()) }; CPU_DOMAIN_COUNT],
+            non_cpus: [const { SpinMutex::new(PowerDomainStats::new()) }; NON_CPU_DOMAIN_COUNT],
+        }
+    }
+}
+
+impl<
+    const CPU_DOMAIN_COUNT: usize,
+    const NON_CPU_DOMAIN_COUNT: usize,
+    PlatformPowerState: PlatformPowerStateInterface,
+> Default for PowerDomainStatsTable<CPU_DOMAIN_COUNT, NON_CPU_DOMAIN_COUNT, PlatformPowerState>
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Returns the current generic timer count, for timestamping power state transitions.
+fn now() -> u64 {
+    read_cntpct_el0().physicalcount()
+}
 
 /// Represents a non-CPU power domain node in the power domain tree.
 #[derive(Debug)]
@@ -43,6 +170,8 @@ pub struct NonCpuPowerNode<
     suspend_states: ArrayVec<Option<PlatformPowerState>, CPU_DOMAIN_COUNT>,
     /// Copy of the direct descendant non-CPU node states.
     non_cpu_states: ArrayVec<PlatformPowerState, NON_CPU_DOMAIN_COUNT>,
+    /// Residency statistics of the node's local power states.
+    stats: &'static SpinMutex<PowerDomainStats<PlatformPowerState>>,
     // OPTIMIZE: The worst case memory usage of requested_states on all NonCpuPowerNode happens
     // when the power domain tree is a complete binary tree. In this case the memory usage is
     // n^2 + n where n is CPU_DOMAIN_COUNT. The optimal case would be n * log2(n) if using Vec of
@@ -56,8 +185,12 @@ impl<
     PlatformPowerState: PlatformPowerStateInterface,
 > NonCpuPowerNode<CPU_DOMAIN_COUNT, NON_CPU_DOMAIN_COUNT, NodeIndex, PlatformPowerState>
 {
-    /// Create new non-CPU power node and assign its parent node index.
-    pub fn new(parent: Option<NodeIndex>) -> Self {
+    /// Create new non-CPU power node and assign its parent node index, and where to record its
+    /// residency statistics.
+    pub fn new(
+        parent: Option<NodeIndex>,
+        stats: &'static SpinMutex<PowerDomainStats<PlatformPowerState>>,
+    ) -> Self {
         Self {
             parent,
             local_state: PlatformPowerState::OFF,
@@ -66,6 +199,7 @@ impl<
             requested_states: ArrayVec::new(),
             suspend_states: ArrayVec::new(),
             non_cpu_states: ArrayVec::new(),
+            stats,
         }
     }
 
@@ -181,7 +315,7 @@ impl<
     /// Smaller power state values represent shallower power states, therefore, it should be set to
     /// the minimal requested power state.
     pub fn set_minimal_allowed_state(&mut self) {
-        self.local_state = *self.requested_states.iter().min().unwrap();
+        self.set_local_state(*self.requested_states.iter().min().unwrap());
     }
 
     /// Get local power state of the node.
@@ -191,6 +325,7 @@ impl<
 
     /// Set local power state of the node.
     pub fn set_local_state(&mut self, local_state: PlatformPowerState) {
+        self.stats.lock().transition(local_state, now());
         self.local_state = local_state;
     }
 
@@ -227,17 +362,23 @@ pub struct CpuPowerNode<
     local_state: PlatformPowerState,
     /// Non-secure entry point of the CPU on waking up
     entry_point: Option<EntryPoint>,
+    /// Residency statistics of the CPU's local power states.
+    stats: &'static SpinMutex<PowerDomainStats<PlatformPowerState>>,
 }
 
 impl<NodeIndex: NodeIndexInterface, PlatformPowerState: PlatformPowerStateInterface>
     CpuPowerNode<NodeIndex, PlatformPowerState>
 {
-    pub fn new(parent: NodeIndex) -> Self {
+    pub fn new(
+        parent: NodeIndex,
+        stats: &'static SpinMutex<PowerDomainStats<PlatformPowerState>>,
+    ) -> Self {
         Self {
             parent,
             affinity_info: AffinityInfo::Off,
             local_state: PlatformPowerState::OFF,
             entry_point: None,
+            stats,
         }
     }
 
@@ -258,6 +399,7 @@ impl<NodeIndex: NodeIndexInterface, PlatformPowerState: PlatformPowerStateInterf
 
     /// Set local state of the CPU.
     pub fn set_local_state(&mut self, local_state: PlatformPowerState) {
+        self.stats.lock().transition(local_state, now());
         self.local_state = local_state;
     }
 
@@ -273,6 +415,61 @@ impl<NodeIndex: NodeIndexInterface, PlatformPowerState: PlatformPowerStateInterf
     }
 }
 
+/// A lock guard for a CPU power node, which releases the lock when dropped.
+///
+/// In debug builds the guard is counted towards the locks held by the core which acquired it, see
+/// [`PowerDomainTree::assert_no_locks_held`].
+#[derive(Debug)]
+pub struct CpuNodeGuard<
+    'a,
+    NodeIndex: NodeIndexInterface,
+    PlatformPowerState: PlatformPowerStateInterface,
+> {
+    guard: SpinMutexGuard<'a, CpuPowerNode<NodeIndex, PlatformPowerState>>,
+    held_locks: Option<&'a AtomicU8>,
+}
+
+impl<NodeIndex: NodeIndexInterface, PlatformPowerState: PlatformPowerStateInterface>
+    CpuNodeGuard<'_, NodeIndex, PlatformPowerState>
+{
+    /// Releases the lock before entering a path which may not return, such as powering down the
+    /// core or panicking.
+    ///
+    /// This is the same as dropping the guard, but makes it explicit at the call site that nothing
+    /// may be locked beyond this point.
+    pub fn unlock_before_noreturn(self) {
+        drop(self);
+    }
+}
+
+impl<NodeIndex: NodeIndexInterface, PlatformPowerState: PlatformPowerStateInterface> Deref
+    for CpuNodeGuard<'_, NodeIndex, PlatformPowerState>
+{
+    type Target = CpuPowerNode<NodeIndex, PlatformPowerState>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.guard
+    }
+}
+
+impl<NodeIndex: NodeIndexInterface, PlatformPowerState: PlatformPowerStateInterface> DerefMut
+    for CpuNodeGuard<'_, NodeIndex, PlatformPowerState>
+{
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.guard
+    }
+}
+
+impl<NodeIndex: NodeIndexInterface, PlatformPowerState: PlatformPowerStateInterface> Drop
+    for CpuNodeGuard<'_, NodeIndex, PlatformPowerState>
+{
+    fn drop(&mut self) {
+        if let Some(held_locks) = self.held_locks {
+            held_locks.fetch_sub(1, Ordering::Relaxed);
+        }
+    }
+}
+
 /// Object for locking multiple non-CPU power nodes. In order to avoid deadlocks and race
 /// conditions the non-CPU power nodes are always locked from the lower level to higher.
 #[derive(Debug)]
@@ -292,6 +489,7 @@ pub struct AncestorPowerDomains<
         MAX_POWER_LEVEL,
     >,
     indices: ArrayVec<NodeIndex, MAX_POWER_LEVEL>,
+    held_locks: Option<&'a AtomicU8>,
 }
 
 impl<
@@ -311,13 +509,15 @@ impl<
         PlatformPowerState,
     >
 {
-    /// Lock the selected node and its ancestors up to `max_level`.
+    /// Lock the selected node and its ancestors up to `max_level`, counting them in `held_locks` if
+    /// given.
     pub fn new_with_max_level(
         index: NodeIndex,
         max_level: usize,
         mutexes: &'a [SpinMutex<
             NonCpuPowerNode<CPU_DOMAIN_COUNT, NON_CPU_DOMAIN_COUNT, NodeIndex, PlatformPowerState>,
         >],
+        held_locks: Option<&'a AtomicU8>,
     ) -> Self {
         let mut list = ArrayVec::new();
         let mut indices = ArrayVec::new();
@@ -337,7 +537,15 @@ impl<
             level += 1;
         }
 
-        Self { list, indices }
+        if let Some(held_locks) = held_locks {
+            held_locks.fetch_add(list.len() as u8, Ordering::Relaxed);
+        }
+
+        Self {
+            list,
+            indices,
+            held_locks,
+        }
     }
 
     /// Creates immutable iterator starting from the lowest level.
@@ -464,12 +672,39 @@ impl<
     >
 {
     fn drop(&mut self) {
+        if let Some(held_locks) = self.held_locks {
+            held_locks.fetch_sub(self.list.len() as u8, Ordering::Relaxed);
+        }
         while let Some(guard) = self.list.pop() {
             drop(guard);
         }
     }
 }
 
+/// Identifies the current core for tracking the power domain locks it holds.
+pub trait LockTracking {
+    /// Returns the index of the current core, or `None` if locks aren't tracked.
+    fn current_core() -> Option<usize>;
+}
+
+/// Doesn't track which locks are held.
+pub struct NoLockTracking;
+
+impl LockTracking for NoLockTracking {
+    fn current_core() -> Option<usize> {
+        None
+    }
+}
+
+/// Tracks the locks held by each core, using `C` to find the current core.
+pub struct CoreLockTracking<C: Cores>(PhantomData<C>);
+
+impl<C: Cores> LockTracking for CoreLockTracking<C> {
+    fn current_core() -> Option<usize> {
+        Some(C::core_index())
+    }
+}
+
 /// The PowerDomainTree is responsible for storing the non-CPU and CPU power nodes and providing
 /// safe ways to access for them.
 ///
@@ -482,6 +717,7 @@ pub struct PowerDomainTree<
     const MAX_POWER_LEVEL: usize,
     NodeIndex: NodeIndexInterface,
     PlatformPowerState: PlatformPowerStateInterface,
+    Tracking: LockTracking = NoLockTracking,
 > {
     non_cpu_power_nodes: ArrayVec<
         SpinMutex<
@@ -491,6 +727,10 @@ pub struct PowerDomainTree<
     >,
     cpu_power_nodes:
         ArrayVec<SpinMutex<CpuPowerNode<NodeIndex, PlatformPowerState>>, CPU_DOMAIN_COUNT>,
+    /// The number of node locks currently held by each core, only maintained in debug builds with
+    /// lock tracking enabled.
+    held_locks: [AtomicU8; CPU_DOMAIN_COUNT],
+    _lock_tracking: PhantomData<Tracking>,
 }
 
 impl<
@@ -506,10 +746,19 @@ impl<
         MAX_POWER_LEVEL,
         NodeIndex,
         PlatformPowerState,
+        NoLockTracking,
     >
 {
-    /// Create power domain tree based on the BFS format topology description.
-    pub fn new(topology: &[usize]) -> Self {
+    /// Create power domain tree based on the BFS format topology description, recording the
+    /// residency statistics of its nodes in `stats`.
+    pub fn new(
+        topology: &[usize],
+        stats: &'static PowerDomainStatsTable<
+            CPU_DOMAIN_COUNT,
+            NON_CPU_DOMAIN_COUNT,
+            PlatformPowerState,
+        >,
+    ) -> Self {
         // Validate `NodeIndex` to be able to store `CPU_DOMAIN_COUNT` and `NON_CPU_DOMAIN_COUNT`.
         const {
             assert!(<NodeIndex as NodeIndexInterface>::MAX >= CPU_DOMAIN_COUNT);
@@ -540,7 +789,10 @@ impl<
                 let child_count = topology[parent_node_index.into()];
 
                 for index in (&mut node_index).take(child_count) {
-                    non_cpu_power_nodes.push(SpinMutex::new(NonCpuPowerNode::new(parent_node)));
+                    non_cpu_power_nodes.push(SpinMutex::new(NonCpuPowerNode::new(
+                        parent_node,
+                        &stats.non_cpus[index],
+                    )));
 
                     if let Some(parent_index) = parent_node {
                         non_cpu_power_nodes[parent_index.into()]
@@ -567,6 +819,7 @@ impl<
             for cpu_index in (&mut node_index).take(*num_children) {
                 cpu_power_nodes.push(SpinMutex::new(CpuPowerNode::new(
                     parent_node_index - 1.into(),
+                    &stats.cpus[cpu_index],
                 )));
                 Self::assign_cpu(
                     &non_cpu_power_nodes,
@@ -584,6 +837,69 @@ impl<
         PowerDomainTree {
             non_cpu_power_nodes,
             cpu_power_nodes,
+            held_locks: [const { AtomicU8::new(0) }; CPU_DOMAIN_COUNT],
+            _lock_tracking: PhantomData,
+        }
+    }
+
+    /// Enables tracking of the locks held by each core in debug builds, using `C` to find the index
+    /// of the core taking a lock.
+    pub fn with_lock_tracking<C: Cores>(
+        self,
+    ) -> PowerDomainTree<
+        CPU_DOMAIN_COUNT,
+        NON_CPU_DOMAIN_COUNT,
+        MAX_POWER_LEVEL,
+        NodeIndex,
+        PlatformPowerState,
+        CoreLockTracking<C>,
+    > {
+        PowerDomainTree {
+            non_cpu_power_nodes: self.non_cpu_power_nodes,
+            cpu_power_nodes: self.cpu_power_nodes,
+            held_locks: self.held_locks,
+            _lock_tracking: PhantomData,
+        }
+    }
+}
+
+impl<
+    const CPU_DOMAIN_COUNT: usize,
+    const NON_CPU_DOMAIN_COUNT: usize,
+    const MAX_POWER_LEVEL: usize,
+    NodeIndex: NodeIndexInterface,
+    PlatformPowerState: PlatformPowerStateInterface,
+    Tracking: LockTracking,
+>
+    PowerDomainTree<
+        CPU_DOMAIN_COUNT,
+        NON_CPU_DOMAIN_COUNT,
+        MAX_POWER_LEVEL,
+        NodeIndex,
+        PlatformPowerState,
+        Tracking,
+    >
+{
+    /// Returns the counter of locks held by the current core, if lock tracking is enabled.
+    fn current_held_locks(&self) -> Option<&AtomicU8> {
+        if DEBUG {
+            Tracking::current_core().map(|core_index| &self.held_locks[core_index])
+        } else {
+            None
+        }
+    }
+
+    /// Panics if lock tracking is enabled and the current core holds any locks in the tree.
+    ///
+    /// This should be checked before anything which may not come back to release them, such as
+    /// entering WFI to power down or returning to a lower EL.
+    pub fn assert_no_locks_held(&self) {
+        if let Some(held_locks) = self.current_held_locks() {
+            let count = held_locks.load(Ordering::Relaxed);
+            assert_eq!(
+                count, 0,
+                "{count} power domain locks held by the current core"
+            );
         }
     }
 
@@ -620,8 +936,13 @@ impl<
     pub fn locked_cpu_node(
         &self,
         cpu_index: NodeIndex,
-    ) -> SpinMutexGuard<'_, CpuPowerNode<NodeIndex, PlatformPowerState>> {
-        self.cpu_power_nodes[cpu_index.into()].lock()
+    ) -> CpuNodeGuard<'_, NodeIndex, PlatformPowerState> {
+        let guard = self.cpu_power_nodes[cpu_index.into()].lock();
+        let held_locks = self.current_held_locks();
+        if let Some(held_locks) = held_locks {
+            held_locks.fetch_add(1, Ordering::Relaxed);
+        }
+        CpuNodeGuard { guard, held_locks }
     }
 
     /// Locks all ancestor nodes of a CPU, runs the closure and unlocks the nodes.
@@ -676,6 +997,7 @@ impl<
             cpu.parent,
             max_level,
             &self.non_cpu_power_nodes,
+            self.current_held_locks(),
         );
         f(cpu, lock_list)
     }
@@ -686,6 +1008,28 @@ impl<
             .iter()
             .all(|core| core.lock().affinity_info() == AffinityInfo::On)
     }
+
+    /// Returns the statistics for the given local power state of the power domain at
+    /// `power_level` which contains the given CPU, or `None` if there is no such level.
+    pub fn power_state_stats(
+        &self,
+        cpu_index: NodeIndex,
+        power_level: usize,
+        state: PlatformPowerState,
+    ) -> Option<PowerStateStats> {
+        let cpu = self.locked_cpu_node(cpu_index);
+        if power_level == CPU_POWER_LEVEL {
+            return Some(cpu.stats.lock().get(state));
+        }
+        let mut index = cpu.parent;
+        drop(cpu);
+
+        for _ in CPU_POWER_LEVEL + 1..power_level {
+            index = self.non_cpu_power_nodes[index.into()].lock().parent?;
+        }
+        let stats = self.non_cpu_power_nodes[index.into()].lock().stats;
+        Some(stats.lock().get(state))
+    }
 }
 
 impl<
@@ -694,6 +1038,7 @@ impl<
     const MAX_POWER_LEVEL: usize,
     NodeIndex: NodeIndexInterface,
     PlatformPowerState: PlatformPowerStateInterface,
+    Tracking: LockTracking,
 > Debug
     for PowerDomainTree<
         CPU_DOMAIN_COUNT,
@@ -701,37 +1046,109 @@ impl<
         MAX_POWER_LEVEL,
         NodeIndex,
         PlatformPowerState,
+        Tracking,
     >
 {
-    /// Outputs the tree in Graphviz DOT format.
+    /// Outputs the tree indented by power level, with the state of each node.
+    ///
+    /// All the nodes are locked while the tree is written, so that it is consistent. Nodes which
+    /// are already locked, such as by a core stuck in the middle of a power state transition, can't
+    /// be read so are listed separately after the tree.
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
-        writeln!(f, "digraph {{")?;
-        for (index, ncpu) in self.non_cpu_power_nodes.iter().enumerate() {
-            if let Some(nc) = ncpu.try_lock() {
-                writeln!(f, "NC{index} [label=\"{nc:#?}\"]")?;
-                if let Some(parent) = nc.parent {
-                    writeln!(f, "NC{parent} -> NC{index}")?;
-                }
-            } else {
-                writeln!(f, "C{index} [label=\"NonCpuPowerNode is locked\"]")?;
-            }
-        }
+        let non_cpu_nodes = self
+            .non_cpu_power_nodes
+            .iter()
+            .map(SpinMutex::try_lock)
+            .collect::<ArrayVec<_, NON_CPU_DOMAIN_COUNT>>();
+        let cpu_nodes = self
+            .cpu_power_nodes
+            .iter()
+            .map(SpinMutex::try_lock)
+            .collect::<ArrayVec<_, CPU_DOMAIN_COUNT>>();
 
-        for (index, cpu) in self.cpu_power_nodes.iter().enumerate() {
-            if let Some(c) = cpu.try_lock() {
-                writeln!(f, "C{index} [label=\"{c:#?}\"]")?;
-                writeln!(f, "NC{} -> C{}", c.parent, index)?;
-            } else {
-                writeln!(f, "C{index} [label=\"CpuPowerNode is locked\"]")?;
+        for (index, node) in non_cpu_nodes.iter().enumerate() {
+            if node.as_ref().is_some_and(|node| node.parent.is_none()) {
+                fmt_non_cpu_node(f, &non_cpu_nodes, &cpu_nodes, index, MAX_POWER_LEVEL, 0)?;
             }
         }
 
-        writeln!(f, "}}")?;
+        for (index, _) in non_cpu_nodes
+            .iter()
+            .enumerate()
+            .filter(|(_, node)| node.is_none())
+        {
+            writeln!(f, "Node {index}: locked")?;
+        }
+        for (index, _) in cpu_nodes
+            .iter()
+            .enumerate()
+            .filter(|(_, node)| node.is_none())
+        {
+            writeln!(f, "CPU {index}: locked")?;
+        }
 
         Ok(())
     }
 }
 
+/// Writes the non-CPU node at `index` at `power_level` and all of its descendants which aren't
+/// locked, indented according to their `depth` in the tree.
+fn fmt_non_cpu_node<
+    const CPU_DOMAIN_COUNT: usize,
+    const NON_CPU_DOMAIN_COUNT: usize,
+    NodeIndex: NodeIndexInterface,
+    PlatformPowerState: PlatformPowerStateInterface,
+>(
+    f: &mut Formatter,
+    non_cpu_nodes: &[Option<
+        SpinMutexGuard<
+            NonCpuPowerNode<CPU_DOMAIN_COUNT, NON_CPU_DOMAIN_COUNT, NodeIndex, PlatformPowerState>,
+        >,
+    >],
+    cpu_nodes: &[Option<SpinMutexGuard<CpuPowerNode<NodeIndex, PlatformPowerState>>>],
+    index: usize,
+    power_level: usize,
+    depth: usize,
+) -> fmt::Result {
+    let Some(node) = &non_cpu_nodes[index] else {
+        return Ok(());
+    };
+    let indent = 2 * depth;
+    writeln!(
+        f,
+        "{:indent$}Level {power_level} node {index}: {:?}, requested {:?}, suspend {:?}",
+        "", node.local_state, node.requested_states, node.suspend_states,
+    )?;
+
+    let is_child = |parent: Option<NodeIndex>| parent.is_some_and(|parent| parent.into() == index);
+    for (child_index, child) in non_cpu_nodes.iter().enumerate() {
+        if child.as_ref().is_some_and(|child| is_child(child.parent)) {
+            fmt_non_cpu_node(
+                f,
+                non_cpu_nodes,
+                cpu_nodes,
+                child_index,
+                power_level - 1,
+                depth + 1,
+            )?;
+        }
+    }
+    let indent = 2 * (depth + 1);
+    for (cpu_index, cpu) in cpu_nodes.iter().enumerate() {
+        if let Some(cpu) = cpu
+            && is_child(Some(cpu.parent))
+        {
+            writeln!(
+                f,
+                "{:indent$}CPU {cpu_index}: {:?}, affinity {:?}, entry point {:?}",
+                "", cpu.local_state, cpu.affinity_info, cpu.entry_point,
+            )?;
+        }
+    }
+
+    Ok(())
+}
+
 #[cfg(test)]
 pub mod test_helpers {
     use super::*;
@@ -743,6 +1160,15 @@ pub mod test_helpers {
         services::psci::PsciPlatformInterface,
     };
 
+    /// Returns a new statistics table for a power domain tree of the test platform.
+    pub fn stats_table() -> &'static PowerDomainStatsTable<
+        { TestPlatform::CORE_COUNT },
+        { TestPsciPlatformImpl::POWER_DOMAIN_COUNT - TestPlatform::CORE_COUNT },
+        TestPowerState,
+    > {
+        Box::leak(Box::default())
+    }
+
     /// Sets the power state (both the local_state and the CPU requested states) of the CPU given by
     /// `cpu_index` to `state` for the given PowerDomainTree. This state will be propagated
     /// throughout the tree; from the CPU nodes all the way to the root non CPU nodes. This
@@ -750,13 +1176,14 @@ pub mod test_helpers {
     /// Note: it is possible to have a mix of power states in a single hierarchy as long as
     /// ancestors are at a shallower power state than descendants. This API does not facilitate this
     /// capability.
-    pub fn set_cpu_power_state_by_index(
+    pub fn set_cpu_power_state_by_index<Tracking: LockTracking>(
         tree: &PowerDomainTree<
             { TestPlatform::CORE_COUNT },
             { TestPsciPlatformImpl::POWER_DOMAIN_COUNT - TestPlatform::CORE_COUNT },
             PSCI_MAX_POWER_LEVEL,
             u8,
             TestPowerState,
+            Tracking,
         >,
         cpu_index: usize,
         state: TestPowerState,
@@ -777,6 +1204,7 @@ mod tests {
     use super::test_helpers::*;
     use super::*;
     use crate::{
+        context::CoresImpl,
         platform::{
             Platform,
             test::{PSCI_MAX_POWER_LEVEL, TestPlatform, TestPowerState, TestPsciPlatformImpl},
@@ -787,6 +1215,10 @@ mod tests {
     const NON_CPU_DOMAIN_COUNT: usize =
         TestPsciPlatformImpl::POWER_DOMAIN_COUNT - TestPlatform::CORE_COUNT;
 
+    fn node_stats() -> &'static SpinMutex<PowerDomainStats<TestPowerState>> {
+        Box::leak(Box::new(SpinMutex::new(PowerDomainStats::new())))
+    }
+
     fn is_last_cpu_to_idle_at_power_level_helper(
         tree: &PowerDomainTree<
             { TestPlatform::CORE_COUNT },
@@ -809,6 +1241,7 @@ mod tests {
         let mut node =
             NonCpuPowerNode::<{ TestPlatform::CORE_COUNT }, NON_CPU_DOMAIN_COUNT, u8, _>::new(
                 Some(1),
+                node_stats(),
             );
         assert_eq!(node.parent, Some(1));
         assert_eq!(TestPowerState::OFF, node.local_state);
@@ -850,11 +1283,46 @@ mod tests {
         assert_eq!(suspend_states, node.suspend_states);
     }
 
+    #[test]
+    fn power_domain_stats() {
+        let mut stats = PowerDomainStats::new();
+        assert_eq!(
+            stats.get(TestPowerState::PowerDown),
+            PowerStateStats::default()
+        );
+
+        // Leaving the initial state isn't counted, as its start time isn't known.
+        stats.transition(TestPowerState::On, 10);
+        stats.transition(TestPowerState::PowerDown, 100);
+        stats.transition(TestPowerState::PowerDown, 120);
+        stats.transition(TestPowerState::On, 150);
+        stats.transition(TestPowerState::PowerDown, 200);
+        stats.transition(TestPowerState::Standby0, 210);
+        stats.transition(TestPowerState::On, 230);
+
+        assert_eq!(
+            stats.get(TestPowerState::PowerDown),
+            PowerStateStats {
+                residency: 60,
+                count: 2,
+            }
+        );
+        assert_eq!(
+            stats.get(TestPowerState::Standby0),
+            PowerStateStats {
+                residency: 20,
+                count: 1,
+            }
+        );
+        assert_eq!(stats.get(TestPowerState::On), PowerStateStats::default());
+    }
+
     #[test]
     fn non_cpu_power_node_is_last_cpu_to_idle() {
         let mut node =
             NonCpuPowerNode::<{ TestPlatform::CORE_COUNT }, NON_CPU_DOMAIN_COUNT, u8, _>::new(
                 Some(0),
+                node_stats(),
             );
         for cpu_index in 0..3 {
             node.assign_cpu(cpu_index);
@@ -887,6 +1355,7 @@ mod tests {
         let mut node0 =
             NonCpuPowerNode::<{ TestPlatform::CORE_COUNT }, NON_CPU_DOMAIN_COUNT, u8, _>::new(
                 Some(0),
+                node_stats(),
             );
         for cpu_index in 0..3 {
             node0.assign_cpu(cpu_index);
@@ -920,7 +1389,7 @@ mod tests {
             NON_CPU_DOMAIN_COUNT,
             u8,
             TestPowerState,
-        >::new(Some(1));
+        >::new(Some(1), node_stats());
         node1.assign_cpu(3);
         assert_eq!(
             node1.get_osi_minimal_allowed_state_without_core(3, None),
@@ -933,6 +1402,7 @@ mod tests {
         let mut node0 =
             NonCpuPowerNode::<{ TestPlatform::CORE_COUNT }, NON_CPU_DOMAIN_COUNT, u8, _>::new(
                 Some(0),
+                node_stats(),
             );
         for cpu_index in 0..3 {
             node0.assign_cpu(cpu_index);
@@ -962,6 +1432,7 @@ mod tests {
         let mut node =
             NonCpuPowerNode::<{ TestPlatform::CORE_COUNT }, NON_CPU_DOMAIN_COUNT, u8, _>::new(
                 Some(1),
+                node_stats(),
             );
         node.assign_cpu(2);
         node.assign_cpu(3);
@@ -970,7 +1441,7 @@ mod tests {
 
     #[test]
     fn cpu_power_node() {
-        let mut node = CpuPowerNode::new(3u8);
+        let mut node = CpuPowerNode::new(3u8, node_stats());
         assert_eq!(3, node.parent);
         assert_eq!(AffinityInfo::Off, node.affinity_info());
         assert_eq!(TestPowerState::OFF, node.local_state());
@@ -1000,7 +1471,7 @@ mod tests {
     #[test]
     #[should_panic]
     fn cpu_power_node_overwrite_entry() {
-        let mut node = CpuPowerNode::<u8, TestPowerState>::new(3);
+        let mut node = CpuPowerNode::<u8, TestPowerState>::new(3, node_stats());
 
         node.set_entry_point(EntryPoint::Entry32 {
             entry_point_address: 1,
@@ -1020,7 +1491,7 @@ mod tests {
             PSCI_MAX_POWER_LEVEL,
             u8,
             TestPowerState,
-        >::new(TestPsciPlatformImpl::topology());
+        >::new(TestPsciPlatformImpl::topology(), stats_table());
         let non_cpu_parents = [None, Some(0), Some(0), Some(1), Some(1), Some(2), Some(2)];
         let non_cpu_ranges = [0..13, 0..6, 6..13, 0..3, 3..6, 6..9, 9..13];
         let cpu_parents = [3, 3, 3, 4, 4, 4, 5, 5, 5, 6, 6, 6, 6];
@@ -1051,7 +1522,7 @@ mod tests {
             PSCI_MAX_POWER_LEVEL,
             u8,
             TestPowerState,
-        >::new(TestPsciPlatformImpl::topology());
+        >::new(TestPsciPlatformImpl::topology(), stats_table());
 
         tree.locked_cpu_node(2).set_affinity_info(AffinityInfo::On);
         assert!(tree.is_last_cpu(2));
@@ -1069,7 +1540,7 @@ mod tests {
             PSCI_MAX_POWER_LEVEL,
             u8,
             TestPowerState,
-        >::new(TestPsciPlatformImpl::topology());
+        >::new(TestPsciPlatformImpl::topology(), stats_table());
 
         let mut cpu = tree.locked_cpu_node(4);
         tree.with_ancestors_locked_to_max_level(&mut cpu, 1, |_cpu, ancestors| {
@@ -1096,7 +1567,7 @@ mod tests {
             PSCI_MAX_POWER_LEVEL,
             u8,
             TestPowerState,
-        >::new(TestPsciPlatformImpl::topology());
+        >::new(TestPsciPlatformImpl::topology(), stats_table());
         for cpu in &tree.cpu_power_nodes {
             cpu.lock().set_affinity_info(AffinityInfo::On);
         }
@@ -1111,7 +1582,7 @@ mod tests {
             PSCI_MAX_POWER_LEVEL,
             u8,
             TestPowerState,
-        >::new(TestPsciPlatformImpl::topology());
+        >::new(TestPsciPlatformImpl::topology(), stats_table());
         for cpu in &tree.cpu_power_nodes {
             cpu.lock().set_affinity_info(AffinityInfo::On);
         }
@@ -1137,7 +1608,7 @@ mod tests {
             PSCI_MAX_POWER_LEVEL,
             u8,
             TestPowerState,
-        >::new(TestPsciPlatformImpl::topology());
+        >::new(TestPsciPlatformImpl::topology(), stats_table());
         for cpu in &tree.cpu_power_nodes {
             cpu.lock().set_affinity_info(AffinityInfo::Off);
         }
@@ -1147,7 +1618,7 @@ mod tests {
 
     #[test]
     fn power_domain_tree_last_cpu_idled_at_power_level_cpu_level_returns_true() {
-        let tree = PowerDomainTree::new(TestPsciPlatformImpl::topology());
+        let tree = PowerDomainTree::new(TestPsciPlatformImpl::topology(), stats_table());
         assert!(is_last_cpu_to_idle_at_power_level_helper(
             &tree,
             0,
@@ -1162,7 +1633,7 @@ mod tests {
 
     #[test]
     fn power_domain_tree_last_cpu_idled_at_power_level_one_cpu_on_returns_true() {
-        let tree = PowerDomainTree::new(TestPsciPlatformImpl::topology());
+        let tree = PowerDomainTree::new(TestPsciPlatformImpl::topology(), stats_table());
         // All power nodes start in off state.
 
         // Turn on some random cores outside the subtree we're going to run tests with to
@@ -1189,7 +1660,7 @@ mod tests {
 
     #[test]
     fn power_domain_tree_last_cpu_idled_at_power_level_two_cpu_on_returns_true() {
-        let tree = PowerDomainTree::new(TestPsciPlatformImpl::topology());
+        let tree = PowerDomainTree::new(TestPsciPlatformImpl::topology(), stats_table());
         // All power nodes start in off state.
 
         // Turn on CPU 1 to demonstrate that the code only looks at the tree up to end_power_level.
@@ -1214,7 +1685,7 @@ mod tests {
 
     #[test]
     fn power_domain_tree_last_cpu_idled_at_root_with_cpu_on_returns_true() {
-        let tree = PowerDomainTree::new(TestPsciPlatformImpl::topology());
+        let tree = PowerDomainTree::new(TestPsciPlatformImpl::topology(), stats_table());
         // All power nodes start in off state.
 
         // Use the root node to turn on CPU 0.
@@ -1246,7 +1717,7 @@ mod tests {
 
     #[test]
     fn power_domain_tree_is_last_cpu_idled_at_power_level_false_for_two_children_on() {
-        let tree = PowerDomainTree::new(TestPsciPlatformImpl::topology());
+        let tree = PowerDomainTree::new(TestPsciPlatformImpl::topology(), stats_table());
 
         set_cpu_power_state_by_index(&tree, 0, TestPowerState::RUN);
         set_cpu_power_state_by_index(&tree, 1, TestPowerState::RUN);
@@ -1259,7 +1730,7 @@ mod tests {
 
     #[test]
     fn power_domain_tree_is_last_cpu_idled_at_power_level_false_for_two_grandchildren_on() {
-        let tree = PowerDomainTree::new(TestPsciPlatformImpl::topology());
+        let tree = PowerDomainTree::new(TestPsciPlatformImpl::topology(), stats_table());
 
         set_cpu_power_state_by_index(&tree, 0, TestPowerState::RUN);
         set_cpu_power_state_by_index(&tree, 1, TestPowerState::RUN);
@@ -1279,7 +1750,7 @@ mod tests {
 
     #[test]
     fn power_domain_tree_is_last_cpu_idled_at_power_level_false_for_two_great_grandchildren_on() {
-        let tree = PowerDomainTree::new(TestPsciPlatformImpl::topology());
+        let tree = PowerDomainTree::new(TestPsciPlatformImpl::topology(), stats_table());
 
         set_cpu_power_state_by_index(&tree, 0, TestPowerState::RUN);
         set_cpu_power_state_by_index(&tree, 1, TestPowerState::RUN);
@@ -1313,4 +1784,82 @@ mod tests {
             PSCI_MAX_POWER_LEVEL
         ));
     }
+
+    #[test]
+    fn power_domain_tree_lock_tracking() {
+        let tree = PowerDomainTree::<
+            { TestPlatform::CORE_COUNT },
+            NON_CPU_DOMAIN_COUNT,
+            PSCI_MAX_POWER_LEVEL,
+            u8,
+            TestPowerState,
+        >::new(TestPsciPlatformImpl::topology(), stats_table())
+        .with_lock_tracking::<CoresImpl<TestPlatform>>();
+
+        let mut cpu = tree.locked_cpu_node(2);
+        tree.with_ancestors_locked(&mut cpu, |_, ancestors| {
+            assert_eq!(
+                tree.current_held_locks().unwrap().load(Ordering::Relaxed) as usize,
+                1 + ancestors.iter().count()
+            );
+        });
+        assert_eq!(
+            tree.current_held_locks().unwrap().load(Ordering::Relaxed),
+            1
+        );
+        cpu.unlock_before_noreturn();
+
+        tree.assert_no_locks_held();
+    }
+
+    #[test]
+    #[should_panic(expected = "1 power domain locks held by the current core")]
+    fn power_domain_tree_lock_held_before_noreturn() {
+        let tree = PowerDomainTree::<
+            { TestPlatform::CORE_COUNT },
+            NON_CPU_DOMAIN_COUNT,
+            PSCI_MAX_POWER_LEVEL,
+            u8,
+            TestPowerState,
+        >::new(TestPsciPlatformImpl::topology(), stats_table())
+        .with_lock_tracking::<CoresImpl<TestPlatform>>();
+
+        let _cpu = tree.locked_cpu_node(2);
+        tree.assert_no_locks_held();
+    }
+
+    #[test]
+    fn power_domain_tree_dump() {
+        let tree = PowerDomainTree::<
+            { TestPlatform::CORE_COUNT },
+            NON_CPU_DOMAIN_COUNT,
+            PSCI_MAX_POWER_LEVEL,
+            u8,
+            TestPowerState,
+        >::new(TestPsciPlatformImpl::topology(), stats_table());
+        set_cpu_power_state_by_index(&tree, 0, TestPowerState::RUN);
+        tree.locked_cpu_node(0).set_affinity_info(AffinityInfo::On);
+
+        // A CPU node which is already locked is listed separately.
+        let _cpu = tree.locked_cpu_node(12);
+        let dump = format!("{tree:?}");
+        let lines = dump.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 20);
+        assert!(lines[0].starts_with("Level 3 node 0: On, requested [On, PowerDown, "));
+        assert_eq!(
+            lines[2..6],
+            [
+                "    Level 1 node 3: On, requested [On, PowerDown, PowerDown], suspend [None, None, \
+                 None]",
+                "      CPU 0: On, affinity On, entry point None",
+                "      CPU 1: PowerDown, affinity Off, entry point None",
+                "      CPU 2: PowerDown, affinity Off, entry point None",
+            ]
+        );
+        assert_eq!(
+            lines[18],
+            "      CPU 11: PowerDown, affinity Off, entry point None"
+        );
+        assert_eq!(lines[19], "CPU 12: locked");
+    }
 }
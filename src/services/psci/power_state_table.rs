@@ -0,0 +1,183 @@
+// Copyright The Rusted Firmware-A Contributors.
+//
+// SPDX-License-Identifier: BSD-3-Clause
+
+//! Declarative description of the power states which a platform supports for `CPU_SUSPEND`.
+
+use super::{NodeIndexInterface, PlatformPowerStateInterface, PsciCompositePowerState};
+use arm_psci::PowerState;
+
+/// A `power_state` parameter which the platform accepts, and the composite power state it maps to.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct PowerStateTableEntry<S, const STATE_COUNT: usize> {
+    /// The type of the power state and the local state fields of its StateID.
+    ///
+    /// The last at power level field must be left as 0, as it is parsed separately.
+    pub power_state: PowerState,
+    /// The local power state requested at each power level, starting with the CPU.
+    pub states: [S; STATE_COUNT],
+}
+
+/// Table of the `power_state` parameters which a platform accepts, using the StateID encoding
+/// recommended by section 6.5 of the PSCI specification.
+///
+/// The StateID has a 4 bit local state field for each power level, starting with the CPU level in
+/// the least significant bits, followed by a 4 bit field giving the highest power level at which
+/// the calling CPU is the last running CPU, which is used in OS-initiated mode.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct PowerStateTable<S: 'static, const STATE_COUNT: usize> {
+    entries: &'static [PowerStateTableEntry<S, STATE_COUNT>],
+}
+
+impl<S: PlatformPowerStateInterface, const STATE_COUNT: usize> PowerStateTable<S, STATE_COUNT> {
+    /// The width in bits of each local state field of a StateID.
+    pub const LOCAL_STATE_WIDTH: u32 = 4;
+    const LOCAL_STATES_MASK: u32 = (1 << (Self::LOCAL_STATE_WIDTH * STATE_COUNT as u32)) - 1;
+    const LAST_AT_POWER_LEVEL_SHIFT: u32 = Self::LOCAL_STATE_WIDTH * STATE_COUNT as u32;
+
+    /// Creates a table accepting the given power states.
+    pub const fn new(entries: &'static [PowerStateTableEntry<S, STATE_COUNT>]) -> Self {
+        Self { entries }
+    }
+
+    /// Looks up the given `power_state` parameter in the table, and returns the corresponding
+    /// composite power state along with the last at power level from its StateID.
+    ///
+    /// Returns `None` if the local states don't match any entry of the table, or the last at power
+    /// level is out of range. Any StateID bits above the last at power level field must be 0.
+    pub fn parse<
+        const MAX_POWER_LEVEL: usize,
+        const CPU_DOMAIN_COUNT: usize,
+        const NON_CPU_DOMAIN_COUNT: usize,
+        NodeIndex: NodeIndexInterface,
+    >(
+        &self,
+        power_state: PowerState,
+    ) -> Option<
+        PsciCompositePowerState<
+            STATE_COUNT,
+            MAX_POWER_LEVEL,
+            CPU_DOMAIN_COUNT,
+            NON_CPU_DOMAIN_COUNT,
+            NodeIndex,
+            S,
+        >,
+    > {
+        let (state_id, local_states) = match power_state {
+            PowerState::StandbyOrRetention(state_id) => (
+                state_id,
+                PowerState::StandbyOrRetention(state_id & Self::LOCAL_STATES_MASK),
+            ),
+            PowerState::PowerDown(state_id) => (
+                state_id,
+                PowerState::PowerDown(state_id & Self::LOCAL_STATES_MASK),
+            ),
+        };
+
+        let last_at_power_level = (state_id >> Self::LAST_AT_POWER_LEVEL_SHIFT) as usize;
+        if last_at_power_level > MAX_POWER_LEVEL {
+            return None;
+        }
+
+        let entry = self
+            .entries
+            .iter()
+            .find(|entry| entry.power_state == local_states)?;
+
+        Some(PsciCompositePowerState::new_with_last_power_level(
+            entry.states,
+            last_at_power_level,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::psci::PowerStateType;
+
+    #[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
+    enum TestState {
+        On,
+        Retention,
+        Off,
+    }
+
+    impl PlatformPowerStateInterface for TestState {
+        const OFF: Self = Self::Off;
+        const RUN: Self = Self::On;
+
+        fn power_state_type(&self) -> PowerStateType {
+            match self {
+                Self::On => PowerStateType::Run,
+                Self::Retention => PowerStateType::StandbyOrRetention,
+                Self::Off => PowerStateType::PowerDown,
+            }
+        }
+    }
+
+    impl From<TestState> for usize {
+        fn from(value: TestState) -> Self {
+            value as usize
+        }
+    }
+
+    const TABLE: PowerStateTable<TestState, 3> = PowerStateTable::new(&[
+        PowerStateTableEntry {
+            power_state: PowerState::StandbyOrRetention(0x001),
+            states: [TestState::Retention, TestState::On, TestState::On],
+        },
+        PowerStateTableEntry {
+            power_state: PowerState::PowerDown(0x002),
+            states: [TestState::Off, TestState::On, TestState::On],
+        },
+        PowerStateTableEntry {
+            power_state: PowerState::PowerDown(0x022),
+            states: [TestState::Off, TestState::Off, TestState::On],
+        },
+    ]);
+
+    fn parse(power_state: PowerState) -> Option<([TestState; 3], Option<usize>)> {
+        TABLE
+            .parse::<2, 4, 3, u8>(power_state)
+            .map(|state| (state.states, state.last_at_power_level))
+    }
+
+    #[test]
+    fn parse_entries() {
+        assert_eq!(
+            parse(PowerState::StandbyOrRetention(0x001)),
+            Some((
+                [TestState::Retention, TestState::On, TestState::On],
+                Some(0)
+            ))
+        );
+        assert_eq!(
+            parse(PowerState::PowerDown(0x022)),
+            Some(([TestState::Off, TestState::Off, TestState::On], Some(0)))
+        );
+    }
+
+    #[test]
+    fn parse_last_at_power_level() {
+        assert_eq!(
+            parse(PowerState::PowerDown(0x1022)),
+            Some(([TestState::Off, TestState::Off, TestState::On], Some(1)))
+        );
+        assert_eq!(
+            parse(PowerState::PowerDown(0x2002)),
+            Some(([TestState::Off, TestState::On, TestState::On], Some(2)))
+        );
+        assert_eq!(parse(PowerState::PowerDown(0x3022)), None);
+    }
+
+    #[test]
+    fn parse_unknown() {
+        // The power state type must match as well as the local states.
+        assert_eq!(parse(PowerState::PowerDown(0x001)), None);
+        assert_eq!(parse(PowerState::StandbyOrRetention(0x002)), None);
+        assert_eq!(parse(PowerState::PowerDown(0x222)), None);
+        // Bits above the last at power level field aren't part of the recommended encoding.
+        assert_eq!(parse(PowerState::PowerDown(0x1_0002)), None);
+    }
+}
@@ -12,10 +12,10 @@ use core::{
     slice::from_raw_parts_mut,
     sync::atomic::{AtomicU8, Ordering},
 };
-use log::{debug, error, warn};
+use log::{debug, error, info, warn};
 use num_enum::TryFromPrimitive;
 use percore::{Cores, ExceptionLock, PerCore};
-use spin::{Once, mutex::SpinMutex};
+use spin::Once;
 
 use crate::{
     aarch64::dsb_osh,
@@ -23,17 +23,23 @@ use crate::{
     gpt::{GPIAccessType, GranuleProtection},
     pagetable::flush_dcache_to_popa_range,
     platform::{Platform, exception_free},
+    runtime_config::runtime_config,
     services::{
-        Service, owns,
+        InitPhase, Service, owns,
         rmmd::svc::{
             Error, RmmAttestGetPlatTokenResponse, RmmAttestGetRealmKeyResponse, RmmCall,
             RmmCommandReturnCode, RmmEl3FeaturesResponse,
         },
     },
     smccc::{FunctionId, NOT_SUPPORTED, OwningEntityNumber, SetFrom, SmcReturn},
+    spin_mutex::SpinMutex,
 };
 use arm_sysregs::{SctlrEl3, read_sctlr_el3};
 
+/// Version of the RMM-EL3 boot interface passed to the RMM on cold boot, as `major << 16 | minor`.
+///
+/// The RMM checks this against the version it implements and reports
+/// [`RmmBootError::VersionMismatch`] through `RMM_BOOT_COMPLETE` if it can't support it.
 const RMM_BOOT_VERSION: u64 = 0x5;
 /// Size in bytes of the EL3 - RMM shared area.
 pub const RMM_SHARED_BUFFER_SIZE: usize = 0x1000;
@@ -117,15 +123,32 @@ fn check_shared_buffer_range<PlatformImpl: Platform>(
 
 const RMM_BOOT_COMPLETE: u32 = 0xC400_01CF;
 
+/// Error codes the RMM may report in `RMM_BOOT_COMPLETE`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, TryFromPrimitive)]
+#[repr(i32)]
+enum RmmBootError {
+    Unknown = -1,
+    VersionMismatch = -2,
+    CpusOutOfRange = -3,
+    CpuIdOutOfRange = -4,
+    InvalidSharedBuffer = -5,
+    ManifestVersionNotSupported = -6,
+    ManifestDataError = -7,
+}
+
 #[derive(Debug)]
 struct RmmdLocal {
     activation_token: Option<u64>,
+    /// Whether the RMM has been entered through its boot entrypoint on this core and not yet
+    /// reported `RMM_BOOT_COMPLETE`.
+    booting: bool,
 }
 
 impl RmmdLocal {
     const fn new() -> Self {
         Self {
             activation_token: None,
+            booting: true,
         }
     }
 }
@@ -196,6 +219,39 @@ pub struct Rmmd<const CORE_COUNT: usize, PlatformImpl: Platform> {
 impl<const CORE_COUNT: usize, PlatformImpl: Platform> Service for Rmmd<CORE_COUNT, PlatformImpl> {
     owns! {OwningEntityNumber::STANDARD_SECURE, 0x0150..=0x01CF}
 
+    fn init(&self, phase: InitPhase) {
+        if phase != InitPhase::Early {
+            return;
+        }
+
+        if !runtime_config().rme_enabled {
+            // Treat this the same as the RMM failing to boot, so that Realm world is never entered
+            // and RMI calls are not supported.
+            info!("RME disabled by runtime configuration, not booting RMM");
+            self.set_boot_failure();
+            return;
+        }
+
+        // Safety:
+        // - This function is called after initializing the MMU and pagetable.
+        // - This function never calls again `get_shared_buffer()`, thus the reference will be dropped
+        //   upon return, before another call is made.
+        // - This function is called before the first switch to Realm world and, similarly to above,
+        //   the reference is dropped before that switch.
+        let buf = unsafe { get_shared_buffer::<PlatformImpl>() };
+        PlatformImpl::rme_prepare_manifest(buf);
+        debug!("RMM Boot Manifest ready");
+
+        #[cfg(all(target_arch = "aarch64", not(any(test, feature = "fakes"))))]
+        GRANULE_PROTECTION_TABLE.call_once(|| {
+            // Safety: this code can only be executed once due to [`Once::call_once`]. The
+            // `discover()` function is not called anywhere else in the code.
+            let gpt = unsafe { GranuleProtection::discover() }.unwrap();
+            debug!("GPT discovered: {gpt:x?}");
+            SpinMutex::new(gpt)
+        });
+    }
+
     fn handle_non_secure_smc(&self, regs: &mut SmcReturn) -> World {
         // Only forward RMI calls, if the RMM successfully booted.
         if RmiFuncId::try_from(regs.values()[0] as u32).is_ok() && self.boot_success() {
@@ -228,25 +284,6 @@ impl<const CORE_COUNT: usize, PlatformImpl: Platform> Rmmd<CORE_COUNT, PlatformI
             [const { ExceptionLock::new(RefCell::new(RmmdLocal::new())) }; CORE_COUNT],
         );
 
-        // Safety:
-        // - This function is called after initializing the MMU and pagetable.
-        // - This function never calls again `get_shared_buffer()`, thus the reference will be dropped
-        //   upon return, before another call is made.
-        // - This function is called before the first switch to Realm world and, similarly to above,
-        //   the reference is dropped before that switch.
-        let buf = unsafe { get_shared_buffer::<PlatformImpl>() };
-        PlatformImpl::rme_prepare_manifest(buf);
-        debug!("RMM Boot Manifest ready");
-
-        #[cfg(all(target_arch = "aarch64", not(any(test, feature = "fakes"))))]
-        GRANULE_PROTECTION_TABLE.call_once(|| {
-            // Safety: this code can only be executed once due to [`Once::call_once`]. The
-            // `discover()` function is not called anywhere else in the code.
-            let gpt = unsafe { GranuleProtection::discover() }.unwrap();
-            debug!("GPT discovered: {gpt:x?}");
-            SpinMutex::new(gpt)
-        });
-
         Self {
             core_local,
             attestation_token_read_index: SpinMutex::new(0),
@@ -254,6 +291,14 @@ impl<const CORE_COUNT: usize, PlatformImpl: Platform> Rmmd<CORE_COUNT, PlatformI
         }
     }
 
+    /// Notifies the RMMD that the current core was turned on after CPU_OFF, so the RMM will be
+    /// entered through its warm boot entrypoint and must report `RMM_BOOT_COMPLETE` again.
+    pub(crate) fn handle_wake_from_cpu_off(&self) {
+        exception_free(|token| {
+            self.core_local.get().borrow_mut(token).booting = true;
+        });
+    }
+
     /// Initializes the set of registers to pass to R-EL2 after waking up from a suspend.
     ///
     /// <https://trustedfirmware-a.readthedocs.io/en/latest/components/rmm-el3-comms-spec.html#warm-boot-interface>
@@ -274,6 +319,7 @@ impl<const CORE_COUNT: usize, PlatformImpl: Platform> Rmmd<CORE_COUNT, PlatformI
             0,
         ]
     }
+
     pub(crate) fn boot_success(&self) -> bool {
         self.rmm_boot_state.load(Ordering::Acquire) == RmmBootState::ColdBootDone as u8
     }
@@ -541,7 +587,11 @@ impl<const CORE_COUNT: usize, PlatformImpl: Platform> Rmmd<CORE_COUNT, PlatformI
                 });
                 Ok(World::Realm)
             }
-            RmmCall::El3Features { .. } => {
+            RmmCall::El3Features { feat_reg_idx } => {
+                // Only feature register 0 is defined by the current interface version.
+                if feat_reg_idx != 0 {
+                    return Err(RmmCommandReturnCode::InvalidValue);
+                }
                 regs.set_from(RmmEl3FeaturesResponse { feat_reg: 0 });
                 Ok(World::Realm)
             }
@@ -562,37 +612,48 @@ impl<const CORE_COUNT: usize, PlatformImpl: Platform> Rmmd<CORE_COUNT, PlatformI
     fn handle_boot_complete(&self, regs: &mut SmcReturn) -> World {
         let ret = regs.values()[1] as i32;
 
-        if ret != 0 {
-            error!("RMM boot failed with code {ret}");
-            self.set_boot_failure();
-            return World::NonSecure;
-        }
-
         exception_free(|token| {
             let mut state = self.core_local.get().borrow_mut(token);
 
-            if state.activation_token.is_none() {
-                let activation_token = regs.values()[2];
-                debug!("Received activation token {activation_token:#x?}");
-                state.activation_token = Some(activation_token);
-
-                // set_boot_success() can fail if RmmBootState is already at the Error state.
-                // This can happen if another core just failed booting the RMM.
-                // Not setting RmmBootState to success is the correct thing to do in this case,
-                // therefore the Result is ignored.
-                let _ = self.set_boot_success();
-                regs.set_from(ret);
-
-                World::NonSecure
-            } else {
+            if !state.booting {
                 warn!(
-                    "Received multiple `RMM_BOOT_COMPLETE` SMCs from core {}",
+                    "Unexpected `RMM_BOOT_COMPLETE` SMC from core {}",
                     CoresImpl::<PlatformImpl>::core_index()
                 );
 
                 regs.set_from(NOT_SUPPORTED);
-                World::Realm
+                return World::Realm;
+            }
+            state.booting = false;
+
+            if ret != 0 {
+                match RmmBootError::try_from(ret) {
+                    Ok(RmmBootError::VersionMismatch) => error!(
+                        "RMM boot failed: RMM doesn't support boot interface version {:#x}",
+                        RMM_BOOT_VERSION
+                    ),
+                    Ok(error) => error!("RMM boot failed: {error:?}"),
+                    Err(_) => error!("RMM boot failed with code {ret}"),
+                }
+                self.set_boot_failure();
+                return World::NonSecure;
+            }
+
+            if state.activation_token.is_none() {
+                let activation_token = regs.values()[2];
+                debug!("Received activation token {activation_token:#x?}");
+                state.activation_token = Some(activation_token);
             }
+
+            // set_boot_success() can fail if RmmBootState is already at the Error state.
+            // This can happen if another core just failed booting the RMM. It also fails on warm
+            // boot, when the state is already ColdBootDone.
+            // Not changing RmmBootState is the correct thing to do in both cases, therefore the
+            // Result is ignored.
+            let _ = self.set_boot_success();
+            regs.set_from(ret);
+
+            World::NonSecure
         })
     }
 
@@ -600,13 +661,21 @@ impl<const CORE_COUNT: usize, PlatformImpl: Platform> Rmmd<CORE_COUNT, PlatformI
     pub fn entrypoint_args(&self) -> [u64; 8] {
         let core_linear_id = CoresImpl::<PlatformImpl>::core_index() as u64;
         if self.boot_success() {
-            // When warmbooting a PE for the first time, it should only receive the core id as
-            // per the RMM-EL3 warmboot interface. Activation token is set to 0 as it was not
-            // generated for this core yet. Subsequent warmboot parameters on this PE will be
-            // provided by [`Rmmd::handle_wake_from_cpu_suspend`].
+            // When warmbooting a PE, it receives the core id and the activation token as per the
+            // RMM-EL3 warmboot interface. The activation token is 0 the first time the PE is
+            // booted, as it was not generated for this core yet. Warmboot parameters after a
+            // suspend are provided by [`Rmmd::handle_wake_from_cpu_suspend`] instead.
             //
             // https://trustedfirmware-a.readthedocs.io/en/latest/components/rmm-el3-comms-spec.html#warm-boot-interface
-            [core_linear_id, 0, 0, 0, 0, 0, 0, 0]
+            let activation_token = exception_free(|token| {
+                self.core_local
+                    .get()
+                    .borrow(token)
+                    .borrow()
+                    .activation_token
+                    .unwrap_or_default()
+            });
+            [core_linear_id, activation_token, 0, 0, 0, 0, 0, 0]
         } else {
             // In case of an unsuccessful RMM boot, we return the coldboot args.
             // This choice is arbitrary, as no more RMM boots will be attempted using these
@@ -630,6 +699,8 @@ mod tests {
     use super::*;
     use crate::platform::test::TestPlatform;
 
+    const RMM_EL3_FEATURES: u64 = 0xC400_01B4;
+
     fn setup() -> Rmmd<{ TestPlatform::CORE_COUNT }, TestPlatform> {
         Rmmd::new()
     }
@@ -663,4 +734,69 @@ mod tests {
         assert_eq!(world, World::NonSecure);
         assert_eq!(regs.values()[0], u64::MAX);
     }
+
+    #[test]
+    fn boot_complete_twice_test() {
+        let rmmd = setup();
+        let mut regs = SmcReturn::EMPTY;
+        regs.set_args3(RMM_BOOT_COMPLETE.into(), 0, 0x1234);
+        regs.mark_all_used();
+        assert_eq!(rmmd.handle_realm_smc(&mut regs), World::NonSecure);
+        assert!(rmmd.boot_success());
+
+        // A second RMM_BOOT_COMPLETE without the core being booted again is rejected.
+        regs.set_args3(RMM_BOOT_COMPLETE.into(), 0, 0x5678);
+        regs.mark_all_used();
+        assert_eq!(rmmd.handle_realm_smc(&mut regs), World::Realm);
+        assert_eq!(regs.values()[0], u64::MAX);
+    }
+
+    #[test]
+    fn warm_boot_handshake_test() {
+        let rmmd = setup();
+        let mut regs = SmcReturn::EMPTY;
+        regs.set_args3(RMM_BOOT_COMPLETE.into(), 0, 0x1234);
+        regs.mark_all_used();
+        assert_eq!(rmmd.handle_realm_smc(&mut regs), World::NonSecure);
+
+        // After CPU_OFF and CPU_ON the RMM gets the activation token and reports completion again.
+        rmmd.handle_wake_from_cpu_off();
+        let args = rmmd.entrypoint_args();
+        assert_eq!(args[1], 0x1234);
+        regs.set_args3(RMM_BOOT_COMPLETE.into(), 0, 0x1234);
+        regs.mark_all_used();
+        assert_eq!(rmmd.handle_realm_smc(&mut regs), World::NonSecure);
+        assert_eq!(regs.values()[0], 0);
+        assert!(rmmd.boot_success());
+    }
+
+    #[test]
+    fn boot_version_mismatch_test() {
+        let rmmd = setup();
+        let mut regs = SmcReturn::EMPTY;
+        regs.set_args2(
+            RMM_BOOT_COMPLETE.into(),
+            RmmBootError::VersionMismatch as i32 as u64,
+        );
+        regs.mark_all_used();
+        assert_eq!(rmmd.handle_realm_smc(&mut regs), World::NonSecure);
+        assert!(rmmd.boot_failure());
+    }
+
+    #[test]
+    fn el3_features_test() {
+        let rmmd = setup();
+        rmmd.set_boot_success().unwrap();
+        let mut regs = SmcReturn::EMPTY;
+        regs.set_args2(RMM_EL3_FEATURES, 0);
+        regs.mark_all_used();
+        assert_eq!(rmmd.handle_realm_smc(&mut regs), World::Realm);
+        assert_eq!(regs.values()[0], 0);
+
+        // Feature registers other than 0 aren't defined.
+        regs.set_args2(RMM_EL3_FEATURES, 1);
+        regs.mark_all_used();
+        assert_eq!(rmmd.handle_realm_smc(&mut regs), World::Realm);
+        assert_ne!(regs.values()[0], 0);
+    }
 }
@@ -0,0 +1,169 @@
+// Copyright The Rusted Firmware-A Contributors.
+//
+// SPDX-License-Identifier: BSD-3-Clause
+
+//! Runtime instrumentation of the PSCI suspend and wake up path, compatible with TF-A's
+//! `ENABLE_RUNTIME_INSTRUMENTATION`.
+//!
+//! The timestamps are recorded by the PSCI service in the [`Pmf`], and this service lets the normal
+//! world read them back through TF-A's PMF SiP SMCs, so that TFTF's runtime instrumentation tests
+//! can run against RF-A.
+
+use crate::{
+    context::World,
+    platform::Platform,
+    pmf::{Pmf, PsciTimestamp},
+    services::{Service, owns},
+    smccc::{FunctionId, NOT_SUPPORTED, OwningEntityNumber, SetFrom, SmcReturn},
+};
+use arm_sysregs::MpidrEl1;
+use core::marker::PhantomData;
+
+const PMF_SMC_GET_TIMESTAMP_32: u32 = 0x8200_0010;
+const PMF_SMC_GET_TIMESTAMP_64: u32 = 0xC200_0010;
+
+/// The PMF service ID of runtime instrumentation timestamps in TF-A.
+const RT_INSTR_SVC_ID: u32 = 1;
+/// The position of the service ID in a PMF timestamp ID.
+const PMF_SVC_ID_SHIFT: u32 = 10;
+/// The mask of the local timestamp ID within a PMF timestamp ID.
+const PMF_TID_MASK: u32 = (1 << PMF_SVC_ID_SHIFT) - 1;
+
+/// TF-A's PMF returns `-EINVAL` for an invalid MPIDR or timestamp ID.
+const EINVAL: i32 = -22;
+
+/// The runtime instrumentation service, which reads back PSCI timestamps from the [`Pmf`].
+pub struct RtInstr<const CORE_COUNT: usize, PlatformImpl: Platform> {
+    pmf: &'static Pmf<CORE_COUNT>,
+    _platform: PhantomData<PlatformImpl>,
+}
+
+impl<const CORE_COUNT: usize, PlatformImpl: Platform> RtInstr<CORE_COUNT, PlatformImpl> {
+    pub(super) fn new(pmf: &'static Pmf<CORE_COUNT>) -> Self {
+        Self {
+            pmf,
+            _platform: PhantomData,
+        }
+    }
+
+    /// Returns the timestamp with the given PMF timestamp ID recorded by the core with the given
+    /// MPIDR, or `None` if either is invalid.
+    fn timestamp(&self, mpidr: u64, tid: u32) -> Option<u64> {
+        if tid >> PMF_SVC_ID_SHIFT != RT_INSTR_SVC_ID {
+            return None;
+        }
+        let event = PsciTimestamp::try_from((tid & PMF_TID_MASK) as u16).ok()?;
+        let mpidr = MpidrEl1::from_bits_retain(mpidr);
+        if !PlatformImpl::mpidr_is_valid(mpidr) {
+            return None;
+        }
+        self.pmf
+            .psci_timestamp(PlatformImpl::core_position(mpidr.bits()), event)
+    }
+}
+
+impl<const CORE_COUNT: usize, PlatformImpl: Platform> Service
+    for RtInstr<CORE_COUNT, PlatformImpl>
+{
+    owns!(OwningEntityNumber::SIP, 0x0010..=0x0010);
+
+    fn handle_non_secure_smc(&self, regs: &mut SmcReturn) -> World {
+        let function = FunctionId(regs.values()[0] as u32);
+        let mpidr = regs.values()[1];
+        let tid = regs.values()[2] as u32;
+        // The flags in x3 only ask for the timestamp to be invalidated from the caches before it is
+        // read, which isn't needed as the timestamps are only accessed through this SMC.
+        match function {
+            FunctionId(PMF_SMC_GET_TIMESTAMP_32) => {
+                match self.timestamp(mpidr as u32 as u64, tid) {
+                    Some(timestamp) => {
+                        regs.set_args3(0, timestamp as u32 as u64, (timestamp >> 32) as u32 as u64)
+                    }
+                    None => regs.set_from(EINVAL),
+                }
+            }
+            FunctionId(PMF_SMC_GET_TIMESTAMP_64) => match self.timestamp(mpidr, tid) {
+                Some(timestamp) => regs.set_args2(0, timestamp),
+                None => regs.set_from(EINVAL),
+            },
+            _ => regs.set_from(NOT_SUPPORTED),
+        }
+        World::NonSecure
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::platform::test::TestPlatform;
+    use arm_sysregs::{CntpctEl0, fake::SYSREGS};
+
+    const CORE_COUNT: usize = TestPlatform::CORE_COUNT;
+
+    fn call(rt_instr: &RtInstr<CORE_COUNT, TestPlatform>, args: [u64; 4]) -> SmcReturn {
+        let mut regs = SmcReturn::EMPTY;
+        regs.set_args4(args[0], args[1], args[2], args[3]);
+        rt_instr.handle_non_secure_smc(&mut regs);
+        regs
+    }
+
+    #[test]
+    fn get_timestamp() {
+        let pmf = Box::leak(Box::new(Pmf::<CORE_COUNT>::new()));
+        let rt_instr = RtInstr::<CORE_COUNT, TestPlatform>::new(pmf);
+        let mpidr = MpidrEl1::from_bits_retain(0x100);
+        let core_index = TestPlatform::core_position(mpidr.bits());
+
+        SYSREGS.lock().unwrap().cntpct_el0 = CntpctEl0::from_bits_retain(0x1_2345_6789);
+        pmf.record_psci(core_index, PsciTimestamp::EnterLowPower);
+        SYSREGS.lock().unwrap().reset();
+
+        let tid = RT_INSTR_SVC_ID << PMF_SVC_ID_SHIFT | PsciTimestamp::EnterLowPower as u32;
+        assert_eq!(
+            call(
+                &rt_instr,
+                [PMF_SMC_GET_TIMESTAMP_64.into(), mpidr.bits(), tid.into(), 0]
+            )
+            .values(),
+            [0, 0x1_2345_6789]
+        );
+        assert_eq!(
+            call(
+                &rt_instr,
+                [PMF_SMC_GET_TIMESTAMP_32.into(), mpidr.bits(), tid.into(), 0]
+            )
+            .values(),
+            [0, 0x2345_6789, 0x1]
+        );
+
+        // Only runtime instrumentation timestamps are supported.
+        let other_service = 2 << PMF_SVC_ID_SHIFT | PsciTimestamp::EnterLowPower as u32;
+        assert_eq!(
+            call(
+                &rt_instr,
+                [
+                    PMF_SMC_GET_TIMESTAMP_64.into(),
+                    mpidr.bits(),
+                    other_service.into(),
+                    0
+                ]
+            )
+            .values(),
+            [EINVAL as u64]
+        );
+        let unknown_tid = RT_INSTR_SVC_ID << PMF_SVC_ID_SHIFT | 6;
+        assert_eq!(
+            call(
+                &rt_instr,
+                [
+                    PMF_SMC_GET_TIMESTAMP_64.into(),
+                    mpidr.bits(),
+                    unknown_tid.into(),
+                    0
+                ]
+            )
+            .values(),
+            [EINVAL as u64]
+        );
+    }
+}
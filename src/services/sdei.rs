@@ -0,0 +1,789 @@
+// Copyright The Rusted Firmware-A Contributors.
+//
+// SPDX-License-Identifier: BSD-3-Clause
+
+//! Service implementing the Software Delegated Exception Interface, as specified by Arm DEN 0054.
+//!
+//! This lets EL3 delegate events such as RAS errors or watchdog timeouts to a handler registered by
+//! the normal world. Only private events of normal priority are supported: event 0, and any events
+//! listed in [`Platform::SDEI_EVENTS`]. Binding events to interrupts, signalling and shared events
+//! are not supported.
+
+use crate::{
+    context::{CoresImpl, CpuContext, CpuStateAccess, World},
+    exceptions::create_spsr,
+    platform::{Platform, exception_free},
+    services::{Service, owns},
+    smccc::{OwningEntityNumber, SUCCESS, SetFrom, SmcReturn},
+    spin_mutex::SpinMutex,
+};
+use arm_sysregs::{
+    ElrEl1, ElrEl2, ExceptionLevel, SpsrEl1, SpsrEl2, SpsrEl3, write_elr_el1, write_elr_el2,
+    write_spsr_el1, write_spsr_el2,
+};
+use core::marker::PhantomData;
+use log::warn;
+use percore::Cores;
+
+const FUNCTION_NUMBER_MIN: u16 = 0x0020;
+const FUNCTION_NUMBER_MAX: u16 = 0x003F;
+
+const SDEI_VERSION: u32 = 0xC400_0020;
+const SDEI_EVENT_REGISTER: u32 = 0xC400_0021;
+const SDEI_EVENT_ENABLE: u32 = 0xC400_0022;
+const SDEI_EVENT_DISABLE: u32 = 0xC400_0023;
+const SDEI_EVENT_CONTEXT: u32 = 0xC400_0024;
+const SDEI_EVENT_COMPLETE: u32 = 0xC400_0025;
+const SDEI_EVENT_COMPLETE_AND_RESUME: u32 = 0xC400_0026;
+const SDEI_EVENT_UNREGISTER: u32 = 0xC400_0027;
+const SDEI_EVENT_STATUS: u32 = 0xC400_0028;
+const SDEI_EVENT_GET_INFO: u32 = 0xC400_0029;
+const SDEI_PE_MASK: u32 = 0xC400_002B;
+const SDEI_PE_UNMASK: u32 = 0xC400_002C;
+const SDEI_PRIVATE_RESET: u32 = 0xC400_0031;
+const SDEI_SHARED_RESET: u32 = 0xC400_0032;
+
+/// SDEI version 1.0, with no vendor-defined version.
+const VERSION: u64 = 1 << 48;
+
+// SDEI_EVENT_STATUS result bits.
+const STATUS_REGISTERED: u64 = 1 << 0;
+const STATUS_ENABLED: u64 = 1 << 1;
+const STATUS_RUNNING: u64 = 1 << 2;
+
+// SDEI_EVENT_GET_INFO information types.
+const INFO_EV_TYPE: u64 = 0;
+const INFO_EV_SIGNALED: u64 = 1;
+const INFO_EV_PRIORITY: u64 = 2;
+
+/// The event which every SDEI implementation must provide.
+pub const SDEI_EVENT_0: u32 = 0;
+
+/// The maximum number of private events on each PE, including event 0.
+pub const MAX_SDEI_EVENTS: usize = 4;
+
+/// The number of registers saved when an event is dispatched, which the handler may read with
+/// `SDEI_EVENT_CONTEXT`.
+const SAVED_REGISTER_COUNT: usize = 18;
+
+/// SDEI error codes.
+#[repr(i32)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SdeiError {
+    /// The function is not implemented.
+    NotSupported = -1,
+    /// An invalid parameter was passed, such as an unknown event number.
+    InvalidParameters = -2,
+    /// The call is not allowed in the current state of the event or PE.
+    Denied = -3,
+    /// The operation will complete once the event handler which is currently running finishes.
+    Pending = -5,
+    /// There are no resources to complete the operation.
+    OutOfResource = -10,
+}
+
+impl SetFrom<SdeiError> for SmcReturn {
+    fn set_from(&mut self, value: SdeiError) {
+        self.set_from(value as i32)
+    }
+}
+
+/// A handler registered for an event on a PE.
+#[derive(Clone, Copy, Debug)]
+struct Registration {
+    entry_point: u64,
+    arg: u64,
+    /// The EL from which the event was registered, at which the handler runs.
+    client_el: ExceptionLevel,
+    enabled: bool,
+    /// Whether the client has asked to unregister while the handler was running.
+    unregister_pending: bool,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct EventState {
+    registration: Option<Registration>,
+    pending: bool,
+}
+
+/// The normal world state interrupted to run an event handler.
+#[derive(Clone, Debug)]
+struct InterruptedContext {
+    event_index: usize,
+    registers: [u64; SAVED_REGISTER_COUNT],
+    elr_el3: usize,
+    spsr_el3: SpsrEl3,
+}
+
+#[derive(Clone, Debug)]
+struct CoreState {
+    masked: bool,
+    events: [EventState; MAX_SDEI_EVENTS],
+    /// The context interrupted by the event handler which is currently running, if any.
+    running: Option<InterruptedContext>,
+}
+
+impl CoreState {
+    /// The state after reset: the PE is masked and no events are registered.
+    const RESET: Self = Self {
+        masked: true,
+        events: [EventState {
+            registration: None,
+            pending: false,
+        }; MAX_SDEI_EVENTS],
+        running: None,
+    };
+
+    fn is_running(&self, event_index: usize) -> bool {
+        self.running
+            .as_ref()
+            .is_some_and(|running| running.event_index == event_index)
+    }
+
+    fn unregister(&mut self, event_index: usize) -> Result<(), SdeiError> {
+        let running = self.is_running(event_index);
+        let event = &mut self.events[event_index];
+        let Some(registration) = &mut event.registration else {
+            return Err(SdeiError::Denied);
+        };
+        if running {
+            registration.unregister_pending = true;
+            return Err(SdeiError::Pending);
+        }
+        event.registration = None;
+        event.pending = false;
+        Ok(())
+    }
+
+    /// Returns the index of an event which may be dispatched now, if there is one.
+    fn next_dispatchable(&self) -> Option<usize> {
+        if self.masked || self.running.is_some() {
+            return None;
+        }
+        self.events.iter().position(|event| {
+            event.pending
+                && event
+                    .registration
+                    .is_some_and(|registration| registration.enabled)
+        })
+    }
+
+    /// Enters the handler of the given pending event, saving the normal world context so that it
+    /// can be restored by `SDEI_EVENT_COMPLETE`.
+    ///
+    /// Returns false without changing anything if the normal world isn't running at an EL from
+    /// which the handler can be entered.
+    fn enter_handler(&mut self, event_index: usize, event: u32, context: &mut CpuContext) -> bool {
+        let registration = self.events[event_index].registration.unwrap();
+        let spsr_el3 = context.el3_state.spsr_el3;
+        if spsr_el3.contains(SpsrEl3::M_4) || spsr_el3.exception_level() > registration.client_el {
+            return false;
+        }
+
+        let registers = &mut context.gpregs.registers;
+        let mut saved = [0; SAVED_REGISTER_COUNT];
+        saved.copy_from_slice(&registers[..SAVED_REGISTER_COUNT]);
+        let elr_el3 = context.el3_state.elr_el3;
+
+        registers[0] = event.into();
+        registers[1] = registration.arg;
+        registers[2] = elr_el3 as u64;
+        registers[3] = spsr_el3.bits();
+        context.el3_state.elr_el3 = registration.entry_point as usize;
+        context.el3_state.spsr_el3 = create_spsr(spsr_el3, registration.client_el);
+
+        self.events[event_index].pending = false;
+        self.running = Some(InterruptedContext {
+            event_index,
+            registers: saved,
+            elr_el3,
+            spsr_el3,
+        });
+        true
+    }
+
+    /// Returns from the running event handler to the context it interrupted.
+    ///
+    /// If `resume_address` is given, the interrupted context is instead resumed by taking an
+    /// exception to the client EL at that address, as if an IRQ had been taken there.
+    fn complete(
+        &mut self,
+        context: &mut CpuContext,
+        resume_address: Option<u64>,
+    ) -> Result<(), SdeiError> {
+        let interrupted = self.running.take().ok_or(SdeiError::Denied)?;
+        let event = &mut self.events[interrupted.event_index];
+        let registration = event.registration.unwrap();
+
+        context.gpregs.registers[..SAVED_REGISTER_COUNT].copy_from_slice(&interrupted.registers);
+        context.el3_state.elr_el3 = interrupted.elr_el3;
+        context.el3_state.spsr_el3 = interrupted.spsr_el3;
+
+        if let Some(resume_address) = resume_address {
+            // Write directly to the client EL's system registers, because they are live while the
+            // normal world is running.
+            match registration.client_el {
+                ExceptionLevel::El1 => {
+                    // SAFETY: These registers only affect the lower EL, and hold the interrupted
+                    // state exactly as an exception taken to EL1 would have saved it.
+                    unsafe {
+                        write_elr_el1(ElrEl1::from_bits_retain(interrupted.elr_el3 as u64));
+                        write_spsr_el1(SpsrEl1::from_bits_retain(interrupted.spsr_el3.bits()));
+                    }
+                }
+                ExceptionLevel::El2 => {
+                    // SAFETY: These registers only affect the lower EL, and hold the interrupted
+                    // state exactly as an exception taken to EL2 would have saved it.
+                    unsafe {
+                        write_elr_el2(ElrEl2::from_bits_retain(interrupted.elr_el3 as u64));
+                        write_spsr_el2(SpsrEl2::from_bits_retain(interrupted.spsr_el3.bits()));
+                    }
+                }
+                ExceptionLevel::El0 | ExceptionLevel::El3 => unreachable!(),
+            }
+            context.el3_state.elr_el3 = resume_address as usize;
+            context.el3_state.spsr_el3 = create_spsr(interrupted.spsr_el3, registration.client_el);
+        }
+
+        if registration.unregister_pending {
+            event.registration = None;
+            event.pending = false;
+        }
+        Ok(())
+    }
+}
+
+/// The SDEI state of every PE.
+///
+/// This is kept outside the [`Sdei`] service so that EL3 interrupt and error handlers can dispatch
+/// events to the normal world through it.
+#[derive(Debug)]
+pub struct SdeiState<const CORE_COUNT: usize, PlatformImpl> {
+    cores: [SpinMutex<CoreState>; CORE_COUNT],
+    _platform: PhantomData<fn() -> PlatformImpl>,
+}
+
+impl<const CORE_COUNT: usize, PlatformImpl: Platform> SdeiState<CORE_COUNT, PlatformImpl> {
+    /// Creates the state for all PEs, as it is after reset.
+    pub const fn new() -> Self {
+        const {
+            assert!(
+                PlatformImpl::SDEI_EVENTS.len() < MAX_SDEI_EVENTS,
+                "Too many SDEI events"
+            );
+            let mut i = 0;
+            while i < PlatformImpl::SDEI_EVENTS.len() {
+                assert!(
+                    PlatformImpl::SDEI_EVENTS[i] != SDEI_EVENT_0,
+                    "SDEI event 0 must not be listed in SDEI_EVENTS"
+                );
+                let mut j = 0;
+                while j < i {
+                    assert!(
+                        PlatformImpl::SDEI_EVENTS[i] != PlatformImpl::SDEI_EVENTS[j],
+                        "Duplicate SDEI event"
+                    );
+                    j += 1;
+                }
+                i += 1;
+            }
+        }
+        Self {
+            cores: [const { SpinMutex::new(CoreState::RESET) }; CORE_COUNT],
+            _platform: PhantomData,
+        }
+    }
+
+    /// Marks the given event pending on the current PE.
+    ///
+    /// The event's handler will be entered the next time the normal world runs on this PE, once the
+    /// PE is unmasked and no other handler is running. Returns an error if the event doesn't exist
+    /// or the normal world hasn't registered and enabled a handler for it, in which case the event
+    /// is dropped.
+    pub fn dispatch(&self, event: u32) -> Result<(), SdeiError> {
+        let event_index = event_index::<PlatformImpl>(event)?;
+        let mut core = self.cores[CoresImpl::<PlatformImpl>::core_index()].lock();
+        let state = &mut core.events[event_index];
+        if !state
+            .registration
+            .is_some_and(|registration| registration.enabled)
+        {
+            warn!("Dropping SDEI event {event} which has no enabled handler");
+            return Err(SdeiError::Denied);
+        }
+        state.pending = true;
+        Ok(())
+    }
+
+    /// Resets the state of the current PE, as when it is turned on.
+    pub fn reset_core(&self) {
+        *self.cores[CoresImpl::<PlatformImpl>::core_index()].lock() = CoreState::RESET;
+    }
+}
+
+impl<const CORE_COUNT: usize, PlatformImpl: Platform> Default
+    for SdeiState<CORE_COUNT, PlatformImpl>
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Returns the index in the per-PE event table of the given event number.
+fn event_index<PlatformImpl: Platform>(event: u32) -> Result<usize, SdeiError> {
+    if event == SDEI_EVENT_0 {
+        return Ok(0);
+    }
+    PlatformImpl::SDEI_EVENTS
+        .iter()
+        .position(|&platform_event| platform_event == event)
+        .map(|index| index + 1)
+        .ok_or(SdeiError::InvalidParameters)
+}
+
+/// The SDEI service, handling calls from the normal world.
+pub struct Sdei<const CORE_COUNT: usize, PlatformImpl: 'static> {
+    state: &'static SdeiState<CORE_COUNT, PlatformImpl>,
+}
+
+impl<const CORE_COUNT: usize, PlatformImpl: CpuStateAccess + Platform>
+    Sdei<CORE_COUNT, PlatformImpl>
+{
+    /// Creates a new instance of the service, using the given per-PE state.
+    pub fn new(state: &'static SdeiState<CORE_COUNT, PlatformImpl>) -> Self {
+        Self { state }
+    }
+
+    /// Resets the state of the current PE, as when it is turned on.
+    pub fn reset_core(&self) {
+        self.state.reset_core();
+    }
+
+    /// Enters the handler of a pending event on the current PE, if any can be dispatched now.
+    ///
+    /// This must be called just before entering the normal world. Any return value in `regs` is
+    /// written to the normal world context first, so that it is part of the interrupted context.
+    pub fn deliver_pending(&self, regs: &mut SmcReturn) {
+        let mut core = self.state.cores[CoresImpl::<PlatformImpl>::core_index()].lock();
+        let Some(event_index) = core.next_dispatchable() else {
+            return;
+        };
+        let event = event_number::<PlatformImpl>(event_index);
+
+        exception_free(|token| {
+            let context = &mut PlatformImpl::cpu_state(token)[World::NonSecure];
+            let mut pending_return = context.gpregs.clone();
+            pending_return.write_return_value(regs);
+            let original = core::mem::replace(&mut context.gpregs, pending_return);
+            if core.enter_handler(event_index, event, context) {
+                regs.mark_empty();
+            } else {
+                context.gpregs = original;
+            }
+        });
+    }
+
+    fn handle(
+        &self,
+        regs: &mut SmcReturn,
+        core: &mut CoreState,
+        context: &mut CpuContext,
+    ) -> Result<(), SdeiError> {
+        let [function, a1, a2, a3, ..] = *regs.values() else {
+            unreachable!()
+        };
+
+        match function as u32 {
+            SDEI_VERSION => regs.set_from(VERSION),
+            SDEI_EVENT_REGISTER => {
+                let event_index = event_index::<PlatformImpl>(a1 as u32)?;
+                let client_el = context.el3_state.spsr_el3.exception_level();
+                if a2 == 0 || client_el == ExceptionLevel::El0 {
+                    return Err(SdeiError::InvalidParameters);
+                }
+                let event = &mut core.events[event_index];
+                if event.registration.is_some() {
+                    return Err(SdeiError::Denied);
+                }
+                // The routing mode and affinity are ignored for private events.
+                event.registration = Some(Registration {
+                    entry_point: a2,
+                    arg: a3,
+                    client_el,
+                    enabled: false,
+                    unregister_pending: false,
+                });
+                regs.set_from(SUCCESS);
+            }
+            SDEI_EVENT_ENABLE | SDEI_EVENT_DISABLE => {
+                let event_index = event_index::<PlatformImpl>(a1 as u32)?;
+                let registration = core.events[event_index]
+                    .registration
+                    .as_mut()
+                    .filter(|registration| !registration.unregister_pending)
+                    .ok_or(SdeiError::Denied)?;
+                registration.enabled = function as u32 == SDEI_EVENT_ENABLE;
+                regs.set_from(SUCCESS);
+            }
+            SDEI_EVENT_CONTEXT => {
+                let running = core.running.as_ref().ok_or(SdeiError::Denied)?;
+                let value = running
+                    .registers
+                    .get(a1 as usize)
+                    .ok_or(SdeiError::InvalidParameters)?;
+                regs.set_from(*value);
+            }
+            SDEI_EVENT_COMPLETE => {
+                core.complete(context, None)?;
+                regs.mark_empty();
+            }
+            SDEI_EVENT_COMPLETE_AND_RESUME => {
+                core.complete(context, Some(a1))?;
+                regs.mark_empty();
+            }
+            SDEI_EVENT_UNREGISTER => {
+                core.unregister(event_index::<PlatformImpl>(a1 as u32)?)?;
+                regs.set_from(SUCCESS);
+            }
+            SDEI_EVENT_STATUS => {
+                let event_index = event_index::<PlatformImpl>(a1 as u32)?;
+                let mut status = 0;
+                if let Some(registration) = core.events[event_index].registration {
+                    status |= STATUS_REGISTERED;
+                    if registration.enabled {
+                        status |= STATUS_ENABLED;
+                    }
+                }
+                if core.is_running(event_index) {
+                    status |= STATUS_RUNNING;
+                }
+                regs.set_from(status);
+            }
+            SDEI_EVENT_GET_INFO => {
+                event_index::<PlatformImpl>(a1 as u32)?;
+                match a2 {
+                    // All events are private, aren't signalled and have normal priority. The
+                    // routing information only applies to shared events.
+                    INFO_EV_TYPE | INFO_EV_SIGNALED | INFO_EV_PRIORITY => regs.set_from(0u64),
+                    _ => return Err(SdeiError::InvalidParameters),
+                }
+            }
+            SDEI_PE_MASK => {
+                let was_masked = core.masked;
+                core.masked = true;
+                regs.set_from(u64::from(!was_masked));
+            }
+            SDEI_PE_UNMASK => {
+                core.masked = false;
+                regs.set_from(SUCCESS);
+            }
+            SDEI_PRIVATE_RESET => {
+                for event_index in 0..MAX_SDEI_EVENTS {
+                    // Handlers which are running are unregistered once they complete.
+                    let _ = core.unregister(event_index);
+                }
+                regs.set_from(SUCCESS);
+            }
+            // There are no shared events to reset.
+            SDEI_SHARED_RESET => regs.set_from(SUCCESS),
+            _ => return Err(SdeiError::NotSupported),
+        }
+        Ok(())
+    }
+}
+
+/// Returns the event number of the given index in the per-PE event table.
+fn event_number<PlatformImpl: Platform>(event_index: usize) -> u32 {
+    if event_index == 0 {
+        SDEI_EVENT_0
+    } else {
+        PlatformImpl::SDEI_EVENTS[event_index - 1]
+    }
+}
+
+impl<const CORE_COUNT: usize, PlatformImpl: CpuStateAccess + Platform> Service
+    for Sdei<CORE_COUNT, PlatformImpl>
+{
+    owns!(
+        OwningEntityNumber::STANDARD_SECURE,
+        FUNCTION_NUMBER_MIN..=FUNCTION_NUMBER_MAX
+    );
+
+    fn handle_non_secure_smc(&self, regs: &mut SmcReturn) -> World {
+        let mut core = self.state.cores[CoresImpl::<PlatformImpl>::core_index()].lock();
+        exception_free(|token| {
+            let context = &mut PlatformImpl::cpu_state(token)[World::NonSecure];
+            if let Err(e) = self.handle(regs, &mut core, context) {
+                regs.set_from(e);
+            }
+        });
+        World::NonSecure
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::platform::test::TestPlatform;
+
+    const ENTRY_POINT: u64 = 0x8000_1000;
+    const HANDLER_ARG: u64 = 0x42;
+
+    type TestSdei = Sdei<{ TestPlatform::CORE_COUNT }, TestPlatform>;
+
+    fn call(core: &mut CoreState, context: &mut CpuContext, args: &[u64]) -> SmcReturn {
+        static STATE: SdeiState<{ TestPlatform::CORE_COUNT }, TestPlatform> = SdeiState::new();
+        let sdei = TestSdei::new(&STATE);
+        let mut regs = SmcReturn::EMPTY;
+        regs.mark_all_used()[..args.len()].copy_from_slice(args);
+        if let Err(e) = sdei.handle(&mut regs, core, context) {
+            regs.set_from(e);
+        }
+        regs
+    }
+
+    /// Returns a normal world context running at EL1.
+    fn el1_context() -> CpuContext {
+        let mut context = CpuContext::EMPTY;
+        context.el3_state.spsr_el3 = SpsrEl3::M_AARCH64_EL1H;
+        context
+    }
+
+    fn error(error: SdeiError) -> SmcReturn {
+        let mut regs = SmcReturn::EMPTY;
+        regs.set_from(error);
+        regs
+    }
+
+    fn value(value: u64) -> SmcReturn {
+        let mut regs = SmcReturn::EMPTY;
+        regs.set_from(value);
+        regs
+    }
+
+    #[test]
+    fn register_and_status() {
+        let mut core = CoreState::RESET;
+        let mut context = el1_context();
+
+        assert_eq!(
+            call(&mut core, &mut context, &[SDEI_VERSION.into()]),
+            value(VERSION)
+        );
+        assert_eq!(
+            call(&mut core, &mut context, &[SDEI_EVENT_STATUS.into(), 0]),
+            value(0)
+        );
+        assert_eq!(
+            call(&mut core, &mut context, &[SDEI_EVENT_STATUS.into(), 1234]),
+            error(SdeiError::InvalidParameters)
+        );
+        assert_eq!(
+            call(&mut core, &mut context, &[SDEI_EVENT_ENABLE.into(), 0]),
+            error(SdeiError::Denied)
+        );
+
+        let register = [
+            SDEI_EVENT_REGISTER.into(),
+            0,
+            ENTRY_POINT,
+            HANDLER_ARG,
+            0,
+            0,
+        ];
+        assert_eq!(call(&mut core, &mut context, &register), value(0));
+        assert_eq!(
+            call(&mut core, &mut context, &register),
+            error(SdeiError::Denied)
+        );
+        assert_eq!(
+            call(&mut core, &mut context, &[SDEI_EVENT_ENABLE.into(), 0]),
+            value(0)
+        );
+        assert_eq!(
+            call(&mut core, &mut context, &[SDEI_EVENT_STATUS.into(), 0]),
+            value(STATUS_REGISTERED | STATUS_ENABLED)
+        );
+
+        assert_eq!(
+            call(&mut core, &mut context, &[SDEI_EVENT_UNREGISTER.into(), 0]),
+            value(0)
+        );
+        assert_eq!(
+            call(&mut core, &mut context, &[SDEI_EVENT_STATUS.into(), 0]),
+            value(0)
+        );
+        assert_eq!(
+            call(&mut core, &mut context, &[SDEI_EVENT_UNREGISTER.into(), 0]),
+            error(SdeiError::Denied)
+        );
+    }
+
+    #[test]
+    fn pe_mask() {
+        let mut core = CoreState::RESET;
+        let mut context = el1_context();
+
+        assert!(core.masked);
+        assert_eq!(
+            call(&mut core, &mut context, &[SDEI_PE_UNMASK.into()]),
+            value(0)
+        );
+        assert_eq!(
+            call(&mut core, &mut context, &[SDEI_PE_MASK.into()]),
+            value(1)
+        );
+        assert_eq!(
+            call(&mut core, &mut context, &[SDEI_PE_MASK.into()]),
+            value(0)
+        );
+        assert!(core.masked);
+    }
+
+    #[test]
+    fn dispatch_and_complete() {
+        let mut core = CoreState::RESET;
+        let mut context = el1_context();
+        call(
+            &mut core,
+            &mut context,
+            &[
+                SDEI_EVENT_REGISTER.into(),
+                0,
+                ENTRY_POINT,
+                HANDLER_ARG,
+                0,
+                0,
+            ],
+        );
+        call(&mut core, &mut context, &[SDEI_EVENT_ENABLE.into(), 0]);
+        core.events[0].pending = true;
+
+        // Nothing is dispatched while the PE is masked.
+        assert_eq!(core.next_dispatchable(), None);
+        call(&mut core, &mut context, &[SDEI_PE_UNMASK.into()]);
+        assert_eq!(core.next_dispatchable(), Some(0));
+
+        context.gpregs.registers[..4].copy_from_slice(&[10, 11, 12, 13]);
+        context.el3_state.elr_el3 = 0x4000;
+        assert!(core.enter_handler(0, SDEI_EVENT_0, &mut context));
+        assert_eq!(
+            context.gpregs.registers[..4],
+            [0, HANDLER_ARG, 0x4000, SpsrEl3::M_AARCH64_EL1H.bits()]
+        );
+        assert_eq!(context.el3_state.elr_el3, ENTRY_POINT as usize);
+        assert_eq!(core.next_dispatchable(), None);
+        assert_eq!(
+            call(&mut core, &mut context, &[SDEI_EVENT_STATUS.into(), 0]),
+            value(STATUS_REGISTERED | STATUS_ENABLED | STATUS_RUNNING)
+        );
+        assert_eq!(
+            call(&mut core, &mut context, &[SDEI_EVENT_CONTEXT.into(), 2]),
+            value(12)
+        );
+        assert_eq!(
+            call(&mut core, &mut context, &[SDEI_EVENT_CONTEXT.into(), 18]),
+            error(SdeiError::InvalidParameters)
+        );
+
+        // Unregistering while the handler runs takes effect when it completes.
+        assert_eq!(
+            call(&mut core, &mut context, &[SDEI_EVENT_UNREGISTER.into(), 0]),
+            error(SdeiError::Pending)
+        );
+        assert_eq!(
+            call(&mut core, &mut context, &[SDEI_EVENT_COMPLETE.into(), 0]),
+            SmcReturn::EMPTY
+        );
+        assert_eq!(context.gpregs.registers[..4], [10, 11, 12, 13]);
+        assert_eq!(context.el3_state.elr_el3, 0x4000);
+        assert_eq!(context.el3_state.spsr_el3, SpsrEl3::M_AARCH64_EL1H);
+        assert_eq!(
+            call(&mut core, &mut context, &[SDEI_EVENT_STATUS.into(), 0]),
+            value(0)
+        );
+
+        assert_eq!(
+            call(&mut core, &mut context, &[SDEI_EVENT_COMPLETE.into(), 0]),
+            error(SdeiError::Denied)
+        );
+    }
+
+    #[test]
+    fn complete_and_resume() {
+        let mut core = CoreState::RESET;
+        let mut context = el1_context();
+        call(
+            &mut core,
+            &mut context,
+            &[
+                SDEI_EVENT_REGISTER.into(),
+                0,
+                ENTRY_POINT,
+                HANDLER_ARG,
+                0,
+                0,
+            ],
+        );
+        call(&mut core, &mut context, &[SDEI_EVENT_ENABLE.into(), 0]);
+        call(&mut core, &mut context, &[SDEI_PE_UNMASK.into()]);
+        core.events[0].pending = true;
+
+        context.el3_state.elr_el3 = 0x4000;
+        assert!(core.enter_handler(0, SDEI_EVENT_0, &mut context));
+        assert_eq!(
+            call(
+                &mut core,
+                &mut context,
+                &[SDEI_EVENT_COMPLETE_AND_RESUME.into(), 0x9000]
+            ),
+            SmcReturn::EMPTY
+        );
+        assert_eq!(context.el3_state.elr_el3, 0x9000);
+        assert_eq!(
+            context.el3_state.spsr_el3.exception_level(),
+            ExceptionLevel::El1
+        );
+        assert_eq!(
+            call(&mut core, &mut context, &[SDEI_EVENT_STATUS.into(), 0]),
+            value(STATUS_REGISTERED | STATUS_ENABLED)
+        );
+    }
+
+    #[test]
+    fn not_dispatched_above_client_el() {
+        let mut core = CoreState::RESET;
+        let mut context = el1_context();
+        call(
+            &mut core,
+            &mut context,
+            &[
+                SDEI_EVENT_REGISTER.into(),
+                0,
+                ENTRY_POINT,
+                HANDLER_ARG,
+                0,
+                0,
+            ],
+        );
+        call(&mut core, &mut context, &[SDEI_EVENT_ENABLE.into(), 0]);
+        core.events[0].pending = true;
+
+        context.el3_state.spsr_el3 = SpsrEl3::M_AARCH64_EL2H;
+        context.el3_state.elr_el3 = 0x4000;
+        assert!(!core.enter_handler(0, SDEI_EVENT_0, &mut context));
+        assert_eq!(context.el3_state.elr_el3, 0x4000);
+        assert!(core.running.is_none());
+        assert!(core.events[0].pending);
+    }
+
+    #[test]
+    fn unsupported() {
+        let mut core = CoreState::RESET;
+        let mut context = el1_context();
+        assert_eq!(
+            call(&mut core, &mut context, &[0xC400_002F, 0]),
+            error(SdeiError::NotSupported)
+        );
+    }
+}
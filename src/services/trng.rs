@@ -5,13 +5,18 @@
 //! Service implementing the Arm True Random Number Generator Firmware Interface, as specified by
 //! Arm DEN 0098.
 
+mod rndr;
+
+pub use rndr::RndrTrngPlatformImpl;
+
 use crate::{
     context::World,
+    scrub::{Secret, zeroize},
     services::{Service, owns},
     smccc::{FunctionId, OwningEntityNumber, SUCCESS, SetFrom, SmcReturn},
+    spin_mutex::SpinMutex,
 };
 use core::marker::PhantomData;
-use spin::mutex::SpinMutex;
 use uuid::Uuid;
 
 // TRNG SMC function identifiers, as defined in the TRNG Firmware Interface
@@ -68,13 +73,22 @@ const fn bits_in_pool(words_in_pool: usize) -> usize {
 /// Platform-specific TRNG interface.
 /// The platform must provide an implementation for this trait. If the platform
 /// does not have a TRNG source, then it can use the default implementation,
-/// `NotSupportedTrngPlatformImpl`
+/// `NotSupportedTrngPlatformImpl`. Platforms implementing FEAT_RNG can use
+/// `RndrTrngPlatformImpl`.
 ///
 /// `REQ_WORDS` is the number of 64-bit words per request from the TRNG.
 pub trait TrngPlatformInterface<const REQ_WORDS: usize> {
     /// A UUID for the entropy source, or nil (all-zero) if not implemented.
     const TRNG_UUID: Uuid = Uuid::nil();
 
+    /// Returns whether the entropy source is actually present on this system.
+    ///
+    /// This is checked once at boot, and if it returns false then the TRNG
+    /// service reports that it is not implemented.
+    fn entropy_source_present() -> bool {
+        true
+    }
+
     /// Perform any necessary platform-specific setup for the entropy source.
     fn entropy_setup() {}
 
@@ -100,7 +114,7 @@ struct EntropyPool<
     entropy: [u64; WORDS_IN_POOL],
     entropy_bit_index: usize,
     entropy_bit_size: usize,
-    _platform: PhantomData<TrngPlatformImpl>,
+    _platform: PhantomData<fn() -> TrngPlatformImpl>,
 }
 
 impl<
@@ -136,6 +150,13 @@ impl<
         Ok(())
     }
 
+    /// Discards all entropy in the pool, zeroing it.
+    fn scrub(&mut self) {
+        zeroize(&mut self.entropy);
+        self.entropy_bit_index = 0;
+        self.entropy_bit_size = 0;
+    }
+
     /// Pack entropy into the out buffer, filling the entropy pool as needed.
     /// Returns Ok on success, and an error on failure.
     /// Note: out must have enough space for nbits of entropy
@@ -290,6 +311,8 @@ pub struct Trng<
     TrngPlatformImpl: TrngPlatformInterface<REQ_WORDS>,
 > {
     pool: SpinMutex<EntropyPool<REQ_WORDS, WORDS_IN_POOL, TrngPlatformImpl>>,
+    /// Whether the platform has an entropy source, so the TRNG interface is implemented.
+    present: bool,
 }
 
 impl<
@@ -320,6 +343,17 @@ impl<
     }
 }
 
+impl<
+    const REQ_WORDS: usize,
+    const WORDS_IN_POOL: usize,
+    TrngPlatformImpl: TrngPlatformInterface<REQ_WORDS>,
+> Secret for Trng<REQ_WORDS, WORDS_IN_POOL, TrngPlatformImpl>
+{
+    fn scrub(&self) {
+        self.pool.lock().scrub();
+    }
+}
+
 impl<
     const REQ_WORDS: usize,
     const WORDS_IN_POOL: usize,
@@ -327,10 +361,29 @@ impl<
 > Trng<REQ_WORDS, WORDS_IN_POOL, TrngPlatformImpl>
 {
     pub(super) fn new() -> Self {
-        TrngPlatformImpl::entropy_setup();
+        let present =
+            !TrngPlatformImpl::TRNG_UUID.is_nil() && TrngPlatformImpl::entropy_source_present();
+        if present {
+            TrngPlatformImpl::entropy_setup();
+        }
         Self {
             pool: SpinMutex::new(EntropyPool::new()),
+            present,
+        }
+    }
+
+    /// Returns a word of entropy for use by EL3 itself, or `None` if the platform has no entropy
+    /// source or it is out of entropy.
+    pub(super) fn entropy_u64(&self) -> Option<u64> {
+        if !self.present {
+            return None;
         }
+        let mut out = [0];
+        self.pool
+            .lock()
+            .pack_entropy(BITS_PER_WORD, &mut out)
+            .ok()?;
+        Some(out[0])
     }
 
     fn handle_smc_common(&self, regs: &mut SmcReturn) {
@@ -338,7 +391,7 @@ impl<
         let mut function = FunctionId(in_regs[0] as u32);
         function.clear_sve_hint();
 
-        if TrngPlatformImpl::TRNG_UUID.is_nil() {
+        if !self.present {
             regs.set_from(TrngError::NotSupported);
             return;
         }
@@ -434,6 +487,22 @@ mod tests {
         assert_eq!(pool.entropy_bit_index, nbits);
     }
 
+    #[test]
+    fn scrub_discards_entropy() {
+        let mut pool = EntropyPool::<TRNG_REQ_WORDS, WORDS_IN_POOL, TestTrngPlatformImpl>::new();
+        let mut out = [0u64; 1];
+
+        pool.pack_entropy(23, &mut out).unwrap();
+        pool.scrub();
+        assert_eq!(pool.entropy, [0; WORDS_IN_POOL]);
+        assert_eq!(pool.entropy_bit_size, 0);
+        assert_eq!(pool.entropy_bit_index, 0);
+
+        // The pool is refilled from the platform on the next request.
+        pool.pack_entropy(64, &mut out).unwrap();
+        assert_eq!(out[0], u64::MAX);
+    }
+
     #[test]
     fn pack_entropy_one_word() {
         let mut pool = EntropyPool::<TRNG_REQ_WORDS, WORDS_IN_POOL, TestTrngPlatformImpl>::new();
@@ -531,6 +600,23 @@ mod tests {
         assert_eq!(regs, expected);
     }
 
+    #[test]
+    fn trng_not_present() {
+        type AbsentTrngPlatformImpl = RndrTrngPlatformImpl<0x1234>;
+        let trng = Trng::<1, { words_in_pool(1) }, AbsentTrngPlatformImpl>::new();
+        let mut regs = SmcReturn::EMPTY;
+        let mut expected = SmcReturn::EMPTY;
+
+        regs.set_from(ARM_TRNG_VERSION);
+        expected.set_from(TrngError::NotSupported);
+        trng.handle_smc_common(&mut regs);
+        assert_eq!(regs, expected);
+
+        regs.set_args2(ARM_TRNG_RND64 as u64, 64);
+        trng.handle_smc_common(&mut regs);
+        assert_eq!(regs, expected);
+    }
+
     #[test]
     fn trng_features() {
         let trng = Trng::<TRNG_REQ_WORDS, WORDS_IN_POOL, TestTrngPlatformImpl>::new();
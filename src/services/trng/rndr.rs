@@ -0,0 +1,52 @@
+// Copyright The Rusted Firmware-A Contributors.
+//
+// SPDX-License-Identifier: BSD-3-Clause
+
+//! TRNG backend using the `RNDRRS` register provided by FEAT_RNG.
+
+use super::{TrngError, TrngPlatformInterface};
+use crate::rng::{is_feat_rng_present, read_rndrrs};
+use uuid::Uuid;
+
+/// TRNG platform implementation backed by the `RNDRRS` register of FEAT_RNG, which returns a random
+/// number from a freshly reseeded DRBG.
+///
+/// `UUID` identifies the entropy source behind FEAT_RNG on the platform, and must not be nil.
+pub struct RndrTrngPlatformImpl<const UUID: u128>;
+
+impl<const UUID: u128> TrngPlatformInterface<1> for RndrTrngPlatformImpl<UUID> {
+    const TRNG_UUID: Uuid = Uuid::from_u128(UUID);
+
+    fn entropy_source_present() -> bool {
+        is_feat_rng_present()
+    }
+
+    fn get_entropy() -> Result<[u64; 1], TrngError> {
+        read_rndrrs()
+            .map(|value| [value])
+            .map_err(|_| TrngError::NoEntropy)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rng::{IdAa64isar0El1, fake::with_fake_rng};
+
+    const TEST_UUID: u128 = 0x0123_4567_89ab_cdef_0123_4567_89ab_cdef;
+
+    #[test]
+    fn entropy_from_rndrrs() {
+        type Impl = RndrTrngPlatformImpl<TEST_UUID>;
+        assert_eq!(Impl::TRNG_UUID, Uuid::from_u128(TEST_UUID));
+        assert!(!Impl::entropy_source_present());
+        assert_eq!(Impl::get_entropy(), Err(TrngError::NoEntropy));
+
+        with_fake_rng(|fake| {
+            fake.id_aa64isar0_el1 = IdAa64isar0El1::from_bits_retain(1 << 60);
+            fake.value = 1234;
+        });
+        assert!(Impl::entropy_source_present());
+        assert_eq!(Impl::get_entropy(), Ok([1234]));
+    }
+}
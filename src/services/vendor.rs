@@ -0,0 +1,456 @@
+// Copyright The Rusted Firmware-A Contributors.
+//
+// SPDX-License-Identifier: BSD-3-Clause
+
+//! Vendor-specific EL3 monitor service, which dispatches SMCs in OEN 7 to handlers registered by
+//! RF-A and the platform.
+//!
+//! The service itself only implements the generic UID and revision queries. Every other function
+//! number is routed to whichever registered [`VendorHandler`] claims it, including those of the
+//! [debug service](super::debug).
+
+use crate::{
+    build_info::BUILD_INFO,
+    context::World,
+    nv_counter::NvCounterError,
+    platform::Platform,
+    services::{
+        Service,
+        debug::{InterruptLatencyPhase, WorldSwitchReason},
+        ffa::spmd::Spmd,
+        owns,
+    },
+    smccc::OwningEntityNumber,
+    spin_mutex::SpinMutex,
+};
+use arrayvec::ArrayVec;
+use core::ops::RangeInclusive;
+use log::info;
+use spin::Once;
+use uuid::Uuid;
+
+// Re-exported for platforms implementing `VendorHandler`.
+pub use crate::smccc::{FunctionId, INVALID_PARAMETER, NOT_SUPPORTED, SUCCESS, SetFrom, SmcReturn};
+
+/// The maximum number of handlers which may be registered.
+pub const MAX_VENDOR_HANDLERS: usize = 8;
+
+/// Function numbers reserved by SMCCC for the generic queries of each service.
+const GENERIC_FUNCTION_NUMBERS: RangeInclusive<u16> = 0xFF00..=0xFFFF;
+
+const VENDOR_EL3_CALL_UID: u32 = 0x8700_FF01;
+const VENDOR_EL3_REVISION: u32 = 0x8700_FF03;
+
+/// The UID returned by `VENDOR_EL3_CALL_UID`, identifying RF-A as the implementer of the service.
+const VENDOR_EL3_UID: Uuid = Uuid::from_u128(0x5f3c8a2e_9b47_4d61_a0c3_7e12b9d4f608);
+
+const VENDOR_EL3_REVISION_MAJOR: u32 = 1;
+const VENDOR_EL3_REVISION_MINOR: u32 = 0;
+
+/// A handler for some range of vendor-specific EL3 monitor SMCs.
+pub trait VendorHandler: Sync {
+    /// Returns the range of function numbers, i.e. bits [15:0] of the function ID, which this
+    /// handler owns.
+    fn function_numbers(&self) -> RangeInclusive<u16>;
+
+    /// Handles an SMC from the normal world with a function number in this handler's range.
+    ///
+    /// `function` is the function ID from x0, with the SVE hint bit cleared.
+    fn handle_non_secure_smc(&self, function: FunctionId, regs: &mut SmcReturn);
+}
+
+/// The handlers registered for vendor-specific EL3 monitor SMCs.
+///
+/// Handlers are registered on the primary core during cold boot, and must not overlap with each
+/// other or with the generic queries.
+pub struct VendorHandlers {
+    handlers: SpinMutex<ArrayVec<&'static dyn VendorHandler, MAX_VENDOR_HANDLERS>>,
+}
+
+impl VendorHandlers {
+    /// Creates an empty list of handlers.
+    pub const fn new() -> Self {
+        Self {
+            handlers: SpinMutex::new(ArrayVec::new_const()),
+        }
+    }
+
+    /// Adds the given handler to the list.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `MAX_VENDOR_HANDLERS` handlers have already been registered, or if the handler's
+    /// function numbers overlap with those of another handler or are reserved.
+    pub fn register(&self, handler: &'static dyn VendorHandler) {
+        let numbers = handler.function_numbers();
+        assert!(
+            !numbers.is_empty() && !overlaps(&numbers, &GENERIC_FUNCTION_NUMBERS),
+            "Vendor handler function numbers {numbers:#x?} are reserved"
+        );
+        let mut handlers = self.handlers.lock();
+        assert!(
+            handlers
+                .iter()
+                .all(|other| !overlaps(&numbers, &other.function_numbers())),
+            "Vendor handler function numbers {numbers:#x?} are already registered"
+        );
+        handlers
+            .try_push(handler)
+            .expect("Too many vendor handlers registered");
+    }
+
+    /// Returns the handler which owns the given function number, if any.
+    fn handler_for(&self, number: u16) -> Option<&'static dyn VendorHandler> {
+        self.handlers
+            .lock()
+            .iter()
+            .copied()
+            .find(|handler| handler.function_numbers().contains(&number))
+    }
+}
+
+impl Default for VendorHandlers {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn overlaps(a: &RangeInclusive<u16>, b: &RangeInclusive<u16>) -> bool {
+    a.start() <= b.end() && b.start() <= a.end()
+}
+
+/// Vendor-specific EL3 monitor service, dispatching to the registered [`VendorHandler`]s.
+pub struct VendorEl3Service {
+    handlers: VendorHandlers,
+}
+
+impl VendorEl3Service {
+    pub(super) const fn new() -> Self {
+        Self {
+            handlers: VendorHandlers::new(),
+        }
+    }
+
+    /// Returns the list of handlers to which SMCs are dispatched.
+    pub fn handlers(&self) -> &VendorHandlers {
+        &self.handlers
+    }
+}
+
+impl Service for VendorEl3Service {
+    owns!(OwningEntityNumber::VENDOR_SPECIFIC_EL3_MONITOR);
+
+    fn handle_non_secure_smc(&self, regs: &mut SmcReturn) -> World {
+        let mut function = FunctionId(regs.values()[0] as u32);
+        function.clear_sve_hint();
+
+        match function.0 {
+            VENDOR_EL3_CALL_UID => regs.set_from(&VENDOR_EL3_UID),
+            VENDOR_EL3_REVISION => {
+                regs.set_args2(
+                    VENDOR_EL3_REVISION_MAJOR.into(),
+                    VENDOR_EL3_REVISION_MINOR.into(),
+                );
+            }
+            _ => {
+                if let Some(handler) = self.handlers.handler_for(function.number()) {
+                    handler.handle_non_secure_smc(function, regs);
+                } else {
+                    regs.set_from(NOT_SUPPORTED);
+                }
+            }
+        }
+
+        World::NonSecure
+    }
+}
+
+const RFA_PERF_DUMP: u32 = 0x8700_0021;
+const RFA_ROLLBACK_VERSION: u32 = 0x8700_0022;
+
+/// How the image's anti-rollback version compares to the platform's trusted firmware NV counter.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum RollbackStatus {
+    /// The image's version is at least the value of the counter.
+    Current = 0,
+    /// The image's version is lower than the value of the counter, so an older image than the
+    /// platform has accepted has been booted.
+    RolledBack = 1,
+    /// The counter couldn't be read, e.g. because the platform doesn't have one.
+    Unknown = 2,
+}
+
+impl RollbackStatus {
+    /// Compares the given anti-rollback version against the given result of reading the counter.
+    fn new(version: u32, counter: Result<u32, NvCounterError>) -> Self {
+        match counter {
+            Ok(counter) if version < counter => Self::RolledBack,
+            Ok(_) => Self::Current,
+            Err(_) => Self::Unknown,
+        }
+    }
+}
+
+/// RF-A's own vendor handler, owning function numbers `0x20`–`0x2F`.
+///
+/// This serves as a reference for platforms adding their own handlers: it reports the anti-rollback
+/// version, and dumps the performance measurements collected by EL3 to the log. The firmware version
+/// and build information are reported by the debug service's `DEBUG_BUILD_INFO`.
+pub struct RfaVendorHandler<const CORE_COUNT: usize, PlatformImpl: Platform + 'static> {
+    spm: fn() -> &'static Spmd<CORE_COUNT, PlatformImpl>,
+    /// The value of the trusted firmware NV counter read during cold boot.
+    trusted_firmware_counter: Once<Result<u32, NvCounterError>>,
+}
+
+impl<const CORE_COUNT: usize, PlatformImpl: Platform> RfaVendorHandler<CORE_COUNT, PlatformImpl> {
+    pub(super) fn new(spm: fn() -> &'static Spmd<CORE_COUNT, PlatformImpl>) -> Self {
+        Self {
+            spm,
+            trusted_firmware_counter: Once::new(),
+        }
+    }
+
+    /// Records the value of the trusted firmware NV counter, to compare against the image's
+    /// anti-rollback version.
+    ///
+    /// This should be called once on the primary core during cold boot.
+    pub(super) fn set_trusted_firmware_counter(&self, counter: Result<u32, NvCounterError>) {
+        self.trusted_firmware_counter.call_once(|| counter);
+    }
+
+    /// Returns the image's anti-rollback version, the value of the trusted firmware NV counter (or
+    /// 0 if it is unknown) and how they compare.
+    fn rollback_version(&self) -> (u32, u32, RollbackStatus) {
+        let counter = self
+            .trusted_firmware_counter
+            .get()
+            .copied()
+            .unwrap_or(Err(NvCounterError::NotSupported));
+        (
+            BUILD_INFO.rollback_version,
+            counter.unwrap_or(0),
+            RollbackStatus::new(BUILD_INFO.rollback_version, counter),
+        )
+    }
+
+    /// Logs the world switch and interrupt latency counters of every core.
+    fn perf_dump(&self) {
+        let spm = (self.spm)();
+        for core_index in 0..CORE_COUNT {
+            for reason in [
+                WorldSwitchReason::NonSecureSmc,
+                WorldSwitchReason::SecureInterrupt,
+                WorldSwitchReason::FfaCompletion,
+                WorldSwitchReason::PsciEvent,
+            ] {
+                let counter = spm.world_switch_stats().get(core_index, reason).unwrap();
+                info!(
+                    "Core {core_index} world switch {reason:?}: {} in {} ticks",
+                    counter.count, counter.ticks
+                );
+            }
+            for phase in [
+                InterruptLatencyPhase::El3Handling,
+                InterruptLatencyPhase::SpmcDelegation,
+            ] {
+                let counter = spm
+                    .interrupt_latency_stats()
+                    .get(core_index, phase)
+                    .unwrap();
+                info!(
+                    "Core {core_index} interrupt latency {phase:?}: {} in {} ticks, max {}",
+                    counter.count, counter.ticks, counter.max_ticks
+                );
+            }
+        }
+    }
+}
+
+impl<const CORE_COUNT: usize, PlatformImpl: Platform> VendorHandler
+    for RfaVendorHandler<CORE_COUNT, PlatformImpl>
+{
+    fn function_numbers(&self) -> RangeInclusive<u16> {
+        0x0020..=0x002F
+    }
+
+    fn handle_non_secure_smc(&self, function: FunctionId, regs: &mut SmcReturn) {
+        match function.0 {
+            // As for `DEBUG_DUMP_POWER_DOMAINS`, only debug builds let the normal world flood the
+            // console with the dump.
+            RFA_PERF_DUMP if cfg!(debug_assertions) => {
+                self.perf_dump();
+                regs.set_from(SUCCESS);
+            }
+            RFA_ROLLBACK_VERSION => {
+                let (version, counter, status) = self.rollback_version();
+                regs.set_args4(
+                    SUCCESS as u64,
+                    version.into(),
+                    counter.into(),
+                    status as u64,
+                );
+            }
+            _ => regs.set_from(NOT_SUPPORTED),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        platform::test::TestPlatform,
+        services::{
+            debug::{DebugService, SmcAuditBuffer},
+            deferred::DeferredWorkQueue,
+            ffa::spmd::MailboxState,
+        },
+    };
+    use std::sync::LazyLock;
+
+    static SMC_AUDIT: SmcAuditBuffer<{ TestPlatform::CORE_COUNT }> = SmcAuditBuffer::new();
+    static DEFERRED_WORK: DeferredWorkQueue<{ TestPlatform::CORE_COUNT }, TestPlatform> =
+        DeferredWorkQueue::new();
+    static MAILBOX_STATE: MailboxState<{ TestPlatform::CORE_COUNT }> = MailboxState::new();
+    static SPMD: LazyLock<Spmd<{ TestPlatform::CORE_COUNT }, TestPlatform>> =
+        LazyLock::new(|| Spmd::new(&SMC_AUDIT, &DEFERRED_WORK, &MAILBOX_STATE));
+
+    struct FakeHandler(RangeInclusive<u16>);
+
+    impl VendorHandler for FakeHandler {
+        fn function_numbers(&self) -> RangeInclusive<u16> {
+            self.0.clone()
+        }
+
+        fn handle_non_secure_smc(&self, function: FunctionId, regs: &mut SmcReturn) {
+            regs.set_args2(SUCCESS as u64, function.0.into());
+        }
+    }
+
+    fn call(service: &VendorEl3Service, function: u32) -> SmcReturn {
+        let mut regs = SmcReturn::EMPTY;
+        regs.set_from(function);
+        assert_eq!(service.handle_non_secure_smc(&mut regs), World::NonSecure);
+        regs
+    }
+
+    #[test]
+    fn owns() {
+        let service = VendorEl3Service::new();
+        assert!(service.owns(FunctionId(VENDOR_EL3_CALL_UID)));
+        assert!(service.owns(FunctionId(RFA_PERF_DUMP)));
+        assert!(service.owns(FunctionId(0xC700_0100)));
+        assert!(service.owns(FunctionId(0x8700_0010)));
+        assert!(!service.owns(FunctionId(0x8600_0020)));
+    }
+
+    #[test]
+    fn generic_queries() {
+        let service = VendorEl3Service::new();
+
+        let regs = call(&service, VENDOR_EL3_CALL_UID);
+        let uid = Uuid::from_u128_le(
+            regs.values()[0] as u128
+                | ((regs.values()[1] as u128) << 32)
+                | ((regs.values()[2] as u128) << 64)
+                | ((regs.values()[3] as u128) << 96),
+        );
+        assert_eq!(uid, VENDOR_EL3_UID);
+
+        let regs = call(&service, VENDOR_EL3_REVISION);
+        assert_eq!(regs.values(), [1, 0]);
+    }
+
+    #[test]
+    fn dispatch_to_handler() {
+        static HANDLER: FakeHandler = FakeHandler(0x0100..=0x01FF);
+
+        let service = VendorEl3Service::new();
+        assert_eq!(call(&service, 0xC700_0100).values(), [NOT_SUPPORTED as u64]);
+
+        service.handlers().register(&HANDLER);
+        assert_eq!(
+            call(&service, 0xC700_0100).values(),
+            [SUCCESS as u64, 0xC700_0100]
+        );
+        // The SVE hint is cleared before the handler sees the function ID.
+        assert_eq!(
+            call(&service, 0xC701_01FF).values(),
+            [SUCCESS as u64, 0xC700_01FF]
+        );
+        assert_eq!(call(&service, 0xC700_0200).values(), [NOT_SUPPORTED as u64]);
+    }
+
+    #[test]
+    #[should_panic(expected = "already registered")]
+    fn overlapping_handlers() {
+        static FIRST: FakeHandler = FakeHandler(0x0100..=0x01FF);
+        static SECOND: FakeHandler = FakeHandler(0x01F0..=0x0200);
+
+        let handlers = VendorHandlers::new();
+        handlers.register(&FIRST);
+        handlers.register(&SECOND);
+    }
+
+    #[test]
+    #[should_panic(expected = "already registered")]
+    fn debug_range_registered() {
+        static DEBUG: LazyLock<DebugService<{ TestPlatform::CORE_COUNT }, TestPlatform>> =
+            LazyLock::new(|| DebugService::new(|| &SPMD, || unimplemented!(), || &()));
+        static HANDLER: FakeHandler = FakeHandler(0x0000..=0x0010);
+
+        let service = VendorEl3Service::new();
+        service.handlers().register(&*DEBUG);
+        assert_eq!(call(&service, 0x8700_0010).values(), [0x0001_0000]);
+
+        service.handlers().register(&HANDLER);
+    }
+
+    #[test]
+    #[should_panic(expected = "reserved")]
+    fn generic_range_reserved() {
+        static HANDLER: FakeHandler = FakeHandler(0xFF03..=0xFF03);
+
+        VendorHandlers::new().register(&HANDLER);
+    }
+
+    #[test]
+    fn rfa_handler() {
+        static HANDLER: LazyLock<RfaVendorHandler<{ TestPlatform::CORE_COUNT }, TestPlatform>> =
+            LazyLock::new(|| RfaVendorHandler::new(|| &SPMD));
+
+        let service = VendorEl3Service::new();
+        service.handlers().register(&*HANDLER);
+
+        assert_eq!(call(&service, 0x8700_0020).values(), [NOT_SUPPORTED as u64]);
+        assert_eq!(call(&service, RFA_PERF_DUMP).values(), [SUCCESS as u64]);
+        assert_eq!(call(&service, 0x8700_002F).values(), [NOT_SUPPORTED as u64]);
+
+        // The test platform's NV counters aren't readable.
+        assert_eq!(
+            call(&service, RFA_ROLLBACK_VERSION).values(),
+            [
+                SUCCESS as u64,
+                BUILD_INFO.rollback_version.into(),
+                0,
+                RollbackStatus::Unknown as u64,
+            ]
+        );
+        HANDLER.set_trusted_firmware_counter(Err(NvCounterError::NotSupported));
+        assert_eq!(
+            call(&service, RFA_ROLLBACK_VERSION).values()[3],
+            RollbackStatus::Unknown as u64
+        );
+    }
+
+    #[test]
+    fn rollback_status() {
+        assert_eq!(RollbackStatus::new(3, Ok(2)), RollbackStatus::Current);
+        assert_eq!(RollbackStatus::new(3, Ok(3)), RollbackStatus::Current);
+        assert_eq!(RollbackStatus::new(3, Ok(4)), RollbackStatus::RolledBack);
+        assert_eq!(
+            RollbackStatus::new(3, Err(NvCounterError::NotSupported)),
+            RollbackStatus::Unknown
+        );
+    }
+}
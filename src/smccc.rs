@@ -202,8 +202,10 @@ macro_rules! define_set_args {
 }
 
 impl SmcReturn {
+    /// The maximum number of values which may be returned, in x0 to x17.
     pub const MAX_VALUES: usize = 18;
 
+    /// No return values.
     pub const EMPTY: Self = Self {
         used: 0,
         values: [0; 18],
@@ -263,6 +265,7 @@ impl PartialEq for SmcReturn {
 /// Implementing this trait for a large `T` type (> 8 bytes) is highly discouraged due to
 /// performance reasons.
 pub trait SetFrom<T> {
+    /// Sets `self` from the given value.
     fn set_from(&mut self, value: T);
 }
 
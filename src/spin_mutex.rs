@@ -0,0 +1,298 @@
+// Copyright The Rusted Firmware-A Contributors.
+//
+// SPDX-License-Identifier: BSD-3-Clause
+
+//! A spinlock which detects deadlocks in debug builds.
+//!
+//! [`SpinMutex`] wraps the `spin` crate's spinlock. In debug builds it records which core holds the
+//! lock and where it was acquired, and a core which spins for longer than [`DEADLOCK_TIMEOUT_MS`]
+//! waiting for the lock panics with that information rather than hanging silently. In release
+//! builds it behaves exactly like the wrapped lock, and is no bigger.
+
+use crate::{
+    debug::DEBUG,
+    timer::{self, Timeout},
+};
+use arm_sysregs::read_mpidr_el1;
+use core::{
+    fmt::{self, Debug, Formatter},
+    hint::spin_loop,
+    mem::ManuallyDrop,
+    ops::{Deref, DerefMut},
+    panic::Location,
+    ptr,
+};
+#[cfg(debug_assertions)]
+use core::{
+    ptr::null_mut,
+    sync::atomic::{AtomicPtr, AtomicU64, Ordering},
+};
+
+/// How long a core may spin waiting for a lock in debug builds before it is treated as a deadlock.
+pub const DEADLOCK_TIMEOUT_MS: u64 = 1000;
+
+/// The core which holds a [`SpinMutex`], and where it acquired it.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+struct Owner {
+    mpidr: u64,
+    location: &'static Location<'static>,
+}
+
+/// Where an [`Owner`] is recorded while a lock is held. Empty in release builds.
+struct OwnerCell {
+    /// The MPIDR of the core which holds the lock.
+    #[cfg(debug_assertions)]
+    mpidr: AtomicU64,
+    /// Where the lock was acquired, or null if it isn't held.
+    #[cfg(debug_assertions)]
+    location: AtomicPtr<Location<'static>>,
+}
+
+impl OwnerCell {
+    const fn new() -> Self {
+        Self {
+            #[cfg(debug_assertions)]
+            mpidr: AtomicU64::new(0),
+            #[cfg(debug_assertions)]
+            location: AtomicPtr::new(null_mut()),
+        }
+    }
+
+    #[cfg_attr(not(debug_assertions), expect(unused_variables))]
+    fn set(&self, location: &'static Location<'static>) {
+        #[cfg(debug_assertions)]
+        {
+            self.mpidr.store(read_mpidr_el1().bits(), Ordering::Relaxed);
+            self.location
+                .store(ptr::from_ref(location).cast_mut(), Ordering::Relaxed);
+        }
+    }
+
+    fn clear(&self) {
+        #[cfg(debug_assertions)]
+        self.location.store(null_mut(), Ordering::Relaxed);
+    }
+
+    /// Returns the core which holds the lock and where it acquired it, if it is held and this is a
+    /// debug build.
+    ///
+    /// This is only a snapshot, as the lock may be released and acquired by another core at any
+    /// time.
+    fn get(&self) -> Option<Owner> {
+        #[cfg(debug_assertions)]
+        {
+            let location = self.location.load(Ordering::Relaxed);
+            // SAFETY: `location` is only ever set from a `&'static Location`, or to null.
+            let location = unsafe { location.as_ref() }?;
+            Some(Owner {
+                mpidr: self.mpidr.load(Ordering::Relaxed),
+                location,
+            })
+        }
+        #[cfg(not(debug_assertions))]
+        None
+    }
+}
+
+/// A spinlock which, in debug builds, panics rather than spinning forever if it is never released.
+pub struct SpinMutex<T> {
+    inner: spin::mutex::SpinMutex<T>,
+    owner: OwnerCell,
+}
+
+impl<T> SpinMutex<T> {
+    /// Creates a new unlocked mutex containing the given value.
+    pub const fn new(value: T) -> Self {
+        Self {
+            inner: spin::mutex::SpinMutex::new(value),
+            owner: OwnerCell::new(),
+        }
+    }
+
+    /// Acquires the lock, spinning until it is available.
+    ///
+    /// In debug builds, panics with the core which holds the lock and where it was acquired if it
+    /// isn't available within [`DEADLOCK_TIMEOUT_MS`].
+    #[track_caller]
+    pub fn lock(&self) -> SpinMutexGuard<'_, T> {
+        let guard = if DEBUG {
+            self.lock_with_watchdog()
+        } else {
+            self.inner.lock()
+        };
+        self.acquired(guard, Location::caller())
+    }
+
+    /// Acquires the lock if it is available, or returns `None` if it is held.
+    #[track_caller]
+    pub fn try_lock(&self) -> Option<SpinMutexGuard<'_, T>> {
+        let guard = self.inner.try_lock()?;
+        Some(self.acquired(guard, Location::caller()))
+    }
+
+    #[track_caller]
+    fn lock_with_watchdog(&self) -> spin::mutex::SpinMutexGuard<'_, T> {
+        if let Some(guard) = self.inner.try_lock() {
+            return guard;
+        }
+        // The counter frequency may not have been configured yet early in cold boot, in which case
+        // there is no way to tell how long we have been waiting.
+        let timeout = (timer::frequency() != 0).then(|| Timeout::from_millis(DEADLOCK_TIMEOUT_MS));
+        match self.spin_until(|| timeout.is_some_and(|timeout| timeout.expired())) {
+            Ok(guard) => guard,
+            Err(owner) => panic!(
+                "Deadlock: core {:#x} waited over {DEADLOCK_TIMEOUT_MS} ms for lock at {}, {}",
+                read_mpidr_el1().bits(),
+                Location::caller(),
+                OwnerDisplay(owner),
+            ),
+        }
+    }
+
+    /// Spins until either the lock is acquired or `expired` returns true, in which case returns the
+    /// owner of the lock if it is known.
+    fn spin_until(
+        &self,
+        mut expired: impl FnMut() -> bool,
+    ) -> Result<spin::mutex::SpinMutexGuard<'_, T>, Option<Owner>> {
+        loop {
+            if let Some(guard) = self.inner.try_lock() {
+                return Ok(guard);
+            }
+            if expired() {
+                return Err(self.owner.get());
+            }
+            spin_loop();
+        }
+    }
+
+    fn acquired<'a>(
+        &'a self,
+        guard: spin::mutex::SpinMutexGuard<'a, T>,
+        location: &'static Location<'static>,
+    ) -> SpinMutexGuard<'a, T> {
+        self.owner.set(location);
+        SpinMutexGuard { mutex: self, guard }
+    }
+}
+
+impl<T: Debug> Debug for SpinMutex<T> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        self.inner.fmt(f)
+    }
+}
+
+impl<T: Default> Default for SpinMutex<T> {
+    fn default() -> Self {
+        Self::new(T::default())
+    }
+}
+
+struct OwnerDisplay(Option<Owner>);
+
+impl fmt::Display for OwnerDisplay {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self.0 {
+            Some(owner) => write!(
+                f,
+                "held by core {:#x} since {}",
+                owner.mpidr, owner.location
+            ),
+            None => write!(f, "holder unknown"),
+        }
+    }
+}
+
+/// A guard for a [`SpinMutex`], which releases it when dropped.
+pub struct SpinMutexGuard<'a, T> {
+    mutex: &'a SpinMutex<T>,
+    guard: spin::mutex::SpinMutexGuard<'a, T>,
+}
+
+impl<'a, T> SpinMutexGuard<'a, T> {
+    /// Leaks the guard, so that the lock is never released, and returns a mutable reference to the
+    /// contained value.
+    pub fn leak(this: Self) -> &'a mut T {
+        let this = ManuallyDrop::new(this);
+        // SAFETY: `this` is never dropped, so the guard is only moved out of it once.
+        let guard = unsafe { ptr::read(&this.guard) };
+        spin::mutex::SpinMutexGuard::leak(guard)
+    }
+}
+
+impl<T> Deref for SpinMutexGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.guard
+    }
+}
+
+impl<T> DerefMut for SpinMutexGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.guard
+    }
+}
+
+impl<T: Debug> Debug for SpinMutexGuard<'_, T> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        self.guard.fmt(f)
+    }
+}
+
+impl<T> Drop for SpinMutexGuard<'_, T> {
+    fn drop(&mut self) {
+        // This runs before the inner guard is dropped, so the owner is cleared while the lock is
+        // still held.
+        self.mutex.owner.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn owner_recorded() {
+        let mutex = SpinMutex::new(42);
+        assert_eq!(mutex.owner.get(), None);
+
+        let line = line!() + 1;
+        let guard = mutex.lock();
+        let owner = mutex.owner.get().unwrap();
+        assert_eq!(owner.location.file(), file!());
+        assert_eq!(owner.location.line(), line);
+        assert_eq!(*guard, 42);
+
+        drop(guard);
+        assert_eq!(mutex.owner.get(), None);
+    }
+
+    #[test]
+    fn contended_lock_reports_owner() {
+        let mutex = SpinMutex::new(0);
+        let mut guard = mutex.try_lock().unwrap();
+        *guard = 1;
+        let owner = mutex.owner.get();
+        assert!(owner.is_some());
+
+        let mut spins = 0;
+        let result = mutex.spin_until(|| {
+            spins += 1;
+            spins == 3
+        });
+        assert_eq!(result.err(), Some(owner));
+        assert!(mutex.try_lock().is_none());
+
+        drop(guard);
+        assert_eq!(*mutex.spin_until(|| panic!("lock not free")).unwrap(), 1);
+    }
+
+    #[test]
+    fn leak() {
+        let mutex = SpinMutex::new(0);
+        *SpinMutexGuard::leak(mutex.lock()) = 1;
+        assert!(mutex.try_lock().is_none());
+        assert!(mutex.owner.get().is_some());
+    }
+}
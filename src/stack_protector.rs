@@ -0,0 +1,46 @@
+// Copyright The Rusted Firmware-A Contributors.
+//
+// SPDX-License-Identifier: BSD-3-Clause
+
+//! Support for the compiler's stack protector, enabled by building with `STACK_PROTECTOR_EL3=1`.
+//!
+//! Functions with buffers on the stack store a canary value next to their saved registers on entry,
+//! and check that it is unchanged before returning, calling `__stack_chk_fail` if it has been
+//! overwritten. The canary is shared by all cores.
+
+use core::sync::atomic::{AtomicU64, Ordering};
+use log::warn;
+
+/// The canary value used until a random one has been set by `init`.
+const DEFAULT_CANARY: u64 = 0x0c0f_fee5_dead_f00d;
+
+/// The canary value which the compiler's stack protector checks.
+#[unsafe(no_mangle)]
+#[allow(non_upper_case_globals)]
+static __stack_chk_guard: AtomicU64 = AtomicU64::new(DEFAULT_CANARY);
+
+/// Called by functions with a stack protector when they find that the canary has been overwritten.
+///
+/// This panics, so the panic handler saves and prints a crash dump.
+#[unsafe(no_mangle)]
+extern "C" fn __stack_chk_fail() -> ! {
+    panic!("Stack corruption detected");
+}
+
+/// Replaces the stack canary with the given random value, or keeps the fixed default value with a
+/// warning if there is none.
+///
+/// # Safety
+///
+/// This must be called on the primary core during cold boot, before any other cores are started.
+/// It must be called from a function which never returns, and not from any function which has
+/// been entered but will return afterwards, as such a function would then find the canary changed.
+/// It is always inlined to ensure that it doesn't have a stack protector of its own.
+#[inline(always)]
+pub unsafe fn init(canary: Option<u64>) {
+    if let Some(canary) = canary {
+        __stack_chk_guard.store(canary, Ordering::Relaxed);
+    } else {
+        warn!("No entropy available for the stack canary, using a fixed value");
+    }
+}
@@ -0,0 +1,153 @@
+// Copyright The Rusted Firmware-A Contributors.
+//
+// SPDX-License-Identifier: BSD-3-Clause
+
+//! Decoding of trapped lower EL system register accesses, and the actions which EL3 may take in
+//! response.
+
+use arm_sysregs::EsrEl3;
+
+/// The encoding of a system register, as used by `MRS` and `MSR`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct SysregEncoding {
+    /// The op0 field.
+    pub op0: u8,
+    /// The op1 field.
+    pub op1: u8,
+    /// The CRn field.
+    pub crn: u8,
+    /// The CRm field.
+    pub crm: u8,
+    /// The op2 field.
+    pub op2: u8,
+}
+
+impl SysregEncoding {
+    /// Returns the encoding `S<op0>_<op1>_C<crn>_C<crm>_<op2>`.
+    pub const fn new(op0: u8, op1: u8, crn: u8, crm: u8, op2: u8) -> Self {
+        Self {
+            op0,
+            op1,
+            crn,
+            crm,
+            op2,
+        }
+    }
+
+    /// Returns whether this encoding is in one of the spaces reserved for IMPLEMENTATION DEFINED
+    /// registers, i.e. op0 is 3 and CRn is 11 or 15.
+    pub const fn is_impdef(self) -> bool {
+        self.op0 == 3 && (self.crn == 11 || self.crn == 15)
+    }
+}
+
+/// The direction of a trapped system register access.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SysregDirection {
+    /// An `MRS` reading the register.
+    Read,
+    /// An `MSR` writing the given value to the register.
+    Write(u64),
+}
+
+/// A lower EL `MRS` or `MSR` instruction which was trapped to EL3.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct SysregAccess {
+    /// The register being accessed.
+    pub encoding: SysregEncoding,
+    /// The general purpose register used by the instruction, where 31 means XZR.
+    pub rt: u8,
+    /// Whether the register is read or written.
+    pub direction: SysregDirection,
+}
+
+impl SysregAccess {
+    /// Decodes the ISS of an ESR_EL3 value for an exception with EC 0x18, given a function to read
+    /// the value of a lower EL general purpose register for writes.
+    pub fn from_esr(esr: EsrEl3, read_gpreg: impl FnOnce(u8) -> u64) -> Self {
+        let iss = esr.iss();
+        let field = |shift: u32, width: u32| ((iss >> shift) & ((1 << width) - 1)) as u8;
+        let rt = field(5, 5);
+        let direction = if field(0, 1) == 1 {
+            SysregDirection::Read
+        } else if rt == 31 {
+            SysregDirection::Write(0)
+        } else {
+            SysregDirection::Write(read_gpreg(rt))
+        };
+
+        Self {
+            encoding: SysregEncoding {
+                op0: field(20, 2),
+                op1: field(14, 3),
+                crn: field(10, 4),
+                crm: field(1, 4),
+                op2: field(17, 3),
+            },
+            rt,
+            direction,
+        }
+    }
+}
+
+/// What EL3 should do in response to a trapped system register access.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SysregTrapAction {
+    /// The access has been emulated. For a read this is the value to return to the lower EL; for a
+    /// write it is ignored.
+    Emulated(u64),
+    /// The register reads as zero and writes are ignored.
+    RazWi,
+    /// Inject an undefined instruction exception into the lower EL, as if the register didn't
+    /// exist.
+    Undefined,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decode_read() {
+        // MRS x3, S3_0_C15_C2_1
+        let esr = EsrEl3::from_bits_retain(
+            0x18 << 26 | 3 << 20 | 1 << 17 | 15 << 10 | 3 << 5 | 2 << 1 | 1,
+        );
+        let access = SysregAccess::from_esr(esr, |_| panic!("Read doesn't need register value"));
+        assert_eq!(
+            access,
+            SysregAccess {
+                encoding: SysregEncoding::new(3, 0, 15, 2, 1),
+                rt: 3,
+                direction: SysregDirection::Read,
+            }
+        );
+        assert!(access.encoding.is_impdef());
+    }
+
+    #[test]
+    fn decode_write() {
+        // MSR S3_1_C11_C0_2, x30
+        let esr =
+            EsrEl3::from_bits_retain(0x18 << 26 | 3 << 20 | 2 << 17 | 1 << 14 | 11 << 10 | 30 << 5);
+        let access = SysregAccess::from_esr(esr, |rt| {
+            assert_eq!(rt, 30);
+            0x1234
+        });
+        assert_eq!(
+            access,
+            SysregAccess {
+                encoding: SysregEncoding::new(3, 1, 11, 0, 2),
+                rt: 30,
+                direction: SysregDirection::Write(0x1234),
+            }
+        );
+        assert!(access.encoding.is_impdef());
+
+        // MSR ACTLR_EL1, xzr
+        let esr = EsrEl3::from_bits_retain(0x18 << 26 | 3 << 20 | 1 << 17 | 1 << 10 | 31 << 5);
+        let access = SysregAccess::from_esr(esr, |_| panic!("XZR isn't saved"));
+        assert_eq!(access.direction, SysregDirection::Write(0));
+        assert!(!access.encoding.is_impdef());
+    }
+}
@@ -0,0 +1,152 @@
+// Copyright The Rusted Firmware-A Contributors.
+//
+// SPDX-License-Identifier: BSD-3-Clause
+
+//! Callbacks for drivers which need to save state before the system power domain is powered down,
+//! or re-initialise the peripherals they configured after it is powered up again.
+
+use crate::spin_mutex::SpinMutex;
+use arrayvec::ArrayVec;
+
+/// The maximum number of notifiers which may be registered.
+pub const MAX_SYSTEM_SUSPEND_NOTIFIERS: usize = 8;
+
+/// A driver which needs to be told when the system power domain is powered down for suspend, and
+/// when it resumes.
+///
+/// Both methods are called on the last core to suspend or the first core to resume, with the PSCI
+/// power domain locks of that core and all its ancestors held.
+pub trait SystemSuspendNotifier: Sync {
+    /// Saves any state which the driver needs to restore on resume, before the system power domain
+    /// is powered down.
+    ///
+    /// The default implementation does nothing, for drivers which can configure their peripherals
+    /// from scratch on resume.
+    fn system_suspend(&self) {}
+
+    /// Re-initialises the peripherals configured by the driver after the system power domain has
+    /// been powered up again, before any core enters a lower EL.
+    fn system_resume(&self);
+}
+
+/// The list of drivers to notify when the system power domain is suspended or resumes.
+///
+/// Notifiers are registered on the primary core during cold boot. They are notified in the order
+/// they were registered on resume, and in the reverse order on suspend.
+pub struct SystemSuspendNotifiers {
+    notifiers:
+        SpinMutex<ArrayVec<&'static dyn SystemSuspendNotifier, MAX_SYSTEM_SUSPEND_NOTIFIERS>>,
+}
+
+impl SystemSuspendNotifiers {
+    /// Creates an empty list of notifiers.
+    pub const fn new() -> Self {
+        Self {
+            notifiers: SpinMutex::new(ArrayVec::new_const()),
+        }
+    }
+
+    /// Adds the given notifier to the list.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `MAX_SYSTEM_SUSPEND_NOTIFIERS` notifiers have already been registered.
+    pub fn register(&self, notifier: &'static dyn SystemSuspendNotifier) {
+        self.notifiers
+            .lock()
+            .try_push(notifier)
+            .expect("Too many system suspend notifiers registered");
+    }
+
+    /// Notifies all registered drivers that the system power domain is about to be powered down.
+    pub(crate) fn system_suspend(&self) {
+        // Copy the list so that the lock isn't held while calling the notifiers.
+        let notifiers = self.notifiers.lock().clone();
+        for notifier in notifiers.into_iter().rev() {
+            notifier.system_suspend();
+        }
+    }
+
+    /// Notifies all registered drivers that the system power domain has been powered up again.
+    pub(crate) fn system_resume(&self) {
+        let notifiers = self.notifiers.lock().clone();
+        for notifier in notifiers {
+            notifier.system_resume();
+        }
+    }
+}
+
+impl Default for SystemSuspendNotifiers {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    struct RecordingNotifier {
+        name: &'static str,
+        events: &'static Mutex<Vec<String>>,
+    }
+
+    impl SystemSuspendNotifier for RecordingNotifier {
+        fn system_suspend(&self) {
+            self.events
+                .lock()
+                .unwrap()
+                .push(format!("{} suspend", self.name));
+        }
+
+        fn system_resume(&self) {
+            self.events
+                .lock()
+                .unwrap()
+                .push(format!("{} resume", self.name));
+        }
+    }
+
+    struct ResumeOnlyNotifier {
+        events: &'static Mutex<Vec<String>>,
+    }
+
+    impl SystemSuspendNotifier for ResumeOnlyNotifier {
+        fn system_resume(&self) {
+            self.events.lock().unwrap().push("tzc resume".to_string());
+        }
+    }
+
+    #[test]
+    fn notify_in_order() {
+        static EVENTS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+        static GIC: RecordingNotifier = RecordingNotifier {
+            name: "gic",
+            events: &EVENTS,
+        };
+        static TIMER: RecordingNotifier = RecordingNotifier {
+            name: "timer",
+            events: &EVENTS,
+        };
+        static TZC: ResumeOnlyNotifier = ResumeOnlyNotifier { events: &EVENTS };
+
+        let notifiers = SystemSuspendNotifiers::new();
+        notifiers.register(&GIC);
+        notifiers.register(&TIMER);
+        notifiers.register(&TZC);
+
+        notifiers.system_suspend();
+        notifiers.system_resume();
+        assert_eq!(
+            *EVENTS.lock().unwrap(),
+            [
+                "timer suspend",
+                "gic suspend",
+                "gic resume",
+                "timer resume",
+                "tzc resume"
+            ]
+        );
+    }
+}
@@ -0,0 +1,157 @@
+// Copyright The Rusted Firmware-A Contributors.
+//
+// SPDX-License-Identifier: BSD-3-Clause
+
+//! Delays and timeouts based on the generic timer's physical counter.
+//!
+//! Drivers which poll hardware for a state change should use [`poll_until`] or a [`Timeout`]
+//! rather than busy-waiting forever, so that a stuck device results in an error rather than a hung
+//! core.
+
+use arm_sysregs::{read_cntfrq_el0, read_cntpct_el0};
+use core::hint::spin_loop;
+
+/// Error returned when a polling loop doesn't complete before its timeout.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct TimedOut;
+
+/// Returns the current value of the generic timer's physical counter.
+pub fn counter() -> u64 {
+    read_cntpct_el0().physicalcount()
+}
+
+/// Returns the frequency of the generic timer's counter, in Hz.
+pub fn frequency() -> u64 {
+    read_cntfrq_el0().clockfreq().into()
+}
+
+/// Converts a number of microseconds to generic timer ticks, saturating on overflow.
+pub fn micros_to_ticks(micros: u64) -> u64 {
+    micros_to_ticks_at(micros, frequency())
+}
+
+/// Converts a number of generic timer ticks to microseconds.
+///
+/// Returns 0 if the counter frequency hasn't been configured.
+pub fn ticks_to_micros(ticks: u64) -> u64 {
+    ticks_to_micros_at(ticks, frequency())
+}
+
+fn micros_to_ticks_at(micros: u64, frequency: u64) -> u64 {
+    (u128::from(micros) * u128::from(frequency))
+        .div_ceil(1_000_000)
+        .try_into()
+        .unwrap_or(u64::MAX)
+}
+
+fn ticks_to_micros_at(ticks: u64, frequency: u64) -> u64 {
+    if frequency == 0 {
+        return 0;
+    }
+    (u128::from(ticks) * 1_000_000 / u128::from(frequency)) as u64
+}
+
+/// A deadline on the generic timer's physical counter.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Timeout {
+    deadline: u64,
+}
+
+impl Timeout {
+    /// Returns a timeout which expires after the given number of generic timer ticks.
+    pub fn from_ticks(ticks: u64) -> Self {
+        Self {
+            deadline: counter().saturating_add(ticks),
+        }
+    }
+
+    /// Returns a timeout which expires after the given number of microseconds.
+    pub fn from_micros(micros: u64) -> Self {
+        Self::from_ticks(micros_to_ticks(micros))
+    }
+
+    /// Returns a timeout which expires after the given number of milliseconds.
+    pub fn from_millis(millis: u64) -> Self {
+        Self::from_micros(millis.saturating_mul(1000))
+    }
+
+    /// Returns whether the timeout has expired.
+    pub fn expired(&self) -> bool {
+        self.expired_at(counter())
+    }
+
+    fn expired_at(&self, now: u64) -> bool {
+        now >= self.deadline
+    }
+}
+
+/// Busy-waits for at least the given number of microseconds.
+pub fn udelay(micros: u64) {
+    let timeout = Timeout::from_micros(micros);
+    while !timeout.expired() {
+        spin_loop();
+    }
+}
+
+/// Busy-waits for at least the given number of milliseconds.
+pub fn mdelay(millis: u64) {
+    udelay(millis.saturating_mul(1000));
+}
+
+/// Calls `done` repeatedly until it returns true, or returns `TimedOut` if it still hasn't after
+/// `timeout_ms` milliseconds.
+///
+/// `done` is always called at least once, and once more after the timeout expires, so a condition
+/// which became true while the core was delayed isn't reported as a timeout.
+pub fn poll_until(timeout_ms: u64, mut done: impl FnMut() -> bool) -> Result<(), TimedOut> {
+    let timeout = Timeout::from_millis(timeout_ms);
+    loop {
+        let expired = timeout.expired();
+        if done() {
+            return Ok(());
+        }
+        if expired {
+            return Err(TimedOut);
+        }
+        spin_loop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn conversions() {
+        assert_eq!(micros_to_ticks_at(1, 100_000_000), 100);
+        assert_eq!(micros_to_ticks_at(3, 1_000_000), 3);
+        // Partial ticks are rounded up, so a delay is never shorter than requested.
+        assert_eq!(micros_to_ticks_at(1, 32_768), 1);
+        assert_eq!(micros_to_ticks_at(u64::MAX, 100_000_000), u64::MAX);
+        assert_eq!(ticks_to_micros_at(2000, 1_000_000), 2000);
+        assert_eq!(ticks_to_micros_at(100, 100_000_000), 1);
+        assert_eq!(ticks_to_micros_at(1234, 0), 0);
+    }
+
+    #[test]
+    fn timeout_expiry() {
+        let timeout = Timeout { deadline: 1000 };
+        assert!(!timeout.expired_at(0));
+        assert!(!timeout.expired_at(999));
+        assert!(timeout.expired_at(1000));
+        assert!(timeout.expired_at(u64::MAX));
+    }
+
+    #[test]
+    fn poll_until_done() {
+        let mut calls = 0;
+        assert_eq!(
+            poll_until(10, || {
+                calls += 1;
+                true
+            }),
+            Ok(())
+        );
+        assert_eq!(calls, 1);
+    }
+}
@@ -0,0 +1,122 @@
+// Copyright The Rusted Firmware-A Contributors.
+//
+// SPDX-License-Identifier: BSD-3-Clause
+
+//! Periodic refreshing of the platform's trusted watchdog by EL3.
+//!
+//! Normally the secure world owns the trusted watchdog and refreshes it whenever it runs. During
+//! long operations at EL3, such as memory initialisation or system suspend entry, the secure world
+//! doesn't get a chance to run, so platforms may instead choose to have EL3 refresh the watchdog
+//! whenever it is due during such operations.
+
+use crate::{platform::Platform, timer};
+use core::sync::atomic::{AtomicU64, Ordering};
+
+/// Who is responsible for refreshing the platform's trusted watchdog.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum WatchdogPolicy {
+    /// The secure world owns the watchdog, and EL3 never refreshes it.
+    SecureWorld,
+    /// EL3 refreshes the watchdog during long operations, at most once every `period_ms`
+    /// milliseconds. The period should be comfortably shorter than the watchdog's timeout.
+    El3 {
+        /// The minimum time between refreshes, in milliseconds.
+        period_ms: u64,
+    },
+}
+
+/// A driver for the platform's trusted watchdog.
+pub trait TrustedWatchdog: Sync {
+    /// Reloads the watchdog's counter so that it doesn't expire.
+    fn refresh(&self);
+}
+
+/// The last time EL3 refreshed the watchdog, shared by all cores.
+static REFRESHER: Refresher = Refresher::new();
+
+/// Refreshes the platform's trusted watchdog if the platform's policy is for EL3 to do so and the
+/// refresh period has elapsed since it was last refreshed.
+///
+/// This may be called from any core, and should be called regularly during long operations at EL3
+/// which don't return to a lower EL.
+pub fn refresh_if_due<PlatformImpl: Platform>() {
+    let WatchdogPolicy::El3 { period_ms } = PlatformImpl::WATCHDOG_POLICY else {
+        return;
+    };
+    if let Some(watchdog) = PlatformImpl::trusted_watchdog() {
+        REFRESHER.refresh_if_due_at(
+            watchdog,
+            timer::micros_to_ticks(period_ms.saturating_mul(1000)),
+            timer::counter(),
+        );
+    }
+}
+
+/// Tracks when the watchdog was last refreshed, so that it isn't refreshed more often than needed.
+struct Refresher {
+    /// The generic timer counter value when the watchdog was last refreshed, or `NEVER`.
+    last_refresh: AtomicU64,
+}
+
+impl Refresher {
+    const NEVER: u64 = u64::MAX;
+
+    const fn new() -> Self {
+        Self {
+            last_refresh: AtomicU64::new(Self::NEVER),
+        }
+    }
+
+    /// Refreshes the watchdog if at least `period_ticks` have elapsed since it was last refreshed
+    /// at time `now`, and returns whether it did so.
+    fn refresh_if_due_at(
+        &self,
+        watchdog: &dyn TrustedWatchdog,
+        period_ticks: u64,
+        now: u64,
+    ) -> bool {
+        let last_refresh = self.last_refresh.load(Ordering::Relaxed);
+        if last_refresh != Self::NEVER && now.wrapping_sub(last_refresh) < period_ticks {
+            return false;
+        }
+        // Only one core needs to refresh the watchdog for each period.
+        if self
+            .last_refresh
+            .compare_exchange(last_refresh, now, Ordering::Relaxed, Ordering::Relaxed)
+            .is_err()
+        {
+            return false;
+        }
+        watchdog.refresh();
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicUsize;
+
+    #[derive(Default)]
+    struct FakeWatchdog {
+        refreshes: AtomicUsize,
+    }
+
+    impl TrustedWatchdog for FakeWatchdog {
+        fn refresh(&self) {
+            self.refreshes.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    #[test]
+    fn refresh_once_per_period() {
+        let watchdog = FakeWatchdog::default();
+        let refresher = Refresher::new();
+
+        assert!(refresher.refresh_if_due_at(&watchdog, 100, 1000));
+        assert!(!refresher.refresh_if_due_at(&watchdog, 100, 1050));
+        assert!(!refresher.refresh_if_due_at(&watchdog, 100, 1099));
+        assert!(refresher.refresh_if_due_at(&watchdog, 100, 1100));
+        assert_eq!(watchdog.refreshes.load(Ordering::Relaxed), 2);
+    }
+}